@@ -0,0 +1,64 @@
+//! Times the generation of the Circom, Halo2 and Noir outputs for the largest email patterns
+//! of `packages/circom/circuits/common`.
+//!
+//! ```text
+//! cargo bench --bench codegen
+//! ```
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use zk_regex_compiler::{compile_decomposed, CompileOptions, NoirOptions, SubstrSelection};
+
+const PATTERNS: [(&str, &str); 3] = [
+    (
+        "from_addr",
+        include_str!("../../circom/circuits/common/from_addr.json"),
+    ),
+    (
+        "timestamp",
+        include_str!("../../circom/circuits/common/timestamp.json"),
+    ),
+    (
+        "email_addr_with_name",
+        include_str!("../../circom/circuits/common/email_addr_with_name.json"),
+    ),
+];
+
+fn bench_codegen(c: &mut Criterion) {
+    let backends = [
+        (
+            "circom",
+            CompileOptions {
+                circom_template_name: Some("Regex".to_string()),
+                ..Default::default()
+            },
+        ),
+        (
+            "halo2",
+            CompileOptions {
+                halo2: true,
+                ..Default::default()
+            },
+        ),
+        (
+            "noir",
+            CompileOptions {
+                noir: Some(NoirOptions::default()),
+                ..Default::default()
+            },
+        ),
+    ];
+    for (name, json) in PATTERNS {
+        for (backend, options) in &backends {
+            let options = CompileOptions {
+                gen_substrs: Some(SubstrSelection::All),
+                ..options.clone()
+            };
+            c.bench_function(&format!("{backend}/{name}"), |b| {
+                b.iter(|| compile_decomposed(json, &options).unwrap())
+            });
+        }
+    }
+}
+
+criterion_group!(benches, bench_codegen);
+criterion_main!(benches);
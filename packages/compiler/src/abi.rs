@@ -0,0 +1,373 @@
+use crate::{
+    errors::CompilerError,
+    structs::{CaptureGroup, RegexAndDFA},
+};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// A machine-readable description of the public functions of a generated Noir matcher, from
+/// which frontend SDKs and verifier templates can be generated without parsing Noir.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoirAbi {
+    /// The regex the matcher was generated from.
+    pub regex: String,
+    /// The public functions, in the order they are defined, the main file first.
+    pub functions: Vec<NoirAbiFunction>,
+    /// The substrings revealed by the matcher, in the order of the capture arrays its
+    /// functions return.
+    pub captures: Vec<NoirAbiCapture>,
+}
+
+/// The signature of a public function of a generated Noir matcher.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoirAbiFunction {
+    /// The module defining the function, empty for the main file.
+    pub module: String,
+    /// The name of the function.
+    pub name: String,
+    /// Whether the function is `unconstrained`, so that it only runs outside of a circuit.
+    pub unconstrained: bool,
+    /// The numeric generics of the function, such as the input length `N`.
+    pub generics: Vec<NoirAbiParam>,
+    /// The parameters of the function.
+    pub params: Vec<NoirAbiParam>,
+    /// The type of the returned value, if any.
+    pub returns: Option<NoirAbiType>,
+}
+
+/// A named parameter or generic of a function.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoirAbiParam {
+    /// The name of the parameter.
+    pub name: String,
+    /// The type of the parameter.
+    #[serde(rename = "type")]
+    pub typ: NoirAbiType,
+}
+
+/// A Noir type. Lengths are kept as written, either a number or an expression of the numeric
+/// generics of the function such as `N`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NoirAbiType {
+    /// A field element.
+    Field,
+    /// A boolean.
+    Bool,
+    /// An integer of `width` bits.
+    Integer { signed: bool, width: u32 },
+    /// A fixed-length array.
+    Array {
+        element: Box<NoirAbiType>,
+        length: String,
+    },
+    /// A string of `length` bytes.
+    String { length: String },
+    /// A vector of at most `max_len` elements, as returned for the captures.
+    BoundedVec {
+        element: Box<NoirAbiType>,
+        max_len: String,
+    },
+    /// A tuple.
+    Tuple { fields: Vec<NoirAbiType> },
+    /// Any other type, with its generic arguments as written.
+    Named { name: String, generics: Vec<String> },
+}
+
+/// A substring revealed by a generated Noir matcher.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoirAbiCapture {
+    /// The index of the capture in the arrays returned by the matcher.
+    pub index: usize,
+    /// For a raw regex compiled with its capture groups, the group of the capture.
+    pub group: Option<CaptureGroup>,
+    /// For a raw regex whose substrings JSON names its substrings, the name of the capture.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The largest number of code units the capture can hold, if bounded.
+    pub max_len: Option<usize>,
+}
+
+/// Describes the public functions of a generated Noir matcher and the captures it reveals.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct the matcher was generated from.
+/// * `sources` - The generated (module name, code) tuples, the main file under an empty name.
+/// * `capture_bounds` - The largest length of each revealed capture, if bounded, or an empty
+///   slice if the matcher reveals none.
+///
+/// # Returns
+///
+/// A `Result` containing the `NoirAbi`, or a `CompilerError` if a signature cannot be parsed.
+pub(crate) fn noir_abi(
+    regex_and_dfa: &RegexAndDFA,
+    sources: &[(String, String)],
+    capture_bounds: &[Option<usize>],
+) -> Result<NoirAbi, CompilerError> {
+    let mut functions = vec![];
+    for (module, code) in sources {
+        for signature in public_signatures(code) {
+            functions.push(parse_function(module, &signature)?);
+        }
+    }
+    let groups = regex_and_dfa.substrings.substring_groups.as_deref();
+    let names = regex_and_dfa.substrings.substring_names.as_deref();
+    let captures = capture_bounds
+        .iter()
+        .enumerate()
+        .map(|(index, &max_len)| NoirAbiCapture {
+            index,
+            group: groups.and_then(|groups| groups.get(index).cloned()),
+            name: names.and_then(|names| names.get(index).cloned().flatten()),
+            max_len,
+        })
+        .collect();
+    Ok(NoirAbi {
+        regex: regex_and_dfa.regex_pattern.clone(),
+        functions,
+        captures,
+    })
+}
+
+/// Returns the signatures of the top-level public functions of a Noir file, from `pub` up to
+/// the brace opening their body, with their line breaks.
+fn public_signatures(code: &str) -> Vec<String> {
+    let mut signatures = vec![];
+    let mut offset = 0;
+    for line in code.split_inclusive('\n') {
+        if line.starts_with("pub fn ") || line.starts_with("pub unconstrained fn ") {
+            if let Some(end) = code[offset..].find('{') {
+                signatures.push(code[offset..offset + end].to_string());
+            }
+        }
+        offset += line.len();
+    }
+    signatures
+}
+
+/// Parses the signature of a public function, e.g.
+/// `pub fn regex_match<let N: u32>(input: [u8; N]) -> [BoundedVec<Field, N>; 1]`.
+///
+/// # Arguments
+///
+/// * `module` - The module defining the function.
+/// * `signature` - The signature, without the body.
+///
+/// # Returns
+///
+/// A `Result` containing the `NoirAbiFunction`, or a `CompilerError` if the signature cannot be
+/// parsed.
+fn parse_function(module: &str, signature: &str) -> Result<NoirAbiFunction, CompilerError> {
+    let error = || {
+        CompilerError::GenericError(format!(
+            "cannot describe the signature \"{}\" in the ABI",
+            signature.split_whitespace().join(" ")
+        ))
+    };
+    let rest = signature.trim().strip_prefix("pub ").ok_or_else(error)?;
+    let (unconstrained, rest) = match rest.strip_prefix("unconstrained ") {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let rest = rest.strip_prefix("fn ").ok_or_else(error)?;
+    let name_end = rest.find(['<', '(']).ok_or_else(error)?;
+    let name = rest[..name_end].trim().to_string();
+    let rest = &rest[name_end..];
+    let (generics, rest) = match rest.starts_with('<') {
+        true => {
+            let end = closing(rest, '<', '>').ok_or_else(error)?;
+            (&rest[1..end], &rest[end + 1..])
+        }
+        false => ("", rest),
+    };
+    let end = closing(rest, '(', ')').ok_or_else(error)?;
+    let (params, rest) = (&rest[1..end], rest[end + 1..].trim());
+    let param = |param: &str| -> Result<NoirAbiParam, CompilerError> {
+        let param = param.trim();
+        let param = param.strip_prefix("let ").unwrap_or(param);
+        let param = param.strip_prefix("mut ").unwrap_or(param);
+        let (name, typ) = param.split_once(':').ok_or_else(error)?;
+        Ok(NoirAbiParam {
+            name: name.trim().to_string(),
+            typ: parse_type(typ).ok_or_else(error)?,
+        })
+    };
+    let returns = match rest.strip_prefix("->") {
+        Some(typ) => Some(parse_type(typ).ok_or_else(error)?),
+        None if rest.is_empty() => None,
+        None => return Err(error()),
+    };
+    Ok(NoirAbiFunction {
+        module: module.to_string(),
+        name,
+        unconstrained,
+        generics: split_top_level(generics, ',')
+            .into_iter()
+            .map(param)
+            .try_collect()?,
+        params: split_top_level(params, ',')
+            .into_iter()
+            .map(param)
+            .try_collect()?,
+        returns,
+    })
+}
+
+/// Parses a Noir type, returning `None` if it is malformed.
+fn parse_type(typ: &str) -> Option<NoirAbiType> {
+    let typ = typ.trim();
+    if let Some(inner) = typ.strip_prefix('[').and_then(|typ| typ.strip_suffix(']')) {
+        let (element, length) = match split_top_level(inner, ';').as_slice() {
+            [element, length] => (*element, *length),
+            _ => return None,
+        };
+        return Some(NoirAbiType::Array {
+            element: Box::new(parse_type(element)?),
+            length: length.trim().to_string(),
+        });
+    }
+    if let Some(inner) = typ.strip_prefix('(').and_then(|typ| typ.strip_suffix(')')) {
+        return Some(NoirAbiType::Tuple {
+            fields: split_top_level(inner, ',')
+                .into_iter()
+                .map(parse_type)
+                .collect::<Option<_>>()?,
+        });
+    }
+    let (name, args) = match typ.find('<') {
+        Some(start) => (
+            typ[..start].trim(),
+            split_top_level(typ[start + 1..].strip_suffix('>')?, ','),
+        ),
+        None => (typ, vec![]),
+    };
+    let integer = |signed: bool, width: &str| {
+        width
+            .parse()
+            .ok()
+            .map(|width| NoirAbiType::Integer { signed, width })
+    };
+    match (name, args.as_slice()) {
+        ("", _) => None,
+        ("Field", []) => Some(NoirAbiType::Field),
+        ("bool", []) => Some(NoirAbiType::Bool),
+        ("str", [length]) => Some(NoirAbiType::String {
+            length: length.trim().to_string(),
+        }),
+        ("BoundedVec", [element, max_len]) => Some(NoirAbiType::BoundedVec {
+            element: Box::new(parse_type(element)?),
+            max_len: max_len.trim().to_string(),
+        }),
+        (name, []) if name.starts_with('u') && integer(false, &name[1..]).is_some() => {
+            integer(false, &name[1..])
+        }
+        (name, []) if name.starts_with('i') && integer(true, &name[1..]).is_some() => {
+            integer(true, &name[1..])
+        }
+        (name, args) => Some(NoirAbiType::Named {
+            name: name.to_string(),
+            generics: args.iter().map(|arg| arg.trim().to_string()).collect(),
+        }),
+    }
+}
+
+/// Returns the index of the delimiter closing the one `text` starts with.
+fn closing(text: &str, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Splits `text` at the separators outside of any brackets, dropping the empty pieces left by
+/// a trailing separator.
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut pieces = vec![];
+    let (mut depth, mut start) = (0i32, 0);
+    for (i, c) in text.char_indices() {
+        match c {
+            '<' | '[' | '(' => depth += 1,
+            '>' | ']' | ')' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                pieces.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    pieces.push(&text[start..]);
+    pieces.retain(|piece| !piece.trim().is_empty());
+    pieces
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_function() {
+        let function = parse_function(
+            "",
+            "pub unconstrained fn regex_match_unconstrained<let N: u32>(\n    input: [u8; N],\n) -> [BoundedVec<Field, N>; 1] ",
+        )
+        .unwrap();
+        let n = NoirAbiParam {
+            name: "N".to_string(),
+            typ: NoirAbiType::Integer {
+                signed: false,
+                width: 32,
+            },
+        };
+        assert_eq!(
+            function,
+            NoirAbiFunction {
+                module: String::new(),
+                name: "regex_match_unconstrained".to_string(),
+                unconstrained: true,
+                generics: vec![n],
+                params: vec![NoirAbiParam {
+                    name: "input".to_string(),
+                    typ: NoirAbiType::Array {
+                        element: Box::new(NoirAbiType::Integer {
+                            signed: false,
+                            width: 8
+                        }),
+                        length: "N".to_string(),
+                    },
+                }],
+                returns: Some(NoirAbiType::Array {
+                    element: Box::new(NoirAbiType::BoundedVec {
+                        element: Box::new(NoirAbiType::Field),
+                        max_len: "N".to_string(),
+                    }),
+                    length: "1".to_string(),
+                }),
+            }
+        );
+        assert_eq!(
+            parse_type("(bool, str<N>, Option<u32>)"),
+            Some(NoirAbiType::Tuple {
+                fields: vec![
+                    NoirAbiType::Bool,
+                    NoirAbiType::String {
+                        length: "N".to_string()
+                    },
+                    NoirAbiType::Named {
+                        name: "Option".to_string(),
+                        generics: vec!["u32".to_string()],
+                    },
+                ],
+            })
+        );
+        assert!(parse_function("", "pub fn broken(input [u8; N])").is_err());
+    }
+}
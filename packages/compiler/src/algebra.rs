@@ -0,0 +1,1361 @@
+//! Operations combining the automata of compiled regexes.
+//!
+//! The operations act on the byte strings accepted by the DFAs, including the byte 255 that
+//! primes `^`-anchored patterns. A regex without an end anchor accepts any continuation of a
+//! match, so it is treated as its matches followed by any bytes, and the results are always
+//! end-anchored. Captures are dropped, so the results are matched without substrings.
+//!
+//! The Noir matcher of a result accepts exactly the inputs the result accepts, reading the
+//! input from its start instead of searching it for a match as the matcher of a regex does.
+//! No input holds the byte 255, so the inputs only accepted after the priming byte of a `^`
+//! are never matched: leave `^` out of the operands, the start of the input being implied.
+//!
+//! ```ignore
+//! let a = algebra::from_regex("[a-z]+@[a-z]+\\.com")?;
+//! let b = algebra::from_regex("[a-z]+@spam\\.com")?;
+//! let allowed = algebra::intersection(&a, &algebra::complement(&b)?)?;
+//! gen_noir_from_dfa(&allowed, "./src/sender.nr", &NoirOptions::default())?;
+//! ```
+
+use crate::{
+    errors::CompilerError,
+    regex::create_regex_and_dfa_from_str_and_defs,
+    structs::{
+        DFAGraph, DFAStateNode, RegexAndDFA, SubstringDefinitions, SubstringDefinitionsJson,
+    },
+};
+use itertools::Itertools;
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet, VecDeque};
+
+/// A complete DFA over bytes, with state 0 as the start state.
+struct DenseDFA {
+    delta: Vec<[usize; 256]>,
+    accept: Vec<bool>,
+}
+
+/// The transitions of a compiled regex, with `None` standing for the dead state.
+struct Operand {
+    delta: Vec<[Option<usize>; 256]>,
+    accept: Vec<bool>,
+}
+
+impl Operand {
+    fn new(regex_and_dfa: &RegexAndDFA) -> Self {
+        let num_states = regex_and_dfa
+            .dfa
+            .states
+            .iter()
+            .map(|state| state.state_id + 1)
+            .max()
+            .unwrap_or(1);
+        let mut delta = vec![[None; 256]; num_states];
+        let mut accept = vec![false; num_states];
+        for state in &regex_and_dfa.dfa.states {
+            for (&next, bytes) in &state.transitions {
+                for &byte in bytes {
+                    delta[state.state_id][byte as usize] = Some(next);
+                }
+            }
+            if state.state_type == "accept" {
+                accept[state.state_id] = true;
+                // Without an end anchor, the input is accepted whatever follows the match.
+                if !regex_and_dfa.has_end_anchor {
+                    delta[state.state_id] = [Some(state.state_id); 256];
+                }
+            }
+        }
+        Operand { delta, accept }
+    }
+
+    fn step(&self, state: Option<usize>, byte: u8) -> Option<usize> {
+        state.and_then(|state| self.delta[state][byte as usize])
+    }
+
+    fn accepts(&self, state: Option<usize>) -> bool {
+        state.is_some_and(|state| self.accept[state])
+    }
+}
+
+/// Builds the DFA exploring the states reachable from `start`.
+///
+/// # Arguments
+///
+/// * `start` - The start state.
+/// * `step` - The transition function.
+/// * `accepts` - Whether a state is accepting.
+///
+/// # Returns
+///
+/// The DFA, with the states numbered in the order they are reached.
+fn explore<S: Ord + Clone>(
+    start: S,
+    step: impl Fn(&S, u8) -> S,
+    accepts: impl Fn(&S) -> bool,
+) -> DenseDFA {
+    let mut ids = BTreeMap::from([(start.clone(), 0)]);
+    let mut states = vec![start];
+    let mut delta = vec![];
+    while delta.len() < states.len() {
+        let state = states[delta.len()].clone();
+        let mut row = [0; 256];
+        for byte in 0..=u8::MAX {
+            let next = step(&state, byte);
+            row[byte as usize] = match ids.get(&next) {
+                Some(&id) => id,
+                None => {
+                    ids.insert(next.clone(), states.len());
+                    states.push(next);
+                    states.len() - 1
+                }
+            };
+        }
+        delta.push(row);
+    }
+    DenseDFA {
+        accept: states.iter().map(accepts).collect(),
+        delta,
+    }
+}
+
+/// Partitions the states of a DFA into the classes of the states accepting the same inputs,
+/// using Moore's partition refinement starting from the accepting and rejecting states.
+///
+/// # Arguments
+///
+/// * `dfa` - The DFA to partition.
+///
+/// # Returns
+///
+/// The class of every state.
+fn moore_classes(dfa: &DenseDFA) -> Vec<usize> {
+    let num_states = dfa.delta.len();
+    let mut classes = dfa
+        .accept
+        .iter()
+        .map(|&accept| accept as usize)
+        .collect_vec();
+    let mut num_classes = 0;
+    loop {
+        let mut signatures = BTreeMap::new();
+        let refined = (0..num_states)
+            .map(|state| {
+                let signature = (
+                    classes[state],
+                    dfa.delta[state]
+                        .iter()
+                        .map(|&next| classes[next])
+                        .collect_vec(),
+                );
+                let next_class = signatures.len();
+                *signatures.entry(signature).or_insert(next_class)
+            })
+            .collect_vec();
+        classes = refined;
+        if signatures.len() == num_classes {
+            break;
+        }
+        num_classes = signatures.len();
+    }
+    classes
+}
+
+/// Minimizes a DFA into its canonical form.
+///
+/// The states are numbered in the order they are reached from the start state, trying the
+/// bytes in ascending order, so two DFAs accepting the same inputs minimize to equal DFAs.
+///
+/// # Arguments
+///
+/// * `dfa` - The DFA to minimize.
+///
+/// # Returns
+///
+/// The minimal DFA.
+fn minimize(dfa: &DenseDFA) -> DenseDFA {
+    let classes = moore_classes(dfa);
+    let mut members = BTreeMap::new();
+    for (state, &class) in classes.iter().enumerate() {
+        members.entry(class).or_insert(state);
+    }
+    explore(
+        classes[0],
+        |class, byte| classes[dfa.delta[members[class]][byte as usize]],
+        |class| dfa.accept[members[class]],
+    )
+}
+
+/// Minimizes a DFA and converts it into a `RegexAndDFA` without substrings.
+///
+/// The states from which no input is accepted are dropped, so that they have no transition
+/// as in the DFAs built from regexes, and the other states are numbered from 0 in the order
+/// they are reached from the start state.
+///
+/// # Arguments
+///
+/// * `dfa` - The DFA to convert.
+/// * `regex_pattern` - The pattern describing the result, emitted as a comment.
+///
+/// # Returns
+///
+/// A `Result` containing the `RegexAndDFA`, or a `CompilerError` if no input is accepted.
+fn to_regex_and_dfa(dfa: DenseDFA, regex_pattern: String) -> Result<RegexAndDFA, CompilerError> {
+    let num_states = dfa.delta.len();
+    let classes = moore_classes(&dfa);
+
+    // Keep the classes from which an accepting class can be reached.
+    let mut live = (0..num_states)
+        .filter(|&state| dfa.accept[state])
+        .map(|state| classes[state])
+        .collect::<BTreeSet<_>>();
+    loop {
+        let grown = (0..num_states)
+            .filter(|&state| {
+                dfa.delta[state]
+                    .iter()
+                    .any(|&next| live.contains(&classes[next]))
+            })
+            .map(|state| classes[state])
+            .collect::<BTreeSet<_>>();
+        if grown.is_subset(&live) {
+            break;
+        }
+        live.extend(grown);
+    }
+    if !live.contains(&classes[0]) {
+        return Err(CompilerError::GenericError(
+            "the combined automaton cannot match any input".to_string(),
+        ));
+    }
+
+    let mut class_ids = BTreeMap::from([(classes[0], 0)]);
+    let mut queue = VecDeque::from([0]);
+    let mut states = vec![];
+    while let Some(state) = queue.pop_front() {
+        let mut transitions: BTreeMap<usize, BTreeSet<u8>> = BTreeMap::new();
+        for byte in 0..=u8::MAX {
+            let next = dfa.delta[state][byte as usize];
+            if !live.contains(&classes[next]) {
+                continue;
+            }
+            let next_id = match class_ids.get(&classes[next]) {
+                Some(&id) => id,
+                None => {
+                    let id = class_ids.len();
+                    class_ids.insert(classes[next], id);
+                    queue.push_back(next);
+                    id
+                }
+            };
+            transitions.entry(next_id).or_default().insert(byte);
+        }
+        states.push(DFAStateNode {
+            state_type: match dfa.accept[state] {
+                true => "accept".to_string(),
+                false => String::new(),
+            },
+            state_id: states.len(),
+            transitions,
+        });
+    }
+
+    Ok(RegexAndDFA {
+        regex_pattern,
+        dfa: DFAGraph { states },
+        has_end_anchor: true,
+        substrings: SubstringDefinitions {
+            substring_ranges: vec![],
+            substring_boundaries: None,
+            substring_groups: None,
+            substring_names: None,
+        },
+        parts: vec![],
+        counters: vec![],
+        whole_input: true,
+    })
+}
+
+/// Compiles a raw regex for use with the other operations.
+///
+/// # Arguments
+///
+/// * `regex` - The raw regex.
+///
+/// # Returns
+///
+/// A `Result` containing the `RegexAndDFA` of the regex, or a `CompilerError`.
+pub fn from_regex(regex: &str) -> Result<RegexAndDFA, CompilerError> {
+    create_regex_and_dfa_from_str_and_defs(
+        regex,
+        SubstringDefinitionsJson {
+            transitions: vec![],
+            names: vec![],
+        },
+    )
+}
+
+/// Builds an automaton accepting the inputs accepted by either automaton.
+///
+/// # Arguments
+///
+/// * `a` - The first automaton.
+/// * `b` - The second automaton.
+///
+/// # Returns
+///
+/// A `Result` containing the union, or a `CompilerError` if it accepts no input.
+pub fn union(a: &RegexAndDFA, b: &RegexAndDFA) -> Result<RegexAndDFA, CompilerError> {
+    let (op_a, op_b) = (Operand::new(a), Operand::new(b));
+    let dfa = explore(
+        (Some(0), Some(0)),
+        |&(s, t), byte| (op_a.step(s, byte), op_b.step(t, byte)),
+        |&(s, t)| op_a.accepts(s) || op_b.accepts(t),
+    );
+    to_regex_and_dfa(dfa, format!("({})|({})", a.regex_pattern, b.regex_pattern))
+}
+
+/// Builds an automaton accepting the inputs accepted by both automata.
+///
+/// # Arguments
+///
+/// * `a` - The first automaton.
+/// * `b` - The second automaton.
+///
+/// # Returns
+///
+/// A `Result` containing the intersection, or a `CompilerError` if it accepts no input.
+pub fn intersection(a: &RegexAndDFA, b: &RegexAndDFA) -> Result<RegexAndDFA, CompilerError> {
+    let (op_a, op_b) = (Operand::new(a), Operand::new(b));
+    let dfa = explore(
+        (Some(0), Some(0)),
+        |&(s, t), byte| (op_a.step(s, byte), op_b.step(t, byte)),
+        |&(s, t)| op_a.accepts(s) && op_b.accepts(t),
+    );
+    to_regex_and_dfa(dfa, format!("({})&({})", a.regex_pattern, b.regex_pattern))
+}
+
+/// Builds an automaton accepting the inputs rejected by an automaton.
+///
+/// # Arguments
+///
+/// * `a` - The automaton to complement.
+///
+/// # Returns
+///
+/// A `Result` containing the complement, or a `CompilerError` if it accepts no input.
+pub fn complement(a: &RegexAndDFA) -> Result<RegexAndDFA, CompilerError> {
+    let op_a = Operand::new(a);
+    let dfa = explore(
+        Some(0),
+        |&s, byte| op_a.step(s, byte),
+        |&s| !op_a.accepts(s),
+    );
+    to_regex_and_dfa(dfa, format!("~({})", a.regex_pattern))
+}
+
+/// Builds an automaton accepting an input accepted by the first automaton followed by an
+/// input accepted by the second one.
+///
+/// # Arguments
+///
+/// * `a` - The automaton matching the beginning of the input.
+/// * `b` - The automaton matching the rest of the input.
+///
+/// # Returns
+///
+/// A `Result` containing the concatenation, or a `CompilerError` if it accepts no input.
+pub fn concatenation(a: &RegexAndDFA, b: &RegexAndDFA) -> Result<RegexAndDFA, CompilerError> {
+    let (op_a, op_b) = (Operand::new(a), Operand::new(b));
+    // The state of `a`, and the states of `b` for every split point seen so far.
+    let with_splits = |s: Option<usize>, mut ts: BTreeSet<usize>| {
+        if op_a.accepts(s) {
+            ts.insert(0);
+        }
+        (s, ts)
+    };
+    let dfa = explore(
+        with_splits(Some(0), BTreeSet::new()),
+        |(s, ts), byte| {
+            let ts = ts
+                .iter()
+                .filter_map(|&t| op_b.step(Some(t), byte))
+                .collect();
+            with_splits(op_a.step(*s, byte), ts)
+        },
+        |(_, ts)| ts.iter().any(|&t| op_b.accepts(Some(t))),
+    );
+    to_regex_and_dfa(dfa, format!("({})({})", a.regex_pattern, b.regex_pattern))
+}
+
+/// The state of the DKIM relaxed header canonicalization while it reads a header block.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct RelaxedHeader {
+    /// Whether the colon ending the field name has been read.
+    in_value: bool,
+    /// Whether a character other than whitespace has been written since the colon.
+    value_started: bool,
+    /// Whether whitespace has been read but not written yet.
+    space: bool,
+    /// Whether a carriage return has been read but not written yet.
+    cr: bool,
+    /// Whether a line break has been read but not written yet, as it is dropped if the next
+    /// line is folded.
+    crlf: bool,
+}
+
+impl RelaxedHeader {
+    /// Writes a character other than whitespace, preceded by the pending whitespace collapsed
+    /// into a single space unless it follows the colon, and lowercased in a field name.
+    fn write(&mut self, byte: u8, out: &mut Vec<u8>) {
+        if self.space && (!self.in_value || self.value_started) {
+            out.push(b' ');
+        }
+        self.space = false;
+        if self.in_value {
+            self.value_started = true;
+            out.push(byte);
+        } else {
+            out.push(byte.to_ascii_lowercase());
+        }
+    }
+
+    /// Reads a byte of the header block and returns the canonicalized bytes it releases.
+    fn step(mut self, byte: u8) -> (Self, Vec<u8>) {
+        let mut out = vec![];
+        // The priming byte starts the header block.
+        if byte == u8::MAX {
+            return (RelaxedHeader::default(), vec![byte]);
+        }
+        if self.crlf {
+            self.crlf = false;
+            if byte == b' ' || byte == b'\t' {
+                // The line is folded: the line break is dropped and the whitespace kept.
+                self.space = true;
+                return (self, out);
+            }
+            // Trailing whitespace is dropped, and the next line starts with a field name.
+            out.extend(b"\r\n");
+            self = RelaxedHeader::default();
+        }
+        if self.cr {
+            self.cr = false;
+            if byte == b'\n' {
+                self.crlf = true;
+                return (self, out);
+            }
+            self.write(b'\r', &mut out);
+        }
+        match byte {
+            b'\r' => self.cr = true,
+            b' ' | b'\t' => self.space = true,
+            b':' if !self.in_value => {
+                // The whitespace around the colon is dropped.
+                self.space = false;
+                self.in_value = true;
+                out.push(b':');
+            }
+            _ => self.write(byte, &mut out),
+        }
+        (self, out)
+    }
+
+    /// Returns the bytes still pending at the end of the input, dropping trailing whitespace.
+    fn flush(mut self) -> Vec<u8> {
+        let mut out = vec![];
+        if self.crlf {
+            out.extend(b"\r\n");
+        } else if self.cr {
+            self.write(b'\r', &mut out);
+        }
+        out
+    }
+}
+
+/// A state of the automaton reading canonicalized bytes: the state of the original automaton
+/// and of the canonicalization, the canonicalized bytes released but not read yet, and whether
+/// the original input has ended.
+type RelaxedNode = (usize, RelaxedHeader, Vec<u8>, bool);
+
+/// Builds an automaton accepting the DKIM relaxed canonicalization of the header blocks
+/// accepted by an automaton.
+///
+/// Following RFC 6376, section 3.4.2, field names are lowercased, folded lines are unfolded,
+/// whitespace runs become a single space, and the whitespace at the end of a line and around
+/// the colon of a field is dropped. A pattern written against the headers of an email then
+/// matches the canonicalized bytes that are signed, and hashed in the circuit. A pattern
+/// starting in the middle of a line may start in a field name or in a value, so both are
+/// accepted. The byte 255 priming `^` starts the header block.
+///
+/// # Arguments
+///
+/// * `a` - The automaton matching raw header bytes.
+///
+/// # Returns
+///
+/// A `Result` containing the automaton of the canonicalized bytes, or a `CompilerError` if it
+/// accepts no input.
+pub fn dkim_relaxed_header(a: &RegexAndDFA) -> Result<RegexAndDFA, CompilerError> {
+    let op_a = Operand::new(a);
+    // Adds the nodes reached by reading raw bytes that release no canonicalized byte, and the
+    // end of the input from the accepting states.
+    let closure = |nodes: BTreeSet<RelaxedNode>| {
+        let mut nodes = nodes;
+        let mut stack = nodes.iter().cloned().collect_vec();
+        while let Some((state, header, pending, ended)) = stack.pop() {
+            if ended || !pending.is_empty() {
+                continue;
+            }
+            let mut next = vec![];
+            if op_a.accepts(Some(state)) {
+                next.push((state, header, header.flush(), true));
+            }
+            for byte in 0..=u8::MAX {
+                if let Some(next_state) = op_a.step(Some(state), byte) {
+                    let (next_header, out) = header.step(byte);
+                    if out.is_empty() {
+                        next.push((next_state, next_header, out, false));
+                    }
+                }
+            }
+            for node in next {
+                if nodes.insert(node.clone()) {
+                    stack.push(node);
+                }
+            }
+        }
+        nodes
+    };
+    let start = [
+        RelaxedHeader::default(),
+        RelaxedHeader {
+            in_value: true,
+            value_started: true,
+            ..Default::default()
+        },
+    ]
+    .iter()
+    .map(|&header| (0, header, vec![], false))
+    .collect();
+    let dfa = explore(
+        closure(start),
+        |nodes, byte| {
+            let mut next = BTreeSet::new();
+            for (state, header, pending, ended) in nodes {
+                match pending.split_first() {
+                    Some((&first, rest)) if first == byte => {
+                        next.insert((*state, *header, rest.to_vec(), *ended));
+                    }
+                    Some(_) => {}
+                    None if *ended => {}
+                    None => {
+                        for raw in 0..=u8::MAX {
+                            if let Some(next_state) = op_a.step(Some(*state), raw) {
+                                let (next_header, out) = header.step(raw);
+                                if out.first() == Some(&byte) {
+                                    next.insert((
+                                        next_state,
+                                        next_header,
+                                        out[1..].to_vec(),
+                                        false,
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            closure(next)
+        },
+        |nodes| {
+            nodes
+                .iter()
+                .any(|(_, _, pending, ended)| *ended && pending.is_empty())
+        },
+    );
+    to_regex_and_dfa(dfa, format!("dkim-relaxed({})", a.regex_pattern))
+}
+
+/// The edits counted by `approximate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditDistance {
+    /// Substituted bytes only, so the input has the length of an exact match.
+    Hamming,
+    /// Substituted, inserted and deleted bytes.
+    Levenshtein,
+}
+
+/// Builds an automaton accepting the inputs that are at most `max_edits` edits away from an
+/// input accepted by an automaton, e.g. a keyword with up to one typo.
+///
+/// The automaton runs as a set of (state, edits) pairs, keeping the fewest edits per state,
+/// which is then determinized. Its size grows quickly with `max_edits` and the size of the
+/// automaton, so it is meant for literals and simple patterns. The byte 255 priming `^` is
+/// never edited.
+///
+/// # Arguments
+///
+/// * `a` - The automaton matching the exact inputs.
+/// * `max_edits` - The number of edits allowed.
+/// * `distance` - The edits counted.
+///
+/// # Returns
+///
+/// A `Result` containing the automaton of the approximate inputs, or a `CompilerError` if it
+/// accepts no input.
+pub fn approximate(
+    a: &RegexAndDFA,
+    max_edits: usize,
+    distance: EditDistance,
+) -> Result<RegexAndDFA, CompilerError> {
+    let op_a = Operand::new(a);
+    // The states reached by one byte other than the priming one, through which a
+    // substitution or a deletion moves.
+    let successors = op_a
+        .delta
+        .iter()
+        .map(|row| {
+            row[..255]
+                .iter()
+                .flatten()
+                .copied()
+                .collect::<BTreeSet<_>>()
+        })
+        .collect_vec();
+    let insert = |nodes: &mut BTreeMap<usize, usize>, state: usize, edits: usize| {
+        if edits <= max_edits && nodes.get(&state).is_none_or(|&fewest| edits < fewest) {
+            nodes.insert(state, edits);
+            true
+        } else {
+            false
+        }
+    };
+    // Adds the deletions of the bytes of an exact match.
+    let closure = |mut nodes: BTreeMap<usize, usize>| {
+        if distance == EditDistance::Levenshtein {
+            let mut stack = nodes
+                .iter()
+                .map(|(&state, &edits)| (state, edits))
+                .collect_vec();
+            while let Some((state, edits)) = stack.pop() {
+                for &next in &successors[state] {
+                    if insert(&mut nodes, next, edits + 1) {
+                        stack.push((next, edits + 1));
+                    }
+                }
+            }
+        }
+        nodes
+    };
+    let dfa = explore(
+        closure(BTreeMap::from([(0, 0)])),
+        |nodes, byte| {
+            let mut next = BTreeMap::new();
+            for (&state, &edits) in nodes {
+                if let Some(matched) = op_a.step(Some(state), byte) {
+                    insert(&mut next, matched, edits);
+                }
+                if byte == u8::MAX {
+                    continue;
+                }
+                for &substituted in &successors[state] {
+                    insert(&mut next, substituted, edits + 1);
+                }
+                if distance == EditDistance::Levenshtein {
+                    insert(&mut next, state, edits + 1);
+                }
+            }
+            closure(next)
+        },
+        |nodes| nodes.keys().any(|&state| op_a.accepts(Some(state))),
+    );
+    let name = match distance {
+        EditDistance::Hamming => "hamming",
+        EditDistance::Levenshtein => "levenshtein",
+    };
+    to_regex_and_dfa(dfa, format!("{name}<={max_edits}({})", a.regex_pattern))
+}
+
+/// Anchors an automaton at the start of the input, so that the Noir matcher, which restarts
+/// from the start state whenever a match fails, accepts exactly the inputs it accepts.
+///
+/// Restarting would let the matcher accept any input with an accepted suffix, e.g.
+/// `spamspam` for the complement of `spam`. The anchored automaton only leaves its start
+/// state on the byte 255, which the matcher takes before the input as for `^`, so a restart
+/// never leads to a match. The other transitions on the byte 255 are never taken, as for
+/// every pattern. The captures are dropped, as in the other operations.
+///
+/// # Arguments
+///
+/// * `a` - The automaton to anchor.
+///
+/// # Returns
+///
+/// A `Result` containing the anchored automaton, or a `CompilerError` if it accepts no input.
+pub(crate) fn anchor_start(a: &RegexAndDFA) -> Result<RegexAndDFA, CompilerError> {
+    let op_a = Operand::new(a);
+    // `None` waits for the byte 255 taken before the input.
+    let dfa = explore(
+        None,
+        |state, byte| match state {
+            None if byte == u8::MAX => Some(Some(0)),
+            None => Some(None),
+            Some(s) => Some(op_a.step(*s, byte)),
+        },
+        |state| state.is_some_and(|s| op_a.accepts(s)),
+    );
+    let anchored = to_regex_and_dfa(dfa, a.regex_pattern.clone())?;
+    Ok(RegexAndDFA {
+        whole_input: false,
+        ..anchored
+    })
+}
+
+/// Builds the complete DFA of the inputs accepted by a compiled regex.
+fn dense(a: &RegexAndDFA) -> DenseDFA {
+    let op_a = Operand::new(a);
+    explore(Some(0), |&s, byte| op_a.step(s, byte), |&s| op_a.accepts(s))
+}
+
+/// Decides whether two automata accept the same inputs, by checking that their minimal DFAs
+/// are isomorphic.
+///
+/// # Arguments
+///
+/// * `a` - The first automaton.
+/// * `b` - The second automaton.
+///
+/// # Returns
+///
+/// `true` if every input accepted by one automaton is accepted by the other.
+pub fn equivalent(a: &RegexAndDFA, b: &RegexAndDFA) -> bool {
+    let (min_a, min_b) = (minimize(&dense(a)), minimize(&dense(b)));
+    // Both are numbered canonically, so they are isomorphic exactly when they are equal.
+    min_a.accept == min_b.accept && min_a.delta == min_b.delta
+}
+
+/// Finds a shortest input accepted by exactly one of two automata.
+///
+/// # Arguments
+///
+/// * `a` - The first automaton.
+/// * `b` - The second automaton.
+///
+/// # Returns
+///
+/// The input, with whether `a` accepts it, or `None` if the automata are equivalent.
+pub fn distinguishing_input(a: &RegexAndDFA, b: &RegexAndDFA) -> Option<(Vec<u8>, bool)> {
+    let (op_a, op_b) = (Operand::new(a), Operand::new(b));
+    let start = (Some(0), Some(0));
+    let mut parents = BTreeMap::from([(start, None)]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(pair @ (s, t)) = queue.pop_front() {
+        if op_a.accepts(s) != op_b.accepts(t) {
+            let mut input = vec![];
+            let mut current = pair;
+            while let Some(&Some((parent, byte))) = parents.get(&current) {
+                input.push(byte);
+                current = parent;
+            }
+            input.reverse();
+            return Some((input, op_a.accepts(s)));
+        }
+        for byte in 0..=u8::MAX {
+            let next = (op_a.step(s, byte), op_b.step(t, byte));
+            if let Entry::Vacant(entry) = parents.entry(next) {
+                entry.insert(Some((pair, byte)));
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+/// A regex over bytes, built while eliminating the states of a DFA.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Re {
+    Set(BTreeSet<u8>),
+    Concat(Vec<Re>),
+    Alt(Vec<Re>),
+    Star(Box<Re>),
+    Plus(Box<Re>),
+    Opt(Box<Re>),
+}
+
+impl Re {
+    /// The regex matching only the empty input.
+    fn empty() -> Self {
+        Re::Concat(vec![])
+    }
+
+    fn is_empty(&self) -> bool {
+        matches!(self, Re::Concat(items) if items.is_empty())
+    }
+
+    /// Builds the alternation of two regexes, merging their byte sets and turning an empty
+    /// branch into `?`.
+    fn alt(a: Re, b: Re) -> Re {
+        let mut optional = false;
+        let mut bytes: Option<BTreeSet<u8>> = None;
+        let mut items: Vec<Re> = vec![];
+        for item in vec![a, b].into_iter().flat_map(|re| match re {
+            Re::Alt(items) => items,
+            re => vec![re],
+        }) {
+            match item {
+                re if re.is_empty() => optional = true,
+                Re::Opt(re) => {
+                    optional = true;
+                    items.push(*re);
+                }
+                Re::Set(set) => match &mut bytes {
+                    Some(bytes) => bytes.extend(set),
+                    None => {
+                        bytes = Some(set);
+                        // Keep the place of the first byte set.
+                        items.push(Re::Set(BTreeSet::new()));
+                    }
+                },
+                re => items.push(re),
+            }
+        }
+        let mut unique = vec![];
+        for item in items {
+            let item = match item {
+                Re::Set(set) if set.is_empty() => Re::Set(bytes.take().unwrap_or_default()),
+                item => item,
+            };
+            if !unique.contains(&item) {
+                unique.push(item);
+            }
+        }
+        let re = match unique.len() {
+            0 => return Re::empty(),
+            1 => unique.pop().expect("one alternative"),
+            _ => Re::Alt(unique),
+        };
+        match re {
+            re if !optional => re,
+            Re::Plus(re) | Re::Star(re) => Re::Star(re),
+            re @ Re::Opt(_) => re,
+            re => Re::Opt(Box::new(re)),
+        }
+    }
+
+    /// Builds the concatenation of regexes, turning `x x*` and `x* x` into `x+`.
+    fn concat(items: Vec<Re>) -> Re {
+        let mut out: Vec<Re> = vec![];
+        for item in items.into_iter().flat_map(|re| match re {
+            Re::Concat(items) => items,
+            re => vec![re],
+        }) {
+            if let Re::Star(re) = &item {
+                let repeated = match &**re {
+                    Re::Concat(items) => items.as_slice(),
+                    re => std::slice::from_ref(re),
+                };
+                if out.ends_with(repeated) {
+                    out.truncate(out.len() - repeated.len());
+                    out.push(Re::Plus(re.clone()));
+                    continue;
+                }
+            }
+            let merged = match (out.last(), &item) {
+                (Some(Re::Star(re)), last) if last == &**re => Some(Re::Plus(re.clone())),
+                (Some(Re::Star(a)), Re::Star(b)) if a == b => Some(Re::Star(a.clone())),
+                _ => None,
+            };
+            match merged {
+                Some(re) => *out.last_mut().expect("a previous item") = re,
+                None => out.push(item),
+            }
+        }
+        match out.len() {
+            1 => out.pop().expect("one item"),
+            _ => Re::Concat(out),
+        }
+    }
+
+    /// Builds the repetition of a regex.
+    fn star(re: Re) -> Re {
+        match re {
+            re if re.is_empty() => re,
+            Re::Star(re) | Re::Plus(re) | Re::Opt(re) => Re::Star(re),
+            re => Re::Star(Box::new(re)),
+        }
+    }
+
+    /// Writes the regex, wrapping alternations in a group where they would bind wrongly.
+    fn render(&self, out: &mut String) {
+        match self {
+            Re::Set(bytes) => render_set(bytes, false, out),
+            Re::Concat(items) if items.is_empty() => out.push_str("(?:)"),
+            Re::Concat(items) => {
+                for item in items {
+                    match item {
+                        Re::Alt(_) => render_group(item, out),
+                        item => item.render(out),
+                    }
+                }
+            }
+            Re::Alt(items) => {
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        out.push('|');
+                    }
+                    item.render(out);
+                }
+            }
+            Re::Star(re) | Re::Plus(re) | Re::Opt(re) => {
+                match &**re {
+                    Re::Set(bytes) => render_set(bytes, true, out),
+                    re => render_group(re, out),
+                }
+                out.push(match self {
+                    Re::Star(_) => '*',
+                    Re::Plus(_) => '+',
+                    _ => '?',
+                });
+            }
+        }
+    }
+}
+
+/// Writes a regex inside a non-capturing group.
+fn render_group(re: &Re, out: &mut String) {
+    out.push_str("(?:");
+    re.render(out);
+    out.push(')');
+}
+
+/// Writes a byte of a regex, escaping it if needed.
+///
+/// # Arguments
+///
+/// * `byte` - The byte.
+/// * `in_class` - Whether the byte is written inside a character class.
+/// * `out` - The regex written so far.
+fn render_byte(byte: u8, in_class: bool, out: &mut String) {
+    match byte {
+        b'\n' => out.push_str("\\n"),
+        b'\r' => out.push_str("\\r"),
+        b'\t' => out.push_str("\\t"),
+        b' '..=b'~' => {
+            let c = byte as char;
+            let escaped = if in_class {
+                matches!(c, '\\' | ']' | '[' | '^' | '-' | '&' | '~')
+            } else {
+                regex_syntax::is_meta_character(c)
+            };
+            if escaped {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        _ => out.push_str(&format!("\\x{byte:02X}")),
+    }
+}
+
+/// Writes a set of bytes as a single byte, `.` or a character class, using the negated class
+/// when it is shorter. The byte 255 alone is written as `^`, which primes the automata.
+///
+/// # Arguments
+///
+/// * `bytes` - The non-empty set of bytes.
+/// * `repeated` - Whether the set is followed by a repetition operator, which `^` cannot be.
+/// * `out` - The regex written so far.
+fn render_set(bytes: &BTreeSet<u8>, repeated: bool, out: &mut String) {
+    if bytes.len() == 256 {
+        out.push_str("(?s-u:.)");
+        return;
+    }
+    if bytes.len() == 1 && bytes.contains(&u8::MAX) && !repeated {
+        out.push('^');
+        return;
+    }
+    let complement = (0..=u8::MAX)
+        .filter(|byte| !bytes.contains(byte))
+        .collect::<BTreeSet<_>>();
+    let negated = complement.len() < bytes.len();
+    let class = if negated { &complement } else { bytes };
+    // Outside ASCII, or negated, the class must match single bytes rather than characters.
+    let bytewise = negated || class.iter().any(|&byte| byte >= 0x80);
+    let mut rendered = String::new();
+    if class.len() == 1 && !negated {
+        render_byte(*class.first().expect("one byte"), false, &mut rendered);
+    } else {
+        rendered.push_str(if negated { "[^" } else { "[" });
+        for (_, run) in &class
+            .iter()
+            .enumerate()
+            .chunk_by(|&(idx, &byte)| byte as usize - idx)
+        {
+            let run = run.map(|(_, &byte)| byte).collect_vec();
+            render_byte(run[0], true, &mut rendered);
+            if run.len() > 2 {
+                rendered.push('-');
+            }
+            if run.len() > 1 {
+                render_byte(run[run.len() - 1], true, &mut rendered);
+            }
+        }
+        rendered.push(']');
+    }
+    if bytewise {
+        out.push_str(&format!("(?-u:{rendered})"));
+    } else {
+        out.push_str(&rendered);
+    }
+}
+
+/// Reconstructs a regex accepting the same inputs as an automaton, so that an automaton built
+/// with the other operations can be reviewed as a pattern.
+///
+/// The regex is derived from the minimal DFA by state elimination, so automata accepting the
+/// same inputs give the same regex. It is end-anchored unless every accepted input may be
+/// followed by any bytes, the byte 255 priming the automata is written as `^`, and bytes
+/// outside ASCII are matched bytewise with `(?-u:...)`.
+///
+/// # Arguments
+///
+/// * `a` - The automaton.
+///
+/// # Returns
+///
+/// A `Result` containing the regex, or a `CompilerError` if the automaton accepts no input.
+pub fn to_regex(a: &RegexAndDFA) -> Result<String, CompilerError> {
+    let dfa = minimize(&dense(a));
+    let num_states = dfa.delta.len();
+    let sink = |state: usize, accept: bool| {
+        dfa.accept[state] == accept && dfa.delta[state].iter().all(|&next| next == state)
+    };
+    let dead = (0..num_states).find(|&state| sink(state, false));
+    if dead == Some(0) {
+        return Err(CompilerError::GenericError(
+            "the automaton cannot match any input".to_string(),
+        ));
+    }
+    // When only a state accepting anything that follows is accepting, the inputs reaching it
+    // describe the automaton without an end anchor.
+    let accepting = (0..num_states)
+        .filter(|&state| dfa.accept[state])
+        .collect_vec();
+    let prefix = accepting.len() == 1 && sink(accepting[0], true);
+
+    // The generalized automaton has a new start state and, unless the regex is a prefix, a
+    // new final state, with the regexes of the transitions on its edges.
+    let (start, last) = (num_states, num_states + 1);
+    let end = if prefix { accepting[0] } else { last };
+    let mut edges: BTreeMap<(usize, usize), Re> = BTreeMap::from([((start, 0), Re::empty())]);
+    for state in (0..num_states).filter(|&state| Some(state) != dead) {
+        if prefix && state == end {
+            continue;
+        }
+        if !prefix && dfa.accept[state] {
+            edges.insert((state, last), Re::empty());
+        }
+        let mut targets: BTreeMap<usize, BTreeSet<u8>> = BTreeMap::new();
+        for byte in 0..=u8::MAX {
+            let next = dfa.delta[state][byte as usize];
+            if Some(next) != dead {
+                targets.entry(next).or_default().insert(byte);
+            }
+        }
+        for (next, bytes) in targets {
+            edges.insert((state, next), Re::Set(bytes));
+        }
+    }
+
+    let mut remaining = (0..num_states)
+        .filter(|&state| Some(state) != dead && state != end)
+        .collect::<BTreeSet<_>>();
+    while !remaining.is_empty() {
+        // Eliminate the state adding the fewest edges first, which keeps the regex short.
+        let degree = |state: usize| {
+            let ins = edges
+                .keys()
+                .filter(|&&(from, to)| to == state && from != state)
+                .count();
+            let outs = edges
+                .keys()
+                .filter(|&&(from, to)| from == state && to != state)
+                .count();
+            ins * outs
+        };
+        let state = *remaining
+            .iter()
+            .min_by_key(|&&state| (degree(state), state))
+            .expect("a remaining state");
+        remaining.remove(&state);
+        let repeated = edges.remove(&(state, state)).map(Re::star);
+        let incoming = edges
+            .iter()
+            .filter(|&(&(_, to), _)| to == state)
+            .map(|(&(from, _), re)| (from, re.clone()))
+            .collect_vec();
+        let outgoing = edges
+            .iter()
+            .filter(|&(&(from, _), _)| from == state)
+            .map(|(&(_, to), re)| (to, re.clone()))
+            .collect_vec();
+        edges.retain(|&(from, to), _| from != state && to != state);
+        for (from, before) in &incoming {
+            for (to, after) in &outgoing {
+                let path = Re::concat(
+                    vec![Some(before.clone()), repeated.clone(), Some(after.clone())]
+                        .into_iter()
+                        .flatten()
+                        .collect(),
+                );
+                let re = match edges.remove(&(*from, *to)) {
+                    Some(re) => Re::alt(re, path),
+                    None => path,
+                };
+                edges.insert((*from, *to), re);
+            }
+        }
+    }
+
+    let re = edges
+        .remove(&(start, end))
+        .expect("the start state reaches an accepting state");
+    let mut regex = String::new();
+    match (&re, prefix) {
+        (re, true) if re.is_empty() => {}
+        (Re::Alt(_), false) => render_group(&re, &mut regex),
+        (re, _) => re.render(&mut regex),
+    }
+    if !prefix {
+        regex.push('$');
+    }
+    Ok(regex)
+}
+
+/// Builds the DFA matching exactly the reversed inputs of a DFA.
+///
+/// # Arguments
+///
+/// * `dfa` - The DFA to reverse, matching its inputs exactly.
+/// * `primed` - Whether the reversed inputs must be preceded by the byte 255, so that the
+///   reversed DFA is anchored at the start where the original one is anchored at the end.
+///
+/// # Returns
+///
+/// The reversed DFA, minimized and with state 0 as the start state.
+pub(crate) fn reverse_dfa(dfa: &DFAGraph, primed: bool) -> DFAGraph {
+    let start = dfa.states.first().map_or(0, |state| state.state_id);
+    let mut predecessors: BTreeMap<(usize, u8), BTreeSet<usize>> = BTreeMap::new();
+    for state in &dfa.states {
+        for (&next, bytes) in &state.transitions {
+            for &byte in bytes {
+                predecessors
+                    .entry((next, byte))
+                    .or_default()
+                    .insert(state.state_id);
+            }
+        }
+    }
+    let accepting = dfa
+        .states
+        .iter()
+        .filter(|state| state.state_type == "accept")
+        .map(|state| state.state_id)
+        .collect::<BTreeSet<_>>();
+    // The reversed run starts from the accepting states, with `None` waiting for the byte 255.
+    let reversed = explore(
+        (!primed).then(|| accepting.clone()),
+        |states, byte| match states {
+            None if byte == u8::MAX => Some(accepting.clone()),
+            None => Some(BTreeSet::new()),
+            Some(states) => Some(
+                states
+                    .iter()
+                    .filter_map(|&state| predecessors.get(&(state, byte)))
+                    .flatten()
+                    .copied()
+                    .collect(),
+            ),
+        },
+        |states| {
+            states
+                .as_ref()
+                .is_some_and(|states| states.contains(&start))
+        },
+    );
+    to_regex_and_dfa(reversed, String::new())
+        .expect("the reversed DFA accepts the reversed inputs")
+        .dfa
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{noir::MatcherRun, regex::match_string_with_dfa_graph, structs::NoirOptions};
+
+    fn matches(regex_and_dfa: &RegexAndDFA, input: &str) -> bool {
+        match_string_with_dfa_graph(&regex_and_dfa.dfa, input)
+    }
+
+    #[test]
+    fn test_algebra() {
+        let a = from_regex("[a-z]+@[a-z]+\\.com$").unwrap();
+        let b = from_regex("[a-z]+@spam\\.com$").unwrap();
+        let digits = from_regex("[0-9]+$").unwrap();
+
+        let both = union(&a, &digits).unwrap();
+        assert!(matches(&both, "bob@mail.com"));
+        assert!(matches(&both, "42"));
+        assert!(!matches(&both, "bob42"));
+
+        let allowed = intersection(&a, &complement(&b).unwrap()).unwrap();
+        assert!(matches(&allowed, "bob@mail.com"));
+        assert!(!matches(&allowed, "bob@spam.com"));
+        assert!(!matches(&allowed, "42"));
+        assert!(intersection(&a, &digits).is_err());
+
+        let tagged = concatenation(&digits, &a).unwrap();
+        assert!(matches(&tagged, "7bob@mail.com"));
+        assert!(!matches(&tagged, "bob@mail.com"));
+        assert!(!matches(&tagged, "7"));
+
+        // Without an end anchor, anything may follow a match.
+        let prefix = from_regex("ab").unwrap();
+        assert!(matches(&union(&prefix, &digits).unwrap(), "abc"));
+        assert!(!matches(&complement(&prefix).unwrap(), "abc"));
+        assert!(matches(&complement(&prefix).unwrap(), "ba"));
+
+        // The result has no dead state, and the start state is state 0.
+        let dfa = &allowed.dfa;
+        assert!(dfa
+            .states
+            .iter()
+            .enumerate()
+            .all(|(idx, state)| idx == state.state_id));
+        assert!(dfa
+            .states
+            .iter()
+            .all(|state| !state.transitions.is_empty() || state.state_type == "accept"));
+    }
+
+    #[test]
+    fn test_matcher_reads_the_whole_input() {
+        let accepts = |regex_and_dfa: &RegexAndDFA, input: &str| {
+            let units = input.bytes().map(|byte| byte as u32).collect_vec();
+            MatcherRun::new(regex_and_dfa, &NoirOptions::default(), false)
+                .run(&units)
+                .is_some()
+        };
+        let not_spam = complement(&from_regex("spam").unwrap()).unwrap();
+        for input in ["spam", "spamX", "spamspam"] {
+            assert!(!accepts(&not_spam, input), "{}", input);
+        }
+        for input in ["", "spa", "xspam"] {
+            assert!(accepts(&not_spam, input), "{}", input);
+        }
+
+        let a = from_regex("[a-z]+@[a-z]+\\.com").unwrap();
+        let b = from_regex("[a-z]+@spam\\.com").unwrap();
+        let allowed = intersection(&a, &complement(&b).unwrap()).unwrap();
+        assert!(accepts(&allowed, "bob@good.com"));
+        assert!(accepts(&allowed, "bob@good.com bob@spam.com"));
+        assert!(!accepts(&allowed, "bob@spam.com bob@good.com"));
+        assert!(!accepts(&allowed, "bob@spam.comx@good.com"));
+
+        // The inputs accepted by the circuit are those accepted by the automaton.
+        let both = union(&from_regex("ab$").unwrap(), &from_regex("[0-9]+$").unwrap()).unwrap();
+        for input in ["ab", "42", "xab", "ab42", "4ab", ""] {
+            assert_eq!(accepts(&both, input), matches(&both, input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_dkim_relaxed_header() {
+        let subject = from_regex(r"(\r\n|^)Subject: [A-Za-z ]+\r\n$").unwrap();
+        let relaxed = dkim_relaxed_header(&subject).unwrap();
+        assert!(matches(&relaxed, "\r\nsubject:Hello world\r\n"));
+        assert!(!matches(&relaxed, "\r\nSubject: Hello world\r\n"));
+        assert!(!matches(&relaxed, "\r\nsubject:Hello  world\r\n"));
+        assert!(!matches(&relaxed, "\r\nsubject:Hello world \r\n"));
+
+        // Folded lines are unfolded, keeping a single space.
+        let folded = from_regex(r"\r\nTo: a,\r\n\tb\r\n$").unwrap();
+        let relaxed = dkim_relaxed_header(&folded).unwrap();
+        assert!(matches(&relaxed, "\r\nto:a, b\r\n"));
+        assert!(!matches(&relaxed, "\r\nto:a,\r\n\tb\r\n"));
+    }
+
+    #[test]
+    fn test_approximate() {
+        let keyword = from_regex("secret$").unwrap();
+        let typo = approximate(&keyword, 1, EditDistance::Hamming).unwrap();
+        assert!(matches(&typo, "secret"));
+        assert!(matches(&typo, "secr3t"));
+        assert!(!matches(&typo, "s3cr3t"));
+        assert!(!matches(&typo, "secet"));
+
+        let typo = approximate(&keyword, 1, EditDistance::Levenshtein).unwrap();
+        assert!(matches(&typo, "secr3t"));
+        assert!(matches(&typo, "secet"));
+        assert!(matches(&typo, "secrret"));
+        assert!(!matches(&typo, "secrrett"));
+
+        let typos = approximate(&keyword, 2, EditDistance::Levenshtein).unwrap();
+        assert!(matches(&typos, "s3cr3t"));
+        assert!(matches(&typos, "scret!"));
+        assert!(!matches(&typos, "s3cr3"));
+    }
+
+    #[test]
+    fn test_equivalent() {
+        let a = from_regex("(a|b)*abb$").unwrap();
+        let b = from_regex("[ab]*a(bb)$").unwrap();
+        assert!(equivalent(&a, &b));
+        assert_eq!(distinguishing_input(&a, &b), None);
+
+        // The automata differ in size but not in the inputs they accept.
+        let prefix = from_regex("ab").unwrap();
+        let padded = from_regex("ab+").unwrap();
+        assert!(equivalent(&prefix, &padded));
+
+        let digits = from_regex("[0-9]+$").unwrap();
+        let number = from_regex("[1-9][0-9]*$").unwrap();
+        assert!(!equivalent(&digits, &number));
+        assert_eq!(
+            distinguishing_input(&digits, &number),
+            Some((b"0".to_vec(), true))
+        );
+    }
+
+    #[test]
+    fn test_to_regex() {
+        assert_eq!(to_regex(&from_regex("ab").unwrap()).unwrap(), "ab");
+        assert_eq!(to_regex(&from_regex("ab+").unwrap()).unwrap(), "ab");
+        assert_eq!(
+            to_regex(&from_regex("(?:ab)+c$").unwrap()).unwrap(),
+            "(?:ab)+c$"
+        );
+        assert_eq!(
+            to_regex(&from_regex("[0-9]+$").unwrap()).unwrap(),
+            "[0-9]+$"
+        );
+
+        let a = from_regex("[a-z]+@[a-z]+\\.com$").unwrap();
+        let b = from_regex("[a-z]+@spam\\.com$").unwrap();
+        let digits = from_regex("[0-9]+$").unwrap();
+        let composed = vec![
+            union(&a, &digits).unwrap(),
+            intersection(&a, &complement(&b).unwrap()).unwrap(),
+            complement(&from_regex("ab").unwrap()).unwrap(),
+            concatenation(&digits, &a).unwrap(),
+            from_regex("(\\r\\n|^)to:[^\\r\\n]+\\r\\n").unwrap(),
+            from_regex("(?-u:\\xC3[\\x80-\\xBF])+x$").unwrap(),
+            from_regex("(a|b)*abb$").unwrap(),
+        ];
+        for automaton in &composed {
+            let regex = to_regex(automaton).unwrap();
+            assert!(
+                equivalent(&from_regex(&regex).unwrap(), automaton),
+                "{}",
+                regex
+            );
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::noir::MatcherRun;
+
+/// A state of a `CompiledAutomaton`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutomatonState {
+    /// The ID of the state.
+    pub id: usize,
+    /// Whether the state is accepting. Without an end anchor, reaching it completes a match;
+    /// with one, the input must end in it.
+    pub accepting: bool,
+}
+
+/// A transition of a `CompiledAutomaton`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutomatonTransition {
+    /// The state the transition leaves.
+    pub from: usize,
+    /// The state the transition leads to.
+    pub to: usize,
+    /// The inclusive ranges of the code units taking the transition: bytes, or UTF-16 code
+    /// units for `u16` input.
+    pub ranges: Vec<(u32, u32)>,
+    /// The substrings revealing the code unit taking the transition.
+    pub substrings: Vec<usize>,
+}
+
+/// The automaton run by the Noir matcher of a pattern, after the start anchor has been folded
+/// into its initial state and the adjustments asked by the options have been applied.
+///
+/// The matcher starts in `init_state` and feeds every code unit of the input to `next_state`.
+/// On a code unit taking no transition, it restarts from `start_state` and feeds the code unit
+/// again, so a match may begin anywhere in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledAutomaton {
+    /// The state the matcher starts in, where the start anchor (^) still matches.
+    pub init_state: usize,
+    /// The state the matcher restarts from when no transition applies.
+    pub start_state: usize,
+    /// The absorbing state entered from an accepting state on any code unit taking no
+    /// transition, present unless the pattern is end-anchored.
+    pub accept_sink: Option<usize>,
+    /// The states, sorted by ID, including the sink.
+    pub states: Vec<AutomatonState>,
+    /// The transitions, sorted by the states they leave and lead to.
+    pub transitions: Vec<AutomatonTransition>,
+    /// The number of substrings.
+    pub num_substrings: usize,
+    /// The logic of the matcher, run by `simulate`.
+    pub(crate) matcher: MatcherRun,
+}
+
+impl CompiledAutomaton {
+    /// Returns the transitions leaving `state`.
+    pub fn transitions_from(&self, state: usize) -> impl Iterator<Item = &AutomatonTransition> {
+        let first = self
+            .transitions
+            .partition_point(|transition| transition.from < state);
+        self.transitions[first..]
+            .iter()
+            .take_while(move |transition| transition.from == state)
+    }
+
+    /// Returns the transitions revealing their code unit to a substring.
+    pub fn capture_edges(&self) -> impl Iterator<Item = &AutomatonTransition> {
+        self.transitions
+            .iter()
+            .filter(|transition| !transition.substrings.is_empty())
+    }
+
+    /// Returns whether `state` is accepting.
+    pub fn is_accepting(&self, state: usize) -> bool {
+        self.states
+            .binary_search_by_key(&state, |state| state.id)
+            .is_ok_and(|idx| self.states[idx].accepting)
+    }
+
+    /// Returns the state the matcher moves to from `state` on `unit`, or `None` if it restarts
+    /// from `start_state`, as the `next_state` function of the Noir matcher returns 0.
+    pub fn next_state(&self, state: usize, unit: u32) -> Option<usize> {
+        self.transitions_from(state)
+            .find(|transition| {
+                transition
+                    .ranges
+                    .iter()
+                    .any(|&(min, max)| (min..=max).contains(&unit))
+            })
+            .map(|transition| transition.to)
+            .or_else(|| {
+                self.accept_sink
+                    .filter(|&sink| state == sink || self.is_accepting(state))
+            })
+    }
+
+    /// Runs the Noir matcher on an input and returns the substrings its `regex_match` function
+    /// would, to build witnesses or debug an extraction without running `nargo`.
+    ///
+    /// Unlike stepping with `next_state`, it reproduces the capture logic of the circuit: the
+    /// captures cleared on every restart, the code units leading into `accept_sink` left out,
+    /// the bytes of a decomposed regex tagged with the part they belong to, the occurrences
+    /// skipped, the repetitions captured separately, the reversed scan and the lowercase
+    /// folding.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input code units: bytes, or UTF-16 code units for `u16` input.
+    ///
+    /// # Returns
+    ///
+    /// The captures if the matcher accepts the input, or `None` if it fails. There is one per
+    /// substring, or one per repetition of a part with `max_repetitions`, in the order of the
+    /// matcher's return value, and none if the substrings are not extracted.
+    pub fn simulate(&self, input: &[u32]) -> Option<Vec<Vec<u32>>> {
+        self.matcher.run(input)
+    }
+}
@@ -1,11 +1,33 @@
 //! ZK Regex Compiler CLI
 //!
 //! This binary provides a command-line interface for the ZK Regex Compiler.
-//! It supports two main commands: `Decomposed` for working with decomposed regex files,
-//! and `Raw` for working with raw regex strings.
+//! It supports seventeen main commands: `Decomposed` for working with decomposed regex files,
+//! `Raw` for working with raw regex strings, `Manifest` for compiling several patterns
+//! into one Noir library, `Denylist` for proving that none of several patterns occurs,
+//! `Compose` for combining several patterns into one automaton, `Fields` for extracting the
+//! fields of a `key=value` or JSON body into a Noir struct, `Test` for checking the Noir
+//! matchers of several patterns with `nargo test`, `Prove` for proving that an input
+//! matches a pattern, `Init` for creating a Nargo project using a generated matcher, `Migrate`
+//! for converting a Circom zk-regex project into a Noir pattern manifest, `Runtime`
+//! for generating the Noir library of the helpers shared by the matchers, `Diff`
+//! for comparing the automata of two versions of a pattern, `Equivalent` for checking that
+//! two patterns accept the same inputs, `Sample` for generating inputs a pattern accepts,
+//! `Explain` for describing the automaton of a pattern, `Bench` for estimating the size of
+//! its Noir matcher over a range of input lengths, and `Validate` for checking the Noir matcher
+//! against its simulation in Rust.
 //!
 //! # Usage
 //!
+//! Pass `--profile` to any command to print the time spent in each phase of the compiler
+//! and its peak memory to stderr.
+//!
+//! Pass `--progress` to any command to print the states built, rows generated and bytes
+//! written by long compilation steps to stderr every two seconds, so that slow compilations
+//! can be told apart from hung ones.
+//!
+//! Pass `-v` to any command to log the compilation steps and the size of the automata to
+//! stderr, `-vv` to also log every regex part and phase, and `-vvv` to log the phase timings.
+//!
 //! ## Decomposed Command
 //! Process a decomposed regex file:
 //!
@@ -14,11 +36,115 @@
 //! ```
 //!
 //! Options:
-//! - `-d, --decomposed-regex-path <PATH>`: Path to the decomposed regex JSON file (required)
+//! - `-d, --decomposed-regex-path <PATH>`: Path to the decomposed regex file in JSON, JSON5
+//!   (`.json5`) or YAML (`.yaml`, `.yml`), or `-` to read it from stdin (required)
 //! - `-h, --halo2-dir-path <PATH>`: Directory path for Halo2 output
 //! - `-c, --circom-file-path <PATH>`: File path for Circom output
 //! - `-t, --template-name <NAME>`: Template name
-//! - `-g, --gen-substrs`: Generate substrings
+//! - `-n, --noir-file-path <PATH>`: File path for Noir output
+//! - `--example <DIR>`: Directory for a runnable Nargo project calling the Noir matcher on a
+//!   sample input
+//! - `--aztec-contract <DIR>`: Directory for a Nargo project wrapping the Noir matcher in an
+//!   Aztec.nr contract whose private function returns a commitment to every capture
+//! - `--aztec-input-len <N>`: Input length of the Aztec contract function (default: 256)
+//! - `--noir-main <PATH>`: File path for a `main` function calling the Noir matcher of
+//!   `--noir-file-path`, in the same directory, on a private input of `--input-len` code units
+//! - `--noir-main-outputs <captures,commitments,starts,input-hash,match,holes>`: Public outputs of
+//!   the `main` function, in order (default: every value the Noir matcher returns)
+//! - `--noir-input-type <u8|u16|packed>`: Element type of the Noir input array (default: u8)
+//! - `--noir-input-hash <sha256|poseidon>`: Hash the Noir input and return the digest
+//! - `--noir-verify-input-hash`: With `--noir-input-hash`, also emit `regex_match_verified`,
+//!   taking the expected digest as an input and asserting that the input hashes to it
+//! - `--strategy <auto|if-chain|table|mux-tree|keyword>`: Layout of the Noir `next_state` function (default: auto,
+//!   the layout estimated to take the fewest gates)
+//! - `--noir-max-branches <N>`: Split an `if-chain` or `keyword` Noir `next_state` with more
+//!   than N branches into helper functions over ranges of states
+//! - `--noir-state-type <field|integer>`: Type of the Noir automaton state, `integer` using a
+//!   `u8` when the states fit in a byte and a `u32` otherwise (default: field)
+//! - `--noir-compact`: Strip the comments and indentation from the generated Noir
+//! - `--noir-class-predicates`: Test the code unit ranges shared by several Noir `next_state`
+//!   branches through predicates such as `is_digit`
+//! - `--noir-empty-match <allow|reject>`: Whether the Noir matcher accepts an empty match (default: allow)
+//! - `--noir-match-semantics <greedy|first-accept|longest>`: Whether a Noir match is extended greedily, dropping it if the extension fails, ends at the first accepting state, or ends at the last accepting state it went through (default: greedy)
+//! - `--noir-split-modules`: Emit `next_state` and the Noir helpers into separate modules
+//! - `--noir-packed-captures`: Also emit `regex_match_packed_captures`, returning the captures
+//!   packed 31 bytes per Field
+//! - `--noir-byte-source`: Also emit a `ByteSource` trait and `regex_match_source`, accepting
+//!   `[u8; N]`, `BoundedVec<u8, N>` or `PackedBytes` inputs (u8 input only)
+//! - `--noir-field-input`: Also emit `regex_match_fields`, taking the input as `[Field; N]` and
+//!   asserting that every Field fits in an input code unit (u8 or u16 input only)
+//! - `--noir-public-next-state`: Make the Noir `next_state` public and emit a `StateInfo`
+//!   struct describing its initial and accepting states, for matching loops written by hand
+//! - `--noir-capture-digests`: Also emit `regex_match_capture_digests`, returning the SHA-256
+//!   digest of every capture
+//! - `--noir-capture-lens`: Also emit `regex_match_capture_lens`, returning the captures along
+//!   with their lengths
+//! - `--noir-prefix-len`: Also emit `regex_match_prefix_len`, returning the length of the
+//!   longest prefix of a match found in the input without asserting that it matches
+//! - `--noir-match-mask`: Also emit `regex_match_mask`, asserting the match and returning a
+//!   `[bool; N]` marking the input positions it covers
+//! - `--noir-match-len`: Also emit `regex_match_len`, asserting the match and returning the
+//!   number of code units it covers
+//! - `--noir-match-end`: Also emit `regex_match_end`, asserting the match and returning the
+//!   index just past the code unit at which it first reaches an accepting state
+//! - `--noir-redaction <CHAR>`: Also emit `regex_match_redacted`, proving that a redacted copy
+//!   of the input equals it except that the code units of the match are replaced with CHAR
+//! - `--noir-match-bound <K>`: Assert in `regex_match` that the match is completed within the
+//!   first K code units of the input, the rest being padding
+//! - `--noir-match-window`: Also emit `regex_match_window`, taking `start` and `end` indices
+//!   and matching only the input between them, e.g. a region located by an outer circuit
+//! - `--noir-match-at`: Also emit `regex_match_at`, verifying a match claimed to start at a
+//!   witness index within a window of L code units, at a cost independent of the input length
+//! - `--noir-capture-count <K>`: Assert in `regex_match` that the match produces exactly K
+//!   non-empty captures
+//! - `--noir-fixed-len <N>`: Also emit `regex_match_fixed`, a non-generic entry point taking
+//!   an input of exactly N code units, for ABIs and tooling without numeric generics
+//! - `--noir-input-segments <K>`: Also emit `regex_match_segments`, matching K input arrays
+//!   as one input, e.g. a header followed by a body
+//! - `--noir-corpus <FILE>`: Run the inputs of FILE, one per line escaped as by `sample`,
+//!   through the automaton and check the `next_state` branches they take most first; the
+//!   states and transitions they spend their bytes in are listed in `--report`
+//! - `--noir-lowercase-captures`: Fold the Noir captures to ASCII lowercase in the circuit
+//! - `--noir-capture-starts`: Also return the index in the input at which every Noir capture
+//!   begins
+//! - `--noir-occurrence <K>`: Match and capture only the K-th occurrence of the pattern in the
+//!   Noir input, counted from 1
+//! - `--noir-reverse`: Scan the Noir input from its end with the reversed pattern, matching
+//!   its last occurrence
+//! - `--noir-reverse-window <W>`: With `--noir-reverse`, only scan the last W code units
+//! - `--noir-input-alphabet <printable|declared>`: Assert that every input code unit is printable
+//!   ASCII or appears in the pattern
+//! - `--noir-use <PATH>`: Path imported with `use` by the generated Noir code; an imported
+//!   `sha256`, `sha256_var` or `Poseidon2` replaces the one from `std` (repeatable)
+//! - `--noir-dependency <NAME=TOML>`: Dependency added to the generated `Nargo.toml`, e.g.
+//!   `sha256={ tag = "v0.1.0", git = "https://github.com/noir-lang/sha256" }` (repeatable)
+//! - `--noir-runtime <NAME>`: Import the capture helpers from the Noir runtime library of
+//!   this name, generated by the `runtime` command, instead of emitting them
+//! - `--noir-capture-overflow <fail|keep-first|keep-last>`: What the Noir matcher does when a
+//!   repeated part matches more times than `max_repetitions` (default: fail)
+//! - `-g, --gen-substrs <true|false|INDICES>`: Generate all, none or only the listed
+//!   substrings, e.g. `0,2`
+//! - `--var <NAME=VALUE>`: Value of a `{{NAME}}` variable in the pattern, matched literally
+//!   (repeatable)
+//! - `--lenient-newlines`: Let every `\r\n` of the pattern also match a bare `\n`
+//! - `--dialect <rust|re2|pcre|ere>`: Regex syntax the pattern is written in (default: rust)
+//! - `--permissive-escapes`: Match the character of an unknown escape such as `\q` literally
+//!   instead of failing
+//! - `--max-estimated-gates <GATES>`: Fail before writing any output if the Noir matcher is
+//!   estimated to take more gates than this for inputs of `--input-len` code units
+//! - `--input-len <N>`: Input length the gate budget is checked for and the `auto` strategy is
+//!   chosen for (default: 1024)
+//! - `--report <PATH>`: Write a JSON summary of the compilation, with the options used, the
+//!   DFA size, the estimated Noir constraints per input unit, warnings and the SHA-256 hash of
+//!   every output file
+//! - `--emit <nr,json,dot,html,report,abi>`: Artifacts written together to `--out`: the Noir
+//!   matcher (`<STEM>.nr`), the regex and its DFA as JSON (`<STEM>.json`), the automaton as a
+//!   Graphviz DOT graph (`<STEM>.dot`), a self-contained HTML page with an interactive graph,
+//!   the transition table and a test string simulator (`<STEM>.html`), the compile report
+//!   of the other artifacts (`<STEM>.report.json`) and the signatures of the public functions
+//!   of the Noir matcher with the captures they return as JSON (`<STEM>.abi.json`). Nothing is
+//!   written unless all of them are generated
+//! - `--out <STEM>`: Path stem of the artifacts of `--emit`, e.g. `out/email`
 //!
 //! Example:
 //! ```
@@ -29,34 +155,787 @@
 //! Process a raw regex string:
 //!
 //! ```
-//! zk-regex raw --raw-regex <REGEX> [OPTIONS]
+//! zk-regex raw (--raw-regex <REGEX> | --pattern-file <PATH> | --preset <NAME>) [OPTIONS]
 //! ```
 //!
 //! Options:
-//! - `-r, --raw-regex <REGEX>`: Raw regex string (required)
+//! - `-r, --raw-regex <REGEX>`: Raw regex string
+//! - `-p, --pattern-file <PATH>`: File holding the raw regex, or `-` to read it from stdin, so
+//!   that long patterns need no shell escaping. A single trailing newline is ignored
+//! - `--preset <url|ipv4|iso8601-timestamp|email|decimal-amount>`: Use a pattern of the
+//!   `presets` module as the raw regex
 //! - `-s, --substrs-json-path <PATH>`: Path to substrings JSON file
+//! - `--substr-range <FROM-TO,...>`: Transitions of a substring, given on the command line
+//!   instead of in a substrings JSON file, e.g. `--substr-range 3-4,4-4` (repeatable, one
+//!   substring per flag)
+//! - `--substr-name <NAME>`: Name of a substring given by `--substr-range`, as the `names` of a
+//!   substrings JSON file (repeatable, one name per `--substr-range` in the same order)
+//! - `--capture-groups`: Capture the groups of the regex, numbered from 1 as in regex engines
+//!   and nested groups included, instead of the substrings of a JSON file
+//! - `--accept-states <STATE,...>`: States of the DFA accepted instead of those derived from
+//!   the regex, as numbered by `explain --raw`, so that a circuit can claim that the input
+//!   reached a milestone of the pattern, e.g. `--accept-states 3`
 //! - `-h, --halo2-dir-path <PATH>`: Directory path for Halo2 output
 //! - `-c, --circom-file-path <PATH>`: File path for Circom output
 //! - `-t, --template-name <NAME>`: Template name
-//! - `-g, --gen-substrs`: Generate substrings
+//! - `-n, --noir-file-path <PATH>`: File path for Noir output
+//! - `--example <DIR>`: Directory for a runnable Nargo project calling the Noir matcher on a
+//!   sample input
+//! - `--aztec-contract <DIR>`: Directory for a Nargo project wrapping the Noir matcher in an
+//!   Aztec.nr contract whose private function returns a commitment to every capture
+//! - `--aztec-input-len <N>`: Input length of the Aztec contract function (default: 256)
+//! - `--noir-main <PATH>`: File path for a `main` function calling the Noir matcher of
+//!   `--noir-file-path`, in the same directory, on a private input of `--input-len` code units
+//! - `--noir-main-outputs <captures,commitments,starts,input-hash,match,holes>`: Public outputs of
+//!   the `main` function, in order (default: every value the Noir matcher returns)
+//! - `--noir-input-type <u8|u16|packed>`: Element type of the Noir input array (default: u8)
+//! - `--noir-input-hash <sha256|poseidon>`: Hash the Noir input and return the digest
+//! - `--noir-verify-input-hash`: With `--noir-input-hash`, also emit `regex_match_verified`,
+//!   taking the expected digest as an input and asserting that the input hashes to it
+//! - `--strategy <auto|if-chain|table|mux-tree|keyword>`: Layout of the Noir `next_state` function (default: auto,
+//!   the layout estimated to take the fewest gates)
+//! - `--noir-max-branches <N>`: Split an `if-chain` or `keyword` Noir `next_state` with more
+//!   than N branches into helper functions over ranges of states
+//! - `--noir-state-type <field|integer>`: Type of the Noir automaton state, `integer` using a
+//!   `u8` when the states fit in a byte and a `u32` otherwise (default: field)
+//! - `--noir-compact`: Strip the comments and indentation from the generated Noir
+//! - `--noir-class-predicates`: Test the code unit ranges shared by several Noir `next_state`
+//!   branches through predicates such as `is_digit`
+//! - `--noir-empty-match <allow|reject>`: Whether the Noir matcher accepts an empty match (default: allow)
+//! - `--noir-match-semantics <greedy|first-accept|longest>`: Whether a Noir match is extended greedily, dropping it if the extension fails, ends at the first accepting state, or ends at the last accepting state it went through (default: greedy)
+//! - `--noir-split-modules`: Emit `next_state` and the Noir helpers into separate modules
+//! - `--noir-packed-captures`: Also emit `regex_match_packed_captures`, returning the captures
+//!   packed 31 bytes per Field
+//! - `--noir-byte-source`: Also emit a `ByteSource` trait and `regex_match_source`, accepting
+//!   `[u8; N]`, `BoundedVec<u8, N>` or `PackedBytes` inputs (u8 input only)
+//! - `--noir-field-input`: Also emit `regex_match_fields`, taking the input as `[Field; N]` and
+//!   asserting that every Field fits in an input code unit (u8 or u16 input only)
+//! - `--noir-public-next-state`: Make the Noir `next_state` public and emit a `StateInfo`
+//!   struct describing its initial and accepting states, for matching loops written by hand
+//! - `--noir-capture-digests`: Also emit `regex_match_capture_digests`, returning the SHA-256
+//!   digest of every capture
+//! - `--noir-capture-lens`: Also emit `regex_match_capture_lens`, returning the captures along
+//!   with their lengths
+//! - `--noir-prefix-len`: Also emit `regex_match_prefix_len`, returning the length of the
+//!   longest prefix of a match found in the input without asserting that it matches
+//! - `--noir-match-mask`: Also emit `regex_match_mask`, asserting the match and returning a
+//!   `[bool; N]` marking the input positions it covers
+//! - `--noir-match-len`: Also emit `regex_match_len`, asserting the match and returning the
+//!   number of code units it covers
+//! - `--noir-match-end`: Also emit `regex_match_end`, asserting the match and returning the
+//!   index just past the code unit at which it first reaches an accepting state
+//! - `--noir-redaction <CHAR>`: Also emit `regex_match_redacted`, proving that a redacted copy
+//!   of the input equals it except that the code units of the match are replaced with CHAR
+//! - `--noir-match-bound <K>`: Assert in `regex_match` that the match is completed within the
+//!   first K code units of the input, the rest being padding
+//! - `--noir-match-window`: Also emit `regex_match_window`, taking `start` and `end` indices
+//!   and matching only the input between them, e.g. a region located by an outer circuit
+//! - `--noir-match-at`: Also emit `regex_match_at`, verifying a match claimed to start at a
+//!   witness index within a window of L code units, at a cost independent of the input length
+//! - `--noir-capture-count <K>`: Assert in `regex_match` that the match produces exactly K
+//!   non-empty captures
+//! - `--noir-fixed-len <N>`: Also emit `regex_match_fixed`, a non-generic entry point taking
+//!   an input of exactly N code units, for ABIs and tooling without numeric generics
+//! - `--noir-input-segments <K>`: Also emit `regex_match_segments`, matching K input arrays
+//!   as one input, e.g. a header followed by a body
+//! - `--noir-corpus <FILE>`: Run the inputs of FILE, one per line escaped as by `sample`,
+//!   through the automaton and check the `next_state` branches they take most first; the
+//!   states and transitions they spend their bytes in are listed in `--report`
+//! - `--noir-lowercase-captures`: Fold the Noir captures to ASCII lowercase in the circuit
+//! - `--noir-capture-starts`: Also return the index in the input at which every Noir capture
+//!   begins
+//! - `--noir-occurrence <K>`: Match and capture only the K-th occurrence of the pattern in the
+//!   Noir input, counted from 1
+//! - `--noir-reverse`: Scan the Noir input from its end with the reversed pattern, matching
+//!   its last occurrence
+//! - `--noir-reverse-window <W>`: With `--noir-reverse`, only scan the last W code units
+//! - `--noir-input-alphabet <printable|declared>`: Assert that every input code unit is printable
+//!   ASCII or appears in the pattern
+//! - `--noir-repetition-counter <N>`: Match the bounded repetitions of a single byte repeating
+//!   more than N times, e.g. `[a-z]{1,500}`, with a counter in the Noir matcher instead of a
+//!   state per repetition (Noir output only)
+//! - `--noir-use <PATH>`: Path imported with `use` by the generated Noir code; an imported
+//!   `sha256`, `sha256_var` or `Poseidon2` replaces the one from `std` (repeatable)
+//! - `--noir-dependency <NAME=TOML>`: Dependency added to the generated `Nargo.toml`, e.g.
+//!   `sha256={ tag = "v0.1.0", git = "https://github.com/noir-lang/sha256" }` (repeatable)
+//! - `--noir-runtime <NAME>`: Import the capture helpers from the Noir runtime library of
+//!   this name, generated by the `runtime` command, instead of emitting them
+//! - `-g, --gen-substrs <true|false|INDICES>`: Generate all, none or only the listed
+//!   substrings, e.g. `0,2`
+//! - `--var <NAME=VALUE>`: Value of a `{{NAME}}` variable in the pattern, matched literally
+//!   (repeatable)
+//! - `--lenient-newlines`: Let every `\r\n` of the pattern also match a bare `\n`
+//! - `--dialect <rust|re2|pcre|ere>`: Regex syntax the pattern is written in (default: rust)
+//! - `--permissive-escapes`: Match the character of an unknown escape such as `\q` literally
+//!   instead of failing
+//! - `--max-estimated-gates <GATES>`: Fail before writing any output if the Noir matcher is
+//!   estimated to take more gates than this for inputs of `--input-len` code units
+//! - `--input-len <N>`: Input length the gate budget is checked for and the `auto` strategy is
+//!   chosen for (default: 1024)
+//! - `--report <PATH>`: Write a JSON summary of the compilation, with the options used, the
+//!   DFA size, the estimated Noir constraints per input unit, warnings and the SHA-256 hash of
+//!   every output file
+//! - `--emit <nr,json,dot,html,report,abi>`: Artifacts written together to `--out`: the Noir
+//!   matcher (`<STEM>.nr`), the regex and its DFA as JSON (`<STEM>.json`), the automaton as a
+//!   Graphviz DOT graph (`<STEM>.dot`), a self-contained HTML page with an interactive graph,
+//!   the transition table and a test string simulator (`<STEM>.html`), the compile report
+//!   of the other artifacts (`<STEM>.report.json`) and the signatures of the public functions
+//!   of the Noir matcher with the captures they return as JSON (`<STEM>.abi.json`). Nothing is
+//!   written unless all of them are generated
+//! - `--out <STEM>`: Path stem of the artifacts of `--emit`, e.g. `out/email`
 //!
 //! Example:
 //! ```
 //! zk-regex raw -r "a*b+c?" -s substrings.json -h ./halo2_output -c ./circom_output.circom -t MyTemplate -g true
+//! cat pattern.txt | zk-regex raw -p - -c ./circom_output.circom -t MyTemplate
+//! zk-regex raw --preset ipv4 -n ./src/ipv4.nr
+//! zk-regex raw -r "from:[a-z]+@{{domain}}" --var domain=example.com -n ./src/from.nr
+//! ```
+//!
+//! ## Manifest Command
+//! Compile the patterns listed in a manifest into one Noir library sharing a single automaton:
+//!
+//! ```
+//! zk-regex manifest --manifest-path <PATH> --noir-file-path <PATH> [OPTIONS]
+//! ```
+//!
+//! Options:
+//! - `-m, --manifest-path <PATH>`: Path to the manifest JSON file (required)
+//! - `-n, --noir-file-path <PATH>`: File path for Noir output (required)
+//! - `--noir-input-type <u8|u16>`: Element type of the Noir input array (default: u8)
+//! - `--noir-input-hash <sha256|poseidon>`: Hash the Noir input and return the digest
+//! - `--strategy <auto|if-chain|table|mux-tree|keyword>`: Layout of the Noir `next_state` function (default: auto,
+//!   the layout estimated to take the fewest gates)
+//! - `--noir-max-branches <N>`: Split an `if-chain` or `keyword` Noir `next_state` with more
+//!   than N branches into helper functions over ranges of states
+//! - `--noir-state-type <field|integer>`: Type of the Noir automaton state, `integer` using a
+//!   `u8` when the states fit in a byte and a `u32` otherwise (default: field)
+//! - `--noir-compact`: Strip the comments and indentation from the generated Noir
+//! - `--noir-class-predicates`: Test the code unit ranges shared by several Noir `next_state`
+//!   branches through predicates such as `is_digit`
+//! - `--noir-empty-match <allow|reject>`: Whether the Noir matcher accepts an empty match (default: allow)
+//! - `--noir-match-semantics <greedy|first-accept|longest>`: Whether a Noir match is extended greedily, dropping it if the extension fails, ends at the first accepting state, or ends at the last accepting state it went through (default: greedy)
+//! - `--noir-lowercase-captures`: Fold the Noir captures to ASCII lowercase in the circuit
+//! - `--noir-capture-starts`: Also return the index in the input at which every Noir capture
+//!   begins
+//! - `--noir-occurrence <K>`: Match and capture only the K-th occurrence of the pattern in the
+//!   Noir input, counted from 1
+//! - `--noir-reverse`: Scan the Noir input from its end with the reversed pattern, matching
+//!   its last occurrence
+//! - `--noir-reverse-window <W>`: With `--noir-reverse`, only scan the last W code units
+//! - `--noir-input-alphabet <printable|declared>`: Assert that every input code unit is printable
+//!   ASCII or appears in the pattern
+//! - `--noir-use <PATH>`: Path imported with `use` by the generated Noir code; an imported
+//!   `sha256`, `sha256_var` or `Poseidon2` replaces the one from `std` (repeatable)
+//! - `--noir-fused`: Also emit `regex_match_all`, checking every pattern in a single loop over
+//!   the input
+//! - `--noir-runtime <NAME>`: Import the capture helpers from the Noir runtime library of
+//!   this name, generated by the `runtime` command, instead of emitting them
+//! - `-g, --gen-substrs <true|false|INDICES>`: Generate all, none or only the listed
+//!   substrings, e.g. `0,2`
+//! - `--var <NAME=VALUE>`: Value of a `{{NAME}}` variable in the patterns, overriding the
+//!   `vars` of the manifest (repeatable)
+//! - `--lenient-newlines`: Let every `\r\n` of the patterns also match a bare `\n`
+//! - `--dialect <rust|re2|pcre|ere>`: Regex syntax the patterns are written in (default: rust)
+//! - `--permissive-escapes`: Match the character of an unknown escape such as `\q` literally
+//!   instead of failing
+//!
+//! Example:
+//! ```
+//! zk-regex manifest -m patterns.json -n ./src/patterns.nr -g true
+//! ```
+//!
+//! ## Denylist Command
+//! Generate a Noir library whose `assert_no_match` asserts that none of the forbidden patterns
+//! occurs anywhere in the input:
+//!
+//! ```
+//! zk-regex denylist (--raw-regex <REGEX>... | --pattern-file <PATH>) --noir-file-path <PATH> [OPTIONS]
+//! ```
+//!
+//! Options:
+//! - `-r, --raw-regex <REGEX>`: Forbidden pattern, without `^` or `$` anchors (repeatable)
+//! - `-p, --pattern-file <PATH>`: File listing forbidden patterns, one per line, or `-` to read
+//!   them from stdin; empty lines are skipped
+//! - `-n, --noir-file-path <PATH>`: File path for Noir output (required)
+//! - `--strategy <auto|if-chain|table|mux-tree|keyword>`: Layout of the Noir `next_state` function (default: auto,
+//!   the layout estimated to take the fewest gates)
+//! - `--noir-state-type <field|integer>`: Type of the Noir automaton state, `integer` using a
+//!   `u8` when the states fit in a byte and a `u32` otherwise (default: field)
+//! - `--noir-compact`: Strip the comments and indentation from the generated Noir
+//! - `--noir-class-predicates`: Test the code unit ranges shared by several Noir `next_state`
+//!   branches through predicates such as `is_digit`
+//! - `--var <NAME=VALUE>`: Value of a `{{NAME}}` variable in the patterns, matched literally
+//!   (repeatable)
+//!
+//! Example:
+//! ```
+//! zk-regex denylist -r "password=" -r "[0-9]{3}-[0-9]{2}-[0-9]{4}" -n ./src/denylist.nr
+//! ```
+//!
+//! ## Compose Command
+//! Generate a Noir library whose `regex_match` checks a combination of several patterns with a
+//! single automaton:
+//!
+//! ```
+//! zk-regex compose --raw-regex <REGEX>... --noir-file-path <PATH> [OPTIONS]
+//! ```
+//!
+//! Options:
+//! - `-r, --raw-regex <REGEX>`: Pattern to combine, matched anywhere in the input unless
+//!   anchored with `^` or `$` (repeatable, required)
+//! - `-n, --noir-file-path <PATH>`: File path for Noir output (required)
+//! - `--composition <all|any|difference|sequence>`: How the patterns are combined: `all`
+//!   requires every pattern to match, `any` requires one of them to and returns the index of
+//!   the first that does, `difference` takes two patterns and requires the first to match and
+//!   the second not to occur, `sequence` requires every pattern to match in order, each after
+//!   the previous one (default: all)
+//! - `--strategy <auto|if-chain|table|mux-tree|keyword>`: Layout of the Noir `next_state` function (default: auto,
+//!   the layout estimated to take the fewest gates)
+//! - `--noir-state-type <field|integer>`: Type of the Noir automaton state, `integer` using a
+//!   `u8` when the states fit in a byte and a `u32` otherwise (default: field)
+//! - `--noir-compact`: Strip the comments and indentation from the generated Noir
+//! - `--noir-class-predicates`: Test the code unit ranges shared by several Noir `next_state`
+//!   branches through predicates such as `is_digit`
+//! - `--var <NAME=VALUE>`: Value of a `{{NAME}}` variable in the patterns, matched literally
+//!   (repeatable)
+//!
+//! Example:
+//! ```
+//! zk-regex compose -r "^from:[a-z]+@a\.com\r\n" -r "subject:[^\r]*urgent" -n ./src/mail.nr
+//! ```
+//! ```
+//! zk-regex compose --composition any -r "@gmail\.com" -r "@outlook\.com" -n ./src/provider.nr
+//! ```
+//! ```
+//! zk-regex compose --composition difference -r "^from:[a-z]+@[a-z]+\.com\r\n" -r "@spam\.com" -n ./src/sender.nr
+//! ```
+//! ```
+//! zk-regex compose --composition sequence -r "\r\nfrom:" -r "\r\nsubject:" -r "\r\n\r\n[^\r]*invoice" -n ./src/doc.nr
+//! ```
+//!
+//! ## Fields Command
+//! Generate a Noir library extracting the fields of a structured body into a named struct:
+//!
+//! ```
+//! zk-regex fields --fields-path <PATH> --noir-file-path <PATH> [OPTIONS]
+//! ```
+//!
+//! The fields config, in JSON, JSON5 or YAML, lists the fields in the order they appear in the
+//! body, each with a `name`, the `pattern` of its value and optionally its `key` in the body,
+//! `max_length`, and `numeric` with `min` and `max` bounds. Its `format` is `key_value`, for
+//! pairs such as `to=alice;amount=10` joined by its `separator` (default: `;`), or `json`, for
+//! the members of an object. The Noir library holds the matcher, a struct named by
+//! `struct_name` (default: `Fields`) and `regex_match_fields`, returning the struct with the
+//! text fields as bytes and the numeric ones as `u64` values checked against their bounds.
+//!
+//! Options:
+//! - `-f, --fields-path <PATH>`: Path to the fields config (required)
+//! - `-n, --noir-file-path <PATH>`: File path for Noir output (required)
+//! - `--decomposed-out <PATH>`: File path for the decomposed regex generated from the fields,
+//!   e.g. to compile it to Circom
+//! - `--strategy <auto|if-chain|table|mux-tree|keyword>`: Layout of the Noir `next_state`
+//!   function (default: auto)
+//! - `--noir-state-type <field|integer>`: Type of the Noir automaton state (default: field)
+//! - `--noir-compact`: Strip the comments and indentation from the generated Noir
+//!
+//! Example:
+//! ```
+//! zk-regex fields -f transfer.json -n ./src/transfer.nr
+//! ```
+//!
+//! ## Test Command
+//! Write a Nargo project for each pattern of a manifest, with a test running the Noir matcher
+//! on a sample input the pattern accepts, and run `nargo test` on it:
+//!
+//! ```
+//! zk-regex test --manifest-path <PATH> [OPTIONS]
+//! ```
+//!
+//! The command prints `PASS` or `FAIL` for each pattern, followed by the output of `nargo` and
+//! the kept project for the failing ones, and exits with status 1 if any pattern fails.
+//!
+//! Options:
+//! - `-m, --manifest-path <PATH>`: Path to the manifest JSON file (required)
+//! - `--noir-input-type <u8|u16|packed>`: Element type of the Noir input array (default: u8)
+//! - `--strategy <auto|if-chain|table|mux-tree|keyword>`: Layout of the Noir `next_state` function (default: auto,
+//!   the layout estimated to take the fewest gates)
+//! - `--noir-state-type <field|integer>`: Type of the Noir automaton state, `integer` using a
+//!   `u8` when the states fit in a byte and a `u32` otherwise (default: field)
+//! - `--noir-empty-match <allow|reject>`: Whether the Noir matcher accepts an empty match (default: allow)
+//! - `--noir-match-semantics <greedy|first-accept|longest>`: Whether a Noir match is extended greedily, dropping it if the extension fails, ends at the first accepting state, or ends at the last accepting state it went through (default: greedy)
+//! - `--noir-lowercase-captures`: Fold the Noir captures to ASCII lowercase in the circuit
+//! - `--noir-capture-starts`: Also return the index in the input at which every Noir capture
+//!   begins
+//! - `--noir-occurrence <K>`: Match and capture only the K-th occurrence of the pattern in the
+//!   Noir input, counted from 1
+//! - `--noir-reverse`: Scan the Noir input from its end with the reversed pattern, matching
+//!   its last occurrence
+//! - `--noir-reverse-window <W>`: With `--noir-reverse`, only scan the last W code units
+//! - `--noir-input-alphabet <printable|declared>`: Assert that every input code unit is printable
+//!   ASCII or appears in the pattern
+//! - `--noir-use <PATH>`: Path imported with `use` by the generated Noir code; an imported
+//!   `sha256`, `sha256_var` or `Poseidon2` replaces the one from `std` (repeatable)
+//! - `--noir-dependency <NAME=TOML>`: Dependency added to the generated `Nargo.toml`, e.g.
+//!   `sha256={ tag = "v0.1.0", git = "https://github.com/noir-lang/sha256" }` (repeatable)
+//! - `-g, --gen-substrs <true|false|INDICES>`: Generate all, none or only the listed
+//!   substrings, e.g. `0,2` (default: true)
+//! - `--var <NAME=VALUE>`: Value of a `{{NAME}}` variable in the patterns, overriding the
+//!   `vars` of the manifest (repeatable)
+//! - `--lenient-newlines`: Let every `\r\n` of the patterns also match a bare `\n`
+//! - `--dialect <rust|re2|pcre|ere>`: Regex syntax the patterns are written in (default: rust)
+//! - `--permissive-escapes`: Match the character of an unknown escape such as `\q` literally
+//!   instead of failing
+//!
+//! Example:
+//! ```
+//! zk-regex test -m patterns.json --strategy table
+//! ```
+//!
+//! ## Prove Command
+//! Write a Nargo project calling the Noir matcher on an input, run `nargo execute`, prove it
+//! with `bb prove` and verify the proof with `bb verify`, printing the time of each step:
+//!
+//! ```
+//! zk-regex prove (--decomposed-regex-path <PATH> | --raw-regex <REGEX>) --input <TEXT> [OPTIONS]
+//! ```
+//!
+//! Options:
+//! - `-d, --decomposed-regex-path <PATH>`: Path to the decomposed regex file in JSON, JSON5
+//!   (`.json5`) or YAML (`.yaml`, `.yml`), or `-` to read it from stdin
+//! - `-r, --raw-regex <REGEX>`: Raw regex string
+//! - `-s, --substrs-json-path <PATH>`: Path to substrings JSON file of the raw regex
+//! - `-i, --input <TEXT>`: Input proven to match the pattern
+//! - `--input-file <PATH>`: File holding the input instead, or `-` to read it from stdin; one
+//!   trailing newline is dropped
+//! - `--project-dir <DIR>`: Directory of the Nargo project (default: ./zk_regex_prove)
+//! - `--noir-input-type <u8|u16|packed>`: Element type of the Noir input array (default: u8)
+//! - `--noir-input-hash <sha256|poseidon>`: Hash the Noir input and return the digest
+//! - `--strategy <auto|if-chain|table|mux-tree|keyword>`: Layout of the Noir `next_state` function (default: auto,
+//!   the layout estimated to take the fewest gates)
+//! - `--noir-state-type <field|integer>`: Type of the Noir automaton state, `integer` using a
+//!   `u8` when the states fit in a byte and a `u32` otherwise (default: field)
+//! - `--noir-empty-match <allow|reject>`: Whether the Noir matcher accepts an empty match (default: allow)
+//! - `--noir-match-semantics <greedy|first-accept|longest>`: Whether a Noir match is extended greedily, dropping it if the extension fails, ends at the first accepting state, or ends at the last accepting state it went through (default: greedy)
+//! - `--noir-lowercase-captures`: Fold the Noir captures to ASCII lowercase in the circuit
+//! - `--noir-capture-starts`: Also return the index in the input at which every Noir capture
+//!   begins
+//! - `--noir-occurrence <K>`: Match and capture only the K-th occurrence of the pattern in the
+//!   Noir input, counted from 1
+//! - `--noir-reverse`: Scan the Noir input from its end with the reversed pattern, matching
+//!   its last occurrence
+//! - `--noir-reverse-window <W>`: With `--noir-reverse`, only scan the last W code units
+//! - `--noir-input-alphabet <printable|declared>`: Assert that every input code unit is printable
+//!   ASCII or appears in the pattern
+//! - `--noir-use <PATH>`: Path imported with `use` by the generated Noir code; an imported
+//!   `sha256`, `sha256_var` or `Poseidon2` replaces the one from `std` (repeatable)
+//! - `--noir-dependency <NAME=TOML>`: Dependency added to the generated `Nargo.toml`, e.g.
+//!   `sha256={ tag = "v0.1.0", git = "https://github.com/noir-lang/sha256" }` (repeatable)
+//! - `-g, --gen-substrs <true|false|INDICES>`: Generate all, none or only the listed
+//!   substrings, e.g. `0,2`
+//! - `--var <NAME=VALUE>`: Value of a `{{NAME}}` variable in the pattern, matched literally
+//!   (repeatable)
+//!
+//! Example:
+//! ```
+//! zk-regex prove -r "from:([a-z]+)@example.com" -i "from:alice@example.com" --strategy table
+//! ```
+//!
+//! ## Init Command
+//! Create a Nargo project with a manifest listing a sample pattern, the Noir matcher generated
+//! from it, a main circuit calling the matcher and its tests:
+//!
+//! ```
+//! zk-regex init <DIR>
+//! ```
+//!
+//! The directory must not exist or be empty. `nargo test` passes in the new project, and the
+//! comment at the top of `src/main.nr` gives the command regenerating the matcher once the
+//! patterns are edited.
+//!
+//! Example:
+//! ```
+//! zk-regex init my_project
+//! ```
+//!
+//! ## Migrate Command
+//! Convert the decomposed regex configs of a Circom zk-regex project into a Noir pattern
+//! manifest and the Noir library generated from it:
+//!
+//! ```
+//! zk-regex migrate <CIRCOM_DIR> --out-dir <DIR> [OPTIONS]
+//! ```
+//!
+//! Every JSON file under CIRCOM_DIR with a `parts` array is migrated to `<DIR>/<NAME>.json`,
+//! named after its file, and listed in `<DIR>/patterns.json`; the library extracting every
+//! public part is written to `<DIR>/regex.nr`. Keys of older configs are converted or dropped,
+//! configs that do not compile are skipped, and the Circom template generated from each config
+//! is read to flag its outputs whose semantics differ in Noir, such as the masked `reveal`
+//! arrays and the unconstrained `out` signal. Every change is printed to stderr as a warning.
+//!
+//! Options:
+//! - `-o, --out-dir <DIR>`: Directory of the manifest, configs and Noir library (required)
+//! - `--strategy <auto|if-chain|table|mux-tree|keyword>`: Layout of the Noir `next_state`
+//!   function (default: auto)
+//! - `--noir-state-type <field|integer>`: Type of the Noir automaton state (default: field)
+//!
+//! Example:
+//! ```
+//! zk-regex migrate packages/circom/circuits -o noir_patterns
+//! ```
+//!
+//! ## Runtime Command
+//! Generate the Nargo library holding the helpers shared by the Noir matchers, such as
+//! `capture_to_bytes`, which the matchers generated with `--noir-runtime <NAME>` import instead
+//! of carrying a copy each:
+//!
+//! ```
+//! zk-regex runtime <DIR> [OPTIONS]
+//! ```
+//!
+//! Options:
+//! - `--name <NAME>`: Name of the library (default: the name of the directory)
+//! - `--noir-use <PATH>`: Path imported with `use` by the library; an imported `sha256_var`
+//!   replaces the one from `std` (repeatable)
+//! - `--noir-dependency <NAME=TOML>`: Dependency added to the `Nargo.toml` of the library
+//!   (repeatable)
+//!
+//! Example:
+//! ```
+//! zk-regex runtime ./zk_regex_runtime
+//! zk-regex raw -r "from:([a-z]+)" -n ./src/from.nr --noir-runtime zk_regex_runtime
+//! ```
+//!
+//! ## Diff Command
+//! Compare the automata compiled from two decomposed regex files, to review how a change of
+//! the pattern changes the circuits generated from it:
+//!
+//! ```
+//! zk-regex diff <OLD> <NEW> [OPTIONS]
+//! ```
+//!
+//! The states of the two automata are paired by running them side by side on the same input.
+//! The command prints the states of either automaton that no pair reaches, then for each pair
+//! of states the transitions taken by only one automaton with the substrings capturing them,
+//! the changes of acceptance and the transitions of both automata captured by a substring in
+//! only one of them. Lines starting with `-` describe
+//! the old automaton and lines starting with `+` the new one, and states are written `OLD/NEW`
+//! for a pair. The command exits with status 1 if the automata differ.
+//!
+//! Options:
+//! - `--var <NAME=VALUE>`: Value of a `{{NAME}}` variable in the patterns, matched literally
+//!   (repeatable)
+//!
+//! Example:
+//! ```
+//! zk-regex diff old/from.json from.json
+//! ```
+//!
+//! ## Equivalent Command
+//! Decide whether the Noir matchers of two patterns accept the same inputs, by running both
+//! matchers side by side over every input:
+//!
+//! ```
+//! zk-regex equivalent <A> <B> [OPTIONS]
+//! ```
+//!
+//! The command prints `equivalent`, or a shortest input accepted by only one of the matchers
+//! and exits with status 1. The substrings are not compared.
+//!
+//! Options:
+//! - `--raw`: Take the patterns as raw regexes rather than decomposed regex files
+//! - `--var <NAME=VALUE>`: Value of a `{{NAME}}` variable in the patterns, matched literally
+//!   (repeatable)
+//!
+//! Example:
+//! ```
+//! zk-regex equivalent --raw "(a|b)*abb" "[ab]*abb"
+//! ```
+//!
+//! ## Sample Command
+//! Print random inputs accepted by a pattern, one per line with non-printable bytes escaped:
+//!
+//! ```
+//! zk-regex sample <PATTERN> [OPTIONS]
+//! ```
+//!
+//! Options:
+//! - `--raw`: Take the pattern as a raw regex rather than a decomposed regex file
+//! - `--count <N>`: Number of inputs to sample (default: 10)
+//! - `--min-len <N>`: Smallest length of an input in bytes (default: 1)
+//! - `--max-len <N>`: Largest length of an input in bytes (default: 32)
+//! - `--seed <SEED>`: Seed of the random generator, the same seed giving the same inputs
+//!   (default: 0)
+//! - `--near-misses`: Print inputs that almost match but are rejected instead, one edit away
+//!   from an accepted input: the last byte replaced, or a byte dropped or repeated
+//! - `--prover-toml`: Print every input as the `input` array of a `Prover.toml` instead
+//! - `--var <NAME=VALUE>`: Value of a `{{NAME}}` variable in the pattern, matched literally
+//!   (repeatable)
+//!
+//! Example:
 //! ```
+//! zk-regex sample --raw "[a-z]+@[a-z]+\\.com" --count 3 --seed 42
+//! ```
+//!
+//! ## Explain Command
+//! Describe the automaton of a pattern in plain text, to review its circuits without reading
+//! the pattern or the generated code:
+//!
+//! ```
+//! zk-regex explain <PATTERN> [OPTIONS]
+//! ```
+//!
+//! Every state is listed with a shortest input reaching it, whether it accepts and the
+//! substrings it lies within, then its transitions with the substrings capturing them and what
+//! the matchers do on any other byte: stay in the initial state, stay matched, or reset and
+//! match the byte again from the initial state. The transitions feeding each substring are
+//! listed last.
+//!
+//! Options:
+//! - `--raw`: Take the pattern as a raw regex rather than a decomposed regex file
+//! - `--capture-groups`: With `--raw`, describe the capture groups of the regex as substrings
+//! - `--var <NAME=VALUE>`: Value of a `{{NAME}}` variable in the pattern, matched literally
+//!   (repeatable)
+//!
+//! Example:
+//! ```
+//! zk-regex explain --raw --capture-groups "^id=([0-9]+);"
+//! ```
+//!
+//! ## Bench Command
+//! Estimate the gates of the Noir matcher of a pattern for a range of input lengths, with
+//! every layout of `next_state` the `auto` strategy chooses from:
+//!
+//! ```
+//! zk-regex bench <PATTERN> [OPTIONS]
+//! ```
+//!
+//! A row is printed per input length, the layout `auto` chooses marked with `*`, followed by
+//! the gates each additional input code unit costs with every layout, so that the largest
+//! input a circuit can afford can be picked. The substrings of the pattern are revealed, if it
+//! has any. The estimates follow the cost model of `--max-estimated-gates`, plus the `global`
+//! arrays the layouts read.
+//!
+//! A second table lists, per input length and proving backend, the circuit size the gates of
+//! the chosen layout are padded to and the time and memory `bb` is estimated to take to prove
+//! it. The estimates are rough figures for comparing pattern designs; pass `--measure` to
+//! prove the matcher on a padded sample input of every length and report the actual costs.
+//!
+//! Options:
+//! - `--raw`: Take the pattern as a raw regex rather than a decomposed regex file
+//! - `--capture-groups`: With `--raw`, reveal the capture groups of the regex as substrings
+//! - `--sizes <N,...>`: Input lengths to estimate, in code units with an optional `K` or `M`
+//!   suffix
+//!   (default: 1K,4K,16K,64K)
+//! - `--noir-input-type <TYPE>`: Element type of the input array, as for `decomposed`
+//! - `--backend <ultra-honk,ultra-plonk>`: Proving backends to estimate the cost with
+//!   (default: ultra-honk)
+//! - `--measure <DIR>`: Measure the proving cost by running `nargo execute` and `bb` on Nargo
+//!   projects written to DIR, one per input length, instead of estimating it
+//! - `--var <NAME=VALUE>`: Value of a `{{NAME}}` variable in the pattern, matched literally
+//!   (repeatable)
+//!
+//! Example:
+//! ```
+//! zk-regex bench --raw --capture-groups "subject:([a-z ]+)\r\n" --sizes 1K,4K,16K
+//! ```
+//!
+//! ## Validate Command
+//! Run the Noir matcher of a pattern with `nargo execute` on sampled inputs and compare it with
+//! its simulation in Rust, as a smoke test of the generated code:
+//!
+//! ```
+//! zk-regex validate <PATTERN> [OPTIONS]
+//! ```
+//!
+//! Inputs the pattern accepts and near misses it rejects are sampled as by `sample`. For each
+//! of them, a Nargo project is written under a temporary directory, asserting that the matcher
+//! accepts or rejects the input as the simulation does and extracts the same substrings. Every
+//! input on which they diverge is printed with the outcomes, the kept project and the output
+//! of `nargo`, and the command exits with status 1 if there is any. The matcher takes `u8`
+//! input and extracts the substrings of the pattern, if it has any.
+//!
+//! Options:
+//! - `--raw`: Take the pattern as a raw regex rather than a decomposed regex file
+//! - `--capture-groups`: With `--raw`, extract the capture groups of the regex as substrings
+//! - `--count <N>`: Number of accepted inputs and of near misses to sample (default: 10)
+//! - `--min-len <N>`: Smallest length of an accepted input in bytes (default: 1)
+//! - `--max-len <N>`: Largest length of an accepted input in bytes (default: 32)
+//! - `--seed <SEED>`: Seed of the random generator (default: 0)
+//! - `--strategy <auto|if-chain|table|mux-tree|keyword>`: Layout of the Noir `next_state`
+//!   function (default: auto)
+//! - `--noir-state-type <field|integer>`: Type of the Noir automaton state (default: field)
+//! - `--var <NAME=VALUE>`: Value of a `{{NAME}}` variable in the pattern, matched literally
+//!   (repeatable)
+//!
+//! Example:
+//! ```
+//! zk-regex validate --raw --capture-groups "from:([a-z]+)@example\.com" --strategy table
+//! ```
+
+use clap::{ArgAction, Args, Parser, Subcommand};
+use itertools::Itertools;
+use std::{io::IsTerminal, path::Path, time::Duration};
+use tracing::{warn, Level};
+use zk_regex_compiler::{
+    bench_pattern, bench_proving, check_equivalence, diff_decomposed, explain_pattern,
+    finish_profiling, gen_denylist, gen_from_composition, gen_from_decomposed, gen_from_fields,
+    gen_from_manifest, gen_from_raw, gen_noir_runtime, gen_samples, init_project, measure_proving,
+    migrate_circom_project, presets, prove_from_decomposed, prove_from_raw, read_pattern,
+    start_profiling, start_progress, test_from_manifest, validate_pattern, CompileOptions, Dialect,
+    EmitKind, EmitOptions, GateBudget, MainOutput, NoirCaptureOverflow, NoirEmptyMatch,
+    NoirInputAlphabet, NoirInputHash, NoirInputType, NoirMainOptions, NoirMatchSemantics,
+    NoirOptions, NoirStateType, NoirStrategy, OutputPaths, PatternComposition, ProvingBackend,
+    SampleOptions, SubstrSelection,
+};
 
-use clap::{Parser, Subcommand};
-use zk_regex_compiler::{gen_from_decomposed, gen_from_raw};
+/// The time between two progress lines of a compilation step.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+    #[arg(long, global = true)]
+    pub profile: bool,
+    #[arg(long, global = true)]
+    pub progress: bool,
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+}
+
+/// The Noir options shared by the `decomposed` and `raw` commands.
+#[derive(Args, Debug, Clone)]
+struct NoirArgs {
+    #[arg(long, default_value = "u8")]
+    noir_input_type: NoirInputType,
+    #[arg(long)]
+    noir_input_hash: Option<NoirInputHash>,
+    #[arg(long, requires = "noir_input_hash")]
+    noir_verify_input_hash: bool,
+    #[arg(long, default_value = "auto")]
+    strategy: NoirStrategy,
+    #[arg(long, default_value = "field")]
+    noir_state_type: NoirStateType,
+    #[arg(long, value_name = "N")]
+    noir_max_branches: Option<usize>,
+    #[arg(long)]
+    noir_compact: bool,
+    #[arg(long)]
+    noir_class_predicates: bool,
+    #[arg(long, default_value = "allow")]
+    noir_empty_match: NoirEmptyMatch,
+    #[arg(long, default_value = "greedy")]
+    noir_match_semantics: NoirMatchSemantics,
+    #[arg(long)]
+    noir_split_modules: bool,
+    #[arg(long)]
+    noir_packed_captures: bool,
+    #[arg(long)]
+    noir_byte_source: bool,
+    #[arg(long)]
+    noir_field_input: bool,
+    #[arg(long)]
+    noir_public_next_state: bool,
+    #[arg(long)]
+    noir_capture_digests: bool,
+    #[arg(long)]
+    noir_capture_lens: bool,
+    #[arg(long)]
+    noir_prefix_len: bool,
+    #[arg(long)]
+    noir_match_mask: bool,
+    #[arg(long)]
+    noir_match_len: bool,
+    #[arg(long)]
+    noir_match_end: bool,
+    #[arg(long, value_name = "CHAR")]
+    noir_redaction: Option<char>,
+    #[arg(long, value_name = "K")]
+    noir_match_bound: Option<usize>,
+    #[arg(long)]
+    noir_match_window: bool,
+    #[arg(long)]
+    noir_match_at: bool,
+    #[arg(long, value_name = "K")]
+    noir_capture_count: Option<usize>,
+    #[arg(long, value_name = "N")]
+    noir_fixed_len: Option<usize>,
+    #[arg(long, value_name = "K")]
+    noir_input_segments: Option<usize>,
+    #[arg(long, value_name = "FILE")]
+    noir_corpus: Option<String>,
+    #[arg(long)]
+    noir_lowercase_captures: bool,
+    #[arg(long)]
+    noir_capture_starts: bool,
+    #[arg(long, value_name = "K")]
+    noir_occurrence: Option<usize>,
+    #[arg(long)]
+    noir_reverse: bool,
+    #[arg(long, value_name = "W")]
+    noir_reverse_window: Option<usize>,
+    #[arg(long)]
+    noir_input_alphabet: Option<NoirInputAlphabet>,
+    #[arg(long = "noir-use", value_name = "PATH")]
+    noir_imports: Vec<String>,
+    #[arg(long = "noir-dependency", value_name = "NAME=TOML", value_parser = parse_var)]
+    noir_dependencies: Vec<(String, String)>,
+    #[arg(long, value_name = "NAME")]
+    noir_runtime: Option<String>,
+}
+
+impl From<&NoirArgs> for NoirOptions {
+    fn from(args: &NoirArgs) -> Self {
+        NoirOptions {
+            input_type: args.noir_input_type,
+            input_hash: args.noir_input_hash,
+            verify_input_hash: args.noir_verify_input_hash,
+            strategy: args.strategy,
+            state_type: args.noir_state_type,
+            max_branches: args.noir_max_branches,
+            compact: args.noir_compact,
+            class_predicates: args.noir_class_predicates,
+            split_modules: args.noir_split_modules,
+            packed_captures: args.noir_packed_captures,
+            byte_source: args.noir_byte_source,
+            field_input: args.noir_field_input,
+            public_next_state: args.noir_public_next_state,
+            capture_digests: args.noir_capture_digests,
+            capture_lens: args.noir_capture_lens,
+            prefix_len: args.noir_prefix_len,
+            match_mask: args.noir_match_mask,
+            match_len: args.noir_match_len,
+            match_end: args.noir_match_end,
+            redaction: args.noir_redaction,
+            match_bound: args.noir_match_bound,
+            match_window: args.noir_match_window,
+            match_at: args.noir_match_at,
+            capture_count: args.noir_capture_count,
+            fixed_len: args.noir_fixed_len,
+            input_segments: args.noir_input_segments,
+            corpus: read_corpus(args.noir_corpus.as_deref()),
+            empty_match: args.noir_empty_match,
+            match_semantics: args.noir_match_semantics,
+            lowercase_captures: args.noir_lowercase_captures,
+            capture_starts: args.noir_capture_starts,
+            occurrence: args.noir_occurrence,
+            reverse: args.noir_reverse,
+            reverse_window: args.noir_reverse_window,
+            input_alphabet: args.noir_input_alphabet,
+            imports: args.noir_imports.clone(),
+            dependencies: args.noir_dependencies.iter().cloned().collect(),
+            runtime: args.noir_runtime.clone(),
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Debug, Subcommand, Clone)]
 enum Commands {
+    // `-h` is the Halo2 directory, so the help is only `--help`.
+    #[command(disable_help_flag = true)]
     Decomposed {
         #[arg(short, long)]
         decomposed_regex_path: String,
@@ -67,13 +946,72 @@ enum Commands {
         #[arg(short, long)]
         template_name: Option<String>,
         #[arg(short, long)]
-        gen_substrs: Option<bool>,
+        noir_file_path: Option<String>,
+        #[arg(long)]
+        example: Option<String>,
+        #[arg(long, value_name = "DIR")]
+        aztec_contract: Option<String>,
+        #[arg(long, value_name = "N", default_value = "256")]
+        aztec_input_len: usize,
+        #[arg(long, value_name = "PATH", requires_all = ["noir_file_path", "input_len"])]
+        noir_main: Option<String>,
+        #[arg(
+            long,
+            value_name = "OUTPUTS",
+            value_delimiter = ',',
+            requires = "noir_main"
+        )]
+        noir_main_outputs: Vec<MainOutput>,
+        #[command(flatten)]
+        noir: NoirArgs,
+        #[arg(long, default_value = "fail")]
+        noir_capture_overflow: NoirCaptureOverflow,
+        #[arg(short, long)]
+        gen_substrs: Option<SubstrSelection>,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+        #[arg(long)]
+        lenient_newlines: bool,
+        #[arg(long, default_value = "rust")]
+        dialect: Dialect,
+        #[arg(long)]
+        permissive_escapes: bool,
+        #[arg(long, value_name = "GATES", requires = "input_len")]
+        max_estimated_gates: Option<usize>,
+        #[arg(long, value_name = "N")]
+        input_len: Option<usize>,
+        #[arg(long, value_name = "PATH")]
+        report: Option<String>,
+        #[arg(long, value_name = "KINDS", value_delimiter = ',', requires = "out")]
+        emit: Vec<EmitKind>,
+        #[arg(long, value_name = "STEM", requires = "emit")]
+        out: Option<String>,
+        #[arg(long, action = ArgAction::Help)]
+        help: Option<bool>,
     },
+    #[command(disable_help_flag = true)]
     Raw {
-        #[arg(short, long)]
-        raw_regex: String,
+        #[arg(short, long, required_unless_present_any = ["pattern_file", "preset"])]
+        raw_regex: Option<String>,
+        #[arg(short, long, conflicts_with = "raw_regex")]
+        pattern_file: Option<String>,
+        #[arg(long, conflicts_with_all = ["raw_regex", "pattern_file"])]
+        preset: Option<String>,
         #[arg(short, long)]
         substrs_json_path: Option<String>,
+        #[arg(
+            long = "substr-range",
+            value_name = "FROM-TO,...",
+            value_parser = parse_substr_range,
+            conflicts_with_all = ["substrs_json_path", "capture_groups"]
+        )]
+        substr_ranges: Vec<Vec<(usize, usize)>>,
+        #[arg(long = "substr-name", value_name = "NAME", requires = "substr_ranges")]
+        substr_names: Vec<String>,
+        #[arg(long, conflicts_with = "substrs_json_path")]
+        capture_groups: bool,
+        #[arg(long, value_name = "STATE,...", value_delimiter = ',')]
+        accept_states: Option<Vec<usize>>,
         #[arg(short, long)]
         halo2_dir_path: Option<String>,
         #[arg(short, long)]
@@ -81,15 +1019,386 @@ enum Commands {
         #[arg(short, long)]
         template_name: Option<String>,
         #[arg(short, long)]
-        gen_substrs: Option<bool>,
+        noir_file_path: Option<String>,
+        #[arg(long)]
+        example: Option<String>,
+        #[arg(long, value_name = "DIR")]
+        aztec_contract: Option<String>,
+        #[arg(long, value_name = "N", default_value = "256")]
+        aztec_input_len: usize,
+        #[arg(long, value_name = "PATH", requires_all = ["noir_file_path", "input_len"])]
+        noir_main: Option<String>,
+        #[arg(
+            long,
+            value_name = "OUTPUTS",
+            value_delimiter = ',',
+            requires = "noir_main"
+        )]
+        noir_main_outputs: Vec<MainOutput>,
+        #[command(flatten)]
+        noir: NoirArgs,
+        #[arg(long, value_name = "N")]
+        noir_repetition_counter: Option<usize>,
+        #[arg(short, long)]
+        gen_substrs: Option<SubstrSelection>,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+        #[arg(long)]
+        lenient_newlines: bool,
+        #[arg(long, default_value = "rust")]
+        dialect: Dialect,
+        #[arg(long)]
+        permissive_escapes: bool,
+        #[arg(long, value_name = "GATES", requires = "input_len")]
+        max_estimated_gates: Option<usize>,
+        #[arg(long, value_name = "N")]
+        input_len: Option<usize>,
+        #[arg(long, value_name = "PATH")]
+        report: Option<String>,
+        #[arg(long, value_name = "KINDS", value_delimiter = ',', requires = "out")]
+        emit: Vec<EmitKind>,
+        #[arg(long, value_name = "STEM", requires = "emit")]
+        out: Option<String>,
+        #[arg(long, action = ArgAction::Help)]
+        help: Option<bool>,
+    },
+    Manifest {
+        #[arg(short, long)]
+        manifest_path: String,
+        #[arg(short, long)]
+        noir_file_path: String,
+        #[arg(long, default_value = "u8")]
+        noir_input_type: NoirInputType,
+        #[arg(long)]
+        noir_input_hash: Option<NoirInputHash>,
+        #[arg(long, default_value = "auto")]
+        strategy: NoirStrategy,
+        #[arg(long, default_value = "field")]
+        noir_state_type: NoirStateType,
+        #[arg(long, value_name = "N")]
+        noir_max_branches: Option<usize>,
+        #[arg(long)]
+        noir_compact: bool,
+        #[arg(long)]
+        noir_class_predicates: bool,
+        #[arg(long, default_value = "allow")]
+        noir_empty_match: NoirEmptyMatch,
+        #[arg(long, default_value = "greedy")]
+        noir_match_semantics: NoirMatchSemantics,
+        #[arg(long)]
+        noir_lowercase_captures: bool,
+        #[arg(long)]
+        noir_capture_starts: bool,
+        #[arg(long, value_name = "K")]
+        noir_occurrence: Option<usize>,
+        #[arg(long)]
+        noir_reverse: bool,
+        #[arg(long, value_name = "W")]
+        noir_reverse_window: Option<usize>,
+        #[arg(long)]
+        noir_input_alphabet: Option<NoirInputAlphabet>,
+        #[arg(long = "noir-use", value_name = "PATH")]
+        noir_imports: Vec<String>,
+        #[arg(long)]
+        noir_fused: bool,
+        #[arg(long, value_name = "NAME")]
+        noir_runtime: Option<String>,
+        #[arg(short, long)]
+        gen_substrs: Option<SubstrSelection>,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+        #[arg(long)]
+        lenient_newlines: bool,
+        #[arg(long, default_value = "rust")]
+        dialect: Dialect,
+        #[arg(long)]
+        permissive_escapes: bool,
+    },
+    Denylist {
+        #[arg(short, long = "raw-regex", required_unless_present = "pattern_file")]
+        raw_regexes: Vec<String>,
+        #[arg(short, long)]
+        pattern_file: Option<String>,
+        #[arg(short, long)]
+        noir_file_path: String,
+        #[arg(long, default_value = "auto")]
+        strategy: NoirStrategy,
+        #[arg(long, default_value = "field")]
+        noir_state_type: NoirStateType,
+        #[arg(long)]
+        noir_compact: bool,
+        #[arg(long)]
+        noir_class_predicates: bool,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    Compose {
+        #[arg(short, long = "raw-regex", required = true)]
+        raw_regexes: Vec<String>,
+        #[arg(short, long)]
+        noir_file_path: String,
+        #[arg(long, default_value = "all")]
+        composition: PatternComposition,
+        #[arg(long, default_value = "auto")]
+        strategy: NoirStrategy,
+        #[arg(long, default_value = "field")]
+        noir_state_type: NoirStateType,
+        #[arg(long)]
+        noir_compact: bool,
+        #[arg(long)]
+        noir_class_predicates: bool,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    Fields {
+        #[arg(short, long)]
+        fields_path: String,
+        #[arg(short, long)]
+        noir_file_path: String,
+        #[arg(long, value_name = "PATH")]
+        decomposed_out: Option<String>,
+        #[arg(long, default_value = "auto")]
+        strategy: NoirStrategy,
+        #[arg(long, default_value = "field")]
+        noir_state_type: NoirStateType,
+        #[arg(long)]
+        noir_compact: bool,
+    },
+    Prove {
+        #[arg(short, long, required_unless_present = "raw_regex")]
+        decomposed_regex_path: Option<String>,
+        #[arg(short, long, conflicts_with = "decomposed_regex_path")]
+        raw_regex: Option<String>,
+        #[arg(short, long, requires = "raw_regex")]
+        substrs_json_path: Option<String>,
+        #[arg(short, long, required_unless_present = "input_file")]
+        input: Option<String>,
+        #[arg(long, conflicts_with = "input")]
+        input_file: Option<String>,
+        #[arg(long, default_value = "./zk_regex_prove")]
+        project_dir: String,
+        #[arg(long, default_value = "u8")]
+        noir_input_type: NoirInputType,
+        #[arg(long)]
+        noir_input_hash: Option<NoirInputHash>,
+        #[arg(long, default_value = "auto")]
+        strategy: NoirStrategy,
+        #[arg(long, default_value = "field")]
+        noir_state_type: NoirStateType,
+        #[arg(long, default_value = "allow")]
+        noir_empty_match: NoirEmptyMatch,
+        #[arg(long, default_value = "greedy")]
+        noir_match_semantics: NoirMatchSemantics,
+        #[arg(long)]
+        noir_lowercase_captures: bool,
+        #[arg(long)]
+        noir_capture_starts: bool,
+        #[arg(long, value_name = "K")]
+        noir_occurrence: Option<usize>,
+        #[arg(long)]
+        noir_reverse: bool,
+        #[arg(long, value_name = "W")]
+        noir_reverse_window: Option<usize>,
+        #[arg(long)]
+        noir_input_alphabet: Option<NoirInputAlphabet>,
+        #[arg(long = "noir-use", value_name = "PATH")]
+        noir_imports: Vec<String>,
+        #[arg(long = "noir-dependency", value_name = "NAME=TOML", value_parser = parse_var)]
+        noir_dependencies: Vec<(String, String)>,
+        #[arg(short, long)]
+        gen_substrs: Option<SubstrSelection>,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    Test {
+        #[arg(short, long)]
+        manifest_path: String,
+        #[arg(long, default_value = "u8")]
+        noir_input_type: NoirInputType,
+        #[arg(long, default_value = "auto")]
+        strategy: NoirStrategy,
+        #[arg(long, default_value = "field")]
+        noir_state_type: NoirStateType,
+        #[arg(long, default_value = "allow")]
+        noir_empty_match: NoirEmptyMatch,
+        #[arg(long, default_value = "greedy")]
+        noir_match_semantics: NoirMatchSemantics,
+        #[arg(long)]
+        noir_lowercase_captures: bool,
+        #[arg(long)]
+        noir_capture_starts: bool,
+        #[arg(long, value_name = "K")]
+        noir_occurrence: Option<usize>,
+        #[arg(long)]
+        noir_reverse: bool,
+        #[arg(long, value_name = "W")]
+        noir_reverse_window: Option<usize>,
+        #[arg(long)]
+        noir_input_alphabet: Option<NoirInputAlphabet>,
+        #[arg(long = "noir-use", value_name = "PATH")]
+        noir_imports: Vec<String>,
+        #[arg(long = "noir-dependency", value_name = "NAME=TOML", value_parser = parse_var)]
+        noir_dependencies: Vec<(String, String)>,
+        #[arg(short, long)]
+        gen_substrs: Option<SubstrSelection>,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+        #[arg(long)]
+        lenient_newlines: bool,
+        #[arg(long, default_value = "rust")]
+        dialect: Dialect,
+        #[arg(long)]
+        permissive_escapes: bool,
+    },
+    Init {
+        project_dir: String,
+    },
+    Migrate {
+        circom_dir: String,
+        #[arg(short, long)]
+        out_dir: String,
+        #[arg(long, default_value = "auto")]
+        strategy: NoirStrategy,
+        #[arg(long, default_value = "field")]
+        noir_state_type: NoirStateType,
+    },
+    Runtime {
+        out_dir: String,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long = "noir-use", value_name = "PATH")]
+        noir_imports: Vec<String>,
+        #[arg(long = "noir-dependency", value_name = "NAME=TOML", value_parser = parse_var)]
+        noir_dependencies: Vec<(String, String)>,
+    },
+    Diff {
+        old_path: String,
+        new_path: String,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    Equivalent {
+        a: String,
+        b: String,
+        #[arg(long)]
+        raw: bool,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    Sample {
+        pattern: String,
+        #[arg(long)]
+        raw: bool,
+        #[arg(long, default_value = "10")]
+        count: usize,
+        #[arg(long, default_value = "1")]
+        min_len: usize,
+        #[arg(long, default_value = "32")]
+        max_len: usize,
+        #[arg(long, default_value = "0")]
+        seed: u64,
+        #[arg(long)]
+        near_misses: bool,
+        #[arg(long)]
+        prover_toml: bool,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    Explain {
+        pattern: String,
+        #[arg(long)]
+        raw: bool,
+        #[arg(long, requires = "raw")]
+        capture_groups: bool,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    Bench {
+        pattern: String,
+        #[arg(long)]
+        raw: bool,
+        #[arg(long, requires = "raw")]
+        capture_groups: bool,
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_parser = parse_size,
+            default_value = "1K,4K,16K,64K"
+        )]
+        sizes: Vec<usize>,
+        #[arg(long, default_value = "u8")]
+        noir_input_type: NoirInputType,
+        #[arg(long = "backend", value_delimiter = ',', default_value = "ultra-honk")]
+        backends: Vec<ProvingBackend>,
+        #[arg(long, value_name = "DIR")]
+        measure: Option<String>,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    Validate {
+        pattern: String,
+        #[arg(long)]
+        raw: bool,
+        #[arg(long, requires = "raw")]
+        capture_groups: bool,
+        #[arg(long, default_value = "10")]
+        count: usize,
+        #[arg(long, default_value = "1")]
+        min_len: usize,
+        #[arg(long, default_value = "32")]
+        max_len: usize,
+        #[arg(long, default_value = "0")]
+        seed: u64,
+        #[arg(long, default_value = "auto")]
+        strategy: NoirStrategy,
+        #[arg(long, default_value = "field")]
+        noir_state_type: NoirStateType,
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
     },
 }
 
 fn main() {
     let cli = Cli::parse();
+    let level = match cli.verbose {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .init();
+    // The timings of the proving steps are the point of the prove command.
+    if cli.profile || matches!(cli.command, Commands::Prove { .. }) {
+        start_profiling();
+    }
+    if cli.progress {
+        start_progress(PROGRESS_INTERVAL);
+    }
     match cli.command {
         Commands::Decomposed { .. } => process_decomposed(cli),
         Commands::Raw { .. } => process_raw(cli),
+        Commands::Manifest { .. } => process_manifest(cli),
+        Commands::Denylist { .. } => process_denylist(cli),
+        Commands::Compose { .. } => process_compose(cli),
+        Commands::Fields { .. } => process_fields(cli),
+        Commands::Prove { .. } => process_prove(cli),
+        Commands::Test { .. } => process_test(cli),
+        Commands::Init { .. } => process_init(cli),
+        Commands::Migrate { .. } => process_migrate(cli),
+        Commands::Runtime { .. } => process_runtime(cli),
+        Commands::Diff { .. } => process_diff(cli),
+        Commands::Equivalent { .. } => process_equivalent(cli),
+        Commands::Sample { .. } => process_sample(cli),
+        Commands::Explain { .. } => process_explain(cli),
+        Commands::Bench { .. } => process_bench(cli),
+        Commands::Validate { .. } => process_validate(cli),
+    }
+    if let Some(report) = finish_profiling() {
+        eprintln!("{}", report);
     }
 }
 
@@ -99,16 +1408,55 @@ fn process_decomposed(cli: Cli) {
         halo2_dir_path,
         circom_file_path,
         template_name,
+        noir_file_path,
+        example,
+        aztec_contract,
+        aztec_input_len,
+        noir_main,
+        noir_main_outputs,
+        noir,
+        noir_capture_overflow,
         gen_substrs,
+        vars,
+        lenient_newlines,
+        dialect,
+        permissive_escapes,
+        max_estimated_gates,
+        input_len,
+        report,
+        emit,
+        out,
+        ..
     } = cli.command
     {
-        if let Err(e) = gen_from_decomposed(
-            &decomposed_regex_path,
-            halo2_dir_path.as_deref(),
-            circom_file_path.as_deref(),
-            template_name.as_deref(),
+        let noir_options = NoirOptions {
+            input_len,
+            capture_overflow: noir_capture_overflow,
+            ..NoirOptions::from(&noir)
+        };
+        let paths = OutputPaths {
+            halo2_dir: halo2_dir_path,
+            circom_file: circom_file_path,
+            noir_file: noir_file_path,
+            noir_example_dir: example,
+            aztec_contract_dir: aztec_contract,
+            aztec_input_len,
+            noir_main: main_options(noir_main, input_len, noir_main_outputs),
+            report,
+            emit: emit_options(emit, out),
+        };
+        let options = CompileOptions {
+            circom_template_name: template_name,
+            noir: Some(noir_options),
             gen_substrs,
-        ) {
+            template_vars: vars.into_iter().collect(),
+            lenient_newlines,
+            dialect,
+            permissive_escapes,
+            gate_budget: gate_budget(max_estimated_gates, input_len),
+            ..Default::default()
+        };
+        if let Err(e) = gen_from_decomposed(&decomposed_regex_path, &paths, &options) {
             eprintln!("Error: {}", e);
             std::process::exit(1);
         }
@@ -118,23 +1466,918 @@ fn process_decomposed(cli: Cli) {
 fn process_raw(cli: Cli) {
     if let Commands::Raw {
         raw_regex,
+        pattern_file,
+        preset,
         substrs_json_path,
+        substr_ranges,
+        substr_names,
+        capture_groups,
+        accept_states,
         halo2_dir_path,
         circom_file_path,
         template_name,
+        noir_file_path,
+        example,
+        aztec_contract,
+        aztec_input_len,
+        noir_main,
+        noir_main_outputs,
+        noir,
+        noir_repetition_counter,
         gen_substrs,
+        vars,
+        lenient_newlines,
+        dialect,
+        permissive_escapes,
+        max_estimated_gates,
+        input_len,
+        report,
+        emit,
+        out,
+        ..
     } = cli.command
     {
+        let raw_regex = match (raw_regex, pattern_file, preset) {
+            (Some(raw_regex), _, _) => raw_regex,
+            (None, Some(path), _) => match read_pattern(&path) {
+                Ok(raw_regex) => raw_regex,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            (None, None, Some(name)) => match presets::get(&name) {
+                Some(raw_regex) => raw_regex.to_string(),
+                None => {
+                    let names = presets::PRESETS.iter().map(|(name, _)| *name);
+                    eprintln!(
+                        "Error: unknown preset \"{}\", expected one of: {}",
+                        name,
+                        names.collect::<Vec<_>>().join(", ")
+                    );
+                    std::process::exit(1);
+                }
+            },
+            (None, None, None) => unreachable!("clap requires one of the pattern arguments"),
+        };
+        let noir_options = NoirOptions {
+            input_len,
+            repetition_counter: noir_repetition_counter,
+            ..NoirOptions::from(&noir)
+        };
+        let paths = OutputPaths {
+            halo2_dir: halo2_dir_path,
+            circom_file: circom_file_path,
+            noir_file: noir_file_path,
+            noir_example_dir: example,
+            aztec_contract_dir: aztec_contract,
+            aztec_input_len,
+            noir_main: main_options(noir_main, input_len, noir_main_outputs),
+            report,
+            emit: emit_options(emit, out),
+        };
+        let options = CompileOptions {
+            substr_transitions: substr_ranges,
+            substr_names: substr_names.into_iter().map(Some).collect(),
+            capture_groups,
+            circom_template_name: template_name,
+            noir: Some(noir_options),
+            gen_substrs,
+            template_vars: vars.into_iter().collect(),
+            lenient_newlines,
+            dialect,
+            permissive_escapes,
+            gate_budget: gate_budget(max_estimated_gates, input_len),
+            ..Default::default()
+        };
         if let Err(e) = gen_from_raw(
             &raw_regex,
             substrs_json_path.as_deref(),
-            halo2_dir_path.as_deref(),
-            circom_file_path.as_deref(),
-            template_name.as_deref(),
+            accept_states
+                .map(|states| states.into_iter().collect())
+                .as_ref(),
+            &paths,
+            &options,
+        ) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn process_manifest(cli: Cli) {
+    if let Commands::Manifest {
+        manifest_path,
+        noir_file_path,
+        noir_input_type,
+        noir_input_hash,
+        strategy,
+        noir_state_type,
+        noir_max_branches,
+        noir_compact,
+        noir_class_predicates,
+        noir_empty_match,
+        noir_match_semantics,
+        noir_lowercase_captures,
+        noir_capture_starts,
+        noir_occurrence,
+        noir_reverse,
+        noir_reverse_window,
+        noir_input_alphabet,
+        noir_imports,
+        noir_fused,
+        noir_runtime,
+        gen_substrs,
+        vars,
+        lenient_newlines,
+        dialect,
+        permissive_escapes,
+    } = cli.command
+    {
+        let noir_options = NoirOptions {
+            input_type: noir_input_type,
+            input_hash: noir_input_hash,
+            strategy,
+            input_len: None,
+            state_type: noir_state_type,
+            max_branches: noir_max_branches,
+            compact: noir_compact,
+            class_predicates: noir_class_predicates,
+            empty_match: noir_empty_match,
+            match_semantics: noir_match_semantics,
+            lowercase_captures: noir_lowercase_captures,
+            capture_starts: noir_capture_starts,
+            occurrence: noir_occurrence,
+            reverse: noir_reverse,
+            reverse_window: noir_reverse_window,
+            input_alphabet: noir_input_alphabet,
+            imports: noir_imports,
+            fused: noir_fused,
+            runtime: noir_runtime,
+            ..Default::default()
+        };
+        let options = CompileOptions {
+            noir: Some(noir_options),
             gen_substrs,
+            template_vars: vars.into_iter().collect(),
+            lenient_newlines,
+            dialect,
+            permissive_escapes,
+            ..Default::default()
+        };
+        if let Err(e) = gen_from_manifest(&manifest_path, &noir_file_path, &options) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn process_denylist(cli: Cli) {
+    if let Commands::Denylist {
+        mut raw_regexes,
+        pattern_file,
+        noir_file_path,
+        strategy,
+        noir_state_type,
+        noir_compact,
+        noir_class_predicates,
+        vars,
+    } = cli.command
+    {
+        if let Some(path) = pattern_file {
+            match read_pattern(&path) {
+                Ok(patterns) => raw_regexes.extend(
+                    patterns
+                        .lines()
+                        .filter(|line| !line.is_empty())
+                        .map(str::to_string),
+                ),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        let noir_options = NoirOptions {
+            strategy,
+            input_len: None,
+            state_type: noir_state_type,
+            compact: noir_compact,
+            class_predicates: noir_class_predicates,
+            ..Default::default()
+        };
+        if let Err(e) = gen_denylist(
+            &raw_regexes,
+            &noir_file_path,
+            &noir_options,
+            &vars.into_iter().collect(),
+        ) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn process_compose(cli: Cli) {
+    if let Commands::Compose {
+        raw_regexes,
+        noir_file_path,
+        composition,
+        strategy,
+        noir_state_type,
+        noir_compact,
+        noir_class_predicates,
+        vars,
+    } = cli.command
+    {
+        let noir_options = NoirOptions {
+            strategy,
+            input_len: None,
+            state_type: noir_state_type,
+            compact: noir_compact,
+            class_predicates: noir_class_predicates,
+            ..Default::default()
+        };
+        if let Err(e) = gen_from_composition(
+            composition,
+            &raw_regexes,
+            &noir_file_path,
+            &noir_options,
+            &vars.into_iter().collect(),
+        ) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn process_fields(cli: Cli) {
+    if let Commands::Fields {
+        fields_path,
+        noir_file_path,
+        decomposed_out,
+        strategy,
+        noir_state_type,
+        noir_compact,
+    } = cli.command
+    {
+        let noir_options = NoirOptions {
+            strategy,
+            state_type: noir_state_type,
+            compact: noir_compact,
+            ..Default::default()
+        };
+        if let Err(e) = gen_from_fields(
+            &fields_path,
+            &noir_file_path,
+            decomposed_out.as_deref(),
+            &noir_options,
         ) {
             eprintln!("Error: {}", e);
             std::process::exit(1);
         }
     }
 }
+
+fn process_prove(cli: Cli) {
+    if let Commands::Prove {
+        decomposed_regex_path,
+        raw_regex,
+        substrs_json_path,
+        input,
+        input_file,
+        project_dir,
+        noir_input_type,
+        noir_input_hash,
+        strategy,
+        noir_state_type,
+        noir_empty_match,
+        noir_match_semantics,
+        noir_lowercase_captures,
+        noir_capture_starts,
+        noir_occurrence,
+        noir_reverse,
+        noir_reverse_window,
+        noir_input_alphabet,
+        noir_imports,
+        noir_dependencies,
+        gen_substrs,
+        vars,
+    } = cli.command
+    {
+        let input = match (input, input_file) {
+            (Some(input), _) => input,
+            (None, Some(path)) => match read_pattern(&path) {
+                Ok(input) => input,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            (None, None) => unreachable!("clap requires one of the input arguments"),
+        };
+        let noir_options = NoirOptions {
+            input_type: noir_input_type,
+            input_hash: noir_input_hash,
+            strategy,
+            input_len: None,
+            state_type: noir_state_type,
+            empty_match: noir_empty_match,
+            match_semantics: noir_match_semantics,
+            lowercase_captures: noir_lowercase_captures,
+            capture_starts: noir_capture_starts,
+            occurrence: noir_occurrence,
+            reverse: noir_reverse,
+            reverse_window: noir_reverse_window,
+            input_alphabet: noir_input_alphabet,
+            imports: noir_imports,
+            dependencies: noir_dependencies.into_iter().collect(),
+            ..Default::default()
+        };
+        let vars = vars.into_iter().collect();
+        let proof = match (decomposed_regex_path, raw_regex) {
+            (Some(path), _) => prove_from_decomposed(
+                &path,
+                &input,
+                &project_dir,
+                &noir_options,
+                gen_substrs,
+                &vars,
+            ),
+            (None, Some(raw_regex)) => prove_from_raw(
+                &raw_regex,
+                substrs_json_path.as_deref(),
+                &input,
+                &project_dir,
+                &noir_options,
+                gen_substrs,
+                &vars,
+            ),
+            (None, None) => unreachable!("clap requires one of the pattern arguments"),
+        };
+        match proof {
+            Ok(proof) => println!("Proof verified, written to {}", proof.display()),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn process_test(cli: Cli) {
+    if let Commands::Test {
+        manifest_path,
+        noir_input_type,
+        strategy,
+        noir_state_type,
+        noir_empty_match,
+        noir_match_semantics,
+        noir_lowercase_captures,
+        noir_capture_starts,
+        noir_occurrence,
+        noir_reverse,
+        noir_reverse_window,
+        noir_input_alphabet,
+        noir_imports,
+        noir_dependencies,
+        gen_substrs,
+        vars,
+        lenient_newlines,
+        dialect,
+        permissive_escapes,
+    } = cli.command
+    {
+        let noir_options = NoirOptions {
+            input_type: noir_input_type,
+            strategy,
+            input_len: None,
+            state_type: noir_state_type,
+            empty_match: noir_empty_match,
+            match_semantics: noir_match_semantics,
+            lowercase_captures: noir_lowercase_captures,
+            capture_starts: noir_capture_starts,
+            occurrence: noir_occurrence,
+            reverse: noir_reverse,
+            reverse_window: noir_reverse_window,
+            input_alphabet: noir_input_alphabet,
+            imports: noir_imports,
+            dependencies: noir_dependencies.into_iter().collect(),
+            ..Default::default()
+        };
+        let results = match test_from_manifest(
+            &manifest_path,
+            &noir_options,
+            gen_substrs,
+            &vars.into_iter().collect(),
+            lenient_newlines,
+            dialect,
+            permissive_escapes,
+        ) {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        for result in &results {
+            match result.passed {
+                true => println!("PASS {}", result.name),
+                false => {
+                    println!("FAIL {} ({})", result.name, result.project_dir.display());
+                    println!("{}", result.output.trim_end());
+                }
+            }
+        }
+        let failed = results.iter().filter(|result| !result.passed).count();
+        println!("{} passed, {} failed", results.len() - failed, failed);
+        if failed > 0 {
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Parses a `NAME=VALUE` pattern variable.
+fn process_init(cli: Cli) {
+    if let Commands::Init { project_dir } = cli.command {
+        if let Err(e) = init_project(&project_dir) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn process_migrate(cli: Cli) {
+    if let Commands::Migrate {
+        circom_dir,
+        out_dir,
+        strategy,
+        noir_state_type,
+    } = cli.command
+    {
+        let noir_options = NoirOptions {
+            strategy,
+            state_type: noir_state_type,
+            ..Default::default()
+        };
+        match migrate_circom_project(&circom_dir, &out_dir, &noir_options) {
+            Ok(report) => {
+                for (path, reason) in &report.skipped {
+                    warn!(path = %path.display(), "skipped: {reason}");
+                }
+                for note in &report.notes {
+                    warn!(pattern = %note.pattern, "{}", note.message);
+                }
+                println!(
+                    "Migrated {} patterns to {}",
+                    report.patterns.len(),
+                    Path::new(&out_dir).join("patterns.json").display()
+                );
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn process_runtime(cli: Cli) {
+    if let Commands::Runtime {
+        out_dir,
+        name,
+        noir_imports,
+        noir_dependencies,
+    } = cli.command
+    {
+        let noir_options = NoirOptions {
+            runtime: name,
+            imports: noir_imports,
+            dependencies: noir_dependencies.into_iter().collect(),
+            ..Default::default()
+        };
+        if let Err(e) = gen_noir_runtime(&out_dir, &noir_options) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn process_diff(cli: Cli) {
+    if let Commands::Diff {
+        old_path,
+        new_path,
+        vars,
+    } = cli.command
+    {
+        let diff = match diff_decomposed(&old_path, &new_path, &vars.into_iter().collect()) {
+            Ok(diff) => diff,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        println!("--- {}", old_path);
+        println!("+++ {}", new_path);
+        print!("{}", diff);
+        if !diff.is_empty() {
+            std::process::exit(1);
+        }
+    }
+}
+
+fn process_equivalent(cli: Cli) {
+    if let Commands::Equivalent { a, b, raw, vars } = cli.command {
+        match check_equivalence(&a, &b, raw, &vars.into_iter().collect()) {
+            Ok(None) => println!("equivalent"),
+            Ok(Some((input, accepted_by_a))) => {
+                let (accepting, rejecting) = match accepted_by_a {
+                    true => (&a, &b),
+                    false => (&b, &a),
+                };
+                println!(
+                    "not equivalent: \"{}\" is accepted by {} but not by {}",
+                    input.escape_ascii(),
+                    accepting,
+                    rejecting
+                );
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn process_sample(cli: Cli) {
+    if let Commands::Sample {
+        pattern,
+        raw,
+        count,
+        min_len,
+        max_len,
+        seed,
+        near_misses,
+        prover_toml,
+        vars,
+    } = cli.command
+    {
+        let options = SampleOptions {
+            count,
+            min_len,
+            max_len,
+            seed,
+            near_misses,
+        };
+        let samples = match gen_samples(&pattern, raw, &options, &vars.into_iter().collect()) {
+            Ok(samples) => samples,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        for sample in samples {
+            match prover_toml {
+                true => println!(
+                    "# \"{}\"\ninput = [{}]\n",
+                    sample.escape_ascii(),
+                    sample.iter().join(", ")
+                ),
+                false => println!("{}", sample.escape_ascii()),
+            }
+        }
+    }
+}
+
+fn process_explain(cli: Cli) {
+    if let Commands::Explain {
+        pattern,
+        raw,
+        capture_groups,
+        vars,
+    } = cli.command
+    {
+        match explain_pattern(&pattern, raw, capture_groups, &vars.into_iter().collect()) {
+            Ok(text) => print!("{}", text),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn process_bench(cli: Cli) {
+    if let Commands::Bench {
+        pattern,
+        raw,
+        capture_groups,
+        sizes,
+        noir_input_type,
+        backends,
+        measure,
+        vars,
+    } = cli.command
+    {
+        let noir_options = NoirOptions {
+            input_type: noir_input_type,
+            ..Default::default()
+        };
+        let vars = vars.into_iter().collect();
+        let estimates =
+            match bench_pattern(&pattern, raw, capture_groups, &sizes, &noir_options, &vars) {
+                Ok(estimates) => estimates,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+        // Every input length is estimated with the same strategies.
+        let strategies = estimates
+            .iter()
+            .take_while(|estimate| estimate.input_len == estimates[0].input_len)
+            .map(|estimate| estimate.strategy)
+            .collect_vec();
+        let header = strategies
+            .iter()
+            .map(|strategy| format!("{:>12}", strategy))
+            .join("");
+        println!("{:>12}{}", "input len", header);
+        for (input_len, row) in &estimates.iter().chunk_by(|estimate| estimate.input_len) {
+            let cells = row
+                .map(|estimate| {
+                    let mark = if estimate.chosen { "*" } else { "" };
+                    format!("{:>12}", format!("{}{}", estimate.gates, mark))
+                })
+                .join("");
+            println!("{:>12}{}", input_len, cells);
+        }
+        let per_unit = estimates
+            .iter()
+            .take(strategies.len())
+            .map(|estimate| format!("{:>12}", estimate.per_unit))
+            .join("");
+        println!("{:>12}{}", "per unit", per_unit);
+
+        let costs = match &measure {
+            Some(dir) => measure_proving(
+                &pattern,
+                raw,
+                &sizes,
+                &backends,
+                dir,
+                &CompileOptions {
+                    capture_groups,
+                    noir: Some(noir_options.clone()),
+                    template_vars: vars.clone(),
+                    ..Default::default()
+                },
+            ),
+            None => bench_proving(
+                &pattern,
+                raw,
+                capture_groups,
+                &sizes,
+                &backends,
+                &noir_options,
+                &vars,
+            ),
+        };
+        let costs = match costs {
+            Ok(costs) => costs,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        let source = if measure.is_some() {
+            "measured"
+        } else {
+            "estimated"
+        };
+        println!();
+        println!("proving cost ({})", source);
+        println!(
+            "{:>12}{:>14}{:>14}{:>14}{:>14}{:>14}",
+            "input len", "backend", "gates", "circuit size", "prove ms", "memory MB"
+        );
+        for cost in &costs {
+            let memory = cost.peak_memory_kb.map_or("unavailable".to_string(), |kb| {
+                format!("{:.1}", kb as f64 / 1024.0)
+            });
+            println!(
+                "{:>12}{:>14}{:>14}{:>14}{:>14}{:>14}",
+                cost.input_len, cost.backend, cost.gates, cost.circuit_size, cost.prove_ms, memory
+            );
+        }
+    }
+}
+
+fn process_validate(cli: Cli) {
+    if let Commands::Validate {
+        pattern,
+        raw,
+        capture_groups,
+        count,
+        min_len,
+        max_len,
+        seed,
+        strategy,
+        noir_state_type,
+        vars,
+    } = cli.command
+    {
+        let options = SampleOptions {
+            count,
+            min_len,
+            max_len,
+            seed,
+            near_misses: false,
+        };
+        let noir_options = NoirOptions {
+            strategy,
+            state_type: noir_state_type,
+            ..Default::default()
+        };
+        let (checked, divergences) = match validate_pattern(
+            &pattern,
+            raw,
+            capture_groups,
+            &options,
+            &noir_options,
+            &vars.into_iter().collect(),
+        ) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        let fmt_substrs = |substrs: &[Vec<u8>]| {
+            substrs
+                .iter()
+                .map(|substr| format!("\"{}\"", substr.escape_ascii()))
+                .join(", ")
+        };
+        for divergence in &divergences {
+            let simulated = match &divergence.simulated {
+                Some(substrs) => format!("accepts [{}]", fmt_substrs(substrs)),
+                None => "rejects".to_string(),
+            };
+            let noir = match (divergence.noir_accepts, &divergence.simulated) {
+                (true, Some(_)) => "extracts other substrings",
+                (true, None) => "accepts",
+                (false, _) => "rejects",
+            };
+            println!(
+                "DIVERGENCE \"{}\": the simulation {}, Noir {} ({})",
+                divergence.input.escape_ascii(),
+                simulated,
+                noir,
+                divergence.project_dir.display()
+            );
+            println!("{}", divergence.output.trim_end());
+        }
+        println!(
+            "{} inputs checked, {} divergent",
+            checked,
+            divergences.len()
+        );
+        if !divergences.is_empty() {
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parse_size(size: &str) -> Result<usize, String> {
+    let size = size.trim();
+    let (digits, scale) = match size.to_ascii_uppercase() {
+        upper if upper.ends_with("KB") || upper.ends_with('K') => (
+            upper
+                .trim_end_matches('B')
+                .trim_end_matches('K')
+                .to_string(),
+            1 << 10,
+        ),
+        upper if upper.ends_with("MB") || upper.ends_with('M') => (
+            upper
+                .trim_end_matches('B')
+                .trim_end_matches('M')
+                .to_string(),
+            1 << 20,
+        ),
+        upper => (upper, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(scale))
+        .ok_or_else(|| format!("expected a size such as 4096 or 4K, got \"{}\"", size))
+}
+
+fn parse_var(var: &str) -> Result<(String, String), String> {
+    var.split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected NAME=VALUE, got \"{}\"", var))
+}
+
+fn parse_substr_range(ranges: &str) -> Result<Vec<(usize, usize)>, String> {
+    ranges
+        .split(',')
+        .map(|range| {
+            let (from, to) = range
+                .split_once('-')
+                .ok_or_else(|| format!("expected FROM-TO, got \"{}\"", range))?;
+            let state = |state: &str| {
+                state
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("expected a state number, got \"{}\"", state))
+            };
+            Ok((state(from)?, state(to)?))
+        })
+        .collect()
+}
+
+fn gate_budget(max_gates: Option<usize>, input_len: Option<usize>) -> Option<GateBudget> {
+    // clap requires the two flags together.
+    Some(GateBudget {
+        max_gates: max_gates?,
+        input_len: input_len?,
+    })
+}
+
+fn main_options(
+    path: Option<String>,
+    input_len: Option<usize>,
+    outputs: Vec<MainOutput>,
+) -> Option<NoirMainOptions> {
+    // clap requires the input length with the path.
+    Some(NoirMainOptions {
+        path: path?,
+        input_len: input_len?,
+        outputs,
+    })
+}
+
+fn read_corpus(path: Option<&str>) -> Vec<Vec<u8>> {
+    let Some(path) = path else {
+        return vec![];
+    };
+    let text = match std::fs::read(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Error: cannot read the corpus {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    let mut corpus = vec![];
+    for line in text.split(|&byte| byte == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        match unescape(line) {
+            Some(input) => corpus.push(input),
+            None => {
+                eprintln!(
+                    "Error: invalid escape in the corpus line \"{}\"",
+                    line.escape_ascii()
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+    corpus
+}
+
+/// Reverses `escape_ascii`, as used by the `sample` command.
+fn unescape(line: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut rest = line.iter().copied();
+    while let Some(byte) = rest.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        bytes.push(match rest.next()? {
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'x' => {
+                let hex = [rest.next()?, rest.next()?];
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?
+            }
+            escaped @ (b'\\' | b'\'' | b'"') => escaped,
+            _ => return None,
+        });
+    }
+    Some(bytes)
+}
+
+fn emit_options(kinds: Vec<EmitKind>, stem: Option<String>) -> Option<EmitOptions> {
+    // clap requires the two flags together.
+    Some(EmitOptions { stem: stem?, kinds })
+}
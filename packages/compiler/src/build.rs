@@ -0,0 +1,268 @@
+//! Regenerating the Noir matchers of a project from its `build.rs` or an xtask.
+//!
+//! `compile_manifests` compiles every pattern manifest of a directory into a Noir library of
+//! the same name in a target directory. A library is only regenerated when its manifest, the
+//! pattern files the manifest refers to, the options or the compiler version changed since it
+//! was last written, so that running it on every build costs little:
+//!
+//! ```no_run
+//! // build.rs
+//! use std::path::Path;
+//! use zk_regex_compiler::build::{compile_manifests, BuildOptions};
+//!
+//! let report = compile_manifests(
+//!     Path::new("patterns"),
+//!     Path::new("src/regex"),
+//!     &BuildOptions::default(),
+//! )
+//! .unwrap();
+//! report.emit_rerun_if_changed();
+//! ```
+
+use crate::{
+    errors::CompilerError,
+    gen_from_manifest,
+    structs::{CompileOptions, Dialect, NoirOptions, PatternManifest, SubstrSelection},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, read, read_dir, read_to_string, write},
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+/// The options every manifest of a directory is compiled with, as for the `manifest` command.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BuildOptions {
+    /// The options controlling the generated Noir matchers.
+    pub noir: NoirOptions,
+    /// The substrings to extract from every pattern, none by default.
+    pub gen_substrs: Option<SubstrSelection>,
+    /// The values of the `{{name}}` variables used in the patterns, overriding those given in
+    /// the manifests.
+    pub template_vars: BTreeMap<String, String>,
+    /// Whether every `\r\n` of the patterns also matches a bare `\n`.
+    pub lenient_newlines: bool,
+    /// The regex syntax the patterns are written in.
+    pub dialect: Dialect,
+    /// Whether unknown escapes such as `\q` match the escaped character instead of failing.
+    pub permissive_escapes: bool,
+}
+
+/// The outcome of `compile_manifests`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildReport {
+    /// The Noir libraries regenerated because their inputs changed.
+    pub compiled: Vec<PathBuf>,
+    /// The Noir libraries left as they were.
+    pub up_to_date: Vec<PathBuf>,
+    /// The manifest directory, the manifests and the pattern files they refer to.
+    pub inputs: Vec<PathBuf>,
+}
+
+impl BuildReport {
+    /// Prints a `cargo:rerun-if-changed` line for every input, so that a build script only
+    /// runs again when a pattern changes.
+    pub fn emit_rerun_if_changed(&self) {
+        for input in &self.inputs {
+            println!("cargo:rerun-if-changed={}", input.display());
+        }
+    }
+}
+
+/// Compiles every pattern manifest of a directory into a Noir library in a target directory,
+/// skipping the manifests whose library is up to date.
+///
+/// A manifest is a `.json` file of the directory with a top-level `patterns` field, and its
+/// library is written to `<target_dir>/<stem>.nr` as by `gen_from_manifest`. The other JSON
+/// files, such as the decomposed regexes the manifests refer to, are skipped. The SHA-256
+/// fingerprint of the inputs and options of a library is kept next to it in
+/// `<stem>.nr.fingerprint`, written once the library is complete.
+///
+/// # Arguments
+///
+/// * `manifest_dir` - The directory holding the manifests.
+/// * `target_dir` - The directory the Noir libraries are written to, created if needed.
+/// * `options` - The options every manifest is compiled with.
+///
+/// # Returns
+///
+/// A `Result` containing the `BuildReport`, or a `CompilerError` if a manifest cannot be read
+/// or compiled.
+pub fn compile_manifests(
+    manifest_dir: &Path,
+    target_dir: &Path,
+    options: &BuildOptions,
+) -> Result<BuildReport, CompilerError> {
+    let mut manifests = vec![];
+    for entry in read_dir(manifest_dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+            && is_manifest(&path)?
+        {
+            manifests.push(path);
+        }
+    }
+    manifests.sort();
+
+    create_dir_all(target_dir)?;
+    let mut report = BuildReport {
+        inputs: vec![manifest_dir.to_path_buf()],
+        ..Default::default()
+    };
+    for manifest in manifests {
+        let stem = manifest
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let noir_path = target_dir.join(format!("{stem}.nr"));
+        let fingerprint_path = target_dir.join(format!("{stem}.nr.fingerprint"));
+        let inputs = manifest_inputs(&manifest)?;
+        let fingerprint = fingerprint(&inputs, options)?;
+        let fresh = noir_path.exists()
+            && read_to_string(&fingerprint_path).is_ok_and(|stored| stored.trim() == fingerprint);
+        if fresh {
+            info!(path = %noir_path.display(), "the Noir library is up to date");
+            report.up_to_date.push(noir_path);
+        } else {
+            info!(path = %noir_path.display(), "regenerating the Noir library");
+            gen_from_manifest(
+                &manifest.to_string_lossy(),
+                &noir_path.to_string_lossy(),
+                &CompileOptions {
+                    noir: Some(options.noir.clone()),
+                    gen_substrs: options.gen_substrs.clone(),
+                    template_vars: options.template_vars.clone(),
+                    lenient_newlines: options.lenient_newlines,
+                    dialect: options.dialect,
+                    permissive_escapes: options.permissive_escapes,
+                    ..Default::default()
+                },
+            )?;
+            write(&fingerprint_path, format!("{fingerprint}\n"))?;
+            report.compiled.push(noir_path);
+        }
+        report.inputs.extend(inputs);
+    }
+    Ok(report)
+}
+
+/// Returns whether a JSON file is a pattern manifest, i.e. has a top-level `patterns` field.
+fn is_manifest(path: &Path) -> Result<bool, CompilerError> {
+    let value: serde_json::Value = serde_json::from_slice(&read(path)?)?;
+    Ok(value.get("patterns").is_some())
+}
+
+/// Lists the files a manifest is compiled from: the manifest itself, the fragment files it
+/// includes, then the decomposed regexes and substring definitions of its patterns, resolved
+/// against its directory.
+fn manifest_inputs(manifest_path: &Path) -> Result<Vec<PathBuf>, CompilerError> {
+    let manifest: PatternManifest = serde_json::from_slice(&read(manifest_path)?)?;
+    let base_dir = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+    let mut inputs = vec![manifest_path.to_path_buf()];
+    inputs.extend(manifest.includes.iter().map(|path| base_dir.join(path)));
+    for pattern in &manifest.patterns {
+        inputs.extend(
+            pattern
+                .decomposed_regex_path
+                .iter()
+                .chain(&pattern.substrs_json_path)
+                .map(|path| base_dir.join(path)),
+        );
+    }
+    Ok(inputs)
+}
+
+/// Hashes the compiler version, the options and the paths and contents of the inputs of a
+/// Noir library, which change whenever the library would.
+///
+/// # Arguments
+///
+/// * `inputs` - The files the library is compiled from.
+/// * `options` - The options it is compiled with.
+///
+/// # Returns
+///
+/// A `Result` containing the hex-encoded SHA-256 fingerprint, or a `CompilerError` if an input
+/// cannot be read.
+fn fingerprint(inputs: &[PathBuf], options: &BuildOptions) -> Result<String, CompilerError> {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update(serde_json::to_vec(options)?);
+    for input in inputs {
+        let contents = read(input)?;
+        hasher.update(input.to_string_lossy().as_bytes());
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(contents);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::progress::ScratchDir;
+
+    #[test]
+    fn test_compile_manifests_skips_unchanged() {
+        let scratch = ScratchDir::new("zk_regex_test_build").unwrap();
+        let dir = scratch.path();
+        let (patterns, target) = (dir.join("patterns"), dir.join("target"));
+        create_dir_all(&patterns).unwrap();
+        write(
+            patterns.join("mail.json"),
+            r#"{"patterns": [
+                {"name": "from", "raw_regex": "from:[a-z]+;"},
+                {"name": "to", "decomposed_regex_path": "to.json"}
+            ]}"#,
+        )
+        .unwrap();
+        write(
+            patterns.join("to.json"),
+            r#"{"parts": [{"is_public": false, "regex_def": "to:"}, {"is_public": true, "regex_def": "[a-z]+"}, {"is_public": false, "regex_def": ";"}]}"#,
+        )
+        .unwrap();
+
+        let options = BuildOptions::default();
+        let report = compile_manifests(&patterns, &target, &options).unwrap();
+        assert_eq!(report.compiled, vec![target.join("mail.nr")]);
+        assert!(report.up_to_date.is_empty());
+        assert_eq!(
+            report.inputs,
+            vec![
+                patterns.clone(),
+                patterns.join("mail.json"),
+                patterns.join("to.json")
+            ]
+        );
+        let code = read_to_string(target.join("mail.nr")).unwrap();
+        assert!(code.contains("pub fn regex_match_from<let N: u32>"));
+
+        let report = compile_manifests(&patterns, &target, &options).unwrap();
+        assert!(report.compiled.is_empty());
+        assert_eq!(report.up_to_date, vec![target.join("mail.nr")]);
+
+        // A change to a referenced pattern or to the options regenerates the library.
+        write(
+            patterns.join("to.json"),
+            r#"{"parts": [{"is_public": false, "regex_def": "to:"}, {"is_public": true, "regex_def": "[a-z0-9]+"}, {"is_public": false, "regex_def": ";"}]}"#,
+        )
+        .unwrap();
+        let report = compile_manifests(&patterns, &target, &options).unwrap();
+        assert_eq!(report.compiled, vec![target.join("mail.nr")]);
+        let options = BuildOptions {
+            gen_substrs: Some(SubstrSelection::All),
+            ..Default::default()
+        };
+        let report = compile_manifests(&patterns, &target, &options).unwrap();
+        assert_eq!(report.compiled, vec![target.join("mail.nr")]);
+    }
+}
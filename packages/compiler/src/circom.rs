@@ -3,13 +3,17 @@ use crate::{
     regex::get_accepted_state,
     structs::{DFAGraph, RegexAndDFA},
 };
+use itertools::Itertools;
 use std::{
     collections::{BTreeMap, BTreeSet},
-    fs::File,
-    io::Write,
-    path::Path,
+    fmt::Write,
 };
 
+#[cfg(feature = "fs")]
+use crate::progress::write_file;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
 /// Builds a reverse graph from a DFA graph and collects accept nodes.
 ///
 /// This function creates a reverse graph where the direction of edges is inverted,
@@ -480,13 +484,15 @@ fn generate_state_transition_logic(
             if *prev_i == 0 {
                 zero_starting_states.push(i);
             }
-            let mut k = chars.clone();
-            k.retain(|&x| x != 0);
-            k.sort();
+            // The bytes come sorted from the DFA, so a 0 byte can only come first.
+            let k = match chars.split_first() {
+                Some((0, rest)) => rest,
+                _ => chars.as_slice(),
+            };
 
             let mut eq_outputs = vec![];
 
-            let (min_maxes, individual_chars) = optimize_char_ranges(&k);
+            let (min_maxes, individual_chars) = optimize_char_ranges(k);
 
             for (min, max) in min_maxes {
                 add_range_check(
@@ -747,9 +753,10 @@ fn gen_circom_allstr(
 
     let accept_lines = generate_accept_logic(accept_nodes, end_anchor);
 
-    let final_code = [declarations, init_code, lines, accept_lines].concat();
-
-    final_code.join("\n")
+    [declarations, init_code, lines, accept_lines]
+        .iter()
+        .flatten()
+        .join("\n")
 }
 
 /// Writes the consecutive logic for the Circom circuit.
@@ -769,13 +776,15 @@ fn write_consecutive_logic(accepted_state: usize) -> String {
     logic += "\tsignal is_consecutive[msg_bytes+1][3];\n";
     logic += "\tis_consecutive[msg_bytes][2] <== 0;\n";
     logic += "\tfor (var i = 0; i < msg_bytes; i++) {\n";
-    logic += &format!(
-        "\t\tis_consecutive[msg_bytes-1-i][0] <== states[num_bytes-i][{accepted_state}] * (1 - is_consecutive[msg_bytes-i][2]) + is_consecutive[msg_bytes-i][2];\n"
+    let _ = writeln!(
+        logic,
+        "\t\tis_consecutive[msg_bytes-1-i][0] <== states[num_bytes-i][{accepted_state}] * (1 - is_consecutive[msg_bytes-i][2]) + is_consecutive[msg_bytes-i][2];"
     );
     logic +=
         "\t\tis_consecutive[msg_bytes-1-i][1] <== state_changed[msg_bytes-i].out * is_consecutive[msg_bytes-1-i][0];\n";
-    logic += &format!(
-        "\t\tis_consecutive[msg_bytes-1-i][2] <== ORAnd()([(1 - from_zero_enabled[msg_bytes-i+1]), states[num_bytes-i][{accepted_state}], is_consecutive[msg_bytes-1-i][1]]);\n"
+    let _ = writeln!(
+        logic,
+        "\t\tis_consecutive[msg_bytes-1-i][2] <== ORAnd()([(1 - from_zero_enabled[msg_bytes-i+1]), states[num_bytes-i][{accepted_state}], is_consecutive[msg_bytes-1-i][1]]);"
     );
     logic += "\t}\n";
     logic
@@ -797,12 +806,14 @@ fn write_prev_states(idx: usize, ranges: &[&(usize, usize)]) -> String {
     let mut prev_states = String::new();
     for (trans_idx, &(cur, _)) in ranges.iter().enumerate() {
         if *cur == 0 {
-            prev_states += &format!(
-                "\t\tprev_states{idx}[{trans_idx}][i] <== from_zero_enabled[i+1] * states[i+1][{cur}];\n"
+            let _ = writeln!(
+                prev_states,
+                "\t\tprev_states{idx}[{trans_idx}][i] <== from_zero_enabled[i+1] * states[i+1][{cur}];"
             );
         } else {
-            prev_states += &format!(
-                "\t\tprev_states{idx}[{trans_idx}][i] <== (1 - from_zero_enabled[i+1]) * states[i+1][{cur}];\n"
+            let _ = writeln!(
+                prev_states,
+                "\t\tprev_states{idx}[{trans_idx}][i] <== (1 - from_zero_enabled[i+1]) * states[i+1][{cur}];"
             );
         }
     }
@@ -828,7 +839,6 @@ fn write_is_substr(idx: usize, ranges: &[&(usize, usize)]) -> String {
         .map(|(trans_idx, (_, next))| {
             format!("prev_states{idx}[{trans_idx}][i] * states[i+2][{next}]")
         })
-        .collect::<Vec<_>>()
         .join(", ");
 
     format!(
@@ -850,10 +860,14 @@ fn write_is_substr(idx: usize, ranges: &[&(usize, usize)]) -> String {
 /// A String containing the generated Circom code for reveal logic.
 fn write_is_reveal_and_reveal(idx: usize) -> String {
     let mut reveal = String::new();
-    reveal += &format!(
-        "\t\tis_reveal{idx}[i] <== MultiAND(3)([out, is_substr{idx}[i], is_consecutive[i][2]]);\n"
+    let _ = writeln!(
+        reveal,
+        "\t\tis_reveal{idx}[i] <== MultiAND(3)([out, is_substr{idx}[i], is_consecutive[i][2]]);"
+    );
+    let _ = writeln!(
+        reveal,
+        "\t\treveal{idx}[i] <== in[i+1] * is_reveal{idx}[i];"
     );
-    reveal += &format!("\t\treveal{idx}[i] <== in[i+1] * is_reveal{idx}[i];\n");
     reveal
 }
 
@@ -864,22 +878,27 @@ fn write_is_reveal_and_reveal(idx: usize) -> String {
 /// # Arguments
 ///
 /// * `idx` - The index of the current substring.
-/// * `ranges` - A slice of tuples representing state transitions.
+/// * `ranges` - The set of tuples representing state transitions.
 ///
 /// # Returns
 ///
 /// A String containing the generated Circom code for the complete substring logic.
-fn write_substr_logic(idx: usize, ranges: &[(usize, usize)]) -> String {
+fn write_substr_logic(idx: usize, ranges: &BTreeSet<(usize, usize)>) -> String {
     let mut logic = String::new();
-    logic += &format!("\tsignal prev_states{idx}[{}][msg_bytes];\n", ranges.len());
-    logic += &format!("\tsignal is_substr{idx}[msg_bytes];\n");
-    logic += &format!("\tsignal is_reveal{idx}[msg_bytes];\n");
-    logic += &format!("\tsignal output reveal{idx}[msg_bytes];\n");
+    let _ = writeln!(
+        logic,
+        "\tsignal prev_states{idx}[{}][msg_bytes];",
+        ranges.len()
+    );
+    let _ = writeln!(logic, "\tsignal is_substr{idx}[msg_bytes];");
+    let _ = writeln!(logic, "\tsignal is_reveal{idx}[msg_bytes];");
+    let _ = writeln!(logic, "\tsignal output reveal{idx}[msg_bytes];");
     logic += "\tfor (var i = 0; i < msg_bytes; i++) {\n";
 
     let sorted_ranges = sort_ranges(ranges);
-    logic += &format!(
-        "\t\t // the {idx}-th substring transitions: {:?}\n",
+    let _ = writeln!(
+        logic,
+        "\t\t // the {idx}-th substring transitions: {:?}",
         sorted_ranges
     );
 
@@ -895,13 +914,15 @@ fn write_substr_logic(idx: usize, ranges: &[(usize, usize)]) -> String {
 ///
 /// # Arguments
 ///
-/// * `ranges` - A slice of tuples representing state transitions.
+/// * `ranges` - The state transitions.
 ///
 /// # Returns
 ///
 /// A Vec of references to the sorted ranges.
-fn sort_ranges(ranges: &[(usize, usize)]) -> Vec<&(usize, usize)> {
-    let mut sorted = ranges.iter().collect::<Vec<_>>();
+fn sort_ranges<'a>(
+    ranges: impl IntoIterator<Item = &'a (usize, usize)>,
+) -> Vec<&'a (usize, usize)> {
+    let mut sorted = ranges.into_iter().collect::<Vec<_>>();
     sorted.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
     sorted
 }
@@ -924,13 +945,14 @@ fn add_substrs_constraints(regex_dfa: &RegexAndDFA) -> Result<String, CompilerEr
 
     circom += &write_consecutive_logic(accepted_state);
 
-    circom += &format!(
-        "\t// substrings calculated: {:?}\n",
+    let _ = writeln!(
+        circom,
+        "\t// substrings calculated: {:?}",
         regex_dfa.substrings.substring_ranges
     );
 
     for (idx, ranges) in regex_dfa.substrings.substring_ranges.iter().enumerate() {
-        circom += &write_substr_logic(idx, &ranges.iter().copied().collect::<Vec<_>>());
+        circom += &write_substr_logic(idx, ranges);
     }
 
     circom += "}";
@@ -951,29 +973,45 @@ fn add_substrs_constraints(regex_dfa: &RegexAndDFA) -> Result<String, CompilerEr
 /// # Returns
 ///
 /// A Result indicating success or a CompilerError.
+#[cfg(feature = "fs")]
 pub(crate) fn gen_circom_template(
     regex_and_dfa: &RegexAndDFA,
     circom_path: &Path,
     template_name: &str,
     gen_substrs: bool,
 ) -> Result<(), CompilerError> {
-    let circom = gen_circom_allstr(
+    let circom = gen_circom_source(regex_and_dfa, template_name, gen_substrs)?;
+
+    write_file(circom_path, circom.as_bytes())?;
+    Ok(())
+}
+
+/// Generates the Circom code of the regex matcher, with the substring constraints if requested.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `template_name` - The name of the Circom template.
+/// * `gen_substrs` - A boolean indicating whether to generate substring constraints.
+///
+/// # Returns
+///
+/// A Result containing the generated Circom code as a String, or a CompilerError.
+pub(crate) fn gen_circom_source(
+    regex_and_dfa: &RegexAndDFA,
+    template_name: &str,
+    gen_substrs: bool,
+) -> Result<String, CompilerError> {
+    let mut circom = gen_circom_allstr(
         &regex_and_dfa.dfa,
         template_name,
         &regex_and_dfa.regex_pattern,
         regex_and_dfa.has_end_anchor,
     );
-
-    let mut file = File::create(circom_path)?;
-    file.write_all(circom.as_bytes())?;
-
     if gen_substrs {
-        let substrs = add_substrs_constraints(regex_and_dfa)?;
-        file.write_all(substrs.as_bytes())?;
+        circom += &add_substrs_constraints(regex_and_dfa)?;
     }
-
-    file.flush()?;
-    Ok(())
+    Ok(circom)
 }
 
 /// Generates a Circom circuit as a string for the given regex and DFA.
@@ -992,13 +1030,5 @@ pub(crate) fn gen_circom_string(
     regex_and_dfa: &RegexAndDFA,
     template_name: &str,
 ) -> Result<String, CompilerError> {
-    let circom = gen_circom_allstr(
-        &regex_and_dfa.dfa,
-        template_name,
-        &regex_and_dfa.regex_pattern,
-        regex_and_dfa.has_end_anchor,
-    );
-    let substrs = add_substrs_constraints(regex_and_dfa)?;
-    let result = circom + &substrs;
-    Ok(result)
+    gen_circom_source(regex_and_dfa, template_name, true)
 }
@@ -0,0 +1,419 @@
+use crate::{
+    errors::CompilerError,
+    structs::{DecomposedRegexConfig, Dialect},
+};
+use regex_syntax::ast;
+use tracing::warn;
+
+/// The group openings, following a `(`, of the PCRE constructs no automaton can match.
+const UNSUPPORTED_GROUPS: [(&str, &str); 11] = [
+    ("?=", "the lookahead"),
+    ("?!", "the negative lookahead"),
+    ("?<=", "the lookbehind"),
+    ("?<!", "the negative lookbehind"),
+    ("?>", "the atomic group"),
+    ("?|", "the branch reset group"),
+    ("?(", "the conditional group"),
+    ("?R", "the recursion"),
+    ("?&", "the subroutine call"),
+    ("?P>", "the subroutine call"),
+    ("?P=", "the backreference"),
+];
+
+/// Translates a pattern written in a regex dialect to the syntax of the compiler.
+///
+/// In RE2 and PCRE, `\d`, `\s` and `\w` match ASCII characters only and become explicit
+/// classes, `\Q...\E` quotes a literal, and a `[`, `&&`, `--` or `~~` inside a class is literal
+/// instead of a nested class or a set operation. PCRE octal and control escapes become
+/// hexadecimal ones. In ERE, only special characters can be escaped, a backslash inside a bracket
+/// expression is literal and `.` also matches a line feed. The constructs a dialect lacks, and
+/// those no automaton can match such as lookarounds and backreferences, are rejected.
+///
+/// Unknown escapes such as `\q` are errors, unless `permissive_escapes` is set and they match
+/// the character they escape, see `literal_unknown_escapes`.
+///
+/// # Arguments
+///
+/// * `regex` - A string slice containing the regex pattern, written in the dialect.
+/// * `dialect` - The dialect of the pattern.
+/// * `permissive_escapes` - Whether unknown escapes match the escaped character literally.
+///
+/// # Returns
+///
+/// A `Result` containing the pattern in the syntax of the compiler, or a `CompilerError` naming
+/// the first unsupported construct and its byte offset.
+pub(crate) fn translate_dialect(
+    regex: &str,
+    dialect: Dialect,
+    permissive_escapes: bool,
+) -> Result<String, CompilerError> {
+    let translated = match dialect {
+        Dialect::Rust => regex.to_string(),
+        _ => translate_syntax(regex, dialect, permissive_escapes)?,
+    };
+    Ok(match permissive_escapes {
+        true => literal_unknown_escapes(&translated),
+        false => translated,
+    })
+}
+
+/// Translates a pattern from a dialect other than `Rust`, see `translate_dialect`.
+fn translate_syntax(
+    regex: &str,
+    dialect: Dialect,
+    permissive_escapes: bool,
+) -> Result<String, CompilerError> {
+    let name = match dialect {
+        Dialect::Rust => "rust",
+        Dialect::Re2 => "re2",
+        Dialect::Pcre => "pcre",
+        Dialect::Ere => "ere",
+    };
+    let reject = |idx: usize, construct: &str| {
+        CompilerError::GenericError(format!(
+            "{} at byte {} is not supported in the {} dialect, in \"{}\"",
+            construct, idx, name, regex
+        ))
+    };
+
+    let mut translated = String::with_capacity(regex.len());
+    let mut in_class = false;
+    let mut pos = 0;
+    while let Some(c) = regex[pos..].chars().next() {
+        let idx = pos;
+        pos += c.len_utf8();
+        let rest = &regex[pos..];
+        let next = rest.chars().next();
+        match c {
+            // A backslash is literal in an ERE bracket expression.
+            '\\' if dialect == Dialect::Ere && in_class => translated.push_str(r"\\"),
+            '\\' if dialect == Dialect::Ere => match next {
+                Some(escaped) if escaped.is_ascii_alphanumeric() && !permissive_escapes => {
+                    return Err(reject(idx, &format!("the escape \\{}", escaped)))
+                }
+                Some(escaped) => {
+                    pos += escaped.len_utf8();
+                    if escaped.is_ascii_alphanumeric() {
+                        warn!(
+                            regex = %regex,
+                            "treating the unknown escape \\{} as a literal {}", escaped, escaped
+                        );
+                    }
+                    translated.push_str(&regex::escape(&escaped.to_string()));
+                }
+                None => translated.push(c),
+            },
+            '\\' => match next {
+                Some(escaped) => {
+                    pos += escaped.len_utf8();
+                    let after = &regex[pos..];
+                    match escaped {
+                        'd' | 'D' | 's' | 'S' | 'w' | 'W' => {
+                            translated.push_str(ascii_class(dialect, escaped))
+                        }
+                        'Q' => {
+                            let end = after.find(r"\E");
+                            translated
+                                .push_str(&regex::escape(&after[..end.unwrap_or(after.len())]));
+                            pos += end.map_or(after.len(), |end| end + 2);
+                        }
+                        'C' | 'Z' => return Err(reject(idx, &format!("the escape \\{}", escaped))),
+                        'h' | 'H' | 'v' | 'V' | 'R' | 'K' | 'G' | 'X' | 'N'
+                            if dialect == Dialect::Pcre =>
+                        {
+                            return Err(reject(idx, &format!("the escape \\{}", escaped)))
+                        }
+                        'g' | 'k' if dialect == Dialect::Pcre => {
+                            return Err(reject(idx, &format!("the backreference \\{}", escaped)))
+                        }
+                        // PCRE reads a number below 10 as a backreference and, without as
+                        // many groups before it, a longer one as an octal escape.
+                        '1'..='9'
+                            if dialect == Dialect::Pcre
+                                && !in_class
+                                && !after.starts_with(|c: char| c.is_ascii_digit()) =>
+                        {
+                            return Err(reject(idx, &format!("the backreference \\{}", escaped)))
+                        }
+                        '0'..='7' => {
+                            // PCRE and RE2 octal escapes have up to three digits.
+                            let digits =
+                                1 + after.chars().take(2).take_while(|c| c.is_digit(8)).count();
+                            let code = u32::from_str_radix(&regex[pos - 1..pos - 1 + digits], 8)
+                                .expect("octal digits");
+                            pos += digits - 1;
+                            translated.push_str(&format!("\\x{{{:X}}}", code));
+                        }
+                        'e' if dialect == Dialect::Pcre => translated.push_str(r"\x1B"),
+                        'b' if dialect == Dialect::Pcre && in_class => translated.push_str(r"\x08"),
+                        'c' if dialect == Dialect::Pcre => match after.chars().next() {
+                            Some(control) if control.is_ascii() => {
+                                pos += 1;
+                                let code = control.to_ascii_uppercase() as u8 ^ 0x40;
+                                translated.push_str(&format!("\\x{:02X}", code));
+                            }
+                            _ => return Err(reject(idx, "the incomplete escape \\c")),
+                        },
+                        _ => {
+                            translated.push(c);
+                            translated.push(escaped);
+                        }
+                    }
+                }
+                None => translated.push(c),
+            },
+            '[' if !in_class => {
+                in_class = true;
+                translated.push(c);
+                // A leading ^ negates the class and a leading ] is a literal.
+                if next == Some('^') {
+                    translated.push('^');
+                    pos += 1;
+                }
+                if regex[pos..].starts_with(']') {
+                    translated.push_str(r"\]");
+                    pos += 1;
+                }
+            }
+            '[' => match next {
+                Some(':') => {
+                    let end = rest.find(":]").map_or(rest.len(), |end| end + 2);
+                    translated.push(c);
+                    translated.push_str(&rest[..end]);
+                    pos += end;
+                }
+                Some('=' | '.') if dialect == Dialect::Ere => {
+                    return Err(reject(idx, "the collating element or equivalence class"))
+                }
+                _ => translated.push_str(r"\["),
+            },
+            ']' if in_class => {
+                in_class = false;
+                translated.push(c);
+            }
+            '&' | '~' if in_class => {
+                translated.push('\\');
+                translated.push(c);
+            }
+            '-' if in_class && next == Some('-') => {
+                translated.push_str(r"-\-");
+                pos += 1;
+            }
+            '(' if !in_class => {
+                if dialect == Dialect::Ere && next == Some('?') {
+                    return Err(reject(idx, "the group syntax (?"));
+                }
+                let numbered = rest.strip_prefix('?').is_some_and(|rest| {
+                    rest.trim_start_matches(['+', '-'])
+                        .starts_with(|c: char| c.is_ascii_digit())
+                });
+                if numbered {
+                    return Err(reject(idx, "the subroutine call"));
+                }
+                if let Some((_, construct)) = UNSUPPORTED_GROUPS
+                    .iter()
+                    .find(|(opening, _)| rest.starts_with(opening))
+                {
+                    return Err(reject(idx, construct));
+                }
+                if dialect == Dialect::Pcre && rest.starts_with("?#") {
+                    pos += rest.find(')').map_or(rest.len(), |end| end + 1);
+                    continue;
+                }
+                match rest
+                    .strip_prefix("?'")
+                    .and_then(|named| named.split_once('\''))
+                {
+                    Some((group_name, _)) if dialect == Dialect::Pcre => {
+                        translated.push_str(&format!("(?P<{}>", group_name));
+                        pos += group_name.len() + 3;
+                    }
+                    _ => translated.push(c),
+                }
+            }
+            '.' if dialect == Dialect::Ere && !in_class => translated.push_str("(?s:.)"),
+            '*' | '+' | '?' | '}' if !in_class => {
+                translated.push(c);
+                match next {
+                    Some('*' | '+' | '?') if dialect == Dialect::Ere => {
+                        return Err(reject(pos, "the stacked quantifier"))
+                    }
+                    Some('+') if dialect == Dialect::Pcre => {
+                        return Err(reject(pos, "the possessive quantifier"))
+                    }
+                    _ => {}
+                }
+            }
+            _ => translated.push(c),
+        }
+    }
+    Ok(translated)
+}
+
+/// Translates every part of a decomposed regex from a dialect, see `translate_dialect`.
+///
+/// # Arguments
+///
+/// * `config` - The decomposed regex whose parts are translated in place.
+/// * `dialect` - The dialect of the parts.
+/// * `permissive_escapes` - Whether unknown escapes match the escaped character literally.
+///
+/// # Returns
+///
+/// A `Result` containing nothing, or a `CompilerError` naming the first unsupported construct.
+pub(crate) fn translate_config_dialect(
+    config: &mut DecomposedRegexConfig,
+    dialect: Dialect,
+    permissive_escapes: bool,
+) -> Result<(), CompilerError> {
+    for part in config.parts.iter_mut() {
+        part.regex_def = translate_dialect(&part.regex_def, dialect, permissive_escapes)?;
+    }
+    Ok(())
+}
+
+/// Replaces the escapes the compiler does not recognize, such as `\q`, with the characters they
+/// escape, matched literally, e.g. `a\qb` becomes `aqb`. Each replacement is logged, since other
+/// tools may give the same escape another meaning.
+///
+/// Only the escapes before the first other syntax error are replaced, and that error is reported
+/// as usual.
+///
+/// # Arguments
+///
+/// * `regex` - A string slice containing the regex pattern.
+///
+/// # Returns
+///
+/// The regex without unknown escapes.
+fn literal_unknown_escapes(regex: &str) -> String {
+    let mut literal = regex.to_string();
+    while let Err(err) = ast::parse::Parser::new().parse(&literal) {
+        if *err.kind() != ast::ErrorKind::EscapeUnrecognized {
+            break;
+        }
+        let span = err.span().start.offset..err.span().end.offset;
+        let escaped = literal[span.start + 1..span.end].to_string();
+        warn!(
+            regex = %regex,
+            "treating the unknown escape \\{} as a literal {}", escaped, escaped
+        );
+        literal.replace_range(span, &regex::escape(&escaped));
+    }
+    literal
+}
+
+/// Returns the ASCII class of a RE2 or PCRE `\d`, `\s` or `\w` escape and their negations. The
+/// `\s` of RE2 excludes the vertical tab.
+fn ascii_class(dialect: Dialect, escape: char) -> &'static str {
+    match (escape, dialect) {
+        ('d', _) => "[0-9]",
+        ('D', _) => "[^0-9]",
+        ('w', _) => "[0-9A-Za-z_]",
+        ('W', _) => "[^0-9A-Za-z_]",
+        ('s', Dialect::Re2) => r"[\t\n\f\r ]",
+        ('S', Dialect::Re2) => r"[^\t\n\f\r ]",
+        ('s', _) => r"[\t\n\v\f\r ]",
+        _ => r"[^\t\n\v\f\r ]",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn translate(regex: &str, dialect: Dialect) -> String {
+        translate_dialect(regex, dialect, false).unwrap()
+    }
+
+    fn rejection(regex: &str, dialect: Dialect) -> String {
+        translate_dialect(regex, dialect, false)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_translate_re2_and_pcre() {
+        assert_eq!(translate(r"[a&&b]\d", Dialect::Rust), r"[a&&b]\d");
+        for dialect in [Dialect::Re2, Dialect::Pcre] {
+            assert_eq!(translate(r"\d+\.\w", dialect), r"[0-9]+\.[0-9A-Za-z_]");
+            assert_eq!(translate(r"[\d_-]", dialect), r"[[0-9]_-]");
+            assert_eq!(
+                translate(r"[[a&&b~~c[:alpha:]+--]", dialect),
+                r"[\[a\&\&b\~\~c[:alpha:]+-\-]"
+            );
+            assert_eq!(translate(r"[]a]", dialect), r"[\]a]");
+            assert_eq!(translate(r"\Qa.b*\Ec\Q(", dialect), r"a\.b\*c\(");
+            assert_eq!(translate(r"\101", dialect), r"\x{41}");
+            assert!(rejection(r"a\C", dialect).starts_with(&format!(
+                "the escape \\C at byte 1 is not supported in the {} dialect",
+                format!("{:?}", dialect).to_lowercase()
+            )));
+            assert!(rejection("(?<=a)b", dialect).starts_with("the lookbehind at byte 0"));
+        }
+        assert_eq!(translate(r"\s", Dialect::Re2), r"[\t\n\f\r ]");
+        assert_eq!(translate(r"\S", Dialect::Pcre), r"[^\t\n\v\f\r ]");
+
+        assert_eq!(
+            translate(
+                r"(?'year'[0-9]{4})(?#the year)-\e\cm\012[\b]",
+                Dialect::Pcre
+            ),
+            r"(?P<year>[0-9]{4})-\x1B\x0D\x{A}[\x08]"
+        );
+        assert!(rejection(r"(a)\1", Dialect::Pcre).starts_with(r"the backreference \1 at byte 3"));
+        assert!(rejection("a*+", Dialect::Pcre).starts_with("the possessive quantifier at byte 2"));
+        assert!(rejection("(?1)", Dialect::Pcre).starts_with("the subroutine call at byte 0"));
+        assert!(rejection(r"\R", Dialect::Pcre).starts_with(r"the escape \R at byte 0"));
+    }
+
+    #[test]
+    fn test_translate_ere() {
+        assert_eq!(translate(r"[\.]\.\/", Dialect::Ere), r"[\\.]\./");
+        assert_eq!(translate("a.[.]", Dialect::Ere), "a(?s:.)[.]");
+        assert_eq!(
+            translate("[[:digit:]]{2,}", Dialect::Ere),
+            "[[:digit:]]{2,}"
+        );
+        assert!(rejection(r"a\d", Dialect::Ere).starts_with(r"the escape \d at byte 1"));
+        assert!(rejection("a+?", Dialect::Ere).starts_with("the stacked quantifier at byte 2"));
+        assert!(rejection("(?:a)", Dialect::Ere).starts_with("the group syntax (? at byte 0"));
+        assert!(rejection("[[=a=]]", Dialect::Ere)
+            .starts_with("the collating element or equivalence class at byte 1"));
+    }
+
+    #[test]
+    fn test_permissive_escapes() {
+        use crate::regex::syntax_errors;
+
+        // Strict by default: the escape is left for the parser to reject.
+        let strict = translate(r"a\qb[\q%]", Dialect::Rust);
+        assert_eq!(strict, r"a\qb[\q%]");
+        assert_eq!(syntax_errors(&strict).len(), 2);
+
+        let permissive =
+            |regex: &str, dialect: Dialect| translate_dialect(regex, dialect, true).unwrap();
+        assert_eq!(permissive(r"a\qb[\q%]\d", Dialect::Rust), r"aqb[q%]\d");
+        assert_eq!(permissive(r"\q\d", Dialect::Pcre), "q[0-9]");
+        assert_eq!(permissive(r"\q\d\.", Dialect::Ere), r"qd\.");
+        assert!(syntax_errors(&permissive(r"\y[\j-\m]", Dialect::Re2)).is_empty());
+    }
+
+    #[test]
+    fn test_translated_semantics() {
+        let matches = |regex: &str, dialect: Dialect, input: &str| {
+            let translated = translate_dialect(regex, dialect, false).unwrap();
+            regex::Regex::new(&format!("^(?:{})$", translated))
+                .unwrap()
+                .is_match(input)
+        };
+        // The Perl classes of RE2 and PCRE are ASCII only, unlike those of the compiler.
+        assert!(matches(r"\d", Dialect::Rust, "٣"));
+        assert!(!matches(r"\d", Dialect::Re2, "٣"));
+        assert!(!matches(r"\w", Dialect::Pcre, "é"));
+        assert!(matches(r"\Q1+1\E", Dialect::Pcre, "1+1"));
+        assert!(matches(r"[\]", Dialect::Ere, "\\"));
+        assert!(matches("a.b", Dialect::Ere, "a\nb"));
+        assert!(!matches("a.b", Dialect::Pcre, "a\nb"));
+    }
+}
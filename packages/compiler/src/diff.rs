@@ -0,0 +1,349 @@
+use crate::structs::{DFAGraph, RegexAndDFA};
+use itertools::Itertools;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt,
+};
+
+/// A state of the old automaton paired with the state of the new one reached by the same input.
+pub type StatePair = (usize, usize);
+
+/// The differences between the automata of two versions of a pattern.
+///
+/// The states of the two automata are paired by running them side by side from their initial
+/// states, so a state of one automaton is only compared with the states of the other that the
+/// same inputs lead to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AutomatonDiff {
+    /// The number of states of the old and of the new automaton.
+    pub states: (usize, usize),
+    /// The states of the old automaton that no input reaching the new one leads to.
+    pub removed_states: Vec<usize>,
+    /// The states of the new automaton that no input reaching the old one leads to.
+    pub added_states: Vec<usize>,
+    /// The transitions that only one of the automata takes from a pair of states.
+    pub transitions: Vec<TransitionChange>,
+    /// The pairs of states accepting in only one of the automata, with whether the new one does.
+    pub accept_changes: Vec<(StatePair, bool)>,
+    /// The transitions captured by a substring in only one of the automata.
+    pub captures: Vec<CaptureChange>,
+}
+
+/// A transition taken by only one of the automata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransitionChange {
+    /// The pair of states the transition leaves.
+    pub from: StatePair,
+    /// The bytes of the transition.
+    pub bytes: BTreeSet<u8>,
+    /// The state of the automaton taking the transition that it leads to.
+    pub to: usize,
+    /// Whether the new automaton takes the transition, rather than the old one.
+    pub added: bool,
+    /// The substrings capturing the transition in the automaton taking it.
+    pub substrings: Vec<usize>,
+}
+
+/// A transition of both automata captured by a substring in only one of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureChange {
+    /// The index of the substring.
+    pub substring: usize,
+    /// The pair of states the transition leaves.
+    pub from: StatePair,
+    /// The bytes of the transition.
+    pub bytes: BTreeSet<u8>,
+    /// Whether the new automaton captures the transition, rather than the old one.
+    pub added: bool,
+}
+
+impl AutomatonDiff {
+    /// Whether the two automata have the same states, transitions and captures.
+    pub fn is_empty(&self) -> bool {
+        self.removed_states.is_empty()
+            && self.added_states.is_empty()
+            && self.transitions.is_empty()
+            && self.accept_changes.is_empty()
+            && self.captures.is_empty()
+    }
+}
+
+impl fmt::Display for AutomatonDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "states: {} -> {}", self.states.0, self.states.1)?;
+        for state in &self.removed_states {
+            writeln!(f, "- state {}", state)?;
+        }
+        for state in &self.added_states {
+            writeln!(f, "+ state {}", state)?;
+        }
+        for change in &self.transitions {
+            write!(
+                f,
+                "{} {}/{} {} -> {}",
+                sign(change.added),
+                change.from.0,
+                change.from.1,
+                fmt_bytes(&change.bytes),
+                change.to
+            )?;
+            match change.substrings.is_empty() {
+                true => writeln!(f)?,
+                false => writeln!(f, " (substring {})", change.substrings.iter().join(", "))?,
+            }
+        }
+        for ((old, new), accepting) in &self.accept_changes {
+            writeln!(f, "{} {}/{} accepting", sign(*accepting), old, new)?;
+        }
+        for change in &self.captures {
+            writeln!(
+                f,
+                "{} substring {}: {}/{} {}",
+                sign(change.added),
+                change.substring,
+                change.from.0,
+                change.from.1,
+                fmt_bytes(&change.bytes)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The prefix of a line of the diff, `+` for what the new automaton adds and `-` otherwise.
+fn sign(added: bool) -> char {
+    match added {
+        true => '+',
+        false => '-',
+    }
+}
+
+/// Formats a set of bytes as a character class, printing printable ASCII as is and other
+/// bytes as `\xNN`.
+pub(crate) fn fmt_bytes(bytes: &BTreeSet<u8>) -> String {
+    let fmt_byte = |byte: u8| match byte {
+        b'!'..=b'~' if !b"-[]\\".contains(&byte) => (byte as char).to_string(),
+        _ => format!("\\x{:02x}", byte),
+    };
+    let mut ranges: Vec<(u8, u8)> = vec![];
+    for &byte in bytes {
+        match ranges.last_mut() {
+            Some((_, max)) if *max as u16 + 1 == byte as u16 => *max = byte,
+            _ => ranges.push((byte, byte)),
+        }
+    }
+    let class = ranges
+        .iter()
+        .map(|&(min, max)| match min == max {
+            true => fmt_byte(min),
+            false => format!("{}-{}", fmt_byte(min), fmt_byte(max)),
+        })
+        .collect::<String>();
+    format!("[{}]", class)
+}
+
+/// Maps every state of a DFA to the state each byte leads to.
+fn byte_targets(dfa: &DFAGraph) -> Vec<BTreeMap<u8, usize>> {
+    dfa.states
+        .iter()
+        .map(|state| {
+            state
+                .transitions
+                .iter()
+                .flat_map(|(&next, bytes)| bytes.iter().map(move |&byte| (byte, next)))
+                .collect()
+        })
+        .collect()
+}
+
+/// Compares the automata of two versions of a pattern.
+///
+/// # Arguments
+///
+/// * `old` - A reference to the RegexAndDFA of the old version.
+/// * `new` - A reference to the RegexAndDFA of the new version.
+///
+/// # Returns
+///
+/// The `AutomatonDiff` of the states, transitions, accept states and substrings of the two.
+pub(crate) fn diff_automata(old: &RegexAndDFA, new: &RegexAndDFA) -> AutomatonDiff {
+    let (old_targets, new_targets) = (byte_targets(&old.dfa), byte_targets(&new.dfa));
+    let captured = |regex_and_dfa: &RegexAndDFA, substring: usize, from: usize, to: usize| {
+        regex_and_dfa
+            .substrings
+            .substring_ranges
+            .get(substring)
+            .is_some_and(|ranges| ranges.contains(&(from, to)))
+    };
+    let substrings = old
+        .substrings
+        .substring_ranges
+        .len()
+        .max(new.substrings.substring_ranges.len());
+
+    let mut diff = AutomatonDiff {
+        states: (old.dfa.states.len(), new.dfa.states.len()),
+        ..Default::default()
+    };
+    // Keyed by the pair, the side and the target, to group the bytes of a transition.
+    let mut transitions: BTreeMap<(StatePair, bool, usize), BTreeSet<u8>> = BTreeMap::new();
+    let mut captures: BTreeMap<(usize, StatePair, bool), BTreeSet<u8>> = BTreeMap::new();
+    let mut pairs = BTreeSet::from([(0, 0)]);
+    let mut queue = VecDeque::from([(0, 0)]);
+    while let Some(pair @ (old_state, new_state)) = queue.pop_front() {
+        let accepting = |dfa: &DFAGraph, state: usize| dfa.states[state].state_type == "accept";
+        let new_accepting = accepting(&new.dfa, new_state);
+        if accepting(&old.dfa, old_state) != new_accepting {
+            diff.accept_changes.push((pair, new_accepting));
+        }
+        for byte in 0..=u8::MAX {
+            let old_next = old_targets[old_state].get(&byte).copied();
+            let new_next = new_targets[new_state].get(&byte).copied();
+            match (old_next, new_next) {
+                (Some(old_next), Some(new_next)) => {
+                    for substring in 0..substrings {
+                        let in_new = captured(new, substring, new_state, new_next);
+                        if captured(old, substring, old_state, old_next) != in_new {
+                            captures
+                                .entry((substring, pair, in_new))
+                                .or_default()
+                                .insert(byte);
+                        }
+                    }
+                    if pairs.insert((old_next, new_next)) {
+                        queue.push_back((old_next, new_next));
+                    }
+                }
+                (Some(old_next), None) => {
+                    transitions
+                        .entry((pair, false, old_next))
+                        .or_default()
+                        .insert(byte);
+                }
+                (None, Some(new_next)) => {
+                    transitions
+                        .entry((pair, true, new_next))
+                        .or_default()
+                        .insert(byte);
+                }
+                (None, None) => {}
+            }
+        }
+    }
+
+    let (old_paired, new_paired): (BTreeSet<usize>, BTreeSet<usize>) =
+        pairs.iter().copied().unzip();
+    diff.removed_states = (0..old.dfa.states.len())
+        .filter(|state| !old_paired.contains(state))
+        .collect();
+    diff.added_states = (0..new.dfa.states.len())
+        .filter(|state| !new_paired.contains(state))
+        .collect();
+    diff.transitions = transitions
+        .into_iter()
+        .map(|((from, added, to), bytes)| {
+            let (regex_and_dfa, state) = match added {
+                true => (new, from.1),
+                false => (old, from.0),
+            };
+            TransitionChange {
+                from,
+                bytes,
+                to,
+                added,
+                substrings: (0..substrings)
+                    .filter(|&substring| captured(regex_and_dfa, substring, state, to))
+                    .collect(),
+            }
+        })
+        .collect();
+    diff.captures = captures
+        .into_iter()
+        .map(|((substring, from, added), bytes)| CaptureChange {
+            substring,
+            from,
+            bytes,
+            added,
+        })
+        .collect();
+    diff
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::regex::create_regex_and_dfa_from_str_and_defs;
+    use crate::structs::SubstringDefinitionsJson;
+
+    fn raw(regex: &str) -> RegexAndDFA {
+        create_regex_and_dfa_from_str_and_defs(
+            regex,
+            SubstringDefinitionsJson {
+                transitions: vec![vec![]],
+                names: vec![],
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_diff_automata() {
+        assert!(diff_automata(&raw("a[0-9]+"), &raw("a[0-9]+")).is_empty());
+
+        // Widening a class only adds the bytes of the existing transitions.
+        let diff = diff_automata(&raw("a[0-9]"), &raw("a[0-9a-f]"));
+        assert!(diff.removed_states.is_empty() && diff.added_states.is_empty());
+        assert_eq!(diff.transitions.len(), 1);
+        let change = &diff.transitions[0];
+        assert!(change.added);
+        assert_eq!(fmt_bytes(&change.bytes), "[a-f]");
+        assert!(diff.to_string().contains(&format!(
+            "+ {}/{} [a-f] -> {}",
+            change.from.0, change.from.1, change.to
+        )));
+
+        // An optional suffix keeps the old accept state and adds a new one.
+        let diff = diff_automata(&raw("ab"), &raw("abc?"));
+        assert_eq!(diff.added_states.len(), 1);
+        assert!(diff.removed_states.is_empty() && diff.accept_changes.is_empty());
+        assert!(diff
+            .transitions
+            .iter()
+            .any(|change| change.added && fmt_bytes(&change.bytes) == "[c]"));
+
+        // Dropping the last byte makes an inner state accepting.
+        let diff = diff_automata(&raw("abc"), &raw("ab"));
+        assert_eq!(diff.removed_states.len(), 1);
+        assert!(diff.accept_changes.iter().any(|(_, accepting)| *accepting));
+    }
+
+    #[test]
+    fn test_diff_captures() {
+        let mut old = raw("x=[0-9]+;");
+        let mut new = old.clone();
+        let digits = |regex_and_dfa: &RegexAndDFA| {
+            let targets = byte_targets(&regex_and_dfa.dfa);
+            (0..targets.len())
+                .flat_map(|from| {
+                    targets[from]
+                        .iter()
+                        .filter(|(byte, _)| byte.is_ascii_digit())
+                        .map(move |(_, &to)| (from, to))
+                })
+                .collect::<BTreeSet<_>>()
+        };
+        old.substrings.substring_ranges = vec![BTreeSet::new()];
+        new.substrings.substring_ranges = vec![digits(&new)];
+        let diff = diff_automata(&old, &new);
+        assert!(diff.transitions.is_empty());
+        assert!(!diff.captures.is_empty());
+        assert!(diff
+            .captures
+            .iter()
+            .all(|change| change.added && fmt_bytes(&change.bytes) == "[0-9]"));
+        assert_eq!(
+            fmt_bytes(&BTreeSet::from([b'-', b'\n', 255])),
+            "[\\x0a\\x2d\\xff]"
+        );
+    }
+}
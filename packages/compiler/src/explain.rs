@@ -0,0 +1,386 @@
+use crate::{diff::fmt_bytes, structs::RegexAndDFA};
+use itertools::Itertools;
+use serde_json::json;
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fmt::Write,
+};
+
+/// The page rendered by `automaton_html`, into which the automaton is spliced as JSON.
+const HTML_PAGE: &str = include_str!("automaton.html");
+
+/// Finds a shortest input leading to every state of the DFA from the initial state, taking
+/// the smallest byte first so that the inputs are stable.
+fn shortest_inputs(regex_and_dfa: &RegexAndDFA) -> Vec<Option<Vec<u8>>> {
+    let states = &regex_and_dfa.dfa.states;
+    let mut inputs = vec![None; states.len()];
+    if states.is_empty() {
+        return inputs;
+    }
+    inputs[0] = Some(vec![]);
+    let mut queue = VecDeque::from([0]);
+    while let Some(state) = queue.pop_front() {
+        let input = inputs[state].clone().unwrap_or_default();
+        let mut edges = states[state]
+            .transitions
+            .iter()
+            .filter_map(|(&next, bytes)| bytes.first().map(|&byte| (byte, next)))
+            .collect_vec();
+        edges.sort_unstable();
+        for (byte, next) in edges {
+            if inputs[next].is_none() {
+                inputs[next] = Some([input.as_slice(), &[byte]].concat());
+                queue.push_back(next);
+            }
+        }
+    }
+    inputs
+}
+
+/// Formats an input reaching a state, writing the byte 255 priming `^` as `^`.
+fn fmt_input(input: &[u8]) -> String {
+    match input.split_first() {
+        Some((&u8::MAX, rest)) => format!("\"^{}\"", rest.escape_ascii()),
+        _ => format!("\"{}\"", input.escape_ascii()),
+    }
+}
+
+/// Names a substring after its capture group or the name given to it, if it has one.
+fn substring_name(regex_and_dfa: &RegexAndDFA, substring: usize) -> String {
+    let substrings = &regex_and_dfa.substrings;
+    let group = substrings
+        .substring_groups
+        .as_ref()
+        .and_then(|groups| groups.get(substring));
+    let name = substrings
+        .substring_names
+        .as_ref()
+        .and_then(|names| names.get(substring).cloned().flatten());
+    match (group, name) {
+        (Some(group), _) => match &group.name {
+            Some(name) => format!("substring {} (group {}, {})", substring, group.index, name),
+            None => format!("substring {} (group {})", substring, group.index),
+        },
+        (None, Some(name)) => format!("substring {} ({})", substring, name),
+        (None, None) => format!("substring {}", substring),
+    }
+}
+
+/// Describes the automaton of a pattern in plain text, for reviewers of the circuits
+/// generated from it.
+///
+/// Every state is described by a shortest input reaching it, whether it accepts and which
+/// substrings it lies within, followed by its transitions with the substrings capturing them
+/// and what the matchers do on any other byte. The substrings are then listed with the
+/// transitions feeding them.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA of the pattern.
+///
+/// # Returns
+///
+/// The description, one line per fact.
+pub(crate) fn explain_automaton(regex_and_dfa: &RegexAndDFA) -> String {
+    let states = &regex_and_dfa.dfa.states;
+    let ranges = &regex_and_dfa.substrings.substring_ranges;
+    let substring_name = |substring: usize| substring_name(regex_and_dfa, substring);
+    let capturing = |from: usize, to: usize| {
+        (0..ranges.len())
+            .filter(|&substring| ranges[substring].contains(&(from, to)))
+            .collect_vec()
+    };
+    let accepting = states
+        .iter()
+        .enumerate()
+        .filter(|(_, state)| state.state_type == "accept")
+        .map(|(idx, _)| idx)
+        .collect_vec();
+    let inputs = shortest_inputs(regex_and_dfa);
+
+    let mut text = String::new();
+    let _ = writeln!(text, "pattern: {}", regex_and_dfa.regex_pattern);
+    let _ = writeln!(
+        text,
+        "{} states, accepting: {}",
+        states.len(),
+        accepting.iter().join(", ")
+    );
+    if states.first().is_some_and(|state| {
+        state
+            .transitions
+            .values()
+            .any(|bytes| bytes.contains(&u8::MAX))
+    }) {
+        let _ = writeln!(
+            text,
+            "the matchers feed the byte 0xff before the input, standing for the start `^` matches"
+        );
+    }
+    for (idx, state) in states.iter().enumerate() {
+        let _ = writeln!(text);
+        let mut facts = vec![];
+        if idx == 0 {
+            facts.push("initial".to_string());
+        }
+        if state.state_type == "accept" {
+            facts.push(match regex_and_dfa.has_end_anchor {
+                true => "accepting if the input ends here".to_string(),
+                false => "accepting, the rest of the input is ignored".to_string(),
+            });
+        }
+        let within: BTreeSet<usize> = (0..states.len())
+            .flat_map(|from| capturing(from, idx))
+            .collect();
+        if !within.is_empty() {
+            facts.push(format!(
+                "within {}",
+                within.iter().map(|&s| substring_name(s)).join(", ")
+            ));
+        }
+        match facts.is_empty() {
+            true => {
+                let _ = writeln!(text, "state {}", idx);
+            }
+            false => {
+                let _ = writeln!(text, "state {}: {}", idx, facts.join("; "));
+            }
+        }
+        match &inputs[idx] {
+            Some(input) => {
+                let _ = writeln!(text, "  reached by {}", fmt_input(input));
+            }
+            None => {
+                let _ = writeln!(text, "  unreachable");
+            }
+        }
+        for (&next, bytes) in &state.transitions {
+            let substrings = capturing(idx, next);
+            let _ = write!(text, "  {} -> {}", fmt_bytes(bytes), next);
+            match substrings.is_empty() {
+                true => {
+                    let _ = writeln!(text);
+                }
+                false => {
+                    let _ = writeln!(
+                        text,
+                        " (captured by {})",
+                        substrings.iter().map(|&s| substring_name(s)).join(", ")
+                    );
+                }
+            }
+        }
+        let reset = match idx {
+            _ if state.state_type == "accept" && !regex_and_dfa.has_end_anchor => "stays matched",
+            0 => "stays in state 0",
+            _ => "resets, the byte being matched again from state 0",
+        };
+        let other = match state.transitions.is_empty() {
+            true => "any byte",
+            false => "any other byte",
+        };
+        let _ = writeln!(text, "  {}: {}", other, reset);
+    }
+    for (substring, transitions) in ranges.iter().enumerate() {
+        let _ = writeln!(text);
+        let _ = writeln!(text, "{}:", substring_name(substring));
+        for &(from, to) in transitions {
+            let bytes = states
+                .get(from)
+                .and_then(|state| state.transitions.get(&to))
+                .cloned()
+                .unwrap_or_default();
+            let _ = writeln!(text, "  {} {} -> {}", from, fmt_bytes(&bytes), to);
+        }
+    }
+    text
+}
+
+/// Renders the automaton of a pattern as a Graphviz DOT graph.
+///
+/// Accepting states are drawn with a double circle, and the transitions captured by a
+/// substring are drawn bold and labelled with the substrings after their bytes.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA of the pattern.
+///
+/// # Returns
+///
+/// The DOT source of the graph.
+pub(crate) fn automaton_dot(regex_and_dfa: &RegexAndDFA) -> String {
+    let states = &regex_and_dfa.dfa.states;
+    let ranges = &regex_and_dfa.substrings.substring_ranges;
+    let escape = |label: &str| label.replace('\\', "\\\\").replace('"', "\\\"");
+    let mut text = String::new();
+    let _ = writeln!(text, "digraph automaton {{");
+    let _ = writeln!(text, "    rankdir=LR;");
+    let _ = writeln!(
+        text,
+        "    label=\"{}\";",
+        escape(&regex_and_dfa.regex_pattern)
+    );
+    let _ = writeln!(text, "    node [shape=circle];");
+    for (idx, state) in states.iter().enumerate() {
+        if state.state_type == "accept" {
+            let _ = writeln!(text, "    {} [shape=doublecircle];", idx);
+        }
+    }
+    for (idx, state) in states.iter().enumerate() {
+        for (&next, bytes) in &state.transitions {
+            let substrings = (0..ranges.len())
+                .filter(|&substring| ranges[substring].contains(&(idx, next)))
+                .collect_vec();
+            let label = escape(&fmt_bytes(bytes));
+            match substrings.is_empty() {
+                true => {
+                    let _ = writeln!(text, "    {} -> {} [label=\"{}\"];", idx, next, label);
+                }
+                false => {
+                    let _ = writeln!(
+                        text,
+                        "    {} -> {} [label=\"{} {{{}}}\", style=bold];",
+                        idx,
+                        next,
+                        label,
+                        substrings.iter().join(",")
+                    );
+                }
+            }
+        }
+    }
+    let _ = writeln!(text, "}}");
+    text
+}
+
+/// Renders the automaton of a pattern as a self-contained HTML page, for reviewers who do not
+/// run the compiler.
+///
+/// The page draws the automaton as an interactive graph, lists its transitions with the
+/// substrings capturing them, and runs a test string through it as the matchers do, showing
+/// the state reached after every byte and the bytes captured by every substring.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA of the pattern.
+///
+/// # Returns
+///
+/// The HTML source of the page.
+pub(crate) fn automaton_html(regex_and_dfa: &RegexAndDFA) -> String {
+    let ranges = &regex_and_dfa.substrings.substring_ranges;
+    let inputs = shortest_inputs(regex_and_dfa);
+    let states = regex_and_dfa
+        .dfa
+        .states
+        .iter()
+        .zip(&inputs)
+        .enumerate()
+        .map(|(idx, (state, input))| {
+            let transitions = state
+                .transitions
+                .iter()
+                .map(|(&next, bytes)| {
+                    json!({
+                        "to": next,
+                        "bytes": bytes,
+                        "label": fmt_bytes(bytes),
+                        "substrings": (0..ranges.len())
+                            .filter(|&substring| ranges[substring].contains(&(idx, next)))
+                            .collect_vec(),
+                    })
+                })
+                .collect_vec();
+            json!({
+                "accepting": state.state_type == "accept",
+                "reached_by": input,
+                "transitions": transitions,
+            })
+        })
+        .collect_vec();
+    let automaton = json!({
+        "pattern": regex_and_dfa.regex_pattern,
+        "end_anchored": regex_and_dfa.has_end_anchor,
+        "substrings": (0..ranges.len())
+            .map(|substring| substring_name(regex_and_dfa, substring))
+            .collect_vec(),
+        "states": states,
+    });
+    // `<` only occurs within the JSON strings, where escaping it keeps a `</script>` of the
+    // pattern from closing the script.
+    let automaton = automaton.to_string().replace('<', "\\u003c");
+    HTML_PAGE.replace("/* automaton */ null", &automaton)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::regex::{create_regex_and_dfa_from_groups, get_regex_and_dfa};
+    use crate::structs::{DecomposedRegexConfig, RegexPartConfig};
+
+    #[test]
+    fn test_explain_automaton() {
+        let regex_and_dfa = create_regex_and_dfa_from_groups("id=([0-9]+);").unwrap();
+        let text = explain_automaton(&regex_and_dfa);
+        assert!(text.contains("  reached by \"id=\"\n"));
+        assert!(text.contains("within substring 0 (group 1)"));
+        assert!(text.contains("(captured by substring 0 (group 1))"));
+        assert!(
+            text.contains("  any other byte: resets, the byte being matched again from state 0")
+        );
+        assert!(text.contains("accepting, the rest of the input is ignored"));
+        assert!(text.contains("\nsubstring 0 (group 1):\n"));
+
+        // The byte priming `^` is written as the anchor.
+        let mut config = DecomposedRegexConfig {
+            parts: VecDeque::from([RegexPartConfig {
+                is_public: false,
+                regex_def: "(\r\n|^)id:".to_string(),
+                max_repetitions: None,
+                max_length: None,
+                numeric: false,
+                hole: false,
+                min: None,
+                max: None,
+            }]),
+        };
+        let text = explain_automaton(&get_regex_and_dfa(&mut config).unwrap());
+        assert!(text.contains("the matchers feed the byte 0xff"));
+        assert!(text.contains("  reached by \"^id\"\n"));
+    }
+
+    #[test]
+    fn test_automaton_dot() {
+        let regex_and_dfa = create_regex_and_dfa_from_groups("id=\"([0-9]+)").unwrap();
+        let dot = automaton_dot(&regex_and_dfa);
+        assert!(dot.starts_with("digraph automaton {\n    rankdir=LR;\n"));
+        assert!(dot.contains("label=\"id=\\\"([0-9]+)\";"));
+        assert!(dot.contains("[label=\"[0-9] {0}\", style=bold];"));
+        assert!(dot.contains("[shape=doublecircle];"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_automaton_html() {
+        let regex_and_dfa = create_regex_and_dfa_from_groups("<b>([0-9]+)</script>").unwrap();
+        let html = automaton_html(&regex_and_dfa);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(!html.contains("/* automaton */ null"));
+        // The pattern cannot close the script embedding it.
+        assert_eq!(html.matches("</script>").count(), 1);
+        let start = html.find("const automaton = ").unwrap() + "const automaton = ".len();
+        let end = start + html[start..].find(";\n").unwrap();
+        let automaton: serde_json::Value = serde_json::from_str(&html[start..end]).unwrap();
+        assert_eq!(automaton["pattern"], "<b>([0-9]+)</script>");
+        assert_eq!(automaton["substrings"][0], "substring 0 (group 1)");
+        let states = automaton["states"].as_array().unwrap();
+        assert_eq!(states.len(), regex_and_dfa.dfa.states.len());
+        assert_eq!(states[0]["reached_by"], json!([]));
+        assert!(states.iter().any(|state| state["transitions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|transition| transition["label"] == "[0-9]"
+                && transition["substrings"] == json!([0]))));
+    }
+}
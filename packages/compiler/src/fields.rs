@@ -0,0 +1,222 @@
+use crate::{
+    errors::CompilerError,
+    structs::{DecomposedRegexConfig, FieldsConfig, FieldsFormat, RegexPartConfig},
+};
+use itertools::Itertools;
+use std::collections::VecDeque;
+
+/// The whitespace allowed around the punctuation of a JSON object.
+const JSON_SPACE: &str = "[ \\t\\r\\n]*";
+
+/// Returns whether a name can be used as a Noir identifier.
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Checks that the names of a `FieldsConfig` can be used in the generated Noir struct.
+///
+/// # Arguments
+///
+/// * `config` - The fields to check.
+///
+/// # Returns
+///
+/// A `Result` indicating whether the fields are valid or a `CompilerError`.
+fn validate_fields(config: &FieldsConfig) -> Result<(), CompilerError> {
+    if config.fields.is_empty() {
+        return Err(CompilerError::GenericError(
+            "a fields config needs at least one field".to_string(),
+        ));
+    }
+    if !is_identifier(&config.struct_name) {
+        return Err(CompilerError::GenericError(format!(
+            "struct name \"{}\" is not a valid Noir identifier",
+            config.struct_name
+        )));
+    }
+    if let Some(field) = config
+        .fields
+        .iter()
+        .find(|field| !is_identifier(&field.name))
+    {
+        return Err(CompilerError::GenericError(format!(
+            "field name \"{}\" is not a valid Noir identifier",
+            field.name
+        )));
+    }
+    if !config.fields.iter().map(|field| &field.name).all_unique() {
+        return Err(CompilerError::GenericError(
+            "field names must be unique".to_string(),
+        ));
+    }
+    if config.format == FieldsFormat::KeyValue && config.separator.is_empty() {
+        return Err(CompilerError::GenericError(
+            "the separator of key=value fields must not be empty".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Builds the decomposed regex extracting every field of a structured body.
+///
+/// Every value is a public part, in the order of the fields, and the keys and punctuation
+/// around them are private parts. The first `key=value` pair must not follow a word character,
+/// so that the key `to` does not match inside `proto=`, and the last value is followed by the
+/// separator or a line break, which ends its capture. The last member of a JSON object is
+/// followed by `,` or `}`.
+///
+/// # Arguments
+///
+/// * `config` - The fields to extract.
+///
+/// # Returns
+///
+/// A `Result` containing the `DecomposedRegexConfig` or a `CompilerError`.
+pub(crate) fn fields_to_decomposed(
+    config: &FieldsConfig,
+) -> Result<DecomposedRegexConfig, CompilerError> {
+    validate_fields(config)?;
+    let separator = regex::escape(&config.separator);
+    let mut parts = VecDeque::new();
+    let mut private = String::new();
+    for (idx, field) in config.fields.iter().enumerate() {
+        let key = regex::escape(field.key.as_deref().unwrap_or(&field.name));
+        let quoted = config.format == FieldsFormat::Json && !field.numeric;
+        match config.format {
+            FieldsFormat::KeyValue if idx == 0 => {
+                private.push_str(&format!("(^|[^A-Za-z0-9_]){}=", key))
+            }
+            FieldsFormat::KeyValue => private.push_str(&format!("{}[ \\t]*{}=", separator, key)),
+            FieldsFormat::Json => {
+                if idx > 0 {
+                    private.push_str(&format!("{},{}", JSON_SPACE, JSON_SPACE));
+                }
+                private.push_str(&format!("\"{}\"{}:{}", key, JSON_SPACE, JSON_SPACE));
+                if quoted {
+                    private.push('"');
+                }
+            }
+        }
+        parts.push_back(private_part(std::mem::take(&mut private)));
+        parts.push_back(RegexPartConfig {
+            is_public: true,
+            regex_def: field.pattern.clone(),
+            max_repetitions: None,
+            max_length: field.max_length,
+            numeric: field.numeric,
+            hole: false,
+            min: field.min,
+            max: field.max,
+        });
+        if quoted {
+            private.push('"');
+        }
+    }
+    match config.format {
+        FieldsFormat::KeyValue => private.push_str(&format!("({}|\\r?\\n)", separator)),
+        FieldsFormat::Json => private.push_str(&format!("{}[,}}]", JSON_SPACE)),
+    }
+    parts.push_back(private_part(private));
+    Ok(DecomposedRegexConfig { parts })
+}
+
+fn private_part(regex_def: String) -> RegexPartConfig {
+    RegexPartConfig {
+        is_public: false,
+        regex_def,
+        max_repetitions: None,
+        max_length: None,
+        numeric: false,
+        hole: false,
+        min: None,
+        max: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::regex::get_regex_and_dfa;
+
+    fn fields_config(json: &str) -> FieldsConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_fields_to_decomposed() {
+        let config = fields_config(
+            r#"{"fields": [
+                {"name": "to", "pattern": "0x[0-9a-fA-F]+"},
+                {"name": "amount", "pattern": "[0-9]+", "numeric": true, "max": 1000},
+                {"name": "token", "key": "tok.en", "pattern": "[A-Z]+"}
+            ]}"#,
+        );
+        let decomposed = fields_to_decomposed(&config).unwrap();
+        assert_eq!(
+            decomposed
+                .parts
+                .iter()
+                .map(|part| (part.is_public, part.regex_def.as_str()))
+                .collect_vec(),
+            [
+                (false, "(^|[^A-Za-z0-9_])to="),
+                (true, "0x[0-9a-fA-F]+"),
+                (false, ";[ \\t]*amount="),
+                (true, "[0-9]+"),
+                (false, ";[ \\t]*tok\\.en="),
+                (true, "[A-Z]+"),
+                (false, "(;|\\r?\\n)"),
+            ]
+        );
+        assert_eq!(decomposed.parts[3].max, Some(1000));
+        let regex_and_dfa = get_regex_and_dfa(&mut decomposed.clone()).unwrap();
+        assert_eq!(regex_and_dfa.substrings.substring_ranges.len(), 3);
+
+        let config = fields_config(
+            r#"{"format": "json", "fields": [
+                {"name": "to", "pattern": "[a-z]+"},
+                {"name": "amount", "pattern": "[0-9]+", "numeric": true}
+            ]}"#,
+        );
+        let decomposed = fields_to_decomposed(&config).unwrap();
+        assert_eq!(
+            decomposed
+                .parts
+                .iter()
+                .map(|part| part.regex_def.as_str())
+                .collect_vec(),
+            [
+                "\"to\"[ \\t\\r\\n]*:[ \\t\\r\\n]*\"",
+                "[a-z]+",
+                "\"[ \\t\\r\\n]*,[ \\t\\r\\n]*\"amount\"[ \\t\\r\\n]*:[ \\t\\r\\n]*",
+                "[0-9]+",
+                "[ \\t\\r\\n]*[,}]",
+            ]
+        );
+        get_regex_and_dfa(&mut decomposed.clone()).unwrap();
+
+        for (json, error) in [
+            (r#"{"fields": []}"#, "at least one field"),
+            (
+                r#"{"fields": [{"name": "to-addr", "pattern": "a"}]}"#,
+                "field name \"to-addr\" is not a valid Noir identifier",
+            ),
+            (
+                r#"{"fields": [{"name": "a", "pattern": "a"}, {"name": "a", "pattern": "b"}]}"#,
+                "field names must be unique",
+            ),
+            (
+                r#"{"struct_name": "1x", "fields": [{"name": "a", "pattern": "a"}]}"#,
+                "struct name \"1x\"",
+            ),
+            (
+                r#"{"separator": "", "fields": [{"name": "a", "pattern": "a"}]}"#,
+                "separator",
+            ),
+        ] {
+            let err = fields_to_decomposed(&fields_config(json)).unwrap_err();
+            assert!(err.to_string().contains(error), "{}", err);
+        }
+    }
+}
@@ -1,13 +1,16 @@
 use crate::{
     errors::CompilerError,
+    progress::Progress,
     regex::{get_accepted_state, get_max_state},
-    structs::RegexAndDFA,
-};
-use std::{
-    fs::File,
-    io::{BufWriter, Write},
-    path::PathBuf,
+    structs::{Halo2Tables, RegexAndDFA},
 };
+use itertools::Itertools;
+use std::fmt::Write;
+
+#[cfg(feature = "fs")]
+use crate::progress::write_file;
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
 
 /// Converts a RegexAndDFA structure to a text representation of the DFA.
 ///
@@ -21,15 +24,27 @@ use std::{
 fn dfa_to_regex_def_text(regex_and_dfa: &RegexAndDFA) -> String {
     let accepted_state = get_accepted_state(&regex_and_dfa.dfa).unwrap();
     let max_state = get_max_state(&regex_and_dfa.dfa);
-    let mut text = format!("0\n{}\n{}\n", accepted_state, max_state);
+    let rows: usize = regex_and_dfa
+        .dfa
+        .states
+        .iter()
+        .flat_map(|state| state.transitions.values())
+        .map(|chars| chars.len())
+        .sum();
+    // A row holds two states and a byte, which rarely take more than 12 characters.
+    let mut text = String::with_capacity(16 * (rows + 3));
+    let _ = write!(text, "0\n{}\n{}\n", accepted_state, max_state);
 
+    let progress = Progress::new("Halo2 codegen", "rows", Some(rows));
     for (i, state) in regex_and_dfa.dfa.states.iter().enumerate() {
         for (next_state, chars) in state.transitions.iter() {
             for &char in chars {
-                text += &format!("{} {} {}\n", i, next_state, char as u8);
+                let _ = writeln!(text, "{} {} {}", i, next_state, char);
             }
+            progress.add(chars.len());
         }
     }
+    progress.finish();
     text
 }
 
@@ -45,52 +60,63 @@ fn dfa_to_regex_def_text(regex_and_dfa: &RegexAndDFA) -> String {
 /// # Returns
 ///
 /// A Result indicating success or containing a CompilerError.
+#[cfg(feature = "fs")]
 pub(crate) fn gen_halo2_tables(
     regex_and_dfa: &RegexAndDFA,
-    allstr_file_path: &PathBuf,
+    allstr_file_path: &Path,
     substr_file_paths: &[PathBuf],
     gen_substrs: bool,
 ) -> Result<(), CompilerError> {
-    let regex_text = dfa_to_regex_def_text(regex_and_dfa);
-    std::fs::write(allstr_file_path, regex_text)?;
+    let tables = gen_halo2_texts(regex_and_dfa, gen_substrs)?;
+    write_file(allstr_file_path, tables.allstr.as_bytes())?;
 
+    for (path, text) in substr_file_paths.iter().zip(tables.substrs) {
+        write_file(path, text.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Generates the contents of the Halo2 tables from a RegexAndDFA structure.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA structure.
+/// * `gen_substrs` - A boolean indicating whether to generate the substring definitions.
+///
+/// # Returns
+///
+/// A Result containing the Halo2 tables or a CompilerError.
+pub(crate) fn gen_halo2_texts(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+) -> Result<Halo2Tables, CompilerError> {
+    let allstr = dfa_to_regex_def_text(regex_and_dfa);
     if !gen_substrs {
-        return Ok(());
+        return Ok(Halo2Tables {
+            allstr,
+            substrs: vec![],
+        });
     }
 
+    let mut substrs = vec![];
     for (idx, defs) in regex_and_dfa.substrings.substring_ranges.iter().enumerate() {
-        let mut writer = BufWriter::new(File::create(&substr_file_paths[idx])?);
         let (starts, ends) = &regex_and_dfa
             .substrings
             .substring_boundaries
             .as_ref()
             .unwrap()[idx];
 
-        writeln!(
-            writer,
-            "{}",
-            starts
-                .iter()
-                .map(ToString::to_string)
-                .collect::<Vec<_>>()
-                .join(" ")
-        )?;
-        writeln!(
-            writer,
-            "{}",
-            ends.iter()
-                .map(ToString::to_string)
-                .collect::<Vec<_>>()
-                .join(" ")
-        )?;
+        let mut text = String::with_capacity(8 * (starts.len() + ends.len() + 2 * defs.len()));
+        let _ = writeln!(text, "{}", starts.iter().join(" "));
+        let _ = writeln!(text, "{}", ends.iter().join(" "));
 
-        let mut sorted_defs: Vec<_> = defs.iter().collect();
-        sorted_defs.sort_unstable_by_key(|&(start, end)| (*start, *end));
-
-        for &(cur, next) in &sorted_defs {
-            writeln!(writer, "{} {}", cur, next)?;
+        // The transitions are kept sorted by the set.
+        for (cur, next) in defs {
+            let _ = writeln!(text, "{} {}", cur, next);
         }
+        substrs.push(text);
     }
 
-    Ok(())
+    Ok(Halo2Tables { allstr, substrs })
 }
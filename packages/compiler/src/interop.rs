@@ -0,0 +1,231 @@
+//! Conversion of the DFAs of the `regex-automata` crate into compiler input.
+//!
+//! Dense and sparse DFAs built and tested with `regex-automata` can be fed straight into the
+//! Noir codegen. The DFA must support anchored searches, i.e. be built with
+//! `StartKind::Anchored` or `StartKind::Both`, and must not quit on any byte. The start state
+//! for the middle of the input is the one the matcher restarts from, and the start state for
+//! the start of the input is reached through the byte 255 priming `^`, as for compiled regexes.
+//!
+//! ```ignore
+//! let dfa = regex_automata::dfa::dense::DFA::new("[a-z]+@[a-z]+\\.com")?;
+//! let regex_and_dfa = interop::from_automaton(&dfa)?;
+//! gen_noir_from_dfa(&regex_and_dfa, "./src/sender.nr", &NoirOptions::default())?;
+//! ```
+
+use crate::{
+    errors::CompilerError,
+    regex::prune_dead_states,
+    structs::{DFAGraph, DFAStateNode, RegexAndDFA, SubstringDefinitions},
+};
+use regex_automata::{
+    dfa::Automaton,
+    util::{primitives::StateID, start},
+    Anchored,
+};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// The byte standing for the start anchor (^) in the DFA graph.
+const PRIMING_BYTE: u8 = 255;
+
+/// The pattern quoted in the generated code for a converted DFA.
+const DFA_PATTERN: &str = "(regex-automata DFA)";
+
+/// Where a state of a `regex-automata` DFA accepts the input consumed so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Acceptance {
+    /// The input is not a match.
+    None,
+    /// The input is a match whatever follows it.
+    Anywhere,
+    /// The input is a match only at the end of the input, as with `$`.
+    End,
+}
+
+/// Converts a dense or sparse DFA of the `regex-automata` crate into a `RegexAndDFA`.
+///
+/// `regex-automata` reports matches one byte late, so a state accepts when the end of the
+/// input or every byte leads to a match state. Matches that depend on the byte following
+/// them, as with word boundaries or multi-line anchors, cannot be checked by the Noir matcher
+/// and are rejected, as are DFAs matching some inputs anywhere and others only at the end of
+/// the input. The converted automaton has no substrings.
+///
+/// # Arguments
+///
+/// * `dfa` - The DFA to convert.
+///
+/// # Returns
+///
+/// A `Result` containing the `RegexAndDFA` of the DFA, or a `CompilerError` if it cannot be
+/// matched by the Noir matcher.
+pub fn from_automaton<A: Automaton>(dfa: &A) -> Result<RegexAndDFA, CompilerError> {
+    let anchored = start::Config::new().anchored(Anchored::Yes);
+    let start_error = |err| {
+        CompilerError::GenericError(format!(
+            "the DFA has no anchored start state, build it with StartKind::Anchored or StartKind::Both: {}",
+            err
+        ))
+    };
+    let start_text = dfa.start_state(&anchored).map_err(start_error)?;
+    let start_mid = dfa
+        .start_state(&anchored.look_behind(Some(PRIMING_BYTE)))
+        .map_err(start_error)?;
+
+    let mut ids = BTreeMap::from([(start_mid, 0)]);
+    let mut queue = VecDeque::from([start_mid]);
+    let mut states = vec![];
+    let mut id_of = |state: StateID, queue: &mut VecDeque<StateID>| {
+        let next_id = ids.len();
+        *ids.entry(state).or_insert_with(|| {
+            queue.push_back(state);
+            next_id
+        })
+    };
+    if start_text != start_mid {
+        id_of(start_text, &mut queue);
+    }
+    let mut acceptances = BTreeSet::new();
+    while let Some(state) = queue.pop_front() {
+        let mut transitions: BTreeMap<usize, BTreeSet<u8>> = BTreeMap::new();
+        let mut byte_matches = 0;
+        for byte in 0..=u8::MAX {
+            let next = dfa.next_state(state, byte);
+            if dfa.is_quit_state(next) {
+                return Err(CompilerError::GenericError(format!(
+                    "the DFA quits on the byte 0x{:02x}, build it without quit bytes",
+                    byte
+                )));
+            }
+            if dfa.is_match_state(next) {
+                byte_matches += 1;
+            }
+            if !dfa.is_dead_state(next) {
+                transitions
+                    .entry(id_of(next, &mut queue))
+                    .or_default()
+                    .insert(byte);
+            }
+        }
+        let acceptance = match (dfa.is_match_state(dfa.next_eoi_state(state)), byte_matches) {
+            (false, 0) => Acceptance::None,
+            (true, 256) => Acceptance::Anywhere,
+            (true, 0) => Acceptance::End,
+            _ => {
+                return Err(CompilerError::GenericError(
+                    "the matches of the DFA depend on the byte following them, as with word boundaries or multi-line anchors, which the Noir matcher cannot check"
+                        .to_string(),
+                ))
+            }
+        };
+        acceptances.insert(acceptance);
+        states.push(DFAStateNode {
+            state_type: match acceptance {
+                Acceptance::None => String::new(),
+                _ => "accept".to_string(),
+            },
+            state_id: states.len(),
+            transitions,
+        });
+    }
+    if acceptances.contains(&Acceptance::Anywhere) && acceptances.contains(&Acceptance::End) {
+        return Err(CompilerError::GenericError(
+            "the DFA matches some inputs anywhere and others only at the end of the input"
+                .to_string(),
+        ));
+    }
+    if start_text != start_mid {
+        let start_text = ids[&start_text];
+        let primed = states[0]
+            .transitions
+            .values()
+            .any(|bytes| bytes.contains(&PRIMING_BYTE));
+        if primed {
+            return Err(CompilerError::GenericError(
+                "the DFA matches the byte 0xff, which stands for the start anchor (^)".to_string(),
+            ));
+        }
+        states[0]
+            .transitions
+            .entry(start_text)
+            .or_default()
+            .insert(PRIMING_BYTE);
+    }
+
+    let mut regex_and_dfa = RegexAndDFA {
+        regex_pattern: DFA_PATTERN.to_string(),
+        dfa: DFAGraph { states },
+        has_end_anchor: acceptances.contains(&Acceptance::End),
+        substrings: SubstringDefinitions {
+            substring_ranges: vec![],
+            substring_boundaries: None,
+            substring_groups: None,
+            substring_names: None,
+        },
+        parts: vec![],
+        counters: vec![],
+        whole_input: false,
+    };
+    prune_dead_states(&mut regex_and_dfa);
+    if !regex_and_dfa
+        .dfa
+        .states
+        .iter()
+        .any(|state| state.state_type == "accept")
+    {
+        return Err(CompilerError::GenericError(
+            "the DFA cannot match any input".to_string(),
+        ));
+    }
+    Ok(regex_and_dfa)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{noir::simulate_matcher, structs::NoirOptions};
+    use regex_automata::dfa::{dense, StartKind};
+
+    #[test]
+    fn test_from_automaton() {
+        let options = NoirOptions::default();
+        let matches = |regex_and_dfa: &RegexAndDFA, input: &str| {
+            simulate_matcher(regex_and_dfa, false, &options, input.as_bytes()).is_some()
+        };
+        let dfa = dense::DFA::new("ab+c").unwrap();
+        for regex_and_dfa in [
+            from_automaton(&dfa).unwrap(),
+            from_automaton(&dfa.to_sparse().unwrap()).unwrap(),
+        ] {
+            assert!(!regex_and_dfa.has_end_anchor);
+            assert!(matches(&regex_and_dfa, "xabbbc"));
+            assert!(matches(&regex_and_dfa, "abcx"));
+            assert!(!matches(&regex_and_dfa, "ac"));
+        }
+
+        let regex_and_dfa = from_automaton(&dense::DFA::new("^ab").unwrap()).unwrap();
+        assert!(matches(&regex_and_dfa, "abx"));
+        assert!(!matches(&regex_and_dfa, "xab"));
+
+        let regex_and_dfa = from_automaton(&dense::DFA::new("ab$").unwrap()).unwrap();
+        assert!(regex_and_dfa.has_end_anchor);
+        assert!(matches(&regex_and_dfa, "xab"));
+        assert!(!matches(&regex_and_dfa, "abx"));
+
+        for (dfa, error) in [
+            (
+                dense::DFA::new(r"ab(?-u:\b)").unwrap(),
+                "depend on the byte",
+            ),
+            (dense::DFA::new("a$|b").unwrap(), "anywhere and others"),
+            (
+                dense::Builder::new()
+                    .configure(dense::Config::new().start_kind(StartKind::Unanchored))
+                    .build("ab")
+                    .unwrap(),
+                "no anchored start state",
+            ),
+        ] {
+            let err = from_automaton(&dfa).unwrap_err();
+            assert!(err.to_string().contains(error), "{}", err);
+        }
+    }
+}
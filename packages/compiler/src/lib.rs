@@ -1,17 +1,131 @@
+#[cfg(feature = "fs")]
+mod abi;
+pub mod algebra;
+mod automaton;
+#[cfg(feature = "fs")]
+pub mod build;
 mod circom;
+mod dialect;
+#[cfg(feature = "fs")]
+mod diff;
 mod errors;
+#[cfg(feature = "fs")]
+mod explain;
+#[cfg(feature = "fs")]
+mod fields;
 mod halo2;
+#[cfg(feature = "dfa-interop")]
+pub mod interop;
+#[cfg(feature = "fs")]
+mod migrate;
+mod noir;
+pub mod presets;
+mod profile;
+mod progress;
 mod regex;
+#[cfg(feature = "fs")]
+mod report;
+#[cfg(feature = "fs")]
+mod sample;
+#[cfg(feature = "fs")]
+mod scaffold;
 mod structs;
+mod template;
 mod wasm;
 
-use circom::gen_circom_template;
+use circom::gen_circom_source;
+use dialect::{translate_config_dialect, translate_dialect};
 use errors::CompilerError;
+use halo2::gen_halo2_texts;
+use noir::{check_gate_budget, compiled_automaton, gen_noir_sources};
+use profile::timed;
+use rayon::prelude::*;
+use regex::{
+    create_counted_regex_and_dfa, create_regex_and_dfa_from_groups,
+    create_regex_and_dfa_from_str_and_defs, get_regex_and_dfa, relax_config_line_endings,
+    relax_line_endings, select_substrs,
+};
+use structs::{DecomposedRegexConfig, SubstringDefinitionsJson};
+#[cfg(feature = "fs")]
+use template::{expand_config_fragments, expand_fragments};
+use template::{substitute_config_vars, substitute_vars};
+
+#[cfg(feature = "fs")]
+use abi::noir_abi;
+#[cfg(feature = "fs")]
+use circom::gen_circom_template;
+#[cfg(feature = "fs")]
+use diff::diff_automata;
+#[cfg(feature = "fs")]
+use explain::{automaton_dot, automaton_html, explain_automaton};
+#[cfg(feature = "fs")]
+use fields::fields_to_decomposed;
+#[cfg(feature = "fs")]
 use halo2::gen_halo2_tables;
+#[cfg(feature = "fs")]
 use itertools::Itertools;
-use regex::{create_regex_and_dfa_from_str_and_defs, get_regex_and_dfa};
-use std::{fs::File, path::PathBuf};
-use structs::{DecomposedRegexConfig, RegexAndDFA, SubstringDefinitionsJson};
+#[cfg(feature = "fs")]
+use migrate::{
+    find_circom_template, parse_circom_config, parse_circom_template, pattern_names, project_files,
+    semantic_differences,
+};
+#[cfg(feature = "fs")]
+use noir::{
+    distinguishing_matcher_input, estimate_proving_costs, estimate_sizes, gen_aztec_contract,
+    gen_noir_composed_fn, gen_noir_denylist_fn, gen_noir_example, gen_noir_fields_fn, gen_noir_fn,
+    gen_noir_main, gen_noir_shared_fn, gen_runtime_lib, nargo_package_name, substr_capture_bounds,
+    ComposedPattern,
+};
+#[cfg(feature = "process")]
+use noir::{gen_noir_check_project, gen_noir_project, sample_input_of_len, simulate_matcher};
+#[cfg(feature = "process")]
+use profile::child_peak_memory_kb;
+#[cfg(feature = "fs")]
+use progress::FileWriter;
+#[cfg(feature = "process")]
+use progress::ScratchDir;
+#[cfg(feature = "fs")]
+use regex::{absolute_anchors, create_dfa_graph_from_regex, set_accept_states, split_anchors};
+#[cfg(feature = "fs")]
+use report::{pattern_report, pattern_report_from_contents, write_report};
+#[cfg(feature = "fs")]
+use sample::{sample_inputs, sample_near_misses};
+#[cfg(feature = "fs")]
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{create_dir_all, write, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+#[cfg(feature = "process")]
+use std::{
+    fs::remove_dir_all,
+    process::Command,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "fs")]
+use structs::PatternManifest;
+#[cfg(feature = "fs")]
+use tracing::info;
+
+#[cfg(feature = "fs")]
+pub use abi::{NoirAbi, NoirAbiCapture, NoirAbiFunction, NoirAbiParam, NoirAbiType};
+pub use automaton::{AutomatonState, AutomatonTransition, CompiledAutomaton};
+#[cfg(feature = "fs")]
+pub use diff::{AutomatonDiff, CaptureChange, StatePair, TransitionChange};
+#[cfg(feature = "fs")]
+pub use migrate::{MigrationNote, MigrationReport};
+pub use profile::{finish_profiling, start_profiling, ProfileReport};
+pub use progress::start_progress;
+pub use structs::{
+    CaptureGroup, CompileOptions, CompileReport, CompiledRegex, CorpusProfile, DFAStats, Dialect,
+    Divergence, EmitKind, EmitOptions, FieldConfig, FieldsConfig, FieldsFormat, GateBudget,
+    Halo2Tables, MainOutput, NoirCaptureOverflow, NoirEmptyMatch, NoirInputAlphabet, NoirInputHash,
+    NoirInputType, NoirMainOptions, NoirMatchSemantics, NoirOptions, NoirStateType, NoirStrategy,
+    OutputFile, OutputPaths, PatternComposition, PatternReport, PatternSource, PatternTestResult,
+    ProvingBackend, ProvingCost, RegexAndDFA, SampleOptions, SizeEstimate, StateVisits,
+    SubstrSelection, TransitionVisits,
+};
 
 /// Loads substring definitions from a JSON file or creates a default one.
 ///
@@ -22,114 +136,334 @@ use structs::{DecomposedRegexConfig, RegexAndDFA, SubstringDefinitionsJson};
 /// # Returns
 ///
 /// A `Result` containing either the loaded `SubstringDefinitionsJson` or a `CompilerError`.
+#[cfg(feature = "fs")]
 fn load_substring_definitions_json(
     substrs_json_path: Option<&str>,
 ) -> Result<SubstringDefinitionsJson, CompilerError> {
     match substrs_json_path {
         Some(path) => {
             let file = File::open(path)?;
-            serde_json::from_reader(file).map_err(CompilerError::JsonParseError)
+            timed("parsing", || serde_json::from_reader(file))
+                .map_err(CompilerError::JsonParseError)
         }
         None => Ok(SubstringDefinitionsJson {
             transitions: vec![vec![]],
+            names: vec![],
+        }),
+    }
+}
+
+/// Reads the contents of a file, or of the standard input if the path is `-`.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file to read, or `-` for the standard input.
+///
+/// # Returns
+///
+/// A `Result` containing either the contents as a `String` or a `CompilerError`.
+#[cfg(feature = "fs")]
+fn read_source(path: &str) -> Result<String, CompilerError> {
+    let mut contents = String::new();
+    if path == "-" {
+        std::io::stdin().read_to_string(&mut contents)?;
+    } else {
+        File::open(path)?.read_to_string(&mut contents)?;
+    }
+    Ok(contents)
+}
+
+/// Parses a configuration, such as a decomposed regex, written in JSON, JSON5 or YAML.
+///
+/// The format is chosen by the extension of the path: `.yaml` or `.yml` for YAML, `.json5` for
+/// JSON5, and JSON otherwise. Sources in JSON that fail to parse, such as the standard input,
+/// are also tried as JSON5 and then YAML, and the JSON error is reported if both fail.
+///
+/// # Arguments
+///
+/// * `source` - The contents of the configuration.
+/// * `path` - The path the configuration was read from, or `-` for the standard input.
+///
+/// # Returns
+///
+/// A `Result` containing the configuration or a `CompilerError`.
+#[cfg(feature = "fs")]
+fn parse_config<T: serde::de::DeserializeOwned>(
+    source: &str,
+    path: &str,
+) -> Result<T, CompilerError> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    let parse_error = |format: &str, e: &dyn std::fmt::Display| {
+        CompilerError::ParseError(format!("{} ({}): {}", path, format, e))
+    };
+    timed("parsing", || match extension.as_deref() {
+        Some("yaml" | "yml") => serde_yaml::from_str(source).map_err(|e| parse_error("YAML", &e)),
+        Some("json5") => json5::from_str(source).map_err(|e| parse_error("JSON5", &e)),
+        _ => serde_json::from_str(source).or_else(|json_error| {
+            json5::from_str(source)
+                .or_else(|_| serde_yaml::from_str(source))
+                .map_err(|_| CompilerError::from(json_error))
         }),
+    })
+}
+
+/// Reads a raw regex pattern from a file, or from the standard input if the path is `-`.
+///
+/// A single trailing newline is dropped, so that patterns written by editors or `echo` do not
+/// end up matching a line break. Any other whitespace is kept as part of the pattern.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file holding the pattern, or `-` for the standard input.
+///
+/// # Returns
+///
+/// A `Result` containing either the pattern as a `String` or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn read_pattern(path: &str) -> Result<String, CompilerError> {
+    let contents = read_source(path)?;
+    Ok(strip_trailing_newline(&contents).to_string())
+}
+
+/// Drops a single trailing `\n` or `\r\n` from `text`.
+#[cfg(feature = "fs")]
+fn strip_trailing_newline(text: &str) -> &str {
+    text.strip_suffix("\r\n")
+        .or_else(|| text.strip_suffix('\n'))
+        .unwrap_or(text)
+}
+
+/// Keeps only the selected substrings of the regex.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - The `RegexAndDFA` struct whose substrings are filtered in place.
+/// * `selection` - The substrings to extract.
+///
+/// # Returns
+///
+/// A `Result` containing whether any substring is extracted, or a `CompilerError`.
+fn apply_substr_selection(
+    regex_and_dfa: &mut RegexAndDFA,
+    selection: &SubstrSelection,
+) -> Result<bool, CompilerError> {
+    match selection {
+        SubstrSelection::All => Ok(true),
+        SubstrSelection::Off => Ok(false),
+        SubstrSelection::Only(indices) => {
+            select_substrs(regex_and_dfa, indices)?;
+            Ok(true)
+        }
     }
 }
 
-/// Generates output files for Halo2 and Circom based on the provided regex and DFA.
+/// Generates output files for Halo2, Circom and Noir based on the provided regex and DFA.
 ///
 /// # Arguments
 ///
 /// * `regex_and_dfa` - The `RegexAndDFA` struct containing the regex pattern and DFA.
-/// * `halo2_dir_path` - An optional path to the directory for Halo2 output files.
-/// * `circom_file_path` - An optional path to the Circom output file.
+/// * `paths` - The files and directories to write.
 /// * `circom_template_name` - An optional name for the Circom template.
+/// * `noir_options` - The options controlling the generated Noir matcher.
 /// * `num_public_parts` - The number of public parts in the regex.
 /// * `gen_substrs` - A boolean indicating whether to generate substrings.
 ///
 /// # Returns
 ///
-/// A `Result` indicating success or a `CompilerError`.
+/// A `Result` containing the files and directories written, or a `CompilerError`.
+#[cfg(feature = "fs")]
 fn generate_outputs(
     regex_and_dfa: &RegexAndDFA,
-    halo2_dir_path: Option<&str>,
-    circom_file_path: Option<&str>,
+    paths: &OutputPaths,
     circom_template_name: Option<&str>,
+    noir_options: &NoirOptions,
     num_public_parts: usize,
     gen_substrs: bool,
-) -> Result<(), CompilerError> {
-    if let Some(halo2_dir_path) = halo2_dir_path {
+) -> Result<Vec<PathBuf>, CompilerError> {
+    if paths.noir_main.is_some() && paths.noir_file.is_none() {
+        return Err(CompilerError::GenericError(
+            "the main function requires a Noir output file to call".to_string(),
+        ));
+    }
+    let mut outputs = vec![];
+    if let Some(halo2_dir_path) = &paths.halo2_dir {
+        info!(path = halo2_dir_path, "writing the Halo2 tables");
         let halo2_dir_path = PathBuf::from(halo2_dir_path);
         let allstr_file_path = halo2_dir_path.join("allstr.txt");
         let substr_file_paths = (0..num_public_parts)
             .map(|idx| halo2_dir_path.join(format!("substr_{}.txt", idx)))
             .collect_vec();
 
-        gen_halo2_tables(
-            regex_and_dfa,
-            &allstr_file_path,
-            &substr_file_paths,
-            gen_substrs,
-        )?;
+        timed("Halo2 codegen", || {
+            gen_halo2_tables(
+                regex_and_dfa,
+                &allstr_file_path,
+                &substr_file_paths,
+                gen_substrs,
+            )
+        })?;
+        outputs.push(allstr_file_path);
+        outputs.extend(substr_file_paths);
     }
 
-    if let Some(circom_file_path) = circom_file_path {
+    if let Some(circom_file_path) = &paths.circom_file {
+        info!(path = circom_file_path, "writing the Circom template");
         let circom_file_path = PathBuf::from(circom_file_path);
         let circom_template_name = circom_template_name
             .expect("circom template name must be specified if circom file path is specified");
 
-        gen_circom_template(
-            regex_and_dfa,
-            &circom_file_path,
-            &circom_template_name,
-            gen_substrs,
-        )?;
+        timed("Circom codegen", || {
+            gen_circom_template(
+                regex_and_dfa,
+                &circom_file_path,
+                circom_template_name,
+                gen_substrs,
+            )
+        })?;
+        outputs.push(circom_file_path);
     }
 
-    Ok(())
+    if let Some(noir_file_path) = &paths.noir_file {
+        info!(path = noir_file_path, "writing the Noir matcher");
+        let noir_file_path = PathBuf::from(noir_file_path);
+        timed("Noir codegen", || {
+            gen_noir_fn(regex_and_dfa, &noir_file_path, gen_substrs, noir_options)
+        })?;
+        if noir_options.split_modules {
+            outputs.push(noir_file_path.with_extension(""));
+        }
+        if let Some(noir_main) = &paths.noir_main {
+            info!(path = %noir_main.path, "writing the Noir main function");
+            let main_path = PathBuf::from(&noir_main.path);
+            timed("Noir codegen", || {
+                gen_noir_main(
+                    regex_and_dfa,
+                    &main_path,
+                    &noir_file_path,
+                    gen_substrs,
+                    noir_options,
+                    noir_main,
+                )
+            })?;
+            outputs.push(main_path);
+        }
+        outputs.push(noir_file_path);
+    }
+
+    if let Some(noir_example_dir) = &paths.noir_example_dir {
+        info!(path = noir_example_dir, "writing the Nargo example project");
+        timed("Noir codegen", || {
+            gen_noir_example(
+                regex_and_dfa,
+                &PathBuf::from(noir_example_dir),
+                gen_substrs,
+                noir_options,
+            )
+        })?;
+        outputs.push(PathBuf::from(noir_example_dir));
+    }
+
+    if let Some(aztec_contract_dir) = &paths.aztec_contract_dir {
+        info!(path = aztec_contract_dir, "writing the Aztec contract");
+        timed("Noir codegen", || {
+            gen_aztec_contract(
+                regex_and_dfa,
+                &PathBuf::from(aztec_contract_dir),
+                gen_substrs,
+                noir_options,
+                paths.aztec_input_len,
+            )
+        })?;
+        outputs.push(PathBuf::from(aztec_contract_dir));
+    }
+
+    Ok(outputs)
 }
 
 /// Generates outputs from a decomposed regex configuration file.
 ///
 /// # Arguments
 ///
-/// * `decomposed_regex_path` - The path to the decomposed regex configuration file.
-/// * `halo2_dir_path` - An optional path to the directory for Halo2 output files.
-/// * `circom_file_path` - An optional path to the Circom output file.
-/// * `circom_template_name` - An optional name for the Circom template.
-/// * `gen_substrs` - An optional boolean indicating whether to generate substrings.
+/// * `decomposed_regex_path` - The path to the decomposed regex configuration file, or `-` to read
+///   it from the standard input.
+/// * `paths` - The files and directories to write.
+/// * `options` - The options of the compilation, of which the substrings to extract are none by
+///   default and the raw regex settings are ignored.
 ///
 /// # Returns
 ///
 /// A `Result` indicating success or a `CompilerError`.
+#[cfg(feature = "fs")]
 pub fn gen_from_decomposed(
     decomposed_regex_path: &str,
-    halo2_dir_path: Option<&str>,
-    circom_file_path: Option<&str>,
-    circom_template_name: Option<&str>,
-    gen_substrs: Option<bool>,
+    paths: &OutputPaths,
+    options: &CompileOptions,
 ) -> Result<(), CompilerError> {
-    let mut decomposed_regex_config: DecomposedRegexConfig =
-        serde_json::from_reader(File::open(decomposed_regex_path)?)?;
-    let gen_substrs = gen_substrs.unwrap_or(false);
+    info!(path = decomposed_regex_path, "compiling a decomposed regex");
+    let noir_options = &options.noir.clone().unwrap_or_default();
+    let source = read_source(decomposed_regex_path)?;
+    let mut decomposed_regex_config = parse_config(&source, decomposed_regex_path)?;
+    translate_config_dialect(
+        &mut decomposed_regex_config,
+        options.dialect,
+        options.permissive_escapes,
+    )?;
+    substitute_config_vars(&mut decomposed_regex_config, &options.template_vars)?;
+    if options.lenient_newlines {
+        relax_config_line_endings(&mut decomposed_regex_config);
+    }
 
-    let regex_and_dfa = get_regex_and_dfa(&mut decomposed_regex_config)?;
+    let mut regex_and_dfa = get_regex_and_dfa(&mut decomposed_regex_config)?;
+    let gen_substrs = apply_substr_selection(
+        &mut regex_and_dfa,
+        options
+            .gen_substrs
+            .as_ref()
+            .unwrap_or(&SubstrSelection::Off),
+    )?;
 
-    let num_public_parts = decomposed_regex_config
-        .parts
-        .iter()
-        .filter(|part| part.is_public)
-        .count();
+    if let Some(gate_budget) = &options.gate_budget {
+        check_gate_budget(&regex_and_dfa, gen_substrs, noir_options, gate_budget)?;
+    }
+
+    let num_public_parts = regex_and_dfa.substrings.substring_ranges.len();
 
-    generate_outputs(
+    let outputs = generate_outputs(
         &regex_and_dfa,
-        halo2_dir_path,
-        circom_file_path,
-        circom_template_name,
+        paths,
+        options.circom_template_name.as_deref(),
+        noir_options,
         num_public_parts,
         gen_substrs,
     )?;
 
+    if let Some(report_path) = &paths.report {
+        let noir = paths.noir_file.is_some()
+            || paths.noir_example_dir.is_some()
+            || paths.aztec_contract_dir.is_some();
+        write_compile_report(
+            report_path,
+            decomposed_regex_path,
+            &regex_and_dfa,
+            gen_substrs,
+            noir.then_some(noir_options),
+            &outputs,
+        )?;
+    }
+
+    if let Some(emit) = &paths.emit {
+        emit_artifacts(
+            emit,
+            decomposed_regex_path,
+            &regex_and_dfa,
+            gen_substrs,
+            noir_options,
+        )?;
+    }
+
     Ok(())
 }
 
@@ -138,38 +472,2963 @@ pub fn gen_from_decomposed(
 /// # Arguments
 ///
 /// * `raw_regex` - The raw regex string.
-/// * `substrs_json_path` - An optional path to the JSON file containing substring definitions.
-/// * `halo2_dir_path` - An optional path to the directory for Halo2 output files.
-/// * `circom_file_path` - An optional path to the Circom output file.
-/// * `template_name` - An optional name for the Circom template.
-/// * `gen_substrs` - An optional boolean indicating whether to generate substrings.
+/// * `substrs_json_path` - An optional path to the JSON file containing substring definitions,
+///   used when the substring transitions of the options are empty.
+/// * `accept_states` - The states of the DFA to accept instead of those derived from the
+///   regex, if any, see `set_accept_states`.
+/// * `paths` - The files and directories to write.
+/// * `options` - The options of the compilation, of which the substrings to extract are all by
+///   default.
 ///
 /// # Returns
 ///
 /// A `Result` indicating success or a `CompilerError`.
+#[cfg(feature = "fs")]
 pub fn gen_from_raw(
     raw_regex: &str,
     substrs_json_path: Option<&str>,
-    halo2_dir_path: Option<&str>,
-    circom_file_path: Option<&str>,
-    template_name: Option<&str>,
-    gen_substrs: Option<bool>,
+    accept_states: Option<&BTreeSet<usize>>,
+    paths: &OutputPaths,
+    options: &CompileOptions,
 ) -> Result<(), CompilerError> {
-    let substrs_defs_json = load_substring_definitions_json(substrs_json_path)?;
-    let num_public_parts = substrs_defs_json.transitions.len();
+    let noir_options = &options.noir.clone().unwrap_or_default();
+    let substrs_defs_json = match options.substr_transitions.is_empty() {
+        true => load_substring_definitions_json(substrs_json_path)?,
+        false => SubstringDefinitionsJson {
+            transitions: options.substr_transitions.clone(),
+            names: options.substr_names.clone(),
+        },
+    };
+
+    let mut raw_regex = substitute_vars(
+        &translate_dialect(raw_regex, options.dialect, options.permissive_escapes)?,
+        &options.template_vars,
+    )?;
+    if options.lenient_newlines {
+        raw_regex = relax_line_endings(&raw_regex);
+    }
+    info!(regex = %raw_regex, "compiling a raw regex");
+    let mut regex_and_dfa = raw_regex_and_dfa(
+        &raw_regex,
+        options.capture_groups,
+        substrs_defs_json,
+        noir_options.repetition_counter,
+        paths.halo2_dir.is_some() || paths.circom_file.is_some(),
+    )?;
+    if let Some(accept_states) = accept_states {
+        set_accept_states(&mut regex_and_dfa, accept_states)?;
+    }
+    let gen_substrs = apply_substr_selection(
+        &mut regex_and_dfa,
+        options
+            .gen_substrs
+            .as_ref()
+            .unwrap_or(&SubstrSelection::All),
+    )?;
 
-    let regex_and_dfa = create_regex_and_dfa_from_str_and_defs(raw_regex, substrs_defs_json)?;
+    if let Some(gate_budget) = &options.gate_budget {
+        check_gate_budget(&regex_and_dfa, gen_substrs, noir_options, gate_budget)?;
+    }
 
-    let gen_substrs = gen_substrs.unwrap_or(true);
+    let num_public_parts = regex_and_dfa.substrings.substring_ranges.len();
 
-    generate_outputs(
+    let outputs = generate_outputs(
         &regex_and_dfa,
-        halo2_dir_path,
-        circom_file_path,
-        template_name,
+        paths,
+        options.circom_template_name.as_deref(),
+        noir_options,
         num_public_parts,
         gen_substrs,
     )?;
 
+    if let Some(report_path) = &paths.report {
+        let noir = paths.noir_file.is_some()
+            || paths.noir_example_dir.is_some()
+            || paths.aztec_contract_dir.is_some();
+        write_compile_report(
+            report_path,
+            &raw_regex,
+            &regex_and_dfa,
+            gen_substrs,
+            noir.then_some(noir_options),
+            &outputs,
+        )?;
+    }
+
+    if let Some(emit) = &paths.emit {
+        emit_artifacts(emit, &raw_regex, &regex_and_dfa, gen_substrs, noir_options)?;
+    }
+
     Ok(())
 }
+
+/// Creates the RegexAndDFA of a raw regex, whose substrings are its capture groups or the
+/// given transitions.
+///
+/// # Arguments
+///
+/// * `raw_regex` - The raw regex.
+/// * `capture_groups` - Whether the substrings are the capture groups of the regex.
+/// * `substrs_defs_json` - Otherwise, the transitions of the substrings.
+/// * `repetition_counter` - If set, the bounded repetitions of a single byte repeating more
+///   than this many times are matched with counters.
+/// * `other_backends` - Whether a Circom or Halo2 circuit is generated as well.
+///
+/// # Returns
+///
+/// A `Result` containing the RegexAndDFA or a `CompilerError`.
+fn raw_regex_and_dfa(
+    raw_regex: &str,
+    capture_groups: bool,
+    substrs_defs_json: SubstringDefinitionsJson,
+    repetition_counter: Option<usize>,
+    other_backends: bool,
+) -> Result<RegexAndDFA, CompilerError> {
+    match (repetition_counter, capture_groups) {
+        (Some(_), _) if other_backends => Err(CompilerError::GenericError(
+            "repetition counters are only supported by the Noir backend".to_string(),
+        )),
+        (Some(threshold), _) => {
+            create_counted_regex_and_dfa(raw_regex, threshold, capture_groups, substrs_defs_json)
+        }
+        (None, true) => create_regex_and_dfa_from_groups(raw_regex),
+        (None, false) => create_regex_and_dfa_from_str_and_defs(raw_regex, substrs_defs_json),
+    }
+}
+
+/// Writes the requested artifacts of a compiled pattern, all named after the same stem.
+///
+/// Every artifact is generated in memory before any is written, and the files are first
+/// written next to their destination and then renamed into place, so that a failure leaves
+/// none of them half-written or out of date with the others. Files whose contents are
+/// unchanged are left untouched, as by `FileWriter`. The report describes the other
+/// artifacts emitted along with it. Split Noir modules are written to the directory named
+/// after the stem.
+///
+/// # Arguments
+///
+/// * `emit` - The stem and the artifacts to write.
+/// * `source` - The decomposed regex file or the raw regex the pattern was compiled from.
+/// * `regex_and_dfa` - The `RegexAndDFA` struct of the compiled pattern.
+/// * `gen_substrs` - A boolean indicating whether the substrings are extracted.
+/// * `noir_options` - The options controlling the generated Noir matcher.
+///
+/// # Returns
+///
+/// A `Result` containing the paths written, or a `CompilerError`.
+#[cfg(feature = "fs")]
+fn emit_artifacts(
+    emit: &EmitOptions,
+    source: &str,
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    noir_options: &NoirOptions,
+) -> Result<Vec<PathBuf>, CompilerError> {
+    let stem = &emit.stem;
+    let kinds: BTreeSet<EmitKind> = emit.kinds.iter().copied().collect();
+    let noir_sources = match kinds.contains(&EmitKind::Noir) || kinds.contains(&EmitKind::Abi) {
+        true => timed("Noir codegen", || {
+            gen_noir_sources(regex_and_dfa, gen_substrs, noir_options)
+        })?,
+        false => vec![],
+    };
+    let mut files: Vec<(PathBuf, Vec<u8>)> = vec![];
+    for kind in &kinds {
+        match kind {
+            EmitKind::Noir => {
+                for (name, code) in &noir_sources {
+                    let path = match name.is_empty() {
+                        true => PathBuf::from(format!("{stem}.nr")),
+                        false => Path::new(stem).join(format!("{name}.nr")),
+                    };
+                    files.push((path, code.clone().into_bytes()));
+                }
+            }
+            EmitKind::Abi => {
+                let capture_bounds = match gen_substrs {
+                    true => substr_capture_bounds(regex_and_dfa),
+                    false => vec![],
+                };
+                files.push((
+                    PathBuf::from(format!("{stem}.abi.json")),
+                    serde_json::to_vec_pretty(&noir_abi(
+                        regex_and_dfa,
+                        &noir_sources,
+                        &capture_bounds,
+                    )?)?,
+                ));
+            }
+            EmitKind::Json => files.push((
+                PathBuf::from(format!("{stem}.json")),
+                serde_json::to_vec_pretty(regex_and_dfa)?,
+            )),
+            EmitKind::Dot => files.push((
+                PathBuf::from(format!("{stem}.dot")),
+                automaton_dot(regex_and_dfa).into_bytes(),
+            )),
+            EmitKind::Html => files.push((
+                PathBuf::from(format!("{stem}.html")),
+                automaton_html(regex_and_dfa).into_bytes(),
+            )),
+            EmitKind::Report => {}
+        }
+    }
+    if kinds.contains(&EmitKind::Report) {
+        let report = CompileReport {
+            patterns: vec![pattern_report_from_contents(
+                source,
+                regex_and_dfa,
+                gen_substrs,
+                kinds.contains(&EmitKind::Noir).then_some(noir_options),
+                &files,
+            )],
+        };
+        files.push((
+            PathBuf::from(format!("{stem}.report.json")),
+            serde_json::to_vec_pretty(&report)?,
+        ));
+    }
+
+    info!(
+        stem = stem.as_str(),
+        files = files.len(),
+        "emitting the artifacts"
+    );
+    let mut staged = vec![];
+    for (path, contents) in &files {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            create_dir_all(parent)?;
+        }
+        // A failure drops the files staged so far, which removes them.
+        let mut file = FileWriter::create(path)?;
+        file.write_all(contents)?;
+        staged.extend(file.stage()?.0);
+    }
+    for file in staged {
+        file.commit()?;
+    }
+    Ok(files.into_iter().map(|(path, _)| path).collect())
+}
+
+/// Writes the JSON report summarizing the compilation of a single pattern.
+///
+/// # Arguments
+///
+/// * `report_path` - The path of the JSON report.
+/// * `source` - The decomposed regex file or the raw regex the pattern was compiled from.
+/// * `regex_and_dfa` - The `RegexAndDFA` struct of the compiled pattern.
+/// * `gen_substrs` - A boolean indicating whether the substrings are extracted.
+/// * `noir_options` - The options of the Noir matcher, if one is generated.
+/// * `outputs` - The files and directories written for the pattern.
+///
+/// # Returns
+///
+/// A `Result` indicating success or a `CompilerError`.
+#[cfg(feature = "fs")]
+fn write_compile_report(
+    report_path: &str,
+    source: &str,
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    noir_options: Option<&NoirOptions>,
+    outputs: &[PathBuf],
+) -> Result<(), CompilerError> {
+    info!(path = report_path, "writing the compile report");
+    let report = CompileReport {
+        patterns: vec![pattern_report(
+            source,
+            regex_and_dfa,
+            gen_substrs,
+            noir_options,
+            outputs,
+        )?],
+    };
+    write_report(report_path, &report)
+}
+
+/// Generates a Noir matcher from an automaton, e.g. one built with the operations of
+/// [`algebra`].
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - The automaton to match.
+/// * `noir_file_path` - The path to the Noir output file.
+/// * `noir_options` - The options controlling the generated Noir matcher.
+///
+/// # Returns
+///
+/// A `Result` indicating success or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn gen_noir_from_dfa(
+    regex_and_dfa: &RegexAndDFA,
+    noir_file_path: &str,
+    noir_options: &NoirOptions,
+) -> Result<(), CompilerError> {
+    let gen_substrs = !regex_and_dfa.substrings.substring_ranges.is_empty();
+    timed("Noir codegen", || {
+        gen_noir_fn(
+            regex_and_dfa,
+            &PathBuf::from(noir_file_path),
+            gen_substrs,
+            noir_options,
+        )
+    })
+}
+
+/// Loads the patterns listed in a manifest file, building their DFAs in parallel.
+///
+/// # Arguments
+///
+/// * `manifest_path` - The path to the JSON manifest listing the patterns.
+/// * `gen_substrs` - The substrings to extract from every pattern.
+/// * `template_vars` - The values of the `{{name}}` variables used in the patterns, overriding
+///   those given in the manifest.
+/// * `lenient_newlines` - Whether every `\r\n` of the patterns also matches a bare `\n`.
+/// * `dialect` - The regex syntax the patterns are written in.
+/// * `permissive_escapes` - Whether unknown escapes such as `\q` match the escaped character
+///   instead of failing.
+///
+/// # Returns
+///
+/// A `Result` containing the patterns as (name, `RegexAndDFA`) tuples, or a `CompilerError`.
+#[cfg(feature = "fs")]
+fn load_manifest_patterns(
+    manifest_path: &str,
+    gen_substrs: &SubstrSelection,
+    template_vars: &BTreeMap<String, String>,
+    lenient_newlines: bool,
+    dialect: Dialect,
+    permissive_escapes: bool,
+) -> Result<Vec<(String, RegexAndDFA)>, CompilerError> {
+    let file = File::open(manifest_path)?;
+    let PatternManifest {
+        patterns,
+        vars: manifest_vars,
+        fragments,
+        includes,
+    } = timed("parsing", || serde_json::from_reader(file))?;
+    let base_dir = Path::new(manifest_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    let resolve = |path: &str| base_dir.join(path).to_string_lossy().into_owned();
+    let fragments = load_fragments(fragments, &includes, resolve)?;
+
+    let results: Vec<_> = patterns
+        .into_par_iter()
+        .map(|pattern| {
+            let vars = manifest_vars
+                .iter()
+                .chain(&pattern.vars)
+                .chain(template_vars)
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect::<BTreeMap<_, _>>();
+            if let Some(name) = vars.keys().find(|name| fragments.contains_key(*name)) {
+                return Err(CompilerError::GenericError(format!(
+                    "\"{}\" of pattern \"{}\" is both a fragment and a variable",
+                    name, pattern.name
+                )));
+            }
+            let mut regex_and_dfa = match (&pattern.decomposed_regex_path, &pattern.raw_regex) {
+                (Some(path), None) => {
+                    let path = resolve(path);
+                    let mut decomposed_regex_config = parse_config(&read_source(&path)?, &path)?;
+                    expand_config_fragments(&mut decomposed_regex_config, &fragments)?;
+                    translate_config_dialect(
+                        &mut decomposed_regex_config,
+                        dialect,
+                        permissive_escapes,
+                    )?;
+                    substitute_config_vars(&mut decomposed_regex_config, &vars)?;
+                    if lenient_newlines {
+                        relax_config_line_endings(&mut decomposed_regex_config);
+                    }
+                    get_regex_and_dfa(&mut decomposed_regex_config)?
+                }
+                (None, Some(raw_regex)) => {
+                    let substrs_json_path = pattern.substrs_json_path.as_deref().map(resolve);
+                    let substrs_defs_json =
+                        load_substring_definitions_json(substrs_json_path.as_deref())?;
+                    let raw_regex = expand_fragments(raw_regex, &fragments)?;
+                    let mut raw_regex = substitute_vars(
+                        &translate_dialect(&raw_regex, dialect, permissive_escapes)?,
+                        &vars,
+                    )?;
+                    if lenient_newlines {
+                        raw_regex = relax_line_endings(&raw_regex);
+                    }
+                    create_regex_and_dfa_from_str_and_defs(&raw_regex, substrs_defs_json)?
+                }
+                _ => {
+                    return Err(CompilerError::GenericError(format!(
+                        "pattern \"{}\" needs exactly one of decomposed_regex_path and raw_regex",
+                        pattern.name
+                    )))
+                }
+            };
+            apply_substr_selection(&mut regex_and_dfa, gen_substrs)?;
+            Ok((pattern.name, regex_and_dfa))
+        })
+        .collect();
+    // Report the error of the first failing pattern, as a sequential build would.
+    results.into_iter().collect()
+}
+
+/// Collects the fragments of a manifest and of the files it includes.
+///
+/// # Arguments
+///
+/// * `fragments` - The fragments defined in the manifest.
+/// * `includes` - The paths of the JSON files mapping more fragment names to their regexes.
+/// * `resolve` - Resolves a path against the directory of the manifest.
+///
+/// # Returns
+///
+/// A `Result` containing the fragments keyed by name, or a `CompilerError` if a file cannot be
+/// read or a fragment is defined more than once.
+#[cfg(feature = "fs")]
+fn load_fragments(
+    fragments: BTreeMap<String, String>,
+    includes: &[String],
+    resolve: impl Fn(&str) -> String,
+) -> Result<BTreeMap<String, String>, CompilerError> {
+    let mut sources = includes
+        .iter()
+        .map(|include| {
+            let path = resolve(include);
+            parse_config::<BTreeMap<String, String>>(&read_source(&path)?, &path)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    sources.push(fragments);
+    let mut all = BTreeMap::new();
+    for (name, fragment) in sources.into_iter().flatten() {
+        if all.insert(name.clone(), fragment).is_some() {
+            return Err(CompilerError::GenericError(format!(
+                "fragment \"{}\" is defined more than once",
+                name
+            )));
+        }
+    }
+    Ok(all)
+}
+
+/// Generates a single Noir library for all the patterns listed in a manifest file.
+///
+/// The patterns share one `next_state` function in which common sub-automata appear only once.
+///
+/// # Arguments
+///
+/// * `manifest_path` - The path to the JSON manifest listing the patterns.
+/// * `noir_file_path` - The path to the Noir output file.
+/// * `options` - The options of the compilation, of which the substrings to extract from every
+///   pattern are none by default and the `{{name}}` variables override those given in the
+///   manifest.
+///
+/// # Returns
+///
+/// A `Result` indicating success or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn gen_from_manifest(
+    manifest_path: &str,
+    noir_file_path: &str,
+    options: &CompileOptions,
+) -> Result<(), CompilerError> {
+    let noir_options = &options.noir.clone().unwrap_or_default();
+    let gen_substrs = options.gen_substrs.clone().unwrap_or(SubstrSelection::Off);
+    let patterns = load_manifest_patterns(
+        manifest_path,
+        &gen_substrs,
+        &options.template_vars,
+        options.lenient_newlines,
+        options.dialect,
+        options.permissive_escapes,
+    )?;
+
+    timed("Noir codegen", || {
+        gen_noir_shared_fn(
+            &patterns,
+            &PathBuf::from(noir_file_path),
+            gen_substrs != SubstrSelection::Off,
+            noir_options,
+        )
+    })
+}
+
+/// Generates a Noir library asserting that none of the forbidden patterns occurs anywhere in
+/// the input.
+///
+/// The patterns are combined into a single automaton searching for all of them at once, whose
+/// complement is checked byte by byte by `assert_no_match`.
+///
+/// # Arguments
+///
+/// * `patterns` - The forbidden raw regexes, without `^` or `$` anchors.
+/// * `noir_file_path` - The path to the Noir output file.
+/// * `noir_options` - The options controlling the generated Noir code.
+/// * `template_vars` - The values of the `{{name}}` variables used in the patterns.
+///
+/// # Returns
+///
+/// A `Result` indicating success or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn gen_denylist(
+    patterns: &[String],
+    noir_file_path: &str,
+    noir_options: &NoirOptions,
+    template_vars: &BTreeMap<String, String>,
+) -> Result<(), CompilerError> {
+    if patterns.is_empty() {
+        return Err(CompilerError::GenericError(
+            "the denylist needs at least one pattern".to_string(),
+        ));
+    }
+    let patterns = patterns
+        .iter()
+        .map(|pattern| {
+            substitute_vars(pattern, template_vars).map(|regex| absolute_anchors(&regex))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut dfas = vec![];
+    for pattern in &patterns {
+        let (start_anchored, _, end_anchored) = split_anchors(pattern);
+        if start_anchored || end_anchored {
+            return Err(CompilerError::GenericError(format!(
+                "forbidden pattern \"{}\" is anchored, but forbidden patterns are searched anywhere in the input",
+                pattern
+            )));
+        }
+        dfas.push(create_dfa_graph_from_regex(pattern)?);
+    }
+
+    timed("Noir codegen", || {
+        gen_noir_denylist_fn(
+            &patterns,
+            &dfas,
+            &PathBuf::from(noir_file_path),
+            noir_options,
+        )
+    })
+}
+
+/// Generates a Noir library matching a composition of several patterns with a single
+/// automaton, which is cheaper than running a matcher per pattern over the same input.
+///
+/// A pattern is satisfied when it matches somewhere in the input, or at its start or end when
+/// anchored with `^` or `$`.
+/// A difference takes two patterns and rejects any input in which the second one is satisfied.
+/// A sequence requires the patterns to match in order, each after the end of the previous one.
+///
+/// # Arguments
+///
+/// * `composition` - How the patterns are combined.
+/// * `patterns` - The raw regexes to combine.
+/// * `noir_file_path` - The path to the Noir output file.
+/// * `noir_options` - The options controlling the generated Noir code.
+/// * `template_vars` - The values of the `{{name}}` variables used in the patterns.
+///
+/// # Returns
+///
+/// A `Result` indicating success or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn gen_from_composition(
+    composition: PatternComposition,
+    patterns: &[String],
+    noir_file_path: &str,
+    noir_options: &NoirOptions,
+    template_vars: &BTreeMap<String, String>,
+) -> Result<(), CompilerError> {
+    if patterns.is_empty() {
+        return Err(CompilerError::GenericError(
+            "a composition needs at least one pattern".to_string(),
+        ));
+    }
+    if composition == PatternComposition::Difference && patterns.len() != 2 {
+        return Err(CompilerError::GenericError(
+            "a difference composition needs exactly two patterns".to_string(),
+        ));
+    }
+    let regexes = patterns
+        .iter()
+        .map(|pattern| {
+            substitute_vars(pattern, template_vars).map(|regex| absolute_anchors(&regex))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut composed = vec![];
+    for (idx, regex) in regexes.iter().enumerate() {
+        let (start_anchored, body, end_anchored) = split_anchors(regex);
+        if composition == PatternComposition::Sequence
+            && ((start_anchored && idx > 0) || (end_anchored && idx + 1 < regexes.len()))
+        {
+            return Err(CompilerError::GenericError(format!(
+                "only the first pattern of a sequence may start with ^ and only the last may end \
+                 with $, unlike {regex}"
+            )));
+        }
+        composed.push(ComposedPattern {
+            dfa: create_dfa_graph_from_regex(body)?,
+            start_anchored,
+            end_anchored,
+        });
+    }
+
+    timed("Noir codegen", || {
+        gen_noir_composed_fn(
+            composition,
+            &regexes,
+            &composed,
+            &PathBuf::from(noir_file_path),
+            noir_options,
+        )
+    })
+}
+
+/// Generates a Noir library extracting the fields of a structured body, such as a `key=value`
+/// list or a JSON object, into a named struct.
+///
+/// The fields are compiled to a decomposed regex with a public part per value, whose matcher
+/// is followed by the struct and `regex_match_fields`, returning the fields of an input.
+///
+/// # Arguments
+///
+/// * `fields_path` - The path to the fields config, in JSON, JSON5 or YAML.
+/// * `noir_file_path` - The path to the Noir output file.
+/// * `decomposed_regex_path` - The path the generated decomposed regex is written to, if any.
+/// * `noir_options` - The options controlling the generated Noir code.
+///
+/// # Returns
+///
+/// A `Result` indicating success or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn gen_from_fields(
+    fields_path: &str,
+    noir_file_path: &str,
+    decomposed_regex_path: Option<&str>,
+    noir_options: &NoirOptions,
+) -> Result<(), CompilerError> {
+    info!(path = fields_path, "compiling fields");
+    let source = read_source(fields_path)?;
+    let config: FieldsConfig = parse_config(&source, fields_path)?;
+    let mut decomposed_regex_config = fields_to_decomposed(&config)?;
+    if let Some(path) = decomposed_regex_path {
+        write(
+            path,
+            serde_json::to_string_pretty(&decomposed_regex_config)?,
+        )?;
+    }
+    let regex_and_dfa = get_regex_and_dfa(&mut decomposed_regex_config)?;
+    timed("Noir codegen", || {
+        gen_noir_fields_fn(
+            &regex_and_dfa,
+            &config,
+            &PathBuf::from(noir_file_path),
+            noir_options,
+        )
+    })
+}
+
+/// Checks every pattern listed in a manifest file with `nargo test`.
+///
+/// Each pattern is written to its own Nargo project under a temporary directory, together with
+/// a test running the Noir matcher on a sample input the pattern accepts. The projects of the
+/// patterns whose tests pass are removed, the others are kept for inspection.
+///
+/// # Arguments
+///
+/// * `manifest_path` - The path to the JSON manifest listing the patterns.
+/// * `noir_options` - The options controlling the generated Noir matchers.
+/// * `gen_substrs` - The substrings to extract from every pattern, all by default.
+/// * `template_vars` - The values of the `{{name}}` variables used in the patterns, overriding
+///   those given in the manifest.
+/// * `lenient_newlines` - Whether every `\r\n` of the patterns also matches a bare `\n`.
+/// * `dialect` - The regex syntax the patterns are written in.
+/// * `permissive_escapes` - Whether unknown escapes such as `\q` match the escaped character
+///   instead of failing.
+///
+/// # Returns
+///
+/// A `Result` containing the outcome for each pattern, or a `CompilerError` if a pattern cannot
+/// be compiled or `nargo` cannot be run.
+#[cfg(feature = "process")]
+pub fn test_from_manifest(
+    manifest_path: &str,
+    noir_options: &NoirOptions,
+    gen_substrs: Option<SubstrSelection>,
+    template_vars: &BTreeMap<String, String>,
+    lenient_newlines: bool,
+    dialect: Dialect,
+    permissive_escapes: bool,
+) -> Result<Vec<PatternTestResult>, CompilerError> {
+    let selection = gen_substrs.unwrap_or(SubstrSelection::All);
+    let patterns = load_manifest_patterns(
+        manifest_path,
+        &selection,
+        template_vars,
+        lenient_newlines,
+        dialect,
+        permissive_escapes,
+    )?;
+    let mut root = ScratchDir::new("zk_regex_test")?;
+
+    let mut results = vec![];
+    for (name, regex_and_dfa) in patterns {
+        let project_dir = root.path().join(&name);
+        timed("Noir codegen", || {
+            gen_noir_example(
+                &regex_and_dfa,
+                &project_dir,
+                selection != SubstrSelection::Off,
+                noir_options,
+            )
+        })?;
+        let output = Command::new("nargo")
+            .arg("test")
+            .current_dir(&project_dir)
+            .output()
+            .map_err(|e| CompilerError::GenericError(format!("failed to run nargo test: {}", e)))?;
+        let passed = output.status.success();
+        if passed {
+            remove_dir_all(&project_dir)?;
+        }
+        results.push(PatternTestResult {
+            name,
+            passed,
+            project_dir,
+            output: format!(
+                "{}{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        });
+    }
+    // The projects of the failed patterns are kept for inspection.
+    if !results.iter().all(|result| result.passed) {
+        root.keep();
+    }
+    Ok(results)
+}
+
+/// Generates the Nargo library of the helpers shared by the Noir matchers, such as
+/// `capture_to_bytes`, which the matchers generated with `NoirOptions::runtime` import instead
+/// of carrying a copy each.
+///
+/// The library is written to `Nargo.toml` and `src/lib.nr` in the project directory, replacing
+/// an earlier version. It is named after `NoirOptions::runtime`, or after the directory if not
+/// set, and depends on `NoirOptions::dependencies`, e.g. the library of an imported `sha256`.
+///
+/// # Arguments
+///
+/// * `project_dir` - The directory of the library, created if it does not exist.
+/// * `noir_options` - The imports, dependencies and name of the library.
+///
+/// # Returns
+///
+/// A `Result` containing the name of the library, or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn gen_noir_runtime(
+    project_dir: &str,
+    noir_options: &NoirOptions,
+) -> Result<String, CompilerError> {
+    let project_dir = Path::new(project_dir);
+    let name = match &noir_options.runtime {
+        Some(name) => name.clone(),
+        None => nargo_package_name(project_dir).unwrap_or_else(|| "zk_regex_runtime".to_string()),
+    };
+    if !(name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+    {
+        return Err(CompilerError::GenericError(format!(
+            "runtime library name \"{}\" is not a valid Nargo package name",
+            name
+        )));
+    }
+    info!(path = %project_dir.display(), name = %name, "generating the Noir runtime library");
+    let src_dir = project_dir.join("src");
+    create_dir_all(&src_dir)?;
+    let mut nargo_toml = format!(
+        "[package]\nname = \"{name}\"\ntype = \"lib\"\nauthors = [\"\"]\n\n[dependencies]\n"
+    );
+    for (dependency, value) in &noir_options.dependencies {
+        nargo_toml += &format!("{dependency} = {value}\n");
+    }
+    std::fs::write(project_dir.join("Nargo.toml"), nargo_toml)?;
+    std::fs::write(
+        src_dir.join("lib.nr"),
+        gen_runtime_lib(&noir_options.imports),
+    )?;
+    Ok(name)
+}
+
+/// Creates a Nargo project using a Noir matcher generated by the compiler, as a starting point
+/// for new users.
+///
+/// The project holds a manifest listing a sample pattern, the matcher generated from it in
+/// `src/regex.nr`, a main circuit revealing the capture of the pattern, tests of the circuit,
+/// and a `Prover.toml` with an input it accepts.
+///
+/// # Arguments
+///
+/// * `project_dir` - The directory of the project, which must not exist or be empty.
+///
+/// # Returns
+///
+/// A `Result` indicating success or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn init_project(project_dir: &str) -> Result<(), CompilerError> {
+    let project_dir = Path::new(project_dir);
+    if project_dir.exists() && project_dir.read_dir()?.next().is_some() {
+        return Err(CompilerError::GenericError(format!(
+            "{} already exists and is not empty",
+            project_dir.display()
+        )));
+    }
+    info!(path = %project_dir.display(), "creating a Nargo project");
+    let src_dir = project_dir.join("src");
+    create_dir_all(&src_dir)?;
+    let name = nargo_package_name(project_dir).unwrap_or_else(|| "regex_project".to_string());
+    for (path, contents) in [
+        (project_dir.join("Nargo.toml"), scaffold::nargo_toml(&name)),
+        (
+            project_dir.join("Prover.toml"),
+            scaffold::PROVER.to_string(),
+        ),
+        (
+            project_dir.join("patterns.json"),
+            scaffold::MANIFEST.to_string(),
+        ),
+        (
+            project_dir.join("greeting.json"),
+            scaffold::GREETING.to_string(),
+        ),
+        (src_dir.join("main.nr"), scaffold::MAIN.to_string()),
+    ] {
+        std::fs::write(path, contents)?;
+    }
+
+    gen_from_manifest(
+        &project_dir.join("patterns.json").to_string_lossy(),
+        &src_dir.join("regex.nr").to_string_lossy(),
+        &CompileOptions {
+            gen_substrs: Some(SubstrSelection::All),
+            ..Default::default()
+        },
+    )
+}
+
+/// Migrates a Circom zk-regex project to a Noir pattern manifest and the library generated from
+/// it.
+///
+/// Every decomposed regex config found under `circom_dir` is normalized and written to
+/// `out_dir` as `<name>.json`, next to a `patterns.json` manifest listing them and the
+/// `regex.nr` library generated from the manifest with every substring. The Circom template
+/// generated from each config, if found, is read to flag the outputs whose semantics differ in
+/// the Noir matcher. Configs that do not compile are skipped and reported.
+///
+/// # Arguments
+///
+/// * `circom_dir` - The root directory of the Circom project.
+/// * `out_dir` - The directory the manifest, configs and Noir library are written to.
+/// * `noir_options` - The options of the generated Noir library.
+///
+/// # Returns
+///
+/// A `Result` containing the `MigrationReport` of the migrated patterns or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn migrate_circom_project(
+    circom_dir: &str,
+    out_dir: &str,
+    noir_options: &NoirOptions,
+) -> Result<MigrationReport, CompilerError> {
+    let circom_dir = Path::new(circom_dir);
+    let out_dir = Path::new(out_dir);
+    let files = project_files(circom_dir, out_dir)?;
+    let mut report = MigrationReport::default();
+    let mut configs = Vec::new();
+    for path in files
+        .iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+    {
+        let json = std::fs::read_to_string(path)?;
+        match parse_circom_config(path, &json) {
+            Some(Ok(config)) => match get_regex_and_dfa(&mut config.config.clone()) {
+                Ok(_) => configs.push(config),
+                Err(e) => report.skipped.push((path.clone(), e.to_string())),
+            },
+            Some(Err(e)) => report.skipped.push((path.clone(), e)),
+            None => {}
+        }
+    }
+    if configs.is_empty() {
+        return Err(CompilerError::GenericError(format!(
+            "no decomposed regex config to migrate was found in {}",
+            circom_dir.display()
+        )));
+    }
+
+    create_dir_all(out_dir)?;
+    let names = pattern_names(&configs.iter().map(|c| c.path.as_path()).collect::<Vec<_>>());
+    let mut manifest_patterns = Vec::new();
+    for (name, config) in names.into_iter().zip(configs) {
+        info!(path = %config.path.display(), pattern = %name, "migrating a Circom config");
+        let template = match find_circom_template(&config.path, &files) {
+            Some(path) => parse_circom_template(&std::fs::read_to_string(path)?),
+            None => None,
+        };
+        let messages = config.changes.into_iter().chain(semantic_differences(
+            &name,
+            &config.config,
+            template.as_ref(),
+        ));
+        report.notes.extend(messages.map(|message| MigrationNote {
+            pattern: name.clone(),
+            message,
+        }));
+        let config_file = format!("{}.json", name);
+        write(
+            out_dir.join(&config_file),
+            serde_json::to_string_pretty(&config.config)?,
+        )?;
+        manifest_patterns.push(serde_json::json!({
+            "name": name,
+            "decomposed_regex_path": config_file,
+        }));
+        report.patterns.push(name);
+    }
+    let manifest_path = out_dir.join("patterns.json");
+    write(
+        &manifest_path,
+        serde_json::to_string_pretty(&serde_json::json!({ "patterns": manifest_patterns }))?,
+    )?;
+
+    gen_from_manifest(
+        &manifest_path.to_string_lossy(),
+        &out_dir.join("regex.nr").to_string_lossy(),
+        &CompileOptions {
+            noir: Some(noir_options.clone()),
+            gen_substrs: Some(SubstrSelection::All),
+            ..Default::default()
+        },
+    )?;
+    Ok(report)
+}
+
+/// Runs a command in a directory, failing with its output if it does not succeed.
+///
+/// # Arguments
+///
+/// * `program` - The program to run, looked up in `PATH`.
+/// * `args` - The arguments of the program.
+/// * `dir` - The working directory of the command.
+///
+/// # Returns
+///
+/// A `Result` containing the standard output of the command, or a `CompilerError` holding its
+/// output if it fails.
+#[cfg(feature = "process")]
+fn run_tool(program: &str, args: &[&str], dir: &Path) -> Result<String, CompilerError> {
+    let command = format!("{} {}", program, args.join(" "));
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| CompilerError::GenericError(format!("failed to run {}: {}", command, e)))?;
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => Err(CompilerError::GenericError(format!(
+            "{} failed:\n{}{}",
+            command,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ))),
+    }
+}
+
+/// Runs a command in a directory as `run_tool` does, timing it and sampling its peak memory.
+///
+/// The output of the command goes to `target/measure.log` in the directory, which is quoted
+/// if it fails. The peak memory is read from `/proc` every 10 ms while the command runs.
+///
+/// # Arguments
+///
+/// * `program` - The program to run, looked up in `PATH`.
+/// * `args` - The arguments of the program.
+/// * `dir` - The working directory of the command.
+///
+/// # Returns
+///
+/// A `Result` containing the time the command took and its peak resident memory in kilobytes
+/// if the platform reports it, or a `CompilerError` holding its output if it fails.
+#[cfg(feature = "process")]
+fn run_measured(
+    program: &str,
+    args: &[&str],
+    dir: &Path,
+) -> Result<(Duration, Option<u64>), CompilerError> {
+    let command = format!("{} {}", program, args.join(" "));
+    let log_path = dir.join("target").join("measure.log");
+    create_dir_all(dir.join("target"))?;
+    let log = File::create(&log_path)?;
+    let start = Instant::now();
+    let mut child = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .spawn()
+        .map_err(|e| CompilerError::GenericError(format!("failed to run {}: {}", command, e)))?;
+    let mut peak_memory_kb = None;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        peak_memory_kb = child_peak_memory_kb(child.id()).max(peak_memory_kb);
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let time = start.elapsed();
+    match status.success() {
+        true => Ok((time, peak_memory_kb)),
+        false => Err(CompilerError::GenericError(format!(
+            "{} failed:\n{}",
+            command,
+            std::fs::read_to_string(&log_path).unwrap_or_default()
+        ))),
+    }
+}
+
+/// Generates a Nargo project calling the Noir matcher on an input, executes it, proves it with
+/// `bb` and verifies the proof.
+///
+/// Every step is timed, so that `finish_profiling` reports the proving time.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - The `RegexAndDFA` struct containing the regex pattern and DFA.
+/// * `gen_substrs` - A boolean indicating whether to generate substrings.
+/// * `input` - The input proven to match the regex.
+/// * `project_dir` - The directory of the Nargo project.
+/// * `noir_options` - The options controlling the generated Noir matcher.
+///
+/// # Returns
+///
+/// A `Result` containing the path to the proof, or a `CompilerError`.
+#[cfg(feature = "process")]
+fn prove(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    input: &str,
+    project_dir: &Path,
+    noir_options: &NoirOptions,
+) -> Result<PathBuf, CompilerError> {
+    let name = timed("Noir codegen", || {
+        gen_noir_project(
+            regex_and_dfa,
+            project_dir,
+            gen_substrs,
+            noir_options,
+            Some(input),
+        )
+    })?;
+    let circuit = format!("./target/{}.json", name);
+    let witness = format!("./target/{}.gz", name);
+    timed("nargo execute", || {
+        run_tool("nargo", &["execute"], project_dir)
+    })?;
+    timed("bb prove", || {
+        run_tool(
+            "bb",
+            &[
+                "prove",
+                "-b",
+                &circuit,
+                "-w",
+                &witness,
+                "-o",
+                "./target/proof",
+            ],
+            project_dir,
+        )
+    })?;
+    timed("bb write_vk", || {
+        run_tool(
+            "bb",
+            &["write_vk", "-b", &circuit, "-o", "./target/vk"],
+            project_dir,
+        )
+    })?;
+    timed("bb verify", || {
+        run_tool(
+            "bb",
+            &["verify", "-k", "./target/vk", "-p", "./target/proof"],
+            project_dir,
+        )
+    })?;
+    Ok(project_dir.join("target").join("proof"))
+}
+
+/// Proves that an input matches a decomposed regex, see `prove_from_raw`.
+///
+/// # Arguments
+///
+/// * `decomposed_regex_path` - The path to the decomposed regex configuration file, or `-` to read
+///   it from the standard input.
+/// * `input` - The input proven to match the regex.
+/// * `project_dir` - The directory of the Nargo project, created if it does not exist.
+/// * `noir_options` - The options controlling the generated Noir matcher.
+/// * `gen_substrs` - The substrings to extract, none by default.
+/// * `template_vars` - The values of the `{{name}}` variables used in the regex parts.
+///
+/// # Returns
+///
+/// A `Result` containing the path to the proof, or a `CompilerError`.
+#[cfg(feature = "process")]
+pub fn prove_from_decomposed(
+    decomposed_regex_path: &str,
+    input: &str,
+    project_dir: &str,
+    noir_options: &NoirOptions,
+    gen_substrs: Option<SubstrSelection>,
+    template_vars: &BTreeMap<String, String>,
+) -> Result<PathBuf, CompilerError> {
+    let source = read_source(decomposed_regex_path)?;
+    let mut decomposed_regex_config = parse_config(&source, decomposed_regex_path)?;
+    substitute_config_vars(&mut decomposed_regex_config, template_vars)?;
+
+    let mut regex_and_dfa = get_regex_and_dfa(&mut decomposed_regex_config)?;
+    let gen_substrs = apply_substr_selection(
+        &mut regex_and_dfa,
+        &gen_substrs.unwrap_or(SubstrSelection::Off),
+    )?;
+    prove(
+        &regex_and_dfa,
+        gen_substrs,
+        input,
+        Path::new(project_dir),
+        noir_options,
+    )
+}
+
+/// Proves that an input matches a raw regex.
+///
+/// The Noir matcher is written to a Nargo project calling it on the input, which is run with
+/// `nargo execute`, proven with `bb prove` and verified with `bb verify`. Both tools must be
+/// installed. The time of each step is recorded for `finish_profiling`.
+///
+/// # Arguments
+///
+/// * `raw_regex` - The raw regex string.
+/// * `substrs_json_path` - An optional path to the JSON file containing substring definitions.
+/// * `input` - The input proven to match the regex.
+/// * `project_dir` - The directory of the Nargo project, created if it does not exist.
+/// * `noir_options` - The options controlling the generated Noir matcher.
+/// * `gen_substrs` - The substrings to extract, all by default.
+/// * `template_vars` - The values of the `{{name}}` variables used in the regex.
+///
+/// # Returns
+///
+/// A `Result` containing the path to the proof, or a `CompilerError`.
+#[cfg(feature = "process")]
+pub fn prove_from_raw(
+    raw_regex: &str,
+    substrs_json_path: Option<&str>,
+    input: &str,
+    project_dir: &str,
+    noir_options: &NoirOptions,
+    gen_substrs: Option<SubstrSelection>,
+    template_vars: &BTreeMap<String, String>,
+) -> Result<PathBuf, CompilerError> {
+    let substrs_defs_json = load_substring_definitions_json(substrs_json_path)?;
+
+    let raw_regex = substitute_vars(raw_regex, template_vars)?;
+    let mut regex_and_dfa = create_regex_and_dfa_from_str_and_defs(&raw_regex, substrs_defs_json)?;
+    let gen_substrs = apply_substr_selection(
+        &mut regex_and_dfa,
+        &gen_substrs.unwrap_or(SubstrSelection::All),
+    )?;
+    prove(
+        &regex_and_dfa,
+        gen_substrs,
+        input,
+        Path::new(project_dir),
+        noir_options,
+    )
+}
+
+/// Generates the artifacts requested by the options from a RegexAndDFA.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - The `RegexAndDFA` struct containing the regex pattern and DFA.
+/// * `options` - The options choosing the artifacts to generate.
+/// * `gen_substrs` - A boolean indicating whether to generate substrings.
+///
+/// # Returns
+///
+/// A `Result` containing the generated artifacts or a `CompilerError`.
+fn compile(
+    regex_and_dfa: &RegexAndDFA,
+    options: &CompileOptions,
+    gen_substrs: bool,
+) -> Result<CompiledRegex, CompilerError> {
+    if let (Some(noir_options), Some(gate_budget)) = (&options.noir, &options.gate_budget) {
+        check_gate_budget(regex_and_dfa, gen_substrs, noir_options, gate_budget)?;
+    }
+    let circom = match &options.circom_template_name {
+        Some(template_name) => Some(timed("Circom codegen", || {
+            gen_circom_source(regex_and_dfa, template_name, gen_substrs)
+        })?),
+        None => None,
+    };
+    let halo2 = match options.halo2 {
+        true => Some(timed("Halo2 codegen", || {
+            gen_halo2_texts(regex_and_dfa, gen_substrs)
+        })?),
+        false => None,
+    };
+    let (noir, noir_modules) = match &options.noir {
+        Some(noir_options) => {
+            let mut sources = timed("Noir codegen", || {
+                gen_noir_sources(regex_and_dfa, gen_substrs, noir_options)
+            })?;
+            let (_, main) = sources.remove(0);
+            (Some(main), sources)
+        }
+        None => (None, vec![]),
+    };
+    let automaton = match options.automaton {
+        true => Some(compiled_automaton(
+            regex_and_dfa,
+            &options.noir.clone().unwrap_or_default(),
+            gen_substrs,
+        )?),
+        false => None,
+    };
+
+    Ok(CompiledRegex {
+        regex_pattern: regex_and_dfa.regex_pattern.clone(),
+        circom,
+        halo2,
+        noir,
+        noir_modules,
+        automaton,
+    })
+}
+
+/// Compiles a raw regex into the artifacts requested by the options, without touching the
+/// filesystem.
+///
+/// # Arguments
+///
+/// * `raw_regex` - The raw regex string.
+/// * `options` - The options choosing the artifacts to generate, including the substring
+///   transitions and their names.
+///
+/// # Returns
+///
+/// A `Result` containing the generated artifacts or a `CompilerError`.
+pub fn compile_raw(
+    raw_regex: &str,
+    options: &CompileOptions,
+) -> Result<CompiledRegex, CompilerError> {
+    let (transitions, names) = match options.substr_transitions.is_empty() {
+        true => (vec![vec![]], vec![]),
+        false => (
+            options.substr_transitions.clone(),
+            options.substr_names.clone(),
+        ),
+    };
+    let mut raw_regex = substitute_vars(
+        &translate_dialect(raw_regex, options.dialect, options.permissive_escapes)?,
+        &options.template_vars,
+    )?;
+    if options.lenient_newlines {
+        raw_regex = relax_line_endings(&raw_regex);
+    }
+    let mut regex_and_dfa = raw_regex_and_dfa(
+        &raw_regex,
+        options.capture_groups,
+        SubstringDefinitionsJson { transitions, names },
+        options
+            .noir
+            .as_ref()
+            .and_then(|noir| noir.repetition_counter),
+        options.halo2 || options.circom_template_name.is_some(),
+    )?;
+    let selection = options.gen_substrs.clone().unwrap_or(SubstrSelection::All);
+    let gen_substrs = apply_substr_selection(&mut regex_and_dfa, &selection)?;
+    compile(&regex_and_dfa, options, gen_substrs)
+}
+
+/// Compares the automata of two decomposed regex files, as the circuits generated from them
+/// would differ.
+///
+/// # Arguments
+///
+/// * `old_path` - The path to the decomposed regex JSON file of the old version.
+/// * `new_path` - The path to the decomposed regex JSON file of the new version.
+/// * `template_vars` - The values of the `{{NAME}}` variables of the patterns.
+///
+/// # Returns
+///
+/// A `Result` containing the `AutomatonDiff` of the two automata or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn diff_decomposed(
+    old_path: &str,
+    new_path: &str,
+    template_vars: &BTreeMap<String, String>,
+) -> Result<AutomatonDiff, CompilerError> {
+    Ok(diff_automata(
+        &load_decomposed(old_path, template_vars)?,
+        &load_decomposed(new_path, template_vars)?,
+    ))
+}
+
+/// Decides whether the Noir matchers of two patterns accept the same inputs, so that a
+/// refactored pattern can be checked to keep the semantics of its circuit.
+///
+/// The matchers search the input for a match, so patterns with different DFAs may still be
+/// equivalent, e.g. `ab` and `(a|x)*ab`, and `^ab` is told apart from `ab` by `aab`.
+///
+/// # Arguments
+///
+/// * `a` - The first pattern: a decomposed regex file, or a raw regex if `raw` is set.
+/// * `b` - The second pattern, given like the first one.
+/// * `raw` - Whether the patterns are raw regexes rather than decomposed regex files.
+/// * `template_vars` - The values of the `{{NAME}}` variables of the patterns.
+///
+/// # Returns
+///
+/// A `Result` containing `None` if the patterns are equivalent, and otherwise a shortest input
+/// accepted by only one of them with whether it is the first one, or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn check_equivalence(
+    a: &str,
+    b: &str,
+    raw: bool,
+    template_vars: &BTreeMap<String, String>,
+) -> Result<Option<(Vec<u8>, bool)>, CompilerError> {
+    let load = |pattern: &str| match raw {
+        true => algebra::from_regex(&substitute_vars(pattern, template_vars)?),
+        false => load_decomposed(pattern, template_vars),
+    };
+    let (a, b) = (load(a)?, load(b)?);
+    Ok(timed("equivalence", || {
+        distinguishing_matcher_input(&a, &b)
+    }))
+}
+
+/// Samples random inputs accepted by a pattern, to be used as test vectors or prover inputs,
+/// or inputs that almost match it, to be used as negative tests.
+///
+/// # Arguments
+///
+/// * `pattern` - A decomposed regex file, or a raw regex if `raw` is set.
+/// * `raw` - Whether the pattern is a raw regex rather than a decomposed regex file.
+/// * `options` - The number, lengths and seed of the samples, and whether they are near misses.
+/// * `template_vars` - The values of the `{{NAME}}` variables of the pattern.
+///
+/// # Returns
+///
+/// A `Result` containing the sampled inputs or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn gen_samples(
+    pattern: &str,
+    raw: bool,
+    options: &SampleOptions,
+    template_vars: &BTreeMap<String, String>,
+) -> Result<Vec<Vec<u8>>, CompilerError> {
+    let regex_and_dfa = match raw {
+        true => algebra::from_regex(&substitute_vars(pattern, template_vars)?)?,
+        false => load_decomposed(pattern, template_vars)?,
+    };
+    timed("sampling", || match options.near_misses {
+        true => sample_near_misses(&regex_and_dfa, options),
+        false => sample_inputs(&regex_and_dfa, options),
+    })
+}
+
+/// Describes the automaton of a pattern in plain text: what each state stands for, which
+/// transitions feed each substring and where the matchers reset, so that the circuits
+/// generated from it can be reviewed by someone who did not write the pattern.
+///
+/// # Arguments
+///
+/// * `pattern` - A decomposed regex file, or a raw regex if `raw` is set.
+/// * `raw` - Whether the pattern is a raw regex rather than a decomposed regex file.
+/// * `capture_groups` - Whether the capture groups of a raw regex are its substrings.
+/// * `template_vars` - The values of the `{{NAME}}` variables of the pattern.
+///
+/// # Returns
+///
+/// A `Result` containing the description or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn explain_pattern(
+    pattern: &str,
+    raw: bool,
+    capture_groups: bool,
+    template_vars: &BTreeMap<String, String>,
+) -> Result<String, CompilerError> {
+    let regex_and_dfa = load_pattern(pattern, raw, capture_groups, template_vars)?;
+    Ok(explain_automaton(&regex_and_dfa))
+}
+
+/// Estimates the size of the Noir matcher of a pattern for several input lengths, with every
+/// layout of `next_state` the `auto` strategy chooses from, so that the largest input length a
+/// circuit can afford can be picked.
+///
+/// The substrings of the pattern are revealed, if it has any. The estimates add the `global`
+/// arrays the layouts read to the comparisons counted for a `GateBudget`, so they compare
+/// input lengths and strategies rather than predict the exact gate count of a backend.
+///
+/// # Arguments
+///
+/// * `pattern` - A decomposed regex file, or a raw regex if `raw` is set.
+/// * `raw` - Whether the pattern is a raw regex rather than a decomposed regex file.
+/// * `capture_groups` - Whether the capture groups of a raw regex are its substrings.
+/// * `input_lens` - The input lengths to estimate the matcher for, in code units, listed in
+///   increasing order without repeats.
+/// * `noir_options` - The options controlling the generated Noir matcher.
+/// * `template_vars` - The values of the `{{NAME}}` variables of the pattern.
+///
+/// # Returns
+///
+/// A `Result` containing the estimates, by input length then strategy, or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn bench_pattern(
+    pattern: &str,
+    raw: bool,
+    capture_groups: bool,
+    input_lens: &[usize],
+    noir_options: &NoirOptions,
+    template_vars: &BTreeMap<String, String>,
+) -> Result<Vec<SizeEstimate>, CompilerError> {
+    if input_lens.is_empty() || input_lens.contains(&0) {
+        return Err(CompilerError::GenericError(
+            "the input lengths to estimate must be positive".to_string(),
+        ));
+    }
+    let regex_and_dfa = load_pattern(pattern, raw, capture_groups, template_vars)?;
+    let gen_substrs = !regex_and_dfa.substrings.substring_ranges.is_empty();
+    let input_lens = input_lens.iter().copied().sorted().dedup().collect_vec();
+    Ok(timed("size estimation", || {
+        estimate_sizes(&regex_and_dfa, gen_substrs, noir_options, &input_lens)
+    }))
+}
+
+/// Estimates the time and memory `bb` takes to prove the Noir matcher of a pattern for several
+/// input lengths, so that pattern designs can be compared by proving cost rather than gates.
+///
+/// The circuit of every input length is sized as by `bench_pattern`, with the layout of
+/// `next_state` the options select, and padded to the next power of two as the backends do.
+/// The time and memory per row of every backend are rough figures, so the estimates compare
+/// patterns rather than predict a given machine; see `measure_proving` for actual costs.
+///
+/// # Arguments
+///
+/// * `pattern` - A decomposed regex file, or a raw regex if `raw` is set.
+/// * `raw` - Whether the pattern is a raw regex rather than a decomposed regex file.
+/// * `capture_groups` - Whether the capture groups of a raw regex are its substrings.
+/// * `input_lens` - The input lengths to estimate the cost for, in code units.
+/// * `backends` - The proving backends to estimate the cost with.
+/// * `noir_options` - The options controlling the generated Noir matcher.
+/// * `template_vars` - The values of the `{{NAME}}` variables of the pattern.
+///
+/// # Returns
+///
+/// A `Result` containing the estimates, by input length then backend, or a `CompilerError`.
+#[cfg(feature = "fs")]
+pub fn bench_proving(
+    pattern: &str,
+    raw: bool,
+    capture_groups: bool,
+    input_lens: &[usize],
+    backends: &[ProvingBackend],
+    noir_options: &NoirOptions,
+    template_vars: &BTreeMap<String, String>,
+) -> Result<Vec<ProvingCost>, CompilerError> {
+    if input_lens.is_empty() || input_lens.contains(&0) {
+        return Err(CompilerError::GenericError(
+            "the input lengths to estimate must be positive".to_string(),
+        ));
+    }
+    let regex_and_dfa = load_pattern(pattern, raw, capture_groups, template_vars)?;
+    let gen_substrs = !regex_and_dfa.substrings.substring_ranges.is_empty();
+    let input_lens = input_lens.iter().copied().sorted().dedup().collect_vec();
+    let backends = backends.iter().copied().sorted().dedup().collect_vec();
+    Ok(timed("size estimation", || {
+        estimate_proving_costs(
+            &regex_and_dfa,
+            gen_substrs,
+            noir_options,
+            &input_lens,
+            &backends,
+        )
+    }))
+}
+
+/// Measures the time and memory `bb` takes to prove the Noir matcher of a pattern for several
+/// input lengths, by proving it on an accepted input of every length.
+///
+/// For every input length, a Nargo project calling the matcher on a sample input padded to
+/// that length is written to a subdirectory of `project_dir` and run with `nargo execute`.
+/// Its gates are then counted with `bb gates` and it is proven with every backend, `bb prove`
+/// for UltraPlonk and `bb prove_ultra_honk` for UltraHonk, timing the proof and sampling the
+/// peak memory of `bb` where `/proc` reports it. Both tools must be installed. The samples of
+/// patterns anchored at the end of the input cannot be padded, so only their own length can be
+/// measured.
+///
+/// # Arguments
+///
+/// * `pattern` - A decomposed regex file, or a raw regex if `raw` is set.
+/// * `raw` - Whether the pattern is a raw regex rather than a decomposed regex file.
+/// * `input_lens` - The input lengths to measure the cost for, in code units.
+/// * `backends` - The proving backends to measure the cost with.
+/// * `project_dir` - The directory the Nargo projects are written to, created if it does not
+///   exist.
+/// * `options` - The options of the compilation, of which only the capture groups, the Noir
+///   options and the `{{NAME}}` variables are used.
+///
+/// # Returns
+///
+/// A `Result` containing the measured costs, by input length then backend, or a
+/// `CompilerError` if an input cannot be sampled or a tool fails.
+#[cfg(feature = "process")]
+pub fn measure_proving(
+    pattern: &str,
+    raw: bool,
+    input_lens: &[usize],
+    backends: &[ProvingBackend],
+    project_dir: &str,
+    options: &CompileOptions,
+) -> Result<Vec<ProvingCost>, CompilerError> {
+    if input_lens.is_empty() || input_lens.contains(&0) {
+        return Err(CompilerError::GenericError(
+            "the input lengths to measure must be positive".to_string(),
+        ));
+    }
+    let noir_options = &options.noir.clone().unwrap_or_default();
+    let regex_and_dfa = load_pattern(pattern, raw, options.capture_groups, &options.template_vars)?;
+    let gen_substrs = !regex_and_dfa.substrings.substring_ranges.is_empty();
+    let backends = backends.iter().copied().sorted().dedup().collect_vec();
+    let mut costs = vec![];
+    for input_len in input_lens.iter().copied().sorted().dedup() {
+        let input = sample_input_of_len(&regex_and_dfa, gen_substrs, noir_options, input_len)?;
+        let dir = Path::new(project_dir).join(format!("len_{}", input_len));
+        let name = gen_noir_project(
+            &regex_and_dfa,
+            &dir,
+            gen_substrs,
+            noir_options,
+            Some(&input),
+        )?;
+        run_tool("nargo", &["execute"], &dir)?;
+        let circuit = format!("./target/{}.json", name);
+        let witness = format!("./target/{}.gz", name);
+        let gates = count_gates(&run_tool("bb", &["gates", "-b", &circuit], &dir)?)?;
+        for &backend in &backends {
+            let prove = match backend {
+                ProvingBackend::UltraHonk => "prove_ultra_honk",
+                ProvingBackend::UltraPlonk => "prove",
+            };
+            let (time, peak_memory_kb) = run_measured(
+                "bb",
+                &[
+                    prove,
+                    "-b",
+                    &circuit,
+                    "-w",
+                    &witness,
+                    "-o",
+                    "./target/proof",
+                ],
+                &dir,
+            )?;
+            info!(input_len, %backend, gates, time_ms = time.as_millis() as u64, "proved the Noir matcher");
+            costs.push(ProvingCost {
+                input_len,
+                backend,
+                gates,
+                circuit_size: gates.max(1).next_power_of_two(),
+                prove_ms: time.as_millis() as u64,
+                peak_memory_kb,
+                measured: true,
+            });
+        }
+    }
+    Ok(costs)
+}
+
+/// Reads the number of gates of a circuit from the JSON output of `bb gates`.
+#[cfg(feature = "process")]
+fn count_gates(output: &str) -> Result<usize, CompilerError> {
+    let gates: serde_json::Value = serde_json::from_str(output)?;
+    gates["functions"]
+        .as_array()
+        .and_then(|functions| {
+            functions
+                .iter()
+                .map(|function| function["circuit_size"].as_u64())
+                .sum::<Option<u64>>()
+        })
+        .map(|gates| gates as usize)
+        .ok_or_else(|| {
+            CompilerError::GenericError(format!("unexpected output of bb gates: {}", output))
+        })
+}
+
+/// Checks the Noir matcher of a pattern against its simulation in Rust on sampled inputs, as a
+/// smoke test of the soundness of the generated code.
+///
+/// Inputs the pattern accepts and near misses it rejects are sampled as by `gen_samples`. The
+/// outcome of the matcher on each of them is simulated in Rust, then a Nargo project asserting
+/// that outcome is written under a temporary directory and run with `nargo execute`, which
+/// must be installed. A divergence is reported when one of them accepts the input and the other
+/// rejects it, or when they extract different substrings. The projects of the inputs both agree
+/// on are removed, the others are kept for inspection. The substrings of the pattern are
+/// extracted, if it has any.
+///
+/// # Arguments
+///
+/// * `pattern` - A decomposed regex file, or a raw regex if `raw` is set.
+/// * `raw` - Whether the pattern is a raw regex rather than a decomposed regex file.
+/// * `capture_groups` - Whether the capture groups of a raw regex are its substrings.
+/// * `options` - The number, lengths and seed of the samples, giving as many accepted inputs as
+///   near misses at most.
+/// * `noir_options` - The options controlling the generated Noir matcher, whose input type must
+///   be `u8`.
+/// * `template_vars` - The values of the `{{NAME}}` variables of the pattern.
+///
+/// # Returns
+///
+/// A `Result` containing the number of inputs checked and the divergences found, or a
+/// `CompilerError` if the pattern cannot be compiled or `nargo` cannot be run.
+#[cfg(feature = "process")]
+pub fn validate_pattern(
+    pattern: &str,
+    raw: bool,
+    capture_groups: bool,
+    options: &SampleOptions,
+    noir_options: &NoirOptions,
+    template_vars: &BTreeMap<String, String>,
+) -> Result<(usize, Vec<Divergence>), CompilerError> {
+    if noir_options.input_type != NoirInputType::U8 {
+        return Err(CompilerError::GenericError(
+            "only matchers of u8 input can be validated".to_string(),
+        ));
+    }
+    let regex_and_dfa = load_pattern(pattern, raw, capture_groups, template_vars)?;
+    let gen_substrs = !regex_and_dfa.substrings.substring_ranges.is_empty();
+    let inputs = timed("sampling", || -> Result<_, CompilerError> {
+        let mut inputs = sample_inputs(&regex_and_dfa, options)?;
+        inputs.extend(sample_near_misses(&regex_and_dfa, options)?);
+        Ok(inputs.into_iter().unique().collect_vec())
+    })?;
+    let mut root = ScratchDir::new("zk_regex_validate")?;
+
+    let mut divergences = vec![];
+    for (i, input) in inputs.iter().enumerate() {
+        let simulated = simulate_matcher(&regex_and_dfa, gen_substrs, noir_options, input);
+        let project_dir = root.path().join(format!("input_{i}"));
+        timed("Noir codegen", || {
+            gen_noir_check_project(
+                &regex_and_dfa,
+                &project_dir,
+                gen_substrs,
+                noir_options,
+                input,
+                simulated.as_deref(),
+            )
+        })?;
+        let output = timed("nargo execute", || {
+            Command::new("nargo")
+                .arg("execute")
+                .current_dir(&project_dir)
+                .output()
+        })
+        .map_err(|e| CompilerError::GenericError(format!("failed to run nargo execute: {}", e)))?;
+        let succeeded = output.status.success();
+        let output = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        if succeeded == simulated.is_some() {
+            remove_dir_all(&project_dir)?;
+            continue;
+        }
+        // A failed assertion on a substring means that the matcher accepted the input.
+        divergences.push(Divergence {
+            input: input.clone(),
+            simulated,
+            noir_accepts: succeeded || output.contains(" diverges"),
+            project_dir,
+            output,
+        });
+    }
+    // The projects of the divergent inputs are kept for inspection.
+    if !divergences.is_empty() {
+        root.keep();
+    }
+    Ok((inputs.len(), divergences))
+}
+
+/// Builds the automaton of a decomposed regex file, or of a raw regex if `raw` is set, whose
+/// capture groups are its substrings if `capture_groups` is set.
+#[cfg(feature = "fs")]
+fn load_pattern(
+    pattern: &str,
+    raw: bool,
+    capture_groups: bool,
+    template_vars: &BTreeMap<String, String>,
+) -> Result<RegexAndDFA, CompilerError> {
+    match (raw, capture_groups) {
+        (true, true) => create_regex_and_dfa_from_groups(&substitute_vars(pattern, template_vars)?),
+        (true, false) => algebra::from_regex(&substitute_vars(pattern, template_vars)?),
+        (false, _) => load_decomposed(pattern, template_vars),
+    }
+}
+
+/// Reads a decomposed regex file and builds its automaton.
+#[cfg(feature = "fs")]
+fn load_decomposed(
+    path: &str,
+    template_vars: &BTreeMap<String, String>,
+) -> Result<RegexAndDFA, CompilerError> {
+    let mut decomposed_regex_config = parse_config(&read_source(path)?, path)?;
+    substitute_config_vars(&mut decomposed_regex_config, template_vars)?;
+    get_regex_and_dfa(&mut decomposed_regex_config)
+}
+
+/// Compiles a decomposed regex given as JSON into the artifacts requested by the options,
+/// without touching the filesystem.
+///
+/// # Arguments
+///
+/// * `decomposed_regex_json` - The decomposed regex configuration as a JSON string.
+/// * `options` - The options choosing the artifacts to generate.
+///
+/// # Returns
+///
+/// A `Result` containing the generated artifacts or a `CompilerError`.
+pub fn compile_decomposed(
+    decomposed_regex_json: &str,
+    options: &CompileOptions,
+) -> Result<CompiledRegex, CompilerError> {
+    let mut decomposed_regex_config: DecomposedRegexConfig =
+        timed("parsing", || serde_json::from_str(decomposed_regex_json))?;
+    translate_config_dialect(
+        &mut decomposed_regex_config,
+        options.dialect,
+        options.permissive_escapes,
+    )?;
+    substitute_config_vars(&mut decomposed_regex_config, &options.template_vars)?;
+    if options.lenient_newlines {
+        relax_config_line_endings(&mut decomposed_regex_config);
+    }
+    let mut regex_and_dfa = get_regex_and_dfa(&mut decomposed_regex_config)?;
+    let selection = options.gen_substrs.clone().unwrap_or(SubstrSelection::Off);
+    let gen_substrs = apply_substr_selection(&mut regex_and_dfa, &selection)?;
+    compile(&regex_and_dfa, options, gen_substrs)
+}
+
+/// Compiles several patterns with the same options, in parallel on all the cores, e.g. the
+/// dozens of patterns of a large project.
+///
+/// # Arguments
+///
+/// * `patterns` - The raw regexes and decomposed regex JSON strings to compile.
+/// * `options` - The options choosing the artifacts to generate, as for `compile_raw` and
+///   `compile_decomposed`.
+///
+/// # Returns
+///
+/// The artifacts of every pattern or the `CompilerError` it failed with, in the order of the
+/// patterns.
+pub fn compile_batch(
+    patterns: &[PatternSource],
+    options: &CompileOptions,
+) -> Vec<Result<CompiledRegex, CompilerError>> {
+    patterns
+        .par_iter()
+        .map(|pattern| match pattern {
+            PatternSource::Raw(raw_regex) => compile_raw(raw_regex, options),
+            PatternSource::Decomposed(decomposed_regex_json) => {
+                compile_decomposed(decomposed_regex_json, options)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn test_read_pattern_drops_one_trailing_newline() {
+        let scratch = ScratchDir::new("zk_regex_read_pattern").unwrap();
+        let path = scratch.path().join("pattern.txt");
+        std::fs::write(&path, "from:[^\r\n]+ \n\n").unwrap();
+        assert_eq!(
+            read_pattern(path.to_str().unwrap()).unwrap(),
+            "from:[^\r\n]+ \n"
+        );
+
+        assert_eq!(strip_trailing_newline("a|b\r\n"), "a|b");
+        assert_eq!(strip_trailing_newline("a|b "), "a|b ");
+    }
+
+    #[test]
+    fn test_parse_decomposed_config_formats() {
+        let json = r#"{"parts": [{"is_public": false, "regex_def": "id:"}, {"is_public": true, "regex_def": "[0-9]+"}]}"#;
+        let json5 = r#"{
+            // The JSON5 form allows comments and trailing commas.
+            parts: [
+                { is_public: false, regex_def: 'id:' },
+                { is_public: true, regex_def: "[0-9]+", },
+            ],
+        }"#;
+        let yaml = "parts:\n  - is_public: false\n    regex_def: 'id:'\n  - is_public: true\n    regex_def: '[0-9]+'\n";
+        let regex_defs = |config: DecomposedRegexConfig| {
+            config
+                .parts
+                .iter()
+                .map(|part| (part.is_public, part.regex_def.clone()))
+                .collect_vec()
+        };
+        let expected = regex_defs(parse_config(json, "id.json").unwrap());
+        for (source, path) in [
+            (json5, "id.json5"),
+            (yaml, "id.yaml"),
+            (yaml, "id.YML"),
+            (json5, "-"),
+            (yaml, "-"),
+        ] {
+            assert_eq!(regex_defs(parse_config(source, path).unwrap()), expected);
+        }
+        let err = parse_config::<DecomposedRegexConfig>("parts: [", "id.yaml").unwrap_err();
+        assert!(err.to_string().contains("id.yaml (YAML)"));
+        assert!(matches!(
+            parse_config::<DecomposedRegexConfig>("{", "-"),
+            Err(CompilerError::JsonParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_gate_budget() {
+        let mut options = CompileOptions {
+            noir: Some(NoirOptions::default()),
+            gate_budget: Some(GateBudget {
+                max_gates: 1_000_000,
+                input_len: 1024,
+            }),
+            ..Default::default()
+        };
+        assert!(compile_raw("a[0-9]+b", &options).is_ok());
+
+        options.gate_budget = Some(GateBudget {
+            max_gates: 100,
+            input_len: 1024,
+        });
+        let err = compile_raw("a[0-9]+b", &options).unwrap_err().to_string();
+        assert!(err.contains("over the budget of 100"), "{}", err);
+
+        // The budget only bounds the Noir matcher.
+        options.noir = None;
+        assert!(compile_raw("a[0-9]+b", &options).is_ok());
+    }
+
+    #[test]
+    fn test_email_patterns_output_is_unchanged() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../circom/circuits/common");
+        let read = |file: &str| std::fs::read_to_string(format!("{dir}/{file}")).unwrap();
+        // The SHA-256 of the Halo2 tables (the whole DFA, then each substring), as generated
+        // before the string building of the backends moved to `write!`.
+        let halo2_digests = [
+            ("body_hash", None),
+            ("email_addr", None),
+            (
+                "email_addr_with_name",
+                Some("185135500a711b845557417aab81a0fa43485030e843fa36b71d7b84af68a27e"),
+            ),
+            ("email_domain", None),
+            (
+                "from_addr",
+                Some("f9676b8fbcdac16d4e1ef29a04d4e7e12eec7519ffc5f65dcc4b23370b2508ec"),
+            ),
+            ("from_all", None),
+            ("message_id", None),
+            ("subject_all", None),
+            (
+                "timestamp",
+                Some("b5ff698ead1fcc76e17e3bee87c7f6d6acabca27fe566a031829658845546e4a"),
+            ),
+            ("to_addr", None),
+            ("to_all", None),
+        ];
+        for (name, halo2_digest) in halo2_digests {
+            let template_name = name
+                .split('_')
+                .map(|word| word[..1].to_uppercase() + &word[1..])
+                .join("")
+                + "Regex";
+            let options = CompileOptions {
+                circom_template_name: Some(template_name),
+                halo2: true,
+                gen_substrs: Some(SubstrSelection::All),
+                ..Default::default()
+            };
+            let compiled = compile_decomposed(&read(&format!("{name}.json")), &options).unwrap();
+            // The checked-in circuits are the output of the compiler for their configs, but for
+            // the address circuits combining the templates of other patterns.
+            let hand_written = ["from_addr", "to_addr"].contains(&name);
+            assert!(
+                hand_written || compiled.circom.unwrap() == read(&format!("{name}_regex.circom")),
+                "the Circom template of {} changed",
+                name
+            );
+            if let Some(expected) = halo2_digest {
+                let tables = compiled.halo2.unwrap();
+                let mut hasher = Sha256::new();
+                hasher.update(tables.allstr.as_bytes());
+                for substr in &tables.substrs {
+                    hasher.update(substr.as_bytes());
+                }
+                assert_eq!(
+                    format!("{:x}", hasher.finalize()),
+                    expected,
+                    "the Halo2 tables of {name} changed"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_equivalence() {
+        let vars = BTreeMap::new();
+        let accepts = |pattern: &str, input: &[u8]| {
+            let regex_and_dfa = algebra::from_regex(pattern).unwrap();
+            let units = input.iter().map(|&byte| byte as u32).collect_vec();
+            noir::MatcherRun::new(&regex_and_dfa, &NoirOptions::default(), false)
+                .run(&units)
+                .is_some()
+        };
+        // The DFAs differ, but both matchers find `ab` anywhere in the input.
+        assert_eq!(
+            check_equivalence("ab", "(a|x)*ab", true, &vars).unwrap(),
+            None
+        );
+        assert_eq!(check_equivalence("ab", "ab+", true, &vars).unwrap(), None);
+
+        for (a, b) in [("^ab", "ab"), ("[0-9]+$", "[1-9][0-9]*$"), ("ab$", "ab")] {
+            let (input, accepted_by_a) = check_equivalence(a, b, true, &vars).unwrap().unwrap();
+            assert_eq!(accepts(a, &input), accepted_by_a, "{a} on {input:?}");
+            assert_eq!(accepts(b, &input), !accepted_by_a, "{b} on {input:?}");
+        }
+        assert_eq!(
+            check_equivalence("^ab", "ab", true, &vars).unwrap(),
+            Some((b"aab".to_vec(), false))
+        );
+        assert_eq!(
+            check_equivalence("[0-9]+$", "[1-9][0-9]*$", true, &vars).unwrap(),
+            Some((b"0".to_vec(), true))
+        );
+    }
+
+    #[test]
+    fn test_bench_pattern() {
+        let vars = BTreeMap::new();
+        let estimates = bench_pattern(
+            "secret=([0-9]+)",
+            true,
+            true,
+            &[4096, 16, 16],
+            &NoirOptions::default(),
+            &vars,
+        )
+        .unwrap();
+        let strategies = [
+            NoirStrategy::IfChain,
+            NoirStrategy::Keyword,
+            NoirStrategy::MuxTree,
+            NoirStrategy::Table,
+        ];
+        assert_eq!(
+            estimates
+                .iter()
+                .map(|estimate| (estimate.input_len, estimate.strategy))
+                .collect_vec(),
+            vec![16, 4096]
+                .into_iter()
+                .cartesian_product(strategies.to_vec())
+                .collect_vec()
+        );
+        // The keyword runs pay off on short inputs, the table on long ones.
+        let chosen = |input_len| {
+            estimates
+                .iter()
+                .filter(|estimate| estimate.input_len == input_len && estimate.chosen)
+                .map(|estimate| estimate.strategy)
+                .collect_vec()
+        };
+        assert_eq!(chosen(16), vec![NoirStrategy::Keyword]);
+        assert_eq!(chosen(4096), vec![NoirStrategy::Table]);
+        // Every strategy grows linearly with the input, its captures included.
+        let (short, long) = estimates.split_at(strategies.len());
+        for (short, long) in short.iter().zip(long) {
+            assert_eq!(long.gates - short.gates, short.per_unit * (4096 - 16));
+        }
+        let no_captures = bench_pattern(
+            "secret=([0-9]+)",
+            true,
+            false,
+            &[16],
+            &NoirOptions::default(),
+            &vars,
+        )
+        .unwrap();
+        assert!(no_captures[0].per_unit < estimates[0].per_unit);
+
+        let err = bench_pattern("a", true, false, &[], &NoirOptions::default(), &vars)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("must be positive"), "{}", err);
+    }
+
+    #[test]
+    fn test_bench_proving() {
+        let vars = BTreeMap::new();
+        let backends = [ProvingBackend::UltraPlonk, ProvingBackend::UltraHonk];
+        let costs = bench_proving(
+            "secret=([0-9]+)",
+            true,
+            true,
+            &[4096, 16],
+            &backends,
+            &NoirOptions::default(),
+            &vars,
+        )
+        .unwrap();
+        assert_eq!(
+            costs
+                .iter()
+                .map(|cost| (cost.input_len, cost.backend))
+                .collect_vec(),
+            vec![16, 4096]
+                .into_iter()
+                .cartesian_product([ProvingBackend::UltraHonk, ProvingBackend::UltraPlonk])
+                .collect_vec()
+        );
+        // The gates are those of the layout `auto` chooses, padded to a power of two.
+        let sizes = bench_pattern(
+            "secret=([0-9]+)",
+            true,
+            true,
+            &[16, 4096],
+            &NoirOptions::default(),
+            &vars,
+        )
+        .unwrap();
+        for cost in &costs {
+            let chosen = sizes
+                .iter()
+                .find(|size| size.input_len == cost.input_len && size.chosen)
+                .unwrap();
+            assert_eq!(cost.gates, chosen.gates);
+            assert!(cost.circuit_size.is_power_of_two() && cost.circuit_size >= cost.gates);
+            assert!(!cost.measured && cost.peak_memory_kb.is_some());
+        }
+        assert!(costs[0].prove_ms < costs[1].prove_ms);
+        assert!(costs[0].prove_ms < costs[2].prove_ms);
+        assert!(costs[0].peak_memory_kb < costs[2].peak_memory_kb);
+
+        assert_eq!(
+            count_gates(r#"{"functions": [{"acir_opcodes": 12, "circuit_size": 3000}]}"#).unwrap(),
+            3000
+        );
+        assert!(count_gates("{}").is_err());
+    }
+
+    #[test]
+    fn test_substr_ranges() {
+        let scratch = ScratchDir::new("zk_regex_test_substr_ranges").unwrap();
+        let dir = scratch.path();
+        let gen = |substr_ranges: &[Vec<(usize, usize)>], capture_groups: bool, name: &str| {
+            let noir_path = dir.join(name);
+            gen_from_raw(
+                "a([0-9]+)b",
+                None,
+                None,
+                &OutputPaths {
+                    noir_file: Some(noir_path.to_string_lossy().into_owned()),
+                    ..Default::default()
+                },
+                &CompileOptions {
+                    substr_transitions: substr_ranges.to_vec(),
+                    capture_groups,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            std::fs::read_to_string(noir_path).unwrap()
+        };
+        let from_flags = gen(&[vec![(1, 2), (2, 2)]], false, "flags.nr");
+        assert!(from_flags.contains("if ((s == 1) & (s_next == 2)) | ((s == 2) & (s_next == 2)) {"));
+        assert!(gen(&[], true, "groups.nr").ends_with(&from_flags));
+    }
+
+    #[test]
+    fn test_accept_states() {
+        let scratch = ScratchDir::new("zk_regex_test_accept_states").unwrap();
+        let dir = scratch.path();
+        let noir_path = dir.join("regex.nr");
+        let gen = |accept_states: &[usize]| {
+            gen_from_raw(
+                "from:[a-z]+;to:[a-z]+;",
+                None,
+                Some(&accept_states.iter().copied().collect()),
+                &OutputPaths {
+                    noir_file: Some(noir_path.to_string_lossy().into_owned()),
+                    ..Default::default()
+                },
+                &CompileOptions::default(),
+            )
+            .map(|_| std::fs::read_to_string(&noir_path).unwrap())
+        };
+        // State 7 is reached by "from:a;", which is then enough for a match.
+        let code = gen(&[12]).unwrap();
+        assert!(code.contains("assert((s == 12) | (s == 13), f\"no match: {s}\");"));
+        let code = gen(&[7]).unwrap();
+        assert!(code.contains("assert((s == 7) | (s == 13), f\"no match: {s}\");"));
+
+        let err = gen(&[7, 99]).unwrap_err().to_string();
+        assert!(err.contains("state 99 is set as accepting"), "{}", err);
+    }
+
+    #[test]
+    fn test_compile_report() {
+        let scratch = ScratchDir::new("zk_regex_test_report").unwrap();
+        let dir = scratch.path();
+        let noir_path = dir.join("regex.nr");
+        let report_path = dir.join("report.json");
+        gen_from_raw(
+            "a[0-9]+b",
+            None,
+            None,
+            &OutputPaths {
+                noir_file: Some(noir_path.to_string_lossy().into_owned()),
+                report: Some(report_path.to_string_lossy().into_owned()),
+                ..Default::default()
+            },
+            &CompileOptions {
+                gen_substrs: Some(SubstrSelection::Off),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let report: CompileReport =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        let pattern = &report.patterns[0];
+        assert_eq!(pattern.source, "a[0-9]+b");
+        assert!(!pattern.gen_substrs);
+        assert_eq!(pattern.dfa.accept_states, 1);
+        assert!(pattern.estimated_constraints_per_unit.unwrap() > 0);
+        assert_eq!(
+            pattern
+                .proving_estimates
+                .iter()
+                .map(|cost| (cost.input_len, cost.backend))
+                .collect_vec(),
+            vec![
+                (1024, ProvingBackend::UltraHonk),
+                (1024, ProvingBackend::UltraPlonk)
+            ]
+        );
+        assert!(pattern.warnings.is_empty());
+        let noir = std::fs::read(&noir_path).unwrap();
+        let sha256: String = Sha256::digest(&noir)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        assert_eq!(
+            pattern.outputs,
+            vec![OutputFile {
+                path: noir_path.to_string_lossy().into_owned(),
+                bytes: noir.len() as u64,
+                sha256,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_named_substrings() {
+        let scratch = ScratchDir::new("zk_regex_test_named_substrings").unwrap();
+        let dir = scratch.path();
+        let substrs_path = dir.join("substrs.json");
+        let noir_path = dir.join("regex.nr");
+        let report_path = dir.join("report.json");
+        let compile = |names: &str, gen_substrs: Option<SubstrSelection>| {
+            std::fs::write(
+                &substrs_path,
+                format!(
+                    r#"{{"transitions": [[[1, 2], [2, 2]], [[3, 4], [4, 4]]]{}}}"#,
+                    names
+                ),
+            )
+            .unwrap();
+            gen_from_raw(
+                "a[0-9]+b[a-z]+c",
+                substrs_path.to_str(),
+                None,
+                &OutputPaths {
+                    noir_file: Some(noir_path.to_string_lossy().into_owned()),
+                    report: Some(report_path.to_string_lossy().into_owned()),
+                    ..Default::default()
+                },
+                &CompileOptions {
+                    gen_substrs,
+                    ..Default::default()
+                },
+            )
+        };
+
+        compile(r#", "names": ["amount", null]"#, None).unwrap();
+        let noir = std::fs::read_to_string(&noir_path).unwrap();
+        assert!(noir.contains("// Capture slots of the named substrings:\n//   [0]: amount\n"));
+        assert!(!noir.contains("//   [1]:"));
+        let report: CompileReport =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        assert_eq!(
+            report.patterns[0].substring_names,
+            Some(vec![Some("amount".to_string()), None])
+        );
+
+        // The names follow the substrings renumbered by the selection.
+        let selection = SubstrSelection::Only(BTreeSet::from([1]));
+        compile(r#", "names": ["amount", "code"]"#, Some(selection)).unwrap();
+        let noir = std::fs::read_to_string(&noir_path).unwrap();
+        assert!(noir.contains("//   [0]: code\n"));
+        let report: CompileReport =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        assert_eq!(
+            report.patterns[0].substring_names,
+            Some(vec![Some("code".to_string())])
+        );
+
+        // Without names, the outputs are unchanged.
+        compile("", None).unwrap();
+        let noir = std::fs::read_to_string(&noir_path).unwrap();
+        assert!(!noir.contains("named substrings"));
+        let report = std::fs::read_to_string(&report_path).unwrap();
+        assert!(!report.contains("substring_names"));
+
+        assert!(compile(r#", "names": ["amount"]"#, None).is_err());
+        assert!(compile(r#", "names": ["amount", "amount"]"#, None).is_err());
+        assert!(compile(r#", "names": ["amount", ""]"#, None).is_err());
+
+        // The names given with the transitions of the options reach the outputs as well.
+        let options = CompileOptions {
+            substr_transitions: vec![vec![(1, 2), (2, 2)], vec![(3, 4), (4, 4)]],
+            substr_names: vec![None, Some("code".to_string())],
+            noir: Some(NoirOptions::default()),
+            ..Default::default()
+        };
+        let compiled = compile_raw("a[0-9]+b[a-z]+c", &options).unwrap();
+        assert!(compiled.noir.unwrap().contains("//   [1]: code\n"));
+        gen_from_raw(
+            "a[0-9]+b[a-z]+c",
+            None,
+            None,
+            &OutputPaths {
+                report: Some(report_path.to_string_lossy().into_owned()),
+                ..Default::default()
+            },
+            &options,
+        )
+        .unwrap();
+        let report: CompileReport =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        assert_eq!(
+            report.patterns[0].substring_names,
+            Some(vec![None, Some("code".to_string())])
+        );
+        let options = CompileOptions {
+            substr_names: vec![Some("code".to_string())],
+            ..options
+        };
+        assert!(compile_raw("a[0-9]+b[a-z]+c", &options).is_err());
+    }
+
+    #[test]
+    fn test_emit_artifacts() {
+        let scratch = ScratchDir::new("zk_regex_test_emit").unwrap();
+        let dir = scratch.path();
+        let stem = dir.join("regex");
+        let emit = EmitOptions {
+            stem: stem.to_string_lossy().into_owned(),
+            kinds: vec![
+                EmitKind::Report,
+                EmitKind::Noir,
+                EmitKind::Json,
+                EmitKind::Dot,
+                EmitKind::Html,
+            ],
+        };
+        let emit_all = || {
+            gen_from_raw(
+                "a([0-9]+)b",
+                None,
+                None,
+                &OutputPaths {
+                    emit: Some(emit.clone()),
+                    ..Default::default()
+                },
+                &CompileOptions {
+                    capture_groups: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+        emit_all();
+
+        let read = |extension: &str| {
+            std::fs::read_to_string(dir.join(format!("regex.{extension}"))).unwrap()
+        };
+        assert!(read("nr").contains("pub fn regex_match<let N: u32>"));
+        assert!(read("dot").starts_with("digraph automaton {"));
+        assert!(read("html").contains("\"pattern\":\"a([0-9]+)b\""));
+        let regex_and_dfa: RegexAndDFA = serde_json::from_str(&read("json")).unwrap();
+        assert_eq!(regex_and_dfa.substrings.substring_ranges.len(), 1);
+        let report: CompileReport = serde_json::from_str(&read("report.json")).unwrap();
+        let outputs = &report.patterns[0].outputs;
+        assert_eq!(outputs.len(), 4);
+        let noir = outputs.iter().find(|file| file.path.ends_with("regex.nr"));
+        assert_eq!(noir.unwrap().bytes, read("nr").len() as u64);
+        assert!(report.patterns[0].estimated_constraints_per_unit.is_some());
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 5);
+
+        // Emitting the same artifacts again leaves the files untouched.
+        let modified = |extension: &str| {
+            std::fs::metadata(dir.join(format!("regex.{extension}")))
+                .unwrap()
+                .modified()
+                .unwrap()
+        };
+        let before = ["nr", "json"].map(modified);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        emit_all();
+        assert_eq!(["nr", "json"].map(modified), before);
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 5);
+    }
+
+    #[test]
+    fn test_emit_abi() {
+        let scratch = ScratchDir::new("zk_regex_test_emit_abi").unwrap();
+        let dir = scratch.path();
+        let emit = EmitOptions {
+            stem: dir.join("regex").to_string_lossy().into_owned(),
+            kinds: vec![EmitKind::Abi],
+        };
+        let noir_options = NoirOptions {
+            match_window: true,
+            ..Default::default()
+        };
+        gen_from_raw(
+            "id=(?P<id>[0-9]{1,8});",
+            None,
+            None,
+            &OutputPaths {
+                emit: Some(emit),
+                ..Default::default()
+            },
+            &CompileOptions {
+                capture_groups: true,
+                noir: Some(noir_options),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // Only the ABI is written, though the matcher is generated to describe it.
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 1);
+        let abi: NoirAbi =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("regex.abi.json")).unwrap())
+                .unwrap();
+        assert_eq!(abi.regex, "id=(?P<id>[0-9]{1,8});");
+        let function = |name: &str| abi.functions.iter().find(|f| f.name == name).unwrap();
+        let regex_match = function("regex_match");
+        assert!(!regex_match.unconstrained);
+        assert_eq!(regex_match.generics[0].name, "N");
+        // The capture vector is as long as the longest capture.
+        assert_eq!(
+            regex_match.returns,
+            Some(NoirAbiType::Array {
+                element: Box::new(NoirAbiType::BoundedVec {
+                    element: Box::new(NoirAbiType::Field),
+                    max_len: "8".to_string(),
+                }),
+                length: "1".to_string(),
+            })
+        );
+        let window = function("regex_match_window");
+        assert_eq!(
+            window.params.iter().map(|p| p.name.as_str()).collect_vec(),
+            ["input", "start", "end"]
+        );
+        assert!(function("regex_match_unconstrained").unconstrained);
+        assert_eq!(
+            abi.captures,
+            vec![NoirAbiCapture {
+                index: 0,
+                group: Some(CaptureGroup {
+                    index: 1,
+                    name: Some("id".to_string()),
+                }),
+                name: None,
+                max_len: Some(8),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_lenient_newlines() {
+        assert_eq!(
+            relax_line_endings(r"(\r\n|^)from:[^\r\n]+\r\n"),
+            r"(\r?\n|^)from:[^\r\n]+\r?\n"
+        );
+        assert_eq!(relax_line_endings("a\r\nb[\r\n]"), "a\r?\nb[\r\n]");
+        assert_eq!(
+            relax_line_endings(r"\\r\n|\r?\n|\r|\n"),
+            r"\\r\n|\r?\n|\r|\n"
+        );
+
+        let graph = create_dfa_graph_from_regex(&relax_line_endings(r"a\r\nb")).unwrap();
+        for input in ["a\r\nb", "a\nb"] {
+            assert!(
+                regex::match_string_with_dfa_graph(&graph, input),
+                "{:?}",
+                input
+            );
+        }
+        assert!(!regex::match_string_with_dfa_graph(&graph, "a\rb"));
+
+        let options = CompileOptions {
+            noir: Some(NoirOptions::default()),
+            lenient_newlines: true,
+            ..Default::default()
+        };
+        let compiled = compile_raw(r"subject:[a-z]+\r\n", &options).unwrap();
+        assert_eq!(compiled.regex_pattern, r"subject:[a-z]+\r?\n");
+    }
+
+    #[test]
+    fn test_compile_raw_and_decomposed() {
+        let options = CompileOptions {
+            substr_transitions: vec![vec![(1, 2), (2, 2)]],
+            circom_template_name: Some("AbRegex".to_string()),
+            noir: Some(NoirOptions::default()),
+            ..Default::default()
+        };
+        let compiled = compile_raw("ab+", &options).unwrap();
+        assert_eq!(compiled.regex_pattern, "ab+");
+        assert!(compiled.circom.unwrap().contains("template AbRegex"));
+        assert!(compiled
+            .noir
+            .unwrap()
+            .contains("pub fn regex_match<let N: u32>"));
+        assert!(compiled.halo2.is_none());
+        assert!(compiled.noir_modules.is_empty());
+
+        let options = CompileOptions {
+            circom_template_name: Some("FromRegex".to_string()),
+            template_vars: vec![("domain".to_string(), "a.com".to_string())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let compiled = compile_raw("from:{{domain}}", &options).unwrap();
+        assert_eq!(compiled.regex_pattern, "from:a\\.com");
+        assert!(compile_raw("from:{{tenant}}", &options).is_err());
+
+        let options = CompileOptions {
+            halo2: true,
+            noir: Some(NoirOptions {
+                split_modules: true,
+                ..Default::default()
+            }),
+            gen_substrs: Some(SubstrSelection::All),
+            ..Default::default()
+        };
+        let json = r#"{"parts": [{"is_public": false, "regex_def": "a"}, {"is_public": true, "regex_def": "b+"}]}"#;
+        let compiled = compile_decomposed(json, &options).unwrap();
+        assert!(compiled.circom.is_none());
+        assert_eq!(compiled.halo2.unwrap().substrs.len(), 1);
+        assert!(compiled.noir.unwrap().starts_with("mod transitions;"));
+        let names = compiled
+            .noir_modules
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect_vec();
+        assert_eq!(names, vec!["transitions", "helpers"]);
+
+        assert!(compile_decomposed("{", &options).is_err());
+    }
+
+    #[test]
+    fn test_compile_batch() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<CompileOptions>();
+        assert_send_sync::<CompiledRegex>();
+        assert_send_sync::<CompilerError>();
+        assert_send_sync::<RegexAndDFA>();
+        assert_send_sync::<NoirOptions>();
+        assert_send_sync::<PatternSource>();
+
+        let options = CompileOptions {
+            noir: Some(NoirOptions::default()),
+            ..Default::default()
+        };
+        let patterns = vec![
+            PatternSource::Raw("a[0-9]+b".to_string()),
+            PatternSource::Decomposed(
+                r#"{"parts": [{"is_public": false, "regex_def": "to:"}, {"is_public": true, "regex_def": "[a-z]+"}]}"#
+                    .to_string(),
+            ),
+            PatternSource::Raw("a(b".to_string()),
+        ];
+        let results = compile_batch(&patterns, &options);
+        assert_eq!(results.len(), 3);
+        for (result, pattern) in results[..2].iter().zip(&patterns) {
+            let sequential = match pattern {
+                PatternSource::Raw(raw_regex) => compile_raw(raw_regex, &options),
+                PatternSource::Decomposed(json) => compile_decomposed(json, &options),
+            };
+            assert_eq!(
+                result.as_ref().unwrap().noir,
+                sequential.unwrap().noir,
+                "{pattern:?}"
+            );
+        }
+        assert!(results[2].is_err());
+    }
+
+    #[test]
+    fn test_compiled_automaton() {
+        let options = CompileOptions {
+            capture_groups: true,
+            automaton: true,
+            ..Default::default()
+        };
+        let automaton = compile_raw("id=([0-9]+);", &options)
+            .unwrap()
+            .automaton
+            .unwrap();
+        assert_eq!(automaton.num_substrings, 1);
+        assert!(automaton.capture_edges().count() > 0);
+        assert!(automaton
+            .capture_edges()
+            .all(|edge| edge.substrings == [0]
+                && edge.ranges == [(u32::from(b'0'), u32::from(b'9'))]));
+        let sink = automaton.accept_sink.unwrap();
+        assert!(automaton.is_accepting(sink));
+        assert_eq!(automaton.transitions_from(sink).count(), 0);
+
+        // Runs the automaton as the Noir matcher does, collecting the revealed code units.
+        let mut state = automaton.init_state;
+        let mut captured = String::new();
+        for byte in "x id=42; y".bytes() {
+            let unit = u32::from(byte);
+            let from = match automaton.next_state(state, unit) {
+                Some(_) => state,
+                None => automaton.start_state,
+            };
+            let Some(to) = automaton.next_state(from, unit) else {
+                state = automaton.start_state;
+                captured.clear();
+                continue;
+            };
+            if automaton
+                .transitions_from(from)
+                .any(|edge| edge.to == to && !edge.substrings.is_empty())
+            {
+                captured.push(char::from(byte));
+            }
+            state = to;
+        }
+        assert_eq!(state, sink);
+        assert_eq!(captured, "42");
+        let units = |input: &str| input.bytes().map(u32::from).collect_vec();
+        let bytes = |captures: Vec<Vec<u32>>| {
+            captures
+                .into_iter()
+                .map(|capture| capture.into_iter().map(|unit| unit as u8).collect_vec())
+                .collect_vec()
+        };
+        let simulated = automaton.simulate(&units("x id=42; y")).map(bytes);
+        assert_eq!(simulated, Some(vec![b"42".to_vec()]));
+        // The captures of an abandoned match are cleared when the matcher restarts.
+        let simulated = automaton.simulate(&units("id=7x id=42;")).map(bytes);
+        assert_eq!(simulated, Some(vec![b"42".to_vec()]));
+        assert_eq!(automaton.simulate(&units("id=;")), None);
+
+        // The captures follow the options shaping the matcher, and the bytes of a decomposed
+        // regex are tagged with their part.
+        let json = r#"{"parts": [
+            {"is_public": false, "regex_def": "ID="},
+            {"is_public": true, "regex_def": "[A-Z]+"},
+            {"is_public": false, "regex_def": "[A-Z]*;"}
+        ]}"#;
+        for reverse in [false, true] {
+            let options = CompileOptions {
+                noir: Some(NoirOptions {
+                    reverse,
+                    lowercase_captures: true,
+                    ..Default::default()
+                }),
+                gen_substrs: Some(SubstrSelection::All),
+                ..options.clone()
+            };
+            let automaton = compile_decomposed(json, &options)
+                .unwrap()
+                .automaton
+                .unwrap();
+            let simulated = automaton.simulate(&units("xx ID=AB; y")).map(bytes);
+            assert_eq!(simulated, Some(vec![b"ab".to_vec()]), "{}", reverse);
+        }
+        let options = CompileOptions {
+            noir: Some(NoirOptions {
+                occurrence: Some(2),
+                ..Default::default()
+            }),
+            ..options
+        };
+        let automaton = compile_raw("id=([0-9]+);", &options)
+            .unwrap()
+            .automaton
+            .unwrap();
+        let simulated = automaton.simulate(&units("id=1;id=2;")).map(bytes);
+        assert_eq!(simulated, Some(vec![b"2".to_vec()]));
+        assert_eq!(automaton.simulate(&units("id=1;")), None);
+        let options = CompileOptions {
+            gen_substrs: Some(SubstrSelection::Off),
+            ..options
+        };
+        let automaton = compile_raw("id=([0-9]+);", &options)
+            .unwrap()
+            .automaton
+            .unwrap();
+        assert_eq!(automaton.simulate(&units("id=1;id=2;")), Some(vec![]));
+
+        let options = CompileOptions {
+            automaton: true,
+            ..Default::default()
+        };
+        let automaton = compile_raw("^ab$", &options).unwrap().automaton.unwrap();
+        assert_eq!(automaton.accept_sink, None);
+        assert!(automaton.capture_edges().next().is_none());
+        assert_eq!(
+            automaton.next_state(automaton.init_state, u32::from(b'b')),
+            None
+        );
+    }
+
+    #[test]
+    fn test_selected_substrs() {
+        assert_eq!("true".parse(), Ok(SubstrSelection::All));
+        assert_eq!("false".parse(), Ok(SubstrSelection::Off));
+        assert_eq!(
+            "2, 0".parse(),
+            Ok(SubstrSelection::Only(vec![0, 2].into_iter().collect()))
+        );
+        assert!("first".parse::<SubstrSelection>().is_err());
+
+        let json = r#"{"parts": [
+            {"is_public": true, "regex_def": "[a-z]+"},
+            {"is_public": false, "regex_def": "@"},
+            {"is_public": true, "regex_def": "[a-z]+"}
+        ]}"#;
+        let options = CompileOptions {
+            halo2: true,
+            noir: Some(NoirOptions::default()),
+            gen_substrs: Some("1".parse().unwrap()),
+            ..Default::default()
+        };
+        let compiled = compile_decomposed(json, &options).unwrap();
+        assert_eq!(compiled.halo2.unwrap().substrs.len(), 1);
+        let noir = compiled.noir.unwrap();
+        assert!(noir.contains("-> [BoundedVec<Field, N>; 1] {"));
+        assert!(!noir.contains("substr1"));
+
+        let options = CompileOptions {
+            gen_substrs: Some("3".parse().unwrap()),
+            ..options
+        };
+        assert!(compile_decomposed(json, &options).is_err());
+    }
+
+    #[test]
+    fn test_init_project() {
+        let scratch = ScratchDir::new("zk_regex_test_init").unwrap();
+        let dir = scratch.path().join("zk_regex_test_init");
+        init_project(dir.to_str().unwrap()).unwrap();
+        let regex = std::fs::read_to_string(dir.join("src/regex.nr")).unwrap();
+        assert!(regex.contains("pub fn regex_match_greeting<let N: u32>"));
+        let nargo_toml = std::fs::read_to_string(dir.join("Nargo.toml")).unwrap();
+        assert!(nargo_toml.contains("name = \"zk_regex_test_init\""));
+        assert!(init_project(dir.to_str().unwrap())
+            .unwrap_err()
+            .to_string()
+            .contains("already exists"));
+    }
+
+    #[test]
+    fn test_migrate_circom_project() {
+        let scratch = ScratchDir::new("zk_regex_test_migrate").unwrap();
+        let dir = scratch.path();
+        let circuits = dir.join("circuits");
+        std::fs::create_dir_all(circuits.join("common")).unwrap();
+        std::fs::create_dir_all(dir.join("node_modules/dep")).unwrap();
+        std::fs::write(
+            circuits.join("common/from-addr.json"),
+            r#"{"max_byte_size": 64, "parts": [
+                {"is_public": false, "regex_def": "from:"},
+                {"is_public": true, "regex_def": "[a-z]+", "max_size": 16, "solidity": true},
+                {"is_public": false, "regex_def": "$"}
+            ]}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            circuits.join("common/from-addr_regex.circom"),
+            "pragma circom 2.1.5;\n\ntemplate FromAddrRegex(msg_bytes) {\n\tsignal input msg[msg_bytes];\n\tsignal output out;\n\tsignal output reveal0[msg_bytes];\n\tsignal output reveal1[msg_bytes];\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            circuits.join("lookahead.json"),
+            r#"{"parts": [{"is_public": false, "regex_def": "a(?=b)"}]}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            circuits.join("1digits.json"),
+            r#"{"parts": [{"is_public": false, "regex_def": "[0-9]+"}]}"#,
+        )
+        .unwrap();
+        std::fs::write(circuits.join("package.json"), r#"{"name": "circuits"}"#).unwrap();
+        std::fs::write(
+            dir.join("node_modules/dep/dep.json"),
+            r#"{"parts": [{"is_public": false, "regex_def": "("}]}"#,
+        )
+        .unwrap();
+
+        let out_dir = dir.join("noir");
+        let report = migrate_circom_project(
+            dir.to_str().unwrap(),
+            out_dir.to_str().unwrap(),
+            &NoirOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(report.patterns, ["pattern_1digits", "from_addr"]);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.skipped[0].0.ends_with("lookahead.json"));
+        let notes = |pattern: &str| {
+            report
+                .notes
+                .iter()
+                .filter(|note| note.pattern == pattern)
+                .map(|note| note.message.as_str())
+                .collect_vec()
+        };
+        let from_addr = notes("from_addr");
+        assert_eq!(from_addr.len(), 7);
+        assert!(from_addr[0].contains("max_byte_size 64"));
+        assert!(from_addr[1].contains("max_size 16 of part 1 becomes its max_length"));
+        assert!(from_addr[2].contains("\"solidity\" of part 1"));
+        assert!(from_addr[3].contains("FromAddrRegex outputs whether the input matches"));
+        assert!(from_addr[4].contains("BoundedVecs"));
+        assert!(from_addr[5].contains("2 reveal outputs but the config has 1 public parts"));
+        assert!(from_addr[6].contains("ends with `$`"));
+        assert_eq!(
+            notes("pattern_1digits"),
+            ["no generated Circom template was found for the config, so its outputs could not be compared with the Noir matcher"]
+        );
+
+        let config: DecomposedRegexConfig =
+            serde_json::from_str(&std::fs::read_to_string(out_dir.join("from_addr.json")).unwrap())
+                .unwrap();
+        assert_eq!(config.parts[1].max_length, Some(16));
+        let manifest: PatternManifest =
+            serde_json::from_str(&std::fs::read_to_string(out_dir.join("patterns.json")).unwrap())
+                .unwrap();
+        assert_eq!(manifest.patterns[1].name, "from_addr");
+        let regex = std::fs::read_to_string(out_dir.join("regex.nr")).unwrap();
+        assert!(regex.contains("pub fn regex_match_from_addr<let N: u32>"));
+        assert!(regex.contains("pub fn regex_match_pattern_1digits<let N: u32>"));
+
+        let report = migrate_circom_project(
+            dir.to_str().unwrap(),
+            out_dir.to_str().unwrap(),
+            &NoirOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(report.patterns.len(), 2);
+        assert!(migrate_circom_project(
+            dir.join("node_modules").to_str().unwrap(),
+            out_dir.to_str().unwrap(),
+            &NoirOptions::default(),
+        )
+        .unwrap_err()
+        .to_string()
+        .contains("no decomposed regex config"));
+    }
+
+    #[test]
+    fn test_manifest_fragments() {
+        let scratch = ScratchDir::new("zk_regex_test_fragments").unwrap();
+        let dir = scratch.path();
+        std::fs::write(
+            dir.join("common.json"),
+            r#"{"digit": "[0-9]", "date": "{{digit}}{4}-{{digit}}{2}-{{digit}}{2}"}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("due.json"),
+            r#"{"parts": [{"is_public": false, "regex_def": "due:"}, {"is_public": true, "regex_def": "{{date}}"}]}"#,
+        )
+        .unwrap();
+        let manifest_path = dir.join("patterns.json");
+        let manifest = |vars: &str| {
+            format!(
+                r#"{{"patterns": [
+                    {{"name": "sent", "raw_regex": "sent:{{{{date}}}} to {{{{user}}}}"}},
+                    {{"name": "due", "decomposed_regex_path": "due.json"}}
+                ], "includes": ["common.json"], "fragments": {{"user": "[a-z]+"}}, "vars": {}}}"#,
+                vars
+            )
+        };
+        std::fs::write(&manifest_path, manifest("{}")).unwrap();
+        let load = || {
+            load_manifest_patterns(
+                manifest_path.to_str().unwrap(),
+                &SubstrSelection::All,
+                &BTreeMap::new(),
+                false,
+                Dialect::Rust,
+                false,
+            )
+        };
+        let patterns = load().unwrap();
+        assert_eq!(
+            patterns[0].1.regex_pattern,
+            "sent:(?:(?:[0-9]){4}-(?:[0-9]){2}-(?:[0-9]){2}) to (?:[a-z]+)"
+        );
+        let input = b"due:2024-01-31";
+        assert_eq!(
+            simulate_matcher(&patterns[1].1, true, &NoirOptions::default(), input),
+            Some(vec![b"2024-01-31".to_vec()])
+        );
+
+        std::fs::write(&manifest_path, manifest(r#"{"user": "alice"}"#)).unwrap();
+        let err = load().unwrap_err().to_string();
+        assert!(err.contains("\"user\" of pattern \"sent\" is both a fragment and a variable"));
+        std::fs::write(
+            &manifest_path,
+            manifest("{}").replace(r#""user": "[a-z]+""#, r#""digit": "[0-9]""#),
+        )
+        .unwrap();
+        let err = load().unwrap_err().to_string();
+        assert!(err.contains("fragment \"digit\" is defined more than once"));
+    }
+
+    #[test]
+    fn test_gen_noir_runtime() {
+        let scratch = ScratchDir::new("zk_regex_test_runtime").unwrap();
+        let dir = scratch.path().join("zk_regex_test_runtime");
+        let options = NoirOptions {
+            imports: vec!["sha256::sha256_var".to_string()],
+            dependencies: BTreeMap::from([(
+                "sha256".to_string(),
+                "{ tag = \"v0.1.0\", git = \"https://github.com/noir-lang/sha256\" }".to_string(),
+            )]),
+            ..Default::default()
+        };
+        let name = gen_noir_runtime(dir.to_str().unwrap(), &options).unwrap();
+        assert_eq!(name, "zk_regex_test_runtime");
+        let nargo_toml = std::fs::read_to_string(dir.join("Nargo.toml")).unwrap();
+        assert!(nargo_toml.contains("type = \"lib\""));
+        assert!(nargo_toml.contains("sha256 = { tag"));
+        let lib = std::fs::read_to_string(dir.join("src/lib.nr")).unwrap();
+        assert!(lib.contains("use sha256::sha256_var;"));
+        assert!(lib.contains("pub fn capture_to_bytes<let N: u32>"));
+        assert!(lib.contains("pub fn to_ascii_lowercase_u16(unit: u16) -> u16 {"));
+
+        let options = NoirOptions {
+            runtime: Some("zk-regex".to_string()),
+            ..Default::default()
+        };
+        assert!(gen_noir_runtime(dir.to_str().unwrap(), &options).is_err());
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[test]
+    fn test_init_project_runs_nargo() {
+        let scratch = ScratchDir::new("zk_regex_test_init_nargo").unwrap();
+        let dir = scratch.path().join("zk_regex_test_init_nargo");
+        init_project(dir.to_str().unwrap()).unwrap();
+        run_tool("nargo", &["test"], &dir).unwrap();
+        run_tool("nargo", &["execute"], &dir).unwrap();
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[test]
+    fn test_from_manifest_runs_nargo() {
+        let scratch = ScratchDir::new("zk_regex_test_manifest").unwrap();
+        let dir = scratch.path();
+        let manifest_path = dir.join("patterns.json");
+        std::fs::write(
+            &manifest_path,
+            r#"{"patterns": [
+                {"name": "email", "raw_regex": "[a-z]+@{{domain}}"},
+                {"name": "amount", "raw_regex": "amount=[0-9]+;"}
+            ], "vars": {"domain": "a.com"}}"#,
+        )
+        .unwrap();
+        let results = test_from_manifest(
+            manifest_path.to_str().unwrap(),
+            &NoirOptions::default(),
+            None,
+            &BTreeMap::new(),
+            false,
+            Dialect::Rust,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            results.iter().map(|result| &result.name).collect_vec(),
+            ["email", "amount"]
+        );
+        assert!(results.iter().all(|result| result.passed));
+        assert!(results.iter().all(|result| !result.project_dir.exists()));
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[test]
+    fn test_validate_pattern_runs_nargo() {
+        let options = SampleOptions {
+            count: 3,
+            max_len: 12,
+            ..Default::default()
+        };
+        let (checked, divergences) = validate_pattern(
+            "id:([a-z]+);",
+            true,
+            true,
+            &options,
+            &NoirOptions::default(),
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        assert!(checked > 3);
+        assert!(divergences.is_empty(), "{:?}", divergences);
+    }
+}
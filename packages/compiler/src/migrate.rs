@@ -0,0 +1,331 @@
+use crate::{
+    errors::CompilerError,
+    structs::{DecomposedRegexConfig, RegexPartConfig},
+};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+/// The directories of a Circom project that hold no configs of its own.
+const SKIPPED_DIRS: [&str; 4] = ["node_modules", "target", ".git", "build"];
+
+/// The outcome of migrating a Circom zk-regex project to a Noir pattern manifest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// The names of the migrated patterns, in the order of the manifest.
+    pub patterns: Vec<String>,
+    /// The configs that could not be migrated, with the reason.
+    pub skipped: Vec<(PathBuf, String)>,
+    /// The constructs whose semantics differ between the Circom templates and the Noir library.
+    pub notes: Vec<MigrationNote>,
+}
+
+/// A difference in semantics between a Circom template and the Noir matcher replacing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationNote {
+    /// The name of the pattern in the manifest.
+    pub pattern: String,
+    pub message: String,
+}
+
+/// A decomposed regex config found in a Circom project, normalized for the Noir compiler.
+#[derive(Debug, Clone)]
+pub(crate) struct CircomConfig {
+    pub(crate) path: PathBuf,
+    pub(crate) config: DecomposedRegexConfig,
+    /// The differences with the original config, described for the migration notes.
+    pub(crate) changes: Vec<String>,
+}
+
+/// The interface of a template generated by the Circom compiler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CircomTemplate {
+    pub(crate) name: String,
+    /// The number of `reveal<i>[msg_bytes]` outputs of the template.
+    pub(crate) reveals: usize,
+}
+
+/// Lists the files of a Circom project, in a stable order.
+///
+/// # Arguments
+///
+/// * `dir` - The root directory of the project.
+/// * `excluded` - A directory not to descend into, such as the output directory.
+///
+/// # Returns
+///
+/// A `Result` containing the paths of the files or a `CompilerError`.
+pub(crate) fn project_files(dir: &Path, excluded: &Path) -> Result<Vec<PathBuf>, CompilerError> {
+    let mut files = Vec::new();
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            let skipped = path
+                .file_name()
+                .is_some_and(|name| SKIPPED_DIRS.iter().any(|dir| name == *dir));
+            if !skipped && !same_dir(&path, excluded) {
+                files.extend(project_files(&path, excluded)?);
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Reads a decomposed regex config of the Circom compiler from JSON.
+///
+/// Configs of older versions of the Circom compiler are accepted: the per-part `max_size` becomes
+/// `max_length`, and keys without a Noir equivalent, such as `max_byte_size`, are dropped. Each
+/// change is described in the returned config.
+///
+/// # Arguments
+///
+/// * `path` - The path of the JSON file.
+/// * `json` - The contents of the file.
+///
+/// # Returns
+///
+/// `None` if the JSON is not a decomposed regex config, such as a `package.json` or a
+/// substrings file, or a `Result` containing the config or a description of why it is invalid.
+pub(crate) fn parse_circom_config(path: &Path, json: &str) -> Option<Result<CircomConfig, String>> {
+    let value: Value = serde_json::from_str(json).ok()?;
+    let object = value.as_object()?;
+    let parts = object.get("parts")?.as_array()?;
+    let mut changes = Vec::new();
+    for (key, value) in object.iter().filter(|(key, _)| *key != "parts") {
+        changes.push(match key.as_str() {
+            "max_byte_size" => format!(
+                "the config sets max_byte_size {}, which is dropped: the length of the input is the generic N of the Noir matcher",
+                value
+            ),
+            _ => format!("the key \"{}\" of the config has no Noir equivalent and is dropped", key),
+        });
+    }
+    let parts = parts
+        .iter()
+        .enumerate()
+        .map(|(idx, part)| {
+            let part = part
+                .as_object()
+                .ok_or_else(|| format!("part {} is not an object", idx))?;
+            parse_circom_part(idx, part, &mut changes)
+        })
+        .collect::<Result<_, String>>();
+    Some(parts.map(|parts| CircomConfig {
+        path: path.to_path_buf(),
+        config: DecomposedRegexConfig { parts },
+        changes,
+    }))
+}
+
+fn parse_circom_part(
+    idx: usize,
+    part: &Map<String, Value>,
+    changes: &mut Vec<String>,
+) -> Result<RegexPartConfig, String> {
+    let mut part = part.clone();
+    if let Some(max_size) = part.remove("max_size") {
+        if !part.contains_key("max_length") {
+            changes.push(format!(
+                "the max_size {} of part {} becomes its max_length",
+                max_size, idx
+            ));
+            part.insert("max_length".to_string(), max_size);
+        }
+    }
+    let known = [
+        "is_public",
+        "regex_def",
+        "max_repetitions",
+        "max_length",
+        "numeric",
+        "hole",
+        "min",
+        "max",
+    ];
+    let unknown = part
+        .keys()
+        .filter(|key| !known.contains(&key.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+    for key in unknown {
+        part.remove(&key);
+        changes.push(format!(
+            "the key \"{}\" of part {} has no Noir equivalent and is dropped",
+            key, idx
+        ));
+    }
+    serde_json::from_value(Value::Object(part)).map_err(|e| format!("part {}: {}", idx, e))
+}
+
+/// Reads the interface of the template generated by the Circom compiler from its source.
+///
+/// # Arguments
+///
+/// * `source` - The contents of the `.circom` file.
+///
+/// # Returns
+///
+/// The name and number of reveal outputs of the first template taking `msg_bytes`, if any.
+pub(crate) fn parse_circom_template(source: &str) -> Option<CircomTemplate> {
+    let name = source.lines().find_map(|line| {
+        let rest = line.trim().strip_prefix("template ")?;
+        let (name, params) = rest.split_once('(')?;
+        params
+            .starts_with("msg_bytes)")
+            .then(|| name.trim().to_string())
+    })?;
+    let reveals = source
+        .lines()
+        .filter(|line| {
+            line.trim()
+                .strip_prefix("signal output reveal")
+                .is_some_and(|rest| rest.ends_with("[msg_bytes];"))
+        })
+        .count();
+    Some(CircomTemplate { name, reveals })
+}
+
+/// Finds the generated Circom template of a config among the files of the project.
+///
+/// The Circom compiler names its output `<stem>_regex.circom` or `<stem>.circom`; a file next to
+/// the config is preferred over one elsewhere in the project.
+///
+/// # Arguments
+///
+/// * `config_path` - The path of the config.
+/// * `files` - The files of the project.
+///
+/// # Returns
+///
+/// The path of the template, if any.
+pub(crate) fn find_circom_template<'a>(
+    config_path: &Path,
+    files: &'a [PathBuf],
+) -> Option<&'a PathBuf> {
+    let stem = config_path.file_stem()?.to_string_lossy();
+    let names = [format!("{}_regex.circom", stem), format!("{}.circom", stem)];
+    let matching = |path: &&PathBuf| {
+        path.file_name()
+            .is_some_and(|name| names.iter().any(|n| name == n.as_str()))
+    };
+    files
+        .iter()
+        .filter(matching)
+        .find(|path| path.parent() == config_path.parent())
+        .or_else(|| files.iter().find(matching))
+}
+
+/// Derives the names of the migrated patterns from the file names of their configs.
+///
+/// The stems are turned into Noir identifiers and suffixed with a number where they collide.
+///
+/// # Arguments
+///
+/// * `paths` - The paths of the configs.
+///
+/// # Returns
+///
+/// The name of the pattern of every config, in order.
+pub(crate) fn pattern_names(paths: &[&Path]) -> Vec<String> {
+    let mut taken = BTreeSet::new();
+    let mut counts = BTreeMap::new();
+    paths
+        .iter()
+        .map(|path| {
+            let stem = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let mut base = stem
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect::<String>();
+            if !base.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                base.insert_str(0, "pattern_");
+            }
+            let mut name = base.clone();
+            while !taken.insert(name.clone()) {
+                let count = counts.entry(base.clone()).or_insert(1);
+                *count += 1;
+                name = format!("{}_{}", base, count);
+            }
+            name
+        })
+        .collect()
+}
+
+/// Describes the constructs of a pattern whose semantics differ between its Circom template and
+/// its Noir matcher.
+///
+/// # Arguments
+///
+/// * `name` - The name of the pattern.
+/// * `config` - The migrated config.
+/// * `template` - The interface of the Circom template, if it was found.
+///
+/// # Returns
+///
+/// The messages of the migration notes of the pattern.
+pub(crate) fn semantic_differences(
+    name: &str,
+    config: &DecomposedRegexConfig,
+    template: Option<&CircomTemplate>,
+) -> Vec<String> {
+    let mut messages = Vec::new();
+    let public_parts = config.parts.iter().filter(|part| part.is_public).count();
+    match template {
+        Some(template) => {
+            messages.push(format!(
+                "{} outputs whether the input matches as `out`, which the caller had to constrain; regex_match_{} asserts the match, so inputs that do not match cannot be proven",
+                template.name, name
+            ));
+            if template.reveals > 0 {
+                messages.push(format!(
+                    "{} reveals its public parts as arrays of msg_bytes bytes, zero outside the revealed positions; regex_match_{} returns them as BoundedVecs of the captured bytes only, without their positions",
+                    template.name, name
+                ));
+            }
+            if template.reveals != public_parts {
+                messages.push(format!(
+                    "{} has {} reveal outputs but the config has {} public parts, so the Circom assets may be stale; the Noir matcher follows the config",
+                    template.name, template.reveals, public_parts
+                ));
+            }
+        }
+        None => messages.push(
+            "no generated Circom template was found for the config, so its outputs could not be compared with the Noir matcher"
+                .to_string(),
+        ),
+    }
+    let ends_anchored = config.parts.back().is_some_and(|part| {
+        let trailing_backslashes = part
+            .regex_def
+            .trim_end_matches('$')
+            .chars()
+            .rev()
+            .take_while(|c| *c == '\\')
+            .count();
+        part.regex_def.ends_with('$') && trailing_backslashes % 2 == 0
+    });
+    if ends_anchored {
+        messages.push(
+            "the pattern ends with `$`, which the Circom template matches where the zero padding of its input begins; the Noir matcher matches it at the end of the input array only, so the input must not be padded"
+                .to_string(),
+        );
+    }
+    messages
+}
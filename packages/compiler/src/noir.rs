@@ -0,0 +1,12467 @@
+use crate::{
+    algebra::{anchor_start, reverse_dfa},
+    automaton::{AutomatonState, AutomatonTransition, CompiledAutomaton},
+    errors::CompilerError,
+    progress::Progress,
+    regex::max_capture_lens,
+    structs::{
+        CaptureGroup, DFAGraph, GateBudget, NoirCaptureOverflow, NoirEmptyMatch, NoirInputAlphabet,
+        NoirInputHash, NoirInputType, NoirMatchSemantics, NoirOptions, NoirStateType, NoirStrategy,
+        RegexAndDFA, RegexPartDFA, RepetitionCounter,
+    },
+};
+use itertools::Itertools;
+use rayon::prelude::*;
+use std::{
+    cmp::Reverse,
+    collections::{btree_map::Entry, BTreeMap, BTreeSet, VecDeque},
+    io::{self, Write},
+};
+use tracing::{debug, info, warn};
+
+#[cfg(feature = "fs")]
+use crate::{
+    progress::{write_file, FileWriter},
+    structs::{
+        CorpusProfile, FieldsConfig, MainOutput, NoirMainOptions, PatternComposition,
+        ProvingBackend, ProvingCost, SizeEstimate, StateVisits, TransitionVisits,
+    },
+};
+#[cfg(feature = "fs")]
+use std::{fs::create_dir_all, path::Path};
+
+/// The byte standing for the start anchor (^) in the DFA, fed before the input by the Circom
+/// template. The Noir automata start in the state it leads to instead, see `unprime`.
+const PRIMING_BYTE: u8 = 255;
+
+/// The number of bytes packed into a single Field in the packed input mode.
+const PACKED_BYTES: usize = 31;
+
+/// The maximal line width of the generated code, matching the default of `nargo fmt`.
+const MAX_WIDTH: usize = 100;
+
+/// The number of states or branches of `next_state` generated at once, so that the transitions
+/// of large automata are written out as they are generated rather than held in memory.
+const STREAM_CHUNK: usize = 1024;
+
+/// The memory `bb` takes to prove the smallest circuit, in kilobytes, see
+/// `estimate_proving_cost`.
+#[cfg(feature = "fs")]
+const BASE_PROVING_MEMORY_KB: u64 = 64 * 1024;
+
+/// The value `next_state` returns when no transition applies, upon which the matcher restarts
+/// from the start state of the automaton, whatever its number.
+const RESET_STATE: usize = 0;
+
+/// The most pairs of states `ambiguous_capture_input` visits before giving up.
+const AMBIGUITY_SEARCH_LIMIT: usize = 1 << 16;
+
+/// An automaton over input code units (bytes or UTF-16 code units) ready for Noir codegen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct NoirAutomaton {
+    /// Transitions as `(from, to) -> sorted inclusive code unit ranges`.
+    transitions: BTreeMap<(usize, usize), Vec<(u32, u32)>>,
+    /// IDs of the accepting states.
+    accept_states: BTreeSet<usize>,
+    /// For each substring, the `(from, to)` transitions whose code unit is revealed.
+    substr_transitions: Vec<BTreeSet<(usize, usize)>>,
+    /// The state the matcher starts in, where the start anchor (^) still matches.
+    init_state: usize,
+    /// The state the matcher restarts from when no transition applies.
+    start_state: usize,
+    /// An ID that is not used by any state of the automaton.
+    free_state_id: usize,
+    /// Whether every code unit is a byte tagged with the substring it belongs to, see
+    /// `build_parts_automaton`.
+    tagged: bool,
+    /// For each substring, the number of repetitions captured separately, if it repeats.
+    max_repetitions: Vec<Option<usize>>,
+    /// For each substring, the maximal number of code units of a capture, if bounded.
+    max_lengths: Vec<Option<usize>>,
+    /// For each substring, the number of code units its capture vector holds: the smallest of
+    /// its `max_length` and of its longest capture through the automaton, if either is bounded.
+    capture_bounds: Vec<Option<usize>>,
+    /// The repetitions matched with counters, whose transitions are those of the automaton.
+    counters: Vec<RepetitionCounter>,
+}
+
+impl NoirAutomaton {
+    /// Returns the number of tags a byte can carry, 1 if the bytes are not tagged.
+    fn num_tags(&self) -> usize {
+        let num_substrs = self.substr_transitions.len();
+        match self.tagged {
+            true if self.max_repetitions.iter().any(Option::is_some) => 2 * num_substrs + 1,
+            true => num_substrs + 1,
+            false => 1,
+        }
+    }
+
+    /// Returns the names of the variables holding the captures of a substring, one per
+    /// repetition if it repeats.
+    fn capture_vars(&self, idx: usize) -> Vec<String> {
+        match self.max_repetitions[idx] {
+            Some(max) => (0..max).map(|rep| format!("substr{idx}_{rep}")).collect(),
+            None => vec![format!("substr{idx}")],
+        }
+    }
+
+    /// Returns the number of captures returned by the matcher.
+    fn num_captures(&self) -> usize {
+        self.max_repetitions
+            .iter()
+            .map(|max| max.unwrap_or(1))
+            .sum()
+    }
+
+    /// Returns the capacity of the capture vectors: the largest bound if every substring is
+    /// bounded, and the input length `N` otherwise.
+    fn capture_len(&self) -> String {
+        let bounds = self
+            .capture_bounds
+            .iter()
+            .copied()
+            .collect::<Option<Vec<_>>>();
+        match bounds.and_then(|bounds| bounds.into_iter().max()) {
+            Some(max) => max.to_string(),
+            None => "N".to_string(),
+        }
+    }
+
+    /// Returns the Noir type of the states, narrowed to `u8` or `u32` for integer states.
+    fn state_type(&self, state_type: NoirStateType) -> &'static str {
+        match state_type {
+            NoirStateType::Field => "Field",
+            NoirStateType::Integer if self.free_state_id <= u8::MAX as usize => "u8",
+            NoirStateType::Integer => "u32",
+        }
+    }
+
+    /// Returns the number of code units of the shortest match, or `None` if no accepting
+    /// state is reachable, so that the pattern can never match.
+    fn min_match_len(&self) -> Option<usize> {
+        let mut dist = BTreeMap::from([(self.start_state, 0), (self.init_state, 0)]);
+        let mut queue = VecDeque::from([self.start_state, self.init_state]);
+        while let Some(from) = queue.pop_front() {
+            if self.accept_states.contains(&from) {
+                return Some(dist[&from]);
+            }
+            for &(_, to) in self
+                .transitions
+                .range((from, 0)..(from + 1, 0))
+                .map(|(key, _)| key)
+            {
+                if !dist.contains_key(&to) {
+                    dist.insert(to, dist[&from] + 1);
+                    queue.push_back(to);
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the input type whose code units `next_state` takes, as tagged bytes do not
+    /// fit in a `u8`.
+    fn unit_type(&self, input_type: NoirInputType) -> NoirInputType {
+        if self.tagged {
+            NoirInputType::U16
+        } else {
+            input_type
+        }
+    }
+}
+
+/// Returns, for each substring, the `max_length` of the part capturing it, if any.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the parts.
+///
+/// # Returns
+///
+/// A Vec with one optional bound per substring.
+fn substr_max_lengths(regex_and_dfa: &RegexAndDFA) -> Vec<Option<usize>> {
+    let mut max_lengths = vec![None; regex_and_dfa.substrings.substring_ranges.len()];
+    for part in &regex_and_dfa.parts {
+        if let Some(idx) = part.substr_idx {
+            max_lengths[idx] = part.max_length;
+        }
+    }
+    max_lengths
+}
+
+/// Returns, for each substring, the capacity of its capture vector: the `max_length` of the
+/// part capturing it or the length of its longest capture, whichever is smaller, if either is
+/// bounded.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the DFA and parts.
+///
+/// # Returns
+///
+/// A Vec with one optional capacity per substring.
+pub(crate) fn substr_capture_bounds(regex_and_dfa: &RegexAndDFA) -> Vec<Option<usize>> {
+    substr_max_lengths(regex_and_dfa)
+        .into_iter()
+        .zip(max_capture_lens(regex_and_dfa))
+        .map(|bounds| match bounds {
+            (Some(declared), Some(inferred)) => Some(declared.min(inferred)),
+            (declared, inferred) => declared.or(inferred),
+        })
+        .collect()
+}
+
+/// Lists the substrings whose `max_length` is shorter than their longest capture, so that the
+/// matcher rejects the inputs with a longer capture.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the DFA and parts.
+///
+/// # Returns
+///
+/// A warning per such substring.
+pub(crate) fn capture_bound_warnings(regex_and_dfa: &RegexAndDFA) -> Vec<String> {
+    substr_max_lengths(regex_and_dfa)
+        .into_iter()
+        .zip(max_capture_lens(regex_and_dfa))
+        .enumerate()
+        .filter_map(|(idx, bounds)| match bounds {
+            (Some(declared), Some(inferred)) if declared < inferred => Some(format!(
+                "substring {idx} has max_length {declared} but its captures can be {inferred} \
+                 bytes long, so the longer ones are rejected"
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the `next_state` function of the generated code, 0 standing for no transition.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the automaton.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+///
+/// # Returns
+///
+/// A closure taking the current state and a code unit to the next state.
+fn next_state_closure(
+    automaton: &NoirAutomaton,
+    accept_sink: Option<usize>,
+) -> impl Fn(usize, u32) -> usize + '_ {
+    let outgoing = outgoing_transitions(automaton);
+    let finished = move |s: usize| automaton.accept_states.contains(&s) || Some(s) == accept_sink;
+    move |s, unit| {
+        outgoing
+            .get(&s)
+            .and_then(|targets| {
+                targets.iter().find(|(_, ranges)| {
+                    ranges.iter().any(|&(min, max)| (min..=max).contains(&unit))
+                })
+            })
+            .map(|&(to, _)| to)
+            .or_else(|| accept_sink.filter(|_| finished(s)))
+            .unwrap_or(RESET_STATE)
+    }
+}
+
+/// Returns the least input length from which the Noir matcher accepts every input, if any.
+///
+/// The matcher restarts from the start state whenever no transition applies, so it is a
+/// deterministic automaton over the code units. The states it can be in after `n` or more
+/// code units only shrink as `n` grows, and every input of at least `n` code units is
+/// accepted once they are all accepting.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to an automaton whose code units are not tagged.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `alphabet` - The code unit ranges the input may hold.
+///
+/// # Returns
+///
+/// The least such length, or `None` if arbitrarily long inputs are rejected.
+fn trivial_match_len(
+    automaton: &NoirAutomaton,
+    accept_sink: Option<usize>,
+    alphabet: &[(u32, u32)],
+) -> Option<usize> {
+    let finished = |s: usize| automaton.accept_states.contains(&s) || Some(s) == accept_sink;
+    let next_state = next_state_closure(automaton, accept_sink);
+    let step = |s: usize, unit: u32| match next_state(s, unit) {
+        RESET_STATE => next_state(automaton.start_state, unit),
+        next => next,
+    };
+    // One code unit per interval of the alphabet on which every state behaves the same.
+    let mut bounds: BTreeSet<u32> = alphabet.iter().map(|&(min, _)| min).collect();
+    for &(min, max) in automaton.transitions.values().flatten() {
+        bounds.insert(min);
+        bounds.extend(max.checked_add(1));
+    }
+    let units = bounds
+        .into_iter()
+        .filter(|unit| {
+            alphabet
+                .iter()
+                .any(|(min, max)| (min..=max).contains(&unit))
+        })
+        .collect_vec();
+
+    // The states reachable after at least `len` code units.
+    let mut states = BTreeSet::from([automaton.init_state]);
+    let mut frontier = states.clone();
+    while let Some(s) = frontier.pop_first() {
+        for &unit in &units {
+            let next = step(s, unit);
+            if states.insert(next) {
+                frontier.insert(next);
+            }
+        }
+    }
+    let mut len = 0;
+    loop {
+        if states.iter().all(|&s| finished(s)) {
+            return Some(len);
+        }
+        let next: BTreeSet<_> = states
+            .iter()
+            .flat_map(|&s| units.iter().map(move |&unit| (s, unit)))
+            .map(|(s, unit)| step(s, unit))
+            .collect();
+        if next == states {
+            return None;
+        }
+        states = next;
+        len += 1;
+    }
+}
+
+/// Warns when the Noir matcher accepts every input, or every input from some length on, as
+/// a stray `.*` easily makes it do, since proving such a match shows nothing about the input.
+///
+/// The inputs are those of the input alphabet if set, and ASCII otherwise.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// The warning, if the pattern is trivial.
+pub(crate) fn trivial_match_warning(
+    regex_and_dfa: &RegexAndDFA,
+    options: &NoirOptions,
+) -> Option<String> {
+    let automaton = build_automaton(regex_and_dfa, options, false);
+    // Counters and later occurrences reject inputs the transitions alone accept.
+    if !automaton.counters.is_empty() || options.occurrence.unwrap_or(1) > 1 {
+        return None;
+    }
+    let accept_sink = match regex_and_dfa.has_end_anchor && !options.reverse {
+        true => None,
+        false => Some(automaton.free_state_id),
+    };
+    // Without an alphabet, an input ending inside a multi-byte character is rejected by
+    // patterns such as `.*`, so the inputs are taken to be ASCII.
+    let (alphabet, inputs) = match options.input_alphabet {
+        Some(alphabet) => (alphabet_ranges(&automaton, alphabet), "input"),
+        None => (vec![(0, 0x7f)], "ASCII input"),
+    };
+    let len = trivial_match_len(&automaton, accept_sink, &alphabet)?;
+    // Shorter inputs fail the length assertion of the matcher anyway.
+    Some(
+        match automaton
+            .min_match_len()
+            .is_some_and(|min_len| len <= min_len)
+        {
+            true => format!("the pattern accepts every {inputs}, so proving a match shows nothing"),
+            false => format!(
+                "the pattern accepts every {inputs} of at least {len} code units, so proving a \
+             match shows nothing"
+            ),
+        },
+    )
+}
+
+/// Finds an input that two accepting runs of a tagged automaton capture differently, i.e.
+/// whose bytes the prover can tag in two ways that `regex_match` both accepts.
+///
+/// The two runs are explored side by side over the same bytes, restarting at the same bytes
+/// so that they match the same occurrence of the pattern, and noting whether their captures
+/// differ so far: they do once a byte is captured by one run but not in the same way by the
+/// other, and agree again once both restart and drop their captures.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the tagged automaton.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+///
+/// # Returns
+///
+/// A shortest such input, or `None` if there is none or the search gives up after
+/// `AMBIGUITY_SEARCH_LIMIT` pairs of states.
+fn ambiguous_capture_input(
+    automaton: &NoirAutomaton,
+    accept_sink: Option<usize>,
+) -> Option<Vec<u8>> {
+    let finished = |s: usize| automaton.accept_states.contains(&s) || Some(s) == accept_sink;
+    let next_state = next_state_closure(automaton, accept_sink);
+    // Whether a tagged byte restarts the match, the next state and the tag the byte is
+    // captured with, 0 if it is not captured.
+    let step = |s: usize, byte: u32, tag: u32| {
+        let unit = byte + 256 * tag;
+        let (restart, next) = match next_state(s, unit) {
+            RESET_STATE => (true, next_state(automaton.start_state, unit)),
+            next => (false, next),
+        };
+        let captured = match next != RESET_STATE && Some(next) != accept_sink {
+            true => tag,
+            false => 0,
+        };
+        (restart, next, captured)
+    };
+    // One byte per interval on which every state behaves the same whatever the tag.
+    let mut bytes = BTreeSet::from([0]);
+    for &(min, max) in automaton.transitions.values().flatten() {
+        bytes.insert(min % 256);
+        bytes.insert((max + 1) % 256);
+    }
+    let num_tags = automaton.num_tags() as u32;
+
+    let start = (automaton.init_state, automaton.init_state, false);
+    let mut parents = BTreeMap::from([(start, None)]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(pair @ (p, q, differ)) = queue.pop_front() {
+        if differ && finished(p) && finished(q) {
+            let mut input = vec![];
+            let mut pair = pair;
+            while let Some(&Some((prev, byte))) = parents.get(&pair) {
+                input.push(byte);
+                pair = prev;
+            }
+            input.reverse();
+            return Some(input);
+        }
+        if parents.len() > AMBIGUITY_SEARCH_LIMIT {
+            debug!("gave up looking for an ambiguous capture");
+            return None;
+        }
+        for &byte in &bytes {
+            for (tag_p, tag_q) in (0..num_tags).cartesian_product(0..num_tags) {
+                let (restart_p, next_p, captured_p) = step(p, byte, tag_p);
+                let (restart_q, next_q, captured_q) = step(q, byte, tag_q);
+                if restart_p != restart_q {
+                    continue;
+                }
+                let differ = (differ && !restart_p) || captured_p != captured_q;
+                let next = (next_p, next_q, differ);
+                if let Entry::Vacant(entry) = parents.entry(next) {
+                    entry.insert(Some((pair, byte as u8)));
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Warns when the captures of an input depend on how the prover tags its bytes, as for
+/// `(a+)(a+)`, since an application treating such captures as canonical can be fooled.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// The warning, with an input captured in two ways, if the captures are ambiguous.
+pub(crate) fn capture_ambiguity_warning(
+    regex_and_dfa: &RegexAndDFA,
+    options: &NoirOptions,
+) -> Option<String> {
+    let automaton = build_automaton(regex_and_dfa, options, true);
+    if !automaton.tagged {
+        return None;
+    }
+    let accept_sink = match regex_and_dfa.has_end_anchor && !options.reverse {
+        true => None,
+        false => Some(automaton.free_state_id),
+    };
+    let input = ambiguous_capture_input(&automaton, accept_sink)?;
+    Some(format!(
+        "the captures of \"{}\" can be split in more than one way, so the prover chooses \
+         them and they must not be treated as canonical",
+        input.escape_ascii()
+    ))
+}
+
+/// Warns when the greedy match semantics drop a match, as for `ab|abcd` on `abc`: the
+/// matcher extends the match `ab` into `abc`, fails to reach `abcd` and restarts, so the
+/// input is rejected although it contains a match.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// The warning, with an input whose match is dropped, if the pattern has one.
+pub(crate) fn lost_match_warning(
+    regex_and_dfa: &RegexAndDFA,
+    options: &NoirOptions,
+) -> Option<String> {
+    // Without an accept sink, the whole input must end in an accepting state anyway.
+    if options.match_semantics != NoirMatchSemantics::Greedy
+        || options.reverse
+        || regex_and_dfa.has_end_anchor
+    {
+        return None;
+    }
+    let automaton = build_automaton(regex_and_dfa, options, false);
+    if !automaton.counters.is_empty() || options.occurrence.unwrap_or(1) > 1 {
+        return None;
+    }
+    let accept_sink = Some(automaton.free_state_id);
+    let finished = |s: usize| automaton.accept_states.contains(&s) || Some(s) == accept_sink;
+    let mut parents = BTreeMap::from([(automaton.init_state, None)]);
+    let mut queue = VecDeque::from([automaton.init_state]);
+    while let Some(from) = queue.pop_front() {
+        for (&(_, to), ranges) in automaton.transitions.range((from, 0)..(from + 1, 0)) {
+            let unit = ranges[0].0;
+            if automaton.accept_states.contains(&from) && !finished(to) {
+                let mut units = vec![unit];
+                let mut state = from;
+                while let Some(&Some((prev, unit))) = parents.get(&state) {
+                    units.push(unit);
+                    state = prev;
+                }
+                units.reverse();
+                let next_state = next_state_closure(&automaton, accept_sink);
+                let end = units
+                    .iter()
+                    .fold(automaton.init_state, |s, &unit| next_state(s, unit));
+                if finished(end) {
+                    continue;
+                }
+                let input = fmt_units(&units, options.input_type);
+                return Some(format!(
+                    "\"{}\" is rejected although it starts with a match, because the greedy match \
+                     semantics extend the match and drop it when the extension fails; pass \
+                     --noir-match-semantics longest to keep the last accepting state, or \
+                     first-accept to end matches at the first one",
+                    input
+                ));
+            }
+            if let Entry::Vacant(entry) = parents.entry(to) {
+                entry.insert(Some((from, unit)));
+                queue.push_back(to);
+            }
+        }
+    }
+    None
+}
+
+/// Warns when the first-accept semantics end a match the pattern could extend, as for
+/// `a[0-9]+` on `a12`: the match ends at `a1`, so a capture of the digits is only `1`.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// The warning, with an input whose match is cut short, if the pattern has one.
+pub(crate) fn cut_match_warning(
+    regex_and_dfa: &RegexAndDFA,
+    options: &NoirOptions,
+) -> Option<String> {
+    // See `build_automaton` for where the first-accept semantics apply.
+    if options.match_semantics != NoirMatchSemantics::FirstAccept
+        || options.reverse
+        || regex_and_dfa.has_end_anchor
+        || !regex_and_dfa.counters.is_empty()
+    {
+        return None;
+    }
+    let greedy = NoirOptions {
+        match_semantics: NoirMatchSemantics::Greedy,
+        ..options.clone()
+    };
+    let automaton = build_automaton(regex_and_dfa, &greedy, false);
+    let mut parents = BTreeMap::from([(automaton.init_state, None)]);
+    let mut queue = VecDeque::from([automaton.init_state]);
+    while let Some(from) = queue.pop_front() {
+        let mut transitions = automaton.transitions.range((from, 0)..(from + 1, 0));
+        if let Some((_, ranges)) = automaton
+            .accept_states
+            .contains(&from)
+            .then(|| transitions.next())
+            .flatten()
+        {
+            let mut units = vec![];
+            let mut state = from;
+            while let Some(&Some((prev, unit))) = parents.get(&state) {
+                units.push(unit);
+                state = prev;
+            }
+            units.reverse();
+            let matched = fmt_units(&units, options.input_type);
+            units.push(ranges[0].0);
+            return Some(format!(
+                "the match of \"{}\" ends at \"{}\", because the first-accept semantics end \
+                 matches at the first accepting state; pass --noir-match-semantics greedy to \
+                 extend them as far as the pattern allows",
+                fmt_units(&units, options.input_type),
+                matched
+            ));
+        }
+        for (&(_, to), ranges) in transitions {
+            if let Entry::Vacant(entry) = parents.entry(to) {
+                entry.insert(Some((from, ranges[0].0)));
+                queue.push_back(to);
+            }
+        }
+    }
+    None
+}
+
+/// Formats code units of an input for a warning, escaping the unprintable ones.
+///
+/// # Arguments
+///
+/// * `units` - The code units.
+/// * `input_type` - The type of the code units.
+///
+/// # Returns
+///
+/// The escaped input.
+fn fmt_units(units: &[u32], input_type: NoirInputType) -> String {
+    match input_type {
+        NoirInputType::U16 => units
+            .iter()
+            .filter_map(|&unit| char::from_u32(unit))
+            .collect::<String>()
+            .escape_default()
+            .to_string(),
+        _ => units
+            .iter()
+            .map(|&unit| unit as u8)
+            .collect_vec()
+            .escape_ascii()
+            .to_string(),
+    }
+}
+
+/// Groups sorted code units into inclusive ranges of consecutive values.
+///
+/// # Arguments
+///
+/// * `codes` - An iterator over code units in ascending order.
+///
+/// # Returns
+///
+/// A Vec of (min, max) tuples covering exactly the given code units.
+fn to_ranges(codes: impl IntoIterator<Item = u32>) -> Vec<(u32, u32)> {
+    let mut ranges: Vec<(u32, u32)> = vec![];
+    for code in codes {
+        match ranges.last_mut() {
+            Some((_, max)) if *max + 1 == code => *max = code,
+            _ => ranges.push((code, code)),
+        }
+    }
+    ranges
+}
+
+/// Builds the byte-level automaton from the DFA graph.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the DFA and substring information.
+///
+/// # Returns
+///
+/// A `NoirAutomaton` whose transitions consume one byte each.
+fn build_u8_automaton(regex_and_dfa: &RegexAndDFA) -> NoirAutomaton {
+    let dfa = &regex_and_dfa.dfa;
+    let transitions = dfa
+        .states
+        .par_iter()
+        .flat_map_iter(|state| {
+            state
+                .transitions
+                .iter()
+                .filter(|(_, chars)| !chars.is_empty())
+                .map(move |(next, chars)| {
+                    (
+                        (state.state_id, *next),
+                        to_ranges(chars.iter().map(|&c| c as u32)),
+                    )
+                })
+        })
+        .collect();
+
+    let init_state = dfa
+        .states
+        .first()
+        .and_then(|start| {
+            start
+                .transitions
+                .iter()
+                .find(|(_, chars)| chars.contains(&PRIMING_BYTE))
+                .map(|(next, _)| *next)
+        })
+        .unwrap_or(0);
+
+    NoirAutomaton {
+        transitions,
+        accept_states: collect_accept_states(dfa),
+        substr_transitions: regex_and_dfa.substrings.substring_ranges.clone(),
+        init_state,
+        start_state: 0,
+        free_state_id: next_free_state_id(dfa),
+        tagged: false,
+        max_repetitions: vec![None; regex_and_dfa.substrings.substring_ranges.len()],
+        max_lengths: substr_max_lengths(regex_and_dfa),
+        capture_bounds: substr_capture_bounds(regex_and_dfa),
+        counters: regex_and_dfa.counters.clone(),
+    }
+}
+
+/// Builds an automaton over UTF-16 code units from the byte-level DFA graph.
+///
+/// Every Basic Multilingual Plane character is fed through the byte-level DFA as UTF-8,
+/// and the state it ends in becomes the target of a single code unit transition.
+/// States that are only reachable in the middle of a UTF-8 sequence are dropped.
+/// Surrogate code units have no transitions, so characters outside the BMP never match.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the DFA and substring information.
+///
+/// # Returns
+///
+/// A `NoirAutomaton` whose transitions consume one UTF-16 code unit each.
+fn build_u16_automaton(regex_and_dfa: &RegexAndDFA) -> NoirAutomaton {
+    let dfa = &regex_and_dfa.dfa;
+    let table = dense_transition_table(dfa);
+    let substr_ranges = &regex_and_dfa.substrings.substring_ranges;
+    let init_state = table
+        .first()
+        .and_then(|row| row[PRIMING_BYTE as usize])
+        .unwrap_or(0);
+
+    let mut units: BTreeMap<(usize, usize), BTreeSet<u32>> = BTreeMap::new();
+    let mut substr_transitions = vec![BTreeSet::new(); substr_ranges.len()];
+    let mut reachable = BTreeSet::from([0, init_state]);
+    let mut queue = VecDeque::from([0, init_state]);
+    let mut utf8 = [0u8; 3];
+
+    while let Some(state) = queue.pop_front() {
+        for unit in (0..=0xFFFFu32).filter(|unit| !(0xD800..=0xDFFF).contains(unit)) {
+            let ch = char::from_u32(unit).expect("non-surrogate BMP code points are chars");
+            let mut path = vec![state];
+            for &byte in ch.encode_utf8(&mut utf8).as_bytes() {
+                match table[*path.last().unwrap()][byte as usize] {
+                    Some(next) => path.push(next),
+                    None => break,
+                }
+            }
+            if path.len() != ch.len_utf8() + 1 {
+                continue;
+            }
+
+            let next = *path.last().unwrap();
+            units.entry((state, next)).or_default().insert(unit);
+            for (idx, ranges) in substr_ranges.iter().enumerate() {
+                if path
+                    .windows(2)
+                    .any(|step| ranges.contains(&(step[0], step[1])))
+                {
+                    substr_transitions[idx].insert((state, next));
+                }
+            }
+            if reachable.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    NoirAutomaton {
+        transitions: units
+            .into_iter()
+            .map(|(key, units)| (key, to_ranges(units)))
+            .collect(),
+        accept_states: collect_accept_states(dfa)
+            .intersection(&reachable)
+            .copied()
+            .collect(),
+        substr_transitions,
+        init_state,
+        start_state: 0,
+        free_state_id: next_free_state_id(dfa),
+        tagged: false,
+        max_repetitions: vec![None; regex_and_dfa.substrings.substring_ranges.len()],
+        max_lengths: substr_max_lengths(regex_and_dfa),
+        capture_bounds: substr_capture_bounds(regex_and_dfa),
+        counters: vec![],
+    }
+}
+
+/// Builds the byte-level automaton of a decomposed regex from the DFAs of its parts.
+///
+/// The states of the automaton are sets of `(part, state)` pairs, so that a byte that may
+/// either continue a part or start the next one keeps both possibilities open instead of
+/// committing to one of them. When `tag_captures` is set, every code unit is
+/// `byte + 256 * tag`, where `tag` is one plus the index of the substring the byte belongs
+/// to, or 0 outside of the substrings. A tagged transition only follows the parts of its
+/// tag, so an accepting run reveals exactly the bytes matched by each public part, whatever
+/// branch the part takes.
+///
+/// A part with `max_repetitions` may match one or more times in a row. The first byte of
+/// each repetition is tagged with the number of substrings plus one plus the index of the
+/// substring instead, which tells the matcher to start the next capture.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the DFAs of the parts.
+/// * `tag_captures` - A boolean indicating whether to tag the bytes with their substring.
+///
+/// # Returns
+///
+/// A `NoirAutomaton` whose transitions consume one (tagged) byte each.
+fn build_parts_automaton(regex_and_dfa: &RegexAndDFA, tag_captures: bool) -> NoirAutomaton {
+    // Stands for the start state of a repeated part when a new repetition begins.
+    const ENTRY: usize = usize::MAX;
+
+    let parts = &regex_and_dfa.parts;
+    let num_substrs = regex_and_dfa.substrings.substring_ranges.len();
+    let last = parts.len() - 1;
+    let tables = parts
+        .par_iter()
+        .map(|part| dense_transition_table(&part.dfa))
+        .collect::<Vec<_>>();
+    let accepting = parts
+        .iter()
+        .map(|part| collect_accept_states(&part.dfa))
+        .collect_vec();
+    let start_of = |part: usize| parts[part].dfa.states.first().map_or(0, |s| s.state_id);
+    let repeated = |part: usize| parts[part].max_repetitions.is_some();
+    let entry_of = |part: usize| {
+        if repeated(part) {
+            (part, ENTRY)
+        } else {
+            (part, start_of(part))
+        }
+    };
+    let resolve = |part: usize, state: usize| {
+        if state == ENTRY {
+            start_of(part)
+        } else {
+            state
+        }
+    };
+    let tag_of = |part: usize, state: usize| match parts[part].substr_idx {
+        Some(idx) if tag_captures && state == ENTRY => num_substrs + idx + 1,
+        Some(idx) if tag_captures => idx + 1,
+        _ => 0,
+    };
+    // Completing a part enters the next one, and a repeated part once more.
+    let epsilons = |part: usize, state: usize| {
+        let mut next = vec![];
+        if accepting[part].contains(&resolve(part, state)) {
+            if part < last {
+                next.push(entry_of(part + 1));
+            }
+            if repeated(part) && state != ENTRY {
+                next.push((part, ENTRY));
+            }
+        }
+        next
+    };
+    let step = |part: usize, state: usize, byte: u8| {
+        tables[part][resolve(part, state)][byte as usize].map(|to| (part, to))
+    };
+
+    // Only keep the (part, state) pairs from which the last part can still be completed.
+    let all_states = parts
+        .iter()
+        .enumerate()
+        .flat_map(|(p, part)| {
+            let entry = repeated(p).then_some((p, ENTRY));
+            part.dfa
+                .states
+                .iter()
+                .map(move |state| (p, state.state_id))
+                .chain(entry)
+        })
+        .collect_vec();
+    let mut live: BTreeSet<(usize, usize)> = all_states
+        .iter()
+        .copied()
+        .filter(|&(p, q)| p == last && accepting[p].contains(&resolve(p, q)))
+        .collect();
+    loop {
+        let before = live.len();
+        for &(p, q) in &all_states {
+            let continues =
+                (0..=u8::MAX).any(|byte| step(p, q, byte).is_some_and(|to| live.contains(&to)));
+            if continues || epsilons(p, q).iter().any(|next| live.contains(next)) {
+                live.insert((p, q));
+            }
+        }
+        if live.len() == before {
+            break;
+        }
+    }
+    let closure = |mut set: BTreeSet<(usize, usize)>| {
+        let mut queue = set.iter().copied().collect_vec();
+        while let Some((p, q)) = queue.pop() {
+            for next in epsilons(p, q) {
+                if live.contains(&next) && set.insert(next) {
+                    queue.push(next);
+                }
+            }
+        }
+        set
+    };
+
+    let initial = closure(BTreeSet::from([entry_of(0)]));
+    let mut ids = BTreeMap::from([(initial.clone(), 0)]);
+    let mut queue = VecDeque::from([initial]);
+    let mut units: BTreeMap<(usize, usize), BTreeSet<u32>> = BTreeMap::new();
+    let mut substr_transitions = vec![BTreeSet::new(); num_substrs];
+    let mut accept_states = BTreeSet::new();
+    let num_tags = match tag_captures {
+        true if (0..=last).any(repeated) => 2 * num_substrs + 1,
+        true => num_substrs + 1,
+        false => 1,
+    };
+
+    while let Some(set) = queue.pop_front() {
+        let from = ids[&set];
+        if set
+            .iter()
+            .any(|&(p, q)| p == last && accepting[p].contains(&resolve(p, q)))
+        {
+            accept_states.insert(from);
+        }
+        for tag in 0..num_tags {
+            for byte in 0..=u8::MAX {
+                let moves = set
+                    .iter()
+                    .filter(|&&(p, q)| tag_of(p, q) == tag)
+                    .filter_map(|&(p, q)| step(p, q, byte))
+                    .filter(|pair| live.contains(pair))
+                    .collect::<BTreeSet<_>>();
+                if moves.is_empty() {
+                    continue;
+                }
+                let revealed = moves
+                    .iter()
+                    .filter_map(|&(p, _)| parts[p].substr_idx)
+                    .collect::<BTreeSet<_>>();
+                let next = closure(moves);
+                let to = match ids.get(&next) {
+                    Some(&id) => id,
+                    None => {
+                        let id = ids.len();
+                        ids.insert(next.clone(), id);
+                        queue.push_back(next);
+                        id
+                    }
+                };
+                units
+                    .entry((from, to))
+                    .or_default()
+                    .insert(byte as u32 + 256 * tag as u32);
+                for idx in revealed {
+                    substr_transitions[idx].insert((from, to));
+                }
+            }
+        }
+    }
+
+    let init_state = units
+        .iter()
+        .find(|((from, _), units)| *from == 0 && units.contains(&(PRIMING_BYTE as u32)))
+        .map_or(0, |((_, to), _)| *to);
+
+    NoirAutomaton {
+        transitions: units
+            .into_iter()
+            .map(|(key, units)| (key, to_ranges(units)))
+            .collect(),
+        accept_states,
+        substr_transitions,
+        init_state,
+        start_state: 0,
+        free_state_id: ids.len(),
+        tagged: tag_captures,
+        // Repetitions can only be told apart by their begin tags.
+        max_repetitions: parts
+            .iter()
+            .filter(|part| part.substr_idx.is_some())
+            .map(|part| part.max_repetitions.filter(|_| tag_captures))
+            .collect(),
+        max_lengths: substr_max_lengths(regex_and_dfa),
+        capture_bounds: substr_capture_bounds(regex_and_dfa),
+        counters: vec![],
+    }
+}
+
+/// Sorts and coalesces overlapping or adjacent inclusive ranges.
+fn merge_ranges(mut ranges: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(u32, u32)> = vec![];
+    for (min, max) in ranges {
+        match merged.last_mut() {
+            Some((_, last_max)) if min <= *last_max + 1 => *last_max = (*last_max).max(max),
+            _ => merged.push((min, max)),
+        }
+    }
+    merged
+}
+
+/// Returns the code unit ranges of an input alphabet.
+///
+/// # Arguments
+///
+/// * `automaton` - The automaton whose transitions make up the declared alphabet.
+/// * `alphabet` - The input alphabet.
+///
+/// # Returns
+///
+/// The sorted inclusive code unit ranges of the alphabet.
+fn alphabet_ranges(automaton: &NoirAutomaton, alphabet: NoirInputAlphabet) -> Vec<(u32, u32)> {
+    if alphabet == NoirInputAlphabet::Printable {
+        return vec![(0x09, 0x0a), (0x0d, 0x0d), (0x20, 0x7e)];
+    }
+    let ranges = automaton.transitions.values().flatten().copied();
+    // Tagged code units carry the tag above the byte, which is all the input holds.
+    match automaton.tagged {
+        true => to_ranges(
+            ranges
+                .flat_map(|(min, max)| min..=max)
+                .map(|unit| unit % 256)
+                .collect::<BTreeSet<_>>(),
+        ),
+        false => merge_ranges(ranges.collect()),
+    }
+}
+
+/// An edge of the union automaton built by `build_shared_automata`.
+#[cfg(feature = "fs")]
+struct SharedEdge {
+    /// The target node, or `None` if the transition resets the match.
+    target: Option<usize>,
+    /// The code unit ranges of the transition.
+    ranges: Vec<(u32, u32)>,
+    /// For each substring of the pattern, whether the transition is revealed.
+    captures: Vec<bool>,
+}
+
+/// Merges the automata of several patterns into a single automaton shared by all of them.
+///
+/// The automata are put side by side and states with the same future behaviour, i.e. the
+/// same acceptance, captures and transitions up to equivalence, are merged by partition
+/// refinement. Sub-automata that several patterns have in common, such as the same date
+/// format, therefore only appear once. The accept sink of patterns without an end anchor
+/// is made explicit, so the shared transitions need no per-pattern fallback branch.
+///
+/// The merged states are numbered from 1, keeping 0 as "no transition". A transition to
+/// the initial state of a pattern resets the match, as in the automaton of a single pattern.
+///
+/// # Arguments
+///
+/// * `automata` - The automaton of each pattern, with whether its regex has an end anchor.
+/// * `max_unit` - The largest value of the input type.
+///
+/// # Returns
+///
+/// One automaton per pattern. They all have the same transitions, but their own accepting
+/// states, substring transitions, initial and start states.
+#[cfg(feature = "fs")]
+fn build_shared_automata(automata: &[(NoirAutomaton, bool)], max_unit: u32) -> Vec<NoirAutomaton> {
+    // Collect the states of every pattern as nodes of the union automaton.
+    let mut node_ids: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+    let mut sinks = vec![];
+    for (pattern, (automaton, has_end_anchor)) in automata.iter().enumerate() {
+        let mut states = BTreeSet::from([0, automaton.init_state]);
+        states.extend(
+            automaton
+                .transitions
+                .keys()
+                .flat_map(|&(from, to)| [from, to]),
+        );
+        states.extend(&automaton.accept_states);
+        let sink = (!has_end_anchor).then_some(automaton.free_state_id);
+        states.extend(sink);
+        sinks.push(sink);
+        for state in states {
+            let id = node_ids.len();
+            node_ids.insert((pattern, state), id);
+        }
+    }
+
+    let mut accepting = vec![false; node_ids.len()];
+    let mut edges: Vec<Vec<SharedEdge>> = (0..node_ids.len()).map(|_| vec![]).collect();
+    for (pattern, (automaton, _)) in automata.iter().enumerate() {
+        let node = |state: usize| node_ids[&(pattern, state)];
+        let finished = |state: usize| {
+            automaton.accept_states.contains(&state) || sinks[pattern] == Some(state)
+        };
+        for ((from, to), ranges) in &automaton.transitions {
+            edges[node(*from)].push(SharedEdge {
+                target: (*to != RESET_STATE).then(|| node(*to)),
+                ranges: ranges.clone(),
+                captures: automaton
+                    .substr_transitions
+                    .iter()
+                    .map(|transitions| transitions.contains(&(*from, *to)))
+                    .collect(),
+            });
+        }
+        for (&(p, state), &id) in &node_ids {
+            if p != pattern || !finished(state) {
+                continue;
+            }
+            accepting[id] = true;
+            if let Some(sink) = sinks[pattern] {
+                // Every code unit without a transition moves a finished state to the sink.
+                let covered = merge_ranges(
+                    edges[id]
+                        .iter()
+                        .flat_map(|edge| edge.ranges.clone())
+                        .collect(),
+                );
+                let mut uncovered = vec![];
+                let mut next = 0;
+                for (min, max) in covered {
+                    if min > next {
+                        uncovered.push((next, min - 1));
+                    }
+                    next = max + 1;
+                }
+                if next <= max_unit {
+                    uncovered.push((next, max_unit));
+                }
+                if !uncovered.is_empty() {
+                    edges[id].push(SharedEdge {
+                        target: Some(node(sink)),
+                        ranges: uncovered,
+                        captures: vec![false; automaton.substr_transitions.len()],
+                    });
+                }
+            }
+        }
+    }
+
+    // Refine the partition by acceptance until the states of a class behave the same. A
+    // class reached from one state with different captures is split, as the generated code
+    // reveals code units based on the (from, to) pair of states only.
+    let mut classes = accepting.iter().map(|&a| a as usize).collect_vec();
+    loop {
+        let mut signatures = BTreeMap::new();
+        let refined = (0..classes.len())
+            .map(|node| {
+                let mut grouped = BTreeMap::new();
+                for edge in &edges[node] {
+                    let target = edge.target.map_or(usize::MAX, |target| classes[target]);
+                    grouped
+                        .entry((target, edge.captures.clone()))
+                        .or_insert_with(Vec::new)
+                        .extend(edge.ranges.iter().copied());
+                }
+                let signature = (
+                    classes[node],
+                    grouped
+                        .into_iter()
+                        .map(|(key, ranges)| (key, merge_ranges(ranges)))
+                        .collect_vec(),
+                );
+                let next_id = signatures.len();
+                *signatures.entry(signature).or_insert(next_id)
+            })
+            .collect_vec();
+        let num_classes = signatures.len();
+        let mut split = None;
+        for node_edges in &edges {
+            let mut captures_by_class: BTreeMap<usize, (&Vec<bool>, usize)> = BTreeMap::new();
+            for edge in node_edges.iter() {
+                if let Some(target) = edge.target {
+                    let (captures, _) = *captures_by_class
+                        .entry(refined[target])
+                        .or_insert((&edge.captures, target));
+                    if captures != &edge.captures {
+                        split = Some(target);
+                    }
+                }
+            }
+        }
+        let unchanged = num_classes == classes.iter().unique().count();
+        classes = refined;
+        if let Some(target) = split {
+            classes[target] = classes.len() + num_classes;
+            continue;
+        }
+        if unchanged {
+            break;
+        }
+    }
+
+    // Number the classes from 1 in the order of their first node.
+    let mut numbering: BTreeMap<usize, usize> = BTreeMap::new();
+    for &class in &classes {
+        let next_id = numbering.len() + 1;
+        numbering.entry(class).or_insert(next_id);
+    }
+    let merged_id = |node: usize| numbering[&classes[node]];
+    let mut transitions: BTreeMap<(usize, usize), Vec<(u32, u32)>> = BTreeMap::new();
+    let mut done = BTreeSet::new();
+    for (node, node_edges) in edges.iter().enumerate() {
+        if !done.insert(merged_id(node)) {
+            continue;
+        }
+        for edge in node_edges {
+            if let Some(target) = edge.target {
+                transitions
+                    .entry((merged_id(node), merged_id(target)))
+                    .or_default()
+                    .extend(edge.ranges.iter().copied());
+            }
+        }
+    }
+    let transitions: BTreeMap<_, _> = transitions
+        .into_iter()
+        .map(|(key, ranges)| (key, merge_ranges(ranges)))
+        .collect();
+    let free_state_id = numbering.len() + 1;
+
+    automata
+        .iter()
+        .enumerate()
+        .map(|(pattern, (automaton, _))| {
+            let id = |state: usize| merged_id(node_ids[&(pattern, state)]);
+            let mut accept_states: BTreeSet<usize> = automaton
+                .accept_states
+                .iter()
+                .map(|&state| id(state))
+                .collect();
+            accept_states.extend(sinks[pattern].map(id));
+            NoirAutomaton {
+                transitions: transitions.clone(),
+                accept_states,
+                substr_transitions: automaton
+                    .substr_transitions
+                    .iter()
+                    .map(|pairs| {
+                        pairs
+                            .iter()
+                            .filter(|&&(_, to)| to != RESET_STATE)
+                            .filter_map(|&(from, to)| {
+                                let from = node_ids.get(&(pattern, from))?;
+                                let to = node_ids.get(&(pattern, to))?;
+                                Some((merged_id(*from), merged_id(*to)))
+                            })
+                            .collect()
+                    })
+                    .collect(),
+                init_state: id(automaton.init_state),
+                start_state: id(0),
+                free_state_id,
+                tagged: automaton.tagged,
+                max_repetitions: automaton.max_repetitions.clone(),
+                max_lengths: automaton.max_lengths.clone(),
+                capture_bounds: automaton.capture_bounds.clone(),
+                counters: vec![],
+            }
+        })
+        .collect()
+}
+
+/// Builds the automaton matched by the generated code for the given options.
+///
+/// Decomposed regexes matched over bytes are rebuilt from the DFAs of their parts, with the
+/// bytes tagged with their substring if `tag_captures` is set and there are substrings. The
+/// tags are left out when a later occurrence is selected: they let the prover make any
+/// occurrence fail, so the occurrences are only counted reliably by an untagged run. With
+/// `reverse`, the untagged automaton of the reversed regex is built, for the same reason:
+/// the tags would let the prover skip the last occurrence. See `reverse_regex_and_dfa`. An
+/// automaton matched against the whole input is first anchored with `anchor_start`.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the DFA and substring information.
+/// * `options` - The options controlling the generated code.
+/// * `tag_captures` - A boolean indicating whether to tag the bytes with their substring.
+///
+/// # Returns
+///
+/// A `NoirAutomaton` over the code units of the input type.
+fn build_automaton(
+    regex_and_dfa: &RegexAndDFA,
+    options: &NoirOptions,
+    tag_captures: bool,
+) -> NoirAutomaton {
+    if regex_and_dfa.whole_input {
+        let anchored =
+            anchor_start(regex_and_dfa).expect("an automaton of the algebra accepts some input");
+        return build_automaton(&anchored, options, false);
+    }
+    if options.reverse {
+        // The reversed match always ends at the start of the input.
+        let options = NoirOptions {
+            reverse: false,
+            match_semantics: NoirMatchSemantics::Greedy,
+            ..options.clone()
+        };
+        return build_automaton(&reverse_regex_and_dfa(regex_and_dfa), &options, false);
+    }
+    let tag_captures = tag_captures
+        && !regex_and_dfa.substrings.substring_ranges.is_empty()
+        && options.occurrence.unwrap_or(1) == 1;
+    let mut automaton = match options.input_type {
+        NoirInputType::U8 | NoirInputType::PackedField if !regex_and_dfa.parts.is_empty() => {
+            build_parts_automaton(regex_and_dfa, tag_captures)
+        }
+        NoirInputType::U8 | NoirInputType::PackedField => build_u8_automaton(regex_and_dfa),
+        NoirInputType::U16 => build_u16_automaton(regex_and_dfa),
+    };
+    if options.input_type != NoirInputType::U16 {
+        unprime(&mut automaton);
+    }
+    if options.empty_match == NoirEmptyMatch::Reject {
+        reject_empty_match(&mut automaton);
+    }
+    // An end-anchored match always ends at the end of the input, and a counted repetition
+    // only accepts once its counter is in range.
+    if !regex_and_dfa.has_end_anchor && regex_and_dfa.counters.is_empty() {
+        match options.match_semantics {
+            NoirMatchSemantics::Greedy => {}
+            NoirMatchSemantics::FirstAccept => end_at_first_accept(&mut automaton),
+            NoirMatchSemantics::Longest => end_at_last_accept(&mut automaton),
+        }
+    }
+    debug!(
+        states = automaton.free_state_id + 1,
+        transitions = automaton.transitions.len(),
+        tagged = automaton.tagged,
+        "built the Noir automaton"
+    );
+    automaton
+}
+
+/// Reverses a regex, so that it is matched by scanning the input from its end.
+///
+/// The parts of a decomposed regex are reversed one by one and put in reverse order, each
+/// keeping its substring, and an end anchor becomes the priming byte at the start of the
+/// reversed regex. `validate_noir_options` rules out start anchors, so the reversed regex
+/// is never end-anchored. The substrings are only kept for their number.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct to reverse.
+///
+/// # Returns
+///
+/// The RegexAndDFA struct of the reversed regex.
+fn reverse_regex_and_dfa(regex_and_dfa: &RegexAndDFA) -> RegexAndDFA {
+    let primed = regex_and_dfa.has_end_anchor;
+    RegexAndDFA {
+        regex_pattern: regex_and_dfa.regex_pattern.clone(),
+        dfa: reverse_dfa(&regex_and_dfa.dfa, primed),
+        has_end_anchor: false,
+        substrings: regex_and_dfa.substrings.clone(),
+        parts: regex_and_dfa
+            .parts
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(idx, part)| RegexPartDFA {
+                dfa: reverse_dfa(&part.dfa, primed && idx == 0),
+                ..part.clone()
+            })
+            .collect(),
+        counters: vec![],
+        whole_input: false,
+    }
+}
+
+/// Removes the byte standing for the start anchor (^) from a byte automaton, which then
+/// starts in the state that byte leads to from the start state, if any.
+///
+/// The DFA encodes `^` as the byte 255, which is never valid UTF-8, so every transition on
+/// it is dropped: the matcher starts in the initial state explicitly rather than feeding the
+/// byte, and an input byte 255 resets the match instead of standing for a start anchor
+/// after a restart.
+///
+/// # Arguments
+///
+/// * `automaton` - A mutable reference to the byte automaton, tagged or not.
+fn unprime(automaton: &mut NoirAutomaton) {
+    let start = automaton.start_state;
+    automaton.init_state = automaton
+        .transitions
+        .range((start, 0)..(start + 1, 0))
+        .find(|(_, ranges)| {
+            ranges
+                .iter()
+                .any(|&(min, max)| (min..=max).contains(&(PRIMING_BYTE as u32)))
+        })
+        .map_or(start, |(&(_, to), _)| to);
+    automaton.transitions.retain(|_, ranges| {
+        let units = ranges
+            .iter()
+            .flat_map(|&(min, max)| min..=max)
+            .filter(|unit| unit % 256 != PRIMING_BYTE as u32)
+            .collect::<BTreeSet<_>>();
+        *ranges = to_ranges(units);
+        !ranges.is_empty()
+    });
+    let transitions = &automaton.transitions;
+    for substr in automaton.substr_transitions.iter_mut() {
+        substr.retain(|key| transitions.contains_key(key));
+    }
+    for counter in automaton.counters.iter_mut() {
+        counter
+            .transitions
+            .retain(|key| transitions.contains_key(key));
+    }
+}
+
+/// Makes the automaton require at least one consumed code unit before accepting.
+///
+/// The start and initial states have not consumed anything, so they must not accept. If
+/// one of them is accepting, it is replaced by an accepting copy in every transition that
+/// reaches it after consuming a code unit, and the original state stops accepting. A
+/// transition back to the start state therefore continues the match into the copy instead
+/// of restarting it.
+///
+/// # Arguments
+///
+/// * `automaton` - A mutable reference to the automaton to update.
+fn reject_empty_match(automaton: &mut NoirAutomaton) {
+    let start = automaton.start_state;
+    let init = automaton.init_state;
+    for state in vec![start, init].into_iter().unique() {
+        if !automaton.accept_states.remove(&state) {
+            continue;
+        }
+        let copy = automaton.free_state_id;
+        automaton.free_state_id += 1;
+        automaton.accept_states.insert(copy);
+
+        let outgoing = automaton
+            .transitions
+            .iter()
+            .filter(|((from, _), _)| *from == state)
+            .map(|((_, to), ranges)| (*to, ranges.clone()))
+            .collect_vec();
+        for (to, ranges) in outgoing {
+            automaton.transitions.insert((copy, to), ranges);
+            let counted = automaton
+                .counters
+                .iter_mut()
+                .map(|counter| &mut counter.transitions);
+            for substr in automaton.substr_transitions.iter_mut().chain(counted) {
+                if substr.contains(&(state, to)) {
+                    substr.insert((copy, to));
+                }
+            }
+        }
+
+        let incoming = automaton
+            .transitions
+            .keys()
+            .filter(|(_, to)| *to == state)
+            .map(|(from, _)| *from)
+            .collect_vec();
+        for from in incoming {
+            let ranges = automaton
+                .transitions
+                .remove(&(from, state))
+                .unwrap_or_default();
+            if !ranges.is_empty() {
+                let entry = automaton.transitions.entry((from, copy)).or_default();
+                entry.extend(ranges);
+                *entry = merge_ranges(entry.clone());
+                let counted = automaton
+                    .counters
+                    .iter_mut()
+                    .map(|counter| &mut counter.transitions);
+                for substr in automaton.substr_transitions.iter_mut().chain(counted) {
+                    if substr.contains(&(from, state)) {
+                        substr.insert((from, copy));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Makes every match end at the first accepting state it reaches.
+///
+/// The transitions leaving the accepting states are dropped, so the matcher enters the
+/// accept sink right after the first accepting state instead of extending the match. The
+/// states only reachable through those transitions are dropped with them.
+///
+/// # Arguments
+///
+/// * `automaton` - A mutable reference to the automaton to update.
+fn end_at_first_accept(automaton: &mut NoirAutomaton) {
+    let accept_states = &automaton.accept_states;
+    automaton
+        .transitions
+        .retain(|(from, _), _| !accept_states.contains(from));
+    let mut reachable = BTreeSet::from([automaton.start_state, automaton.init_state]);
+    let mut queue = reachable.iter().copied().collect::<VecDeque<_>>();
+    while let Some(state) = queue.pop_front() {
+        for &(_, to) in automaton
+            .transitions
+            .range((state, 0)..(state + 1, 0))
+            .map(|(k, _)| k)
+        {
+            if reachable.insert(to) {
+                queue.push_back(to);
+            }
+        }
+    }
+    automaton
+        .transitions
+        .retain(|(from, _), _| reachable.contains(from));
+    automaton
+        .accept_states
+        .retain(|state| reachable.contains(state));
+    let transitions = &automaton.transitions;
+    for substr in automaton.substr_transitions.iter_mut() {
+        substr.retain(|key| transitions.contains_key(key));
+    }
+    for counter in automaton.counters.iter_mut() {
+        counter
+            .transitions
+            .retain(|key| transitions.contains_key(key));
+    }
+}
+
+/// Collects the states a match may pass through after an accepting state before it reaches
+/// another one, as the state after `abc` for `ab(cd)?`.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the automaton.
+///
+/// # Returns
+///
+/// The non-accepting states reachable from an accepting state through non-accepting states.
+fn extension_states(automaton: &NoirAutomaton) -> BTreeSet<usize> {
+    let accept_states = &automaton.accept_states;
+    let mut extensions = BTreeSet::new();
+    let mut queue = accept_states.iter().copied().collect::<VecDeque<_>>();
+    while let Some(state) = queue.pop_front() {
+        for &(_, to) in automaton
+            .transitions
+            .range((state, 0)..(state + 1, 0))
+            .map(|(k, _)| k)
+        {
+            if !accept_states.contains(&to) && extensions.insert(to) {
+                queue.push_back(to);
+            }
+        }
+    }
+    extensions
+}
+
+/// Makes every match end at the last accepting state it reaches.
+///
+/// Each state of `extension_states` gets an accepting copy, which replaces it in the
+/// transitions leaving the accepting states and the copies. A match extended past an
+/// accepting state therefore stays in the copies until it reaches another accepting state,
+/// and enters the accept sink instead of restarting when the extension fails, so `ab(cd)?`
+/// accepts `abc`.
+///
+/// # Arguments
+///
+/// * `automaton` - A mutable reference to the automaton to update.
+fn end_at_last_accept(automaton: &mut NoirAutomaton) {
+    let copies = extension_states(automaton)
+        .into_iter()
+        .map(|state| {
+            automaton.free_state_id += 1;
+            (state, automaton.free_state_id - 1)
+        })
+        .collect::<BTreeMap<_, _>>();
+    let mut transitions = BTreeMap::new();
+    let mut renamed = vec![];
+    for (&(from, to), ranges) in &automaton.transitions {
+        let target = copies.get(&to).copied().unwrap_or(to);
+        let key = if automaton.accept_states.contains(&from) {
+            (from, target)
+        } else {
+            (from, to)
+        };
+        transitions.insert(key, ranges.clone());
+        renamed.push(((from, to), key));
+        if let Some(&copy) = copies.get(&from) {
+            transitions.insert((copy, target), ranges.clone());
+            renamed.push(((from, to), (copy, target)));
+        }
+    }
+    automaton.transitions = transitions;
+    automaton.accept_states.extend(copies.values());
+    let counted = automaton
+        .counters
+        .iter_mut()
+        .map(|counter| &mut counter.transitions);
+    for substr in automaton.substr_transitions.iter_mut().chain(counted) {
+        *substr = renamed
+            .iter()
+            .filter(|(key, _)| substr.contains(key))
+            .map(|&(_, key)| key)
+            .collect();
+    }
+}
+
+/// Builds a dense `[state][byte] -> next state` lookup table from the DFA graph.
+fn dense_transition_table(dfa: &DFAGraph) -> Vec<[Option<usize>; 256]> {
+    let mut table = vec![[None; 256]; next_free_state_id(dfa)];
+    for state in &dfa.states {
+        let row = &mut table[state.state_id];
+        for (next, chars) in &state.transitions {
+            for &c in chars {
+                row[c as usize] = Some(*next);
+            }
+        }
+    }
+    table
+}
+
+/// Collects the IDs of all accepting states in the DFA graph.
+fn collect_accept_states(dfa: &DFAGraph) -> BTreeSet<usize> {
+    dfa.states
+        .iter()
+        .filter(|state| state.state_type == "accept")
+        .map(|state| state.state_id)
+        .collect()
+}
+
+/// Returns the smallest state ID that is greater than every state ID in the DFA graph.
+fn next_free_state_id(dfa: &DFAGraph) -> usize {
+    dfa.states
+        .iter()
+        .map(|state| state.state_id + 1)
+        .max()
+        .unwrap_or(1)
+}
+
+/// A boolean Noir expression, kept structured so that it can be laid out like `nargo fmt` does.
+#[derive(Debug, Clone)]
+enum Condition {
+    /// A parenthesized comparison, e.g. `(s == 1)`.
+    Atom(String),
+    /// A disjunction of conditions.
+    Or(Vec<Condition>),
+    /// A conjunction of conditions.
+    And(Vec<Condition>),
+}
+
+impl Condition {
+    /// Builds the disjunction of the given conditions, collapsing a single condition.
+    fn any(mut terms: Vec<Condition>) -> Condition {
+        if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            Condition::Or(terms)
+        }
+    }
+
+    /// Renders the condition on a single line.
+    fn flat(&self) -> String {
+        match self {
+            Condition::Atom(atom) => atom.clone(),
+            Condition::Or(terms) => terms.iter().map(Condition::flat_operand).join(" | "),
+            Condition::And(terms) => terms.iter().map(Condition::flat_operand).join(" & "),
+        }
+    }
+
+    /// Renders the condition on a single line as the operand of a binary operator.
+    fn flat_operand(&self) -> String {
+        match self {
+            Condition::Atom(atom) => atom.clone(),
+            _ => format!("({})", self.flat()),
+        }
+    }
+
+    /// Lays out the condition starting at column `start`.
+    ///
+    /// If the condition does not fit on one line, each operand after the first is moved to its
+    /// own line, indented one level deeper than `indent` and led by the operator.
+    ///
+    /// # Arguments
+    ///
+    /// * `indent` - The indentation of the statement containing the condition.
+    /// * `start` - The column the first line of the condition starts at.
+    /// * `reserve` - The number of columns needed after the condition on its last line.
+    ///
+    /// # Returns
+    ///
+    /// The lines of the condition. The first line is not indented, the others are.
+    fn layout(&self, indent: usize, start: usize, reserve: usize) -> Vec<String> {
+        let flat = self.flat();
+        let (terms, op) = match self {
+            _ if start + flat.len() + reserve <= MAX_WIDTH => return vec![flat],
+            Condition::Atom(_) => return vec![flat],
+            Condition::Or(terms) => (terms, "|"),
+            Condition::And(terms) => (terms, "&"),
+        };
+        let continuation = indent + 4;
+        let mut lines = terms[0].layout_operand(indent, start, 0);
+        for (i, term) in terms.iter().enumerate().skip(1) {
+            let prefix = format!("{}{op} ", " ".repeat(continuation));
+            let term_reserve = if i + 1 == terms.len() { reserve } else { 0 };
+            let mut operand = term
+                .layout_operand(continuation, prefix.len(), term_reserve)
+                .into_iter();
+            lines.push(format!("{prefix}{}", operand.next().unwrap_or_default()));
+            lines.extend(operand);
+        }
+        lines
+    }
+
+    /// Lays out the condition as the operand of a binary operator, see `layout`.
+    ///
+    /// A compound operand that does not fit is opened with a parenthesis on the current line,
+    /// laid out one level deeper, and closed on its own line.
+    fn layout_operand(&self, indent: usize, start: usize, reserve: usize) -> Vec<String> {
+        let flat = self.flat_operand();
+        if matches!(self, Condition::Atom(_)) || start + flat.len() + reserve <= MAX_WIDTH {
+            return vec![flat];
+        }
+        let inner = indent + 4;
+        let mut body = self.layout(inner, inner, 0).into_iter();
+        let mut lines = vec!["(".to_string()];
+        lines.push(format!(
+            "{}{}",
+            " ".repeat(inner),
+            body.next().unwrap_or_default()
+        ));
+        lines.extend(body);
+        lines.push(format!("{})", " ".repeat(indent)));
+        lines
+    }
+}
+
+/// Lays out an `if` (or `} else if`) header, moving the brace to its own line when the
+/// condition spans several lines.
+///
+/// # Arguments
+///
+/// * `indent` - The indentation of the statement.
+/// * `keyword` - The keyword(s) introducing the condition, e.g. `if` or `} else if`.
+/// * `condition` - The condition of the branch.
+///
+/// # Returns
+///
+/// The lines of the header, including the opening brace.
+fn fmt_if(indent: usize, keyword: &str, condition: &Condition) -> Vec<String> {
+    let pad = " ".repeat(indent);
+    let head = format!("{pad}{keyword} ");
+    let mut body = condition.layout(indent, head.len(), 2).into_iter();
+    let mut lines = vec![format!("{head}{}", body.next().unwrap_or_default())];
+    lines.extend(body);
+    if lines.len() == 1 {
+        lines[0].push_str(" {");
+    } else {
+        lines.push(format!("{pad}{{"));
+    }
+    lines
+}
+
+/// Lays out an `assert(condition, message);` statement.
+///
+/// # Arguments
+///
+/// * `indent` - The indentation of the statement.
+/// * `condition` - The asserted condition.
+/// * `message` - The Noir expression of the failure message.
+///
+/// # Returns
+///
+/// The lines of the statement.
+fn fmt_assert(indent: usize, condition: &Condition, message: &str) -> Vec<String> {
+    let pad = " ".repeat(indent);
+    let line = format!("{pad}assert({}, {message});", condition.flat());
+    if line.len() <= MAX_WIDTH {
+        return vec![line];
+    }
+    let inner = indent + 4;
+    let inner_pad = " ".repeat(inner);
+    let mut body = condition.layout(inner, inner, 1).into_iter();
+    let mut lines = vec![
+        format!("{pad}assert("),
+        format!("{inner_pad}{}", body.next().unwrap_or_default()),
+    ];
+    lines.extend(body);
+    if let Some(last) = lines.last_mut() {
+        last.push(',');
+    }
+    lines.push(format!("{inner_pad}{message},"));
+    lines.push(format!("{pad});"));
+    lines
+}
+
+/// Lays out a function signature, putting each parameter on its own line when it does not fit.
+///
+/// # Arguments
+///
+/// * `decl` - The declaration up to the parameter list, e.g. `pub fn regex_match<let N: u32>`.
+/// * `params` - The parameters of the function.
+/// * `return_type` - The return type including the leading arrow, or an empty string.
+///
+/// # Returns
+///
+/// The lines of the signature, including the opening brace.
+fn fmt_fn_signature(decl: &str, params: &[String], return_type: &str) -> Vec<String> {
+    let line = format!("{decl}({}){return_type} {{", params.join(", "));
+    if line.len() <= MAX_WIDTH {
+        return vec![line];
+    }
+    let mut lines = vec![format!("{decl}(")];
+    lines.extend(params.iter().map(|param| format!("    {param},")));
+    lines.push(format!("){return_type} {{"));
+    lines
+}
+
+/// Builds a Noir condition checking that `var` lies within one of the given ranges.
+///
+/// # Arguments
+///
+/// * `var` - The name of the Noir variable holding the code unit.
+/// * `ranges` - The inclusive code unit ranges.
+/// * `max_unit` - The largest value of the input type, used to drop redundant bounds.
+///
+/// # Returns
+///
+/// A String containing the Noir boolean expression.
+fn ranges_condition(var: &str, ranges: &[(u32, u32)], max_unit: u32) -> Condition {
+    let conditions = ranges
+        .iter()
+        .map(|&(min, max)| match (min, max) {
+            (min, max) if min == max => Condition::Atom(format!("({var} == {min})")),
+            (0, max) => Condition::Atom(format!("({var} <= {max})")),
+            (min, max) if max == max_unit => Condition::Atom(format!("({var} >= {min})")),
+            (min, max) => Condition::And(vec![
+                Condition::Atom(format!("({var} >= {min})")),
+                Condition::Atom(format!("({var} <= {max})")),
+            ]),
+        })
+        .collect_vec();
+
+    Condition::any(conditions)
+}
+
+/// Counts the comparisons `ranges_condition` emits for the given ranges.
+fn range_comparisons(ranges: &[(u32, u32)], max_unit: u32) -> usize {
+    ranges
+        .iter()
+        .map(|&(min, max)| match (min, max) {
+            (min, max) if min == max => 1,
+            (0, _) => 1,
+            (_, max) if max == max_unit => 1,
+            _ => 2,
+        })
+        .sum()
+}
+
+/// Returns the code units up to `max_unit` not covered by the given merged ranges.
+fn complement_ranges(ranges: &[(u32, u32)], max_unit: u32) -> Vec<(u32, u32)> {
+    let mut complement = vec![];
+    let mut next = 0;
+    for &(min, max) in ranges {
+        if min > next {
+            complement.push((next, min - 1));
+        }
+        next = max + 1;
+    }
+    if next <= max_unit {
+        complement.push((next, max_unit));
+    }
+    complement
+}
+
+/// Builds a Noir condition checking that `var` lies within the given self-loop ranges.
+///
+/// States such as those of `.*` loop on most code units and only leave on a few. When the
+/// code units outside the loop take fewer comparisons, the condition checks that `var` lies
+/// outside each of them instead of enumerating the ranges of the loop.
+///
+/// # Arguments
+///
+/// * `var` - The name of the Noir variable holding the code unit.
+/// * `ranges` - The merged inclusive code unit ranges of the self-loop.
+/// * `max_unit` - The largest value of the input type.
+///
+/// # Returns
+///
+/// The conditions to conjoin with the state check, none if the loop takes every code unit,
+/// or `None` if enumerating the ranges of the loop is as cheap.
+fn self_loop_conditions(var: &str, ranges: &[(u32, u32)], max_unit: u32) -> Option<Vec<Condition>> {
+    let exits = complement_ranges(ranges, max_unit);
+    if range_comparisons(&exits, max_unit) >= range_comparisons(ranges, max_unit) {
+        return None;
+    }
+    let conditions = exits
+        .iter()
+        .map(|&(min, max)| match (min, max) {
+            (min, max) if min == max => Condition::Atom(format!("({var} != {min})")),
+            (0, max) => Condition::Atom(format!("({var} > {max})")),
+            (min, max) if max == max_unit => Condition::Atom(format!("({var} < {min})")),
+            (min, max) => Condition::Or(vec![
+                Condition::Atom(format!("({var} < {min})")),
+                Condition::Atom(format!("({var} > {max})")),
+            ]),
+        })
+        .collect();
+    Some(conditions)
+}
+
+/// Builds a Noir condition checking whether `var` equals one of the given states.
+fn states_condition(var: &str, states: &BTreeSet<usize>) -> Condition {
+    Condition::any(
+        states
+            .iter()
+            .map(|state| Condition::Atom(format!("({var} == {state})")))
+            .collect_vec(),
+    )
+}
+
+/// The number of times each transition was taken on each code unit over a corpus, keyed by
+/// (from, to, code unit).
+type TransitionHits = BTreeMap<(usize, usize, u32), usize>;
+
+/// The outgoing transitions of each state, as (target, code unit ranges) pairs.
+type OutgoingTransitions<'a> = BTreeMap<usize, Vec<(usize, &'a [(u32, u32)])>>;
+
+/// Groups the transitions of the automaton by source state.
+fn outgoing_transitions(automaton: &NoirAutomaton) -> OutgoingTransitions<'_> {
+    let mut outgoing = OutgoingTransitions::new();
+    for ((from, to), ranges) in &automaton.transitions {
+        outgoing
+            .entry(*from)
+            .or_default()
+            .push((*to, ranges.as_slice()));
+    }
+    outgoing
+}
+
+/// Finds runs of consecutive states that each consume a single literal code unit.
+///
+/// A state belongs to a run if it is not accepting and its only transition goes to the
+/// following state on a single code unit, as is the case for the states spelling out a
+/// fixed keyword.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the automaton to search.
+///
+/// # Returns
+///
+/// A Vec of (first state, code units) tuples, one per run of at least two states.
+fn find_keyword_runs(automaton: &NoirAutomaton) -> Vec<(usize, Vec<u32>)> {
+    let outgoing = outgoing_transitions(automaton);
+    let literal = |state: usize| match outgoing.get(&state).map(Vec::as_slice) {
+        Some([(to, ranges)]) if *to == state + 1 && !automaton.accept_states.contains(&state) => {
+            match ranges {
+                [(min, max)] if min == max => Some(*min),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    let mut runs: Vec<(usize, Vec<u32>)> = vec![];
+    for &state in outgoing.keys() {
+        if let Some(unit) = literal(state) {
+            match runs.last_mut() {
+                Some((first, units)) if *first + units.len() == state => units.push(unit),
+                _ => runs.push((state, vec![unit])),
+            }
+        }
+    }
+    runs.retain(|(_, units)| units.len() >= 2);
+    runs
+}
+
+/// Lays out a `global` array, packing the items onto as few lines as fit.
+///
+/// # Arguments
+///
+/// * `name` - The name of the global.
+/// * `typ` - The Noir type of the array elements.
+/// * `items` - The array elements.
+///
+/// # Returns
+///
+/// The lines of the global declaration.
+fn fmt_global_array(name: &str, typ: &str, items: &[String]) -> Vec<String> {
+    let head = format!("global {name}: [{typ}; {}] = [", items.len());
+    let line = format!("{head}{}];", items.join(", "));
+    if line.len() <= MAX_WIDTH {
+        return vec![line];
+    }
+
+    let mut lines = vec![head];
+    let mut packed = ArrayLines::default();
+    lines.extend(items.iter().filter_map(|item| packed.push(item)));
+    lines.push(packed.finish());
+    lines.push("];".to_string());
+    lines
+}
+
+/// Packs the elements of a `global` array spanning several lines into indented lines of at
+/// most `MAX_WIDTH` characters.
+#[derive(Default)]
+struct ArrayLines {
+    line: String,
+}
+
+impl ArrayLines {
+    /// Adds an element, returning the current line if the element does not fit on it.
+    fn push(&mut self, item: &str) -> Option<String> {
+        let full = !self.line.is_empty() && 4 + self.line.len() + 1 + item.len() + 1 > MAX_WIDTH;
+        let line = full.then(|| format!("    {}", std::mem::take(&mut self.line)));
+        if !self.line.is_empty() {
+            self.line.push(' ');
+        }
+        self.line.push_str(item);
+        self.line.push(',');
+        line
+    }
+
+    /// Returns the last line.
+    fn finish(self) -> String {
+        format!("    {}", self.line)
+    }
+}
+
+/// Names the code unit ranges tested by several transitions, so that their branches call a
+/// predicate function instead of repeating the comparisons.
+///
+/// Only the ranges taking at least two comparisons are named, after the ASCII class they
+/// cover, such as `is_digit`, or `is_class{k}` for the others.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the automaton whose transitions are tested.
+/// * `skipped` - Whether the transitions of a state are not tested by ranges.
+/// * `max_unit` - The largest value of the input type.
+///
+/// # Returns
+///
+/// A BTreeMap from the merged ranges to the name of their predicate.
+fn class_predicates(
+    automaton: &NoirAutomaton,
+    skipped: &dyn Fn(&usize) -> bool,
+    max_unit: u32,
+) -> BTreeMap<Vec<(u32, u32)>, String> {
+    let mut counts: BTreeMap<Vec<(u32, u32)>, usize> = BTreeMap::new();
+    for ((from, _), ranges) in &automaton.transitions {
+        let ranges = merge_ranges(ranges.clone());
+        if !skipped(from) && range_comparisons(&ranges, max_unit) >= 2 {
+            *counts.entry(ranges).or_default() += 1;
+        }
+    }
+    let mut num_classes = 0;
+    counts
+        .into_iter()
+        .filter(|&(_, count)| count >= 2)
+        .map(|(ranges, _)| {
+            let name = match ranges.as_slice() {
+                [(48, 57)] => "is_digit".to_string(),
+                [(97, 122)] => "is_lowercase".to_string(),
+                [(65, 90)] => "is_uppercase".to_string(),
+                [(65, 90), (97, 122)] => "is_alphabetic".to_string(),
+                [(48, 57), (65, 90), (97, 122)] => "is_alphanumeric".to_string(),
+                [(48, 57), (97, 122)] => "is_lowercase_alphanumeric".to_string(),
+                [(48, 57), (65, 90), (95, 95), (97, 122)] => "is_word".to_string(),
+                [(48, 57), (65, 70), (97, 102)] => "is_hex_digit".to_string(),
+                [(9, 13), (32, 32)] => "is_whitespace".to_string(),
+                _ => {
+                    num_classes += 1;
+                    format!("is_class{}", num_classes - 1)
+                }
+            };
+            (ranges, name)
+        })
+        .collect()
+}
+
+/// The automaton a function of the Noir matcher is generated from, with the settings shared by
+/// the functions generated from it.
+#[derive(Clone, Copy)]
+struct CodegenContext<'a> {
+    /// The automaton to generate the function from.
+    automaton: &'a NoirAutomaton,
+    /// The options controlling the generated code.
+    options: &'a NoirOptions,
+    /// The element type of the input array, which differs from that of the options for packed
+    /// inputs.
+    input_type: NoirInputType,
+    /// The absorbing state entered once a match has completed, if any.
+    accept_sink: Option<usize>,
+    /// Whether the substrings are extracted.
+    gen_substrs: bool,
+}
+
+/// Writes the `next_state` function of the Noir matcher, formatting its branches a chunk at
+/// a time.
+///
+/// # Arguments
+///
+/// * `ctx` - The automaton to generate the transitions from, the type of its input and its
+///   accepting sink. The branches are limited by the `max_branches` of the options.
+/// * `keywords` - A boolean indicating whether to look runs of literal code units up in
+///   `global` arrays instead of emitting one branch per state.
+/// * `predicates` - A boolean indicating whether to test the code unit ranges shared by
+///   several branches through predicate functions, see `class_predicates`.
+/// * `hits` - The transitions taken over a corpus, if any, whose most taken branches are
+///   checked first so that the unconstrained matcher leaves the chain sooner. Functions with
+///   more branches than `max_branches` are split by state ranges into `{name}_part{k}` helpers
+///   the function dispatches to.
+/// * `state` - The Noir type of the states.
+/// * `fn_decl` - The declaration of the function up to its name, e.g. `fn next_state`.
+/// * `out` - The writer the Noir function, preceded by the predicates and keyword globals if
+///   any, is written to.
+///
+/// # Returns
+///
+/// An `io::Result` indicating whether the function was written.
+fn write_next_state_fn(
+    ctx: CodegenContext,
+    keywords: bool,
+    predicates: bool,
+    hits: &TransitionHits,
+    state: &str,
+    fn_decl: &str,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let CodegenContext {
+        automaton,
+        input_type,
+        accept_sink,
+        ..
+    } = ctx;
+    let max_branches = ctx.options.max_branches;
+    let (var, typ, max_unit) = input_vars(input_type);
+    let runs = if keywords {
+        find_keyword_runs(automaton)
+    } else {
+        vec![]
+    };
+    let in_run = |state: &usize| {
+        runs.iter()
+            .any(|(first, units)| (*first..*first + units.len()).contains(state))
+    };
+    let predicates = match predicates {
+        true => class_predicates(automaton, &in_run, max_unit),
+        false => BTreeMap::new(),
+    };
+    // The number of times the corpus took the transitions of a branch.
+    let taken = |taken: &dyn Fn(usize, usize, u32) -> bool| {
+        hits.iter()
+            .filter(|(&(from, to, unit), _)| taken(from, to, unit))
+            .map(|(_, &count)| count)
+            .sum::<usize>()
+    };
+
+    // The globals and branches of the states in `lo..hi`.
+    let part = |lo: usize, hi: usize| {
+        let in_part = |state: &usize| (lo..hi).contains(state);
+        let mut globals = vec![];
+        let mut branches = vec![];
+        for (idx, (first, units)) in runs.iter().enumerate() {
+            if !in_part(first) {
+                continue;
+            }
+            let last = first + units.len() - 1;
+            let name = format!("KEYWORD_{idx}");
+            globals.extend(fmt_global_array(
+                &name,
+                typ,
+                &units.iter().map(|unit| unit.to_string()).collect_vec(),
+            ));
+            branches.push((
+                taken(&|from, to, _| (*first..=last).contains(&from) && to == from + 1),
+                Condition::And(vec![
+                    Condition::Atom(format!("((s as u32) >= {first})")),
+                    Condition::Atom(format!("((s as u32) <= {last})")),
+                ]),
+                vec![
+                    format!("if {var} == {name}[(s as u32) - {first}] {{"),
+                    "    next = s + 1;".to_string(),
+                    "}".to_string(),
+                ],
+            ));
+        }
+        // The states looping on most code units stay unless the code unit leaves the loop.
+        let mut self_loops = BTreeSet::new();
+        for ((from, to), ranges) in &automaton.transitions {
+            if from != to || in_run(from) || !in_part(from) {
+                continue;
+            }
+            let ranges = merge_ranges(ranges.clone());
+            if let Some(exits) = self_loop_conditions(var, &ranges, max_unit) {
+                let mut conditions = vec![Condition::Atom(format!("(s == {from})"))];
+                conditions.extend(exits);
+                let condition = match conditions.len() {
+                    1 => conditions.remove(0),
+                    _ => Condition::And(conditions),
+                };
+                branches.push((
+                    taken(&|f, t, _| f == *from && t == *to),
+                    condition,
+                    vec![format!("next = {to};")],
+                ));
+                self_loops.insert(*from);
+            }
+        }
+        // The states entering the same state on the same code unit range share a single branch.
+        let mut sources: BTreeMap<_, BTreeSet<usize>> = BTreeMap::new();
+        for ((from, to), ranges) in &automaton.transitions {
+            let compressed = from == to && self_loops.contains(from);
+            if !in_run(from) && !compressed && in_part(from) {
+                for &range in ranges {
+                    sources.entry((*to, range)).or_default().insert(*from);
+                }
+            }
+        }
+        let mut groups: BTreeMap<_, Vec<(u32, u32)>> = BTreeMap::new();
+        for ((to, range), froms) in sources {
+            groups.entry((froms, to)).or_default().push(range);
+        }
+        branches.par_extend(groups.into_par_iter().map(|((froms, to), ranges)| {
+            let ranges = merge_ranges(ranges);
+            (
+                taken(&|f, t, unit| {
+                    t == to
+                        && froms.contains(&f)
+                        && ranges.iter().any(|&(min, max)| (min..=max).contains(&unit))
+                }),
+                Condition::And(vec![
+                    states_condition("s", &froms),
+                    match predicates.get(&ranges) {
+                        Some(name) => Condition::Atom(format!("{name}({var})")),
+                        None => ranges_condition(var, &ranges, max_unit),
+                    },
+                ]),
+                vec![format!("next = {to};")],
+            )
+        }));
+        // The branches are disjoint, so they can be checked in any order. The sort is stable
+        // and keeps the order of the branches the corpus never took.
+        branches.sort_by_key(|&(taken, _, _)| Reverse(taken));
+        let mut branches = branches
+            .into_iter()
+            .map(|(_, condition, body)| (condition, body))
+            .collect_vec();
+
+        if let Some(sink) = accept_sink {
+            let mut finished_states = automaton.accept_states.clone();
+            finished_states.insert(sink);
+            finished_states.retain(|state| in_part(state));
+            if !finished_states.is_empty() {
+                branches.push((
+                    states_condition("s", &finished_states),
+                    vec![format!("next = {sink};")],
+                ));
+            }
+        }
+        (globals, branches)
+    };
+
+    // Splits the states into ranges of at most `max_branches` branches, halving the ranges
+    // with more and never splitting a keyword run.
+    let end = automaton
+        .transitions
+        .keys()
+        .flat_map(|&(from, to)| [from, to])
+        .chain(automaton.accept_states.iter().copied())
+        .chain(accept_sink)
+        .max()
+        .map_or(1, |max| max + 1);
+    let mut parts = vec![];
+    let mut pending = vec![(0, end)];
+    while let Some((lo, hi)) = pending.pop() {
+        let (globals, branches) = part(lo, hi);
+        let mid = lo + (hi - lo) / 2;
+        let mid = runs
+            .iter()
+            .find(|(first, units)| *first < mid && mid < first + units.len())
+            .map_or(mid, |(first, units)| match *first > lo {
+                true => *first,
+                false => first + units.len(),
+            });
+        match max_branches {
+            Some(max) if branches.len() > max && lo < mid && mid < hi => {
+                pending.push((mid, hi));
+                pending.push((lo, mid));
+            }
+            _ => parts.push((hi, globals, branches)),
+        }
+    }
+
+    // The predicates precede the functions, dropping those no branch ended up testing.
+    for (ranges, name) in &predicates {
+        let call = format!("{name}({var})");
+        let used = parts.iter().any(|(_, _, branches)| {
+            branches
+                .iter()
+                .any(|(condition, _)| condition.flat().contains(&call))
+        });
+        if used {
+            let mut lines = vec![format!("fn {name}({var}: {typ}) -> bool {{")];
+            let mut body = ranges_condition(var, ranges, max_unit)
+                .layout(4, 4, 0)
+                .into_iter();
+            lines.push(format!("    {}", body.next().unwrap_or_default()));
+            lines.extend(body);
+            lines.push("}".to_string());
+            writeln!(out, "{}\n", lines.join("\n"))?;
+        }
+    }
+
+    let params = [format!("s: {state}"), format!("{var}: {typ}")];
+    let write_part = |decl: &str,
+                      globals: Vec<String>,
+                      branches: Vec<(Condition, Vec<String>)>,
+                      out: &mut dyn Write|
+     -> io::Result<()> {
+        let mut lines = vec![];
+        if !globals.is_empty() {
+            lines.extend(globals);
+            lines.push(String::new());
+        }
+        lines.extend(fmt_fn_signature(decl, &params, &format!(" -> {state}")));
+        lines.push(format!("    let mut next = {RESET_STATE};"));
+        for line in lines {
+            writeln!(out, "{line}")?;
+        }
+        let progress = Progress::new("Noir codegen", "branches", Some(branches.len()));
+        for (chunk_idx, chunk) in branches.chunks(STREAM_CHUNK).enumerate() {
+            let branch_lines = chunk
+                .par_iter()
+                .enumerate()
+                .map(|(i, (condition, body))| {
+                    let keyword = match chunk_idx * STREAM_CHUNK + i {
+                        0 => "if",
+                        _ => "} else if",
+                    };
+                    let mut lines = fmt_if(4, keyword, condition);
+                    lines.extend(body.iter().map(|line| format!("        {line}")));
+                    progress.add(1);
+                    lines
+                })
+                .collect::<Vec<_>>();
+            for line in branch_lines.into_iter().flatten() {
+                writeln!(out, "{line}")?;
+            }
+        }
+        progress.finish();
+        if !branches.is_empty() {
+            writeln!(out, "    }}")?;
+        }
+        writeln!(out, "    next")?;
+        write!(out, "}}")
+    };
+    if parts.len() == 1 {
+        let (_, globals, branches) = parts.remove(0);
+        return write_part(fn_decl, globals, branches, out);
+    }
+
+    // The helpers are private, and the function dispatches to the one of the current state.
+    let helper_decl = format!("{}_part", fn_decl.trim_start_matches("pub "));
+    let helper = helper_decl.rsplit(' ').next().unwrap_or_default();
+    let num_parts = parts.len();
+    let mut dispatch = vec![];
+    for (idx, (hi, globals, branches)) in parts.into_iter().enumerate() {
+        write_part(&format!("{helper_decl}{idx}"), globals, branches, out)?;
+        write!(out, "\n\n")?;
+        let call = format!("        {helper}{idx}(s, {var})");
+        match idx {
+            0 => dispatch.push(format!("    if state < {hi} {{")),
+            _ if idx + 1 == num_parts => dispatch.push("    } else {".to_string()),
+            _ => dispatch.push(format!("    }} else if state < {hi} {{")),
+        }
+        dispatch.push(call);
+    }
+    let mut lines = fmt_fn_signature(fn_decl, &params, &format!(" -> {state}"));
+    lines.push("    let state = s as u32;".to_string());
+    lines.extend(dispatch);
+    lines.push("    }".to_string());
+    for line in lines {
+        writeln!(out, "{line}")?;
+    }
+    write!(out, "}}")
+}
+
+/// Generates a `next_state` function selecting the next state through a binary tree over
+/// the current state.
+///
+/// Each leaf computes the next state of a single state from the code unit, and the inner
+/// nodes split the states in halves, so that every call only compares the current state
+/// against a logarithmic number of bounds.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the automaton to generate the transitions from.
+/// * `input_type` - The element type of the input array.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `state` - The Noir type of the states.
+/// * `fn_decl` - The declaration of the function up to its name, e.g. `fn next_state`.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_next_state_mux_tree_fn(
+    automaton: &NoirAutomaton,
+    input_type: NoirInputType,
+    accept_sink: Option<usize>,
+    state: &str,
+    fn_decl: &str,
+) -> String {
+    let (var, typ, max_unit) = input_vars(input_type);
+    let outgoing = outgoing_transitions(automaton);
+    let mut states: BTreeSet<usize> = outgoing.keys().copied().collect();
+    states.insert(RESET_STATE);
+    states.extend(&automaton.accept_states);
+    states.extend(accept_sink);
+
+    let leaf = |state: usize, indent: usize| {
+        let pad = " ".repeat(indent);
+        let default = match accept_sink {
+            Some(sink) if state == sink || automaton.accept_states.contains(&state) => sink,
+            _ => RESET_STATE,
+        };
+        let transitions = outgoing.get(&state).map(Vec::as_slice).unwrap_or_default();
+        if transitions.is_empty() {
+            return vec![format!("{pad}{default}")];
+        }
+        let mut lines = vec![];
+        for (i, (to, ranges)) in transitions.iter().enumerate() {
+            let keyword = if i == 0 { "if" } else { "} else if" };
+            let ranges = merge_ranges(ranges.to_vec());
+            let condition = match self_loop_conditions(var, &ranges, max_unit) {
+                Some(exits) if *to == state && !exits.is_empty() => Condition::And(exits),
+                _ => ranges_condition(var, &ranges, max_unit),
+            };
+            lines.extend(fmt_if(indent, keyword, &condition));
+            lines.push(format!("{pad}    {to}"));
+        }
+        lines.push(format!("{pad}}} else {{"));
+        lines.push(format!("{pad}    {default}"));
+        lines.push(format!("{pad}}}"));
+        lines
+    };
+
+    fn tree(
+        states: &[usize],
+        indent: usize,
+        leaf: &dyn Fn(usize, usize) -> Vec<String>,
+    ) -> Vec<String> {
+        if states.len() == 1 {
+            return leaf(states[0], indent);
+        }
+        let pad = " ".repeat(indent);
+        let mid = states.len() / 2;
+        let mut lines = vec![format!("{pad}if state < {} {{", states[mid])];
+        lines.extend(tree(&states[..mid], indent + 4, leaf));
+        lines.push(format!("{pad}}} else {{"));
+        lines.extend(tree(&states[mid..], indent + 4, leaf));
+        lines.push(format!("{pad}}}"));
+        lines
+    }
+
+    let mut lines = fmt_fn_signature(
+        fn_decl,
+        &[format!("s: {state}"), format!("{var}: {typ}")],
+        &format!(" -> {state}"),
+    );
+    lines.push("    let state = s as u32;".to_string());
+    lines.extend(tree(&states.into_iter().collect_vec(), 4, &leaf));
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Writes a `next_state` function looking the transitions up in a `global` table, filling
+/// the rows of the table a chunk of states at a time.
+///
+/// The table holds the next state of every (state, byte) pair at index `state * 256 + byte`,
+/// with 0 standing for a missing transition as in the if-chain layout. Only byte inputs are
+/// supported, as a table over UTF-16 code units would have 65536 entries per state. Tagged
+/// bytes widen the rows to 256 entries per tag.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the automaton to generate the transitions from.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `state` - The Noir type of the states.
+/// * `fn_decl` - The declaration of the function up to its name, e.g. `fn next_state`.
+/// * `out` - The writer the Noir global and function are written to.
+///
+/// # Returns
+///
+/// An `io::Result` indicating whether the global and function were written.
+fn write_next_state_table_fn(
+    automaton: &NoirAutomaton,
+    accept_sink: Option<usize>,
+    state: &str,
+    fn_decl: &str,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let num_states = automaton.free_state_id + 1;
+    let width = 256 * automaton.num_tags();
+    let outgoing = outgoing_transitions(automaton);
+    // The table holds at least the rows of the initial state and of the free state, so it
+    // never fits on a single line.
+    writeln!(
+        out,
+        "global TRANSITIONS: [{state}; {}] = [",
+        num_states * width
+    )?;
+    let progress = Progress::new("Noir codegen", "table rows", Some(num_states));
+    let mut packed = ArrayLines::default();
+    for first in (0..num_states).step_by(STREAM_CHUNK) {
+        let rows = STREAM_CHUNK.min(num_states - first);
+        let mut table: Vec<Option<usize>> = vec![None; rows * width];
+        table
+            .par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(idx, row)| {
+                let state = first + idx;
+                for (to, ranges) in outgoing.get(&state).into_iter().flatten() {
+                    for &(min, max) in ranges.iter() {
+                        for unit in min..=max {
+                            row[unit as usize] = Some(*to);
+                        }
+                    }
+                }
+                if let Some(sink) = accept_sink {
+                    if state == sink || automaton.accept_states.contains(&state) {
+                        for entry in row.iter_mut() {
+                            entry.get_or_insert(sink);
+                        }
+                    }
+                }
+                progress.add(1);
+            });
+        for entry in table {
+            if let Some(line) = packed.push(&entry.unwrap_or(RESET_STATE).to_string()) {
+                writeln!(out, "{line}")?;
+            }
+        }
+    }
+    progress.finish();
+    writeln!(out, "{}", packed.finish())?;
+    writeln!(out, "];")?;
+    writeln!(out)?;
+
+    let (var, typ, _) = input_vars(automaton.unit_type(NoirInputType::U8));
+    writeln!(out, "{fn_decl}(s: {state}, {var}: {typ}) -> {state} {{")?;
+    writeln!(
+        out,
+        "    TRANSITIONS[(s as u32) * {width} + ({var} as u32)]"
+    )?;
+    write!(out, "}}")
+}
+
+/// Returns the variable name, Noir type and maximal value of an input code unit.
+fn input_vars(input_type: NoirInputType) -> (&'static str, &'static str, u32) {
+    match input_type {
+        NoirInputType::U8 => ("byte", "u8", u8::MAX as u32),
+        NoirInputType::U16 => ("unit", "u16", u16::MAX as u32),
+        NoirInputType::PackedField => unreachable!("packed inputs are matched as bytes"),
+    }
+}
+
+/// Returns the Noir type of the digest produced by the given input hash.
+fn input_hash_type(input_hash: NoirInputHash) -> &'static str {
+    match input_hash {
+        NoirInputHash::Sha256 => "[u8; 32]",
+        NoirInputHash::Poseidon => "Field",
+    }
+}
+
+/// Builds the Noir expression hashing an input array.
+///
+/// # Arguments
+///
+/// * `input_hash` - The hash function to use.
+/// * `input` - The name of the array to hash.
+/// * `len` - The length of the array.
+/// * `is_field` - Whether the array elements are already Fields.
+/// * `imports` - The paths imported by the generated code.
+///
+/// # Returns
+///
+/// A String containing the Noir expression.
+fn input_hash_expr(
+    input_hash: NoirInputHash,
+    input: &str,
+    len: &str,
+    is_field: bool,
+    imports: &[String],
+) -> String {
+    match input_hash {
+        NoirInputHash::Sha256 => {
+            format!("{}({input})", std_item(imports, "std::hash::sha256"))
+        }
+        NoirInputHash::Poseidon if is_field => format!(
+            "{}::hash({input}, {len})",
+            std_item(imports, "std::hash::poseidon2::Poseidon2")
+        ),
+        NoirInputHash::Poseidon => format!(
+            "{}::hash({input}.map(|c| c as Field), {len})",
+            std_item(imports, "std::hash::poseidon2::Poseidon2")
+        ),
+    }
+}
+
+/// Returns the name the generated code uses for an item of `std`, which is the bare name if
+/// an item with the same name is imported.
+///
+/// # Arguments
+///
+/// * `imports` - The paths imported by the generated code.
+/// * `path` - The path of the item in `std`.
+///
+/// # Returns
+///
+/// The path or name to refer to the item with.
+fn std_item(imports: &[String], path: &str) -> String {
+    let name = path.rsplit("::").next().unwrap_or(path);
+    match imports
+        .iter()
+        .any(|import| import.rsplit("::").next() == Some(name))
+    {
+        true => name.to_string(),
+        false => path.to_string(),
+    }
+}
+
+/// Generates the `use` statements importing the given paths.
+///
+/// # Arguments
+///
+/// * `imports` - The paths to import.
+///
+/// # Returns
+///
+/// The `use` statements, or `None` if there are no imports.
+fn gen_imports(imports: &[String]) -> Option<String> {
+    (!imports.is_empty()).then(|| {
+        imports
+            .iter()
+            .map(|import| format!("use {import};"))
+            .join("\n")
+    })
+}
+
+/// Returns the values returned by the matcher, in order: the captures, their start indices
+/// and the input hash, each present only if enabled.
+fn matcher_return_values<T>(
+    gen_substrs: bool,
+    capture_starts: bool,
+    input_hash: Option<NoirInputHash>,
+    substrs: T,
+    starts: T,
+    hash: impl FnOnce(NoirInputHash) -> T,
+) -> Vec<T> {
+    let mut values = vec![];
+    if gen_substrs {
+        values.push(substrs);
+        if capture_starts {
+            values.push(starts);
+        }
+    }
+    values.extend(input_hash.map(hash));
+    values
+}
+
+/// Returns the return type of the matcher, including the leading arrow, if it returns anything.
+///
+/// The captures of an input of length `len` are returned as `BoundedVec<Field, len>`, and
+/// their start indices as `u32`.
+fn matcher_return_type(
+    num_substrs: usize,
+    len: &str,
+    gen_substrs: bool,
+    capture_starts: bool,
+    input_hash: Option<NoirInputHash>,
+) -> String {
+    let values = matcher_return_values(
+        gen_substrs,
+        capture_starts,
+        input_hash,
+        format!("[BoundedVec<Field, {len}>; {num_substrs}]"),
+        format!("[u32; {num_substrs}]"),
+        |hash| input_hash_type(hash).to_string(),
+    );
+    match values.len() {
+        0 => String::new(),
+        1 => format!(" -> {}", values[0]),
+        _ => format!(" -> ({})", values.join(", ")),
+    }
+}
+
+/// Returns the final expression of the matcher matching `matcher_return_type`, if any.
+fn matcher_return_expr(
+    substrs: &str,
+    starts: &str,
+    gen_substrs: bool,
+    capture_starts: bool,
+    input_hash: Option<NoirInputHash>,
+) -> Option<String> {
+    let values = matcher_return_values(
+        gen_substrs,
+        capture_starts,
+        input_hash,
+        substrs,
+        starts,
+        |_| "input_hash",
+    );
+    match values.len() {
+        0 => None,
+        1 => Some(values[0].to_string()),
+        _ => Some(format!("({})", values.join(", "))),
+    }
+}
+
+/// Returns the variable holding the start index of the given capture variable.
+fn start_var(capture: &str) -> String {
+    capture.replacen("substr", "start", 1)
+}
+
+/// Generates the `regex_match` function of the Noir matcher.
+///
+/// Every substring, and every repetition of a repeated one, has a fixed slot in the returned
+/// array. A substring that does not take part in the match, such as an optional part matched
+/// zero times, is returned as an empty `BoundedVec` in its slot, so the later captures keep
+/// their positions.
+///
+/// # Arguments
+///
+/// * `ctx` - The automaton to match against, the type of its input, its accepting sink and
+///   whether to extract the substrings. Of the options, the capture settings shape the
+///   captures, a later `occurrence` requires an `accept_sink`, whose entry marks a completed
+///   match, and with `reverse` the automaton, built from the reversed regex, scans the last
+///   `reverse_window` code units of the input from its end, the captures then being pushed
+///   backwards and reversed back. The match is bounded by `match_bound` and `capture_count`,
+///   which require an `accept_sink` as well.
+/// * `regex_pattern` - The regex pattern, emitted as a comment.
+/// * `alphabet` - The code unit ranges every input code unit is asserted to lie in, if any.
+/// * `input_hash` - The hash function binding the input, if any.
+/// * `fn_decl` - The declaration of the function up to its name, e.g. `pub fn regex_match`.
+/// * `segments` - The number of input arrays matched one after the other as a single input,
+///   with the state carried from one to the next, or 1 for a single `input`.
+/// * `windowed` - A boolean indicating whether the function takes `start` and `end` indices
+///   and only matches the code units between them, the others leaving the state as it is.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_regex_match_fn(
+    ctx: CodegenContext,
+    regex_pattern: &str,
+    alphabet: Option<&[(u32, u32)]>,
+    input_hash: Option<NoirInputHash>,
+    fn_decl: &str,
+    segments: usize,
+    windowed: bool,
+) -> String {
+    let CodegenContext {
+        automaton,
+        options,
+        input_type,
+        accept_sink,
+        gen_substrs,
+    } = ctx;
+    let capture_overflow = options.capture_overflow;
+    let lowercase_captures = options.lowercase_captures;
+    let capture_starts = options.capture_starts;
+    let occurrence = options.occurrence.unwrap_or(1);
+    let reverse = options.reverse;
+    let window = options.reverse_window.filter(|_| reverse);
+    let imports = &options.imports;
+    let match_bound = options.match_bound;
+    let capture_count = options.capture_count;
+    let (var, typ, max_unit) = input_vars(input_type);
+    let num_substrs = automaton.substr_transitions.len();
+    // The segments `input0`, `input1`, ... have the lengths `N0`, `N1`, ...
+    let segment_lens = (0..segments).map(|k| format!("N{k}")).collect_vec();
+    let total = match segments {
+        1 => "N".to_string(),
+        _ => segment_lens.join(" + "),
+    };
+    let captured = match lowercase_captures {
+        true => format!("to_ascii_lowercase({var}) as Field"),
+        false => format!("{var} as Field"),
+    };
+    // A reversed scan visits the input at `i = N - 1 - k`.
+    let len = window.map_or("N".to_string(), |window| window.to_string());
+    // Pushes the captured code unit, checking the bound of the substring first.
+    let push = |indent: usize, capture: &str, idx: usize| {
+        let pad = " ".repeat(indent);
+        let mut push_lines = vec![];
+        if capture_starts && reverse {
+            push_lines.push(format!("{pad}{} = i;", start_var(capture)));
+        } else if capture_starts {
+            push_lines.push(format!("{pad}if {capture}.len() == 0 {{"));
+            push_lines.push(format!("{pad}    {} = i;", start_var(capture)));
+            push_lines.push(format!("{pad}}}"));
+        }
+        if let Some(max) = automaton.max_lengths[idx] {
+            push_lines.push(format!(
+                "{pad}assert({capture}.len() < {max}, \"substring {idx} is longer than {max}\");"
+            ));
+        }
+        push_lines.push(format!("{pad}{capture}.push({captured});"));
+        push_lines
+    };
+
+    let (generics, params) = match segments {
+        1 => (
+            "<let N: u32>".to_string(),
+            vec![format!("input: [{typ}; N]")],
+        ),
+        _ => (
+            format!(
+                "<{}>",
+                segment_lens
+                    .iter()
+                    .map(|len| format!("let {len}: u32"))
+                    .join(", ")
+            ),
+            segment_lens
+                .iter()
+                .enumerate()
+                .map(|(k, len)| format!("input{k}: [{typ}; {len}]"))
+                .collect_vec(),
+        ),
+    };
+    let mut params = params;
+    if windowed {
+        params.extend(["start: u32".to_string(), "end: u32".to_string()]);
+    }
+    let capture_len = match automaton.capture_len().as_str() {
+        "N" => total.clone(),
+        bound => bound.to_string(),
+    };
+    let mut lines = fmt_fn_signature(
+        &format!("{fn_decl}{generics}"),
+        &params,
+        &matcher_return_type(
+            automaton.num_captures(),
+            &capture_len,
+            gen_substrs,
+            capture_starts,
+            input_hash,
+        ),
+    );
+    lines.push(format!(
+        "    // regex: {}",
+        regex_pattern.replace('\n', "\\n").replace('\r', "\\r")
+    ));
+    if segments > 1 && (input_hash.is_some() || automaton.tagged) {
+        lines.push(
+            "    // The segments joined, to hash them and choose the capture tags.".to_string(),
+        );
+        lines.push(format!("    let mut input = [0; {total}];"));
+        for (k, len) in segment_lens.iter().enumerate() {
+            let offset = segment_lens[..k].join(" + ");
+            lines.push(format!("    for j in 0..{len} {{"));
+            match k {
+                0 => lines.push("        input[j] = input0[j];".to_string()),
+                _ => lines.push(format!("        input[{offset} + j] = input{k}[j];")),
+            }
+            lines.push("    }".to_string());
+        }
+    }
+    if let Some(hash) = input_hash {
+        lines.push(format!(
+            "    let input_hash = {};",
+            input_hash_expr(hash, "input", &total, false, imports)
+        ));
+    }
+    if gen_substrs {
+        for idx in 0..num_substrs {
+            for capture in automaton.capture_vars(idx) {
+                lines.push(format!("    let mut {capture} = BoundedVec::new();"));
+                if capture_starts {
+                    lines.push(format!("    let mut {}: u32 = 0;", start_var(&capture)));
+                }
+            }
+            if automaton.max_repetitions[idx].is_some() {
+                lines.push(format!("    let mut count{idx}: u32 = 0;"));
+            }
+        }
+    }
+    if occurrence > 1 {
+        lines
+            .push("    // The number of occurrences completed before the one matched.".to_string());
+        lines.push("    let mut matches: u32 = 0;".to_string());
+    }
+    if window.is_some() {
+        lines.push(format!(
+            "    assert(N >= {len}, \"the input is shorter than the scanned suffix\");"
+        ));
+    }
+    // N is known at compile time, so a too short input type fails to compile rather than
+    // giving a circuit that rejects every input.
+    if let Some(min_len) = automaton.min_match_len().filter(|&min_len| min_len > 0) {
+        let len = match segments {
+            1 => total.clone(),
+            _ => format!("({total})"),
+        };
+        lines.push(format!(
+            "    assert({len} >= {min_len}, \"the input is shorter than the shortest match of \
+             {min_len} code units\");"
+        ));
+    }
+    if windowed {
+        lines.push("    assert(start <= end, \"the window ends before it starts\");".to_string());
+        lines.push("    assert(end <= N, \"the window ends past the input\");".to_string());
+    }
+    if automaton.tagged {
+        lines.push(
+            "    // The tags are only trusted as far as `next_state` accepts them.".to_string(),
+        );
+        match windowed {
+            true => {
+                lines.push("    let tags = capture_tags_window(input, start, end);".to_string())
+            }
+            false => lines.push("    let tags = capture_tags(input);".to_string()),
+        }
+    }
+    for (k, counter) in automaton.counters.iter().enumerate() {
+        lines.push(format!(
+            "    // The code units matched so far by {}, counted rather than unrolled.",
+            counter.repetition
+        ));
+        lines.push(format!("    let mut reps{k}: u32 = 0;"));
+    }
+    let start = automaton.start_state;
+    lines.push(format!("    let mut s = {};", automaton.init_state));
+    if reverse {
+        lines.push(format!("    for k in 0..{len} {{"));
+        lines.push("        let i = N - 1 - k;".to_string());
+    } else if segments == 1 {
+        lines.push("    for i in 0..N {".to_string());
+    }
+    // The loop body, repeated for every segment.
+    let body_start = lines.len();
+    if segments == 1 {
+        lines.push(format!("        let {var} = input[i];"));
+    }
+    if let Some(alphabet) = alphabet {
+        lines.extend(fmt_assert(
+            8,
+            &ranges_condition(var, alphabet, max_unit),
+            "f\"input[{i}] is outside the alphabet\"",
+        ));
+    }
+    let unit = if automaton.tagged {
+        lines.push(format!(
+            "        let unit = {var} as u16 + 256 * (tags[i] as u16);"
+        ));
+        "unit"
+    } else {
+        var
+    };
+    // Restarts the match from the start state, dropping the captures so far.
+    let restart = |lines: &mut Vec<String>| {
+        lines.push(format!("            s = {start};"));
+        lines.push(format!("            s_next = next_state({start}, {unit});"));
+        for k in 0..automaton.counters.len() {
+            lines.push(format!("            reps{k} = 0;"));
+        }
+        if gen_substrs {
+            for idx in 0..num_substrs {
+                for capture in automaton.capture_vars(idx) {
+                    lines.push(format!("            {capture} = BoundedVec::new();"));
+                    if capture_starts {
+                        lines.push(format!("            {} = 0;", start_var(&capture)));
+                    }
+                }
+                if automaton.max_repetitions[idx].is_some() {
+                    lines.push(format!("            count{idx} = 0;"));
+                }
+            }
+        }
+    };
+    lines.push(format!("        let mut s_next = next_state(s, {unit});"));
+    let counted = |counter: &RepetitionCounter| {
+        Condition::any(
+            counter
+                .transitions
+                .iter()
+                .map(|(from, to)| {
+                    Condition::And(vec![
+                        Condition::Atom(format!("(s == {from})")),
+                        Condition::Atom(format!("(s_next == {to})")),
+                    ])
+                })
+                .collect_vec(),
+        )
+    };
+    for (k, counter) in automaton.counters.iter().enumerate() {
+        lines.push(format!(
+            "        // {} stops at its maximum and is only left after its minimum.",
+            counter.repetition
+        ));
+        let head = format!("        let counted{k} = ");
+        let mut condition = counted(counter).layout(8, head.len(), 1).into_iter();
+        lines.push(format!("{head}{}", condition.next().unwrap_or_default()));
+        lines.extend(condition);
+        if let Some(last) = lines.last_mut() {
+            last.push(';');
+        }
+        if let Some(max) = counter.max {
+            lines.push(format!("        if counted{k} & (reps{k} == {max}) {{"));
+            // An accepting state has no other transition on the code unit once unrolled, so
+            // the completed match goes on to the sink.
+            let accepting = counter
+                .transitions
+                .iter()
+                .map(|&(from, _)| from)
+                .filter(|from| automaton.accept_states.contains(from))
+                .collect::<BTreeSet<_>>();
+            match accept_sink {
+                Some(sink) if !accepting.is_empty() => {
+                    lines.extend(fmt_if(12, "if", &states_condition("s", &accepting)));
+                    lines.push(format!("                s_next = {sink};"));
+                    lines.push("            } else {".to_string());
+                    lines.push(format!("                s_next = {RESET_STATE};"));
+                    lines.push("            }".to_string());
+                }
+                _ => lines.push(format!("            s_next = {RESET_STATE};")),
+            }
+            lines.push("        }".to_string());
+        }
+        if counter.min > 1 {
+            lines.push(format!(
+                "        if !counted{k} & (reps{k} != 0) & (reps{k} < {}) {{",
+                counter.min
+            ));
+            lines.push(format!("            s_next = {RESET_STATE};"));
+            lines.push("        }".to_string());
+        }
+    }
+    lines.push(format!("        if s_next == {RESET_STATE} {{"));
+    lines.push("            // No transition: restart the match from the start state.".to_string());
+    restart(&mut lines);
+    lines.push("        }".to_string());
+    if let (Some(sink), true) = (accept_sink, occurrence > 1) {
+        lines.push(format!(
+            "        if (s_next == {sink}) & (matches < {}) {{",
+            occurrence - 1
+        ));
+        lines.push(
+            "            // An earlier occurrence is complete: count it and look for the next one."
+                .to_string(),
+        );
+        lines.push("            matches += 1;".to_string());
+        restart(&mut lines);
+        lines.push("        }".to_string());
+    }
+    if gen_substrs && automaton.tagged {
+        let matched = |tag: Condition| {
+            let mut atoms = vec![tag, Condition::Atom(format!("(s_next != {RESET_STATE})"))];
+            if let Some(sink) = accept_sink {
+                atoms.push(Condition::Atom(format!("(s_next != {sink})")));
+            }
+            Condition::And(atoms)
+        };
+        for idx in 0..num_substrs {
+            let tag = Condition::Atom(format!("(tags[i] == {})", idx + 1));
+            let max = match automaton.max_repetitions[idx] {
+                Some(max) => max,
+                None => {
+                    lines.extend(fmt_if(8, "if", &matched(tag)));
+                    lines.extend(push(12, &format!("substr{idx}"), idx));
+                    lines.push("        }".to_string());
+                    continue;
+                }
+            };
+            // The first byte of every repetition carries the begin tag.
+            let begin = Condition::Atom(format!("(tags[i] == {})", num_substrs + idx + 1));
+            let captures = automaton.capture_vars(idx);
+            lines.extend(fmt_if(8, "if", &matched(begin.clone())));
+            if capture_overflow == NoirCaptureOverflow::KeepLast {
+                // Once every capture is used, drop the oldest one to make room.
+                lines.push(format!("            if count{idx} == {max} {{"));
+                for (rep, capture) in captures.iter().enumerate().skip(1) {
+                    lines.push(format!(
+                        "                {} = {capture};",
+                        captures[rep - 1]
+                    ));
+                    if capture_starts {
+                        lines.push(format!(
+                            "                {} = {};",
+                            start_var(&captures[rep - 1]),
+                            start_var(capture)
+                        ));
+                    }
+                }
+                lines.push(format!(
+                    "                {} = BoundedVec::new();",
+                    captures[max - 1]
+                ));
+                if capture_starts {
+                    lines.push(format!(
+                        "                {} = 0;",
+                        start_var(&captures[max - 1])
+                    ));
+                }
+                lines.push("            } else {".to_string());
+                lines.push(format!("                count{idx} += 1;"));
+                lines.push("            }".to_string());
+            } else {
+                lines.push(format!("            count{idx} += 1;"));
+            }
+            lines.push("        }".to_string());
+            lines.extend(fmt_if(8, "if", &matched(Condition::Or(vec![tag, begin]))));
+            if capture_overflow == NoirCaptureOverflow::Fail {
+                lines.push(format!(
+                    "            assert(count{idx} <= {max}, \"substring {idx} repeats more than {max} times\");"
+                ));
+            }
+            for (rep, capture) in captures.iter().enumerate() {
+                lines.push(format!("            if count{idx} == {} {{", rep + 1));
+                lines.extend(push(16, capture, idx));
+                lines.push("            }".to_string());
+            }
+            lines.push("        }".to_string());
+        }
+    } else if gen_substrs {
+        for (idx, transitions) in automaton.substr_transitions.iter().enumerate() {
+            if transitions.is_empty() {
+                continue;
+            }
+            let condition = Condition::any(
+                transitions
+                    .iter()
+                    .map(|(from, to)| {
+                        Condition::And(vec![
+                            Condition::Atom(format!("(s == {from})")),
+                            Condition::Atom(format!("(s_next == {to})")),
+                        ])
+                    })
+                    .collect_vec(),
+            );
+            lines.extend(fmt_if(8, "if", &condition));
+            lines.extend(push(12, &format!("substr{idx}"), idx));
+            lines.push("        }".to_string());
+        }
+    }
+    for (k, counter) in automaton.counters.iter().enumerate() {
+        // The match may have restarted, so the transition taken is checked again.
+        lines.extend(fmt_if(8, "if", &counted(counter)));
+        lines.push(format!("            reps{k} += 1;"));
+        lines.push("        } else {".to_string());
+        lines.push(format!("            reps{k} = 0;"));
+        lines.push("        }".to_string());
+    }
+    lines.push("        s = s_next;".to_string());
+    if windowed {
+        let body = lines.split_off(body_start);
+        lines.push(
+            "        // The code units outside the window leave the state as it is.".to_string(),
+        );
+        lines.push("        if (i >= start) & (i < end) {".to_string());
+        lines.extend(body.into_iter().map(|line| format!("    {line}")));
+        lines.push("        }".to_string());
+    }
+    if let (Some(bound), Some(sink)) = (match_bound, accept_sink) {
+        // A match still going on at this index would end past the bound.
+        lines.push(format!("        if i == {bound} {{"));
+        lines.push(format!(
+            "            assert(s == {sink}, \"the match does not complete within the first {bound} code units\");"
+        ));
+        lines.push("        }".to_string());
+    }
+    if segments == 1 {
+        lines.push("    }".to_string());
+    } else {
+        let body = lines.split_off(body_start);
+        // The body only refers to the index in the joined input through the tags, the
+        // alphabet check, the window, the match bound and the capture starts.
+        let indexed = automaton.tagged
+            || alphabet.is_some()
+            || windowed
+            || match_bound.is_some()
+            || (gen_substrs && capture_starts);
+        for (k, len) in segment_lens.iter().enumerate() {
+            match k {
+                0 => {
+                    lines.push(format!("    for i in 0..{len} {{"));
+                    lines.push(format!("        let {var} = input0[i];"));
+                }
+                _ => {
+                    lines.push(format!(
+                        "    // input{k} continues from the state input{} ended in.",
+                        k - 1
+                    ));
+                    lines.push(format!("    for j in 0..{len} {{"));
+                    if indexed {
+                        lines.push(format!(
+                            "        let i = {} + j;",
+                            segment_lens[..k].join(" + ")
+                        ));
+                    }
+                    lines.push(format!("        let {var} = input{k}[j];"));
+                }
+            }
+            lines.extend(body.iter().cloned());
+            lines.push("    }".to_string());
+        }
+    }
+
+    if occurrence > 1 {
+        lines.push(format!(
+            "    assert(matches == {}, \"the pattern occurs fewer than {occurrence} times\");",
+            occurrence - 1
+        ));
+    }
+    let mut accept_states = automaton.accept_states.clone();
+    accept_states.extend(accept_sink);
+    lines.extend(fmt_assert(
+        4,
+        &states_condition("s", &accept_states),
+        "f\"no match: {s}\"",
+    ));
+    for (k, counter) in automaton.counters.iter().enumerate() {
+        if counter.min > 1 {
+            lines.extend(fmt_assert(
+                4,
+                &Condition::Or(vec![
+                    Condition::Atom(format!("(reps{k} == 0)")),
+                    Condition::Atom(format!("(reps{k} >= {})", counter.min)),
+                ]),
+                &format!(
+                    "\"{} matched fewer than {} times\"",
+                    counter
+                        .repetition
+                        .replace('\\', "\\\\")
+                        .replace('"', "\\\""),
+                    counter.min
+                ),
+            ));
+        }
+    }
+    let captures = (0..num_substrs)
+        .flat_map(|idx| automaton.capture_vars(idx))
+        .collect_vec();
+    if let (true, Some(count)) = (gen_substrs, capture_count) {
+        lines.push(
+            "    // The match must produce exactly the captures the application expects."
+                .to_string(),
+        );
+        lines.push("    let mut num_captures: u32 = 0;".to_string());
+        for capture in &captures {
+            lines.push(format!(
+                "    num_captures += ({capture}.len() != 0) as u32;"
+            ));
+        }
+        lines.push(format!(
+            "    assert(num_captures == {count}, f\"the match produces {{num_captures}} captures instead of {count}\");"
+        ));
+    }
+    let substrs = match reverse {
+        true => format!(
+            "[{}]",
+            captures
+                .iter()
+                .map(|c| format!("reverse_capture({c})"))
+                .join(", ")
+        ),
+        false => format!("[{}]", captures.join(", ")),
+    };
+    let starts = format!("[{}]", captures.iter().map(|c| start_var(c)).join(", "));
+    if let Some(expr) =
+        matcher_return_expr(&substrs, &starts, gen_substrs, capture_starts, input_hash)
+    {
+        lines.push(format!("    {expr}"));
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates the unconstrained `capture_tags` function choosing the tag of every byte.
+///
+/// It runs the tagged automaton forwards over the input, keeping every state that some
+/// choice of tags leads to, and then walks back from an accepting state to pick the tags
+/// of one accepting run, restarts included. The matcher checks every tag against
+/// `next_state`, so a dishonest choice can make the proof fail but not alter the captures.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the tagged automaton.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `state` - The Noir type of the states.
+/// * `windowed` - A boolean indicating whether to also generate `capture_tags_window`, which
+///   only runs the automaton over the code units between `start` and `end`.
+///
+/// # Returns
+///
+/// A String containing the Noir functions.
+fn gen_capture_tags_fn(
+    automaton: &NoirAutomaton,
+    accept_sink: Option<usize>,
+    state: &str,
+    windowed: bool,
+) -> String {
+    let num_states = automaton.free_state_id + 1;
+    let num_tags = automaton.num_tags();
+    let start = automaton.start_state;
+    let mut finished_states = automaton.accept_states.clone();
+    finished_states.extend(accept_sink);
+    let live = |var: &str| {
+        format!("let live = if i == 0 {{ {var} as {state} == initial }} else {{ reachable[i - 1][{var}] }};")
+    };
+    let indent = |lines: Vec<String>| lines.into_iter().map(|line| format!("    {line}"));
+    // Outside the window of `capture_tags_window`, the states are kept as they are.
+    let tags_fn = |windowed: bool| {
+        let (name, params) = match windowed {
+            true => (
+                "capture_tags_window",
+                "input: [u8; N], start: u32, end: u32",
+            ),
+            false => ("capture_tags", "input: [u8; N]"),
+        };
+        let mut step = vec![
+            format!("for tag in 0..{num_tags} {{"),
+            format!("    let s_next = capture_step(s as {state}, input[i], tag);"),
+            "    reachable[i][s_next as u32] = true;".to_string(),
+            "}".to_string(),
+        ];
+        if windowed {
+            let mut outside = vec![
+                "if (i < start) | (i >= end) {".to_string(),
+                "    reachable[i][s] = true;".to_string(),
+                "} else {".to_string(),
+            ];
+            outside.extend(indent(step));
+            outside.push("}".to_string());
+            step = outside;
+        }
+        let mut lines = vec![
+            format!("unconstrained fn {name}<let N: u32>({params}) -> [u8; N] {{"),
+            format!("    let initial: {state} = {};", automaton.init_state),
+            "    // reachable[i][s]: some choice of tags leads to s once input[i] is consumed."
+                .to_string(),
+            format!("    let mut reachable = [[false; {num_states}]; N];"),
+            "    for i in 0..N {".to_string(),
+            format!("        for s in 0..{num_states} {{"),
+            format!("            {}", live("s")),
+            "            if live {".to_string(),
+        ];
+        lines.extend(step.iter().map(|line| format!("                {line}")));
+        lines.extend([
+            "            }".to_string(),
+            "        }".to_string(),
+            "    }".to_string(),
+            "    let mut tags = [0; N];".to_string(),
+            "    if N != 0 {".to_string(),
+            "        let mut s = 0;".to_string(),
+            "        let mut found = false;".to_string(),
+            format!("        for t in 0..{num_states} {{"),
+        ]);
+        lines.extend(fmt_if(
+            12,
+            "if",
+            &Condition::And(vec![
+                Condition::Atom("!found".to_string()),
+                Condition::Atom("reachable[N - 1][t]".to_string()),
+                states_condition("t", &finished_states),
+            ]),
+        ));
+        lines.extend([
+            "                s = t;".to_string(),
+            "                found = true;".to_string(),
+            "            }".to_string(),
+            "        }".to_string(),
+            "        for k in 0..N {".to_string(),
+            "            let i = N - 1 - k;".to_string(),
+        ]);
+        let mut back = vec![
+            "let mut found = false;".to_string(),
+            "let mut prev = 0;".to_string(),
+            format!("for p in 0..{num_states} {{"),
+            format!("    {}", live("p")),
+            format!("    for tag in 0..{num_tags} {{"),
+            format!("        let s_next = capture_step(p as {state}, input[i], tag);"),
+            format!("        if !found & live & (s_next == s as {state}) {{"),
+            "            found = true;".to_string(),
+            "            prev = p;".to_string(),
+            "            tags[i] = tag;".to_string(),
+            "        }".to_string(),
+            "    }".to_string(),
+            "}".to_string(),
+            "s = prev;".to_string(),
+        ];
+        if windowed {
+            let mut inside = vec!["if (i >= start) & (i < end) {".to_string()];
+            inside.extend(indent(back));
+            inside.push("}".to_string());
+            back = inside;
+        }
+        lines.extend(back.iter().map(|line| format!("            {line}")));
+        lines.extend([
+            "        }".to_string(),
+            "    }".to_string(),
+            "    tags".to_string(),
+            "}".to_string(),
+        ]);
+        lines
+    };
+
+    let mut lines = vec![
+        format!("unconstrained fn capture_step(s: {state}, byte: u8, tag: u8) -> {state} {{"),
+        "    let unit = byte as u16 + 256 * (tag as u16);".to_string(),
+        "    let s_next = next_state(s, unit);".to_string(),
+        format!("    if s_next == {RESET_STATE} {{"),
+        format!("        next_state({start}, unit)"),
+        "    } else {".to_string(),
+        "        s_next".to_string(),
+        "    }".to_string(),
+        "}".to_string(),
+        String::new(),
+    ];
+    lines.extend(tags_fn(false));
+    if windowed {
+        lines.push(String::new());
+        lines.extend(tags_fn(true));
+    }
+    lines.join("\n")
+}
+
+/// Generates the `regex_match` entry point taking the input packed 31 bytes per Field.
+///
+/// The Field at index `i` holds the bytes `31 * i .. 31 * i + 31` in little-endian order.
+/// Each Field is decomposed with `to_le_bytes`, which constrains it to fit in 31 bytes,
+/// and the bytes past `N` in the last Field are constrained to be zero.
+///
+/// # Arguments
+///
+/// * `num_substrs` - The number of substrings returned by the byte matcher.
+/// * `capture_len` - The capacity of the capture vectors, see `NoirAutomaton::capture_len`.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `capture_starts` - A boolean indicating whether to return the start index of every
+///   capture.
+/// * `input_hash` - The hash function binding the input, if any. SHA-256 hashes the
+///   unpacked bytes while Poseidon hashes the packed Fields.
+/// * `imports` - The paths imported by the generated code.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_packed_regex_match_fn(
+    num_substrs: usize,
+    capture_len: &str,
+    gen_substrs: bool,
+    capture_starts: bool,
+    input_hash: Option<NoirInputHash>,
+    imports: &[String],
+) -> String {
+    let mut lines = fmt_fn_signature(
+        "pub fn regex_match<let N: u32, let M: u32>",
+        &["input: [Field; M]".to_string()],
+        &matcher_return_type(
+            num_substrs,
+            capture_len,
+            gen_substrs,
+            capture_starts,
+            input_hash,
+        ),
+    );
+    lines.extend(
+        unpack_lines("input")
+            .into_iter()
+            .map(|line| format!("    {line}")),
+    );
+    match input_hash {
+        Some(hash @ NoirInputHash::Sha256) => lines.push(format!(
+            "    let input_hash = {};",
+            input_hash_expr(hash, "bytes", "N", false, imports)
+        )),
+        Some(hash @ NoirInputHash::Poseidon) => lines.push(format!(
+            "    let input_hash = {};",
+            input_hash_expr(hash, "input", "M", true, imports)
+        )),
+        None => {}
+    }
+    // The start indices are returned as is, unless the hash has to be added after them.
+    let expr = match (gen_substrs && capture_starts, input_hash) {
+        (true, Some(_)) => {
+            lines.push("    let (substrs, starts) = regex_match_bytes(bytes);".to_string());
+            matcher_return_expr("substrs", "starts", true, true, input_hash)
+        }
+        (true, None) => Some("regex_match_bytes(bytes)".to_string()),
+        (false, _) => matcher_return_expr(
+            "regex_match_bytes(bytes)",
+            "",
+            gen_substrs,
+            false,
+            input_hash,
+        ),
+    };
+    match expr {
+        Some(expr) if gen_substrs => lines.push(format!("    {expr}")),
+        Some(expr) => {
+            lines.push("    regex_match_bytes(bytes);".to_string());
+            lines.push(format!("    {expr}"));
+        }
+        None => lines.push("    regex_match_bytes(bytes);".to_string()),
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Returns the unindented lines unpacking an array of Fields holding 31 bytes each in
+/// little-endian order into `bytes: [u8; N]`, asserting that the padding is zero.
+///
+/// # Arguments
+///
+/// * `input` - The Noir expression of the `[Field; M]` array.
+///
+/// # Returns
+///
+/// The Noir statements.
+fn unpack_lines(input: &str) -> Vec<String> {
+    vec![
+        format!("assert(M == (N + {PACKED_BYTES} - 1) / {PACKED_BYTES}, \"packed input length does not match N\");"),
+        "let mut bytes: [u8; N] = [0; N];".to_string(),
+        "for i in 0..M {".to_string(),
+        format!("    let chunk: [u8; {PACKED_BYTES}] = {input}[i].to_le_bytes();"),
+        format!("    for j in 0..{PACKED_BYTES} {{"),
+        format!("        let idx = i * {PACKED_BYTES} + j;"),
+        "        if idx < N {".to_string(),
+        "            bytes[idx] = chunk[j];".to_string(),
+        "        } else {".to_string(),
+        "            assert(chunk[j] == 0, \"non-zero padding in packed input\");".to_string(),
+        "        }".to_string(),
+        "    }".to_string(),
+        "}".to_string(),
+    ]
+}
+
+/// Generates helpers converting the Field-based captures back to input elements.
+///
+/// Each captured Field is range checked against the element type before being narrowed,
+/// so the helpers can be used on captures that did not come from `regex_match` as well.
+///
+/// # Arguments
+///
+/// * `input_type` - The element type of the input array.
+///
+/// # Returns
+///
+/// A String containing the Noir functions.
+fn gen_capture_helpers_fn(input_type: NoirInputType) -> String {
+    let (name, typ) = match input_type {
+        NoirInputType::U8 | NoirInputType::PackedField => ("bytes", "u8"),
+        NoirInputType::U16 => ("units", "u16"),
+    };
+    let capture = ["capture: BoundedVec<Field, N>".to_string()];
+    let mut to_vec = fmt_fn_signature(
+        &format!("pub fn capture_to_{name}<let N: u32>"),
+        &capture,
+        &format!(" -> BoundedVec<{typ}, N>"),
+    );
+    to_vec.extend([
+        "    let mut out = BoundedVec::new();".to_string(),
+        "    for i in 0..N {".to_string(),
+        "        if i < capture.len() {".to_string(),
+        "            let value = capture.get_unchecked(i);".to_string(),
+        format!("            let narrowed = value as {typ};"),
+        format!(
+            "            assert(narrowed as Field == value, \"capture value does not fit in {typ}\");"
+        ),
+        "            out.push(narrowed);".to_string(),
+        "        }".to_string(),
+        "    }".to_string(),
+        "    out".to_string(),
+        "}".to_string(),
+    ]);
+    let mut to_array = fmt_fn_signature(
+        &format!("pub fn capture_to_{name}_array<let N: u32, let M: u32>"),
+        &capture,
+        &format!(" -> [{typ}; M]"),
+    );
+    to_array.extend([
+        "    assert(capture.len() <= M, \"capture does not fit in the output array\");".to_string(),
+        format!("    let narrowed = capture_to_{name}(capture);"),
+        format!("    let mut out: [{typ}; M] = [0; M];"),
+        "    for i in 0..M {".to_string(),
+        "        if i < narrowed.len() {".to_string(),
+        "            out[i] = narrowed.get_unchecked(i);".to_string(),
+        "        }".to_string(),
+        "    }".to_string(),
+        "    out".to_string(),
+        "}".to_string(),
+    ]);
+    format!("{}\n\n{}", to_vec.join("\n"), to_array.join("\n"))
+}
+
+/// Generates the `to_ascii_lowercase` helper folding ASCII uppercase letters to lowercase.
+///
+/// The matcher applies it to every captured code unit, so the case folding is constrained
+/// along with the match and other code units are kept unchanged.
+///
+/// # Arguments
+///
+/// * `input_type` - The element type of the input array, after unpacking.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_to_ascii_lowercase_fn(input_type: NoirInputType) -> String {
+    let (var, typ, _) = input_vars(input_type);
+    let mut lines = fmt_fn_signature(
+        "pub fn to_ascii_lowercase",
+        &[format!("{var}: {typ}")],
+        &format!(" -> {typ}"),
+    );
+    lines.extend([
+        format!("    if ({var} >= 65) & ({var} <= 90) {{"),
+        format!("        {var} + 32"),
+        "    } else {".to_string(),
+        format!("        {var}"),
+        "    }".to_string(),
+        "}".to_string(),
+    ]);
+    lines.join("\n")
+}
+
+/// Generates the Noir runtime library, holding every helper that does not depend on the
+/// pattern once, so that the matchers generated with a `runtime` import them instead of
+/// carrying their own copies.
+///
+/// The `u16` version of `to_ascii_lowercase` is named `to_ascii_lowercase_u16`, and imported
+/// under its usual name by the matchers taking `u16` input.
+///
+/// # Arguments
+///
+/// * `imports` - The paths imported by the library, e.g. an external `sha256`.
+///
+/// # Returns
+///
+/// A String containing the Noir library.
+#[cfg(feature = "fs")]
+pub(crate) fn gen_runtime_lib(imports: &[String]) -> String {
+    let mut sections = vec![format!(
+        "// The helpers shared by the Noir matchers generated by zk-regex {} with a runtime.",
+        env!("CARGO_PKG_VERSION")
+    )];
+    sections.extend(gen_imports(imports));
+    sections.extend([
+        gen_capture_helpers_fn(NoirInputType::U8),
+        gen_capture_helpers_fn(NoirInputType::U16),
+        gen_to_ascii_lowercase_fn(NoirInputType::U8),
+        gen_to_ascii_lowercase_fn(NoirInputType::U16).replacen(
+            "pub fn to_ascii_lowercase",
+            "pub fn to_ascii_lowercase_u16",
+            1,
+        ),
+        gen_reverse_capture_fn(),
+        gen_capture_to_packed_fn(),
+        gen_capture_to_sha256_fn(imports),
+        gen_capture_to_integer_fn(),
+    ]);
+    format!("{}\n", sections.join("\n\n"))
+}
+
+/// Generates the statement importing the given helpers from the runtime library, see
+/// `gen_runtime_lib`. They are re-exported, so the matcher keeps exposing them.
+///
+/// # Arguments
+///
+/// * `runtime` - The name of the runtime library.
+/// * `helpers` - The helpers the matcher would otherwise hold.
+/// * `input_type` - The element type of the input array, after unpacking.
+///
+/// # Returns
+///
+/// A String containing the `pub use` statement.
+fn gen_runtime_imports(runtime: &str, helpers: &[String], input_type: NoirInputType) -> String {
+    let names = helpers
+        .iter()
+        .flat_map(|helper| helper.lines())
+        .filter_map(|line| line.strip_prefix("pub fn "))
+        .map(|decl| {
+            let name = &decl[..decl.find(['<', '(']).unwrap_or(decl.len())];
+            match (name, input_type) {
+                ("to_ascii_lowercase", NoirInputType::U16) => {
+                    "to_ascii_lowercase_u16 as to_ascii_lowercase".to_string()
+                }
+                _ => name.to_string(),
+            }
+        })
+        .collect_vec();
+    let line = format!("pub use {runtime}::{{{}}};", names.join(", "));
+    if line.len() <= MAX_WIDTH {
+        return line;
+    }
+    let mut lines = vec![format!("pub use {runtime}::{{")];
+    lines.extend(names.iter().map(|name| format!("    {name},")));
+    lines.push("};".to_string());
+    lines.join("\n")
+}
+
+/// Generates the `reverse_capture` helper restoring the order of a capture pushed backwards.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_reverse_capture_fn() -> String {
+    let mut lines = fmt_fn_signature(
+        "pub fn reverse_capture<let N: u32>",
+        &["capture: BoundedVec<Field, N>".to_string()],
+        " -> BoundedVec<Field, N>",
+    );
+    lines.extend([
+        "    let mut reversed = BoundedVec::new();".to_string(),
+        "    for k in 0..N {".to_string(),
+        "        if k < capture.len() {".to_string(),
+        "            reversed.push(capture.get_unchecked(capture.len() - 1 - k));".to_string(),
+        "        }".to_string(),
+        "    }".to_string(),
+        "    reversed".to_string(),
+        "}".to_string(),
+    ]);
+    lines.join("\n")
+}
+
+/// Generates the `capture_to_packed` helper packing a byte capture 31 bytes per Field.
+///
+/// The bytes are packed in little-endian order like the packed input, and only the Fields
+/// holding at least one byte are pushed.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_capture_to_packed_fn() -> String {
+    let mut lines = fmt_fn_signature(
+        "pub fn capture_to_packed<let N: u32, let M: u32>",
+        &["capture: BoundedVec<Field, N>".to_string()],
+        " -> BoundedVec<Field, M>",
+    );
+    lines.extend([
+        "    let bytes = capture_to_bytes(capture);".to_string(),
+        format!(
+            "    assert(bytes.len() <= M * {PACKED_BYTES}, \"capture does not fit in the packed output\");"
+        ),
+        "    let mut out = BoundedVec::new();".to_string(),
+        "    for i in 0..M {".to_string(),
+        "        let mut chunk = 0;".to_string(),
+        "        let mut shift = 1;".to_string(),
+        format!("        for j in 0..{PACKED_BYTES} {{"),
+        format!("            let idx = i * {PACKED_BYTES} + j;"),
+        "            if idx < bytes.len() {".to_string(),
+        "                chunk += bytes.get_unchecked(idx) as Field * shift;".to_string(),
+        "            }".to_string(),
+        "            shift *= 256;".to_string(),
+        "        }".to_string(),
+        format!("        if i * {PACKED_BYTES} < bytes.len() {{"),
+        "            out.push(chunk);".to_string(),
+        "        }".to_string(),
+        "    }".to_string(),
+        "    out".to_string(),
+        "}".to_string(),
+    ]);
+    lines.join("\n")
+}
+
+/// Generates the `regex_match_packed_captures` entry point, which calls `regex_match` and
+/// packs every capture with `capture_to_packed` into at most `P` Fields.
+///
+/// # Arguments
+///
+/// * `num_captures` - The number of captures returned by `regex_match`.
+/// * `input_type` - The element type of the input array.
+/// * `capture_starts` - A boolean indicating whether `regex_match` also returns the start
+///   indices, which are dropped.
+/// * `input_hash` - The hash function binding the input, if any.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_packed_captures_fn(
+    num_captures: usize,
+    input_type: NoirInputType,
+    capture_starts: bool,
+    input_hash: Option<NoirInputHash>,
+) -> String {
+    let (decl, input, call) = match input_type {
+        NoirInputType::PackedField => (
+            "pub fn regex_match_packed_captures<let N: u32, let M: u32, let P: u32>",
+            "input: [Field; M]",
+            "regex_match::<N, M>(input)",
+        ),
+        _ => (
+            "pub fn regex_match_packed_captures<let N: u32, let P: u32>",
+            "input: [u8; N]",
+            "regex_match(input)",
+        ),
+    };
+    let packed = format!("[BoundedVec<Field, P>; {num_captures}]");
+    let return_type = match input_hash {
+        Some(hash) => format!(" -> ({packed}, {})", input_hash_type(hash)),
+        None => format!(" -> {packed}"),
+    };
+    let mut lines = fmt_fn_signature(decl, &[input.to_string()], &return_type);
+    let pattern = matcher_return_expr("substrs", "_", true, capture_starts, input_hash);
+    lines.extend(pattern.map(|pattern| format!("    let {pattern} = {call};")));
+    lines.extend([
+        format!("    let mut packed: {packed} = [BoundedVec::new(); {num_captures}];"),
+        format!("    for i in 0..{num_captures} {{"),
+        "        packed[i] = capture_to_packed(substrs[i]);".to_string(),
+        "    }".to_string(),
+    ]);
+    match input_hash {
+        Some(_) => lines.push("    (packed, input_hash)".to_string()),
+        None => lines.push("    packed".to_string()),
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Returns the indices, among the captures returned by the matcher, of the captures of the
+/// selected parts, such as the numeric ones.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the parts.
+/// * `automaton` - A reference to the automaton laying out the captures.
+/// * `selected` - Whether a part is selected.
+///
+/// # Returns
+///
+/// A Vec of capture indices in ascending order.
+fn part_capture_indices(
+    regex_and_dfa: &RegexAndDFA,
+    automaton: &NoirAutomaton,
+    selected: impl Fn(&RegexPartDFA) -> bool,
+) -> Vec<usize> {
+    let selected_substrs: BTreeSet<usize> = regex_and_dfa
+        .parts
+        .iter()
+        .filter(|part| selected(part))
+        .filter_map(|part| part.substr_idx)
+        .collect();
+    let mut indices = vec![];
+    let mut first = 0;
+    for idx in 0..automaton.substr_transitions.len() {
+        let num = automaton.capture_vars(idx).len();
+        if selected_substrs.contains(&idx) {
+            indices.extend(first..first + num);
+        }
+        first += num;
+    }
+    indices
+}
+
+/// Returns the ranges the numeric parts bound their values to, by index among the captures
+/// returned by the matcher.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the parts.
+/// * `automaton` - A reference to the automaton laying out the captures.
+///
+/// # Returns
+///
+/// A BTreeMap from capture index to the range of its part.
+fn numeric_capture_ranges(
+    regex_and_dfa: &RegexAndDFA,
+    automaton: &NoirAutomaton,
+) -> BTreeMap<usize, CaptureRange> {
+    let mut ranges = BTreeMap::new();
+    for part in &regex_and_dfa.parts {
+        if !part.numeric || (part.min.is_none() && part.max.is_none()) {
+            continue;
+        }
+        let range = CaptureRange {
+            min: part.min,
+            max: part.max,
+            optional: part.max_repetitions.is_some(),
+        };
+        let indices = part_capture_indices(regex_and_dfa, automaton, |other| {
+            other.substr_idx.is_some() && other.substr_idx == part.substr_idx
+        });
+        ranges.extend(indices.into_iter().map(|idx| (idx, range)));
+    }
+    ranges
+}
+
+/// Generates the `capture_to_field` and `capture_to_u64` helpers converting a capture of
+/// ASCII digits to its decimal value.
+///
+/// Every captured Field is checked to be a digit, and the number of digits is bounded so
+/// that the value cannot wrap around: 76 digits for a Field and 19 for a `u64`. An empty
+/// capture, such as an unused repetition, converts to 0.
+///
+/// # Returns
+///
+/// A String containing the Noir functions.
+fn gen_capture_to_integer_fn() -> String {
+    let capture = ["capture: BoundedVec<Field, N>".to_string()];
+    let mut to_field =
+        fmt_fn_signature("pub fn capture_to_field<let N: u32>", &capture, " -> Field");
+    to_field.extend([
+        "    assert(capture.len() <= 76, \"numeric capture has more than 76 digits\");".to_string(),
+        "    let mut value = 0;".to_string(),
+        "    for i in 0..N {".to_string(),
+        "        if i < capture.len() {".to_string(),
+        "            let c = capture.get_unchecked(i);".to_string(),
+        "            let byte = c as u8;".to_string(),
+        "            assert(byte as Field == c, \"numeric capture holds a non-digit\");"
+            .to_string(),
+        "            assert((byte >= 48) & (byte <= 57), \"numeric capture holds a non-digit\");"
+            .to_string(),
+        "            value = value * 10 + (byte - 48) as Field;".to_string(),
+        "        }".to_string(),
+        "    }".to_string(),
+        "    value".to_string(),
+        "}".to_string(),
+    ]);
+    let mut to_u64 = fmt_fn_signature("pub fn capture_to_u64<let N: u32>", &capture, " -> u64");
+    to_u64.extend([
+        "    assert(capture.len() <= 19, \"numeric capture has more than 19 digits\");".to_string(),
+        "    capture_to_field(capture) as u64".to_string(),
+        "}".to_string(),
+    ]);
+    format!("{}\n\n{}", to_field.join("\n"), to_u64.join("\n"))
+}
+
+/// The range of values a numeric capture is asserted to lie in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CaptureRange {
+    min: Option<u64>,
+    max: Option<u64>,
+    /// Whether the capture may be empty, for an unused repetition, and then escapes the range.
+    optional: bool,
+}
+
+/// Generates the `numeric_captures` function converting the captures of the numeric parts,
+/// as returned by `regex_match`, to `u64` values with `capture_to_u64`.
+///
+/// The values of the captures with a range are asserted to lie in it, so that a circuit
+/// converting the captures cannot be proven with a value out of it.
+///
+/// # Arguments
+///
+/// * `num_captures` - The number of captures returned by `regex_match`.
+/// * `indices` - The indices of the captures of the numeric parts.
+/// * `ranges` - The ranges of the captures bounded by their parts, by capture index.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_numeric_captures_fn(
+    num_captures: usize,
+    indices: &[usize],
+    ranges: &BTreeMap<usize, CaptureRange>,
+) -> String {
+    let mut lines = fmt_fn_signature(
+        "pub fn numeric_captures<let N: u32>",
+        &[format!("substrs: [BoundedVec<Field, N>; {num_captures}]")],
+        &format!(" -> [u64; {}]", indices.len()),
+    );
+    let values = indices
+        .iter()
+        .map(|idx| format!("capture_to_u64(substrs[{idx}])"))
+        .collect_vec();
+    let (open, close) = match ranges.is_empty() {
+        true => ("    [", "    ]"),
+        false => ("    let values = [", "    ];"),
+    };
+    let array = format!("{}{}{}", open, values.join(", "), close.trim_start());
+    if array.len() <= MAX_WIDTH {
+        lines.push(array);
+    } else {
+        lines.push(open.to_string());
+        lines.extend(values.iter().map(|value| format!("        {value},")));
+        lines.push(close.to_string());
+    }
+    if !ranges.is_empty() {
+        for (value_idx, idx) in indices.iter().enumerate() {
+            let Some(range) = ranges.get(idx) else {
+                continue;
+            };
+            let bounds = [(range.min, ">=", "below"), (range.max, "<=", "above")];
+            for (bound, op, word) in bounds {
+                let Some(bound) = bound else {
+                    continue;
+                };
+                let check = format!("values[{value_idx}] {op} {bound}");
+                let check = match range.optional {
+                    true => format!("(substrs[{idx}].len() == 0) | ({check})"),
+                    false => check,
+                };
+                lines.push(format!(
+                    "    assert({check}, \"numeric capture {idx} is {word} {bound}\");"
+                ));
+            }
+        }
+        lines.push("    values".to_string());
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates the `hole_commitments` function committing to the captures of the holes of a
+/// template, as returned by `regex_match`, with Poseidon2.
+///
+/// Every commitment hashes the first `len()` elements of the capture, so that a circuit can
+/// reveal it in place of the capture and prove that the input fills the template with the
+/// committed values.
+///
+/// # Arguments
+///
+/// * `num_captures` - The number of captures returned by `regex_match`.
+/// * `indices` - The indices of the captures of the holes.
+/// * `imports` - The paths imported by the generated code.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_hole_commitments_fn(num_captures: usize, indices: &[usize], imports: &[String]) -> String {
+    let hash = std_item(imports, "std::hash::poseidon2::Poseidon2");
+    let mut lines = fmt_fn_signature(
+        "pub fn hole_commitments<let N: u32>",
+        &[format!("substrs: [BoundedVec<Field, N>; {num_captures}]")],
+        &format!(" -> [Field; {}]", indices.len()),
+    );
+    lines.push("    [".to_string());
+    lines.extend(indices.iter().map(|idx| {
+        format!("        {hash}::hash(substrs[{idx}].storage(), substrs[{idx}].len()),")
+    }));
+    lines.push("    ]".to_string());
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates the `capture_to_sha256` helper hashing the bytes of a capture with SHA-256.
+///
+/// Only the captured bytes are hashed, so the digest is the SHA-256 of the substring itself
+/// and can be compared against commitments computed outside the circuit.
+///
+/// # Arguments
+///
+/// * `imports` - The paths imported by the generated code.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_capture_to_sha256_fn(imports: &[String]) -> String {
+    let mut lines = fmt_fn_signature(
+        "pub fn capture_to_sha256<let N: u32>",
+        &["capture: BoundedVec<Field, N>".to_string()],
+        " -> [u8; 32]",
+    );
+    lines.extend([
+        "    let bytes = capture_to_bytes(capture);".to_string(),
+        format!(
+            "    {}(bytes.storage(), bytes.len() as u64)",
+            std_item(imports, "std::hash::sha256_var")
+        ),
+        "}".to_string(),
+    ]);
+    lines.join("\n")
+}
+
+/// Generates the `regex_match_capture_digests` entry point, which calls `regex_match` and
+/// hashes every capture with `capture_to_sha256`.
+///
+/// # Arguments
+///
+/// * `num_captures` - The number of captures returned by `regex_match`.
+/// * `input_type` - The element type of the input array.
+/// * `capture_starts` - A boolean indicating whether `regex_match` also returns the start
+///   indices, which are dropped.
+/// * `input_hash` - The hash function binding the input, if any.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_capture_digests_fn(
+    num_captures: usize,
+    input_type: NoirInputType,
+    capture_starts: bool,
+    input_hash: Option<NoirInputHash>,
+) -> String {
+    let (decl, input, call) = match input_type {
+        NoirInputType::PackedField => (
+            "pub fn regex_match_capture_digests<let N: u32, let M: u32>",
+            "input: [Field; M]",
+            "regex_match::<N, M>(input)",
+        ),
+        _ => (
+            "pub fn regex_match_capture_digests<let N: u32>",
+            "input: [u8; N]",
+            "regex_match(input)",
+        ),
+    };
+    let digests = format!("[[u8; 32]; {num_captures}]");
+    let return_type = match input_hash {
+        Some(hash) => format!(" -> ({digests}, {})", input_hash_type(hash)),
+        None => format!(" -> {digests}"),
+    };
+    let mut lines = fmt_fn_signature(decl, &[input.to_string()], &return_type);
+    let pattern = matcher_return_expr("substrs", "_", true, capture_starts, input_hash);
+    lines.extend(pattern.map(|pattern| format!("    let {pattern} = {call};")));
+    lines.extend([
+        format!("    let mut digests: {digests} = [[0; 32]; {num_captures}];"),
+        format!("    for i in 0..{num_captures} {{"),
+        "        digests[i] = capture_to_sha256(substrs[i]);".to_string(),
+        "    }".to_string(),
+    ]);
+    match input_hash {
+        Some(_) => lines.push("    (digests, input_hash)".to_string()),
+        None => lines.push("    digests".to_string()),
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates the `regex_match_verified` entry point, which calls `regex_match` and asserts that
+/// the input hashes to an expected digest, taken as an input, instead of returning the digest.
+///
+/// # Arguments
+///
+/// * `num_captures` - The number of captures returned by `regex_match`.
+/// * `capture_len` - The capacity of the capture vectors, see `NoirAutomaton::capture_len`.
+/// * `input_type` - The element type of the input array.
+/// * `gen_substrs` - A boolean indicating whether `regex_match` returns the captures.
+/// * `capture_starts` - A boolean indicating whether `regex_match` also returns the start
+///   indices.
+/// * `input_hash` - The hash function binding the input.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_verified_input_fn(
+    num_captures: usize,
+    capture_len: &str,
+    input_type: NoirInputType,
+    gen_substrs: bool,
+    capture_starts: bool,
+    input_hash: NoirInputHash,
+) -> String {
+    let (decl, input, call) = match input_type {
+        NoirInputType::PackedField => (
+            "pub fn regex_match_verified<let N: u32, let M: u32>",
+            "input: [Field; M]",
+            "regex_match::<N, M>(input)",
+        ),
+        NoirInputType::U16 => (
+            "pub fn regex_match_verified<let N: u32>",
+            "input: [u16; N]",
+            "regex_match(input)",
+        ),
+        NoirInputType::U8 => (
+            "pub fn regex_match_verified<let N: u32>",
+            "input: [u8; N]",
+            "regex_match(input)",
+        ),
+    };
+    let return_type =
+        matcher_return_type(num_captures, capture_len, gen_substrs, capture_starts, None);
+    let params = [
+        input.to_string(),
+        format!("expected_hash: {}", input_hash_type(input_hash)),
+    ];
+    let mut lines = fmt_fn_signature(decl, &params, &return_type);
+    let pattern = matcher_return_expr(
+        "substrs",
+        "starts",
+        gen_substrs,
+        capture_starts,
+        Some(input_hash),
+    );
+    lines.extend(pattern.map(|pattern| format!("    let {pattern} = {call};")));
+    lines.push(
+        "    assert(input_hash == expected_hash, \"the input does not hash to the expected digest\");"
+            .to_string(),
+    );
+    lines.extend(
+        matcher_return_expr("substrs", "starts", gen_substrs, capture_starts, None)
+            .map(|expr| format!("    {expr}")),
+    );
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates the `regex_match_capture_lens` entry point, which calls `regex_match` and returns
+/// the captures along with their lengths.
+///
+/// Every code unit is pushed to its capture in constrained code, so the length of a capture
+/// is constrained as much as its content, and can be range checked downstream directly.
+///
+/// # Arguments
+///
+/// * `num_captures` - The number of captures returned by `regex_match`.
+/// * `capture_len` - The capacity of the capture vectors, see `NoirAutomaton::capture_len`.
+/// * `input_type` - The element type of the input array.
+/// * `capture_starts` - A boolean indicating whether `regex_match` also returns the start
+///   indices, which are dropped.
+/// * `input_hash` - The hash function binding the input, if any.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_capture_lens_fn(
+    num_captures: usize,
+    capture_len: &str,
+    input_type: NoirInputType,
+    capture_starts: bool,
+    input_hash: Option<NoirInputHash>,
+) -> String {
+    let (decl, input, call) = match input_type {
+        NoirInputType::PackedField => (
+            "pub fn regex_match_capture_lens<let N: u32, let M: u32>",
+            "input: [Field; M]".to_string(),
+            "regex_match::<N, M>(input)",
+        ),
+        input_type => (
+            "pub fn regex_match_capture_lens<let N: u32>",
+            format!("input: [{}; N]", input_vars(input_type).1),
+            "regex_match(input)",
+        ),
+    };
+    let captures = format!("[BoundedVec<Field, {capture_len}>; {num_captures}]");
+    let lens = format!("[u32; {num_captures}]");
+    let return_type = match input_hash {
+        Some(hash) => format!(" -> ({captures}, {lens}, {})", input_hash_type(hash)),
+        None => format!(" -> ({captures}, {lens})"),
+    };
+    let mut lines = fmt_fn_signature(decl, &[input], &return_type);
+    let pattern = matcher_return_expr("substrs", "_", true, capture_starts, input_hash);
+    lines.extend(pattern.map(|pattern| format!("    let {pattern} = {call};")));
+    lines.extend([
+        format!("    let mut lens: {lens} = [0; {num_captures}];"),
+        format!("    for i in 0..{num_captures} {{"),
+        "        lens[i] = substrs[i].len();".to_string(),
+        "    }".to_string(),
+    ]);
+    match input_hash {
+        Some(_) => lines.push("    (substrs, lens, input_hash)".to_string()),
+        None => lines.push("    (substrs, lens)".to_string()),
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates the comment mapping the capture groups of a raw regex to the slots of the
+/// captures returned by the matcher.
+///
+/// # Arguments
+///
+/// * `groups` - The capture group of every substring, in slot order.
+///
+/// # Returns
+///
+/// A String containing the comment lines.
+fn gen_capture_groups_comment(groups: &[CaptureGroup]) -> String {
+    let mut lines = vec![
+        "// Capture slots of the regex groups, numbered from 1 as in regex engines:".to_string(),
+    ];
+    for (slot, group) in groups.iter().enumerate() {
+        match &group.name {
+            Some(name) => lines.push(format!("//   [{slot}]: group {} ({name})", group.index)),
+            None => lines.push(format!("//   [{slot}]: group {}", group.index)),
+        }
+    }
+    lines.join("\n")
+}
+
+/// Generates the comment mapping the names given to the substrings of a raw regex by its
+/// substrings JSON to the slots of the captures returned by the matcher.
+///
+/// # Arguments
+///
+/// * `names` - The name of every substring, if it has one, in slot order.
+///
+/// # Returns
+///
+/// A String containing the comment lines.
+fn gen_substring_names_comment(names: &[Option<String>]) -> String {
+    let mut lines = vec!["// Capture slots of the named substrings:".to_string()];
+    for (slot, name) in names.iter().enumerate() {
+        if let Some(name) = name {
+            lines.push(format!("//   [{slot}]: {name}"));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Generates the `regex_match_prefix_len` function, returning how far the automaton got
+/// towards a match.
+///
+/// The automaton runs over the input as in `regex_match`, restarting whenever no transition
+/// applies, but nothing is asserted. The function returns the largest number of consecutive
+/// code units consumed by one attempt, which is the length of the match if the pattern
+/// matches, since the absorbing accepting state is not counted. Being constrained, it backs
+/// claims such as "the input starts a match of at least k code units".
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to an automaton whose code units are not tagged.
+/// * `regex_pattern` - The regex pattern, quoted in a comment.
+/// * `input_type` - The element type of the input array.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `next_state` - The name of the transition function of the automaton.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_prefix_len_fn(
+    automaton: &NoirAutomaton,
+    regex_pattern: &str,
+    input_type: NoirInputType,
+    accept_sink: Option<usize>,
+    next_state: &str,
+) -> String {
+    let (var, typ, _) = input_vars(input_type);
+    let start = automaton.start_state;
+    let mut lines = fmt_fn_signature(
+        "pub fn regex_match_prefix_len<let N: u32>",
+        &[format!("input: [{typ}; N]")],
+        " -> u32",
+    );
+    lines.push(format!(
+        "    // regex: {}",
+        regex_pattern.replace('\n', "\\n").replace('\r', "\\r")
+    ));
+    lines.push(format!("    let mut s = {};", automaton.init_state));
+    lines.push("    let mut len: u32 = 0;".to_string());
+    lines.push("    let mut longest: u32 = 0;".to_string());
+    lines.push("    for i in 0..N {".to_string());
+    lines.push(format!("        let {var} = input[i];"));
+    lines.push(format!("        let mut s_next = {next_state}(s, {var});"));
+    lines.push(format!("        if s_next == {RESET_STATE} {{"));
+    lines.push("            // No transition: restart from the start state.".to_string());
+    lines.push("            len = 0;".to_string());
+    lines.push(format!(
+        "            s_next = {next_state}({start}, {var});"
+    ));
+    lines.push("        }".to_string());
+    let mut progressed = vec![Condition::Atom(format!("(s_next != {RESET_STATE})"))];
+    if let Some(sink) = accept_sink {
+        progressed.push(Condition::Atom(format!("(s_next != {sink})")));
+    }
+    lines.extend(fmt_if(8, "if", &Condition::And(progressed)));
+    lines.push("            len += 1;".to_string());
+    lines.push("        }".to_string());
+    lines.push("        if len > longest {".to_string());
+    lines.push("            longest = len;".to_string());
+    lines.push("        }".to_string());
+    lines.push("        s = s_next;".to_string());
+    lines.push("    }".to_string());
+    lines.push("    longest".to_string());
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates the loop shared by `regex_match_mask`, `regex_match_len` and
+/// `regex_match_redacted`, which leaves the match at `start..end` of the input.
+///
+/// The automaton runs over the input as in `regex_match`, counting the code units consumed by
+/// the current attempt. Whenever it reaches an accepting state, the match so far spans the
+/// last `len` code units, and the last such span is the one of the match once the absorbing
+/// accepting state, which is never left, is entered. The match is then asserted as in
+/// `regex_match`.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to an automaton whose code units are not tagged.
+/// * `input_type` - The element type of the input array.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `alphabet` - The code unit ranges every input code unit is asserted to lie in, if any.
+/// * `next_state` - The name of the transition function of the automaton.
+///
+/// # Returns
+///
+/// The lines of the function body computing `start` and `end`.
+fn match_span_lines(
+    automaton: &NoirAutomaton,
+    input_type: NoirInputType,
+    accept_sink: Option<usize>,
+    alphabet: Option<&[(u32, u32)]>,
+    next_state: &str,
+) -> Vec<String> {
+    let (var, _, max_unit) = input_vars(input_type);
+    let start = automaton.start_state;
+    let mut lines = vec![];
+    if let Some(min_len) = automaton.min_match_len().filter(|&min_len| min_len > 0) {
+        lines.push(format!(
+            "    assert(N >= {min_len}, \"the input is shorter than the shortest match of \
+             {min_len} code units\");"
+        ));
+    }
+    lines.push(format!("    let mut s = {};", automaton.init_state));
+    lines.push("    let mut len: u32 = 0;".to_string());
+    lines.push("    let mut start: u32 = 0;".to_string());
+    lines.push("    let mut end: u32 = 0;".to_string());
+    lines.push("    for i in 0..N {".to_string());
+    lines.push(format!("        let {var} = input[i];"));
+    if let Some(alphabet) = alphabet {
+        lines.extend(fmt_assert(
+            8,
+            &ranges_condition(var, alphabet, max_unit),
+            "f\"input[{i}] is outside the alphabet\"",
+        ));
+    }
+    lines.push(format!("        let mut s_next = {next_state}(s, {var});"));
+    lines.push(format!("        if s_next == {RESET_STATE} {{"));
+    lines.push("            // No transition: restart the match from the start state.".to_string());
+    lines.push("            len = 0;".to_string());
+    lines.push(format!(
+        "            s_next = {next_state}({start}, {var});"
+    ));
+    lines.push("        }".to_string());
+    let mut progressed = vec![Condition::Atom(format!("(s_next != {RESET_STATE})"))];
+    if let Some(sink) = accept_sink {
+        progressed.push(Condition::Atom(format!("(s_next != {sink})")));
+    }
+    lines.extend(fmt_if(8, "if", &Condition::And(progressed)));
+    lines.push("            len += 1;".to_string());
+    lines.push("        }".to_string());
+    lines.extend(fmt_if(
+        8,
+        "if",
+        &states_condition("s_next", &automaton.accept_states),
+    ));
+    lines.push("            // The match so far ends with this code unit.".to_string());
+    lines.push("            start = i + 1 - len;".to_string());
+    lines.push("            end = i + 1;".to_string());
+    lines.push("        }".to_string());
+    lines.push("        s = s_next;".to_string());
+    lines.push("    }".to_string());
+    let mut accept_states = automaton.accept_states.clone();
+    accept_states.extend(accept_sink);
+    lines.extend(fmt_assert(
+        4,
+        &states_condition("s", &accept_states),
+        "f\"no match: {s}\"",
+    ));
+    lines
+}
+
+/// Generates the `regex_match_mask` function, returning which input positions the match
+/// covers.
+///
+/// The span of the match is computed by `match_span_lines`, which asserts the match as in
+/// `regex_match`, so the mask can be trusted by redaction or selective hashing circuits.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to an automaton whose code units are not tagged.
+/// * `regex_pattern` - The regex pattern, quoted in a comment.
+/// * `input_type` - The element type of the input array.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `alphabet` - The code unit ranges every input code unit is asserted to lie in, if any.
+/// * `next_state` - The name of the transition function of the automaton.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_match_mask_fn(
+    automaton: &NoirAutomaton,
+    regex_pattern: &str,
+    input_type: NoirInputType,
+    accept_sink: Option<usize>,
+    alphabet: Option<&[(u32, u32)]>,
+    next_state: &str,
+) -> String {
+    let (_, typ, _) = input_vars(input_type);
+    let mut lines = fmt_fn_signature(
+        "pub fn regex_match_mask<let N: u32>",
+        &[format!("input: [{typ}; N]")],
+        " -> [bool; N]",
+    );
+    lines.push(format!(
+        "    // regex: {}",
+        regex_pattern.replace('\n', "\\n").replace('\r', "\\r")
+    ));
+    lines.extend(match_span_lines(
+        automaton,
+        input_type,
+        accept_sink,
+        alphabet,
+        next_state,
+    ));
+    lines.push("    let mut mask = [false; N];".to_string());
+    lines.push("    for i in 0..N {".to_string());
+    lines.push("        mask[i] = (i >= start) & (i < end);".to_string());
+    lines.push("    }".to_string());
+    lines.push("    mask".to_string());
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates the `regex_match_len` function, returning the length of the match.
+///
+/// The span of the match is computed as in `regex_match_mask`, so circuits can bound the
+/// matched region, e.g. assert that a quoted section is under 280 bytes, without extracting
+/// and measuring its content.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to an automaton whose code units are not tagged.
+/// * `regex_pattern` - The regex pattern, quoted in a comment.
+/// * `input_type` - The element type of the input array.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `alphabet` - The code unit ranges every input code unit is asserted to lie in, if any.
+/// * `next_state` - The name of the transition function of the automaton.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_match_len_fn(
+    automaton: &NoirAutomaton,
+    regex_pattern: &str,
+    input_type: NoirInputType,
+    accept_sink: Option<usize>,
+    alphabet: Option<&[(u32, u32)]>,
+    next_state: &str,
+) -> String {
+    let (_, typ, _) = input_vars(input_type);
+    let mut lines = fmt_fn_signature(
+        "pub fn regex_match_len<let N: u32>",
+        &[format!("input: [{typ}; N]")],
+        " -> u32",
+    );
+    lines.push(format!(
+        "    // regex: {}",
+        regex_pattern.replace('\n', "\\n").replace('\r', "\\r")
+    ));
+    lines.extend(match_span_lines(
+        automaton,
+        input_type,
+        accept_sink,
+        alphabet,
+        next_state,
+    ));
+    lines.push("    end - start".to_string());
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates the `regex_match_redacted` function, proving that a redacted copy of the input
+/// only differs from it by masking the match.
+///
+/// The span of the match is computed as in `regex_match_mask`, and every code unit of the
+/// redacted copy is asserted to be the mask inside the span and the code unit of the input
+/// outside of it. Keeping the input private and the redacted copy public gives verifiable
+/// redaction of the matched region of a document.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to an automaton whose code units are not tagged.
+/// * `regex_pattern` - The regex pattern, quoted in a comment.
+/// * `input_type` - The element type of the input array.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `alphabet` - The code unit ranges every input code unit is asserted to lie in, if any.
+/// * `next_state` - The name of the transition function of the automaton.
+/// * `mask` - The character replacing the code units of the match.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_match_redacted_fn(
+    automaton: &NoirAutomaton,
+    regex_pattern: &str,
+    input_type: NoirInputType,
+    accept_sink: Option<usize>,
+    alphabet: Option<&[(u32, u32)]>,
+    next_state: &str,
+    mask: char,
+) -> String {
+    let (_, typ, _) = input_vars(input_type);
+    let mut lines = fmt_fn_signature(
+        "pub fn regex_match_redacted<let N: u32>",
+        &[
+            format!("input: [{typ}; N]"),
+            format!("redacted: [{typ}; N]"),
+        ],
+        "",
+    );
+    lines.push(format!(
+        "    // regex: {}",
+        regex_pattern.replace('\n', "\\n").replace('\r', "\\r")
+    ));
+    lines.extend(match_span_lines(
+        automaton,
+        input_type,
+        accept_sink,
+        alphabet,
+        next_state,
+    ));
+    lines.push("    for i in 0..N {".to_string());
+    lines.push(format!(
+        "        let expected = if (i >= start) & (i < end) {{ {} }} else {{ input[i] }};",
+        mask as u32
+    ));
+    lines.push(
+        "        assert(redacted[i] == expected, f\"redacted[{i}] is not the redacted input\");"
+            .to_string(),
+    );
+    lines.push("    }".to_string());
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates the `regex_match_end` function, returning where the match ends.
+///
+/// The automaton runs over the input as in `regex_match`, recording the index just past the
+/// code unit at which it first reaches an accepting state, i.e. the earliest end of a match.
+/// The match is asserted as in `regex_match`, so circuits can rely on "the match ends at
+/// index i" without scanning the input a second time.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to an automaton whose code units are not tagged.
+/// * `regex_pattern` - The regex pattern, quoted in a comment.
+/// * `input_type` - The element type of the input array.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `alphabet` - The code unit ranges every input code unit is asserted to lie in, if any.
+/// * `next_state` - The name of the transition function of the automaton.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_match_end_fn(
+    automaton: &NoirAutomaton,
+    regex_pattern: &str,
+    input_type: NoirInputType,
+    accept_sink: Option<usize>,
+    alphabet: Option<&[(u32, u32)]>,
+    next_state: &str,
+) -> String {
+    let (var, typ, max_unit) = input_vars(input_type);
+    let start = automaton.start_state;
+    let mut lines = fmt_fn_signature(
+        "pub fn regex_match_end<let N: u32>",
+        &[format!("input: [{typ}; N]")],
+        " -> u32",
+    );
+    lines.push(format!(
+        "    // regex: {}",
+        regex_pattern.replace('\n', "\\n").replace('\r', "\\r")
+    ));
+    lines.push(format!("    let mut s = {};", automaton.init_state));
+    lines.push("    let mut accepted = false;".to_string());
+    lines.push("    let mut end: u32 = 0;".to_string());
+    lines.push("    for i in 0..N {".to_string());
+    lines.push(format!("        let {var} = input[i];"));
+    if let Some(alphabet) = alphabet {
+        lines.extend(fmt_assert(
+            8,
+            &ranges_condition(var, alphabet, max_unit),
+            "f\"input[{i}] is outside the alphabet\"",
+        ));
+    }
+    lines.push(format!("        let mut s_next = {next_state}(s, {var});"));
+    lines.push(format!("        if s_next == {RESET_STATE} {{"));
+    lines.push("            // No transition: restart from the start state.".to_string());
+    lines.push(format!(
+        "            s_next = {next_state}({start}, {var});"
+    ));
+    lines.push("        }".to_string());
+    lines.extend(fmt_if(
+        8,
+        "if",
+        &Condition::And(vec![
+            Condition::Atom("!accepted".to_string()),
+            states_condition("s_next", &automaton.accept_states),
+        ]),
+    ));
+    lines.push("            // The first match ends with this code unit.".to_string());
+    lines.push("            accepted = true;".to_string());
+    lines.push("            end = i + 1;".to_string());
+    lines.push("        }".to_string());
+    lines.push("        s = s_next;".to_string());
+    lines.push("    }".to_string());
+    let mut accept_states = automaton.accept_states.clone();
+    accept_states.extend(accept_sink);
+    lines.extend(fmt_assert(
+        4,
+        &states_condition("s", &accept_states),
+        "f\"no match: {s}\"",
+    ));
+    lines.push("    end".to_string());
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates the `regex_match_at` function, verifying a match claimed to start at a given
+/// index.
+///
+/// The prover supplies the index at which the match starts, and the automaton only runs over
+/// the `L` code units from there instead of the whole input, so the cost of the check depends
+/// on the length of the match rather than that of the input. The automaton starts in the
+/// initial state at the start of the input, where the start anchor (^) still matches, and in
+/// the state it restarts from elsewhere. Every code unit of the window must have a
+/// transition, and the automaton must end in an accepting state, either at the end of the
+/// window or at the end of the input, which an end-anchored pattern must reach. The function
+/// returns the index just past the last code unit at which the automaton is in an accepting
+/// state. It proves that the window holds a match, not that no match starts before it.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to an automaton whose code units are not tagged.
+/// * `regex_pattern` - The regex pattern, quoted in a comment.
+/// * `input_type` - The element type of the input array.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `alphabet` - The code unit ranges every input code unit is asserted to lie in, if any.
+/// * `next_state` - The name of the transition function of the automaton.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_match_at_fn(
+    automaton: &NoirAutomaton,
+    regex_pattern: &str,
+    input_type: NoirInputType,
+    accept_sink: Option<usize>,
+    alphabet: Option<&[(u32, u32)]>,
+    next_state: &str,
+) -> String {
+    let (var, typ, max_unit) = input_vars(input_type);
+    let mut lines = fmt_fn_signature(
+        "pub fn regex_match_at<let N: u32, let L: u32>",
+        &[format!("input: [{typ}; N]"), "start: u32".to_string()],
+        " -> u32",
+    );
+    lines.push(format!(
+        "    // regex: {}",
+        regex_pattern.replace('\n', "\\n").replace('\r', "\\r")
+    ));
+    lines
+        .push("    assert(start < N, \"the match starts past the end of the input\");".to_string());
+    if accept_sink.is_none() {
+        lines.push(
+            "    assert(N - start <= L, \"the window does not reach the end of the input\");"
+                .to_string(),
+        );
+    }
+    if automaton.init_state == automaton.start_state {
+        lines.push(format!("    let mut s = {};", automaton.start_state));
+    } else {
+        lines.push(format!(
+            "    let mut s = if start == 0 {{ {} }} else {{ {} }};",
+            automaton.init_state, automaton.start_state
+        ));
+    }
+    lines.push("    let mut end: u32 = 0;".to_string());
+    lines.push("    for j in 0..L {".to_string());
+    lines.push("        let i = start + j;".to_string());
+    lines.push("        if i < N {".to_string());
+    lines.push(format!("            let {var} = input[i];"));
+    if let Some(alphabet) = alphabet {
+        lines.extend(fmt_assert(
+            12,
+            &ranges_condition(var, alphabet, max_unit),
+            "f\"input[{i}] is outside the alphabet\"",
+        ));
+    }
+    lines.push(format!("            s = {next_state}(s, {var});"));
+    lines.push(
+        "            // The match may not restart: every code unit must have a transition."
+            .to_string(),
+    );
+    lines.push(format!(
+        "            assert(s != {RESET_STATE}, f\"no match at {{start}}: input[{{i}}]\");"
+    ));
+    lines.extend(fmt_if(
+        12,
+        "if",
+        &states_condition("s", &automaton.accept_states),
+    ));
+    lines.push("                end = i + 1;".to_string());
+    lines.push("            }".to_string());
+    lines.push("        }".to_string());
+    lines.push("    }".to_string());
+    let mut accept_states = automaton.accept_states.clone();
+    accept_states.extend(accept_sink);
+    lines.extend(fmt_assert(
+        4,
+        &states_condition("s", &accept_states),
+        "f\"no match at {start}: {s}\"",
+    ));
+    lines.push("    end".to_string());
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates a wrapper forwarding its input, converted to bytes, to `regex_match`.
+///
+/// # Arguments
+///
+/// * `ctx` - The automaton matched by `regex_match`, whose captures, their start indices and
+///   the input hash, as set by the options, the wrapper returns as well.
+/// * `fn_decl` - The declaration of the wrapper up to its generics, e.g.
+///   `pub fn regex_match_str<let N: u32>`.
+/// * `param` - The parameter of the wrapper, e.g. `input: str<N>`.
+/// * `bytes` - The Noir expression converting the parameter to `[u8; N]`.
+/// * `capture_len` - The capacity of the capture vectors, see `NoirAutomaton::capture_len`.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_wrapper_fn(
+    ctx: CodegenContext,
+    fn_decl: &str,
+    param: &str,
+    bytes: &str,
+    capture_len: &str,
+) -> String {
+    let return_type = matcher_return_type(
+        ctx.automaton.num_captures(),
+        capture_len,
+        ctx.gen_substrs,
+        ctx.options.capture_starts,
+        ctx.options.input_hash,
+    );
+    let call = if return_type.is_empty() {
+        format!("    regex_match({bytes});")
+    } else {
+        format!("    regex_match({bytes})")
+    };
+    let mut lines = fmt_fn_signature(fn_decl, &[param.to_string()], &return_type);
+    lines.push(call);
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates `regex_match_fields`, taking the input as Fields, asserting that every Field fits
+/// in an input code unit and forwarding the code units to `regex_match`.
+///
+/// # Arguments
+///
+/// * `unit` - The Noir type of the input code units, `u8` or `u16`.
+/// * `num_substrs` - The number of substrings returned by `regex_match`.
+/// * `capture_len` - The capacity of the capture vectors, see `NoirAutomaton::capture_len`.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `capture_starts` - A boolean indicating whether to return the start index of every
+///   capture.
+/// * `input_hash` - The hash function binding the input, if any.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+fn gen_field_input_fn(
+    unit: &str,
+    num_substrs: usize,
+    capture_len: &str,
+    gen_substrs: bool,
+    capture_starts: bool,
+    input_hash: Option<NoirInputHash>,
+) -> String {
+    let return_type = matcher_return_type(
+        num_substrs,
+        capture_len,
+        gen_substrs,
+        capture_starts,
+        input_hash,
+    );
+    let mut lines = fmt_fn_signature(
+        "pub fn regex_match_fields<let N: u32>",
+        &["input: [Field; N]".to_string()],
+        &return_type,
+    );
+    lines.extend([
+        format!("    let mut units: [{unit}; N] = [0; N];"),
+        "    for i in 0..N {".to_string(),
+        format!("        let unit = input[i] as {unit};"),
+        format!(
+            "        assert(unit as Field == input[i], \"input value does not fit in {unit}\");"
+        ),
+        "        units[i] = unit;".to_string(),
+        "    }".to_string(),
+    ]);
+    lines.push(match return_type.is_empty() {
+        true => "    regex_match(units);".to_string(),
+        false => "    regex_match(units)".to_string(),
+    });
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates the `ByteSource` trait over the representations of a byte input, with its
+/// implementations for `[u8; N]`, `BoundedVec<u8, N>` and `PackedBytes`, a wrapper of Fields
+/// holding 31 bytes each in little-endian order.
+///
+/// The bytes of a `BoundedVec` past its length are read as 0, and the padding of packed
+/// Fields is asserted to be 0 as for packed inputs.
+///
+/// # Returns
+///
+/// A String containing the Noir trait, struct and implementations.
+fn gen_byte_source_trait() -> String {
+    let mut lines = vec![
+        "pub trait ByteSource<let N: u32> {".to_string(),
+        "    fn to_bytes(self) -> [u8; N];".to_string(),
+        "}".to_string(),
+        String::new(),
+        "impl<let N: u32> ByteSource<N> for [u8; N] {".to_string(),
+        "    fn to_bytes(self) -> [u8; N] {".to_string(),
+        "        self".to_string(),
+        "    }".to_string(),
+        "}".to_string(),
+        String::new(),
+        "impl<let N: u32> ByteSource<N> for BoundedVec<u8, N> {".to_string(),
+        "    fn to_bytes(self) -> [u8; N] {".to_string(),
+        "        let mut bytes: [u8; N] = [0; N];".to_string(),
+        "        for i in 0..N {".to_string(),
+        "            if i < self.len() {".to_string(),
+        "                bytes[i] = self.get_unchecked(i);".to_string(),
+        "            }".to_string(),
+        "        }".to_string(),
+        "        bytes".to_string(),
+        "    }".to_string(),
+        "}".to_string(),
+        String::new(),
+        format!("/// N bytes packed {PACKED_BYTES} per Field in little-endian order."),
+        "pub struct PackedBytes<let N: u32, let M: u32> {".to_string(),
+        "    pub fields: [Field; M],".to_string(),
+        "}".to_string(),
+        String::new(),
+        "impl<let N: u32, let M: u32> ByteSource<N> for PackedBytes<N, M> {".to_string(),
+        "    fn to_bytes(self) -> [u8; N] {".to_string(),
+    ];
+    lines.extend(
+        unpack_lines("self.fields")
+            .into_iter()
+            .map(|line| format!("        {line}")),
+    );
+    lines.extend([
+        "        bytes".to_string(),
+        "    }".to_string(),
+        "}".to_string(),
+    ]);
+    lines.join("\n")
+}
+
+/// Generates the `StateInfo` struct describing the automaton of `next_state` to matching
+/// loops written by hand.
+///
+/// A loop starts from `initial` and feeds every input code unit to `next_state`. When it
+/// returns `reset`, no transition applies and the code unit is fed again from `start`, as
+/// `regex_match` does. The input matches if the last state is accepting. `initial` differs
+/// from `start` when the pattern has start anchors (^), which only match in `initial`. Its
+/// `step` method does both and also tells whether the new state is accepting.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the automaton of `next_state`.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `state` - The Noir type of the states.
+/// * `input_type` - The element type `next_state` is fed, u8 or u16.
+///
+/// # Returns
+///
+/// A String containing the Noir struct and its implementation.
+fn gen_state_info_fn(
+    automaton: &NoirAutomaton,
+    accept_sink: Option<usize>,
+    state: &str,
+    input_type: NoirInputType,
+) -> String {
+    let (var, typ, _) = input_vars(input_type);
+    let start = automaton.start_state;
+    let initial = automaton.init_state;
+    let mut accept_states = automaton.accept_states.clone();
+    accept_states.extend(accept_sink);
+    let mut accepting = states_condition("s", &accept_states)
+        .layout(8, 8, 0)
+        .into_iter();
+    let mut lines = vec![
+        "/// The automaton of `next_state`, for matching loops written by hand: start from"
+            .to_string(),
+        "/// `initial` and feed every code unit to `next_state`, feeding it again from `start`"
+            .to_string(),
+        "/// whenever `reset` is returned. The input matches if the last state is accepting."
+            .to_string(),
+        "pub struct StateInfo {".to_string(),
+        format!("    pub start: {state},"),
+        format!("    pub initial: {state},"),
+        format!("    pub reset: {state},"),
+        "}".to_string(),
+        String::new(),
+        "impl StateInfo {".to_string(),
+        "    pub fn new() -> Self {".to_string(),
+        format!("        StateInfo {{ start: {start}, initial: {initial}, reset: {RESET_STATE} }}"),
+        "    }".to_string(),
+        String::new(),
+        format!("    pub fn is_accepting(self, s: {state}) -> bool {{"),
+        format!("        {}", accepting.next().unwrap_or_default()),
+    ];
+    lines.extend(accepting);
+    lines.extend([
+        "    }".to_string(),
+        String::new(),
+        "    /// Feeds a code unit to `next_state` from `s`, feeding it again from `start` on a"
+            .to_string(),
+        "    /// reset, and returns the new state and whether it is accepting.".to_string(),
+        format!("    pub fn step(self, s: {state}, {var}: {typ}) -> ({state}, bool) {{"),
+        format!("        let mut next = next_state(s, {var});"),
+        "        if next == self.reset {".to_string(),
+        format!("            next = next_state(self.start, {var});"),
+        "        }".to_string(),
+        "        (next, self.is_accepting(next))".to_string(),
+        "    }".to_string(),
+        "}".to_string(),
+    ]);
+    lines.join("\n")
+}
+
+/// Writes the `next_state` function with the layout of the given strategy.
+///
+/// # Arguments
+///
+/// * `ctx` - The automaton to generate the transitions from, the type of its input and its
+///   accepting sink. The `class_predicates`, `max_branches` and `state_type` of the options
+///   shape the if-chain and keyword layouts and the type of the states.
+/// * `strategy` - The layout of the function, as chosen by `resolve_strategy`.
+/// * `hits` - The transitions taken over a corpus, which order the branches of the if-chain
+///   and keyword layouts.
+/// * `fn_decl` - The declaration of the function up to its name, e.g. `fn next_state`.
+/// * `out` - The writer the Noir function and the globals it uses are written to.
+///
+/// # Returns
+///
+/// An `io::Result` indicating whether the function was written.
+fn write_transitions_fn(
+    ctx: CodegenContext,
+    strategy: NoirStrategy,
+    hits: &TransitionHits,
+    fn_decl: &str,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let CodegenContext {
+        automaton,
+        options,
+        accept_sink,
+        ..
+    } = ctx;
+    let input_type = automaton.unit_type(ctx.input_type);
+    let ctx = CodegenContext { input_type, ..ctx };
+    let predicates = options.class_predicates;
+    let state = automaton.state_type(options.state_type);
+    match strategy {
+        NoirStrategy::Auto | NoirStrategy::IfChain => {
+            write_next_state_fn(ctx, false, predicates, hits, state, fn_decl, out)
+        }
+        NoirStrategy::Table => {
+            write_next_state_table_fn(automaton, accept_sink, state, fn_decl, out)
+        }
+        NoirStrategy::MuxTree => {
+            let code =
+                gen_next_state_mux_tree_fn(automaton, input_type, accept_sink, state, fn_decl);
+            out.write_all(code.as_bytes())
+        }
+        NoirStrategy::Keyword => {
+            write_next_state_fn(ctx, true, predicates, hits, state, fn_decl, out)
+        }
+    }
+}
+
+/// Generates the `next_state` function with the layout chosen by `resolve_strategy`, see
+/// `write_transitions_fn`.
+///
+/// # Returns
+///
+/// A String containing the Noir function and the globals it uses.
+#[cfg(feature = "fs")]
+fn gen_transitions_fn(ctx: CodegenContext, fn_decl: &str) -> String {
+    let strategy = resolve_strategy(ctx.automaton, ctx.input_type, ctx.options);
+    write_to_string(|out| write_transitions_fn(ctx, strategy, &TransitionHits::new(), fn_decl, out))
+}
+
+/// Runs a code writer on an in-memory buffer and returns the code it wrote.
+fn write_to_string(write: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> String {
+    let mut code = vec![];
+    write(&mut code).expect("writing to memory cannot fail");
+    String::from_utf8(code).expect("the generated code is UTF-8")
+}
+
+/// Writes the `next_state` functions and the globals they use.
+type TransitionsWriter<'a> = Box<dyn FnOnce(&mut dyn Write) -> io::Result<()> + 'a>;
+
+/// The generated Noir code, grouped by the module it belongs to when split.
+struct NoirSections<'a> {
+    /// The matcher entry points.
+    matcher: Vec<String>,
+    /// Helpers that do not depend on the automaton.
+    helpers: Vec<String>,
+    /// The `next_state` function and the globals it uses, which hold most of the code of
+    /// large automata and are therefore written out rather than built in memory.
+    transitions: TransitionsWriter<'a>,
+    /// Whether the transitions also hold a `prefix_next_state` function.
+    prefix_next_state: bool,
+}
+
+/// Generates the Noir code of the regex matcher.
+///
+/// The generated `regex_match` function runs the automaton over the whole input,
+/// restarting from the start state whenever no transition applies, and asserts
+/// that an accepting state was reached. Without an end anchor, the first match is
+/// final: every accepting state moves to an absorbing accepting state once it has
+/// no further transition for the input. With `reverse`, the reversed regex is run from
+/// the end of the input instead, so the last match is found first.
+///
+/// A `regex_match_unconstrained` function running the same automaton over the unpacked
+/// input is emitted as well, for use in unconstrained helpers that need to locate data
+/// before constraining it. It does not hash the input.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+///
+/// * `next_state_decl` - The declaration of `next_state` up to its name.
+///
+/// # Returns
+///
+/// The generated Noir code, split by module.
+fn gen_noir_sections<'a>(
+    regex_and_dfa: &'a RegexAndDFA,
+    gen_substrs: bool,
+    options: &'a NoirOptions,
+    next_state_decl: &'a str,
+) -> NoirSections<'a> {
+    let input_type = options.input_type;
+    let automaton = build_automaton(regex_and_dfa, options, gen_substrs);
+    // The reversed regex is never end-anchored, see `reverse_regex_and_dfa`.
+    let accept_sink = if regex_and_dfa.has_end_anchor && !options.reverse {
+        None
+    } else {
+        Some(automaton.free_state_id)
+    };
+
+    // Packed inputs are unpacked and then matched byte by byte.
+    let matcher_type = match input_type {
+        NoirInputType::PackedField => NoirInputType::U8,
+        input_type => input_type,
+    };
+    let num_captures = automaton.num_captures();
+    let alphabet = options
+        .input_alphabet
+        .map(|alphabet| alphabet_ranges(&automaton, alphabet));
+    let ctx = CodegenContext {
+        automaton: &automaton,
+        options,
+        input_type: matcher_type,
+        accept_sink,
+        gen_substrs,
+    };
+    let gen_matcher = |input_hash, fn_decl, segments, windowed| {
+        gen_regex_match_fn(
+            ctx,
+            &regex_and_dfa.regex_pattern,
+            alphabet.as_deref(),
+            input_hash,
+            fn_decl,
+            segments,
+            windowed,
+        )
+    };
+
+    let mut matcher = vec![];
+    if input_type == NoirInputType::PackedField {
+        matcher.push(gen_packed_regex_match_fn(
+            num_captures,
+            &automaton.capture_len(),
+            gen_substrs,
+            options.capture_starts,
+            options.input_hash,
+            &options.imports,
+        ));
+        matcher.push(gen_matcher(None, "fn regex_match_bytes", 1, false));
+    } else {
+        matcher.push(gen_matcher(
+            options.input_hash,
+            "pub fn regex_match",
+            1,
+            false,
+        ));
+    }
+    matcher.push(gen_matcher(
+        None,
+        "pub unconstrained fn regex_match_unconstrained",
+        1,
+        false,
+    ));
+    if let Some(segments) = options.input_segments {
+        matcher.push(gen_matcher(
+            options.input_hash,
+            "pub fn regex_match_segments",
+            segments,
+            false,
+        ));
+    }
+    if options.match_window {
+        matcher.push(gen_matcher(
+            options.input_hash,
+            "pub fn regex_match_window",
+            1,
+            true,
+        ));
+    }
+    if automaton.tagged {
+        matcher.push(gen_capture_tags_fn(
+            &automaton,
+            accept_sink,
+            automaton.state_type(options.state_type),
+            options.match_window,
+        ));
+    }
+    if input_type == NoirInputType::U8 {
+        matcher.push(gen_wrapper_fn(
+            ctx,
+            "pub fn regex_match_str<let N: u32>",
+            "input: str<N>",
+            "input.as_bytes()",
+            &automaton.capture_len(),
+        ));
+        if options.byte_source {
+            matcher.push(gen_byte_source_trait());
+            matcher.push(gen_wrapper_fn(
+                ctx,
+                "pub fn regex_match_source<let N: u32, S: ByteSource<N>>",
+                "input: S",
+                "input.to_bytes()",
+                &automaton.capture_len(),
+            ));
+        }
+    }
+    if options.field_input {
+        let unit = match input_type {
+            NoirInputType::U16 => "u16",
+            _ => "u8",
+        };
+        matcher.push(gen_field_input_fn(
+            unit,
+            num_captures,
+            &automaton.capture_len(),
+            gen_substrs,
+            options.capture_starts,
+            options.input_hash,
+        ));
+    }
+    if let Some(len) = options.fixed_len {
+        let (_, typ, _) = input_vars(input_type);
+        let capture_len = match automaton.capture_len().as_str() {
+            "N" => len.to_string(),
+            bound => bound.to_string(),
+        };
+        matcher.push(gen_wrapper_fn(
+            ctx,
+            "pub fn regex_match_fixed",
+            &format!("input: [{typ}; {len}]"),
+            "input",
+            &capture_len,
+        ));
+    }
+    if options.public_next_state {
+        matcher.push(gen_state_info_fn(
+            &automaton,
+            accept_sink,
+            automaton.state_type(options.state_type),
+            matcher_type,
+        ));
+    }
+    if let (true, Some(groups)) = (gen_substrs, &regex_and_dfa.substrings.substring_groups) {
+        matcher[0] = format!("{}\n{}", gen_capture_groups_comment(groups), matcher[0]);
+    }
+    if let (true, Some(names)) = (gen_substrs, &regex_and_dfa.substrings.substring_names) {
+        matcher[0] = format!("{}\n{}", gen_substring_names_comment(names), matcher[0]);
+    }
+    let mut helpers = vec![];
+    if gen_substrs {
+        helpers.push(gen_capture_helpers_fn(input_type));
+    }
+    if gen_substrs && options.lowercase_captures {
+        helpers.push(gen_to_ascii_lowercase_fn(matcher_type));
+    }
+    if gen_substrs && options.reverse {
+        helpers.push(gen_reverse_capture_fn());
+    }
+    if gen_substrs && options.packed_captures {
+        matcher.push(gen_packed_captures_fn(
+            num_captures,
+            input_type,
+            options.capture_starts,
+            options.input_hash,
+        ));
+        helpers.push(gen_capture_to_packed_fn());
+    }
+    if gen_substrs && options.capture_digests {
+        matcher.push(gen_capture_digests_fn(
+            num_captures,
+            input_type,
+            options.capture_starts,
+            options.input_hash,
+        ));
+        helpers.push(gen_capture_to_sha256_fn(&options.imports));
+    }
+    if gen_substrs && options.capture_lens {
+        matcher.push(gen_capture_lens_fn(
+            num_captures,
+            &automaton.capture_len(),
+            input_type,
+            options.capture_starts,
+            options.input_hash,
+        ));
+    }
+    if let (true, Some(hash)) = (options.verify_input_hash, options.input_hash) {
+        matcher.push(gen_verified_input_fn(
+            num_captures,
+            &automaton.capture_len(),
+            input_type,
+            gen_substrs,
+            options.capture_starts,
+            hash,
+        ));
+    }
+    let numeric = part_capture_indices(regex_and_dfa, &automaton, |part| part.numeric);
+    if gen_substrs && !numeric.is_empty() {
+        let ranges = numeric_capture_ranges(regex_and_dfa, &automaton);
+        matcher.push(gen_numeric_captures_fn(num_captures, &numeric, &ranges));
+        helpers.push(gen_capture_to_integer_fn());
+    }
+    if let (Some(runtime), false) = (&options.runtime, helpers.is_empty()) {
+        matcher.insert(0, gen_runtime_imports(runtime, &helpers, matcher_type));
+        helpers.clear();
+    }
+    let holes = part_capture_indices(regex_and_dfa, &automaton, |part| part.hole);
+    if gen_substrs && !holes.is_empty() {
+        matcher.push(gen_hole_commitments_fn(
+            num_captures,
+            &holes,
+            &options.imports,
+        ));
+    }
+    // The tags of an input that does not match are meaningless, so the prefix and the mask
+    // are computed on the untagged automaton with its own transitions.
+    let untagged = ((options.prefix_len
+        || options.match_mask
+        || options.match_len
+        || options.match_end
+        || options.match_at
+        || options.redaction.is_some())
+        && automaton.tagged)
+        .then(|| build_automaton(regex_and_dfa, options, false));
+    let (plain, plain_sink, plain_next_state) = match &untagged {
+        Some(untagged) => (
+            untagged,
+            accept_sink.map(|_| untagged.free_state_id),
+            "prefix_next_state",
+        ),
+        None => (&automaton, accept_sink, "next_state"),
+    };
+    if options.prefix_len {
+        matcher.push(gen_prefix_len_fn(
+            plain,
+            &regex_and_dfa.regex_pattern,
+            matcher_type,
+            plain_sink,
+            plain_next_state,
+        ));
+    }
+    let plain_alphabet = match &untagged {
+        Some(untagged) => options
+            .input_alphabet
+            .map(|alphabet| alphabet_ranges(untagged, alphabet)),
+        None => alphabet.clone(),
+    };
+    if options.match_mask {
+        matcher.push(gen_match_mask_fn(
+            plain,
+            &regex_and_dfa.regex_pattern,
+            matcher_type,
+            plain_sink,
+            plain_alphabet.as_deref(),
+            plain_next_state,
+        ));
+    }
+    if options.match_len {
+        matcher.push(gen_match_len_fn(
+            plain,
+            &regex_and_dfa.regex_pattern,
+            matcher_type,
+            plain_sink,
+            plain_alphabet.as_deref(),
+            plain_next_state,
+        ));
+    }
+    if let Some(mask) = options.redaction {
+        matcher.push(gen_match_redacted_fn(
+            plain,
+            &regex_and_dfa.regex_pattern,
+            matcher_type,
+            plain_sink,
+            plain_alphabet.as_deref(),
+            plain_next_state,
+            mask,
+        ));
+    }
+    if options.match_end {
+        matcher.push(gen_match_end_fn(
+            plain,
+            &regex_and_dfa.regex_pattern,
+            matcher_type,
+            plain_sink,
+            plain_alphabet.as_deref(),
+            plain_next_state,
+        ));
+    }
+    if options.match_at {
+        matcher.push(gen_match_at_fn(
+            plain,
+            &regex_and_dfa.regex_pattern,
+            matcher_type,
+            plain_sink,
+            plain_alphabet.as_deref(),
+            plain_next_state,
+        ));
+    }
+    let prefix_next_state = untagged.is_some();
+    let strategy = resolve_strategy(&automaton, matcher_type, options);
+    let hits = match options.corpus.is_empty() {
+        true => TransitionHits::new(),
+        false => {
+            let (hits, matched) = run_corpus(&automaton, accept_sink, options);
+            info!(
+                inputs = options.corpus.len(),
+                matched, "ordered the next_state branches by the transitions the corpus takes"
+            );
+            hits
+        }
+    };
+    let transitions = Box::new(move |out: &mut dyn Write| {
+        let ctx = CodegenContext {
+            automaton: &automaton,
+            options,
+            input_type: matcher_type,
+            accept_sink,
+            gen_substrs,
+        };
+        write_transitions_fn(ctx, strategy, &hits, next_state_decl, out)?;
+        if let Some(untagged) = untagged {
+            let prefix_decl = next_state_decl.replace("next_state", "prefix_next_state");
+            write!(out, "\n\n")?;
+            let ctx = CodegenContext {
+                automaton: &untagged,
+                accept_sink: accept_sink.map(|_| untagged.free_state_id),
+                ..ctx
+            };
+            write_next_state_fn(
+                ctx,
+                false,
+                false,
+                &TransitionHits::new(),
+                untagged.state_type(options.state_type),
+                &prefix_decl,
+                out,
+            )?;
+        }
+        Ok(())
+    });
+
+    NoirSections {
+        matcher,
+        helpers,
+        transitions,
+        prefix_next_state,
+    }
+}
+
+/// Writes the Noir code of the regex matcher as a single file, see `gen_noir_sections`.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+/// * `out` - The writer the generated Noir code is written to.
+///
+/// # Returns
+///
+/// An `io::Result` indicating whether the code was written.
+fn write_noir_fn(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let next_state_decl = match options.public_next_state {
+        true => "pub fn next_state",
+        false => "fn next_state",
+    };
+    let sections = gen_noir_sections(regex_and_dfa, gen_substrs, options, next_state_decl);
+    let imports = gen_imports(&options.imports);
+    for section in imports
+        .iter()
+        .chain(&sections.matcher)
+        .chain(&sections.helpers)
+    {
+        write!(out, "{section}\n\n")?;
+    }
+    (sections.transitions)(out)?;
+    writeln!(out)
+}
+
+/// Generates the Noir code of the regex matcher as a single file, see `write_noir_fn`.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A String containing the generated Noir code.
+pub(crate) fn to_noir_fn(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+) -> String {
+    write_to_string(|out| write_noir_fn(regex_and_dfa, gen_substrs, options, out))
+}
+
+/// Writes the Noir code of the regex matcher split into modules, see `gen_noir_sections`.
+///
+/// The main file declares a private `transitions` module holding `next_state` and, when
+/// substrings are extracted, a public `helpers` module holding the capture conversions.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+/// * `open` - Opens the writer of a module given its name, empty for the main file.
+///
+/// # Returns
+///
+/// An `io::Result` containing the (module name, writer) tuples, starting with the main file.
+fn write_noir_modules<W: Write>(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+    mut open: impl FnMut(&str) -> io::Result<W>,
+) -> io::Result<Vec<(String, W)>> {
+    let sections = gen_noir_sections(regex_and_dfa, gen_substrs, options, "pub fn next_state");
+    let mut header = vec!["mod transitions;".to_string()];
+    if !sections.helpers.is_empty() {
+        header.push("pub mod helpers;".to_string());
+    }
+    header.push(String::new());
+    header.extend(gen_imports(&options.imports));
+    match options.public_next_state {
+        true => header.push("pub use transitions::next_state;".to_string()),
+        false => header.push("use transitions::next_state;".to_string()),
+    }
+    if sections.prefix_next_state {
+        header.push("use transitions::prefix_next_state;".to_string());
+    }
+    if sections
+        .helpers
+        .iter()
+        .any(|helper| helper.starts_with("pub fn capture_to_packed"))
+    {
+        header.push("use helpers::capture_to_packed;".to_string());
+    }
+    if sections
+        .helpers
+        .iter()
+        .any(|helper| helper.starts_with("pub fn capture_to_sha256"))
+    {
+        header.push("use helpers::capture_to_sha256;".to_string());
+    }
+    if sections
+        .helpers
+        .iter()
+        .any(|helper| helper.starts_with("pub fn capture_to_field"))
+    {
+        header.push("use helpers::capture_to_u64;".to_string());
+    }
+    if sections
+        .helpers
+        .iter()
+        .any(|helper| helper.starts_with("pub fn to_ascii_lowercase"))
+    {
+        header.push("use helpers::to_ascii_lowercase;".to_string());
+    }
+    if sections
+        .helpers
+        .iter()
+        .any(|helper| helper.starts_with("pub fn reverse_capture"))
+    {
+        header.push("use helpers::reverse_capture;".to_string());
+    }
+
+    let mut main = vec![header.join("\n")];
+    main.extend(sections.matcher);
+    let mut main_file = open("")?;
+    writeln!(main_file, "{}", main.join("\n\n"))?;
+    let mut transitions = open("transitions")?;
+    (sections.transitions)(&mut transitions)?;
+    writeln!(transitions)?;
+    let mut modules = vec![
+        (String::new(), main_file),
+        ("transitions".to_string(), transitions),
+    ];
+    if !sections.helpers.is_empty() {
+        let mut helpers = gen_imports(&options.imports).into_iter().collect_vec();
+        helpers.extend(sections.helpers);
+        let mut helpers_file = open("helpers")?;
+        writeln!(helpers_file, "{}", helpers.join("\n\n"))?;
+        modules.push(("helpers".to_string(), helpers_file));
+    }
+    Ok(modules)
+}
+
+/// Generates the Noir code of the regex matcher split into modules, see `write_noir_modules`.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Vec of (module name, code) tuples, starting with the main file under an empty name.
+pub(crate) fn to_noir_modules(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+) -> Vec<(String, String)> {
+    write_noir_modules(regex_and_dfa, gen_substrs, options, |_| Ok(vec![]))
+        .expect("writing to memory cannot fail")
+        .into_iter()
+        .map(|(name, code)| {
+            (
+                name,
+                String::from_utf8(code).expect("the generated code is UTF-8"),
+            )
+        })
+        .collect()
+}
+
+/// Checks that the regex can be matched in Noir with the given options.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Result indicating whether the options are supported or a CompilerError.
+fn validate_noir_options(
+    regex_and_dfa: &RegexAndDFA,
+    options: &NoirOptions,
+) -> Result<(), CompilerError> {
+    if !regex_and_dfa
+        .dfa
+        .states
+        .iter()
+        .any(|state| state.state_type == "accept")
+    {
+        return Err(CompilerError::NoAcceptedState);
+    }
+    if options.input_type == NoirInputType::U16 && options.input_hash == Some(NoirInputHash::Sha256)
+    {
+        return Err(CompilerError::GenericError(
+            "SHA-256 input hashing requires u8 or packed input".to_string(),
+        ));
+    }
+    if options.verify_input_hash && options.input_hash.is_none() {
+        return Err(CompilerError::GenericError(
+            "verifying the input hash requires an input hash".to_string(),
+        ));
+    }
+    if options.input_type == NoirInputType::U16 && options.strategy == NoirStrategy::Table {
+        return Err(CompilerError::GenericError(
+            "the table strategy requires u8 or packed input".to_string(),
+        ));
+    }
+    if options.prefix_len && (options.input_type == NoirInputType::PackedField || options.reverse) {
+        return Err(CompilerError::GenericError(
+            "the prefix length requires u8 or u16 input scanned forwards".to_string(),
+        ));
+    }
+    if options.max_branches == Some(0) {
+        return Err(CompilerError::GenericError(
+            "next_state must keep at least 1 branch per function".to_string(),
+        ));
+    }
+    if options.input_segments.is_some_and(|segments| segments < 2) {
+        return Err(CompilerError::GenericError(
+            "the input must be split into at least 2 segments".to_string(),
+        ));
+    }
+    if options.input_segments.is_some()
+        && (options.input_type == NoirInputType::PackedField || options.reverse)
+    {
+        return Err(CompilerError::GenericError(
+            "input segments require u8 or u16 input scanned forwards".to_string(),
+        ));
+    }
+    if options.match_window && (options.input_type == NoirInputType::PackedField || options.reverse)
+    {
+        return Err(CompilerError::GenericError(
+            "the match window requires u8 or u16 input scanned forwards".to_string(),
+        ));
+    }
+    if options.match_mask
+        && (options.input_type == NoirInputType::PackedField
+            || options.reverse
+            || options.occurrence.is_some_and(|occurrence| occurrence > 1))
+    {
+        return Err(CompilerError::GenericError(
+            "the match mask requires u8 or u16 input scanned forwards for the first occurrence"
+                .to_string(),
+        ));
+    }
+    if options.match_len
+        && (options.input_type == NoirInputType::PackedField
+            || options.reverse
+            || options.occurrence.is_some_and(|occurrence| occurrence > 1))
+    {
+        return Err(CompilerError::GenericError(
+            "the match length requires u8 or u16 input scanned forwards for the first occurrence"
+                .to_string(),
+        ));
+    }
+    if let Some(mask) = options.redaction {
+        if options.input_type == NoirInputType::PackedField
+            || options.reverse
+            || options.occurrence.is_some_and(|occurrence| occurrence > 1)
+        {
+            return Err(CompilerError::GenericError(
+                "redaction requires u8 or u16 input scanned forwards for the first occurrence"
+                    .to_string(),
+            ));
+        }
+        let max_mask = match options.input_type {
+            NoirInputType::U16 => u16::MAX as u32,
+            _ => 0x7F,
+        };
+        if mask as u32 > max_mask {
+            return Err(CompilerError::GenericError(format!(
+                "the redaction mask {:?} does not fit in one code unit of the input",
+                mask
+            )));
+        }
+    }
+    if options.match_bound == Some(0) {
+        return Err(CompilerError::GenericError(
+            "the match bound must be at least 1".to_string(),
+        ));
+    }
+    if let Some(bound) = options.match_bound {
+        if options.reverse || regex_and_dfa.has_end_anchor {
+            return Err(CompilerError::GenericError(
+                "the match bound requires a pattern without an end anchor ($) scanned forwards"
+                    .to_string(),
+            ));
+        }
+        let min_len = build_automaton(regex_and_dfa, options, false)
+            .min_match_len()
+            .unwrap_or(0);
+        if bound < min_len {
+            return Err(CompilerError::GenericError(format!(
+                "the match bound of {} code units is shorter than the shortest match of {}",
+                bound, min_len
+            )));
+        }
+    }
+    if options.match_end
+        && (options.input_type == NoirInputType::PackedField
+            || options.reverse
+            || options.occurrence.is_some_and(|occurrence| occurrence > 1))
+    {
+        return Err(CompilerError::GenericError(
+            "the match end requires u8 or u16 input scanned forwards for the first occurrence"
+                .to_string(),
+        ));
+    }
+    if options.match_at
+        && (options.input_type == NoirInputType::PackedField
+            || options.reverse
+            || options.occurrence.is_some_and(|occurrence| occurrence > 1))
+    {
+        return Err(CompilerError::GenericError(
+            "the match offset requires u8 or u16 input scanned forwards for the first occurrence"
+                .to_string(),
+        ));
+    }
+    if options.fixed_len == Some(0) {
+        return Err(CompilerError::GenericError(
+            "the fixed input length must be at least 1".to_string(),
+        ));
+    }
+    if options.fixed_len.is_some() && options.input_type == NoirInputType::PackedField {
+        return Err(CompilerError::GenericError(
+            "the fixed-length entry point requires u8 or u16 input".to_string(),
+        ));
+    }
+    if options.byte_source && options.input_type != NoirInputType::U8 {
+        return Err(CompilerError::GenericError(
+            "the byte source entry point requires u8 input".to_string(),
+        ));
+    }
+    if let Some(runtime) = &options.runtime {
+        let valid_name = runtime.starts_with(|c: char| c.is_ascii_alphabetic())
+            && runtime
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(CompilerError::GenericError(format!(
+                "runtime library name \"{}\" is not a valid Nargo package name",
+                runtime
+            )));
+        }
+    }
+    if options.field_input && options.input_type == NoirInputType::PackedField {
+        return Err(CompilerError::GenericError(
+            "the Field input entry point requires u8 or u16 input".to_string(),
+        ));
+    }
+    if options.input_type == NoirInputType::U16 && options.packed_captures {
+        return Err(CompilerError::GenericError(
+            "packed captures require u8 or packed input".to_string(),
+        ));
+    }
+    if regex_and_dfa
+        .parts
+        .iter()
+        .any(|part| part.max_length == Some(0))
+    {
+        return Err(CompilerError::GenericError(
+            "max_length must be at least 1".to_string(),
+        ));
+    }
+    if options.occurrence == Some(0) {
+        return Err(CompilerError::GenericError(
+            "the occurrence must be at least 1".to_string(),
+        ));
+    }
+    if options.occurrence.is_some_and(|occurrence| occurrence > 1) {
+        if regex_and_dfa.has_end_anchor && !options.reverse {
+            return Err(CompilerError::GenericError(
+                "selecting a later occurrence requires a pattern without an end anchor".to_string(),
+            ));
+        }
+        if regex_and_dfa
+            .parts
+            .iter()
+            .any(|part| part.max_repetitions.is_some())
+        {
+            return Err(CompilerError::GenericError(
+                "selecting a later occurrence is not supported with repeated captures".to_string(),
+            ));
+        }
+        // The next occurrence may start inside an abandoned extension of the previous one.
+        if options.match_semantics == NoirMatchSemantics::Longest && !options.reverse {
+            return Err(CompilerError::GenericError(
+                "selecting a later occurrence is not supported with the longest match semantics"
+                    .to_string(),
+            ));
+        }
+    }
+    if options.reverse_window.is_some() && !options.reverse {
+        return Err(CompilerError::GenericError(
+            "a reverse window requires reverse matching".to_string(),
+        ));
+    }
+    if options.reverse_window == Some(0) {
+        return Err(CompilerError::GenericError(
+            "the reverse window must be at least 1".to_string(),
+        ));
+    }
+    if options.reverse {
+        if options.input_type == NoirInputType::U16 {
+            return Err(CompilerError::GenericError(
+                "reverse matching requires u8 or packed input".to_string(),
+            ));
+        }
+        let start_anchored = |dfa: &DFAGraph| {
+            dfa.states.first().is_some_and(|state| {
+                state
+                    .transitions
+                    .values()
+                    .any(|bytes| bytes.contains(&PRIMING_BYTE))
+            })
+        };
+        if regex_and_dfa.whole_input {
+            return Err(CompilerError::GenericError(
+                "reverse matching does not support automata matched against the whole input"
+                    .to_string(),
+            ));
+        }
+        if start_anchored(&regex_and_dfa.dfa)
+            || regex_and_dfa
+                .parts
+                .first()
+                .is_some_and(|part| start_anchored(&part.dfa))
+        {
+            return Err(CompilerError::GenericError(
+                "reverse matching does not support patterns anchored with ^".to_string(),
+            ));
+        }
+        if regex_and_dfa
+            .parts
+            .iter()
+            .any(|part| part.max_repetitions.is_some())
+        {
+            return Err(CompilerError::GenericError(
+                "reverse matching is not supported with repeated captures".to_string(),
+            ));
+        }
+    }
+    if options.repetition_counter.is_some() && !regex_and_dfa.parts.is_empty() {
+        return Err(CompilerError::GenericError(
+            "repetition counters require a raw regex".to_string(),
+        ));
+    }
+    if !regex_and_dfa.counters.is_empty() {
+        if options.input_type == NoirInputType::U16 {
+            return Err(CompilerError::GenericError(
+                "repetition counters require u8 or packed input".to_string(),
+            ));
+        }
+        if options.reverse
+            || options.occurrence.is_some_and(|occurrence| occurrence > 1)
+            || options.prefix_len
+            || options.match_mask
+            || options.match_len
+            || options.match_end
+            || options.match_at
+            || options.redaction.is_some()
+            || options.public_next_state
+        {
+            return Err(CompilerError::GenericError(
+                "repetition counters are not supported with reverse matching, later occurrences, the prefix length, the match mask, the match length, the match end, the match offset, redaction or a public next_state"
+                    .to_string(),
+            ));
+        }
+    }
+    if options.input_type == NoirInputType::U16 && options.capture_digests {
+        return Err(CompilerError::GenericError(
+            "capture digests require u8 or packed input".to_string(),
+        ));
+    }
+    if options.input_type == NoirInputType::U16
+        && regex_and_dfa
+            .parts
+            .iter()
+            .any(|part| part.max_repetitions.is_some())
+    {
+        return Err(CompilerError::GenericError(
+            "repeated captures require u8 or packed input".to_string(),
+        ));
+    }
+    // The DFA may accept while the matcher cannot, e.g. once the start anchor is removed.
+    if build_automaton(regex_and_dfa, options, false)
+        .min_match_len()
+        .is_none()
+    {
+        return Err(CompilerError::GenericError(
+            "the pattern can never match: no accepting state is reachable in the Noir matcher"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Generates a Noir file containing the regex matcher for the given regex and DFA.
+///
+/// The code is written out as it is generated, so that the output of a large automaton is
+/// never held in memory as a whole.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `noir_path` - The path where the generated Noir file should be saved. When the output
+///   is split into modules, they are saved in a directory named after the file's stem.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Result indicating success or a CompilerError.
+#[cfg(feature = "fs")]
+pub(crate) fn gen_noir_fn(
+    regex_and_dfa: &RegexAndDFA,
+    noir_path: &Path,
+    gen_substrs: bool,
+    options: &NoirOptions,
+) -> Result<(), CompilerError> {
+    validate_noir_matcher(regex_and_dfa, gen_substrs, options)?;
+    let module_dir = noir_path.with_extension("");
+    let open = |name: &str| {
+        let path = if name.is_empty() {
+            noir_path.to_path_buf()
+        } else {
+            create_dir_all(&module_dir)?;
+            module_dir.join(format!("{name}.nr"))
+        };
+        Ok(CompactWriter::new(
+            FileWriter::create(&path)?,
+            options.compact,
+        ))
+    };
+    let files = match options.split_modules {
+        true => write_noir_modules(regex_and_dfa, gen_substrs, options, open)?,
+        false => {
+            let mut file = open("")?;
+            write_noir_fn(regex_and_dfa, gen_substrs, options, &mut file)?;
+            vec![(String::new(), file)]
+        }
+    };
+    let modules = files.len();
+    let mut bytes = 0;
+    for (_, file) in files {
+        bytes += file.into_inner()?.finish()?;
+    }
+    info!(modules, bytes, "wrote the Noir matcher");
+    Ok(())
+}
+
+/// Strips the comments from Noir code and collapses its whitespace, keeping one line per
+/// line of code without indentation. String literals are left untouched.
+///
+/// # Arguments
+///
+/// * `code` - The Noir code.
+///
+/// # Returns
+///
+/// The compacted code, ending with a newline.
+pub(crate) fn compact_noir(code: &str) -> String {
+    let mut lines = vec![];
+    for line in code.lines() {
+        let mut compacted = String::new();
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if in_string {
+                compacted.push(c);
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '/' if chars.peek() == Some(&'/') => break,
+                '"' => {
+                    in_string = true;
+                    compacted.push(c);
+                }
+                _ if c.is_whitespace() => {
+                    if !compacted.is_empty() && !compacted.ends_with(' ') {
+                        compacted.push(' ');
+                    }
+                }
+                _ => compacted.push(c),
+            }
+        }
+        let compacted = compacted.trim_end();
+        if !compacted.is_empty() {
+            lines.push(compacted.to_string());
+        }
+    }
+    format!("{}\n", lines.join("\n"))
+}
+
+/// Compacts the Noir code written through it a line at a time when enabled, see
+/// `compact_noir`.
+#[cfg(feature = "fs")]
+struct CompactWriter<W: Write> {
+    inner: W,
+    compact: bool,
+    /// The line being written.
+    line: Vec<u8>,
+}
+
+#[cfg(feature = "fs")]
+impl<W: Write> CompactWriter<W> {
+    fn new(inner: W, compact: bool) -> Self {
+        CompactWriter {
+            inner,
+            compact,
+            line: vec![],
+        }
+    }
+
+    /// Writes the compacted line, dropping it if nothing is left of it.
+    fn write_line(&mut self) -> io::Result<()> {
+        let line = String::from_utf8_lossy(&self.line);
+        let compacted = compact_noir(&line);
+        if compacted != "\n" {
+            self.inner.write_all(compacted.as_bytes())?;
+        }
+        self.line.clear();
+        Ok(())
+    }
+
+    /// Writes the last line if it has no line break and returns the inner writer.
+    fn into_inner(mut self) -> io::Result<W> {
+        if !self.line.is_empty() {
+            self.write_line()?;
+        }
+        Ok(self.inner)
+    }
+}
+
+#[cfg(feature = "fs")]
+impl<W: Write> Write for CompactWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.compact {
+            return self.inner.write(buf);
+        }
+        for chunk in buf.split_inclusive(|&byte| byte == b'\n') {
+            self.line.extend_from_slice(chunk);
+            if chunk.ends_with(b"\n") {
+                self.write_line()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes a generated Noir file, compacting it when the options ask for it.
+///
+/// # Arguments
+///
+/// * `noir_path` - The path of the file.
+/// * `noir` - The generated code.
+/// * `options` - The options the code was generated with.
+///
+/// # Returns
+///
+/// A Result indicating success or a CompilerError.
+#[cfg(feature = "fs")]
+fn write_noir_file(
+    noir_path: &Path,
+    noir: &str,
+    options: &NoirOptions,
+) -> Result<(), CompilerError> {
+    let noir = match options.compact {
+        true => compact_noir(noir),
+        false => noir.to_string(),
+    };
+    write_file(noir_path, noir.as_bytes())?;
+    Ok(())
+}
+
+/// Checks that the longest match semantics do not reveal the captures of an extension that
+/// may be abandoned, as for `ab(cd)?` on `abc`: the `c` would be revealed although the match
+/// is `ab`.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Result indicating whether the captures can be extracted or a CompilerError.
+fn validate_longest_captures(
+    regex_and_dfa: &RegexAndDFA,
+    options: &NoirOptions,
+) -> Result<(), CompilerError> {
+    // See `build_automaton` for where the longest match semantics apply.
+    if options.match_semantics != NoirMatchSemantics::Longest
+        || options.reverse
+        || regex_and_dfa.has_end_anchor
+        || !regex_and_dfa.counters.is_empty()
+    {
+        return Ok(());
+    }
+    let greedy = NoirOptions {
+        match_semantics: NoirMatchSemantics::Greedy,
+        ..options.clone()
+    };
+    let automaton = build_automaton(regex_and_dfa, &greedy, true);
+    let extensions = extension_states(&automaton);
+    let abandoned = automaton
+        .substr_transitions
+        .iter()
+        .flatten()
+        .any(|(from, to)| {
+            extensions.contains(to)
+                && (automaton.accept_states.contains(from) || extensions.contains(from))
+        });
+    if abandoned {
+        return Err(CompilerError::GenericError(
+            "the longest match semantics cannot extract a capture past an accepting state, \
+             since the match may end before it"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the regex matcher can be generated with the given options.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Result indicating whether the matcher can be generated or a CompilerError.
+fn validate_noir_matcher(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+) -> Result<(), CompilerError> {
+    validate_noir_options(regex_and_dfa, options)?;
+    if gen_substrs {
+        // The default substring definitions of a raw regex hold a single empty set.
+        if options.reverse
+            && regex_and_dfa.parts.is_empty()
+            && regex_and_dfa
+                .substrings
+                .substring_ranges
+                .iter()
+                .any(|ranges| !ranges.is_empty())
+        {
+            return Err(CompilerError::GenericError(
+                "reverse matching requires a decomposed regex to extract substrings".to_string(),
+            ));
+        }
+        validate_longest_captures(regex_and_dfa, options)?;
+        for warning in capture_bound_warnings(regex_and_dfa) {
+            warn!("{warning}");
+        }
+    }
+    if let Some(warning) = trivial_match_warning(regex_and_dfa, options) {
+        warn!("{warning}");
+    }
+    if let Some(warning) = lost_match_warning(regex_and_dfa, options) {
+        warn!("{warning}");
+    }
+    if let Some(warning) = cut_match_warning(regex_and_dfa, options) {
+        warn!("{warning}");
+    }
+    if let Some(warning) = gen_substrs
+        .then(|| capture_ambiguity_warning(regex_and_dfa, options))
+        .flatten()
+    {
+        warn!("{warning}");
+    }
+    if let Some(count) = options.capture_count {
+        if !gen_substrs {
+            return Err(CompilerError::GenericError(
+                "the capture count requires the substrings to be extracted".to_string(),
+            ));
+        }
+        let slots = build_automaton(regex_and_dfa, options, true).num_captures();
+        if count > slots {
+            return Err(CompilerError::GenericError(format!(
+                "the capture count of {} exceeds the {} captures the pattern has",
+                count, slots
+            )));
+        }
+    }
+    if options.public_next_state && build_automaton(regex_and_dfa, options, gen_substrs).tagged {
+        return Err(CompilerError::GenericError(
+            "the public next_state takes bytes tagged with their captures; generate it without substrings"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Generates the Noir code of the regex matcher after checking the options, as a single
+/// file or split into modules depending on the options.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Result containing the (module name, code) tuples, starting with the main file under an
+/// empty name, or a CompilerError.
+pub(crate) fn gen_noir_sources(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+) -> Result<Vec<(String, String)>, CompilerError> {
+    validate_noir_matcher(regex_and_dfa, gen_substrs, options)?;
+    let mut sources = if options.split_modules {
+        to_noir_modules(regex_and_dfa, gen_substrs, options)
+    } else {
+        vec![(
+            String::new(),
+            to_noir_fn(regex_and_dfa, gen_substrs, options),
+        )]
+    };
+    if options.compact {
+        for (_, code) in &mut sources {
+            *code = compact_noir(code);
+        }
+    }
+    info!(
+        modules = sources.len(),
+        lines = sources
+            .iter()
+            .map(|(_, code)| code.lines().count())
+            .sum::<usize>(),
+        "generated the Noir matcher"
+    );
+    Ok(sources)
+}
+
+/// Builds the automaton run by the Noir matcher, for the tools consuming it without
+/// generating code.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `options` - The options controlling the generated code, which shape the automaton.
+/// * `gen_substrs` - A boolean indicating whether the matcher extracts the substrings.
+///
+/// # Returns
+///
+/// A Result containing the automaton or a CompilerError.
+pub(crate) fn compiled_automaton(
+    regex_and_dfa: &RegexAndDFA,
+    options: &NoirOptions,
+    gen_substrs: bool,
+) -> Result<CompiledAutomaton, CompilerError> {
+    validate_noir_options(regex_and_dfa, options)?;
+    if !regex_and_dfa.counters.is_empty() {
+        return Err(CompilerError::GenericError(
+            "the automaton of a regex with repetition counters cannot be exposed".to_string(),
+        ));
+    }
+    let automaton = build_automaton(regex_and_dfa, options, false);
+    // The reversed regex is never end-anchored, see `reverse_regex_and_dfa`.
+    let accept_sink = if regex_and_dfa.has_end_anchor && !options.reverse {
+        None
+    } else {
+        Some(automaton.free_state_id)
+    };
+
+    let mut ids: BTreeSet<usize> = automaton
+        .transitions
+        .keys()
+        .flat_map(|&(from, to)| [from, to])
+        .collect();
+    ids.extend([automaton.init_state, automaton.start_state]);
+    ids.extend(&automaton.accept_states);
+    ids.extend(accept_sink);
+    let states = ids
+        .into_iter()
+        .map(|id| AutomatonState {
+            id,
+            accepting: automaton.accept_states.contains(&id) || accept_sink == Some(id),
+        })
+        .collect();
+    let transitions = automaton
+        .transitions
+        .iter()
+        .map(|(&(from, to), ranges)| AutomatonTransition {
+            from,
+            to,
+            ranges: ranges.clone(),
+            substrings: automaton
+                .substr_transitions
+                .iter()
+                .positions(|transitions| transitions.contains(&(from, to)))
+                .collect(),
+        })
+        .collect();
+    Ok(CompiledAutomaton {
+        init_state: automaton.init_state,
+        start_state: automaton.start_state,
+        accept_sink,
+        states,
+        transitions,
+        num_substrings: automaton.substr_transitions.len(),
+        matcher: MatcherRun::new(regex_and_dfa, options, gen_substrs),
+    })
+}
+
+/// The input length `NoirStrategy::Auto` chooses the layout for when the options do not give
+/// one.
+const DEFAULT_INPUT_LEN: usize = 1024;
+
+/// The comparisons a read of a `global` array at an index computed from the state costs: the
+/// read and its record in the sorted copy of the array checking it, plus the range checks of
+/// the `u32` index and of its bound, two comparisons each.
+const LOOKUP_COMPARISONS: usize = 6;
+
+/// The gates an entry of a `global` array read at a dynamic index costs, once written into
+/// the memory of the circuit and once into the sorted copy checking the reads.
+const GLOBAL_ENTRY_GATES: usize = 2;
+
+/// Estimates the number of comparisons `next_state` makes per input code unit with a strategy.
+///
+/// Every branch of `next_state` is evaluated in the circuit, so a transition of an if-chain
+/// costs one state comparison plus one or two comparisons per code unit range. A run of
+/// literal code units found by the keyword strategy costs two state comparisons and a lookup.
+/// A mux tree compares only the code units in its leaves, but selects among the states with
+/// one comparison per state. A table costs a single lookup whatever the automaton, see
+/// `LOOKUP_COMPARISONS`.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the automaton the function is generated from.
+/// * `max_unit` - The largest input code unit.
+/// * `strategy` - The layout of the function, `Auto` counting as an if-chain.
+///
+/// # Returns
+///
+/// The estimated number of comparisons per input code unit.
+fn next_state_comparisons(
+    automaton: &NoirAutomaton,
+    max_unit: u32,
+    strategy: NoirStrategy,
+) -> usize {
+    let comparisons = |ranges: &[(u32, u32)]| {
+        ranges
+            .iter()
+            .map(|(lo, hi)| if lo == hi { 1 } else { 2 })
+            .sum::<usize>()
+    };
+    let runs = match strategy {
+        NoirStrategy::Keyword => find_keyword_runs(automaton),
+        _ => vec![],
+    };
+    let in_run = |state: usize| {
+        runs.iter()
+            .any(|(first, units)| (*first..*first + units.len()).contains(&state))
+    };
+    let unit_comparisons = automaton
+        .transitions
+        .iter()
+        .filter(|((from, _), _)| !in_run(*from))
+        .map(|((from, to), ranges)| {
+            // A self-loop is checked against the code units leaving it when they are fewer.
+            let exits = match from == to {
+                true => comparisons(&complement_ranges(&merge_ranges(ranges.clone()), max_unit)),
+                false => usize::MAX,
+            };
+            comparisons(ranges).min(exits)
+        })
+        .collect_vec();
+    let branches = unit_comparisons.len() + unit_comparisons.iter().sum::<usize>();
+    match strategy {
+        NoirStrategy::Auto | NoirStrategy::IfChain => branches,
+        NoirStrategy::Keyword => branches + (2 + LOOKUP_COMPARISONS) * runs.len(),
+        NoirStrategy::MuxTree => unit_comparisons.iter().sum::<usize>() + automaton.free_state_id,
+        NoirStrategy::Table => LOOKUP_COMPARISONS,
+    }
+}
+
+/// Estimates the gates of the `global` arrays `next_state` reads with a strategy, which the
+/// circuit pays for once whatever the input length, see `GLOBAL_ENTRY_GATES`.
+fn next_state_globals(automaton: &NoirAutomaton, strategy: NoirStrategy) -> usize {
+    let entries = match strategy {
+        NoirStrategy::Table => (automaton.free_state_id + 1) * 256 * automaton.num_tags(),
+        NoirStrategy::Keyword => find_keyword_runs(automaton)
+            .iter()
+            .map(|(_, units)| units.len())
+            .sum(),
+        NoirStrategy::Auto | NoirStrategy::IfChain | NoirStrategy::MuxTree => 0,
+    };
+    GLOBAL_ENTRY_GATES * entries
+}
+
+/// Estimates the gates of `next_state` with every strategy `Auto` chooses from.
+///
+/// The gates of a strategy are estimated as its comparisons per code unit times the input
+/// length, plus the entries of the `global` arrays it reads. The strategies are listed in the
+/// order if-chain, keyword, mux tree and table, and the table is not considered for `u16`
+/// input.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the automaton `next_state` is generated from.
+/// * `input_type` - The element type of the input array.
+/// * `input_len` - The number of input code units.
+///
+/// # Returns
+///
+/// A vector of the strategies with their comparisons per code unit and estimated gates.
+fn strategy_estimates(
+    automaton: &NoirAutomaton,
+    input_type: NoirInputType,
+    input_len: usize,
+) -> Vec<(NoirStrategy, usize, usize)> {
+    let (max_unit, candidates) = match automaton.unit_type(input_type) {
+        NoirInputType::U16 => (
+            u16::MAX as u32,
+            &[
+                NoirStrategy::IfChain,
+                NoirStrategy::Keyword,
+                NoirStrategy::MuxTree,
+            ][..],
+        ),
+        _ => (
+            u8::MAX as u32,
+            &[
+                NoirStrategy::IfChain,
+                NoirStrategy::Keyword,
+                NoirStrategy::MuxTree,
+                NoirStrategy::Table,
+            ][..],
+        ),
+    };
+    candidates
+        .iter()
+        .map(|&strategy| {
+            let per_unit = next_state_comparisons(automaton, max_unit, strategy);
+            let gates = per_unit
+                .saturating_mul(input_len)
+                .saturating_add(next_state_globals(automaton, strategy));
+            (strategy, per_unit, gates)
+        })
+        .collect()
+}
+
+/// Returns the strategy of the options, choosing the one estimated to take the fewest gates
+/// for the automaton if it is `Auto`.
+///
+/// The gates of every strategy are estimated by `strategy_estimates` for the input length of
+/// the options. Ties go to the simplest layout, in the order if-chain, keyword, mux tree and
+/// table.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the automaton `next_state` is generated from.
+/// * `input_type` - The element type of the input array.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// The strategy to generate `next_state` with, never `Auto`.
+fn resolve_strategy(
+    automaton: &NoirAutomaton,
+    input_type: NoirInputType,
+    options: &NoirOptions,
+) -> NoirStrategy {
+    if options.strategy != NoirStrategy::Auto {
+        return options.strategy;
+    }
+    let input_len = options.input_len.unwrap_or(DEFAULT_INPUT_LEN);
+    let estimates = strategy_estimates(automaton, input_type, input_len);
+    let (strategy, _, gates) = *estimates
+        .iter()
+        .min_by_key(|(_, _, gates)| *gates)
+        .expect("there is always a candidate strategy");
+    info!(?strategy, gates, input_len, "chose the Noir strategy");
+    debug!(?estimates, "estimated the gates of every Noir strategy");
+    strategy
+}
+
+/// Estimates the number of comparisons the matcher makes per input code unit, which dominates
+/// its constraint count.
+///
+/// The comparisons of `next_state` are given by `next_state_comparisons` for the strategy of
+/// the options, and each revealed capture adds one comparison. The estimate is meant to
+/// compare patterns and strategies, not to predict the exact gate count of a backend.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// The estimated number of comparisons per input code unit.
+pub(crate) fn estimate_constraints_per_unit(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+) -> usize {
+    let automaton = build_automaton(regex_and_dfa, options, gen_substrs);
+    let max_unit = match automaton.unit_type(options.input_type) {
+        NoirInputType::U16 => u16::MAX as u32,
+        _ => u8::MAX as u32,
+    };
+    let strategy = resolve_strategy(&automaton, options.input_type, options);
+    let next_state = next_state_comparisons(&automaton, max_unit, strategy);
+    let captures = if gen_substrs {
+        automaton.num_captures()
+    } else {
+        0
+    };
+    next_state + captures
+}
+
+/// Estimates the size of the matcher for several input lengths with every strategy `Auto`
+/// chooses from, so that the largest input a circuit can afford can be picked.
+///
+/// The gates of `next_state` are estimated by `strategy_estimates`, and each revealed capture
+/// adds one comparison per code unit, as in `estimate_constraints_per_unit`.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+/// * `input_lens` - The input lengths to estimate the matcher for.
+///
+/// # Returns
+///
+/// A vector of the estimates, by input length then strategy.
+#[cfg(feature = "fs")]
+pub(crate) fn estimate_sizes(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+    input_lens: &[usize],
+) -> Vec<SizeEstimate> {
+    let automaton = build_automaton(regex_and_dfa, options, gen_substrs);
+    let captures = if gen_substrs {
+        automaton.num_captures()
+    } else {
+        0
+    };
+    let mut sizes = vec![];
+    for &input_len in input_lens {
+        let estimates = strategy_estimates(&automaton, options.input_type, input_len);
+        let chosen = estimates
+            .iter()
+            .min_by_key(|(_, _, gates)| *gates)
+            .map(|(strategy, _, _)| *strategy);
+        sizes.extend(
+            estimates
+                .into_iter()
+                .map(|(strategy, per_unit, gates)| SizeEstimate {
+                    input_len,
+                    strategy,
+                    gates: gates.saturating_add(captures.saturating_mul(input_len)),
+                    per_unit: per_unit + captures,
+                    chosen: Some(strategy) == chosen,
+                }),
+        );
+    }
+    sizes
+}
+
+/// Estimates the cost of proving the matcher for several input lengths with every given
+/// backend.
+///
+/// The gates are those of the layout the options select, estimated as by `estimate_sizes`, and
+/// the backend pads them to the next power of two, which its proving time and memory grow
+/// with. The time and memory per row are rough figures for a multi-threaded `bb` on a laptop,
+/// so the estimates compare pattern designs rather than predict a given machine; measure them
+/// with `measure_proving` when it matters.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+/// * `input_lens` - The input lengths to estimate the cost for, in code units.
+/// * `backends` - The backends to estimate the cost with.
+///
+/// # Returns
+///
+/// The estimates, by input length then backend.
+#[cfg(feature = "fs")]
+pub(crate) fn estimate_proving_costs(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+    input_lens: &[usize],
+    backends: &[ProvingBackend],
+) -> Vec<ProvingCost> {
+    let sizes = estimate_sizes(regex_and_dfa, gen_substrs, options, input_lens);
+    let mut costs = vec![];
+    for &input_len in input_lens {
+        let mut sizes = sizes.iter().filter(|size| size.input_len == input_len);
+        let size = match options.strategy {
+            NoirStrategy::Auto => sizes.find(|size| size.chosen),
+            strategy => sizes.find(|size| size.strategy == strategy),
+        };
+        let Some(size) = size else {
+            continue;
+        };
+        for &backend in backends {
+            costs.push(estimate_proving_cost(input_len, backend, size.gates));
+        }
+    }
+    costs
+}
+
+/// Estimates the cost of proving the matcher with every backend for the input length of the
+/// options, 1024 if not set, as listed in the compile report.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// The estimates, by backend.
+#[cfg(feature = "fs")]
+pub(crate) fn report_proving_costs(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+) -> Vec<ProvingCost> {
+    estimate_proving_costs(
+        regex_and_dfa,
+        gen_substrs,
+        options,
+        &[options.input_len.unwrap_or(DEFAULT_INPUT_LEN)],
+        &[ProvingBackend::UltraHonk, ProvingBackend::UltraPlonk],
+    )
+}
+
+/// Estimates the cost of proving a circuit of the given number of gates with a backend, see
+/// `estimate_proving_costs`.
+#[cfg(feature = "fs")]
+fn estimate_proving_cost(input_len: usize, backend: ProvingBackend, gates: usize) -> ProvingCost {
+    // Nanoseconds and bytes per row of the padded circuit.
+    let (row_ns, row_bytes) = match backend {
+        ProvingBackend::UltraHonk => (2_000, 640),
+        ProvingBackend::UltraPlonk => (5_000, 1_024),
+    };
+    let circuit_size = gates.max(1).next_power_of_two();
+    ProvingCost {
+        input_len,
+        backend,
+        gates,
+        circuit_size,
+        prove_ms: (circuit_size as u64).saturating_mul(row_ns) / 1_000_000,
+        peak_memory_kb: Some(
+            BASE_PROVING_MEMORY_KB + (circuit_size as u64).saturating_mul(row_bytes) / 1024,
+        ),
+        measured: false,
+    }
+}
+
+/// Runs the automaton over the corpus of the options as `regex_match` does, restarting from
+/// the initial state whenever no transition applies.
+///
+/// The bytes of a tagged automaton are tagged as by `capture_tags`. Repetition counters are
+/// not modelled, so a counted repetition may run on where the circuit would restart.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the automaton run by the matcher.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `options` - The options holding the corpus and the input type.
+///
+/// # Returns
+///
+/// A tuple of the transitions taken and the number of inputs the automaton accepts.
+fn run_corpus(
+    automaton: &NoirAutomaton,
+    accept_sink: Option<usize>,
+    options: &NoirOptions,
+) -> (TransitionHits, usize) {
+    let finished = |s: usize| automaton.accept_states.contains(&s) || Some(s) == accept_sink;
+    let next_state = next_state_closure(automaton, accept_sink);
+    // The state the code unit is consumed from, after a restart if needed, and the next state.
+    let step = |s: usize, unit: u32| match next_state(s, unit) {
+        RESET_STATE => (
+            automaton.start_state,
+            next_state(automaton.start_state, unit),
+        ),
+        next => (s, next),
+    };
+    let num_tags = automaton.num_tags() as u32;
+    let tag = |unit: u32, tag: u32| unit + 256 * tag;
+    // Keeps the states some choice of tags leads to, then walks back from an accepting state.
+    let tags = |units: &[u32]| {
+        let mut tags = vec![0; units.len()];
+        if !automaton.tagged {
+            return tags;
+        }
+        let mut before = vec![];
+        let mut live = BTreeSet::from([automaton.init_state]);
+        for &unit in units {
+            let next = live
+                .iter()
+                .flat_map(|&s| (0..num_tags).map(move |t| step(s, tag(unit, t)).1))
+                .collect();
+            before.push(std::mem::replace(&mut live, next));
+        }
+        let mut s = live.into_iter().find(|&s| finished(s)).unwrap_or(0);
+        for (i, &unit) in units.iter().enumerate().rev() {
+            let prev = before[i]
+                .iter()
+                .cartesian_product(0..num_tags)
+                .find(|&(&p, t)| step(p, tag(unit, t)).1 == s);
+            let (&p, t) = prev.unwrap_or((&0, 0));
+            tags[i] = t;
+            s = p;
+        }
+        tags
+    };
+
+    let mut hits = TransitionHits::new();
+    let mut matched = 0;
+    for input in &options.corpus {
+        let mut units = match options.input_type {
+            NoirInputType::U16 => String::from_utf8_lossy(input)
+                .encode_utf16()
+                .map(u32::from)
+                .collect_vec(),
+            _ => input.iter().map(|&byte| byte as u32).collect_vec(),
+        };
+        if options.reverse {
+            units.reverse();
+        }
+        let mut s = automaton.init_state;
+        for (&unit, t) in units.iter().zip(tags(&units)) {
+            let unit = tag(unit, t);
+            let (from, next) = step(s, unit);
+            if automaton.transitions.contains_key(&(from, next)) {
+                *hits.entry((from, next, unit)).or_default() += 1;
+            }
+            s = next;
+        }
+        matched += finished(s) as usize;
+    }
+    (hits, matched)
+}
+
+/// Profiles the automaton run by the Noir matcher over the corpus of the options, to show
+/// which states and transitions representative inputs spend their code units in.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code, holding the corpus.
+///
+/// # Returns
+///
+/// The `CorpusProfile`, listing the states and transitions most taken first.
+#[cfg(feature = "fs")]
+pub(crate) fn profile_corpus(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+) -> CorpusProfile {
+    let automaton = build_automaton(regex_and_dfa, options, gen_substrs);
+    let accept_sink = if regex_and_dfa.has_end_anchor && !options.reverse {
+        None
+    } else {
+        Some(automaton.free_state_id)
+    };
+    let (hits, matched) = run_corpus(&automaton, accept_sink, options);
+    let mut states = BTreeMap::<usize, usize>::new();
+    let mut transitions = BTreeMap::<(usize, usize), usize>::new();
+    for (&(from, to, _), &count) in &hits {
+        *states.entry(from).or_default() += count;
+        *transitions.entry((from, to)).or_default() += count;
+    }
+    CorpusProfile {
+        inputs: options.corpus.len(),
+        matched,
+        units: options.corpus.iter().map(Vec::len).sum(),
+        states: states
+            .into_iter()
+            .map(|(state, units)| StateVisits { state, units })
+            .sorted_by_key(|visits| Reverse(visits.units))
+            .collect(),
+        transitions: transitions
+            .into_iter()
+            .map(|((from, to), units)| TransitionVisits { from, to, units })
+            .sorted_by_key(|visits| Reverse(visits.units))
+            .collect(),
+    }
+}
+
+/// Fails if the matcher, instantiated with `budget.input_len` code units, is estimated to
+/// exceed `budget.max_gates` gates, so that an oversized circuit is caught before `nargo
+/// compile` runs.
+///
+/// The estimate is the number of comparisons per input code unit given by
+/// `estimate_constraints_per_unit` times the input length.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+/// * `budget` - The budget of the circuit.
+///
+/// # Returns
+///
+/// A `Result` indicating whether the estimate fits the budget, or a `CompilerError`.
+pub(crate) fn check_gate_budget(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+    budget: &GateBudget,
+) -> Result<(), CompilerError> {
+    let per_unit = estimate_constraints_per_unit(regex_and_dfa, gen_substrs, options);
+    let estimate = per_unit.saturating_mul(budget.input_len);
+    info!(
+        per_unit,
+        estimate,
+        max_gates = budget.max_gates,
+        "estimated the size of the Noir matcher"
+    );
+    if estimate > budget.max_gates {
+        return Err(CompilerError::GenericError(format!(
+            "the Noir matcher is estimated at {} gates for {} input units ({} per unit), over the \
+             budget of {}; shorten the input, simplify the pattern or try another --strategy",
+            estimate, budget.input_len, per_unit, budget.max_gates
+        )));
+    }
+    Ok(())
+}
+
+/// Runs the Noir matcher on an input in Rust, mirroring the generated code: the tags computed
+/// by `capture_tags`, the restarts from the start state, the occurrences skipped and the
+/// repetition counters.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the automaton the matcher is generated from.
+/// * `accept_sink` - The absorbing state entered once a match has completed, if any.
+/// * `input` - The input code units, in the order the matcher scans them.
+/// * `capture_overflow` - What happens when a repeated part matches more times than captured.
+/// * `occurrence` - The occurrence of the pattern to match, counted from 1.
+///
+/// # Returns
+///
+/// The captures of the matcher if it accepts the input, or `None`.
+fn run_matcher(
+    automaton: &NoirAutomaton,
+    accept_sink: Option<usize>,
+    input: &[u32],
+    capture_overflow: NoirCaptureOverflow,
+    occurrence: usize,
+) -> Option<Vec<Vec<u32>>> {
+    let finished = |s: usize| automaton.accept_states.contains(&s) || Some(s) == accept_sink;
+    let next_state = next_state_closure(automaton, accept_sink);
+    let start = automaton.start_state;
+    let initial = automaton.init_state;
+    let unit = |unit: u32, tag: usize| unit + 256 * tag as u32;
+    let last = occurrence - 1;
+    let step = |s: usize, unit: u32| match next_state(s, unit) {
+        RESET_STATE => next_state(start, unit),
+        next => next,
+    };
+
+    // Mirrors `capture_tags`.
+    let mut tags = vec![0; input.len()];
+    if automaton.tagged && !input.is_empty() {
+        let mut reachable = vec![BTreeSet::new(); input.len()];
+        let live = |reachable: &[BTreeSet<usize>], i: usize| {
+            if i == 0 {
+                BTreeSet::from([initial])
+            } else {
+                reachable[i - 1].clone()
+            }
+        };
+        for (i, &byte) in input.iter().enumerate() {
+            for s in live(&reachable, i) {
+                for tag in 0..automaton.num_tags() {
+                    reachable[i].insert(step(s, unit(byte, tag)));
+                }
+            }
+        }
+        let mut s = reachable[input.len() - 1]
+            .iter()
+            .copied()
+            .find(|&s| finished(s))
+            .unwrap_or(0);
+        for i in (0..input.len()).rev() {
+            let prev = live(&reachable, i)
+                .into_iter()
+                .cartesian_product(0..automaton.num_tags())
+                .find(|&(p, tag)| step(p, unit(input[i], tag)) == s);
+            let (p, tag) = prev.unwrap_or((0, 0));
+            tags[i] = tag;
+            s = p;
+        }
+    }
+
+    let num_substrs = automaton.substr_transitions.len();
+    let offsets = automaton
+        .max_repetitions
+        .iter()
+        .scan(0, |offset, max| {
+            *offset += max.unwrap_or(1);
+            Some(*offset - max.unwrap_or(1))
+        })
+        .collect_vec();
+    let mut captures = vec![vec![]; automaton.num_captures()];
+    let mut counts = vec![0; num_substrs];
+    let mut reps = vec![0; automaton.counters.len()];
+    let mut s = initial;
+    let mut matches = 0;
+    for (&byte, &tag) in input.iter().zip(&tags) {
+        let unit = unit(byte, tag);
+        let mut s_next = next_state(s, unit);
+        for (counter, &rep) in automaton.counters.iter().zip(&reps) {
+            let counted = counter.transitions.contains(&(s, s_next));
+            if counted && Some(rep) == counter.max {
+                s_next = match automaton.accept_states.contains(&s) {
+                    true => accept_sink.unwrap_or(RESET_STATE),
+                    false => RESET_STATE,
+                };
+            }
+            if !counted && rep != 0 && rep < counter.min {
+                s_next = RESET_STATE;
+            }
+        }
+        if s_next == RESET_STATE {
+            s = start;
+            s_next = next_state(start, unit);
+            captures.iter_mut().for_each(Vec::clear);
+            counts.iter_mut().for_each(|count| *count = 0);
+            reps.iter_mut().for_each(|rep| *rep = 0);
+        }
+        if Some(s_next) == accept_sink && matches < last {
+            matches += 1;
+            s = start;
+            s_next = next_state(start, unit);
+            captures.iter_mut().for_each(Vec::clear);
+            counts.iter_mut().for_each(|count| *count = 0);
+        }
+        if automaton.tagged {
+            if tag > 0 && s_next != RESET_STATE && Some(s_next) != accept_sink {
+                let idx = (tag - 1) % num_substrs;
+                match automaton.max_repetitions[idx] {
+                    Some(max) => {
+                        let slots = offsets[idx]..offsets[idx] + max;
+                        if tag > num_substrs {
+                            if capture_overflow == NoirCaptureOverflow::KeepLast
+                                && counts[idx] == max
+                            {
+                                captures[slots.clone()].rotate_left(1);
+                                captures[slots.end - 1].clear();
+                            } else {
+                                counts[idx] += 1;
+                            }
+                        }
+                        match counts[idx] {
+                            count if count <= max => captures[slots.start + count - 1].push(byte),
+                            _ if capture_overflow == NoirCaptureOverflow::Fail => return None,
+                            _ => {}
+                        }
+                    }
+                    None => captures[offsets[idx]].push(byte),
+                }
+            }
+        } else {
+            for (idx, transitions) in automaton.substr_transitions.iter().enumerate() {
+                if transitions.contains(&(s, s_next)) {
+                    captures[idx].push(byte);
+                }
+            }
+        }
+        for (counter, rep) in automaton.counters.iter().zip(reps.iter_mut()) {
+            match counter.transitions.contains(&(s, s_next)) {
+                true => *rep += 1,
+                false => *rep = 0,
+            }
+        }
+        s = s_next;
+    }
+    let counted = automaton
+        .counters
+        .iter()
+        .zip(&reps)
+        .all(|(counter, &rep)| rep == 0 || rep >= counter.min);
+    (matches == last && finished(s) && counted).then_some(captures)
+}
+
+/// The logic of the `regex_match` function generated for a pattern, run in Rust to predict
+/// the substrings it returns, see `run_matcher`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MatcherRun {
+    /// The automaton the matcher runs, on tagged bytes if it tags its captures.
+    automaton: NoirAutomaton,
+    /// The absorbing state entered once a match has completed, if any.
+    accept_sink: Option<usize>,
+    /// Whether the matcher extracts the substrings.
+    gen_substrs: bool,
+    /// What happens when a repeated part matches more times than captured.
+    capture_overflow: NoirCaptureOverflow,
+    /// The occurrence of the pattern to match, counted from 1.
+    occurrence: usize,
+    /// Whether the input is scanned from its end, the captures being reversed back.
+    reverse: bool,
+    /// With `reverse`, the number of code units scanned at the end of the input, if bounded.
+    reverse_window: Option<usize>,
+    /// Whether the captures are folded to ASCII lowercase.
+    lowercase_captures: bool,
+    /// The number of non-empty captures a match must produce, if asserted.
+    capture_count: Option<usize>,
+}
+
+impl MatcherRun {
+    /// Builds the logic of the matcher generated with the given options.
+    ///
+    /// # Arguments
+    ///
+    /// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+    /// * `options` - The options controlling the generated code.
+    /// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+    pub(crate) fn new(
+        regex_and_dfa: &RegexAndDFA,
+        options: &NoirOptions,
+        gen_substrs: bool,
+    ) -> Self {
+        let automaton = build_automaton(regex_and_dfa, options, gen_substrs);
+        let accept_sink = if regex_and_dfa.has_end_anchor && !options.reverse {
+            None
+        } else {
+            Some(automaton.free_state_id)
+        };
+        MatcherRun {
+            automaton,
+            accept_sink,
+            gen_substrs,
+            capture_overflow: options.capture_overflow,
+            occurrence: options.occurrence.unwrap_or(1),
+            reverse: options.reverse,
+            reverse_window: options.reverse_window,
+            lowercase_captures: options.lowercase_captures,
+            capture_count: options.capture_count,
+        }
+    }
+
+    /// Runs the matcher on an input.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input code units: bytes, or UTF-16 code units for `u16` input.
+    ///
+    /// # Returns
+    ///
+    /// The captures the matcher returns if it accepts the input, one per capture slot and none
+    /// if it does not extract the substrings, or `None`.
+    pub(crate) fn run(&self, input: &[u32]) -> Option<Vec<Vec<u32>>> {
+        let scanned = match self.reverse {
+            true => input
+                .iter()
+                .rev()
+                .take(self.reverse_window.unwrap_or(input.len()))
+                .copied()
+                .collect_vec(),
+            false => input.to_vec(),
+        };
+        let mut captures = run_matcher(
+            &self.automaton,
+            self.accept_sink,
+            &scanned,
+            self.capture_overflow,
+            self.occurrence,
+        )?;
+        if !self.gen_substrs {
+            return Some(vec![]);
+        }
+        let produced = captures
+            .iter()
+            .filter(|capture| !capture.is_empty())
+            .count();
+        if self.capture_count.is_some_and(|count| count != produced) {
+            return None;
+        }
+        for capture in captures.iter_mut() {
+            if self.reverse {
+                capture.reverse();
+            }
+            if self.lowercase_captures {
+                capture
+                    .iter_mut()
+                    .filter(|unit| (65..=90).contains(*unit))
+                    .for_each(|unit| *unit += 32);
+            }
+        }
+        Some(captures)
+    }
+
+    /// Runs the `regex_match_at` function of the matcher on an input, over a window of
+    /// `window` code units from `start` in which the match may not restart. The matcher must
+    /// be built without the substrings, as `regex_match_at` runs on untagged code units.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input code units: bytes, or UTF-16 code units for `u16` input.
+    /// * `start` - The index the match starts at.
+    /// * `window` - The number of code units the window holds, `L` in the generated code.
+    ///
+    /// # Returns
+    ///
+    /// The end of the match if the matcher accepts the window, or `None`.
+    #[cfg(test)]
+    pub(crate) fn run_at(&self, input: &[u32], start: usize, window: usize) -> Option<usize> {
+        if start >= input.len() || (self.accept_sink.is_none() && input.len() - start > window) {
+            return None;
+        }
+        let automaton = &self.automaton;
+        let next_state = next_state_closure(automaton, self.accept_sink);
+        let mut s = match start {
+            0 => automaton.init_state,
+            _ => automaton.start_state,
+        };
+        let mut end = 0;
+        for (i, &unit) in input.iter().enumerate().skip(start).take(window) {
+            s = next_state(s, unit);
+            if s == RESET_STATE {
+                return None;
+            }
+            if automaton.accept_states.contains(&s) {
+                end = i + 1;
+            }
+        }
+        (automaton.accept_states.contains(&s) || Some(s) == self.accept_sink).then_some(end)
+    }
+}
+
+/// Runs the Noir matcher of a pattern on an input in Rust, see `MatcherRun`.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code, whose input type must be `u8`.
+/// * `input` - The input bytes.
+///
+/// # Returns
+///
+/// The substrings the matcher extracts if it accepts the input, or `None`.
+#[cfg(feature = "process")]
+pub(crate) fn simulate_matcher(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+    input: &[u8],
+) -> Option<Vec<Vec<u8>>> {
+    let units = input.iter().map(|&byte| byte as u32).collect_vec();
+    let captures = MatcherRun::new(regex_and_dfa, options, gen_substrs).run(&units)?;
+    Some(
+        captures
+            .into_iter()
+            .map(|capture| capture.into_iter().map(|unit| unit as u8).collect())
+            .collect(),
+    )
+}
+
+/// Finds a shortest input accepted by the Noir matcher of exactly one of two patterns, so that
+/// the patterns are compared by what their circuits accept rather than by their DFAs.
+///
+/// The matchers are generated with the default options from patterns without counted
+/// repetitions, so each is a deterministic automaton over its state, restarts included, and
+/// the pairs of states of the two matchers are searched breadth first as in
+/// `algebra::distinguishing_input`. The bytes are tried
+/// among lowercase letters, then digits, uppercase letters, other printable ASCII characters
+/// and the other bytes, so the input stays readable whenever the patterns allow.
+///
+/// # Arguments
+///
+/// * `a` - A reference to the RegexAndDFA struct of the first pattern.
+/// * `b` - A reference to the RegexAndDFA struct of the second pattern.
+///
+/// # Returns
+///
+/// The input, with whether the matcher of `a` accepts it, or `None` if the matchers accept
+/// the same inputs.
+#[cfg(feature = "fs")]
+pub(crate) fn distinguishing_matcher_input(
+    a: &RegexAndDFA,
+    b: &RegexAndDFA,
+) -> Option<(Vec<u8>, bool)> {
+    let options = NoirOptions::default();
+    let automata = [a, b].map(|regex_and_dfa| {
+        let automaton = build_automaton(regex_and_dfa, &options, false);
+        let accept_sink = (!regex_and_dfa.has_end_anchor).then_some(automaton.free_state_id);
+        (automaton, accept_sink)
+    });
+    let matchers = automata
+        .iter()
+        .map(|(automaton, accept_sink)| {
+            let accept_sink = *accept_sink;
+            let next_state = next_state_closure(automaton, accept_sink);
+            let step = move |s: usize, byte: u8| match next_state(s, byte as u32) {
+                RESET_STATE => next_state(automaton.start_state, byte as u32),
+                next => next,
+            };
+            let finished =
+                move |s: usize| automaton.accept_states.contains(&s) || Some(s) == accept_sink;
+            (automaton.init_state, step, finished)
+        })
+        .collect_vec();
+    let [(init_a, step_a, finished_a), (init_b, step_b, finished_b)] = &matchers[..] else {
+        unreachable!("there are two matchers");
+    };
+    let bytes = (0..=u8::MAX)
+        .sorted_by_key(|&byte| match byte {
+            b'a'..=b'z' => (0, byte),
+            b'0'..=b'9' => (1, byte),
+            b'A'..=b'Z' => (2, byte),
+            0x20..=0x7e => (3, byte),
+            _ => (4, byte),
+        })
+        .collect_vec();
+
+    let start = (*init_a, *init_b);
+    let mut parents = BTreeMap::from([(start, None)]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(pair @ (s, t)) = queue.pop_front() {
+        if finished_a(s) != finished_b(t) {
+            let mut input = vec![];
+            let mut current = pair;
+            while let Some(&Some((parent, byte))) = parents.get(&current) {
+                input.push(byte);
+                current = parent;
+            }
+            input.reverse();
+            return Some((input, finished_a(s)));
+        }
+        for &byte in &bytes {
+            let next = (step_a(s, byte), step_b(t, byte));
+            if let Entry::Vacant(entry) = parents.entry(next) {
+                entry.insert(Some((pair, byte)));
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+/// Finds a short input accepted by the automaton, consuming at least one code unit if possible.
+///
+/// The code units are picked among lowercase letters, then digits, uppercase letters and
+/// other printable ASCII characters, so the sample stays readable whenever the pattern allows.
+///
+/// # Arguments
+///
+/// * `automaton` - A reference to the automaton to sample.
+/// * `occurrence` - The number of times the pattern should occur in the sample.
+/// * `min_len` - The length the sample is padded to after the last occurrence, if possible.
+///
+/// # Returns
+///
+/// The code units of the sample input, without tags, or `None` if no input is accepted.
+#[cfg(feature = "fs")]
+fn sample_input(automaton: &NoirAutomaton, occurrence: usize, min_len: usize) -> Option<Vec<u32>> {
+    let rank = |c: u32| match c {
+        0x61..=0x7a => (0, c),
+        0x30..=0x39 => (1, c),
+        0x41..=0x5a => (2, c),
+        0x20..=0x7e => (3, c),
+        _ => (4, c),
+    };
+    // Tagged code units carry the tag above the byte, which is all the input holds.
+    let fallback = |unit: u32| if automaton.tagged { unit % 256 } else { unit };
+    let unit_of = |ranges: &[(u32, u32)]| {
+        (0..automaton.num_tags() as u32)
+            .flat_map(|tag| (0x20..=0x7e).map(move |c| (c, c + 256 * tag)))
+            .filter(|&(_, unit)| ranges.iter().any(|&(min, max)| (min..=max).contains(&unit)))
+            .map(|(c, _)| c)
+            .chain(ranges.iter().map(|&(min, _)| fallback(min)))
+            .min_by_key(|&c| rank(c))
+    };
+
+    let init = automaton.init_state;
+    let mut parents: BTreeMap<usize, (usize, u32)> = BTreeMap::new();
+    let mut queue = VecDeque::from([init]);
+    while let Some(from) = queue.pop_front() {
+        for (&(_, to), ranges) in automaton.transitions.range((from, 0)..(from + 1, 0)) {
+            if to == init || parents.contains_key(&to) {
+                continue;
+            }
+            let Some(unit) = unit_of(ranges) else {
+                continue;
+            };
+            parents.insert(to, (from, unit));
+            if automaton.accept_states.contains(&to) {
+                let mut steps = vec![];
+                let mut state = to;
+                while state != init {
+                    let (prev, unit) = parents[&state];
+                    steps.push((prev, state, unit));
+                    state = prev;
+                }
+                steps.reverse();
+                let mut units = vec![];
+                for (idx, &(_, state, unit)) in steps.iter().enumerate() {
+                    units.push(unit);
+                    // A counted repetition is taken once on the shortest path, so it loops
+                    // on its last state up to its minimum.
+                    for counter in &automaton.counters {
+                        let counted = |&(from, to, _): &(usize, usize, u32)| {
+                            counter.transitions.contains(&(from, to))
+                        };
+                        if !counted(&steps[idx]) || steps.get(idx + 1).is_some_and(counted) {
+                            continue;
+                        }
+                        let run = steps[..=idx]
+                            .iter()
+                            .rev()
+                            .take_while(|s| counted(s))
+                            .count();
+                        let looped = automaton
+                            .transitions
+                            .get(&(state, state))
+                            .filter(|_| counter.transitions.contains(&(state, state)))
+                            .and_then(|ranges| unit_of(ranges));
+                        if let Some(unit) = looped {
+                            units.extend(vec![unit; counter.min.saturating_sub(run)]);
+                        }
+                    }
+                }
+                return Some(repeat_sample(automaton, units, to, occurrence, min_len));
+            }
+            queue.push_back(to);
+        }
+    }
+    automaton.accept_states.contains(&init).then(Vec::new)
+}
+
+/// Finds an input of exactly `len` code units that the matcher accepts, for circuits
+/// instantiated with that input length.
+///
+/// The sample of `gen_noir_project` is padded to `len` with a code unit that does not extend
+/// the match, which fails for patterns anchored at the end of the input or without such a
+/// code unit.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+/// * `len` - The number of code units of the input.
+///
+/// # Returns
+///
+/// A `Result` containing the input, or a `CompilerError` if no accepted input of that length
+/// is found.
+#[cfg(feature = "process")]
+pub(crate) fn sample_input_of_len(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    options: &NoirOptions,
+    len: usize,
+) -> Result<String, CompilerError> {
+    let automaton = build_automaton(regex_and_dfa, options, gen_substrs);
+    let mut sample =
+        sample_input(&automaton, options.occurrence.unwrap_or(1), len).ok_or_else(|| {
+            CompilerError::GenericError("the regex does not accept any input".to_string())
+        })?;
+    if options.reverse {
+        sample.reverse();
+    }
+    let accepted = sample.len() == len
+        && MatcherRun::new(regex_and_dfa, options, gen_substrs)
+            .run(&sample)
+            .is_some();
+    let input = match options.input_type {
+        NoirInputType::U16 => {
+            String::from_utf16(&sample.iter().map(|&unit| unit as u16).collect_vec()).ok()
+        }
+        _ => String::from_utf8(sample.iter().map(|&unit| unit as u8).collect_vec()).ok(),
+    };
+    match input {
+        Some(input) if accepted => Ok(input),
+        _ => Err(CompilerError::GenericError(format!(
+            "no input of {} code units accepted by the regex could be sampled",
+            len
+        ))),
+    }
+}
+
+/// Repeats a sample ending in the state `end` to make the pattern occur `occurrence` times.
+///
+/// The copies are separated by a printable character or a line feed that completes the
+/// occurrence before it without starting the next one, if there is such a code unit. The
+/// same code unit pads the result to `min_len`.
+#[cfg(feature = "fs")]
+fn repeat_sample(
+    automaton: &NoirAutomaton,
+    sample: Vec<u32>,
+    end: usize,
+    occurrence: usize,
+    min_len: usize,
+) -> Vec<u32> {
+    let has_transition = |state: usize, unit: u32| {
+        automaton
+            .transitions
+            .range((state, 0)..(state + 1, 0))
+            .any(|(_, ranges)| ranges.iter().any(|&(min, max)| (min..=max).contains(&unit)))
+    };
+    let separator = (0x20..=0x7e)
+        .chain(std::iter::once(u32::from(b'\n')))
+        .find(|&unit| !has_transition(end, unit) && !has_transition(automaton.start_state, unit));
+    match separator {
+        Some(separator) => {
+            let mut units = vec![sample; occurrence].join(&separator);
+            units.resize(units.len().max(min_len), separator);
+            units
+        }
+        None => sample.repeat(occurrence),
+    }
+}
+
+/// Packs bytes 31 per Field in little-endian order and renders each Field as a hex literal.
+#[cfg(feature = "fs")]
+fn pack_sample(bytes: &[u32]) -> Vec<String> {
+    bytes
+        .chunks(PACKED_BYTES)
+        .map(|chunk| {
+            let hex = chunk
+                .iter()
+                .rev()
+                .map(|byte| format!("{byte:02x}"))
+                .join("");
+            format!("0x{hex}")
+        })
+        .collect()
+}
+
+/// Generates a runnable Nargo binary project calling the regex matcher on a sample input.
+///
+/// The project contains the matcher in `src/regex.nr`, a `main` function calling it from
+/// `src/main.nr` together with a `#[test]`, and a `Prover.toml` holding the sample input, so
+/// that `nargo test`, `nargo execute` and `nargo prove` can be run in it right away.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `project_dir` - The directory of the project, created if it does not exist.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Result indicating success or a CompilerError.
+#[cfg(feature = "fs")]
+pub(crate) fn gen_noir_example(
+    regex_and_dfa: &RegexAndDFA,
+    project_dir: &Path,
+    gen_substrs: bool,
+    options: &NoirOptions,
+) -> Result<(), CompilerError> {
+    gen_noir_project(regex_and_dfa, project_dir, gen_substrs, options, None).map(|_| ())
+}
+
+/// Derives the name of a Nargo package from the name of its directory.
+///
+/// # Arguments
+///
+/// * `project_dir` - The directory of the project.
+///
+/// # Returns
+///
+/// The directory name with every character other than an ASCII letter or digit replaced by
+/// `_`, or `None` if it does not start with a letter.
+#[cfg(feature = "fs")]
+pub(crate) fn nargo_package_name(project_dir: &Path) -> Option<String> {
+    project_dir
+        .file_name()
+        .map(|name| {
+            name.to_string_lossy()
+                .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        })
+        .filter(|name| name.starts_with(|c: char| c.is_ascii_alphabetic()))
+}
+
+/// Generates a Nargo binary project calling the regex matcher on the given input, or on a
+/// sample input when there is none, as described in `gen_noir_example`.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `project_dir` - The directory of the project, created if it does not exist.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+/// * `input` - The input written to `Prover.toml` and the test, encoded in UTF-16 for `u16`
+///   input and in UTF-8 otherwise.
+///
+/// # Returns
+///
+/// A Result containing the name of the Nargo package, which names its build artifacts, or a
+/// CompilerError.
+#[cfg(feature = "fs")]
+pub(crate) fn gen_noir_project(
+    regex_and_dfa: &RegexAndDFA,
+    project_dir: &Path,
+    gen_substrs: bool,
+    options: &NoirOptions,
+    input: Option<&str>,
+) -> Result<String, CompilerError> {
+    let src_dir = project_dir.join("src");
+    create_dir_all(&src_dir)?;
+    gen_noir_fn(
+        regex_and_dfa,
+        &src_dir.join("regex.nr"),
+        gen_substrs,
+        options,
+    )?;
+
+    let automaton = build_automaton(regex_and_dfa, options, gen_substrs);
+    let sample = match input {
+        Some("") => {
+            return Err(CompilerError::GenericError(
+                "the input is empty".to_string(),
+            ))
+        }
+        Some(input) => match options.input_type {
+            NoirInputType::U16 => input.encode_utf16().map(u32::from).collect(),
+            _ => input.bytes().map(u32::from).collect(),
+        },
+        None => {
+            let occurrence = options.occurrence.unwrap_or(1);
+            let min_len = options.reverse_window.unwrap_or(0);
+            let mut sample = sample_input(&automaton, occurrence, min_len).ok_or_else(|| {
+                CompilerError::GenericError("the regex does not accept any input".to_string())
+            })?;
+            // The reversed automaton samples the input in the order it is scanned.
+            if options.reverse {
+                sample.reverse();
+            }
+            sample
+        }
+    };
+    let len = sample.len();
+    let (input_type, values, call) = match options.input_type {
+        NoirInputType::PackedField => {
+            let packed = pack_sample(&sample);
+            let call = format!("regex::regex_match::<{len}, {}>(input)", packed.len());
+            (format!("[Field; {}]", packed.len()), packed, call)
+        }
+        input_type => {
+            let (_, typ, _) = input_vars(input_type);
+            let values = sample.iter().map(u32::to_string).collect_vec();
+            (
+                format!("[{typ}; {len}]"),
+                values,
+                "regex::regex_match(input)".to_string(),
+            )
+        }
+    };
+    let capture_len = match automaton.capture_len().as_str() {
+        "N" => len.to_string(),
+        bound => bound.to_string(),
+    };
+    let return_type = matcher_return_type(
+        automaton.num_captures(),
+        &capture_len,
+        gen_substrs,
+        options.capture_starts,
+        options.input_hash,
+    )
+    .replacen(" -> ", " -> pub ", 1);
+    let printable = sample.iter().all(|&c| (0x20..=0x7e).contains(&c));
+
+    let mut main = vec!["mod regex;".to_string(), String::new()];
+    if printable {
+        let text = sample.iter().map(|&c| c as u8 as char).collect::<String>();
+        match input {
+            Some(_) => main.push(format!("// Input: {text:?}")),
+            None => main.push(format!("// Sample input: {text:?}")),
+        }
+    }
+    main.extend(fmt_fn_signature(
+        "fn main",
+        &[format!("input: {input_type}")],
+        &return_type,
+    ));
+    match return_type.is_empty() {
+        true => main.push(format!("    {call};")),
+        false => main.push(format!("    {call}")),
+    }
+    main.extend([
+        "}".to_string(),
+        String::new(),
+        "#[test]".to_string(),
+        "fn test_main() {".to_string(),
+    ]);
+    let input = format!("    let input = [{}];", values.join(", "));
+    if input.len() <= MAX_WIDTH {
+        main.push(input);
+    } else {
+        main.push("    let input = [".to_string());
+        main.extend(values.iter().map(|value| format!("        {value},")));
+        main.push("    ];".to_string());
+    }
+    match return_type.is_empty() {
+        true => main.push("    main(input);".to_string()),
+        false => main.push("    let _ = main(input);".to_string()),
+    }
+    main.push("}".to_string());
+
+    let name = nargo_package_name(project_dir).unwrap_or_else(|| "regex_example".to_string());
+    let mut nargo_toml = format!(
+        "[package]\nname = \"{name}\"\ntype = \"bin\"\nauthors = [\"\"]\n\n[dependencies]\n"
+    );
+    for (dependency, value) in &options.dependencies {
+        nargo_toml += &format!("{dependency} = {value}\n");
+    }
+    let prover_toml = match options.input_type {
+        NoirInputType::PackedField => format!(
+            "input = [{}]\n",
+            values.iter().map(|value| format!("\"{value}\"")).join(", ")
+        ),
+        _ => format!("input = [{}]\n", values.join(", ")),
+    };
+
+    for (path, contents) in [
+        (project_dir.join("Nargo.toml"), nargo_toml),
+        (project_dir.join("Prover.toml"), prover_toml),
+        (src_dir.join("main.nr"), format!("{}\n", main.join("\n"))),
+    ] {
+        write_file(&path, contents.as_bytes())?;
+    }
+    Ok(name)
+}
+
+/// Generates a Nargo binary project checking the regex matcher on an input against the outcome
+/// expected from `simulate_matcher`, for `nargo execute` to run.
+///
+/// When the input is expected to match, `main` asserts that every substring the matcher
+/// extracts holds the expected bytes, failing with `substring <k> diverges` otherwise. When it
+/// is expected not to match, `main` only calls the matcher, which should fail.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `project_dir` - The directory of the project, created if it does not exist.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code, whose input type must be `u8`.
+/// * `input` - The input bytes, written to `Prover.toml`.
+/// * `expected` - The substrings the matcher should extract, or `None` if it should fail.
+///
+/// # Returns
+///
+/// A Result indicating success or a CompilerError.
+#[cfg(feature = "process")]
+pub(crate) fn gen_noir_check_project(
+    regex_and_dfa: &RegexAndDFA,
+    project_dir: &Path,
+    gen_substrs: bool,
+    options: &NoirOptions,
+    input: &[u8],
+    expected: Option<&[Vec<u8>]>,
+) -> Result<(), CompilerError> {
+    let src_dir = project_dir.join("src");
+    create_dir_all(&src_dir)?;
+    gen_noir_fn(
+        regex_and_dfa,
+        &src_dir.join("regex.nr"),
+        gen_substrs,
+        options,
+    )?;
+
+    let mut main = vec![
+        "mod regex;".to_string(),
+        String::new(),
+        format!("// Input: \"{}\"", input.escape_ascii()),
+        format!("fn main(input: [u8; {}]) {{", input.len()),
+    ];
+    match expected {
+        Some(substrs) if gen_substrs && !substrs.is_empty() => {
+            main.push("    let substrs = regex::regex_match(input);".to_string());
+            for (k, substr) in substrs.iter().enumerate() {
+                let message = format!("\"substring {k} diverges\"");
+                main.push(format!(
+                    "    assert(substrs[{k}].len() == {}, {message});",
+                    substr.len()
+                ));
+                for (i, byte) in substr.iter().enumerate() {
+                    main.push(format!(
+                        "    assert(substrs[{k}].get({i}) == {byte}, {message});"
+                    ));
+                }
+            }
+        }
+        _ if gen_substrs => main.push("    let _ = regex::regex_match(input);".to_string()),
+        _ => main.push("    regex::regex_match(input);".to_string()),
+    }
+    main.push("}".to_string());
+
+    let name = nargo_package_name(project_dir).unwrap_or_else(|| "regex_check".to_string());
+    let mut nargo_toml = format!(
+        "[package]\nname = \"{name}\"\ntype = \"bin\"\nauthors = [\"\"]\n\n[dependencies]\n"
+    );
+    for (dependency, value) in &options.dependencies {
+        nargo_toml += &format!("{dependency} = {value}\n");
+    }
+    for (path, contents) in [
+        (project_dir.join("Nargo.toml"), nargo_toml),
+        (
+            project_dir.join("Prover.toml"),
+            format!("input = [{}]\n", input.iter().join(", ")),
+        ),
+        (src_dir.join("main.nr"), format!("{}\n", main.join("\n"))),
+    ] {
+        write_file(&path, contents.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// The `aztec` dependency of the generated Aztec contracts, unless another one is given.
+#[cfg(feature = "fs")]
+const AZTEC_DEPENDENCY: &str = "{ git = \"https://github.com/AztecProtocol/aztec-packages/\", tag = \"v0.87.2\", directory = \"noir-projects/aztec-nr/aztec\" }";
+
+/// Generates a Nargo contract project wrapping the regex matcher in an Aztec.nr contract.
+///
+/// The contract `RegexMatcher` has a single private function `verify_match` taking an input of
+/// `input_len` code units and asserting that it matches. Instead of the captures themselves,
+/// it returns a Poseidon2 commitment to each of them, hashing their first `len()` elements, so
+/// that the contract can bind a capture to its state without revealing it. The start indices
+/// of the captures and the hash of the input are returned as the matcher returns them. The
+/// matcher is written to `src/regex.nr`, and the contract is a skeleton meant to be extended.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `project_dir` - The directory of the project, created if it does not exist.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+/// * `input_len` - The number of code units of the input of `verify_match`.
+///
+/// # Returns
+///
+/// A Result indicating success or a CompilerError.
+#[cfg(feature = "fs")]
+pub(crate) fn gen_aztec_contract(
+    regex_and_dfa: &RegexAndDFA,
+    project_dir: &Path,
+    gen_substrs: bool,
+    options: &NoirOptions,
+    input_len: usize,
+) -> Result<(), CompilerError> {
+    if options.input_type == NoirInputType::PackedField {
+        return Err(CompilerError::GenericError(
+            "the Aztec contract does not support packed input".to_string(),
+        ));
+    }
+    if input_len == 0 {
+        return Err(CompilerError::GenericError(
+            "the input length of the Aztec contract must be positive".to_string(),
+        ));
+    }
+    let src_dir = project_dir.join("src");
+    create_dir_all(&src_dir)?;
+    gen_noir_fn(
+        regex_and_dfa,
+        &src_dir.join("regex.nr"),
+        gen_substrs,
+        options,
+    )?;
+
+    let automaton = build_automaton(regex_and_dfa, options, gen_substrs);
+    let num_captures = automaton.num_captures();
+    let (_, typ, _) = input_vars(options.input_type);
+    let return_type = matcher_return_values(
+        gen_substrs,
+        options.capture_starts,
+        options.input_hash,
+        format!("[Field; {num_captures}]"),
+        format!("[u32; {num_captures}]"),
+        |hash| input_hash_type(hash).to_string(),
+    );
+    let return_type = match return_type.len() {
+        0 => String::new(),
+        1 => format!(" -> {}", return_type[0]),
+        _ => format!(" -> ({})", return_type.join(", ")),
+    };
+    let call = "regex::regex_match(input)";
+
+    let mut contract = vec![
+        "mod regex;".to_string(),
+        String::new(),
+        "use dep::aztec::macros::aztec;".to_string(),
+        String::new(),
+        "#[aztec]".to_string(),
+        "pub contract RegexMatcher {".to_string(),
+        "    use crate::regex;".to_string(),
+        "    use dep::aztec::macros::functions::private;".to_string(),
+    ];
+    contract.extend(
+        gen_imports(&options.imports)
+            .into_iter()
+            .flat_map(|imports| {
+                imports
+                    .lines()
+                    .map(|line| format!("    {line}"))
+                    .collect_vec()
+            }),
+    );
+    contract.extend([
+        String::new(),
+        "    // Asserts that the input matches the regex.".to_string(),
+        "    #[private]".to_string(),
+    ]);
+    contract.extend(
+        fmt_fn_signature(
+            "fn verify_match",
+            &[format!("input: [{typ}; {input_len}]")],
+            &return_type,
+        )
+        .into_iter()
+        .map(|line| format!("    {line}")),
+    );
+    if gen_substrs {
+        let bindings = matcher_return_values(
+            true,
+            options.capture_starts,
+            options.input_hash,
+            "captures",
+            "starts",
+            |_| "input_hash",
+        );
+        match bindings.len() {
+            1 => contract.push(format!("        let captures = {call};")),
+            _ => contract.push(format!("        let ({}) = {call};", bindings.join(", "))),
+        }
+        contract.extend(gen_commitments(8, num_captures, &options.imports));
+        let values = matcher_return_values(
+            true,
+            options.capture_starts,
+            options.input_hash,
+            "commitments",
+            "starts",
+            |_| "input_hash",
+        );
+        match values.len() {
+            1 => contract.push("        commitments".to_string()),
+            _ => contract.push(format!("        ({})", values.join(", "))),
+        }
+    } else if return_type.is_empty() {
+        contract.push(format!("        {call};"));
+    } else {
+        contract.push(format!("        {call}"));
+    }
+    contract.extend(["    }".to_string(), "}".to_string()]);
+
+    let name = nargo_package_name(project_dir).unwrap_or_else(|| "regex_contract".to_string());
+    let mut dependencies = BTreeMap::from([("aztec".to_string(), AZTEC_DEPENDENCY.to_string())]);
+    dependencies.extend(options.dependencies.clone());
+    let mut nargo_toml = format!(
+        "[package]\nname = \"{name}\"\ntype = \"contract\"\nauthors = [\"\"]\n\n[dependencies]\n"
+    );
+    for (dependency, value) in &dependencies {
+        nargo_toml += &format!("{dependency} = {value}\n");
+    }
+
+    for (path, contents) in [
+        (project_dir.join("Nargo.toml"), nargo_toml),
+        (
+            src_dir.join("main.nr"),
+            format!("{}\n", contract.join("\n")),
+        ),
+    ] {
+        write_file(&path, contents.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Generates the statements committing to every capture of `captures` with Poseidon2, hashing
+/// its first `len()` elements into the array `commitments`.
+///
+/// # Arguments
+///
+/// * `indent` - The indentation of the statements.
+/// * `num_captures` - The number of captures.
+/// * `imports` - The paths imported by the generated code.
+///
+/// # Returns
+///
+/// The lines of the statements.
+#[cfg(feature = "fs")]
+fn gen_commitments(indent: usize, num_captures: usize, imports: &[String]) -> Vec<String> {
+    let pad = " ".repeat(indent);
+    let hash = std_item(imports, "std::hash::poseidon2::Poseidon2");
+    let mut lines = vec![
+        format!("{pad}let mut commitments = [0; {num_captures}];"),
+        format!("{pad}for i in 0..{num_captures} {{"),
+        format!("{pad}    let capture = captures[i];"),
+    ];
+    let commitment = format!("{hash}::hash(capture.storage(), capture.len());");
+    match format!("{pad}    commitments[i] = {commitment}") {
+        line if line.len() <= MAX_WIDTH => lines.push(line),
+        _ => lines.extend([
+            format!("{pad}    commitments[i] ="),
+            format!("{pad}        {commitment}"),
+        ]),
+    }
+    lines.push(format!("{pad}}}"));
+    lines
+}
+
+/// Generates the `main` function of a Noir binary calling the regex matcher, with the input
+/// private and the selected outputs public.
+///
+/// The matcher is imported as the module named after its file, which must be in the same
+/// directory. Without selected outputs, `main` returns every value the matcher returns.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `main_path` - The path of the file holding `main`.
+/// * `noir_path` - The path of the Noir matcher.
+/// * `gen_substrs` - A boolean indicating whether the substrings are extracted.
+/// * `options` - The options controlling the generated code.
+/// * `main` - The input length and public outputs of `main`.
+///
+/// # Returns
+///
+/// A Result indicating success or a CompilerError.
+#[cfg(feature = "fs")]
+pub(crate) fn gen_noir_main(
+    regex_and_dfa: &RegexAndDFA,
+    main_path: &Path,
+    noir_path: &Path,
+    gen_substrs: bool,
+    options: &NoirOptions,
+    main: &NoirMainOptions,
+) -> Result<(), CompilerError> {
+    if options.input_type == NoirInputType::PackedField {
+        return Err(CompilerError::GenericError(
+            "the main function does not support packed input".to_string(),
+        ));
+    }
+    if main.input_len == 0 {
+        return Err(CompilerError::GenericError(
+            "the input length of the main function must be positive".to_string(),
+        ));
+    }
+    let module = noir_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .filter(|stem| {
+            stem.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && stem.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+        .ok_or_else(|| {
+            CompilerError::GenericError(format!(
+                "the Noir file name {} is not a valid module name",
+                noir_path.display()
+            ))
+        })?;
+    let outputs = match main.outputs.is_empty() {
+        true => matcher_return_values(
+            gen_substrs,
+            options.capture_starts,
+            options.input_hash,
+            MainOutput::Captures,
+            MainOutput::Starts,
+            |_| MainOutput::InputHash,
+        ),
+        false => main.outputs.clone(),
+    };
+    if !outputs.iter().all_unique() {
+        return Err(CompilerError::GenericError(
+            "every output of the main function must be listed once".to_string(),
+        ));
+    }
+    for output in &outputs {
+        let requirement = match output {
+            MainOutput::Captures | MainOutput::Commitments if !gen_substrs => {
+                "the captures and their commitments require extracting the substrings"
+            }
+            MainOutput::Starts if !gen_substrs || !options.capture_starts => {
+                "the capture starts require extracting the substrings with their start indices"
+            }
+            MainOutput::InputHash if options.input_hash.is_none() => {
+                "the input hash requires hashing the input"
+            }
+            MainOutput::Holes
+                if !gen_substrs || !regex_and_dfa.parts.iter().any(|part| part.hole) =>
+            {
+                "the hole commitments require a pattern with holes and extracting the substrings"
+            }
+            _ => continue,
+        };
+        return Err(CompilerError::GenericError(format!(
+            "{requirement} to be an output of the main function"
+        )));
+    }
+
+    let automaton = build_automaton(regex_and_dfa, options, gen_substrs);
+    let num_captures = automaton.num_captures();
+    let num_holes = part_capture_indices(regex_and_dfa, &automaton, |part| part.hole).len();
+    let capture_len = match automaton.capture_len().as_str() {
+        "N" => main.input_len.to_string(),
+        bound => bound.to_string(),
+    };
+    let (_, typ, _) = input_vars(options.input_type);
+    let (types, values): (Vec<_>, Vec<_>) = outputs
+        .iter()
+        .map(|output| match output {
+            MainOutput::Captures => (
+                format!("[BoundedVec<Field, {capture_len}>; {num_captures}]"),
+                "captures",
+            ),
+            MainOutput::Commitments => (format!("[Field; {num_captures}]"), "commitments"),
+            MainOutput::Starts => (format!("[u32; {num_captures}]"), "starts"),
+            MainOutput::InputHash => (
+                input_hash_type(options.input_hash.unwrap()).to_string(),
+                "input_hash",
+            ),
+            MainOutput::Match => ("bool".to_string(), "true"),
+            MainOutput::Holes => (format!("[Field; {num_holes}]"), "holes"),
+        })
+        .unzip();
+    let return_type = match types.len() {
+        0 => String::new(),
+        1 => format!(" -> pub {}", types[0]),
+        _ => format!(" -> pub ({})", types.join(", ")),
+    };
+
+    let mut lines = vec![format!("mod {module};")];
+    if let Some(imports) = gen_imports(&options.imports) {
+        lines.push(String::new());
+        lines.push(imports);
+    }
+    lines.push(String::new());
+    match outputs.is_empty() {
+        true => lines.push("// The input is private and nothing is returned.".to_string()),
+        false => lines.push(format!(
+            "// The input is private and the outputs are public: {}.",
+            outputs.iter().map(MainOutput::name).join(", ")
+        )),
+    }
+    lines.extend(fmt_fn_signature(
+        "fn main",
+        &[format!("input: [{typ}; {}]", main.input_len)],
+        &return_type,
+    ));
+    // The values of the matcher that no output uses are bound to `_` names.
+    let used = |name: &str| {
+        values.contains(&name)
+            || (name == "captures"
+                && (outputs.contains(&MainOutput::Commitments)
+                    || outputs.contains(&MainOutput::Holes)))
+    };
+    let bindings = matcher_return_values(
+        gen_substrs,
+        options.capture_starts,
+        options.input_hash,
+        "captures",
+        "starts",
+        |_| "input_hash",
+    )
+    .into_iter()
+    .map(|name| match used(name) {
+        true => name.to_string(),
+        false => format!("_{name}"),
+    })
+    .collect_vec();
+    let call = format!("{module}::regex_match(input)");
+    match bindings.len() {
+        0 => lines.push(format!("    {call};")),
+        1 => lines.push(format!("    let {} = {call};", bindings[0])),
+        _ => lines.push(format!("    let ({}) = {call};", bindings.join(", "))),
+    }
+    if outputs.contains(&MainOutput::Commitments) {
+        lines.extend(gen_commitments(4, num_captures, &options.imports));
+    }
+    if outputs.contains(&MainOutput::Holes) {
+        lines.push(format!(
+            "    let holes = {module}::hole_commitments(captures);"
+        ));
+    }
+    match values.len() {
+        0 => {}
+        1 => lines.push(format!("    {}", values[0])),
+        _ => lines.push(format!("    ({})", values.join(", "))),
+    }
+    lines.push("}".to_string());
+    write_file(main_path, format!("{}\n", lines.join("\n")).as_bytes())?;
+    Ok(())
+}
+
+/// Intersects two sorted lists of disjoint inclusive code unit ranges.
+#[cfg(feature = "fs")]
+fn intersect_ranges(a: &[(u32, u32)], b: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    let (mut i, mut j) = (0, 0);
+    let mut ranges = vec![];
+    while i < a.len() && j < b.len() {
+        let (min, max) = (a[i].0.max(b[j].0), a[i].1.min(b[j].1));
+        if min <= max {
+            ranges.push((min, max));
+        }
+        match a[i].1 < b[j].1 {
+            true => i += 1,
+            false => j += 1,
+        }
+    }
+    ranges
+}
+
+/// Generates the `regex_match_all` function checking every pattern of a shared library in a
+/// single loop over the input.
+///
+/// Each pattern keeps its own state, advanced through the shared `next_state` and restarted
+/// from its start state like in its own `regex_match_<name>` function, so the input is read,
+/// checked against the alphabet and hashed once for all of them.
+///
+/// # Arguments
+///
+/// * `patterns` - The name, regex pattern and view of the shared automaton of each pattern.
+/// * `input_type` - The element type of the input array.
+/// * `alphabet` - The code unit ranges every input code unit is asserted to lie in, if any.
+/// * `input_hash` - The hash function binding the input, if any.
+/// * `imports` - The paths imported by the generated code.
+///
+/// # Returns
+///
+/// A String containing the Noir function.
+#[cfg(feature = "fs")]
+fn gen_fused_match_fn(
+    patterns: &[(&str, &str, &NoirAutomaton)],
+    input_type: NoirInputType,
+    alphabet: Option<&[(u32, u32)]>,
+    input_hash: Option<NoirInputHash>,
+    imports: &[String],
+) -> String {
+    let (var, typ, max_unit) = input_vars(input_type);
+    let mut lines = fmt_fn_signature(
+        "pub fn regex_match_all<let N: u32>",
+        &[format!("input: [{typ}; N]")],
+        &matcher_return_type(0, "N", false, false, input_hash),
+    );
+    for (name, regex_pattern, _) in patterns {
+        lines.push(format!(
+            "    // regex_match_{name}: {}",
+            regex_pattern.replace('\n', "\\n").replace('\r', "\\r")
+        ));
+    }
+    if let Some(hash) = input_hash {
+        lines.push(format!(
+            "    let input_hash = {};",
+            input_hash_expr(hash, "input", "N", false, imports)
+        ));
+    }
+    let min_len = patterns
+        .iter()
+        .filter_map(|(_, _, automaton)| automaton.min_match_len())
+        .max()
+        .unwrap_or(0);
+    if min_len > 0 {
+        lines.push(format!(
+            "    assert(N >= {min_len}, \"the input is shorter than the shortest match of \
+             {min_len} code units\");"
+        ));
+    }
+    for (name, _, automaton) in patterns {
+        lines.push(format!("    let mut s_{name} = {};", automaton.init_state));
+    }
+    lines.push("    for i in 0..N {".to_string());
+    lines.push(format!("        let {var} = input[i];"));
+    if let Some(alphabet) = alphabet {
+        lines.extend(fmt_assert(
+            8,
+            &ranges_condition(var, alphabet, max_unit),
+            "f\"input[{i}] is outside the alphabet\"",
+        ));
+    }
+    for (name, _, automaton) in patterns {
+        let start = automaton.start_state;
+        lines.push(format!(
+            "        let mut next_{name} = next_state(s_{name}, {var});"
+        ));
+        lines.push(format!("        if next_{name} == {RESET_STATE} {{"));
+        lines.push(format!(
+            "            next_{name} = next_state({start}, {var});"
+        ));
+        lines.push("        }".to_string());
+        lines.push(format!("        s_{name} = next_{name};"));
+    }
+    lines.push("    }".to_string());
+    for (name, _, automaton) in patterns {
+        lines.extend(fmt_assert(
+            4,
+            &states_condition(&format!("s_{name}"), &automaton.accept_states),
+            &format!("f\"no match of {name}: {{s_{name}}}\""),
+        ));
+    }
+    if let Some(expr) = matcher_return_expr("", "", false, false, input_hash) {
+        lines.push(format!("    {expr}"));
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/// Generates the Noir code matching several patterns with a single shared `next_state`.
+///
+/// Each pattern gets a `regex_match_<name>` function running from its own start state, and
+/// all of them share the transitions built by `build_shared_automata`. With `fused`, a
+/// `regex_match_all` function also checks all of them in a single loop.
+///
+/// # Arguments
+///
+/// * `patterns` - The name and RegexAndDFA struct of each pattern.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A String containing the generated Noir code.
+#[cfg(feature = "fs")]
+pub(crate) fn to_noir_shared_fn(
+    patterns: &[(String, RegexAndDFA)],
+    gen_substrs: bool,
+    options: &NoirOptions,
+) -> String {
+    let input_type = options.input_type;
+    let (_, _, max_unit) = input_vars(input_type);
+    let automata = patterns
+        .iter()
+        .map(|(_, regex_and_dfa)| {
+            (
+                build_automaton(regex_and_dfa, options, false),
+                regex_and_dfa.has_end_anchor,
+            )
+        })
+        .collect_vec();
+    let shared = build_shared_automata(&automata, max_unit);
+    let alphabets = automata
+        .iter()
+        .map(|(own, _)| {
+            options
+                .input_alphabet
+                .map(|alphabet| alphabet_ranges(own, alphabet))
+        })
+        .collect_vec();
+
+    let mut sections = gen_imports(&options.imports).into_iter().collect_vec();
+    sections.extend(patterns.iter().zip(&alphabets).zip(&shared).map(
+        |(((name, regex_and_dfa), alphabet), automaton)| {
+            // The occurrences, reverse matching and match bounds of the options are rejected
+            // for multiple patterns, see `gen_noir_shared_fn`.
+            let ctx = CodegenContext {
+                automaton,
+                options,
+                input_type,
+                accept_sink: None,
+                gen_substrs,
+            };
+            gen_regex_match_fn(
+                ctx,
+                &regex_and_dfa.regex_pattern,
+                alphabet.as_deref(),
+                options.input_hash,
+                &format!("pub fn regex_match_{name}"),
+                1,
+                false,
+            )
+        },
+    ));
+    if options.fused {
+        // The input must satisfy the alphabet of every pattern, as with their own functions.
+        let alphabet = alphabets.iter().flatten().map(Vec::as_slice).fold(
+            None,
+            |all: Option<Vec<(u32, u32)>>, ranges| {
+                Some(match all {
+                    Some(all) => intersect_ranges(&all, ranges),
+                    None => ranges.to_vec(),
+                })
+            },
+        );
+        let fused = patterns
+            .iter()
+            .zip(&shared)
+            .map(|((name, regex_and_dfa), automaton)| {
+                (
+                    name.as_str(),
+                    regex_and_dfa.regex_pattern.as_str(),
+                    automaton,
+                )
+            })
+            .collect_vec();
+        sections.push(gen_fused_match_fn(
+            &fused,
+            input_type,
+            alphabet.as_deref(),
+            options.input_hash,
+            &options.imports,
+        ));
+    }
+    let mut helpers = vec![];
+    if gen_substrs {
+        helpers.push(gen_capture_helpers_fn(input_type));
+    }
+    if gen_substrs && options.lowercase_captures {
+        helpers.push(gen_to_ascii_lowercase_fn(input_type));
+    }
+    match &options.runtime {
+        Some(runtime) if !helpers.is_empty() => sections.insert(
+            usize::from(!options.imports.is_empty()),
+            gen_runtime_imports(runtime, &helpers, input_type),
+        ),
+        _ => sections.extend(helpers),
+    }
+    if let Some(automaton) = shared.first() {
+        let ctx = CodegenContext {
+            automaton,
+            options,
+            input_type,
+            accept_sink: None,
+            gen_substrs,
+        };
+        sections.push(gen_transitions_fn(ctx, "fn next_state"));
+    }
+    format!("{}\n", sections.join("\n\n"))
+}
+
+/// Generates a Noir file matching several patterns with a single shared automaton.
+///
+/// # Arguments
+///
+/// * `patterns` - The name and RegexAndDFA struct of each pattern.
+/// * `noir_path` - The path where the generated Noir file should be saved.
+/// * `gen_substrs` - A boolean indicating whether to extract the substrings.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Result indicating success or a CompilerError.
+#[cfg(feature = "fs")]
+pub(crate) fn gen_noir_shared_fn(
+    patterns: &[(String, RegexAndDFA)],
+    noir_path: &Path,
+    gen_substrs: bool,
+    options: &NoirOptions,
+) -> Result<(), CompilerError> {
+    if options.input_type == NoirInputType::PackedField
+        || options.split_modules
+        || options.packed_captures
+        || options.capture_digests
+        || options.capture_lens
+        || options.byte_source
+        || options.field_input
+        || options.public_next_state
+        || options.occurrence.is_some()
+        || options.reverse
+        || options.repetition_counter.is_some()
+        || options.match_mask
+        || options.match_len
+        || options.match_end
+        || options.redaction.is_some()
+        || options.match_bound.is_some()
+        || options.input_segments.is_some()
+        || options.match_window
+        || options.match_at
+        || options.capture_count.is_some()
+        || options.fixed_len.is_some()
+        || !options.corpus.is_empty()
+        || options.verify_input_hash
+    {
+        return Err(CompilerError::GenericError(
+            "packed input, packed captures, capture digests, capture lengths, byte sources, Field inputs, public transitions, occurrences, reverse matching, repetition counters, match masks, match lengths, match ends, redactions, match bounds, input segments, match windows, match offsets, capture counts, fixed lengths, corpora, input hash checks and split modules are not supported for multiple patterns"
+                .to_string(),
+        ));
+    }
+    for (name, regex_and_dfa) in patterns {
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(CompilerError::GenericError(format!(
+                "pattern name \"{}\" is not a valid Noir identifier",
+                name
+            )));
+        }
+        validate_noir_options(regex_and_dfa, options)?;
+        if gen_substrs {
+            validate_longest_captures(regex_and_dfa, options)?;
+            for warning in capture_bound_warnings(regex_and_dfa) {
+                warn!(pattern = %name, "{warning}");
+            }
+        }
+        if let Some(warning) = trivial_match_warning(regex_and_dfa, options) {
+            warn!(pattern = %name, "{warning}");
+        }
+        if let Some(warning) = lost_match_warning(regex_and_dfa, options) {
+            warn!(pattern = %name, "{warning}");
+        }
+        if let Some(warning) = cut_match_warning(regex_and_dfa, options) {
+            warn!(pattern = %name, "{warning}");
+        }
+        if let Some(warning) = gen_substrs
+            .then(|| capture_ambiguity_warning(regex_and_dfa, options))
+            .flatten()
+        {
+            warn!(pattern = %name, "{warning}");
+        }
+        if gen_substrs
+            && regex_and_dfa
+                .parts
+                .iter()
+                .any(|part| part.max_repetitions.is_some())
+        {
+            return Err(CompilerError::GenericError(format!(
+                "pattern \"{}\" has repeated captures, which are not supported for multiple patterns",
+                name
+            )));
+        }
+        if gen_substrs && regex_and_dfa.parts.iter().any(|part| part.hole) {
+            return Err(CompilerError::GenericError(format!(
+                "pattern \"{}\" has holes, which are not supported for multiple patterns",
+                name
+            )));
+        }
+        if gen_substrs && regex_and_dfa.parts.iter().any(|part| part.numeric) {
+            return Err(CompilerError::GenericError(format!(
+                "pattern \"{}\" has numeric captures, which are not supported for multiple patterns",
+                name
+            )));
+        }
+    }
+    if !patterns.iter().map(|(name, _)| name).all_unique() {
+        return Err(CompilerError::GenericError(
+            "pattern names must be unique".to_string(),
+        ));
+    }
+    if options.fused && patterns.iter().any(|(name, _)| name == "all") {
+        return Err(CompilerError::GenericError(
+            "pattern name \"all\" is taken by regex_match_all".to_string(),
+        ));
+    }
+
+    let noir = to_noir_shared_fn(patterns, gen_substrs, options);
+    write_noir_file(noir_path, &noir, options)
+}
+
+/// Builds an automaton over bytes that reaches no state once any forbidden pattern has occurred.
+///
+/// The automaton tracks, for every pattern, the states of its DFA reached by the matches that
+/// may still be in progress, starting a new match at every byte. Transitions into a set holding
+/// an accepting state are left out, so that `next_state` returns 0 as soon as a forbidden
+/// pattern ends. State 0 is never used otherwise, and the automaton starts in state 1.
+///
+/// # Arguments
+///
+/// * `dfas` - The DFA graph of each forbidden pattern, matching the pattern exactly.
+///
+/// # Returns
+///
+/// A Result containing the automaton, or a CompilerError if a pattern matches the empty string.
+#[cfg(feature = "fs")]
+fn build_denylist_automaton(dfas: &[DFAGraph]) -> Result<NoirAutomaton, CompilerError> {
+    let tables = dfas.iter().map(dense_transition_table).collect_vec();
+    let accepts = dfas.iter().map(collect_accept_states).collect_vec();
+    if accepts.iter().any(|accept| accept.contains(&0)) {
+        return Err(CompilerError::GenericError(
+            "a forbidden pattern matches the empty string, so every input contains it".to_string(),
+        ));
+    }
+
+    let starts = (0..dfas.len()).map(|idx| (idx, 0)).collect::<BTreeSet<_>>();
+    let mut ids = BTreeMap::from([(starts.clone(), 1)]);
+    let mut queue = VecDeque::from([starts.clone()]);
+    let mut edges: BTreeMap<(usize, usize), BTreeSet<u32>> = BTreeMap::new();
+    while let Some(active) = queue.pop_front() {
+        let from = ids[&active];
+        for byte in 0..=u8::MAX {
+            let mut next = starts.clone();
+            next.extend(
+                active.iter().filter_map(|&(idx, state)| {
+                    tables[idx][state][byte as usize].map(|to| (idx, to))
+                }),
+            );
+            if next
+                .iter()
+                .any(|(idx, state)| accepts[*idx].contains(state))
+            {
+                continue;
+            }
+            let to = match ids.get(&next) {
+                Some(&to) => to,
+                None => {
+                    let to = ids.len() + 1;
+                    ids.insert(next.clone(), to);
+                    queue.push_back(next);
+                    to
+                }
+            };
+            edges.entry((from, to)).or_default().insert(byte as u32);
+        }
+    }
+
+    Ok(NoirAutomaton {
+        transitions: edges
+            .into_iter()
+            .map(|(edge, bytes)| (edge, to_ranges(bytes)))
+            .collect(),
+        accept_states: BTreeSet::new(),
+        substr_transitions: vec![],
+        init_state: 1,
+        start_state: 1,
+        free_state_id: ids.len() + 1,
+        tagged: false,
+        max_repetitions: vec![],
+        max_lengths: vec![],
+        capture_bounds: vec![],
+        counters: vec![],
+    })
+}
+
+/// Generates the Noir code asserting that none of the forbidden patterns occurs in the input.
+///
+/// # Arguments
+///
+/// * `patterns` - The forbidden patterns, emitted as comments.
+/// * `dfas` - The DFA graph of each forbidden pattern, matching the pattern exactly.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Result containing the generated Noir code, or a CompilerError.
+#[cfg(feature = "fs")]
+pub(crate) fn to_noir_denylist_fn(
+    patterns: &[String],
+    dfas: &[DFAGraph],
+    options: &NoirOptions,
+) -> Result<String, CompilerError> {
+    let automaton = build_denylist_automaton(dfas)?;
+    let mut lines = vec!["pub fn assert_no_match<let N: u32>(input: [u8; N]) {".to_string()];
+    for pattern in patterns {
+        lines.push(format!(
+            "    // forbidden: {}",
+            pattern.replace('\n', "\\n").replace('\r', "\\r")
+        ));
+    }
+    lines.extend([
+        format!("    let mut s = {};", automaton.init_state),
+        "    for i in 0..N {".to_string(),
+        "        s = next_state(s, input[i]);".to_string(),
+        format!(
+            "        assert(s != {RESET_STATE}, f\"a forbidden pattern ends at input[{{i}}]\");"
+        ),
+        "    }".to_string(),
+        "}".to_string(),
+    ]);
+    let ctx = CodegenContext {
+        automaton: &automaton,
+        options,
+        input_type: NoirInputType::U8,
+        accept_sink: None,
+        gen_substrs: false,
+    };
+    let transitions = gen_transitions_fn(ctx, "fn next_state");
+    Ok(format!("{}\n\n{}\n", lines.join("\n"), transitions))
+}
+
+/// Generates a Noir file asserting that none of the forbidden patterns occurs in the input.
+///
+/// # Arguments
+///
+/// * `patterns` - The forbidden patterns, emitted as comments.
+/// * `dfas` - The DFA graph of each forbidden pattern, matching the pattern exactly.
+/// * `noir_path` - The path where the generated Noir file should be saved.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Result indicating success or a CompilerError.
+#[cfg(feature = "fs")]
+pub(crate) fn gen_noir_denylist_fn(
+    patterns: &[String],
+    dfas: &[DFAGraph],
+    noir_path: &Path,
+    options: &NoirOptions,
+) -> Result<(), CompilerError> {
+    if options.input_type != NoirInputType::U8 {
+        return Err(CompilerError::GenericError(
+            "the denylist requires u8 input".to_string(),
+        ));
+    }
+    let noir = to_noir_denylist_fn(patterns, dfas, options)?;
+    write_noir_file(noir_path, &noir, options)
+}
+
+/// Generates the Noir matcher of a structured body followed by the struct of its fields and
+/// `regex_match_fields`, returning the fields of the body matched by `regex_match`.
+///
+/// Text fields are returned as bytes and numeric ones as `u64` values, converted by
+/// `numeric_captures` so that their ranges are asserted.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - The regex built by `fields_to_decomposed`, a public part per field.
+/// * `config` - The fields of the body.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Result containing the Noir code or a CompilerError.
+#[cfg(feature = "fs")]
+pub(crate) fn to_noir_fields_fn(
+    regex_and_dfa: &RegexAndDFA,
+    config: &FieldsConfig,
+    options: &NoirOptions,
+) -> Result<String, CompilerError> {
+    validate_noir_matcher(regex_and_dfa, true, options)?;
+    if options.input_type != NoirInputType::U8
+        || options.capture_starts
+        || options.input_hash.is_some()
+        || options.split_modules
+    {
+        return Err(CompilerError::GenericError(
+            "fields require u8 input without capture starts, input hashes or split modules"
+                .to_string(),
+        ));
+    }
+    let automaton = build_automaton(regex_and_dfa, options, false);
+    let capture_len = automaton.capture_len();
+    let text = config.fields.iter().any(|field| !field.numeric);
+    let generics = if text { "<let L: u32>" } else { "" };
+    let mut lines = vec![format!("pub struct {}{} {{", config.struct_name, generics)];
+    for field in &config.fields {
+        let typ = if field.numeric {
+            "u64"
+        } else {
+            "BoundedVec<u8, L>"
+        };
+        lines.push(format!("    pub {}: {},", field.name, typ));
+    }
+    lines.push("}".to_string());
+    lines.push(String::new());
+    let return_type = match text {
+        true => format!(" -> {}<{}>", config.struct_name, capture_len),
+        false => format!(" -> {}", config.struct_name),
+    };
+    lines.extend(fmt_fn_signature(
+        "pub fn regex_match_fields<let N: u32>",
+        &["input: [u8; N]".to_string()],
+        &return_type,
+    ));
+    lines.push("    let substrs = regex_match(input);".to_string());
+    if config.fields.iter().any(|field| field.numeric) {
+        lines.push("    let numbers = numeric_captures(substrs);".to_string());
+    }
+    lines.push(format!("    {} {{", config.struct_name));
+    let mut numbers = 0;
+    for (idx, field) in config.fields.iter().enumerate() {
+        let value = match field.numeric {
+            true => {
+                numbers += 1;
+                format!("numbers[{}]", numbers - 1)
+            }
+            false => {
+                let captures = part_capture_indices(regex_and_dfa, &automaton, |part| {
+                    part.substr_idx == Some(idx)
+                });
+                format!("capture_to_bytes(substrs[{}])", captures[0])
+            }
+        };
+        lines.push(format!("        {}: {},", field.name, value));
+    }
+    lines.push("    }".to_string());
+    lines.push("}".to_string());
+    Ok(format!(
+        "{}\n{}\n",
+        to_noir_fn(regex_and_dfa, true, options),
+        lines.join("\n")
+    ))
+}
+
+/// Generates a Noir file extracting the fields of a structured body, see `to_noir_fields_fn`.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - The regex built by `fields_to_decomposed`, a public part per field.
+/// * `config` - The fields of the body.
+/// * `noir_path` - The path where the generated Noir file should be saved.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Result indicating success or a CompilerError.
+#[cfg(feature = "fs")]
+pub(crate) fn gen_noir_fields_fn(
+    regex_and_dfa: &RegexAndDFA,
+    config: &FieldsConfig,
+    noir_path: &Path,
+    options: &NoirOptions,
+) -> Result<(), CompilerError> {
+    let noir = to_noir_fields_fn(regex_and_dfa, config, options)?;
+    write_noir_file(noir_path, &noir, options)
+}
+
+/// A pattern of a composition, see `build_composed_automaton`.
+#[cfg(feature = "fs")]
+pub(crate) struct ComposedPattern {
+    /// The DFA graph matching the pattern without its anchors exactly.
+    pub(crate) dfa: DFAGraph,
+    /// Whether the pattern starts with `^`, so that its match must start the input.
+    pub(crate) start_anchored: bool,
+    /// Whether the pattern ends with `$`, so that its match must end the input.
+    pub(crate) end_anchored: bool,
+}
+
+/// The search for one pattern of a composition: the states of its DFA reached by the matches
+/// still in progress, and whether a match has already been found.
+#[cfg(feature = "fs")]
+type PatternSearch = (BTreeSet<usize>, bool);
+
+/// Builds an automaton over bytes running the search for several patterns at once.
+///
+/// Every state of the product records, for each pattern, the matches in progress and whether
+/// one has been found, like the automaton of `build_denylist_automaton`. The label of a state
+/// is computed from the patterns satisfied by the input read so far, and the states with a
+/// label accept. In a sequence, the search for a pattern only starts once the previous one has
+/// been found, after the earliest end of its match, which leaves the most input to the others. The product is then minimized, and the states from which no accepting state
+/// can be reached are dropped, so that `next_state` returns 0 once the input cannot match.
+///
+/// # Arguments
+///
+/// * `patterns` - The patterns to search for.
+/// * `sequential` - Whether the patterns are searched for one after the other, in order.
+/// * `label` - Computes the label of a state from the satisfied patterns, `None` if the state
+///   does not accept.
+///
+/// # Returns
+///
+/// A Result containing the automaton, starting in state 1, and the label of each accepting
+/// state, or a CompilerError if no input is accepted.
+#[cfg(feature = "fs")]
+fn build_composed_automaton(
+    patterns: &[ComposedPattern],
+    sequential: bool,
+    label: impl Fn(&[bool]) -> Option<usize>,
+) -> Result<(NoirAutomaton, BTreeMap<usize, usize>), CompilerError> {
+    let tables = patterns
+        .iter()
+        .map(|pattern| dense_transition_table(&pattern.dfa))
+        .collect_vec();
+    let accepts = patterns
+        .iter()
+        .map(|pattern| collect_accept_states(&pattern.dfa))
+        .collect_vec();
+    // Once an unanchored match is found, the matches in progress no longer matter.
+    let normalize = |idx: usize, active: BTreeSet<usize>, found: bool| -> PatternSearch {
+        let found = found
+            || (!patterns[idx].end_anchored && active.iter().any(|s| accepts[idx].contains(s)));
+        match found {
+            true => (BTreeSet::new(), true),
+            false => (active, false),
+        }
+    };
+    let satisfied = |state: &[PatternSearch]| {
+        state
+            .iter()
+            .enumerate()
+            .map(|(idx, (active, found))| *found || active.iter().any(|s| accepts[idx].contains(s)))
+            .collect_vec()
+    };
+
+    // Whether the search for a pattern has started, given the searches before it.
+    let started =
+        |before: &[PatternSearch]| !sequential || before.last().is_none_or(|&(_, found)| found);
+
+    let mut init: Vec<PatternSearch> = vec![];
+    for idx in 0..patterns.len() {
+        let active = match started(&init) {
+            true => BTreeSet::from([0]),
+            false => BTreeSet::new(),
+        };
+        init.push(normalize(idx, active, false));
+    }
+    let mut ids = BTreeMap::from([(init.clone(), 0)]);
+    let mut states = vec![init];
+    let mut delta: Vec<[usize; 256]> = vec![];
+    while delta.len() < states.len() {
+        let state = states[delta.len()].clone();
+        let mut row = [0; 256];
+        for (byte, next_id) in row.iter_mut().enumerate() {
+            let mut next: Vec<PatternSearch> = vec![];
+            for (idx, (active, found)) in state.iter().enumerate() {
+                let mut active = active
+                    .iter()
+                    .filter_map(|&s| tables[idx][s][byte])
+                    .collect::<BTreeSet<_>>();
+                if !*found && !patterns[idx].start_anchored && started(&next) {
+                    active.insert(0);
+                }
+                next.push(normalize(idx, active, *found));
+            }
+            *next_id = match ids.get(&next) {
+                Some(&id) => id,
+                None => {
+                    ids.insert(next.clone(), states.len());
+                    states.push(next);
+                    states.len() - 1
+                }
+            };
+        }
+        delta.push(row);
+    }
+    let labels = states
+        .iter()
+        .map(|state| label(&satisfied(state)))
+        .collect_vec();
+
+    // Moore's partition refinement, starting from the states grouped by label.
+    let mut classes = labels
+        .iter()
+        .map(|label| label.map_or(0, |label| label + 1))
+        .collect_vec();
+    let mut num_classes = 0;
+    loop {
+        let mut signatures = BTreeMap::new();
+        let refined = (0..states.len())
+            .map(|state| {
+                let signature = (
+                    classes[state],
+                    delta[state].iter().map(|&next| classes[next]).collect_vec(),
+                );
+                let next_class = signatures.len();
+                *signatures.entry(signature).or_insert(next_class)
+            })
+            .collect_vec();
+        classes = refined;
+        if signatures.len() == num_classes {
+            break;
+        }
+        num_classes = signatures.len();
+    }
+
+    // Keep the classes from which an accepting class can be reached.
+    let mut live = states
+        .iter()
+        .enumerate()
+        .filter(|(state, _)| labels[*state].is_some())
+        .map(|(state, _)| classes[state])
+        .collect::<BTreeSet<_>>();
+    loop {
+        let grown = (0..states.len())
+            .filter(|&state| {
+                delta[state]
+                    .iter()
+                    .any(|&next| live.contains(&classes[next]))
+            })
+            .map(|state| classes[state])
+            .collect::<BTreeSet<_>>();
+        if grown.is_subset(&live) {
+            break;
+        }
+        live.extend(grown);
+    }
+    if !live.contains(&classes[0]) {
+        return Err(CompilerError::GenericError(
+            "the composed patterns cannot match any input".to_string(),
+        ));
+    }
+
+    // Number the live classes from 1 in the order they are reached from the start.
+    let mut class_ids = BTreeMap::from([(classes[0], 1)]);
+    let mut representatives = vec![0];
+    let mut transitions: BTreeMap<(usize, usize), BTreeSet<u32>> = BTreeMap::new();
+    let mut idx = 0;
+    while idx < representatives.len() {
+        let state = representatives[idx];
+        idx += 1;
+        for (byte, &next) in delta[state].iter().enumerate() {
+            if !live.contains(&classes[next]) {
+                continue;
+            }
+            let to = match class_ids.get(&classes[next]) {
+                Some(&to) => to,
+                None => {
+                    class_ids.insert(classes[next], representatives.len() + 1);
+                    representatives.push(next);
+                    representatives.len()
+                }
+            };
+            transitions
+                .entry((idx, to))
+                .or_default()
+                .insert(byte as u32);
+        }
+    }
+    let state_labels = representatives
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, &state)| labels[state].map(|label| (idx + 1, label)))
+        .collect::<BTreeMap<_, _>>();
+
+    let automaton = NoirAutomaton {
+        transitions: transitions
+            .into_iter()
+            .map(|(edge, bytes)| (edge, to_ranges(bytes)))
+            .collect(),
+        accept_states: state_labels.keys().copied().collect(),
+        substr_transitions: vec![],
+        init_state: 1,
+        start_state: 1,
+        free_state_id: representatives.len() + 1,
+        tagged: false,
+        max_repetitions: vec![],
+        max_lengths: vec![],
+        capture_bounds: vec![],
+        counters: vec![],
+    };
+    Ok((automaton, state_labels))
+}
+
+/// Labels a state of a composition from the patterns it satisfies.
+///
+/// # Arguments
+///
+/// * `composition` - How the patterns are combined.
+/// * `satisfied` - Whether each pattern is satisfied in the state.
+///
+/// # Returns
+///
+/// The index returned by the generated code if the state is accepting, or `None` otherwise.
+#[cfg(feature = "fs")]
+fn composition_label(composition: PatternComposition, satisfied: &[bool]) -> Option<usize> {
+    match composition {
+        PatternComposition::All => satisfied.iter().all(|&sat| sat).then_some(0),
+        PatternComposition::Any => satisfied.iter().position(|&sat| sat),
+        PatternComposition::Difference => (satisfied[0] && !satisfied[1]).then_some(0),
+        PatternComposition::Sequence => satisfied.iter().all(|&sat| sat).then_some(0),
+    }
+}
+
+/// Generates the Noir code of a composition of several patterns.
+///
+/// # Arguments
+///
+/// * `composition` - How the patterns are combined.
+/// * `regexes` - The patterns, emitted as comments.
+/// * `patterns` - The patterns to combine, in the same order.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Result containing the generated Noir code, or a CompilerError if no input is accepted.
+#[cfg(feature = "fs")]
+pub(crate) fn to_noir_composed_fn(
+    composition: PatternComposition,
+    regexes: &[String],
+    patterns: &[ComposedPattern],
+    options: &NoirOptions,
+) -> Result<String, CompilerError> {
+    let sequential = composition == PatternComposition::Sequence;
+    let (automaton, labels) = build_composed_automaton(patterns, sequential, |satisfied| {
+        composition_label(composition, satisfied)
+    })?;
+    let return_type = match composition {
+        PatternComposition::Any => " -> u32",
+        _ => "",
+    };
+
+    let mut lines = vec![format!(
+        "pub fn regex_match<let N: u32>(input: [u8; N]){return_type} {{"
+    )];
+    for (idx, regex) in regexes.iter().enumerate() {
+        let comment = match (composition, idx) {
+            (PatternComposition::All, _) => "all of",
+            (PatternComposition::Any, _) => "any of",
+            (PatternComposition::Difference, 0) => "matches",
+            (PatternComposition::Difference, _) => "but not",
+            (PatternComposition::Sequence, 0) => "first",
+            (PatternComposition::Sequence, _) => "then",
+        };
+        lines.push(format!(
+            "    // {comment}: {}",
+            regex.replace('\n', "\\n").replace('\r', "\\r")
+        ));
+    }
+    lines.extend([
+        format!("    let mut s = {};", automaton.init_state),
+        "    for i in 0..N {".to_string(),
+        "        s = next_state(s, input[i]);".to_string(),
+        "    }".to_string(),
+    ]);
+    lines.extend(fmt_assert(
+        4,
+        &states_condition("s", &automaton.accept_states),
+        "f\"no match: {s}\"",
+    ));
+    if composition == PatternComposition::Any {
+        // The states of the first pattern are left to the default index.
+        let mut states_by_label: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        for (&state, &label) in labels.iter().filter(|(_, &label)| label != 0) {
+            states_by_label.entry(label).or_default().insert(state);
+        }
+        lines.push("    let mut index = 0;".to_string());
+        for (i, (label, states)) in states_by_label.iter().enumerate() {
+            let keyword = if i == 0 { "if" } else { "} else if" };
+            lines.extend(fmt_if(4, keyword, &states_condition("s", states)));
+            lines.push(format!("        index = {label};"));
+        }
+        if !states_by_label.is_empty() {
+            lines.push("    }".to_string());
+        }
+        lines.push("    index".to_string());
+    }
+    lines.push("}".to_string());
+    let ctx = CodegenContext {
+        automaton: &automaton,
+        options,
+        input_type: NoirInputType::U8,
+        accept_sink: None,
+        gen_substrs: false,
+    };
+    let transitions = gen_transitions_fn(ctx, "fn next_state");
+    Ok(format!("{}\n\n{}\n", lines.join("\n"), transitions))
+}
+
+/// Generates a Noir file matching a composition of several patterns with a single automaton.
+///
+/// # Arguments
+///
+/// * `composition` - How the patterns are combined.
+/// * `regexes` - The patterns, emitted as comments.
+/// * `patterns` - The patterns to combine, in the same order.
+/// * `noir_path` - The path where the generated Noir file should be saved.
+/// * `options` - The options controlling the generated code.
+///
+/// # Returns
+///
+/// A Result indicating success or a CompilerError.
+#[cfg(feature = "fs")]
+pub(crate) fn gen_noir_composed_fn(
+    composition: PatternComposition,
+    regexes: &[String],
+    patterns: &[ComposedPattern],
+    noir_path: &Path,
+    options: &NoirOptions,
+) -> Result<(), CompilerError> {
+    if options.input_type != NoirInputType::U8 {
+        return Err(CompilerError::GenericError(
+            "composed patterns require u8 input".to_string(),
+        ));
+    }
+    let noir = to_noir_composed_fn(composition, regexes, patterns, options)?;
+    write_noir_file(noir_path, &noir, options)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fields::fields_to_decomposed,
+        progress::ScratchDir,
+        regex::{
+            create_counted_regex_and_dfa, create_dfa_graph_from_regex,
+            create_regex_and_dfa_from_groups, create_regex_and_dfa_from_str_and_defs,
+            get_regex_and_dfa, split_anchors,
+        },
+        structs::{DecomposedRegexConfig, RegexPartConfig, SubstringDefinitionsJson},
+    };
+
+    fn raw(regex: &str, transitions: Vec<Vec<(usize, usize)>>) -> RegexAndDFA {
+        create_regex_and_dfa_from_str_and_defs(
+            regex,
+            SubstringDefinitionsJson {
+                transitions,
+                names: vec![],
+            },
+        )
+        .expect("failed to build dfa")
+    }
+
+    fn decomposed(parts: &[(&str, bool)]) -> RegexAndDFA {
+        let mut config = DecomposedRegexConfig {
+            parts: parts
+                .iter()
+                .map(|&(regex_def, is_public)| RegexPartConfig {
+                    is_public,
+                    regex_def: regex_def.to_string(),
+                    max_repetitions: None,
+                    max_length: None,
+                    numeric: false,
+                    hole: false,
+                    min: None,
+                    max: None,
+                })
+                .collect(),
+        };
+        get_regex_and_dfa(&mut config).expect("failed to build dfa")
+    }
+
+    /// Generates the matcher for a regex exercising long conditions in every input mode.
+    fn formatting_samples() -> Vec<String> {
+        let samples = vec![
+            raw(
+                "(0|1|2|3|4|5|6|7|8|9)+@[a-zA-Z0-9_.%+-]+\\.(com|org|net|edu|gov)",
+                vec![vec![(0, 1), (1, 1)]],
+            ),
+            decomposed(&[
+                ("(0|1|2|3|4|5|6|7|8|9)+", true),
+                ("@[a-zA-Z0-9_.%+-]+\\.", false),
+                ("(com|org|net|edu|gov)", true),
+            ]),
+        ];
+        vec![
+            NoirInputType::U8,
+            NoirInputType::U16,
+            NoirInputType::PackedField,
+        ]
+        .into_iter()
+        .cartesian_product(vec![
+            NoirStrategy::IfChain,
+            NoirStrategy::Table,
+            NoirStrategy::MuxTree,
+            NoirStrategy::Keyword,
+        ])
+        .filter(|&(input_type, strategy)| {
+            input_type != NoirInputType::U16 || strategy != NoirStrategy::Table
+        })
+        .cartesian_product(&samples)
+        .flat_map(|((input_type, strategy), regex_and_dfa)| {
+            [true, false].map(|gen_substrs| {
+                let options = NoirOptions {
+                    input_type,
+                    input_hash: Some(NoirInputHash::Poseidon),
+                    strategy,
+                    input_alphabet: gen_substrs.then_some(NoirInputAlphabet::Declared),
+                    ..Default::default()
+                };
+                to_noir_fn(regex_and_dfa, gen_substrs, &options)
+            })
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_output_fits_nargo_fmt_width() {
+        for code in formatting_samples() {
+            for line in code.lines() {
+                assert!(line.len() <= MAX_WIDTH, "line too long: {}", line);
+                assert_eq!(line.trim_end(), line, "trailing whitespace: {}", line);
+                let indent = line.len() - line.trim_start().len();
+                assert_eq!(indent % 4, 0, "misaligned line: {}", line);
+            }
+        }
+    }
+
+    #[cfg(feature = "integration-tests")]
+    #[test]
+    fn test_output_is_nargo_fmt_clean() {
+        use std::process::Command;
+
+        for (idx, code) in formatting_samples().into_iter().enumerate() {
+            let scratch = ScratchDir::new(&format!("zk_regex_noir_fmt_{idx}")).unwrap();
+            let dir = scratch.path();
+            std::fs::create_dir_all(dir.join("src")).unwrap();
+            std::fs::write(
+                dir.join("Nargo.toml"),
+                "[package]\nname = \"regex\"\ntype = \"lib\"\n\n[dependencies]\n",
+            )
+            .unwrap();
+            std::fs::write(dir.join("src/lib.nr"), &code).unwrap();
+            let status = Command::new("nargo")
+                .args(["fmt", "--check"])
+                .current_dir(dir)
+                .status()
+                .expect("failed to run nargo");
+            assert!(status.success(), "nargo fmt would change:\n{}", code);
+        }
+    }
+
+    #[test]
+    fn test_example_project() {
+        for regex_and_dfa in [
+            raw("ab+c?", vec![vec![(1, 2), (2, 2)]]),
+            raw("^x[0-9]{3}$", vec![]),
+            decomposed(&[("from:", false), ("[A-Z][a-z]+", true), ("\r\n", false)]),
+        ] {
+            let automaton = build_automaton(&regex_and_dfa, &NoirOptions::default(), true);
+            let accept_sink = (!regex_and_dfa.has_end_anchor).then_some(automaton.free_state_id);
+            let sample = sample_input(&automaton, 1, 0).unwrap();
+            assert!(!sample.is_empty());
+            let bytes = sample.iter().map(|&c| c as u8).collect_vec();
+            assert!(
+                simulate(&automaton, accept_sink, &bytes).is_some(),
+                "{:?}",
+                sample
+            );
+        }
+        assert_eq!(pack_sample(&[0x61, 0x62]), vec!["0x6261"]);
+
+        let scratch = ScratchDir::new("zk_regex_noir_example").unwrap();
+        let dir = scratch.path().join("zk_regex_noir_example");
+        let options = NoirOptions {
+            input_type: NoirInputType::PackedField,
+            ..Default::default()
+        };
+        gen_noir_example(
+            &raw("ab+", vec![vec![(1, 2), (2, 2)]]),
+            &dir,
+            true,
+            &options,
+        )
+        .unwrap();
+        let main = std::fs::read_to_string(dir.join("src/main.nr")).unwrap();
+        assert!(main.contains("fn main(input: [Field; 1]) -> pub [BoundedVec<Field, 2>; 1] {"));
+        assert!(main.contains("regex::regex_match::<2, 1>(input)"));
+        assert!(main.contains("#[test]"));
+        let prover = std::fs::read_to_string(dir.join("Prover.toml")).unwrap();
+        assert_eq!(prover, "input = [\"0x6261\"]\n");
+        assert!(dir.join("src/regex.nr").exists());
+        assert!(dir.join("Nargo.toml").exists());
+
+        let dir = scratch.path().join("zk_regex_noir_project");
+        let options = NoirOptions {
+            input_type: NoirInputType::U16,
+            ..Default::default()
+        };
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2)]]);
+        let name = gen_noir_project(&regex_and_dfa, &dir, true, &options, Some("xabé")).unwrap();
+        assert_eq!(name, "zk_regex_noir_project");
+        let prover = std::fs::read_to_string(dir.join("Prover.toml")).unwrap();
+        assert_eq!(prover, "input = [120, 97, 98, 233]\n");
+        assert!(gen_noir_project(&regex_and_dfa, &dir, true, &options, Some("")).is_err());
+    }
+
+    #[test]
+    fn test_gen_noir_check_project() {
+        let regex_and_dfa = create_regex_and_dfa_from_groups("id:([a-z]+);").unwrap();
+        let options = NoirOptions::default();
+        let simulated = simulate_matcher(&regex_and_dfa, true, &options, b"xid:ab;");
+        assert_eq!(simulated, Some(vec![b"ab".to_vec()]));
+        assert_eq!(
+            simulate_matcher(&regex_and_dfa, true, &options, b"id:;"),
+            None
+        );
+
+        let scratch = ScratchDir::new("zk_regex_noir_check").unwrap();
+        let dir = scratch.path();
+        let expected = [b"ab".to_vec()];
+        gen_noir_check_project(
+            &regex_and_dfa,
+            dir,
+            true,
+            &options,
+            b"xid:ab;",
+            Some(&expected),
+        )
+        .unwrap();
+        let main = std::fs::read_to_string(dir.join("src/main.nr")).unwrap();
+        assert!(main.contains("fn main(input: [u8; 7]) {"));
+        assert!(main.contains("    assert(substrs[0].len() == 2, \"substring 0 diverges\");"));
+        assert!(main.contains("    assert(substrs[0].get(1) == 98, \"substring 0 diverges\");"));
+        let prover = std::fs::read_to_string(dir.join("Prover.toml")).unwrap();
+        assert_eq!(prover, "input = [120, 105, 100, 58, 97, 98, 59]\n");
+        assert!(dir.join("src/regex.nr").exists());
+
+        gen_noir_check_project(&regex_and_dfa, dir, true, &options, b"id:;", None).unwrap();
+        let main = std::fs::read_to_string(dir.join("src/main.nr")).unwrap();
+        assert!(main.contains("    let _ = regex::regex_match(input);\n}"));
+    }
+
+    #[test]
+    fn test_aztec_contract() {
+        let scratch = ScratchDir::new("zk_regex_aztec_contract").unwrap();
+        let dir = scratch.path();
+        let options = NoirOptions {
+            dependencies: BTreeMap::from([(
+                "aztec".to_string(),
+                "{ path = \"aztec\" }".to_string(),
+            )]),
+            ..Default::default()
+        };
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2)]]);
+        gen_aztec_contract(&regex_and_dfa, dir, true, &options, 32).unwrap();
+        let main = std::fs::read_to_string(dir.join("src/main.nr")).unwrap();
+        assert!(
+            main.contains("    #[private]\n    fn verify_match(input: [u8; 32]) -> [Field; 1] {")
+        );
+        assert!(main.contains("Poseidon2::hash(capture.storage(), capture.len());"));
+        let nargo_toml = std::fs::read_to_string(dir.join("Nargo.toml")).unwrap();
+        assert!(nargo_toml.contains("type = \"contract\""));
+        assert!(nargo_toml.contains("aztec = { path = \"aztec\" }"));
+        assert!(dir.join("src/regex.nr").exists());
+
+        gen_aztec_contract(&regex_and_dfa, dir, false, &options, 32).unwrap();
+        let main = std::fs::read_to_string(dir.join("src/main.nr")).unwrap();
+        assert!(
+            main.contains("fn verify_match(input: [u8; 32]) {\n        regex::regex_match(input);")
+        );
+        assert!(gen_aztec_contract(&regex_and_dfa, dir, true, &options, 0).is_err());
+    }
+
+    #[test]
+    fn test_noir_main() {
+        let scratch = ScratchDir::new("zk_regex_noir_main").unwrap();
+        let dir = scratch.path();
+        let (main_path, noir_path) = (dir.join("main.nr"), dir.join("email.nr"));
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            capture_starts: true,
+            ..Default::default()
+        };
+        let main = NoirMainOptions {
+            path: main_path.to_string_lossy().into_owned(),
+            input_len: 16,
+            outputs: vec![],
+        };
+        gen_noir_main(
+            &regex_and_dfa,
+            &main_path,
+            &noir_path,
+            true,
+            &options,
+            &main,
+        )
+        .unwrap();
+        let code = std::fs::read_to_string(&main_path).unwrap();
+        assert!(code.starts_with("mod email;\n"));
+        assert!(code.contains(
+            "fn main(input: [u8; 16]) -> pub ([BoundedVec<Field, 16>; 1], [u32; 1]) {\n    let (captures, starts) = email::regex_match(input);"
+        ));
+
+        let main = NoirMainOptions {
+            outputs: vec![MainOutput::Commitments, MainOutput::Match],
+            ..main
+        };
+        gen_noir_main(
+            &regex_and_dfa,
+            &main_path,
+            &noir_path,
+            true,
+            &options,
+            &main,
+        )
+        .unwrap();
+        let code = std::fs::read_to_string(&main_path).unwrap();
+        assert!(code.contains("fn main(input: [u8; 16]) -> pub ([Field; 1], bool) {"));
+        assert!(code.contains("let (captures, _starts) = email::regex_match(input);"));
+        assert!(code.contains("Poseidon2::hash(capture.storage(), capture.len());"));
+        assert!(code.ends_with("    (commitments, true)\n}\n"));
+
+        // The outputs must be returned by the matcher.
+        assert!(gen_noir_main(
+            &regex_and_dfa,
+            &main_path,
+            &noir_path,
+            false,
+            &options,
+            &main
+        )
+        .is_err());
+        let main = NoirMainOptions {
+            outputs: vec![MainOutput::InputHash],
+            ..main
+        };
+        assert!(gen_noir_main(
+            &regex_and_dfa,
+            &main_path,
+            &noir_path,
+            true,
+            &options,
+            &main
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_to_ranges() {
+        assert_eq!(to_ranges([1, 2, 3, 5, 7, 8]), vec![(1, 3), (5, 5), (7, 8)]);
+        assert!(to_ranges([]).is_empty());
+    }
+
+    #[test]
+    fn test_u16_collapses_multibyte_chars() {
+        let regex_and_dfa = raw("é[α-ω]+", vec![]);
+        let automaton = build_u16_automaton(&regex_and_dfa);
+        let ranges = automaton.transitions.values().flatten().collect_vec();
+        assert!(ranges.contains(&&(0xE9, 0xE9)));
+        assert!(ranges.contains(&&(0x3B1, 0x3C9)));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::U16,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains("pub fn regex_match<let N: u32>(input: [u16; N])"));
+        assert!(code.contains("fn next_state(s: Field, unit: u16) -> Field {"));
+        assert!(code.contains("(unit >= 945) & (unit <= 969)"));
+    }
+
+    #[test]
+    fn test_packed_field_unpacks_into_byte_matcher() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            input_type: NoirInputType::PackedField,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.starts_with(
+            "pub fn regex_match<let N: u32, let M: u32>(input: [Field; M]) -> [BoundedVec<Field, N>; 1] {"
+        ));
+        assert!(code.contains("let chunk: [u8; 31] = input[i].to_le_bytes();"));
+        assert!(code.contains("fn regex_match_bytes<let N: u32>(input: [u8; N])"));
+        assert!(!code.contains("pub fn regex_match_bytes"));
+        assert!(code.contains(
+            "pub unconstrained fn regex_match_unconstrained<let N: u32>(\n    input: [u8; N],\n)"
+        ));
+    }
+
+    #[test]
+    fn test_input_hash_is_returned() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            input_hash: Some(NoirInputHash::Sha256),
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("-> ([BoundedVec<Field, N>; 1], [u8; 32]) {"));
+        assert!(code.contains("let input_hash = std::hash::sha256(input);"));
+        assert!(code.contains("([substr0], input_hash)"));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::PackedField,
+            input_hash: Some(NoirInputHash::Poseidon),
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains("(input: [Field; M]) -> Field {"));
+        assert!(code.contains("std::hash::poseidon2::Poseidon2::hash(input, M)"));
+        assert!(code.contains("    regex_match_bytes(bytes);\n    input_hash\n}"));
+    }
+
+    #[test]
+    fn test_verify_input_hash() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            input_hash: Some(NoirInputHash::Sha256),
+            verify_input_hash: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains(
+            "pub fn regex_match_verified<let N: u32>(\n    input: [u8; N],\n    expected_hash: [u8; 32],\n) -> [BoundedVec<Field, N>; 1] {\n    let (substrs, input_hash) = regex_match(input);\n    assert(input_hash == expected_hash, \"the input does not hash to the expected digest\");\n    substrs\n}"
+        ), "{}", code);
+
+        // Without captures, nothing is returned.
+        let options = NoirOptions {
+            input_type: NoirInputType::PackedField,
+            input_hash: Some(NoirInputHash::Poseidon),
+            ..options
+        };
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains("(input: [Field; M], expected_hash: Field) {\n    let input_hash = regex_match::<N, M>(input);\n    assert(input_hash == expected_hash,"), "{}", code);
+
+        let options = NoirOptions {
+            input_hash: None,
+            ..options
+        };
+        let err = validate_noir_options(&regex_and_dfa, &options).unwrap_err();
+        assert!(err.to_string().contains("requires an input hash"));
+    }
+
+    #[test]
+    fn test_imports_replace_std_items() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            input_hash: Some(NoirInputHash::Sha256),
+            capture_digests: true,
+            imports: vec![
+                "sha256::sha256".to_string(),
+                "sha256::sha256_var".to_string(),
+            ],
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.starts_with("use sha256::sha256;\nuse sha256::sha256_var;\n\n"));
+        assert!(code.contains("let input_hash = sha256(input);"));
+        assert!(code.contains("    sha256_var(bytes.storage(), bytes.len() as u64)"));
+        assert!(!code.contains("std::hash"));
+
+        let options = NoirOptions {
+            split_modules: true,
+            ..options
+        };
+        let modules = to_noir_modules(&regex_and_dfa, true, &options);
+        assert!(modules[0]
+            .1
+            .contains("\nuse sha256::sha256;\nuse sha256::sha256_var;\n"));
+        assert!(modules[2]
+            .1
+            .starts_with("use sha256::sha256;\nuse sha256::sha256_var;\n\n"));
+
+        let scratch = ScratchDir::new("zk_regex_noir_dependencies").unwrap();
+        let dir = scratch.path();
+        let options = NoirOptions {
+            dependencies: BTreeMap::from([(
+                "sha256".to_string(),
+                "{ tag = \"v0.1.0\", git = \"https://github.com/noir-lang/sha256\" }".to_string(),
+            )]),
+            ..options
+        };
+        gen_noir_example(&regex_and_dfa, dir, true, &options).unwrap();
+        let nargo_toml = std::fs::read_to_string(dir.join("Nargo.toml")).unwrap();
+        assert!(nargo_toml.ends_with(
+            "[dependencies]\nsha256 = { tag = \"v0.1.0\", git = \"https://github.com/noir-lang/sha256\" }\n"
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_str_wrapper_only_for_bytes() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let code = to_noir_fn(&regex_and_dfa, true, &NoirOptions::default());
+        assert!(code.contains(
+            "pub fn regex_match_str<let N: u32>(input: str<N>) -> [BoundedVec<Field, N>; 1] {\n    regex_match(input.as_bytes())\n}"
+        ));
+
+        let code = to_noir_fn(&regex_and_dfa, false, &NoirOptions::default());
+        assert!(code.contains("    regex_match(input.as_bytes());\n}"));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::U16,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(!code.contains("regex_match_str"));
+    }
+
+    #[test]
+    fn test_capture_helpers_follow_input_type() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let code = to_noir_fn(&regex_and_dfa, false, &NoirOptions::default());
+        assert!(!code.contains("capture_to_"));
+
+        let code = to_noir_fn(&regex_and_dfa, true, &NoirOptions::default());
+        assert!(code.contains("-> BoundedVec<u8, N> {"));
+        assert!(code.contains("pub fn capture_to_bytes_array<let N: u32, let M: u32>"));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::U16,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("-> BoundedVec<u16, N> {"));
+        assert!(code.contains("let narrowed = value as u16;"));
+    }
+
+    #[test]
+    fn test_table_matches_if_chain_transitions() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            strategy: NoirStrategy::Table,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        let automaton = build_u8_automaton(&regex_and_dfa);
+        let num_states = automaton.free_state_id + 1;
+        assert!(code.contains(&format!(
+            "global TRANSITIONS: [Field; {}] = [",
+            num_states * 256
+        )));
+        assert!(code.contains("    TRANSITIONS[(s as u32) * 256 + (byte as u32)]"));
+        assert!(!code.contains("} else if"));
+
+        let entries = code
+            .split("global TRANSITIONS")
+            .nth(1)
+            .and_then(|rest| rest.split("= [").nth(1))
+            .and_then(|rest| rest.split("];").next())
+            .unwrap()
+            .split(',')
+            .filter_map(|entry| entry.trim().parse::<usize>().ok())
+            .collect_vec();
+        assert_eq!(entries.len(), num_states * 256);
+        for ((from, to), ranges) in &automaton.transitions {
+            for &(min, _) in ranges {
+                assert_eq!(entries[from * 256 + min as usize], *to);
+            }
+        }
+        let sink = automaton.free_state_id;
+        assert!(entries[sink * 256..].iter().all(|&entry| entry == sink));
+    }
+
+    #[test]
+    fn test_if_chain_shares_branches_across_states() {
+        let regex_and_dfa = raw("(a[0-9]|b[0-9a-z])y", vec![]);
+        let options = NoirOptions {
+            strategy: NoirStrategy::IfChain,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains(
+            "    } else if ((s == 1) | (s == 2)) & ((byte >= 48) & (byte <= 57)) {\n        next = 3;"
+        ));
+        assert!(code.contains(
+            "    } else if (s == 2) & ((byte >= 97) & (byte <= 122)) {\n        next = 3;"
+        ));
+    }
+
+    #[test]
+    fn test_integer_state_type() {
+        let options = NoirOptions {
+            state_type: NoirStateType::Integer,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&raw("ab+", vec![]), false, &options);
+        assert!(code.contains("fn next_state(s: u8, byte: u8) -> u8 {"));
+
+        let regex_and_dfa = raw("[a-z]{300}", vec![]);
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains("fn next_state(s: u32, byte: u8) -> u32 {"));
+        let options = NoirOptions {
+            strategy: NoirStrategy::Table,
+            ..options
+        };
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains("global TRANSITIONS: [u32; "));
+        assert!(code.contains("fn next_state(s: u32, byte: u8) -> u32 {"));
+    }
+
+    #[test]
+    fn test_keyword_runs_use_global_arrays() {
+        let regex_and_dfa = raw("secret=[0-9]+", vec![vec![(7, 8), (8, 8)]]);
+        let automaton = build_u8_automaton(&regex_and_dfa);
+        let runs = find_keyword_runs(&automaton);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(
+            runs[0].1,
+            b"secret=".iter().map(|&b| b as u32).collect_vec()
+        );
+
+        let options = NoirOptions {
+            strategy: NoirStrategy::Keyword,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("global KEYWORD_0: [u8; 7] = [115, 101, 99, 114, 101, 116, 61];"));
+        assert!(code.contains("if byte == KEYWORD_0[(s as u32) - "));
+    }
+
+    #[test]
+    fn test_auto_strategy() {
+        let regex_and_dfa = raw("secret=[0-9]+", vec![vec![(7, 8), (8, 8)]]);
+        let automaton = build_automaton(&regex_and_dfa, &NoirOptions::default(), false);
+        let auto = |automaton: &NoirAutomaton, input_type, input_len| {
+            let options = NoirOptions {
+                input_type,
+                input_len: Some(input_len),
+                ..Default::default()
+            };
+            resolve_strategy(automaton, input_type, &options)
+        };
+        // The globals of the keyword runs are cheap, those of the table pay off on long inputs.
+        assert_eq!(
+            auto(&automaton, NoirInputType::U8, 16),
+            NoirStrategy::Keyword
+        );
+        assert_eq!(
+            auto(&automaton, NoirInputType::U8, 4096),
+            NoirStrategy::Table
+        );
+        assert_eq!(
+            auto(&automaton, NoirInputType::U16, 4096),
+            NoirStrategy::Keyword
+        );
+        let options = NoirOptions {
+            strategy: NoirStrategy::MuxTree,
+            input_len: Some(4096),
+            ..Default::default()
+        };
+        assert_eq!(
+            resolve_strategy(&automaton, NoirInputType::U8, &options),
+            NoirStrategy::MuxTree
+        );
+
+        // The mux tree pays off when the states have more transitions than there are states.
+        let automaton = build_automaton(
+            &raw("(ab|cd|ef|gh|ij|kl)x", vec![]),
+            &NoirOptions::default(),
+            false,
+        );
+        assert_eq!(
+            auto(&automaton, NoirInputType::U8, 16),
+            NoirStrategy::MuxTree
+        );
+        // The few comparisons of a tiny automaton cost less than the lookups of a table.
+        let automaton = build_automaton(&raw("ab", vec![]), &NoirOptions::default(), false);
+        assert_eq!(
+            auto(&automaton, NoirInputType::U8, 4096),
+            NoirStrategy::IfChain
+        );
+        // Without any literal run, the keyword strategy is the if-chain.
+        assert_eq!(
+            auto(
+                &build_automaton(&raw("[a-z]+", vec![]), &NoirOptions::default(), false),
+                NoirInputType::U16,
+                16
+            ),
+            NoirStrategy::IfChain
+        );
+    }
+
+    #[test]
+    fn test_mux_tree_has_a_leaf_per_state() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            strategy: NoirStrategy::MuxTree,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("    let state = s as u32;"));
+        assert!(!code.contains("let mut next"));
+        // States 0..=2 and the accept sink give three inner nodes.
+        assert_eq!(code.matches("if state < ").count(), 3);
+    }
+
+    #[test]
+    fn test_split_modules_share_next_state() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            strategy: NoirStrategy::IfChain,
+            ..Default::default()
+        };
+        let modules = to_noir_modules(&regex_and_dfa, true, &options);
+        let names = modules.iter().map(|(name, _)| name.as_str()).collect_vec();
+        assert_eq!(names, vec!["", "transitions", "helpers"]);
+        assert!(modules[0]
+            .1
+            .starts_with("mod transitions;\npub mod helpers;\n\nuse transitions::next_state;\n"));
+        assert!(!modules[0].1.contains("fn next_state"));
+        assert!(modules[1]
+            .1
+            .starts_with("pub fn next_state(s: Field, byte: u8) -> Field {"));
+        assert!(modules[2].1.contains("pub fn capture_to_bytes<"));
+
+        let modules = to_noir_modules(&regex_and_dfa, false, &NoirOptions::default());
+        assert_eq!(modules.len(), 2);
+        assert!(!modules[0].1.contains("mod helpers"));
+    }
+
+    #[test]
+    fn test_public_next_state() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            public_next_state: true,
+            ..Default::default()
+        };
+        let code = gen_noir_sources(&regex_and_dfa, false, &options).unwrap()[0]
+            .1
+            .clone();
+        assert!(code.contains("pub fn next_state(s: Field, byte: u8) -> Field {"));
+        assert!(code.contains("pub struct StateInfo {"));
+        assert!(code.contains("StateInfo { start: 0, initial: 0, reset: 0 }"));
+        assert!(code.contains("pub fn is_accepting(self, s: Field) -> bool {"));
+        assert!(code.contains("pub fn step(self, s: Field, byte: u8) -> (Field, bool) {"));
+        assert!(code.contains("            next = next_state(self.start, byte);\n"));
+        assert!(!to_noir_fn(&regex_and_dfa, false, &NoirOptions::default()).contains("StateInfo"));
+
+        let options = NoirOptions {
+            public_next_state: true,
+            split_modules: true,
+            ..Default::default()
+        };
+        let modules = to_noir_modules(&regex_and_dfa, false, &options);
+        assert!(modules[0].1.contains("pub use transitions::next_state;"));
+    }
+
+    #[test]
+    fn test_compact_noir() {
+        let code = "// Generated\n\npub fn f(x: u8) -> u8 {\n    // comment\n    assert(x  != 1, \"a  // \\\"b\\\"\"); // why\n    x\n}\n";
+        assert_eq!(
+            compact_noir(code),
+            "pub fn f(x: u8) -> u8 {\nassert(x != 1, \"a  // \\\"b\\\"\");\nx\n}\n"
+        );
+
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let code = to_noir_fn(&regex_and_dfa, true, &NoirOptions::default());
+        let compacted = compact_noir(&code);
+        assert!(compacted.len() < code.len());
+        assert!(!compacted.contains("//"));
+        assert!(!compacted.lines().any(|line| line.starts_with(' ')));
+        assert_eq!(compact_noir(&compacted), compacted);
+    }
+
+    #[test]
+    fn test_streamed_noir_file() {
+        // More states and branches than are generated at once.
+        let long = create_regex_and_dfa_from_groups("id:([a-z]{2,1100});").unwrap();
+        assert!(long.dfa.states.len() > STREAM_CHUNK);
+        let short = create_regex_and_dfa_from_groups("(a|b)+c").unwrap();
+        let scratch = ScratchDir::new("zk_regex_noir_streamed").unwrap();
+        let dir = scratch.path();
+        let noir_path = dir.join("matcher.nr");
+        let cases = [
+            (&long, NoirOptions::default()),
+            (
+                &long,
+                NoirOptions {
+                    strategy: NoirStrategy::Table,
+                    compact: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                &short,
+                NoirOptions {
+                    split_modules: true,
+                    prefix_len: true,
+                    compact: true,
+                    ..Default::default()
+                },
+            ),
+        ];
+        for (regex_and_dfa, options) in cases {
+            gen_noir_fn(regex_and_dfa, &noir_path, true, &options).unwrap();
+            for (name, code) in gen_noir_sources(regex_and_dfa, true, &options).unwrap() {
+                let path = match name.is_empty() {
+                    true => noir_path.clone(),
+                    false => dir.join("matcher").join(format!("{name}.nr")),
+                };
+                assert_eq!(std::fs::read_to_string(path).unwrap(), code);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parallel_codegen_is_deterministic() {
+        // More branches than are formatted at once, so that several chunks are formatted by
+        // the threads of the pool.
+        let regex_and_dfa = create_regex_and_dfa_from_groups("id:([a-z]{2,1100});").unwrap();
+        let gen = |options: &NoirOptions, threads: usize| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| to_noir_fn(&regex_and_dfa, true, options))
+        };
+        for strategy in [NoirStrategy::IfChain, NoirStrategy::Keyword] {
+            let options = NoirOptions {
+                strategy,
+                ..Default::default()
+            };
+            let code = gen(&options, 4);
+            assert!(code.matches("} else if").count() > STREAM_CHUNK);
+            assert_eq!(gen(&options, 4), code);
+            assert_eq!(gen(&options, 1), code);
+        }
+
+        // The branches are ordered by the states they leave, the accepting ones last.
+        let options = NoirOptions {
+            strategy: NoirStrategy::IfChain,
+            ..Default::default()
+        };
+        let code = gen(&options, 4);
+        let next_state = &code[code.find("fn next_state(").unwrap()..];
+        let sources = next_state
+            .split("else if")
+            .map(|branch| {
+                let from = &branch[branch.find("(s == ").unwrap() + 6..];
+                from[..from.find(')').unwrap()].parse::<usize>().unwrap()
+            })
+            .collect_vec();
+        assert!(sources.len() > STREAM_CHUNK);
+        let (_, transitions) = sources.split_last().unwrap();
+        assert!(
+            transitions.windows(2).all(|pair| pair[0] <= pair[1]),
+            "{:?}",
+            transitions
+        );
+    }
+
+    #[test]
+    fn test_byte_source() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            byte_source: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("pub trait ByteSource<let N: u32> {"));
+        assert!(code.contains("impl<let N: u32> ByteSource<N> for [u8; N] {"));
+        assert!(code.contains("impl<let N: u32> ByteSource<N> for BoundedVec<u8, N> {"));
+        assert!(code.contains("impl<let N: u32, let M: u32> ByteSource<N> for PackedBytes<N, M> {"));
+        assert!(code.contains("let chunk: [u8; 31] = self.fields[i].to_le_bytes();"));
+        assert!(code.contains("pub fn regex_match_source<let N: u32, S: ByteSource<N>>(input: S)"));
+        assert!(code.contains("    regex_match(input.to_bytes())\n"));
+        assert!(!to_noir_fn(&regex_and_dfa, true, &NoirOptions::default()).contains("ByteSource"));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::PackedField,
+            byte_source: true,
+            ..Default::default()
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_field_input() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            field_input: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains(
+            "pub fn regex_match_fields<let N: u32>(input: [Field; N]) -> [BoundedVec<Field, N>; 1] {"
+        ));
+        assert!(code.contains("        let unit = input[i] as u8;\n        assert(unit as Field == input[i], \"input value does not fit in u8\");"));
+        assert!(code.contains("    regex_match(units)\n}"));
+        assert!(!to_noir_fn(&regex_and_dfa, true, &NoirOptions::default())
+            .contains("regex_match_fields"));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::U16,
+            field_input: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("    let mut units: [u16; N] = [0; N];"));
+        let options = NoirOptions {
+            input_type: NoirInputType::PackedField,
+            field_input: true,
+            ..Default::default()
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_packed_captures() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            packed_captures: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("pub fn regex_match_packed_captures<let N: u32, let P: u32>"));
+        assert!(code.contains("pub fn capture_to_packed<let N: u32, let M: u32>("));
+        assert!(!to_noir_fn(&regex_and_dfa, false, &options).contains("capture_to_packed"));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::PackedField,
+            packed_captures: true,
+            split_modules: true,
+            ..Default::default()
+        };
+        let modules = to_noir_modules(&regex_and_dfa, true, &options);
+        assert!(modules[0].1.contains("use helpers::capture_to_packed;"));
+        assert!(modules[0].1.contains("regex_match::<N, M>(input)"));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::U16,
+            packed_captures: true,
+            ..Default::default()
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_min_match_len_assert() {
+        let options = NoirOptions::default();
+        let assert_min = |min: usize| {
+            format!("assert(N >= {min}, \"the input is shorter than the shortest match of {min} code units\");")
+        };
+        let code = to_noir_fn(&raw("x=[0-9]+;", vec![vec![]]), false, &options);
+        assert!(code.contains(&assert_min(4)));
+
+        // The priming byte of `^` is not counted, the line break of the alternative is.
+        let regex_and_dfa = decomposed(&[("(\r\n|^)id:", false), ("[a-z]+", true)]);
+        let automaton = build_automaton(&regex_and_dfa, &options, true);
+        assert_eq!(automaton.min_match_len(), Some(4));
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains(&assert_min(4)));
+
+        // Patterns matching the empty input need no assertion.
+        let code = to_noir_fn(&raw("[a-z]*", vec![vec![]]), false, &options);
+        assert!(!code.contains("shortest match"));
+
+        // The byte 255 stands for the start anchor, so a match through it is unreachable.
+        let regex_and_dfa = raw(r"a(?-u:\xFF)", vec![vec![]]);
+        let automaton = build_automaton(&regex_and_dfa, &options, false);
+        assert_eq!(automaton.min_match_len(), None);
+        let err = validate_noir_options(&regex_and_dfa, &options).unwrap_err();
+        assert!(err.to_string().contains("the pattern can never match"));
+    }
+
+    #[test]
+    fn test_self_loop_compression() {
+        // The loop of `[^\n]*` is checked against the few code units leaving it.
+        let code = to_noir_fn(
+            &raw("a[^\n]*b", vec![vec![]]),
+            false,
+            &NoirOptions {
+                strategy: NoirStrategy::IfChain,
+                ..Default::default()
+            },
+        );
+        assert!(code.contains("(s == 1) & (byte != 10) & (byte != 98) & (byte < 128) {"));
+        let options = NoirOptions {
+            strategy: NoirStrategy::MuxTree,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&raw("a[^\n]*b", vec![vec![]]), false, &options);
+        assert!(code.contains("if (byte != 10) & (byte != 98) & (byte < 128) {"));
+
+        // Narrow loops keep their ranges.
+        assert!(self_loop_conditions("byte", &[(48, 57)], 255).is_none());
+        assert_eq!(complement_ranges(&[(0, 9), (11, 255)], 255), vec![(10, 10)]);
+        assert!(complement_ranges(&[(0, 255)], 255).is_empty());
+    }
+
+    #[test]
+    fn test_class_predicates() {
+        let regex_and_dfa = raw("[0-9]{3}-[a-z0-9]{2}-[a-z0-9]+x", vec![vec![]]);
+        let mut options = NoirOptions {
+            strategy: NoirStrategy::IfChain,
+            class_predicates: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(
+            code.contains("fn is_digit(byte: u8) -> bool {\n    (byte >= 48) & (byte <= 57)\n}")
+        );
+        assert!(code.contains("if (s == 0) & is_digit(byte) {"));
+        assert!(code.contains("(s == 4) & is_lowercase_alphanumeric(byte) {"));
+        // A range tested by a single branch keeps its comparisons.
+        assert!(!code.contains("fn is_class"));
+
+        options.class_predicates = false;
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(!code.contains("is_digit"));
+        assert!(code.contains("if (s == 0) & ((byte >= 48) & (byte <= 57)) {"));
+    }
+
+    #[test]
+    fn test_prefix_len() {
+        let regex_and_dfa = raw("x=[0-9]+;", vec![vec![]]);
+        let options = NoirOptions {
+            prefix_len: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains("pub fn regex_match_prefix_len<let N: u32>(input: [u8; N]) -> u32 {"));
+        assert!(code.contains("let mut s_next = next_state(s, byte);"));
+        assert!(!code.contains("prefix_next_state"));
+
+        // Tagged captures get untagged transitions of their own.
+        let regex_and_dfa = decomposed(&[("x=", false), ("[0-9]+", true), (";", false)]);
+        let modules = to_noir_modules(
+            &regex_and_dfa,
+            true,
+            &NoirOptions {
+                split_modules: true,
+                ..options.clone()
+            },
+        );
+        assert!(modules[0].1.contains("use transitions::prefix_next_state;"));
+        assert!(modules[0]
+            .1
+            .contains("let mut s_next = prefix_next_state(s, byte);"));
+        assert!(modules[1]
+            .1
+            .contains("pub fn prefix_next_state(s: Field, byte: u8) -> Field {"));
+
+        let options = NoirOptions {
+            reverse: true,
+            ..options
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_corpus_profile() {
+        let regex_and_dfa = raw("(id=[a-z]+|[0-9]+)", vec![]);
+        let mut options = NoirOptions {
+            strategy: NoirStrategy::IfChain,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains("    let mut next = 0;\n    if (s == 0) & (byte == 105) {"));
+
+        // The digits the corpus is made of are checked first.
+        options.corpus = vec![b"12345".to_vec(), b"999".to_vec(), b"id=7".to_vec()];
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains(
+            "    let mut next = 0;\n    if ((s == 0) | (s == 1)) & ((byte >= 48) & (byte <= 57)) {"
+        ));
+        let profile = profile_corpus(&regex_and_dfa, false, &options);
+        assert_eq!((profile.inputs, profile.matched, profile.units), (3, 3, 12));
+        assert_eq!(profile.states[0], StateVisits { state: 1, units: 6 });
+        assert_eq!(
+            profile.transitions[..2],
+            [
+                TransitionVisits {
+                    from: 1,
+                    to: 1,
+                    units: 6
+                },
+                TransitionVisits {
+                    from: 0,
+                    to: 1,
+                    units: 3
+                }
+            ]
+        );
+
+        // The bytes of a tagged automaton are tagged as by `capture_tags`.
+        let regex_and_dfa = decomposed(&[("to:", false), ("[a-z]+", true), (";", false)]);
+        let options = NoirOptions {
+            corpus: vec![b"to:abc;".to_vec(), b"xto:d;".to_vec(), b"to:".to_vec()],
+            ..Default::default()
+        };
+        assert!(build_automaton(&regex_and_dfa, &options, true).tagged);
+        let profile = profile_corpus(&regex_and_dfa, true, &options);
+        assert_eq!(profile.matched, 2);
+        assert!(gen_noir_sources(&regex_and_dfa, true, &options).is_ok());
+    }
+
+    #[test]
+    fn test_max_branches() {
+        let regex_and_dfa = raw("(alpha|beta|gamma)=[0-9]+;", vec![]);
+        let mut options = NoirOptions {
+            strategy: NoirStrategy::Keyword,
+            max_branches: Some(4),
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert_eq!(code.matches("fn next_state_part").count(), 5);
+        assert!(code.contains(
+            "fn next_state_part0(s: Field, byte: u8) -> Field {\n    let mut next = 0;\n    \
+             if (s == 0) & (byte == 97) {"
+        ));
+        assert!(code.contains(
+            "    let state = s as u32;\n    if state < 1 {\n        next_state_part0(s, byte)\n    \
+             } else if state < 3 {"
+        ));
+        assert!(code.contains("    } else {\n        next_state_part4(s, byte)\n    }\n}"));
+
+        // A function within the limit is left whole.
+        options.max_branches = Some(20);
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(!code.contains("next_state_part"));
+        options.max_branches = Some(0);
+        assert!(gen_noir_sources(&regex_and_dfa, false, &options).is_err());
+    }
+
+    #[test]
+    fn test_input_segments() {
+        let regex_and_dfa = decomposed(&[("to:", false), ("[a-z]+", true), (";", false)]);
+        let options = NoirOptions {
+            input_segments: Some(3),
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains(
+            "pub fn regex_match_segments<let N0: u32, let N1: u32, let N2: u32>(\n    input0: [u8; N0],\n    input1: [u8; N1],\n    input2: [u8; N2],\n) -> [BoundedVec<Field, N0 + N1 + N2>; 1] {"
+        ));
+        // The segments are only joined to choose the capture tags.
+        assert!(code.contains("        input[N0 + N1 + j] = input2[j];"));
+        assert!(code.contains("let tags = capture_tags(input);"));
+        assert!(code.contains(
+            "    for j in 0..N2 {\n        let i = N0 + N1 + j;\n        let byte = input2[j];"
+        ));
+        let segments = &code[code.find("pub fn regex_match_segments").unwrap()..];
+        assert_eq!(
+            segments
+                .matches("let mut s_next = next_state(s, unit);")
+                .count(),
+            3
+        );
+
+        // Without tags nor hash, nothing is joined.
+        let regex_and_dfa = raw("to:[a-z]+;", vec![vec![]]);
+        let options = NoirOptions {
+            input_segments: Some(2),
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains("pub fn regex_match_segments<let N0: u32, let N1: u32>(input0: [u8; N0], input1: [u8; N1]) {"));
+        assert!(!code.contains("let mut input ="));
+        // Nothing refers to the index in the joined input, so it is not declared.
+        assert!(code.contains("    for j in 0..N1 {\n        let byte = input1[j];"));
+        assert!(!code.contains("let i ="));
+
+        let options = NoirOptions {
+            input_segments: Some(1),
+            ..options
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_trivial_match() {
+        let options = NoirOptions::default();
+        let warning = |regex: &str, options: &NoirOptions| {
+            trivial_match_warning(&raw(regex, vec![]), options)
+        };
+        for regex in [".*", "x*", "[a-z]*$"] {
+            assert_eq!(
+                warning(regex, &options).as_deref(),
+                Some("the pattern accepts every ASCII input, so proving a match shows nothing"),
+                "{regex}"
+            );
+        }
+        assert_eq!(
+            warning("[\\x00-\\x60\\x62-\\x7f]{2}|a", &options).as_deref(),
+            Some(
+                "the pattern accepts every ASCII input of at least 2 code units, so proving a \
+                 match shows nothing"
+            )
+        );
+        let printable = NoirOptions {
+            input_alphabet: Some(NoirInputAlphabet::Printable),
+            ..Default::default()
+        };
+        assert!(warning("[ -~\t\r\n]", &printable)
+            .is_some_and(|warning| warning.starts_with("the pattern accepts every input,")));
+        for regex in ["a", "a.*", "^.*x", "[a-z]+$"] {
+            assert_eq!(warning(regex, &options), None, "{regex}");
+        }
+        // Matching the empty string no longer passes every input once it is rejected.
+        let options = NoirOptions {
+            empty_match: NoirEmptyMatch::Reject,
+            ..Default::default()
+        };
+        assert_eq!(warning(".*", &options), None);
+        assert!(warning("x*|[^x]", &options).is_some());
+    }
+
+    #[test]
+    fn test_capture_ambiguity() {
+        let options = NoirOptions::default();
+        let regex_and_dfa = decomposed(&[("a+", true), ("a+", false)]);
+        assert_eq!(
+            capture_ambiguity_warning(&regex_and_dfa, &options).as_deref(),
+            Some(
+                "the captures of \"aaa\" can be split in more than one way, so the prover \
+                 chooses them and they must not be treated as canonical"
+            )
+        );
+        let regex_and_dfa = decomposed(&[("x=", false), ("[0-9]+", true), ("[0-9]*;", false)]);
+        assert!(capture_ambiguity_warning(&regex_and_dfa, &options)
+            .is_some_and(|warning| warning.contains("\"x=00;\"")));
+
+        // A capture delimited by bytes it cannot hold is determined by the input.
+        for parts in [
+            vec![("to:", false), ("[a-z]+", true), (";", false)],
+            vec![("a+", true), ("b+", false)],
+            vec![("x=", false), ("[0-9]+", true), (";", false)],
+        ] {
+            let regex_and_dfa = decomposed(&parts);
+            assert_eq!(capture_ambiguity_warning(&regex_and_dfa, &options), None);
+        }
+    }
+
+    #[test]
+    fn test_match_window() {
+        let regex_and_dfa = decomposed(&[("to:", false), ("[a-z]+", true), (";", false)]);
+        let options = NoirOptions {
+            match_window: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains(
+            "pub fn regex_match_window<let N: u32>(\n    input: [u8; N],\n    start: u32,\n    end: u32,\n) -> [BoundedVec<Field, N>; 1] {"
+        ));
+        assert!(code.contains(
+            "    assert(start <= end, \"the window ends before it starts\");\n    assert(end <= N, \"the window ends past the input\");"
+        ));
+        assert!(code.contains("    let tags = capture_tags_window(input, start, end);"));
+        assert!(code.contains(
+            "        if (i >= start) & (i < end) {\n            let byte = input[i];\n            let unit = byte as u16 + 256 * (tags[i] as u16);"
+        ));
+        // The tags outside the window are never chosen, the state being kept there.
+        assert!(code.contains(
+            "                if (i < start) | (i >= end) {\n                    reachable[i][s] = true;\n                } else {"
+        ));
+        assert_eq!(code.matches("let tags = capture_tags(input);").count(), 2);
+
+        let regex_and_dfa = raw("to:[a-z]+;", vec![vec![]]);
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains(
+            "pub fn regex_match_window<let N: u32>(input: [u8; N], start: u32, end: u32) {"
+        ));
+        assert!(!code.contains("capture_tags"));
+        let options = NoirOptions {
+            reverse: true,
+            ..options
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_match_mask() {
+        let regex_and_dfa = raw("x=[0-9]+;", vec![vec![]]);
+        let options = NoirOptions {
+            match_mask: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains("pub fn regex_match_mask<let N: u32>(input: [u8; N]) -> [bool; N] {"));
+        assert!(code.contains("mask[i] = (i >= start) & (i < end);"));
+
+        // Mirrors the loop of `regex_match_mask`.
+        let automaton = build_u8_automaton(&regex_and_dfa);
+        let sink = automaton.free_state_id;
+        let next_state = |s: usize, byte: u8| {
+            automaton
+                .transitions
+                .iter()
+                .find(|((from, _), ranges)| {
+                    *from == s
+                        && ranges
+                            .iter()
+                            .any(|&(min, max)| (min..=max).contains(&(byte as u32)))
+                })
+                .map(|((_, to), _)| *to)
+                .unwrap_or_else(|| match s == sink || automaton.accept_states.contains(&s) {
+                    true => sink,
+                    false => 0,
+                })
+        };
+        let mask = |input: &[u8]| {
+            let (mut s, mut len, mut span) = (automaton.init_state, 0, 0..0);
+            for (i, &byte) in input.iter().enumerate() {
+                let mut s_next = next_state(s, byte);
+                if s_next == 0 {
+                    len = 0;
+                    s_next = next_state(automaton.start_state, byte);
+                }
+                if s_next != 0 && s_next != sink {
+                    len += 1;
+                }
+                if automaton.accept_states.contains(&s_next) {
+                    span = i + 1 - len..i + 1;
+                }
+                s = s_next;
+            }
+            span
+        };
+        assert_eq!(mask(b"x=12;"), 0..5);
+        assert_eq!(mask(b"ab x=x=7; x=8;"), 5..9);
+
+        // Tagged captures get untagged transitions of their own.
+        let regex_and_dfa = decomposed(&[("x=", false), ("[0-9]+", true), (";", false)]);
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("let mut s_next = prefix_next_state(s, byte);"));
+
+        let options = NoirOptions {
+            occurrence: Some(2),
+            ..options
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_match_len() {
+        let regex_and_dfa = raw("\"[^\"]*\"", vec![vec![]]);
+        let options = NoirOptions {
+            match_len: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains("pub fn regex_match_len<let N: u32>(input: [u8; N]) -> u32 {"));
+        assert!(code.contains("    end - start\n}"));
+        assert!(code.contains("assert(N >= 2, "));
+        // The span is computed by the same loop as the mask.
+        let mask = NoirOptions {
+            match_mask: true,
+            ..Default::default()
+        };
+        let mask_code = to_noir_fn(&regex_and_dfa, false, &mask);
+        let span = |code: &str| {
+            let start = code.find("    let mut len: u32 = 0;").unwrap();
+            let end = start + code[start..].find("no match").unwrap();
+            code[start..end].to_string()
+        };
+        assert_eq!(span(&code), span(&mask_code));
+
+        let options = NoirOptions {
+            reverse: true,
+            ..options
+        };
+        let err = validate_noir_options(&regex_and_dfa, &options).unwrap_err();
+        assert!(err.to_string().contains("match length"));
+    }
+
+    #[test]
+    fn test_match_at() {
+        let regex_and_dfa = decomposed(&[("ssn=", false), ("[0-9]+", true), (";", false)]);
+        let options = NoirOptions {
+            match_at: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains(
+            "pub fn regex_match_at<let N: u32, let L: u32>(input: [u8; N], start: u32) -> u32 {"
+        ));
+        assert!(code.contains("    for j in 0..L {\n        let i = start + j;"));
+        // The tags of the captures are not part of the window.
+        assert!(code.contains("            s = prefix_next_state(s, byte);"));
+        assert!(!code.contains("the window does not reach the end of the input"));
+
+        let regex_and_dfa = raw("[0-9]+$", vec![vec![]]);
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains("assert(N - start <= L, "));
+        assert!(code.contains("            s = next_state(s, byte);"));
+
+        let options = NoirOptions {
+            reverse: true,
+            ..options
+        };
+        let err = validate_noir_options(&regex_and_dfa, &options).unwrap_err();
+        assert!(err.to_string().contains("match offset"));
+
+        // The window may not hold a code unit without a transition, even where the automaton
+        // starts in a state other than its start state.
+        let regex_and_dfa = raw("(?:^|;)id=[0-9]+;", vec![]);
+        let options = NoirOptions {
+            match_at: true,
+            ..Default::default()
+        };
+        let automaton = build_automaton(&regex_and_dfa, &options, false);
+        assert_ne!(automaton.init_state, automaton.start_state);
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains(&format!(
+            "    let mut s = if start == 0 {{ {} }} else {{ {} }};",
+            automaton.init_state, automaton.start_state
+        )));
+        assert!(code.contains(&format!(
+            "            assert(s != {RESET_STATE}, f\"no match at {{start}}: input[{{i}}]\");"
+        )));
+        let matcher = MatcherRun::new(&regex_and_dfa, &options, false);
+        let match_at = |input: &[u8], start: usize, window: usize| {
+            let units = input.iter().map(|&byte| byte as u32).collect_vec();
+            matcher.run_at(&units, start, window)
+        };
+        assert_eq!(match_at(b"id=12;", 0, 8), Some(6));
+        assert_eq!(match_at(b"x;id=12;", 1, 8), Some(8));
+        // `x` has no transition after the digits.
+        assert_eq!(match_at(b"x;id=1x2;", 1, 8), None);
+        assert_eq!(match_at(b"xid=12;", 1, 8), None);
+
+        // Without a start anchor, the match starts in the same state wherever it starts.
+        let regex_and_dfa = raw("id=[0-9]+;", vec![]);
+        let automaton = build_automaton(&regex_and_dfa, &options, false);
+        assert_eq!(automaton.init_state, automaton.start_state);
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains(&format!("    let mut s = {};\n", automaton.start_state)));
+        assert!(!code.contains("if start == 0"));
+    }
+
+    #[test]
+    fn test_capture_count() {
+        let regex_and_dfa = decomposed(&[("a=", false), ("[0-9]*", true), (";", false)]);
+        let options = NoirOptions {
+            capture_count: Some(1),
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("    num_captures += (substr0.len() != 0) as u32;\n"));
+        assert!(code.contains(
+            "assert(num_captures == 1, f\"the match produces {num_captures} captures instead of 1\");"
+        ));
+        assert_eq!(
+            simulate_matcher(&regex_and_dfa, true, &options, b"a=12;"),
+            Some(vec![b"12".to_vec()])
+        );
+        // An empty capture does not count.
+        assert_eq!(
+            simulate_matcher(&regex_and_dfa, true, &options, b"a=;"),
+            None
+        );
+
+        let err = validate_noir_matcher(&regex_and_dfa, false, &options).unwrap_err();
+        assert!(err.to_string().contains("requires the substrings"));
+        let options = NoirOptions {
+            capture_count: Some(2),
+            ..options
+        };
+        let err = validate_noir_matcher(&regex_and_dfa, true, &options).unwrap_err();
+        assert!(err.to_string().contains("exceeds the 1 captures"));
+    }
+
+    #[test]
+    fn test_fixed_len() {
+        let regex_and_dfa = decomposed(&[("id:", false), ("[a-z]+", true), (";", false)]);
+        let options = NoirOptions {
+            fixed_len: Some(64),
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains(
+            "pub fn regex_match_fixed(input: [u8; 64]) -> [BoundedVec<Field, 64>; 1] {\n    regex_match(input)\n}"
+        ));
+
+        // The capture bounds are kept.
+        let json = r#"{"parts": [
+            {"is_public": false, "regex_def": "id:"},
+            {"is_public": true, "regex_def": "[a-z]+", "max_length": 8},
+            {"is_public": false, "regex_def": ";"}
+        ]}"#;
+        let mut config: DecomposedRegexConfig = serde_json::from_str(json).unwrap();
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code
+            .contains("pub fn regex_match_fixed(input: [u8; 64]) -> [BoundedVec<Field, 8>; 1] {"));
+
+        let options = NoirOptions {
+            fixed_len: Some(0),
+            ..options
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_match_redacted() {
+        let regex_and_dfa = decomposed(&[("ssn=", false), ("[0-9]+", true), (";", false)]);
+        let options = NoirOptions {
+            redaction: Some('*'),
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains(
+            "pub fn regex_match_redacted<let N: u32>(input: [u8; N], redacted: [u8; N]) {"
+        ));
+        assert!(
+            code.contains("let expected = if (i >= start) & (i < end) { 42 } else { input[i] };")
+        );
+        // The tags of the captures are not part of the redacted input.
+        assert!(code.contains("let mut s_next = prefix_next_state(s, byte);"));
+
+        let options = NoirOptions {
+            redaction: Some('█'),
+            ..Default::default()
+        };
+        let err = validate_noir_options(&regex_and_dfa, &options).unwrap_err();
+        assert!(err.to_string().contains("does not fit"));
+        let options = NoirOptions {
+            input_type: NoirInputType::U16,
+            ..options
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_ok());
+        let options = NoirOptions {
+            occurrence: Some(2),
+            ..options
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_match_end() {
+        let regex_and_dfa = raw("x=[0-9]+;", vec![vec![]]);
+        let options = NoirOptions {
+            match_end: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        assert!(code.contains("pub fn regex_match_end<let N: u32>(input: [u8; N]) -> u32 {"));
+        assert!(code.contains("            end = i + 1;\n"));
+
+        // Mirrors the loop of `regex_match_end`.
+        let automaton = build_u8_automaton(&regex_and_dfa);
+        let sink = automaton.free_state_id;
+        let next_state = |s: usize, byte: u8| {
+            automaton
+                .transitions
+                .iter()
+                .find(|((from, _), ranges)| {
+                    *from == s
+                        && ranges
+                            .iter()
+                            .any(|&(min, max)| (min..=max).contains(&(byte as u32)))
+                })
+                .map(|((_, to), _)| *to)
+                .unwrap_or_else(|| match s == sink || automaton.accept_states.contains(&s) {
+                    true => sink,
+                    false => 0,
+                })
+        };
+        let end = |input: &[u8]| {
+            let (mut s, mut end) = (automaton.init_state, None);
+            for (i, &byte) in input.iter().enumerate() {
+                let mut s_next = next_state(s, byte);
+                if s_next == 0 {
+                    s_next = next_state(automaton.start_state, byte);
+                }
+                if end.is_none() && automaton.accept_states.contains(&s_next) {
+                    end = Some(i + 1);
+                }
+                s = s_next;
+            }
+            end
+        };
+        assert_eq!(end(b"ab x=12; x=3;"), Some(8));
+        assert_eq!(end(b"x=x=1;"), Some(6));
+        assert_eq!(end(b"x=1"), None);
+
+        let options = NoirOptions {
+            reverse: true,
+            ..options
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_match_bound() {
+        let regex_and_dfa = raw("x=[0-9]+;", vec![vec![]]);
+        let options = NoirOptions {
+            match_bound: Some(16),
+            ..Default::default()
+        };
+        validate_noir_options(&regex_and_dfa, &options).unwrap();
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        let sink = build_u8_automaton(&regex_and_dfa).free_state_id;
+        assert!(code.contains(&format!(
+            "        if i == 16 {{\n            assert(s == {sink}, \"the match does not complete within the first 16 code units\");\n        }}\n    }}\n"
+        )));
+        // The unconstrained matcher asserts the bound too.
+        assert_eq!(code.matches("if i == 16 {").count(), 2);
+
+        for bound in [0, 3] {
+            let options = NoirOptions {
+                match_bound: Some(bound),
+                ..Default::default()
+            };
+            assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+        }
+        let regex_and_dfa = raw("x=[0-9]+;$", vec![vec![]]);
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_capture_digests() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            capture_digests: true,
+            input_hash: Some(NoirInputHash::Poseidon),
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains(
+            "pub fn regex_match_capture_digests<let N: u32>(input: [u8; N]) -> ([[u8; 32]; 1], Field) {"
+        ));
+        assert!(code.contains("std::hash::sha256_var(bytes.storage(), bytes.len() as u64)"));
+        assert!(!to_noir_fn(&regex_and_dfa, false, &options).contains("capture_to_sha256"));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::PackedField,
+            capture_digests: true,
+            split_modules: true,
+            ..Default::default()
+        };
+        let modules = to_noir_modules(&regex_and_dfa, true, &options);
+        assert!(modules[0].1.contains("use helpers::capture_to_sha256;"));
+        assert!(modules[0].1.contains("regex_match::<N, M>(input)"));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::U16,
+            capture_digests: true,
+            ..Default::default()
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_capture_lens() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            capture_lens: true,
+            input_hash: Some(NoirInputHash::Poseidon),
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains(
+            "pub fn regex_match_capture_lens<let N: u32>(\n    input: [u8; N],\n) -> ([BoundedVec<Field, N>; 1], [u32; 1], Field) {"
+        ));
+        assert!(code.contains("    let (substrs, input_hash) = regex_match(input);\n"));
+        assert!(code.contains("        lens[i] = substrs[i].len();\n"));
+        assert!(!to_noir_fn(&regex_and_dfa, false, &options).contains("regex_match_capture_lens"));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::U16,
+            capture_lens: true,
+            capture_starts: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("input: [u16; N]"));
+        assert!(code.contains("    let (substrs, _) = regex_match(input);\n"));
+        assert!(code.contains("    (substrs, lens)\n"));
+    }
+
+    #[test]
+    fn test_runtime() {
+        let regex_and_dfa = raw("ab+", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            runtime: Some("zk_regex_runtime".to_string()),
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.starts_with(
+            "pub use zk_regex_runtime::{capture_to_bytes, capture_to_bytes_array};\n"
+        ));
+        assert!(!code.contains("pub fn capture_to_bytes"));
+        // Without substrings there are no helpers to import.
+        assert!(!to_noir_fn(&regex_and_dfa, false, &options).contains("zk_regex_runtime"));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::U16,
+            lowercase_captures: true,
+            split_modules: true,
+            ..options
+        };
+        let modules = to_noir_modules(&regex_and_dfa, true, &options);
+        assert_eq!(modules.len(), 2);
+        assert!(modules[0].1.contains(
+            "pub use zk_regex_runtime::{\n    capture_to_units,\n    capture_to_units_array,\n    to_ascii_lowercase_u16 as to_ascii_lowercase,\n};"
+        ));
+
+        let lib = gen_runtime_lib(&[]);
+        for helper in [
+            "capture_to_bytes",
+            "capture_to_units_array",
+            "to_ascii_lowercase",
+            "reverse_capture",
+            "capture_to_packed",
+            "capture_to_sha256",
+            "capture_to_u64",
+        ] {
+            assert!(lib.contains(&format!("pub fn {helper}")), "{}", helper);
+        }
+
+        let options = NoirOptions {
+            runtime: Some("../runtime".to_string()),
+            ..Default::default()
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_capture_max_length() {
+        let json = r#"{"parts": [
+            {"is_public": false, "regex_def": "to:"},
+            {"is_public": true, "regex_def": "[a-z]+", "max_length": 16},
+            {"is_public": false, "regex_def": "@"},
+            {"is_public": true, "regex_def": "[a-z.]+", "max_length": 64}
+        ]}"#;
+        let mut config: DecomposedRegexConfig = serde_json::from_str(json).unwrap();
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        let code = to_noir_fn(&regex_and_dfa, true, &NoirOptions::default());
+        assert!(code.contains(
+            "pub fn regex_match<let N: u32>(input: [u8; N]) -> [BoundedVec<Field, 64>; 2] {"
+        ));
+        assert!(code.contains(
+            "            assert(substr0.len() < 16, \"substring 0 is longer than 16\");\n            substr0.push(byte as Field);"
+        ));
+
+        // A single unbounded part keeps the captures sized to the input.
+        config.parts[3].max_length = None;
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        let code = to_noir_fn(&regex_and_dfa, true, &NoirOptions::default());
+        assert!(code.contains("-> [BoundedVec<Field, N>; 2] {"));
+        assert!(code.contains("assert(substr0.len() < 16"));
+        assert!(!code.contains("assert(substr1.len()"));
+
+        config.parts[3].max_length = Some(0);
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        assert!(validate_noir_options(&regex_and_dfa, &NoirOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_inferred_capture_bounds() {
+        // Bounded captures are sized to their longest match without a max_length.
+        let json = r#"{"parts": [
+            {"is_public": false, "regex_def": "id="},
+            {"is_public": true, "regex_def": "[0-9]{4}"},
+            {"is_public": false, "regex_def": ";"},
+            {"is_public": true, "regex_def": "[a-z]{1,6}"}
+        ]}"#;
+        let mut config: DecomposedRegexConfig = serde_json::from_str(json).unwrap();
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        let code = to_noir_fn(&regex_and_dfa, true, &NoirOptions::default());
+        assert!(code.contains(
+            "pub fn regex_match<let N: u32>(input: [u8; N]) -> [BoundedVec<Field, 6>; 2] {"
+        ));
+        assert!(!code.contains("assert(substr0.len()"));
+        assert!(capture_bound_warnings(&regex_and_dfa).is_empty());
+
+        // A larger max_length is narrowed, a smaller one is kept and reported.
+        config.parts[3].max_length = Some(32);
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        let code = to_noir_fn(&regex_and_dfa, true, &NoirOptions::default());
+        assert!(code.contains("-> [BoundedVec<Field, 6>; 2] {"));
+        assert!(capture_bound_warnings(&regex_and_dfa).is_empty());
+        config.parts[1].max_length = Some(2);
+        config.parts[3].max_length = Some(3);
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        let code = to_noir_fn(&regex_and_dfa, true, &NoirOptions::default());
+        assert!(code.contains("-> [BoundedVec<Field, 3>; 2] {"));
+        assert!(code.contains("assert(substr0.len() < 2"));
+        assert_eq!(
+            capture_bound_warnings(&regex_and_dfa),
+            vec![
+                "substring 0 has max_length 2 but its captures can be 4 bytes long, so the longer \
+                 ones are rejected",
+                "substring 1 has max_length 3 but its captures can be 6 bytes long, so the longer \
+                 ones are rejected"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_numeric_captures() {
+        let mut config: DecomposedRegexConfig = serde_json::from_str(
+            r#"{"parts": [
+                {"is_public": true, "regex_def": "[a-z]+"},
+                {"is_public": false, "regex_def": ": "},
+                {"is_public": true, "regex_def": "[0-9]+", "numeric": true}
+            ]}"#,
+        )
+        .unwrap();
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        let options = NoirOptions::default();
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("pub fn numeric_captures<let N: u32>(substrs: [BoundedVec<Field, N>; 2]) -> [u64; 1] {\n    [capture_to_u64(substrs[1])]\n}"));
+        assert!(code.contains(
+            "pub fn capture_to_field<let N: u32>(capture: BoundedVec<Field, N>) -> Field {"
+        ));
+        assert!(!to_noir_fn(&regex_and_dfa, false, &options).contains("capture_to_u64"));
+
+        let options = NoirOptions {
+            split_modules: true,
+            ..Default::default()
+        };
+        let modules = to_noir_modules(&regex_and_dfa, true, &options);
+        assert!(modules[0].1.contains("use helpers::capture_to_u64;"));
+        assert!(modules[2].1.contains("pub fn capture_to_u64<let N: u32>"));
+    }
+
+    #[test]
+    fn test_numeric_capture_ranges() {
+        let mut config: DecomposedRegexConfig = serde_json::from_str(
+            r#"{"parts": [
+                {"is_public": false, "regex_def": "t="},
+                {"is_public": true, "regex_def": "[0-9]+", "numeric": true, "min": 1000000000, "max": 9999999999},
+                {"is_public": false, "regex_def": ";( n=[0-9]+)*"},
+                {"is_public": true, "regex_def": "[0-9]+", "numeric": true},
+                {"is_public": false, "regex_def": ";"},
+                {"is_public": true, "regex_def": "[0-9][0-9]", "numeric": true, "max_repetitions": 2, "min": 5}
+            ]}"#,
+        )
+        .unwrap();
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        let code = to_noir_fn(&regex_and_dfa, true, &NoirOptions::default());
+        assert!(code.contains(
+            "-> [u64; 4] {
+    let values = [
+        capture_to_u64(substrs[0]),
+        capture_to_u64(substrs[1]),
+        capture_to_u64(substrs[2]),
+        capture_to_u64(substrs[3]),
+    ];
+    assert(values[0] >= 1000000000, \"numeric capture 0 is below 1000000000\");
+    assert(values[0] <= 9999999999, \"numeric capture 0 is above 9999999999\");
+    assert((substrs[2].len() == 0) | (values[2] >= 5), \"numeric capture 2 is below 5\");
+    assert((substrs[3].len() == 0) | (values[3] >= 5), \"numeric capture 3 is below 5\");
+    values
+}"
+        ));
+
+        for (part, error) in [
+            (
+                r#"{"is_public": true, "regex_def": "[0-9]+", "max": 10}"#,
+                "min and max bound the values of numeric parts",
+            ),
+            (
+                r#"{"is_public": true, "regex_def": "[0-9]+", "numeric": true, "min": 10, "max": 9}"#,
+                "the min 10 of \"[0-9]+\" is greater than its max 9",
+            ),
+        ] {
+            let mut config: DecomposedRegexConfig =
+                serde_json::from_str(&format!(r#"{{"parts": [{}]}}"#, part)).unwrap();
+            let err = get_regex_and_dfa(&mut config).unwrap_err();
+            assert!(err.to_string().contains(error), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_fields() {
+        let config: FieldsConfig = serde_json::from_str(
+            r#"{"struct_name": "Transfer", "fields": [
+                {"name": "to", "pattern": "0x[0-9a-f]+", "max_length": 42},
+                {"name": "amount", "pattern": "[0-9]+", "numeric": true, "min": 1},
+                {"name": "token", "pattern": "[A-Z]+", "max_length": 8}
+            ]}"#,
+        )
+        .unwrap();
+        let regex_and_dfa = get_regex_and_dfa(&mut fields_to_decomposed(&config).unwrap()).unwrap();
+        let code = to_noir_fields_fn(&regex_and_dfa, &config, &NoirOptions::default()).unwrap();
+        assert!(code.contains(
+            "pub struct Transfer<let L: u32> {
+    pub to: BoundedVec<u8, L>,
+    pub amount: u64,
+    pub token: BoundedVec<u8, L>,
+}
+
+pub fn regex_match_fields<let N: u32>(input: [u8; N]) -> Transfer<N> {
+    let substrs = regex_match(input);
+    let numbers = numeric_captures(substrs);
+    Transfer {
+        to: capture_to_bytes(substrs[0]),
+        amount: numbers[0],
+        token: capture_to_bytes(substrs[2]),
+    }
+}
+"
+        ));
+        assert!(code.contains("assert(values[0] >= 1, \"numeric capture 1 is below 1\");"));
+
+        let config: FieldsConfig = serde_json::from_str(
+            r#"{"format": "json", "fields": [{"name": "n", "pattern": "[0-9]+", "numeric": true}]}"#,
+        )
+        .unwrap();
+        let regex_and_dfa = get_regex_and_dfa(&mut fields_to_decomposed(&config).unwrap()).unwrap();
+        let code = to_noir_fields_fn(&regex_and_dfa, &config, &NoirOptions::default()).unwrap();
+        assert!(code.contains("pub struct Fields {\n    pub n: u64,\n}"));
+        assert!(code.contains("-> Fields {"));
+        let options = NoirOptions {
+            capture_starts: true,
+            ..Default::default()
+        };
+        assert!(to_noir_fields_fn(&regex_and_dfa, &config, &options).is_err());
+    }
+
+    #[test]
+    fn test_hole_commitments() {
+        let mut config: DecomposedRegexConfig = serde_json::from_str(
+            r#"{"parts": [
+                {"is_public": false, "regex_def": "to: "},
+                {"is_public": true, "regex_def": "[^;]+", "hole": true},
+                {"is_public": false, "regex_def": "; id: "},
+                {"is_public": true, "regex_def": "[0-9]+"},
+                {"is_public": false, "regex_def": "; note: "},
+                {"is_public": true, "regex_def": "[a-z]*", "hole": true}
+            ]}"#,
+        )
+        .unwrap();
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        let options = NoirOptions::default();
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains(
+            "pub fn hole_commitments<let N: u32>(substrs: [BoundedVec<Field, N>; 3]) -> [Field; 2] {\n    [\n        std::hash::poseidon2::Poseidon2::hash(substrs[0].storage(), substrs[0].len()),\n        std::hash::poseidon2::Poseidon2::hash(substrs[2].storage(), substrs[2].len()),\n    ]\n}"
+        ));
+        assert!(!to_noir_fn(&regex_and_dfa, false, &options).contains("hole_commitments"));
+
+        let scratch = ScratchDir::new("zk_regex_noir_holes").unwrap();
+        let dir = scratch.path();
+        let (main_path, noir_path) = (dir.join("main.nr"), dir.join("form.nr"));
+        let main = NoirMainOptions {
+            path: main_path.to_string_lossy().into_owned(),
+            input_len: 64,
+            outputs: vec![MainOutput::Holes],
+        };
+        gen_noir_main(
+            &regex_and_dfa,
+            &main_path,
+            &noir_path,
+            true,
+            &options,
+            &main,
+        )
+        .unwrap();
+        let code = std::fs::read_to_string(&main_path).unwrap();
+        assert!(code.contains(
+            "fn main(input: [u8; 64]) -> pub [Field; 2] {\n    let captures = form::regex_match(input);\n    let holes = form::hole_commitments(captures);\n    holes\n}"
+        ));
+        assert!(gen_noir_main(
+            &regex_and_dfa,
+            &main_path,
+            &noir_path,
+            false,
+            &options,
+            &main
+        )
+        .is_err());
+
+        // A hole is a capture, so it must be public.
+        config.parts[1].is_public = false;
+        assert!(get_regex_and_dfa(&mut config).is_err());
+    }
+
+    #[test]
+    fn test_lowercase_captures() {
+        let regex_and_dfa = raw("a[a-zA-Z]+;", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            lowercase_captures: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("substr0.push(to_ascii_lowercase(byte) as Field);"));
+        assert!(code.contains("pub fn to_ascii_lowercase(byte: u8) -> u8 {"));
+        assert!(!to_noir_fn(&regex_and_dfa, false, &options).contains("to_ascii_lowercase"));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::U16,
+            lowercase_captures: true,
+            split_modules: true,
+            ..Default::default()
+        };
+        let modules = to_noir_modules(&regex_and_dfa, true, &options);
+        assert!(modules[0].1.contains("use helpers::to_ascii_lowercase;"));
+        assert!(modules[2]
+            .1
+            .contains("pub fn to_ascii_lowercase(unit: u16) -> u16 {"));
+    }
+
+    #[test]
+    fn test_capture_starts() {
+        let regex_and_dfa = raw("a(b+)c", vec![vec![(1, 2), (2, 2)]]);
+        let options = NoirOptions {
+            capture_starts: true,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains(
+            "pub fn regex_match<let N: u32>(input: [u8; N]) -> ([BoundedVec<Field, N>; 1], [u32; 1]) {"
+        ));
+        assert!(code.contains("    let mut start0: u32 = 0;"));
+        assert!(code.contains(
+            "            if substr0.len() == 0 {\n                start0 = i;\n            }\n            substr0.push(byte as Field);"
+        ));
+        assert!(code.contains("            substr0 = BoundedVec::new();\n            start0 = 0;"));
+        assert!(code.contains("    ([substr0], [start0])\n}"));
+        assert!(!to_noir_fn(&regex_and_dfa, false, &options).contains("start0"));
+
+        let options = NoirOptions {
+            input_hash: Some(NoirInputHash::Sha256),
+            capture_digests: true,
+            ..options
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("    ([substr0], [start0], input_hash)\n}"));
+        assert!(code.contains("    let (substrs, _, input_hash) = regex_match(input);"));
+    }
+
+    #[test]
+    fn test_input_alphabet() {
+        let options = NoirOptions::default();
+        let regex_and_dfa = raw("a[bc]+", vec![vec![(1, 2), (2, 2)]]);
+        let automaton = build_automaton(&regex_and_dfa, &options, true);
+        assert_eq!(
+            alphabet_ranges(&automaton, NoirInputAlphabet::Declared),
+            vec![(0x61, 0x63)]
+        );
+        let regex_and_dfa = decomposed(&[("x=", false), ("[0-9]+", true), (";", false)]);
+        let automaton = build_automaton(&regex_and_dfa, &options, true);
+        assert!(automaton.tagged);
+        assert_eq!(
+            alphabet_ranges(&automaton, NoirInputAlphabet::Declared),
+            vec![(0x30, 0x39), (0x3b, 0x3b), (0x3d, 0x3d), (0x78, 0x78)]
+        );
+
+        let options = NoirOptions {
+            input_alphabet: Some(NoirInputAlphabet::Printable),
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains(
+            "            ((byte >= 9) & (byte <= 10)) | (byte == 13) | ((byte >= 32) & (byte <= 126)),\n            f\"input[{i}] is outside the alphabet\","
+        ));
+        let options = NoirOptions {
+            input_alphabet: Some(NoirInputAlphabet::Declared),
+            ..Default::default()
+        };
+        assert!(
+            to_noir_shared_fn(&[("x".to_string(), regex_and_dfa)], false, &options)
+                .contains("is outside the alphabet")
+        );
+    }
+
+    /// Runs an automaton without restarts on `input`, returning the state it ends in, or the
+    /// index of the first byte without a transition.
+    fn run_bytes(automaton: &NoirAutomaton, input: &str) -> Result<usize, usize> {
+        let mut s = automaton.init_state;
+        for (idx, byte) in input.bytes().enumerate() {
+            let next = automaton.transitions.iter().find(|((from, _), ranges)| {
+                *from == s
+                    && ranges
+                        .iter()
+                        .any(|&(min, max)| (min..=max).contains(&(byte as u32)))
+            });
+            s = next.map(|(&(_, to), _)| to).ok_or(idx)?;
+        }
+        Ok(s)
+    }
+
+    #[test]
+    fn test_denylist() {
+        let patterns = ["foo".to_string(), "ab+c".to_string()];
+        let dfas = patterns
+            .iter()
+            .map(|pattern| create_dfa_graph_from_regex(pattern).unwrap())
+            .collect_vec();
+        let automaton = build_denylist_automaton(&dfas).unwrap();
+        let violation = |input: &str| run_bytes(&automaton, input).err();
+        assert_eq!(violation(""), None);
+        assert_eq!(violation("fo abd fo o"), None);
+        assert_eq!(violation("xxfofoo"), Some(6));
+        assert_eq!(violation("aabbc"), Some(4));
+
+        let code = to_noir_denylist_fn(&patterns, &dfas, &NoirOptions::default()).unwrap();
+        assert!(code.contains("pub fn assert_no_match<let N: u32>(input: [u8; N]) {"));
+        assert!(code.contains("    // forbidden: ab+c\n"));
+        assert!(code.contains("fn next_state(s: Field, byte: u8) -> Field {"));
+
+        let dfas = [create_dfa_graph_from_regex("a*").unwrap()];
+        assert!(build_denylist_automaton(&dfas).is_err());
+    }
+
+    fn composed(regexes: &[&str]) -> Vec<ComposedPattern> {
+        regexes
+            .iter()
+            .map(|regex| {
+                let (start_anchored, body, end_anchored) = split_anchors(regex);
+                ComposedPattern {
+                    dfa: create_dfa_graph_from_regex(body).unwrap(),
+                    start_anchored,
+                    end_anchored,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compositions() {
+        let patterns = composed(&["^from:[a-z]+", "urgent", "[0-9]+$"]);
+        let (automaton, labels) = build_composed_automaton(&patterns, false, |satisfied| {
+            satisfied.iter().all(|&sat| sat).then_some(0)
+        })
+        .unwrap();
+        let matches = |input: &str| {
+            run_bytes(&automaton, input).is_ok_and(|s| automaton.accept_states.contains(&s))
+        };
+        assert!(matches("from:bob urgent 12"));
+        assert!(matches("from:urgent 7"));
+        assert!(!matches("from:bob 12"));
+        assert!(!matches("x from:bob urgent 12"));
+        assert!(!matches("from:bob urgent 12x"));
+        assert!(labels.values().all(|&label| label == 0));
+        // Prefixes that can no longer match have no transition.
+        assert_eq!(run_bytes(&automaton, "x"), Err(0));
+
+        let regexes = ["@a\\.com", "@b\\.com$", "@c\\.com"];
+        let patterns = composed(&regexes);
+        let code = to_noir_composed_fn(
+            PatternComposition::Any,
+            &regexes.map(String::from),
+            &patterns,
+            &NoirOptions::default(),
+        )
+        .unwrap();
+        assert!(code.contains("pub fn regex_match<let N: u32>(input: [u8; N]) -> u32 {"));
+        assert!(code.contains("        index = 2;"));
+        let (automaton, labels) = build_composed_automaton(&patterns, false, |satisfied| {
+            satisfied.iter().position(|&sat| sat)
+        })
+        .unwrap();
+        let index = |input: &str| {
+            run_bytes(&automaton, input)
+                .ok()
+                .and_then(|s| labels.get(&s).copied())
+        };
+        assert_eq!(index("x@a.com"), Some(0));
+        assert_eq!(index("x@b.com"), Some(1));
+        assert_eq!(index("x@b.com "), None);
+        assert_eq!(index("x@c.com, y@a.com"), Some(0));
+        assert_eq!(index("x@c.com, y@b.com"), Some(1));
+
+        let patterns = composed(&["^a$", "b"]);
+        assert!(build_composed_automaton(&patterns, false, |satisfied| {
+            satisfied.iter().all(|&sat| sat).then_some(0)
+        })
+        .is_err());
+
+        let patterns = composed(&["^[a-z]+@[a-z]+\\.com$", "spam"]);
+        let (automaton, _) = build_composed_automaton(&patterns, false, |satisfied| {
+            composition_label(PatternComposition::Difference, satisfied)
+        })
+        .unwrap();
+        let matches = |input: &str| {
+            run_bytes(&automaton, input).is_ok_and(|s| automaton.accept_states.contains(&s))
+        };
+        assert!(matches("bob@mail.com"));
+        assert!(matches("spa@mail.com"));
+        assert!(!matches("bob@spam.com"));
+        assert!(!matches("bob@mail.co"));
+        assert_eq!(run_bytes(&automaton, "spam@mail.com"), Err(3));
+
+        // A sequence requires the matches in order, without overlapping.
+        let regexes = ["^from:[a-z]+\r\n", "subject:[a-z ]+\r\n", "urgent"];
+        let patterns = composed(&regexes);
+        let (automaton, _) = build_composed_automaton(&patterns, true, |satisfied| {
+            composition_label(PatternComposition::Sequence, satisfied)
+        })
+        .unwrap();
+        let matches = |input: &str| {
+            run_bytes(&automaton, input).is_ok_and(|s| automaton.accept_states.contains(&s))
+        };
+        assert!(matches("from:bob\r\nsubject:hi\r\n\r\nurgent!"));
+        assert!(matches("from:bob\r\nto:amy\r\nsubject:an urgent\r\nurgent"));
+        assert!(!matches("from:bob\r\nurgent\r\nsubject:hi\r\n"));
+        assert!(!matches("from:bob\r\nsubject:urgent\r\n"));
+        let code = to_noir_composed_fn(
+            PatternComposition::Sequence,
+            &regexes.map(String::from),
+            &patterns,
+            &NoirOptions::default(),
+        )
+        .unwrap();
+        assert!(code.contains("    // first: ^from:[a-z]+\\r\\n\n    // then: subject:"));
+        // The first pattern ending early leaves the later ones the rest of the input.
+        let patterns = composed(&["a+", "ab"]);
+        let (automaton, _) = build_composed_automaton(&patterns, true, |satisfied| {
+            composition_label(PatternComposition::Sequence, satisfied)
+        })
+        .unwrap();
+        assert!(run_bytes(&automaton, "aab").is_ok_and(|s| automaton.accept_states.contains(&s)));
+        assert!(!run_bytes(&automaton, "ab").is_ok_and(|s| automaton.accept_states.contains(&s)));
+    }
+
+    /// Runs the generated matcher logic on `input`, returning the captures if it matches.
+    fn simulate(
+        automaton: &NoirAutomaton,
+        accept_sink: Option<usize>,
+        input: &[u8],
+    ) -> Option<Vec<Vec<u8>>> {
+        simulate_with(automaton, accept_sink, input, NoirCaptureOverflow::Fail, 1)
+    }
+
+    /// Like `simulate`, with the given policy for repeated parts matching too many times and
+    /// the given occurrence of the pattern to match.
+    fn simulate_with(
+        automaton: &NoirAutomaton,
+        accept_sink: Option<usize>,
+        input: &[u8],
+        capture_overflow: NoirCaptureOverflow,
+        occurrence: usize,
+    ) -> Option<Vec<Vec<u8>>> {
+        let units = input.iter().map(|&byte| byte as u32).collect_vec();
+        let captures = run_matcher(automaton, accept_sink, &units, capture_overflow, occurrence)?;
+        Some(
+            captures
+                .into_iter()
+                .map(|capture| capture.into_iter().map(|unit| unit as u8).collect())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_shared_automata_merge_common_structure() {
+        let date = "[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]";
+        let patterns = [
+            raw(
+                &format!("from: {date}"),
+                vec![vec![(6, 7), (7, 8), (8, 9), (9, 10)]],
+            ),
+            raw(&format!("to: {date}$"), vec![vec![(4, 5), (5, 6)]]),
+            raw("(a|b)+c", vec![vec![(0, 1), (1, 1)]]),
+        ];
+        let automata = patterns
+            .iter()
+            .map(|regex_and_dfa| {
+                (
+                    build_u8_automaton(regex_and_dfa),
+                    regex_and_dfa.has_end_anchor,
+                )
+            })
+            .collect_vec();
+        let shared = build_shared_automata(&automata, u8::MAX as u32);
+        let separate_states: usize = automata
+            .iter()
+            .map(|(automaton, _)| automaton.free_state_id + 1)
+            .sum();
+        assert!(shared[0].free_state_id < separate_states);
+
+        let inputs: Vec<&[u8]> = vec![
+            b"from: 2024-01-31",
+            b"xx from: 2024-01-31 yy",
+            b"to: 2024-01-31",
+            b"to: 2024-01-31 ",
+            b"to: to: 1999-12-01",
+            b"from: 2024-1-31",
+            b"abbac",
+            b"xaac",
+            b"",
+        ];
+        for ((automaton, has_end_anchor), view) in automata.iter().zip(&shared) {
+            let accept_sink = (!has_end_anchor).then_some(automaton.free_state_id);
+            for input in &inputs {
+                assert_eq!(
+                    simulate(automaton, accept_sink, input),
+                    simulate(view, None, input),
+                    "input {:?}",
+                    String::from_utf8_lossy(input)
+                );
+            }
+        }
+
+        let named = vec![
+            ("from".to_string(), patterns[0].clone()),
+            ("to".to_string(), patterns[1].clone()),
+        ];
+        let code = to_noir_shared_fn(&named, true, &NoirOptions::default());
+        assert!(code.contains("pub fn regex_match_from<let N: u32>"));
+        assert!(code.contains("pub fn regex_match_to<let N: u32>"));
+        assert_eq!(code.matches("fn next_state(").count(), 1);
+
+        let options = NoirOptions {
+            fused: true,
+            input_alphabet: Some(NoirInputAlphabet::Declared),
+            ..Default::default()
+        };
+        let code = to_noir_shared_fn(&named, false, &options);
+        let fused = &code[code.find("pub fn regex_match_all<let N: u32>").unwrap()..];
+        assert_eq!(fused.matches("for i in 0..N").count(), 1);
+        assert!(fused.contains("let mut s_from = "));
+        assert!(fused.contains("s_to = next_to;"));
+        assert_eq!(code.matches("fn next_state(").count(), 1);
+        assert_eq!(
+            intersect_ranges(&[(0, 10), (20, 30)], &[(5, 25), (28, 40)]),
+            vec![(5, 10), (20, 25), (28, 30)]
+        );
+    }
+
+    #[test]
+    fn test_empty_match_policy() {
+        let reject = NoirOptions {
+            empty_match: NoirEmptyMatch::Reject,
+            ..Default::default()
+        };
+        let cases: Vec<(&str, &[u8], bool, bool)> = vec![
+            // (regex, input, accepted with allow, accepted with reject)
+            ("a*", b"", true, false),
+            ("a*", b"bbb", true, false),
+            ("a*", b"baa", true, true),
+            ("(foo)?", b"xyz", true, false),
+            ("(foo)?", b"xfoo", true, true),
+            ("(ab)*", b"abx", true, true),
+            ("(ab)*", b"ax", true, false),
+            ("a*$", b"bbb", true, false),
+            ("a*$", b"baa", true, true),
+            ("ab", b"xab", true, true),
+            ("^a*", b"", true, false),
+            ("^a*", b"aab", true, true),
+        ];
+        for (regex, input, allowed, rejected) in cases {
+            let regex_and_dfa = raw(regex, vec![vec![]]);
+            let accept_sink = (!regex_and_dfa.has_end_anchor)
+                .then_some(build_u8_automaton(&regex_and_dfa).free_state_id);
+            for (options, expected) in [
+                (NoirOptions::default(), allowed),
+                (reject.clone(), rejected),
+            ] {
+                let automaton = build_automaton(&regex_and_dfa, &options, false);
+                let accept_sink = accept_sink.map(|_| automaton.free_state_id);
+                assert_eq!(
+                    simulate(&automaton, accept_sink, input).is_some(),
+                    expected,
+                    "{} on {:?} with {:?}",
+                    regex,
+                    String::from_utf8_lossy(input),
+                    options.empty_match
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_u16_keeps_substring_transitions() {
+        let regex_and_dfa = raw("a(é)b", vec![vec![(1, 2), (2, 3)]]);
+        let automaton = build_u16_automaton(&regex_and_dfa);
+        assert_eq!(automaton.substr_transitions.len(), 1);
+        let (from, to) = *automaton.substr_transitions[0].iter().next().unwrap();
+        assert_eq!(automaton.transitions[&(from, to)], vec![(0xE9, 0xE9)]);
+    }
+
+    #[test]
+    fn test_match_semantics() {
+        let greedy = NoirOptions::default();
+        let first_accept = NoirOptions {
+            match_semantics: NoirMatchSemantics::FirstAccept,
+            ..Default::default()
+        };
+        let longest = NoirOptions {
+            match_semantics: NoirMatchSemantics::Longest,
+            ..Default::default()
+        };
+        let regex_and_dfa = decomposed(&[("a", false), ("b+", true)]);
+        for (options, capture) in [(&greedy, "bbb"), (&first_accept, "b"), (&longest, "bbb")] {
+            assert_eq!(
+                simulate_matcher(&regex_and_dfa, true, options, b"xabbbc"),
+                Some(vec![capture.as_bytes().to_vec()])
+            );
+        }
+        assert_eq!(lost_match_warning(&regex_and_dfa, &greedy), None);
+        assert_eq!(cut_match_warning(&regex_and_dfa, &greedy), None);
+        assert!(cut_match_warning(&regex_and_dfa, &first_accept)
+            .unwrap()
+            .starts_with("the match of \"abb\" ends at \"ab\""));
+
+        let regex_and_dfa = raw("ab|abcd", vec![]);
+        assert_eq!(
+            simulate_matcher(&regex_and_dfa, false, &greedy, b"abc"),
+            None
+        );
+        assert_eq!(
+            simulate_matcher(&regex_and_dfa, false, &first_accept, b"abc"),
+            Some(vec![])
+        );
+        assert!(lost_match_warning(&regex_and_dfa, &greedy)
+            .unwrap()
+            .starts_with("\"abc\" is rejected"));
+        assert_eq!(lost_match_warning(&regex_and_dfa, &first_accept), None);
+        let automaton = build_automaton(&regex_and_dfa, &first_accept, false);
+        assert_eq!(automaton.transitions.len(), 2);
+
+        // The first-accept semantics keep the shorter match when the optional group is
+        // incomplete.
+        let regex_and_dfa = create_regex_and_dfa_from_groups("ab(cd)?").unwrap();
+        for input in [&b"abc"[..], b"abcx", b"xabcd"] {
+            assert_eq!(
+                simulate_matcher(&regex_and_dfa, true, &first_accept, input),
+                Some(vec![vec![]]),
+                "{:?}",
+                String::from_utf8_lossy(input)
+            );
+        }
+        assert_eq!(
+            simulate_matcher(&regex_and_dfa, true, &greedy, b"abc"),
+            None
+        );
+        assert_eq!(
+            simulate_matcher(&regex_and_dfa, true, &greedy, b"abcd"),
+            Some(vec![b"cd".to_vec()])
+        );
+
+        // The longest match semantics keep the last accepting state when an extension fails,
+        // but cannot reveal the captures of an extension that may be abandoned.
+        let raw_optional = raw("ab(cd)?", vec![]);
+        for (input, accepted) in [
+            (&b"abc"[..], true),
+            (b"abcx", true),
+            (b"abcd", true),
+            (b"xabcdab", true),
+            (b"acd", false),
+            (b"xa", false),
+        ] {
+            assert_eq!(
+                simulate_matcher(&raw_optional, false, &longest, input).is_some(),
+                accepted,
+                "{:?}",
+                String::from_utf8_lossy(input)
+            );
+        }
+        assert_eq!(lost_match_warning(&raw_optional, &longest), None);
+        assert!(validate_noir_matcher(&raw_optional, true, &longest).is_ok());
+        let err = validate_noir_matcher(&regex_and_dfa, true, &longest).unwrap_err();
+        assert!(err.to_string().contains("past an accepting state"));
+        assert!(validate_noir_matcher(&regex_and_dfa, false, &longest).is_ok());
+        let regex_and_dfa = raw("ab|abcd", vec![]);
+        assert_eq!(
+            simulate_matcher(&regex_and_dfa, false, &longest, b"abc"),
+            Some(vec![])
+        );
+        let err = validate_noir_options(
+            &regex_and_dfa,
+            &NoirOptions {
+                occurrence: Some(2),
+                ..longest.clone()
+            },
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("longest match semantics"));
+
+        // End-anchored and reversed matches always end at the end of the input.
+        for options in [
+            first_accept.clone(),
+            NoirOptions {
+                reverse: true,
+                ..first_accept.clone()
+            },
+        ] {
+            let regex_and_dfa = raw("ab+$", vec![]);
+            assert!(validate_noir_options(&regex_and_dfa, &options).is_ok());
+            assert_eq!(cut_match_warning(&regex_and_dfa, &options), None);
+            assert_eq!(
+                build_automaton(&regex_and_dfa, &options, false).transitions,
+                build_automaton(
+                    &regex_and_dfa,
+                    &NoirOptions {
+                        match_semantics: NoirMatchSemantics::Greedy,
+                        ..options.clone()
+                    },
+                    false
+                )
+                .transitions
+            );
+        }
+        let regex_and_dfa = raw("ab+", vec![]);
+        let reverse = NoirOptions {
+            reverse: true,
+            ..first_accept.clone()
+        };
+        assert_eq!(
+            simulate_matcher(&regex_and_dfa, false, &reverse, b"xabbb"),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_tagged_captures_follow_the_branch_taken() {
+        let cases = vec![
+            (
+                decomposed(&[("x=", false), ("(ab|c)?", true), (";", false)]),
+                vec![
+                    ("x=ab;", "ab"),
+                    ("x=c;", "c"),
+                    ("x=;", ""),
+                    ("x=x=ab;", "ab"),
+                ],
+            ),
+            (
+                decomposed(&[("a", false), ("(bc|b)", true), ("c", false)]),
+                vec![("abc", "b"), ("abcc", "bc"), ("zzabcc", "bc")],
+            ),
+            (
+                decomposed(&[
+                    ("from:", false),
+                    ("([a-z ]+<)?", false),
+                    ("[a-z]+", true),
+                    (">?;", false),
+                ]),
+                vec![("from:bob <al>;", "al"), ("from:al;", "al")],
+            ),
+        ];
+        for (regex_and_dfa, inputs) in cases {
+            let automaton = build_automaton(&regex_and_dfa, &NoirOptions::default(), true);
+            assert!(automaton.tagged);
+            let accept_sink = Some(automaton.free_state_id);
+            for (input, expected) in inputs {
+                let captures = simulate(&automaton, accept_sink, input.as_bytes());
+                assert_eq!(
+                    captures,
+                    Some(vec![expected.as_bytes().to_vec()]),
+                    "input {}",
+                    input
+                );
+            }
+        }
+
+        let code = to_noir_fn(
+            &decomposed(&[("a", false), ("(bc|b)", true), ("c", false)]),
+            true,
+            &NoirOptions::default(),
+        );
+        assert!(code.contains("let tags = capture_tags(input);"));
+        assert!(code.contains("fn next_state(s: Field, unit: u16) -> Field"));
+    }
+
+    #[test]
+    fn test_capture_groups() {
+        let regex_and_dfa =
+            create_regex_and_dfa_from_groups(r"from:(?P<user>[a-z]+)@(([a-z]+)\.com)( ok)?")
+                .unwrap();
+        let automaton = build_automaton(&regex_and_dfa, &NoirOptions::default(), true);
+        let accept_sink = Some(automaton.free_state_id);
+        let captures = |input: &str| {
+            simulate(&automaton, accept_sink, input.as_bytes()).map(|captures| {
+                captures
+                    .into_iter()
+                    .map(|capture| String::from_utf8(capture).unwrap())
+                    .collect_vec()
+            })
+        };
+        assert_eq!(
+            captures("from:bob@mail.com ok"),
+            Some(vec![
+                "bob".to_string(),
+                "mail.com".to_string(),
+                "mail".to_string(),
+                " ok".to_string()
+            ])
+        );
+
+        let code = to_noir_fn(&regex_and_dfa, true, &NoirOptions::default());
+        assert!(code.starts_with(
+            "// Capture slots of the regex groups, numbered from 1 as in regex engines:\n\
+             //   [0]: group 1 (user)\n\
+             //   [1]: group 2\n"
+        ));
+        assert!(create_regex_and_dfa_from_groups("(?:ab)+").is_err());
+
+        // A start anchor holds at the start of the input only, so nothing is revealed past it.
+        let options = NoirOptions::default();
+        let regex_and_dfa = create_regex_and_dfa_from_groups("^a(b)c").unwrap();
+        let captures = |input: &[u8]| simulate_matcher(&regex_and_dfa, true, &options, input);
+        assert_eq!(captures(b"abc"), Some(vec![b"b".to_vec()]));
+        assert_eq!(captures(b"xabc"), None);
+        assert_eq!(captures(b"aabc"), None);
+        let regex_and_dfa = create_regex_and_dfa_from_groups("(?:;|^)id=([0-9]+)").unwrap();
+        let captures = |input: &[u8]| simulate_matcher(&regex_and_dfa, true, &options, input);
+        assert_eq!(captures(b"id=12"), Some(vec![b"12".to_vec()]));
+        assert_eq!(captures(b"x;id=3"), Some(vec![b"3".to_vec()]));
+        assert_eq!(captures(b"xid=3"), None);
+    }
+
+    #[test]
+    fn test_repetition_counter() {
+        let no_defs = || SubstringDefinitionsJson {
+            transitions: vec![],
+            names: vec![],
+        };
+        let run = |regex_and_dfa: &RegexAndDFA, input: &str| {
+            let automaton = build_automaton(regex_and_dfa, &NoirOptions::default(), true);
+            let accept_sink = (!regex_and_dfa.has_end_anchor).then_some(automaton.free_state_id);
+            simulate(&automaton, accept_sink, input.as_bytes())
+        };
+        let cases: [(&str, Vec<String>); 4] = [
+            (
+                "id:([a-z]{3,50});",
+                vec![
+                    "id:ab;".to_string(),
+                    "id:abc;".to_string(),
+                    format!("id:{};", "x".repeat(50)),
+                    format!("id:{};", "x".repeat(51)),
+                    format!("xxid:{};yy", "q".repeat(20)),
+                    "id:id:abcd;".to_string(),
+                    "id:ab;id:abcd;".to_string(),
+                    "id:abcd".to_string(),
+                ],
+            ),
+            (
+                "^[a-z]{2,40}$",
+                vec![
+                    "a".to_string(),
+                    "ab".to_string(),
+                    "x".repeat(40),
+                    "x".repeat(41),
+                    "ab1".to_string(),
+                ],
+            ),
+            (
+                "x[a-z]{3,40}",
+                vec![
+                    "xab".to_string(),
+                    "xabc".to_string(),
+                    format!("x{}", "y".repeat(60)),
+                    "1xab2xabcd".to_string(),
+                ],
+            ),
+            (
+                "a[0-9]{0,30}b",
+                vec![
+                    "ab".to_string(),
+                    "a12b".to_string(),
+                    format!("a{}b", "7".repeat(30)),
+                    format!("a{}b", "7".repeat(31)),
+                    format!("a{}bab", "7".repeat(31)),
+                ],
+            ),
+        ];
+        for (regex, inputs) in cases {
+            let groups = regex.contains('(');
+            let counted = create_counted_regex_and_dfa(regex, 8, groups, no_defs()).unwrap();
+            let unrolled = match groups {
+                true => create_regex_and_dfa_from_groups(regex).unwrap(),
+                false => raw(regex, vec![]),
+            };
+            assert_eq!(counted.counters.len(), 1, "{}", regex);
+            assert!(counted.dfa.states.len() < unrolled.dfa.states.len());
+            for input in inputs {
+                assert_eq!(
+                    run(&counted, &input),
+                    run(&unrolled, &input),
+                    "{} on {:?}",
+                    regex,
+                    input
+                );
+            }
+        }
+
+        // Short repetitions are left to the DFA.
+        let short = create_counted_regex_and_dfa("id:([a-z]{3,5});", 8, true, no_defs()).unwrap();
+        assert!(short.counters.is_empty());
+
+        // The bytes after the repetition would be counted as part of it.
+        assert!(create_counted_regex_and_dfa("[a-z]{1,50}z", 8, false, no_defs()).is_err());
+
+        let counted =
+            create_counted_regex_and_dfa("id:([a-z]{3,50});", 8, true, no_defs()).unwrap();
+        let code = to_noir_fn(&counted, true, &NoirOptions::default());
+        assert!(code.contains("let mut reps0: u32 = 0;"));
+        assert!(code.contains("matched fewer than 3 times"));
+    }
+
+    #[test]
+    fn test_optional_part_keeps_its_capture_slot() {
+        let regex_and_dfa = decomposed(&[
+            ("a=", false),
+            ("(foo)?", true),
+            (";", false),
+            ("[0-9]+", true),
+            ("\\.", false),
+        ]);
+        for input_type in [NoirInputType::U8, NoirInputType::U16] {
+            let options = NoirOptions {
+                input_type,
+                ..Default::default()
+            };
+            let automaton = build_automaton(&regex_and_dfa, &options, true);
+            let accept_sink = Some(automaton.free_state_id);
+            let captures = |input: &str| simulate(&automaton, accept_sink, input.as_bytes());
+            assert_eq!(
+                captures("a=foo;12."),
+                Some(vec![b"foo".to_vec(), b"12".to_vec()])
+            );
+            assert_eq!(captures("a=;12."), Some(vec![vec![], b"12".to_vec()]));
+        }
+        let code = to_noir_fn(&regex_and_dfa, true, &NoirOptions::default());
+        assert!(code.contains("[substr0, substr1]"));
+    }
+
+    #[test]
+    fn test_repeated_part_captures_each_repetition() {
+        let mut config = DecomposedRegexConfig {
+            parts: vec![
+                RegexPartConfig {
+                    is_public: false,
+                    regex_def: "n=".to_string(),
+                    max_repetitions: None,
+                    max_length: None,
+                    numeric: false,
+                    hole: false,
+                    min: None,
+                    max: None,
+                },
+                RegexPartConfig {
+                    is_public: true,
+                    regex_def: "[0-9]+,".to_string(),
+                    max_repetitions: Some(3),
+                    max_length: None,
+                    numeric: false,
+                    hole: false,
+                    min: None,
+                    max: None,
+                },
+                RegexPartConfig {
+                    is_public: false,
+                    regex_def: ";".to_string(),
+                    max_repetitions: None,
+                    max_length: None,
+                    numeric: false,
+                    hole: false,
+                    min: None,
+                    max: None,
+                },
+            ]
+            .into(),
+        };
+        let regex_and_dfa = get_regex_and_dfa(&mut config).expect("failed to build dfa");
+        let automaton = build_automaton(&regex_and_dfa, &NoirOptions::default(), true);
+        assert_eq!(automaton.num_captures(), 3);
+        let accept_sink = Some(automaton.free_state_id);
+        let captures = |input: &str| {
+            simulate(&automaton, accept_sink, input.as_bytes()).map(|captures| {
+                captures
+                    .into_iter()
+                    .map(|capture| String::from_utf8(capture).unwrap())
+                    .collect_vec()
+            })
+        };
+        assert_eq!(
+            captures("n=12,3,;"),
+            Some(vec!["12,".to_string(), "3,".to_string(), "".to_string()])
+        );
+        assert_eq!(
+            captures("xn=1,2,3,;"),
+            Some(vec!["1,".to_string(), "2,".to_string(), "3,".to_string()])
+        );
+        assert_eq!(captures("n=1,2,3,4,;"), None);
+        let overflowing = |capture_overflow| {
+            simulate_with(&automaton, accept_sink, b"n=1,2,3,4,;", capture_overflow, 1)
+        };
+        assert_eq!(
+            overflowing(NoirCaptureOverflow::KeepFirst),
+            Some(vec![b"1,".to_vec(), b"2,".to_vec(), b"3,".to_vec()])
+        );
+        assert_eq!(
+            overflowing(NoirCaptureOverflow::KeepLast),
+            Some(vec![b"2,".to_vec(), b"3,".to_vec(), b"4,".to_vec()])
+        );
+
+        let code = to_noir_fn(&regex_and_dfa, true, &NoirOptions::default());
+        assert!(code.contains("-> [BoundedVec<Field, N>; 3]"));
+        assert!(code.contains("[substr0_0, substr0_1, substr0_2]"));
+        assert!(code.contains("repeats more than 3 times"));
+        let options = NoirOptions {
+            capture_overflow: NoirCaptureOverflow::KeepLast,
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(!code.contains("repeats more than 3 times"));
+        assert!(code.contains("substr0_0 = substr0_1;"));
+
+        let options = NoirOptions {
+            input_type: NoirInputType::U16,
+            ..Default::default()
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_occurrence_selects_a_later_match() {
+        let regex_and_dfa = decomposed(&[("Received: ", false), ("[a-z]+", true), (";", false)]);
+        let options = NoirOptions {
+            occurrence: Some(2),
+            ..Default::default()
+        };
+        let automaton = build_automaton(&regex_and_dfa, &options, true);
+        assert!(!automaton.tagged);
+        let accept_sink = Some(automaton.free_state_id);
+        let input = b"Received: ab;\nReceived: cd;\nReceived: ef;";
+        let captures = |occurrence: usize| {
+            simulate_with(
+                &automaton,
+                accept_sink,
+                input,
+                NoirCaptureOverflow::Fail,
+                occurrence,
+            )
+        };
+        assert_eq!(captures(1), Some(vec![b"ab".to_vec()]));
+        assert_eq!(captures(2), Some(vec![b"cd".to_vec()]));
+        assert_eq!(captures(3), Some(vec![b"ef".to_vec()]));
+        assert_eq!(captures(4), None);
+        let sample = sample_input(&automaton, 2, 0).unwrap();
+        assert_eq!(
+            sample
+                .into_iter()
+                .map(|unit| unit as u8 as char)
+                .collect::<String>(),
+            "Received: a; Received: a;"
+        );
+
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(code.contains("        if (s_next == 13) & (matches < 1) {"));
+        assert!(
+            code.contains("    assert(matches == 1, \"the pattern occurs fewer than 2 times\");")
+        );
+        assert!(!code.contains("capture_tags"));
+
+        let regex_and_dfa = raw("a(b+)c", vec![vec![(1, 2), (2, 2)]]);
+        let automaton = build_automaton(&regex_and_dfa, &options, true);
+        let accept_sink = Some(automaton.free_state_id);
+        assert_eq!(
+            simulate_with(
+                &automaton,
+                accept_sink,
+                b"abcxabbc",
+                NoirCaptureOverflow::Fail,
+                2
+            ),
+            Some(vec![b"bb".to_vec()])
+        );
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_ok());
+        let anchored = raw("a(b+)c$", vec![vec![(1, 2), (2, 2)]]);
+        assert!(validate_noir_options(&anchored, &options).is_err());
+        let options = NoirOptions {
+            occurrence: Some(0),
+            ..Default::default()
+        };
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+    }
+
+    #[test]
+    fn test_reverse_matches_the_last_occurrence() {
+        let regex_and_dfa = decomposed(&[("Received: ", false), ("[a-z]+", true), (";", false)]);
+        let options = NoirOptions {
+            reverse: true,
+            ..Default::default()
+        };
+        let automaton = build_automaton(&regex_and_dfa, &options, true);
+        assert!(!automaton.tagged);
+        let accept_sink = Some(automaton.free_state_id);
+        let reversed = |input: &[u8]| input.iter().rev().copied().collect_vec();
+        let captures = |input: &[u8]| {
+            simulate(&automaton, accept_sink, &reversed(input)).map(|captures| {
+                captures
+                    .iter()
+                    .map(|capture| reversed(capture))
+                    .collect_vec()
+            })
+        };
+        assert_eq!(
+            captures(b"Received: ab;\nReceived: cd;"),
+            Some(vec![b"cd".to_vec()])
+        );
+        assert_eq!(
+            captures(b"Received: ab; Received: "),
+            Some(vec![b"ab".to_vec()])
+        );
+        assert_eq!(captures(b"Received: ab"), None);
+
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(!code.contains("capture_tags"));
+        assert!(code.contains("    for k in 0..N {\n        let i = N - 1 - k;"));
+        assert!(code.contains("    [reverse_capture(substr0)]"));
+        assert!(code.contains("pub fn reverse_capture<let N: u32>"));
+
+        let anchored = decomposed(&[("x=", false), ("[0-9]+", true), (";$", false)]);
+        let automaton = build_automaton(&anchored, &options, true);
+        assert!(simulate(&automaton, Some(automaton.free_state_id), b";1=x;2=x").is_some());
+        assert!(simulate(&automaton, Some(automaton.free_state_id), b" ;1=x").is_none());
+
+        let options = NoirOptions {
+            reverse: true,
+            reverse_window: Some(32),
+            ..Default::default()
+        };
+        let code = to_noir_fn(&regex_and_dfa, true, &options);
+        assert!(
+            code.contains("    assert(N >= 32, \"the input is shorter than the scanned suffix\");")
+        );
+        assert!(code.contains("    for k in 0..32 {"));
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_ok());
+        let automaton = build_automaton(&regex_and_dfa, &options, true);
+        assert_eq!(sample_input(&automaton, 1, 32).unwrap().len(), 32);
+
+        let regex_and_dfa = decomposed(&[("^x=", false), ("[0-9]+", true)]);
+        assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+        // A raw regex is only refused when its substrings are extracted.
+        let regex_and_dfa = raw("a(b+)c", vec![vec![(1, 2), (2, 2)]]);
+        let err = validate_noir_matcher(&regex_and_dfa, true, &options).unwrap_err();
+        assert!(err.to_string().contains("requires a decomposed regex"));
+        assert!(validate_noir_matcher(&regex_and_dfa, false, &options).is_ok());
+        let regex_and_dfa = raw("a(b+)c", vec![vec![]]);
+        assert!(validate_noir_matcher(&regex_and_dfa, true, &options).is_ok());
+        for options in [
+            NoirOptions {
+                reverse_window: Some(32),
+                ..Default::default()
+            },
+            NoirOptions {
+                reverse: true,
+                reverse_window: Some(0),
+                ..Default::default()
+            },
+            NoirOptions {
+                reverse: true,
+                input_type: NoirInputType::U16,
+                ..Default::default()
+            },
+        ] {
+            assert!(validate_noir_options(&regex_and_dfa, &options).is_err());
+        }
+    }
+
+    #[test]
+    fn test_anchors_in_alternations() {
+        let regex_and_dfa = decomposed(&[
+            ("(^Subject:|^From:) ", false),
+            ("[a-z]+", true),
+            ("\r\n", false),
+        ]);
+        let automaton = build_automaton(&regex_and_dfa, &NoirOptions::default(), true);
+        let captures = |input: &[u8]| simulate(&automaton, Some(automaton.free_state_id), input);
+        assert_eq!(captures(b"From: ab\r\n"), Some(vec![b"ab".to_vec()]));
+        assert_eq!(captures(b"Subject: cd\r\nx"), Some(vec![b"cd".to_vec()]));
+        assert_eq!(captures(b"x From: ab\r\n"), None);
+        assert_eq!(captures(b"\r\nSubject: cd\r\n"), None);
+        // The start anchor is the initial state, not a byte fed to next_state.
+        assert_ne!(automaton.init_state, automaton.start_state);
+        assert!(automaton
+            .transitions
+            .values()
+            .flatten()
+            .all(|&(min, max)| (min..=max).all(|unit| unit % 256 != PRIMING_BYTE as u32)));
+        assert_eq!(captures(b"x\xffFrom: ab\r\n"), None);
+        let code = to_noir_fn(&regex_and_dfa, true, &NoirOptions::default());
+        assert!(code.contains(&format!("    let mut s = {};\n", automaton.init_state)));
+        assert!(!code.contains("255"));
+
+        let regex_and_dfa =
+            decomposed(&[("((^a)|b)", false), ("[0-9]+", true), ("(;$|,$)", false)]);
+        assert!(regex_and_dfa.has_end_anchor);
+        let automaton = build_automaton(&regex_and_dfa, &NoirOptions::default(), true);
+        let captures = |input: &[u8]| simulate(&automaton, None, input);
+        assert_eq!(captures(b"a12;"), Some(vec![b"12".to_vec()]));
+        assert_eq!(captures(b"xb3,"), Some(vec![b"3".to_vec()]));
+        assert_eq!(captures(b"xa3,"), None);
+        assert_eq!(captures(b"b3;x"), None);
+
+        let regex_and_dfa = decomposed(&[("[a^]=", false), ("[0-9]+", true), ("\\$", false)]);
+        assert!(!regex_and_dfa.has_end_anchor);
+        assert_eq!(regex_and_dfa.parts.len(), 3);
+        assert!(!raw("(com|org)\\$", vec![]).has_end_anchor);
+        assert!(raw("(com$|org$)", vec![]).has_end_anchor);
+
+        let mut config = DecomposedRegexConfig {
+            parts: VecDeque::from([RegexPartConfig {
+                is_public: false,
+                regex_def: "(;$|,)".to_string(),
+                max_repetitions: None,
+                max_length: None,
+                numeric: false,
+                hole: false,
+                min: None,
+                max: None,
+            }]),
+        };
+        assert!(get_regex_and_dfa(&mut config).is_err());
+    }
+
+    #[test]
+    fn test_raw_anchors_in_alternations() {
+        let options = NoirOptions::default();
+        let matches = |regex_and_dfa: &RegexAndDFA, input: &[u8]| {
+            simulate_matcher(regex_and_dfa, false, &options, input).is_some()
+        };
+        let regex_and_dfa = raw("(^Subject:|^From:) ([a-z]+)", vec![]);
+        assert!(matches(&regex_and_dfa, b"From: x"));
+        assert!(matches(&regex_and_dfa, b"Subject: ab"));
+        assert!(!matches(&regex_and_dfa, b"xFrom: x"));
+        assert!(!matches(&regex_and_dfa, b"Subject:From: x"));
+
+        let regex_and_dfa = raw(r"(\r\n|^)from:([a-z]+)\r\n", vec![]);
+        assert!(matches(&regex_and_dfa, b"from:ab\r\n"));
+        assert!(matches(&regex_and_dfa, b"to:cd\r\nfrom:ab\r\n"));
+        assert!(!matches(&regex_and_dfa, b"xfrom:ab\r\n"));
+        assert!(!matches(&regex_and_dfa, b"to:cd\nfrom:ab\r\n"));
+        let code = to_noir_fn(&regex_and_dfa, false, &options);
+        let automaton = build_automaton(&regex_and_dfa, &options, false);
+        assert_ne!(automaton.init_state, automaton.start_state);
+        assert!(code.contains(&format!("    let mut s = {};\n", automaton.init_state)));
+        assert!(!code.contains("255"));
+    }
+
+    #[test]
+    fn test_raw_absolute_anchors() {
+        let options = NoirOptions::default();
+        let regex_and_dfa = raw(r"\Aabc", vec![]);
+        let matches =
+            |input: &[u8]| simulate_matcher(&regex_and_dfa, false, &options, input).is_some();
+        assert!(matches(b"abc"));
+        assert!(matches(b"abcx"));
+        assert!(!matches(b"xabc"));
+        assert!(!matches(b"ababc"));
+        // The matcher starts past the anchor and cannot restart before it.
+        let automaton = build_automaton(&regex_and_dfa, &options, false);
+        assert_ne!(automaton.init_state, automaton.start_state);
+        assert_eq!(
+            serde_json::to_string(&regex_and_dfa.dfa).unwrap(),
+            serde_json::to_string(&raw("^abc", vec![]).dfa).unwrap()
+        );
+
+        let regex_and_dfa = raw(r"\Aabc\z", vec![]);
+        assert!(regex_and_dfa.has_end_anchor);
+        assert!(simulate_matcher(&regex_and_dfa, false, &options, b"abc").is_some());
+        assert!(simulate_matcher(&regex_and_dfa, false, &options, b"xabc").is_none());
+        assert!(simulate_matcher(&regex_and_dfa, false, &options, b"abcx").is_none());
+    }
+
+    /// Runs `nargo` on generated code, see the `integration-tests` feature.
+    #[cfg(feature = "integration-tests")]
+    mod nargo {
+        use super::*;
+        use std::process::Command;
+
+        /// An input and the captures expected from it, or `None` if it must not match.
+        type Case<'a> = (&'a str, Option<Vec<&'a str>>);
+
+        /// Generates an example project for the regex, adds a Noir test checking the
+        /// captures of every case, and runs `nargo test` and `nargo execute` in it.
+        fn check(name: &str, regex_and_dfa: &RegexAndDFA, options: &NoirOptions, cases: &[Case]) {
+            let scratch = ScratchDir::new(&format!("zk_regex_nargo_{name}")).unwrap();
+            let dir = scratch.path();
+            gen_noir_example(regex_and_dfa, dir, true, options).unwrap();
+
+            let mut tests = vec![];
+            for (idx, (input, expected)) in cases.iter().enumerate() {
+                let bytes = input.bytes().join(", ");
+                match expected {
+                    Some(captures) => {
+                        tests.push(format!("#[test]\nfn test_case_{idx}() {{"));
+                        tests.push(format!("    let captures = regex::regex_match([{bytes}]);"));
+                        for (i, capture) in captures.iter().enumerate() {
+                            tests.push(format!(
+                                "    assert(captures[{i}].len() == {});",
+                                capture.len()
+                            ));
+                            for (j, byte) in capture.bytes().enumerate() {
+                                tests
+                                    .push(format!("    assert(captures[{i}].get({j}) == {byte});"));
+                            }
+                        }
+                    }
+                    None => {
+                        tests.push(format!("#[test(should_fail)]\nfn test_case_{idx}() {{"));
+                        tests.push(format!("    let _ = regex::regex_match([{bytes}]);"));
+                    }
+                }
+                tests.push("}\n".to_string());
+            }
+            let main_path = dir.join("src/main.nr");
+            let main = std::fs::read_to_string(&main_path).unwrap();
+            std::fs::write(&main_path, format!("{main}\n{}", tests.join("\n"))).unwrap();
+
+            for command in ["test", "execute"] {
+                let output = Command::new("nargo")
+                    .arg(command)
+                    .current_dir(dir)
+                    .output()
+                    .expect("failed to run nargo");
+                assert!(
+                    output.status.success(),
+                    "nargo {} failed for {}:\n{}",
+                    command,
+                    name,
+                    String::from_utf8_lossy(&output.stderr)
+                );
+            }
+        }
+
+        #[test]
+        fn test_strategies_match_and_capture() {
+            let regex_and_dfa = raw("a(b+)c", vec![vec![(1, 2), (2, 2)]]);
+            let cases = vec![
+                ("abc", Some(vec!["b"])),
+                ("xxabbbc", Some(vec!["bbb"])),
+                ("ac", None),
+            ];
+            let strategies = [
+                NoirStrategy::IfChain,
+                NoirStrategy::Table,
+                NoirStrategy::MuxTree,
+                NoirStrategy::Keyword,
+            ];
+            let state_types = [NoirStateType::Field, NoirStateType::Integer];
+            for (&strategy, &state_type) in strategies.iter().cartesian_product(&state_types) {
+                let options = NoirOptions {
+                    strategy,
+                    state_type,
+                    ..Default::default()
+                };
+                let name = format!("{:?}_{:?}", strategy, state_type);
+                check(&name, &regex_and_dfa, &options, &cases);
+            }
+        }
+
+        #[test]
+        fn test_optional_part_captures_empty() {
+            let regex_and_dfa = decomposed(&[
+                ("a=", false),
+                ("(foo)?", true),
+                (";", false),
+                ("[0-9]+", true),
+                ("\\.", false),
+            ]);
+            let cases = vec![
+                ("a=foo;12.", Some(vec!["foo", "12"])),
+                ("a=;12.", Some(vec!["", "12"])),
+                ("a=fo;12.", None),
+            ];
+            check(
+                "optional_part",
+                &regex_and_dfa,
+                &NoirOptions::default(),
+                &cases,
+            );
+        }
+
+        #[test]
+        fn test_capture_max_length() {
+            let mut config: DecomposedRegexConfig = serde_json::from_str(
+                r#"{"parts": [
+                    {"is_public": false, "regex_def": "id="},
+                    {"is_public": true, "regex_def": "[a-z]+", "max_length": 3},
+                    {"is_public": false, "regex_def": ";"}
+                ]}"#,
+            )
+            .unwrap();
+            let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+            let cases = vec![("id=abc;", Some(vec!["abc"])), ("id=abcd;", None)];
+            check(
+                "max_length",
+                &regex_and_dfa,
+                &NoirOptions::default(),
+                &cases,
+            );
+        }
+
+        #[test]
+        fn test_lowercase_captures() {
+            let regex_and_dfa = raw("from:[a-zA-Z]+;", vec![vec![(5, 6), (6, 6)]]);
+            let options = NoirOptions {
+                lowercase_captures: true,
+                ..Default::default()
+            };
+            let cases = vec![("from:AbC;", Some(vec!["abc"])), ("from:;", None)];
+            check("lowercase", &regex_and_dfa, &options, &cases);
+        }
+
+        #[test]
+        fn test_occurrence() {
+            let regex_and_dfa = decomposed(&[("to:", false), ("[a-z]+", true), (";", false)]);
+            let options = NoirOptions {
+                occurrence: Some(2),
+                ..Default::default()
+            };
+            let cases = vec![("to:ab; to:cd; to:ef;", Some(vec!["cd"])), ("to:ab;", None)];
+            check("occurrence", &regex_and_dfa, &options, &cases);
+        }
+
+        #[test]
+        fn test_reverse() {
+            let regex_and_dfa = decomposed(&[("to:", false), ("[a-z]+", true), (";", false)]);
+            let options = NoirOptions {
+                reverse: true,
+                ..Default::default()
+            };
+            let cases = vec![("to:ab; to:cd;", Some(vec!["cd"])), ("to:ab", None)];
+            check("reverse", &regex_and_dfa, &options, &cases);
+
+            let options = NoirOptions {
+                reverse: true,
+                reverse_window: Some(8),
+                capture_starts: true,
+                ..Default::default()
+            };
+            let cases = vec![("to:ab; to:cd;", Some(vec!["cd"])), ("to:ab; x;", None)];
+            check("reverse_window", &regex_and_dfa, &options, &cases);
+        }
+
+        #[test]
+        fn test_input_alphabet() {
+            let regex_and_dfa = raw("id=[0-9]+;", vec![vec![(3, 4), (4, 4)]]);
+            let options = NoirOptions {
+                input_alphabet: Some(NoirInputAlphabet::Declared),
+                ..Default::default()
+            };
+            let cases = vec![("id=42;", Some(vec!["42"])), ("x id=42;", None)];
+            check("declared_alphabet", &regex_and_dfa, &options, &cases);
+
+            let options = NoirOptions {
+                input_alphabet: Some(NoirInputAlphabet::Printable),
+                ..Default::default()
+            };
+            let cases = vec![("x id=42;", Some(vec!["42"])), ("\u{1}id=42;", None)];
+            check("printable_alphabet", &regex_and_dfa, &options, &cases);
+        }
+
+        #[test]
+        fn test_tagged_and_repeated_captures() {
+            let regex_and_dfa = decomposed(&[("a", false), ("(bc|b)", true), ("c", false)]);
+            let cases = vec![("abc", Some(vec!["b"])), ("abcc", Some(vec!["bc"]))];
+            check("tagged", &regex_and_dfa, &NoirOptions::default(), &cases);
+            let options = NoirOptions {
+                state_type: NoirStateType::Integer,
+                ..Default::default()
+            };
+            check("tagged_integer", &regex_and_dfa, &options, &cases);
+            let first_accept = NoirOptions {
+                match_semantics: NoirMatchSemantics::FirstAccept,
+                ..Default::default()
+            };
+            check(
+                "tagged_first_accept",
+                &regex_and_dfa,
+                &first_accept,
+                &[("abc", Some(vec!["b"])), ("abcc", Some(vec!["b"]))],
+            );
+
+            let mut config = DecomposedRegexConfig {
+                parts: vec![
+                    RegexPartConfig {
+                        is_public: false,
+                        regex_def: "n=".to_string(),
+                        max_repetitions: None,
+                        max_length: None,
+                        numeric: false,
+                        hole: false,
+                        min: None,
+                        max: None,
+                    },
+                    RegexPartConfig {
+                        is_public: true,
+                        regex_def: "[0-9]+,".to_string(),
+                        max_repetitions: Some(2),
+                        max_length: None,
+                        numeric: false,
+                        hole: false,
+                        min: None,
+                        max: None,
+                    },
+                    RegexPartConfig {
+                        is_public: false,
+                        regex_def: ";".to_string(),
+                        max_repetitions: None,
+                        max_length: None,
+                        numeric: false,
+                        hole: false,
+                        min: None,
+                        max: None,
+                    },
+                ]
+                .into(),
+            };
+            let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+            let cases = vec![("n=12,3,;", Some(vec!["12,", "3,"])), ("n=1,2,3,;", None)];
+            check("repeated", &regex_and_dfa, &NoirOptions::default(), &cases);
+        }
+
+        #[test]
+        fn test_empty_match_policy() {
+            let regex_and_dfa = raw("a*", vec![]);
+            let options = NoirOptions {
+                empty_match: NoirEmptyMatch::Reject,
+                ..Default::default()
+            };
+            check(
+                "reject_empty",
+                &regex_and_dfa,
+                &options,
+                &[("xa", Some(vec![])), ("x", None)],
+            );
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Ready-made patterns for common kinds of values.
+//!
+//! The patterns are raw regexes matching the whole value, without anchors, so they can be
+//! compiled on their own or embedded in larger patterns and decomposed regex parts.
+//!
+//! The Circom backend needs a single accepting state, which `URL`, `IPV4` and `DECIMAL_AMOUNT`
+//! only have when followed by a delimiter, e.g. `ip=<IPV4>;`.
+
+/// An `http` or `https` URL with a host name, an optional port, path and query, e.g.
+/// `https://example.com:8080/a/b?c=d`.
+pub const URL: &str = "https?://[a-zA-Z0-9\\-]+(\\.[a-zA-Z0-9\\-]+)*(:[0-9]+)?(/[a-zA-Z0-9\\-._~%!$&'()*+,;=:@/]*)?(\\?[a-zA-Z0-9\\-._~%!$&'()*+,;=:@/?]*)?";
+
+/// A dotted-decimal IPv4 address whose octets are between 0 and 255 without leading zeros,
+/// e.g. `192.168.0.1`.
+pub const IPV4: &str = "(25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9]?[0-9])(\\.(25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9]?[0-9])){3}";
+
+/// An ISO-8601 timestamp with a date, a time with optional fractional seconds and a `Z` or
+/// `±hh:mm` offset, e.g. `2024-02-29T13:45:00.123+09:00`.
+pub const ISO8601_TIMESTAMP: &str = "[0-9]{4}-(0[1-9]|1[0-2])-(0[1-9]|[12][0-9]|3[01])T([01][0-9]|2[0-3]):[0-5][0-9]:[0-5][0-9](\\.[0-9]+)?(Z|[+\\-]([01][0-9]|2[0-3]):[0-5][0-9])";
+
+/// An email address with a dot-separated domain, e.g. `alice.smith+tag@mail.example.com`.
+pub const EMAIL: &str = "[a-zA-Z0-9._%+\\-]+@[a-zA-Z0-9\\-]+(\\.[a-zA-Z0-9\\-]+)+";
+
+/// An optionally negative decimal amount without leading zeros or thousands separators, e.g.
+/// `-1250.75`.
+pub const DECIMAL_AMOUNT: &str = "\\-?(0|[1-9][0-9]*)(\\.[0-9]+)?";
+
+/// All the presets, as (name, pattern) tuples, under the names accepted by `--preset`.
+pub const PRESETS: &[(&str, &str)] = &[
+    ("url", URL),
+    ("ipv4", IPV4),
+    ("iso8601-timestamp", ISO8601_TIMESTAMP),
+    ("email", EMAIL),
+    ("decimal-amount", DECIMAL_AMOUNT),
+];
+
+/// Looks up a preset by name.
+///
+/// # Arguments
+///
+/// * `name` - The name of the preset, as listed in `PRESETS`.
+///
+/// # Returns
+///
+/// The pattern of the preset, or `None` if there is no preset with this name.
+pub fn get(name: &str) -> Option<&'static str> {
+    PRESETS
+        .iter()
+        .find(|(preset, _)| *preset == name)
+        .map(|(_, pattern)| *pattern)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::regex::{create_dfa_graph_from_regex, match_string_with_dfa_graph};
+
+    fn check(pattern: &str, pass: &[&str], fail: &[&str]) {
+        let dfa_graph = create_dfa_graph_from_regex(pattern).unwrap();
+        for input in pass {
+            assert!(match_string_with_dfa_graph(&dfa_graph, input), "{}", input);
+        }
+        for input in fail {
+            assert!(!match_string_with_dfa_graph(&dfa_graph, input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_presets() {
+        check(
+            URL,
+            &[
+                "https://example.com",
+                "http://localhost:8080/a/b.html",
+                "https://x.org/search?q=zk&page=2",
+            ],
+            &["ftp://example.com", "https://", "https://exa mple.com"],
+        );
+        check(
+            IPV4,
+            &["0.0.0.0", "192.168.0.1", "255.255.255.255"],
+            &["256.1.1.1", "01.2.3.4", "1.2.3", "1.2.3.4.5"],
+        );
+        check(
+            ISO8601_TIMESTAMP,
+            &[
+                "2024-02-29T13:45:00Z",
+                "1999-12-31T23:59:59.999+09:00",
+                "2000-01-01T00:00:00-05:30",
+            ],
+            &[
+                "2024-13-01T00:00:00Z",
+                "2024-01-01T24:00:00Z",
+                "2024-01-01 00:00:00Z",
+                "2024-01-01T00:00:00",
+            ],
+        );
+        check(
+            EMAIL,
+            &["alice@example.com", "alice.smith+tag@mail.example.co.uk"],
+            &["alice@localhost", "@example.com", "alice@@example.com"],
+        );
+        check(
+            DECIMAL_AMOUNT,
+            &["0", "1250.75", "-3", "0.5"],
+            &["007", "1,250", "1.", ".5", "--1"],
+        );
+        assert_eq!(get("ipv4"), Some(IPV4));
+        assert_eq!(get("ipv6"), None);
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{debug_span, trace};
+
+/// The time spent in each phase so far, in the order the phases were first entered, or
+/// `None` while profiling is disabled.
+static PHASES: Mutex<Option<Vec<(&'static str, Duration)>>> = Mutex::new(None);
+
+/// A summary of where the compiler spent its time.
+#[derive(Debug, Clone)]
+pub struct ProfileReport {
+    /// The time spent in each phase, in the order the phases were first entered.
+    pub phases: Vec<(String, Duration)>,
+    /// The peak resident memory of the process in kilobytes, if the platform reports it.
+    pub peak_memory_kb: Option<u64>,
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total: Duration = self.phases.iter().map(|(_, time)| *time).sum();
+        let width = self
+            .phases
+            .iter()
+            .map(|(phase, _)| phase.len())
+            .chain(["peak memory".len()])
+            .max()
+            .unwrap_or(0);
+        for (phase, time) in &self.phases {
+            writeln!(f, "{:width$}  {:>10.1} ms", phase, time.as_secs_f64() * 1e3)?;
+        }
+        writeln!(
+            f,
+            "{:width$}  {:>10.1} ms",
+            "total",
+            total.as_secs_f64() * 1e3
+        )?;
+        match self.peak_memory_kb {
+            Some(kb) => write!(
+                f,
+                "{:width$}  {:>10.1} MB",
+                "peak memory",
+                kb as f64 / 1024.0
+            ),
+            None => write!(f, "{:width$}  {:>13}", "peak memory", "unavailable"),
+        }
+    }
+}
+
+/// Starts recording the time spent in each phase of the compiler, discarding earlier records.
+pub fn start_profiling() {
+    *PHASES.lock().unwrap() = Some(vec![]);
+}
+
+/// Stops recording and returns the report, or `None` if profiling was not started.
+pub fn finish_profiling() -> Option<ProfileReport> {
+    let phases = PHASES.lock().unwrap().take()?;
+    Some(ProfileReport {
+        phases: phases
+            .into_iter()
+            .map(|(phase, time)| (phase.to_string(), time))
+            .collect(),
+        peak_memory_kb: peak_memory_kb(),
+    })
+}
+
+/// Returns whether profiling is enabled.
+pub(crate) fn is_profiling() -> bool {
+    PHASES.lock().unwrap().is_some()
+}
+
+/// Adds `time` to the time spent in `phase`, if profiling is enabled.
+pub(crate) fn record(phase: &'static str, time: Duration) {
+    if let Some(phases) = PHASES.lock().unwrap().as_mut() {
+        accumulate(phases, phase, time);
+    }
+}
+
+/// Adds `time` to the entry of `phase`, appending a new entry the first time it is seen.
+fn accumulate(phases: &mut Vec<(&'static str, Duration)>, phase: &'static str, time: Duration) {
+    match phases.iter_mut().find(|(name, _)| *name == phase) {
+        Some((_, total)) => *total += time,
+        None => phases.push((phase, time)),
+    }
+}
+
+/// Returns the current time, or `None` on `wasm32-unknown-unknown`, which has no clock and
+/// where `Instant::now` panics.
+pub(crate) fn now() -> Option<Instant> {
+    match cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        true => None,
+        false => Some(Instant::now()),
+    }
+}
+
+/// Runs `f` and returns its result along with the time it took, zero without a clock.
+pub(crate) fn measure<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = now();
+    let result = f();
+    (
+        result,
+        start.map_or(Duration::ZERO, |start| start.elapsed()),
+    )
+}
+
+/// Runs `f` in a `phase` span, adding the time it took to `phase` if profiling is enabled.
+pub(crate) fn timed<T>(phase: &'static str, f: impl FnOnce() -> T) -> T {
+    let (result, time) = debug_span!("phase", phase).in_scope(|| measure(f));
+    trace!(
+        phase,
+        elapsed_ms = time.as_secs_f64() * 1e3,
+        "phase finished"
+    );
+    record(phase, time);
+    result
+}
+
+/// Reads the peak resident memory of the process from `/proc/self/status`.
+fn peak_memory_kb() -> Option<u64> {
+    read_peak_memory_kb("/proc/self/status")
+}
+
+/// Reads the peak resident memory so far of a running child process from `/proc`.
+#[cfg(feature = "process")]
+pub(crate) fn child_peak_memory_kb(pid: u32) -> Option<u64> {
+    read_peak_memory_kb(&format!("/proc/{}/status", pid))
+}
+
+/// Reads the `VmHWM` line of a `/proc/<pid>/status` file, in kilobytes.
+fn read_peak_memory_kb(path: &str) -> Option<u64> {
+    let status = std::fs::read_to_string(path).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_phases_accumulate_in_order() {
+        let mut phases = vec![];
+        accumulate(&mut phases, "parsing", Duration::from_millis(2));
+        accumulate(&mut phases, "codegen", Duration::from_millis(5));
+        accumulate(&mut phases, "parsing", Duration::from_millis(3));
+        assert_eq!(
+            phases,
+            vec![
+                ("parsing", Duration::from_millis(5)),
+                ("codegen", Duration::from_millis(5)),
+            ]
+        );
+
+        let report = ProfileReport {
+            phases: phases
+                .into_iter()
+                .map(|(phase, time)| (phase.to_string(), time))
+                .collect(),
+            peak_memory_kb: Some(2048),
+        };
+        let lines = report
+            .to_string()
+            .lines()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[2], "total              10.0 ms");
+        assert_eq!(lines[3], "peak memory         2.0 MB");
+    }
+}
@@ -0,0 +1,519 @@
+use crate::profile::now;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "process")]
+use std::fs::{create_dir_all, remove_dir_all};
+#[cfg(feature = "fs")]
+use std::{
+    fs::{remove_file, rename, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+#[cfg(feature = "fs")]
+use tracing::debug;
+
+/// The time between two progress lines of a task, or `None` while progress reporting is
+/// disabled.
+static INTERVAL: Mutex<Option<Duration>> = Mutex::new(None);
+
+/// The size of the chunks the outputs are written in, so that large files report their
+/// progress.
+#[cfg(feature = "fs")]
+const WRITE_CHUNK: usize = 1 << 20;
+
+/// The number of bytes an output file buffers before comparing them with the existing file or
+/// writing them.
+#[cfg(feature = "fs")]
+const BUFFER_SIZE: usize = 1 << 16;
+
+/// Starts reporting the progress of long tasks on stderr, printing a line per task at most
+/// every `interval`. It does nothing on targets without a clock.
+pub fn start_progress(interval: Duration) {
+    if now().is_some() {
+        *INTERVAL.lock().unwrap() = Some(interval);
+    }
+}
+
+/// Returns the time between two progress lines, or `None` if progress reporting is disabled.
+fn interval() -> Option<Duration> {
+    *INTERVAL.lock().unwrap()
+}
+
+/// Formats a progress line of a task.
+///
+/// # Arguments
+///
+/// * `task` - What the task does.
+/// * `done` - The number of items produced so far.
+/// * `total` - The number of items the task produces, if known.
+/// * `unit` - The name of the items.
+/// * `elapsed` - The time since the task started.
+///
+/// # Returns
+///
+/// The line, without its line break.
+fn progress_line(
+    task: &str,
+    done: usize,
+    total: Option<usize>,
+    unit: &str,
+    elapsed: Duration,
+) -> String {
+    let count = match total {
+        Some(total) => format!("{done}/{total}"),
+        None => done.to_string(),
+    };
+    format!(
+        "progress: {task}: {count} {unit} after {:.1} s",
+        elapsed.as_secs_f64()
+    )
+}
+
+/// When a task started and last printed its progress, kept only while progress reporting is
+/// enabled so that the core pipeline never reads the clock otherwise.
+struct Clock {
+    interval: Duration,
+    start: Instant,
+    last: Mutex<Instant>,
+}
+
+/// Counts the items produced by a long task, printing the count at most every interval
+/// while progress reporting is enabled. It can be shared by the threads of a parallel task.
+pub(crate) struct Progress {
+    task: String,
+    unit: &'static str,
+    total: Option<usize>,
+    clock: Option<Clock>,
+    done: AtomicUsize,
+}
+
+impl Progress {
+    /// Starts counting the items of a task.
+    ///
+    /// # Arguments
+    ///
+    /// * `task` - What the task does, e.g. `writing out.nr`.
+    /// * `unit` - The name of the items, e.g. `bytes`.
+    /// * `total` - The number of items the task produces, if known.
+    ///
+    /// # Returns
+    ///
+    /// The counter, starting at zero.
+    pub(crate) fn new(task: impl Into<String>, unit: &'static str, total: Option<usize>) -> Self {
+        let clock = interval().map(|interval| {
+            let start = Instant::now();
+            Clock {
+                interval,
+                start,
+                last: Mutex::new(start),
+            }
+        });
+        Progress {
+            task: task.into(),
+            unit,
+            total,
+            clock,
+            done: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds `count` items, printing the progress if the interval has passed since the last line.
+    pub(crate) fn add(&self, count: usize) {
+        let done = self.done.fetch_add(count, Ordering::Relaxed) + count;
+        let Some(clock) = &self.clock else {
+            return;
+        };
+        // Another thread printing the progress right now is as good.
+        if let Ok(mut last) = clock.last.try_lock() {
+            if last.elapsed() >= clock.interval {
+                *last = Instant::now();
+                eprintln!(
+                    "{}",
+                    progress_line(
+                        &self.task,
+                        done,
+                        self.total,
+                        self.unit,
+                        clock.start.elapsed()
+                    )
+                );
+            }
+        }
+    }
+
+    /// Returns the number of items produced so far.
+    pub(crate) fn done(&self) -> usize {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    /// Prints the final count if the task took longer than the interval, so that the lines of a
+    /// long task end with its outcome.
+    pub(crate) fn finish(&self) {
+        match &self.clock {
+            Some(clock) if clock.start.elapsed() >= clock.interval => eprintln!(
+                "{}",
+                progress_line(
+                    &self.task,
+                    self.done(),
+                    self.total,
+                    self.unit,
+                    clock.start.elapsed()
+                )
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Runs `f`, printing how long it has been running every interval while progress reporting is
+/// enabled, for the tasks that cannot count what they produce.
+///
+/// # Arguments
+///
+/// * `task` - What the task does.
+/// * `f` - The task.
+///
+/// # Returns
+///
+/// The result of `f`.
+pub(crate) fn heartbeat<T>(task: &str, f: impl FnOnce() -> T) -> T {
+    let Some(interval) = interval() else {
+        return f();
+    };
+    let (finished, wait) = mpsc::channel::<()>();
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            let start = Instant::now();
+            while let Err(RecvTimeoutError::Timeout) = wait.recv_timeout(interval) {
+                eprintln!(
+                    "progress: {task}: still running after {:.1} s",
+                    start.elapsed().as_secs_f64()
+                );
+            }
+        });
+        let result = f();
+        drop(finished);
+        result
+    })
+}
+
+/// A buffered output file reporting the bytes written to it while progress reporting is
+/// enabled.
+///
+/// The output is compared with the existing contents of the file as it is written. From the
+/// first byte that differs, it is written to a sibling file instead, see `staged_path`, which
+/// replaces the file once the output is complete, so that a failure never leaves the file
+/// half-written. A file whose contents are unchanged is left untouched, keeping its
+/// modification time, so that build systems such as `nargo` or `make` do not rebuild what
+/// depends on it.
+#[cfg(feature = "fs")]
+pub(crate) struct FileWriter {
+    path: PathBuf,
+    /// The existing file, read while the output matches its contents.
+    existing: Option<File>,
+    /// The sibling file the output is written to once it differs from the existing file.
+    staged: Option<StagedFile>,
+    buffer: Vec<u8>,
+    /// The number of bytes written to the staged file or found equal to the existing file.
+    position: u64,
+    progress: Progress,
+}
+
+/// An output written next to its destination, which it replaces when committed. It is removed
+/// if dropped before.
+#[cfg(feature = "fs")]
+pub(crate) struct StagedFile {
+    path: PathBuf,
+    file: Option<File>,
+    committed: bool,
+}
+
+/// Returns the sibling file an output is written to before it replaces `path`.
+#[cfg(feature = "fs")]
+fn staged_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+#[cfg(feature = "fs")]
+impl StagedFile {
+    /// Replaces the destination with the staged output.
+    pub(crate) fn commit(mut self) -> io::Result<()> {
+        drop(self.file.take());
+        rename(staged_path(&self.path), &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "fs")]
+impl Drop for StagedFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            drop(self.file.take());
+            let _ = remove_file(staged_path(&self.path));
+        }
+    }
+}
+
+/// A directory of the system temporary directory unique to the call creating it, so that
+/// concurrent runs never share one. It is removed with its contents when dropped, unless kept.
+#[cfg(feature = "process")]
+pub(crate) struct ScratchDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+#[cfg(feature = "process")]
+impl ScratchDir {
+    /// Creates a directory named after `prefix`, the process and the number of directories
+    /// the process created before.
+    pub(crate) fn new(prefix: &str) -> io::Result<Self> {
+        static CREATED: AtomicUsize = AtomicUsize::new(0);
+        let id = CREATED.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("{prefix}_{}_{id}", std::process::id()));
+        // A directory left by an earlier process of the same id.
+        let _ = remove_dir_all(&path);
+        create_dir_all(&path)?;
+        Ok(Self { path, keep: false })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keeps the directory when dropped, e.g. for the projects of failed checks to be
+    /// inspected.
+    pub(crate) fn keep(&mut self) {
+        self.keep = true;
+    }
+}
+
+#[cfg(feature = "process")]
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = remove_dir_all(&self.path);
+        }
+    }
+}
+
+#[cfg(feature = "fs")]
+impl FileWriter {
+    /// Opens the file for comparison if it exists. Its contents are replaced by the output
+    /// when the writer finishes.
+    pub(crate) fn create(path: &Path) -> io::Result<Self> {
+        let existing = match File::open(path) {
+            Ok(file) => Some(file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        Ok(FileWriter {
+            path: path.to_path_buf(),
+            existing,
+            staged: None,
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            position: 0,
+            progress: Progress::new(format!("writing {}", path.display()), "bytes", None),
+        })
+    }
+
+    /// Writes the output to the file, returning the number of bytes written to it.
+    pub(crate) fn finish(self) -> io::Result<usize> {
+        let (staged, bytes) = self.stage()?;
+        if let Some(staged) = staged {
+            staged.commit()?;
+        }
+        Ok(bytes)
+    }
+
+    /// Completes the output without replacing the file yet, returning the staged output if it
+    /// differs from the file, and the number of bytes written.
+    pub(crate) fn stage(mut self) -> io::Result<(Option<StagedFile>, usize)> {
+        self.flush()?;
+        let unchanged = match &self.existing {
+            Some(file) => self.staged.is_none() && file.metadata()?.len() == self.position,
+            None => false,
+        };
+        if unchanged {
+            debug!(
+                bytes = self.position,
+                "left an unchanged output file untouched"
+            );
+        } else {
+            self.start_staging()?;
+        }
+        self.progress.finish();
+        let bytes = self.progress.done();
+        Ok((self.staged.take(), bytes))
+    }
+
+    /// Starts writing the output to the staged file, copying the part found equal to the
+    /// existing file.
+    fn start_staging(&mut self) -> io::Result<()> {
+        if self.staged.is_some() {
+            return Ok(());
+        }
+        let path = staged_path(&self.path);
+        let mut staged = StagedFile {
+            file: Some(File::create(&path)?),
+            path: self.path.clone(),
+            committed: false,
+        };
+        if let (Some(existing), Some(file)) = (&mut self.existing, &mut staged.file) {
+            existing.seek(SeekFrom::Start(0))?;
+            io::copy(&mut existing.take(self.position), file)?;
+        }
+        self.staged = Some(staged);
+        Ok(())
+    }
+
+    /// Compares the buffered bytes with the contents of the existing file at the same
+    /// position, and writes them to the staged file from the first one that differs.
+    fn flush_buffer(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        if self.staged.is_none() {
+            let mut existing = Vec::with_capacity(self.buffer.len());
+            if let Some(file) = &mut self.existing {
+                file.take(self.buffer.len() as u64)
+                    .read_to_end(&mut existing)?;
+            }
+            if existing != self.buffer {
+                self.start_staging()?;
+            }
+        }
+        if let Some(file) = self.staged.as_mut().and_then(|staged| staged.file.as_mut()) {
+            file.write_all(&self.buffer)?;
+        }
+        self.position += self.buffer.len() as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "fs")]
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.flush_buffer()?;
+        }
+        self.progress.add(buf.len());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_buffer()?;
+        match self.staged.as_mut().and_then(|staged| staged.file.as_mut()) {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes `contents` to `path` in chunks, reporting the bytes written while progress reporting
+/// is enabled.
+///
+/// # Arguments
+///
+/// * `path` - The file to write.
+/// * `contents` - The bytes to write.
+///
+/// # Returns
+///
+/// An `io::Result` indicating whether the file was written.
+#[cfg(feature = "fs")]
+pub(crate) fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = FileWriter::create(path)?;
+    for chunk in contents.chunks(WRITE_CHUNK) {
+        file.write_all(chunk)?;
+    }
+    file.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_progress_lines() {
+        assert_eq!(
+            progress_line(
+                "writing out.nr",
+                1024,
+                Some(4096),
+                "bytes",
+                Duration::from_millis(2500)
+            ),
+            "progress: writing out.nr: 1024/4096 bytes after 2.5 s"
+        );
+        assert_eq!(
+            progress_line(
+                "DFA construction",
+                12,
+                None,
+                "states",
+                Duration::from_secs(3)
+            ),
+            "progress: DFA construction: 12 states after 3.0 s"
+        );
+
+        let progress = Progress::new("generating rows", "rows", None);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| (0..100).for_each(|_| progress.add(1)));
+            }
+        });
+        assert_eq!(progress.done(), 400);
+        assert_eq!(heartbeat("waiting", || 7), 7);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_write_file_skips_unchanged_contents() {
+        let path =
+            std::env::temp_dir().join(format!("zk_regex_write_file_{}.nr", std::process::id()));
+        let contents = "fn main() {}\n".repeat(10000);
+        write_file(&path, contents.as_bytes()).unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        write_file(&path, contents.as_bytes()).unwrap();
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().modified().unwrap(),
+            modified
+        );
+
+        for contents in [
+            contents.replacen("main", "test", 1),
+            contents.replace("main", "entry_point"),
+            contents[..1000].to_string(),
+            String::new(),
+        ] {
+            write_file(&path, contents.as_bytes()).unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+        }
+
+        // An output dropped before it is finished leaves the file as it was.
+        write_file(&path, contents.as_bytes()).unwrap();
+        let mut file = FileWriter::create(&path).unwrap();
+        file.write_all(&contents.as_bytes()[..BUFFER_SIZE]).unwrap();
+        file.write_all(b"changed").unwrap();
+        file.flush().unwrap();
+        drop(file);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+        assert!(!staged_path(&path).exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
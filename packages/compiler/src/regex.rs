@@ -1,20 +1,35 @@
 use crate::{
     errors::CompilerError,
+    profile::{is_profiling, measure, record, timed},
+    progress::{heartbeat, Progress},
     structs::{
-        DFAGraph, DFAGraphInfo, DFAStateInfo, DFAStateNode, RegexAndDFA, RegexPartConfig,
-        SubstringDefinitions, SubstringDefinitionsJson,
+        CaptureGroup, DFAGraph, DFAGraphInfo, DFAStateInfo, DFAStateNode, RegexAndDFA,
+        RegexPartConfig, RegexPartDFA, RepetitionCounter, SubstringDefinitions,
+        SubstringDefinitionsJson,
     },
     DecomposedRegexConfig,
 };
+use itertools::Itertools;
+use petgraph::{algo::tarjan_scc, graphmap::DiGraphMap};
 use regex::Regex;
-use regex_automata::dfa::{
-    dense::{Config, DFA},
-    StartKind,
+use regex_automata::{
+    dfa::{
+        dense::{Config, DFA},
+        StartKind,
+    },
+    nfa::thompson::{self, State, WhichCaptures},
+    util::{
+        look,
+        primitives::{PatternID, StateID},
+        syntax,
+    },
 };
+use regex_syntax::hir::{Capture, Hir, HirKind, Look, Repetition};
 use std::{
     collections::{BTreeMap, BTreeSet, VecDeque},
     num::ParseIntError,
 };
+use tracing::{debug, info};
 
 /// Creates a DFA configuration with specific settings.
 ///
@@ -29,7 +44,11 @@ fn create_dfa_config() -> Config {
         .accelerate(true)
 }
 
-/// Finds the index of the first caret (^) in a regex string that is not inside parentheses.
+/// The pattern matching the byte 255 fed to the automata before the input, which stands for
+/// the start anchor (^).
+const PRIMING_BYTE_PATTERN: &str = r"(?-u:\xFF)";
+
+/// Lists the characters of a regex that are neither escaped nor inside a character class.
 ///
 /// # Arguments
 ///
@@ -37,53 +56,527 @@ fn create_dfa_config() -> Config {
 ///
 /// # Returns
 ///
-/// An `Option<usize>` containing the index of the caret if found, or `None` if not found.
-fn find_caret_index(regex: &str) -> Option<usize> {
-    let regex_bytes = regex.as_bytes();
-    let mut is_in_parenthesis = false;
-    let mut caret_found = false;
-    let mut idx = 0;
+/// A `Vec` of (byte index, character) tuples in order.
+fn unescaped_chars(regex: &str) -> Vec<(usize, char)> {
+    let mut unescaped = vec![];
+    let mut chars = regex.char_indices().peekable();
+    // The nesting depth of the character classes around the current character.
+    let mut class_depth = 0;
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => {
+                // Skip the escaped character, and the braces of `\p{..}` and `\x{..}`.
+                if let Some((_, escaped)) = chars.next() {
+                    if matches!(escaped, 'p' | 'P' | 'x' | 'u' | 'U')
+                        && chars.peek().map(|&(_, c)| c) == Some('{')
+                    {
+                        for (_, c) in chars.by_ref() {
+                            if c == '}' {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+            '[' => {
+                class_depth += 1;
+                // A leading ^ negates the class and a leading ] is a literal.
+                chars.next_if(|&(_, c)| c == '^');
+                chars.next_if(|&(_, c)| c == ']');
+            }
+            ']' if class_depth > 0 => class_depth -= 1,
+            _ if class_depth > 0 => {}
+            _ => unescaped.push((idx, c)),
+        }
+    }
+    unescaped
+}
+
+/// The most syntax errors reported for a regex, as the later ones tend to follow from the
+/// earlier ones.
+const MAX_SYNTAX_ERRORS: usize = 16;
 
-    while idx < regex_bytes.len() {
-        match regex_bytes[idx] {
-            b'\\' => {
-                idx += 2;
+/// Lists the Unicode property classes of a regex, such as `\p{L}`, `\P{Greek}` or `\pN`, that
+/// name properties unknown to the compiler, so that a misspelt property is reported as such
+/// rather than as a failure to build the automaton.
+///
+/// # Arguments
+///
+/// * `regex` - A string slice containing the regex pattern.
+///
+/// # Returns
+///
+/// A Vec of the errors, one per unknown property class.
+fn unknown_unicode_classes(regex: &str) -> Vec<String> {
+    let mut errors = vec![];
+    let mut chars = regex.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        if c != '\\' {
+            continue;
+        }
+        let Some((_, 'p' | 'P')) = chars.next() else {
+            continue;
+        };
+        let mut end = regex.len();
+        match chars.next() {
+            Some((_, '{')) => {
+                for (brace_idx, c) in chars.by_ref() {
+                    if c == '}' {
+                        end = brace_idx + 1;
+                        break;
+                    }
+                }
             }
-            b'(' => {
-                is_in_parenthesis = true;
-                idx += 1;
+            Some((name_idx, name)) => end = name_idx + name.len_utf8(),
+            None => {}
+        }
+        let class = &regex[idx..end];
+        if regex_syntax::parse(class).is_err() {
+            errors.push(format!(
+                "unknown Unicode property class {} in \"{}\", expected a general category such as \\p{{L}} or \\p{{Nd}}, a script such as \\p{{Greek}} or a binary property such as \\p{{Alphabetic}}",
+                class, regex
+            ));
+        }
+    }
+    errors
+}
+
+/// Lists the syntax errors of a regex, recovering after each one so that a single run reports
+/// them all.
+///
+/// The regex is parsed, then translated as the automaton builder does. After an error, the
+/// offending text is masked with literal characters, a whole `{...}` for an invalid repetition
+/// count, and the masked regex is parsed again, until it parses or `MAX_SYNTAX_ERRORS` errors
+/// are found. The unknown Unicode property classes are listed first, see
+/// `unknown_unicode_classes`, then the other errors in the order they appear in the regex.
+///
+/// # Arguments
+///
+/// * `regex` - A string slice containing the regex pattern.
+///
+/// # Returns
+///
+/// A Vec of the errors, each naming the regex and the byte offset of the problem in it.
+pub(crate) fn syntax_errors(regex: &str) -> Vec<String> {
+    use regex_syntax::{ast, hir};
+
+    let unicode_errors = unknown_unicode_classes(regex);
+    let mut errors = vec![];
+    let mut masked = regex.to_string();
+    while unicode_errors.len() + errors.len() < MAX_SYNTAX_ERRORS {
+        let (kind, span, in_count, known) = match ast::parse::Parser::new().parse(&masked) {
+            Err(err) => {
+                let in_count = matches!(
+                    err.kind(),
+                    ast::ErrorKind::RepetitionCountDecimalEmpty
+                        | ast::ErrorKind::RepetitionCountInvalid
+                        | ast::ErrorKind::RepetitionCountUnclosed
+                );
+                (err.kind().to_string(), *err.span(), in_count, false)
             }
-            b'[' => {
-                idx += 2;
+            Ok(parsed) => {
+                let translated = hir::translate::TranslatorBuilder::new()
+                    .utf8(false)
+                    .build()
+                    .translate(&masked, &parsed);
+                match translated {
+                    Err(err) => {
+                        let known = matches!(
+                            err.kind(),
+                            hir::ErrorKind::UnicodePropertyNotFound
+                                | hir::ErrorKind::UnicodePropertyValueNotFound
+                        );
+                        (err.kind().to_string(), *err.span(), false, known)
+                    }
+                    Ok(_) => break,
+                }
             }
-            b')' => {
-                debug_assert!(is_in_parenthesis, "Unmatched parenthesis");
-                is_in_parenthesis = false;
-                idx += 1;
-                if caret_found {
-                    break;
+        };
+        let (start, end) = (span.start.offset, span.end.offset);
+        if !known {
+            let at = match &regex[start..end] {
+                "" => match regex[start..].chars().next() {
+                    Some(c) => format!("\"{}\"", c),
+                    None => "the end".to_string(),
+                },
+                text => format!("\"{}\"", text),
+            };
+            let error = format!("{} at byte {} ({}) in \"{}\"", kind, start, at, regex);
+            errors.push((start, error));
+        }
+        // Masks the offending text, at least one character of it.
+        let (start, end) = match in_count {
+            true => (
+                match masked[start..].starts_with('{') {
+                    true => start,
+                    false => masked[..start].rfind('{').unwrap_or(start),
+                },
+                masked[start..]
+                    .find('}')
+                    .map_or(masked.len(), |i| start + i + 1),
+            ),
+            false => (
+                start,
+                end.max(start + masked[start..].chars().next().map_or(0, char::len_utf8)),
+            ),
+        };
+        if masked[start..end].bytes().all(|byte| byte == b'x') {
+            break;
+        }
+        masked.replace_range(start..end, &"x".repeat(end - start));
+    }
+    errors.sort_by_key(|&(start, _)| start);
+    unicode_errors
+        .into_iter()
+        .chain(errors.into_iter().map(|(_, error)| error))
+        .collect()
+}
+
+/// Fails with every syntax error of a regex, see `syntax_errors`, one per line.
+///
+/// # Arguments
+///
+/// * `regex` - A string slice containing the regex pattern.
+///
+/// # Returns
+///
+/// A `Result` indicating whether the regex is well-formed, or a `CompilerError` listing its
+/// syntax errors.
+fn check_syntax(regex: &str) -> Result<(), CompilerError> {
+    match syntax_errors(regex) {
+        errors if errors.is_empty() => Ok(()),
+        errors => Err(CompilerError::GenericError(errors.join("\n"))),
+    }
+}
+
+/// Splits the anchors off a regex, i.e. a start anchor (^) opening it and an end anchor ($)
+/// closing it. Escaped carets and dollars, such as in `\$[0-9]+`, and those inside a
+/// character class are literals and left in the body.
+///
+/// # Arguments
+///
+/// * `regex` - A string slice containing the regex pattern.
+///
+/// # Returns
+///
+/// A tuple of whether the regex is anchored at its start, its body without the anchors, and
+/// whether it is anchored at its end.
+#[cfg(feature = "fs")]
+pub(crate) fn split_anchors(regex: &str) -> (bool, &str, bool) {
+    let unescaped = unescaped_chars(regex);
+    let start_anchored = unescaped.first() == Some(&(0, '^'));
+    let end_anchored = regex.len() > usize::from(start_anchored)
+        && unescaped.last() == Some(&(regex.len() - 1, '$'));
+    let start = usize::from(start_anchored);
+    let end = regex.len() - usize::from(end_anchored);
+    (start_anchored, &regex[start..end], end_anchored)
+}
+
+/// Replaces every start anchor (^) of a regex by the byte 255 fed to the automata before the
+/// input, so that an anchored branch only matches at the start of the input wherever it
+/// appears, e.g. in `(\r\n|^)from:` or `(^Subject:|^From:)`.
+///
+/// # Arguments
+///
+/// * `regex` - A string slice containing the regex pattern.
+///
+/// # Returns
+///
+/// The regex with its start anchors replaced.
+fn prime_carets(regex: &str) -> String {
+    let mut primed = String::with_capacity(regex.len());
+    let mut last = 0;
+    for (idx, c) in unescaped_chars(regex) {
+        if c == '^' {
+            primed.push_str(&regex[last..idx]);
+            primed.push_str(PRIMING_BYTE_PATTERN);
+            last = idx + 1;
+        }
+    }
+    primed.push_str(&regex[last..]);
+    primed
+}
+
+/// Makes every line ending `\r\n` of a regex also match a bare `\n`, by making its `\r`
+/// optional, e.g. `(\r\n|^)from:` becomes `(\r?\n|^)from:`.
+///
+/// The carriage return and the line feed may be escaped or literal, and those inside a
+/// character class, such as in `[^\r\n]+`, are left untouched.
+///
+/// # Arguments
+///
+/// * `regex` - A string slice containing the regex pattern.
+///
+/// # Returns
+///
+/// The regex accepting both line endings.
+pub(crate) fn relax_line_endings(regex: &str) -> String {
+    let mut relaxed = String::with_capacity(regex.len());
+    let mut chars = regex.char_indices().peekable();
+    // The end of the previous token if it is a carriage return outside a character class.
+    let mut cr_end = None;
+    let mut class_depth = 0;
+    while let Some((idx, c)) = chars.next() {
+        let mut end = idx + c.len_utf8();
+        let token = match c {
+            '\\' => match chars.next() {
+                Some((escaped_idx, escaped)) => {
+                    end = escaped_idx + escaped.len_utf8();
+                    match escaped {
+                        'r' => Some('\r'),
+                        'n' => Some('\n'),
+                        _ => None,
+                    }
+                }
+                None => None,
+            },
+            '[' => {
+                class_depth += 1;
+                // A leading ^ negates the class and a leading ] is a literal.
+                for leading in ['^', ']'] {
+                    if let Some((leading_idx, _)) = chars.next_if(|&(_, c)| c == leading) {
+                        end = leading_idx + 1;
+                    }
                 }
+                None
+            }
+            ']' if class_depth > 0 => {
+                class_depth -= 1;
+                None
             }
-            b'^' => {
-                caret_found = true;
-                idx += 1;
-                if !is_in_parenthesis {
-                    break;
+            '\r' | '\n' => Some(c),
+            _ => None,
+        }
+        .filter(|_| class_depth == 0);
+        if token == Some('\n') && cr_end == Some(idx) {
+            relaxed.push('?');
+        }
+        cr_end = (token == Some('\r')).then_some(end);
+        relaxed.push_str(&regex[idx..end]);
+    }
+    relaxed
+}
+
+/// Rewrites the absolute anchors of a regex, `\A` and `\z`, as the start (^) and end ($)
+/// anchors they are equivalent to, since `^` and `$` always stand for the start and end of
+/// the input. Escaped backslashes, such as in `\\A`, and character classes are left untouched.
+///
+/// # Arguments
+///
+/// * `regex` - A string slice containing the regex pattern.
+///
+/// # Returns
+///
+/// The regex anchored with `^` and `$` only.
+pub(crate) fn absolute_anchors(regex: &str) -> String {
+    let mut rewritten = String::with_capacity(regex.len());
+    let mut chars = regex.chars().peekable();
+    let mut class_depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('A') if class_depth == 0 => rewritten.push('^'),
+                Some('z') if class_depth == 0 => rewritten.push('$'),
+                Some(escaped) => {
+                    rewritten.push(c);
+                    rewritten.push(escaped);
+                }
+                None => rewritten.push(c),
+            },
+            '[' => {
+                class_depth += 1;
+                rewritten.push(c);
+                // A leading ^ negates the class and a leading ] is a literal.
+                for leading in ['^', ']'] {
+                    if let Some(leading) = chars.next_if(|&c| c == leading) {
+                        rewritten.push(leading);
+                    }
                 }
             }
-            _ => {
-                idx += 1;
+            ']' if class_depth > 0 => {
+                class_depth -= 1;
+                rewritten.push(c);
+            }
+            _ => rewritten.push(c),
+        }
+    }
+    rewritten
+}
+
+/// Makes every part of a decomposed regex accept both line endings, see `relax_line_endings`.
+///
+/// # Arguments
+///
+/// * `decomposed_regex` - A mutable reference to the `DecomposedRegexConfig` to rewrite.
+pub(crate) fn relax_config_line_endings(decomposed_regex: &mut DecomposedRegexConfig) {
+    for part in decomposed_regex.parts.iter_mut() {
+        part.regex_def = relax_line_endings(&part.regex_def);
+    }
+}
+
+/// Collects the flag groups of a regex that are not nested in another group, such as the
+/// `(?i)` of `(?i)subject:`, which apply up to the end of the regex.
+///
+/// # Arguments
+///
+/// * `regex` - A string slice containing the regex pattern.
+///
+/// # Returns
+///
+/// The flag groups in order, concatenated.
+fn top_level_flags(regex: &str) -> String {
+    let flag_group = Regex::new(r"^\(\?[imsRUux-]+\)").expect("valid flag group regex");
+    let mut flags = String::new();
+    let mut depth = 0;
+    for (idx, c) in unescaped_chars(regex) {
+        match c {
+            '(' if depth == 0 => match flag_group.find(&regex[idx..]) {
+                Some(group) => flags.push_str(group.as_str()),
+                None => depth += 1,
+            },
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            _ => {}
+        }
+    }
+    flags
+}
+
+/// Wraps a regex part in a non-capturing group if it sets flags for the rest of itself, so
+/// that they stay scoped to the part once the parts are joined, as they are in its DFA.
+fn scope_part_flags(regex_def: String) -> String {
+    match top_level_flags(&regex_def).is_empty() {
+        true => regex_def,
+        false => format!("(?:{})", regex_def),
+    }
+}
+
+/// Finds the end of the prefix of a regex holding its first caret (^): the caret itself when
+/// it is outside parentheses, or else the outermost group containing it.
+///
+/// # Arguments
+///
+/// * `regex` - A string slice containing the regex pattern.
+///
+/// # Returns
+///
+/// An `Option<usize>` containing the index following the caret or its group if found, or
+/// `None` if not found.
+fn find_caret_index(regex: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut caret_found = false;
+    for (idx, c) in unescaped_chars(regex) {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if caret_found && depth == 0 {
+                    return Some(idx + 1);
+                }
             }
+            '^' if depth == 0 => return Some(idx + 1),
+            '^' => caret_found = true,
+            _ => {}
         }
     }
+    None
+}
 
-    if caret_found {
-        Some(idx)
+/// Tells whether a regex is anchored at its end, i.e. whether every alternative of it ends
+/// with an end anchor ($), e.g. `(\.com$|\.org$)`.
+///
+/// # Arguments
+///
+/// * `regex` - A string slice containing the regex pattern.
+///
+/// # Returns
+///
+/// A `Result` containing whether the regex is anchored at its end, or a `CompilerError` if
+/// it only is in some of its alternatives.
+fn find_end_anchor(regex: &str) -> Result<bool, CompilerError> {
+    let hir = regex_syntax::ParserBuilder::new()
+        .utf8(false)
+        .build()
+        .parse(regex)
+        .map_err(|err| CompilerError::ParseError(format!("Invalid regex {}: {}", regex, err)))?;
+    let properties = hir.properties();
+    if properties.look_set().contains_anchor_line() {
+        return Err(CompilerError::GenericError(format!(
+            "Invalid regex {}, the multi-line flag (?m) is not supported: ^ and $ stand for the \
+             start and end of the input, and line breaks must be matched explicitly, e.g. \
+             (\\r\\n|^)",
+            regex
+        )));
+    }
+    if properties.look_set_suffix().contains(Look::End) {
+        Ok(true)
+    } else if properties.look_set().contains(Look::End) {
+        Err(CompilerError::GenericError(format!(
+            "Invalid regex {}, $ must end every alternative it appears in",
+            regex
+        )))
     } else {
-        None
+        Ok(false)
+    }
+}
+
+/// Tells whether a start anchor (^) of a regex always follows some input, as in `a^b` or
+/// `x(^a)`, so that it can never match.
+///
+/// # Arguments
+///
+/// * `hir` - A reference to the parsed regex.
+/// * `at_start` - Whether the regex may start at the start of the input.
+///
+/// # Returns
+///
+/// `true` if a start anchor of the regex follows some input in every match.
+fn caret_after_input(hir: &Hir, at_start: bool) -> bool {
+    match hir.kind() {
+        HirKind::Look(Look::Start) => !at_start,
+        HirKind::Repetition(Repetition { sub, .. }) | HirKind::Capture(Capture { sub, .. }) => {
+            caret_after_input(sub, at_start)
+        }
+        HirKind::Concat(subs) => {
+            let mut at_start = at_start;
+            subs.iter().any(|sub| {
+                let after_input = caret_after_input(sub, at_start);
+                at_start &= sub.properties().minimum_len() == Some(0);
+                after_input
+            })
+        }
+        HirKind::Alternation(subs) => subs.iter().any(|sub| caret_after_input(sub, at_start)),
+        _ => false,
     }
 }
 
+/// Checks that every start anchor (^) of a regex may stand at the start of the input, i.e.
+/// that it begins the regex or one of the alternatives it starts with, as in `(\r\n|^)from:`.
+///
+/// # Arguments
+///
+/// * `regex` - A string slice containing the regex pattern.
+///
+/// # Returns
+///
+/// A `Result` indicating whether the start anchors are valid, or a `CompilerError` if one of
+/// them always follows some input.
+fn validate_start_anchors(regex: &str) -> Result<(), CompilerError> {
+    let hir = regex_syntax::ParserBuilder::new()
+        .utf8(false)
+        .build()
+        .parse(regex)
+        .map_err(|err| CompilerError::ParseError(format!("Invalid regex {}: {}", regex, err)))?;
+    if caret_after_input(&hir, true) {
+        return Err(CompilerError::GenericError(format!(
+            "Invalid regex {}, ^ can only match at the start of the input, so it must not \
+             follow characters the regex matches first",
+            regex
+        )));
+    }
+    Ok(())
+}
+
 /// Processes the caret (^) in a regex, splitting it into two parts if necessary.
 ///
 /// # Arguments
@@ -100,12 +593,20 @@ fn process_caret_in_regex(
 
     if let Some(index) = caret_position {
         let caret_regex = decomposed_regex.parts[0].regex_def[0..index].to_string();
+        // The flags set before the split keep applying to the rest of the part.
+        let flags = top_level_flags(&caret_regex);
         decomposed_regex.parts.push_front(RegexPartConfig {
             is_public: false,
             regex_def: caret_regex,
+            max_repetitions: None,
+            max_length: None,
+            numeric: false,
+            hole: false,
+            min: None,
+            max: None,
         });
         decomposed_regex.parts[1].regex_def =
-            decomposed_regex.parts[1].regex_def[index..].to_string();
+            format!("{}{}", flags, &decomposed_regex.parts[1].regex_def[index..]);
     }
 
     Ok(caret_position)
@@ -128,7 +629,7 @@ fn validate_end_anchor(
     regex: &RegexPartConfig,
 ) -> Result<bool, CompilerError> {
     let is_last_part = idx == decomposed_regex.parts.len() - 1;
-    let ends_with_dollar = regex.regex_def.ends_with('$');
+    let ends_with_dollar = find_end_anchor(&regex.regex_def)?;
 
     if ends_with_dollar && !is_last_part {
         return Err(CompilerError::GenericError(
@@ -162,6 +663,7 @@ fn parse_states(output: &str, dfa_info: &mut DFAGraphInfo) -> Result<(), Compile
         r"\s+[^=]+\s*=>\s*(\d+)+\s*|\s+=+\s*=>\s*(\d+)+|\s+=-[^=]+=>\s*\s*(\d+)+\s*|\s+[^=]+-=\s*=>\s*(\d+)+\s*",
     )?;
 
+    let progress = Progress::new("DFA construction", "states", None);
     for captures in state_re.captures_iter(output) {
         let src = captures[1]
             .parse::<usize>()
@@ -182,7 +684,9 @@ fn parse_states(output: &str, dfa_info: &mut DFAGraphInfo) -> Result<(), Compile
         }
 
         dfa_info.states.push(state);
+        progress.add(1);
     }
+    progress.finish();
 
     Ok(())
 }
@@ -328,6 +832,7 @@ fn create_special_char_mappings() -> BTreeMap<&'static str, u8> {
         ("\\\"", 34),
         ("\\'", 39),
         ("\\", 92),
+        ("\\\\", 92),
         ("' '", 32),
     ]
     .iter()
@@ -543,106 +1048,6 @@ fn convert_dfa_to_graph(dfa: DFA<Vec<u32>>) -> Result<DFAGraph, CompilerError> {
     Ok(graph)
 }
 
-/// Modifies the DFA graph to handle the caret (^) anchor at the start of a regex.
-///
-/// # Arguments
-///
-/// * `graph` - A mutable reference to the DFAGraph to be modified.
-///
-/// # Returns
-///
-/// A `Result` containing `()` if successful, or a `CompilerError` if modification fails.
-///
-/// # Function Behavior
-///
-/// 1. Clears the state type of the start state.
-/// 2. Finds the accept state in the graph.
-/// 3. Adds a transition from the start state to the accept state with byte value 255.
-fn modify_graph_for_caret(graph: &mut DFAGraph) -> Result<(), CompilerError> {
-    if let Some(start_state) = graph.states.get_mut(0) {
-        start_state.state_type.clear();
-    } else {
-        return Err(CompilerError::GraphError(
-            "Start state not found".to_string(),
-        ));
-    }
-
-    let accepted_state = graph
-        .states
-        .iter()
-        .find(|state| state.state_type == "accept")
-        .ok_or_else(|| CompilerError::GraphError("Accept state not found".to_string()))?
-        .clone();
-
-    if let Some(start_state) = graph.states.get_mut(0) {
-        start_state
-            .transitions
-            .entry(accepted_state.state_id)
-            .or_insert_with(BTreeSet::new)
-            .insert(255u8);
-    }
-
-    Ok(())
-}
-
-/// Creates a simple DFA graph for the caret (^) anchor.
-///
-/// # Returns
-///
-/// A `DFAGraph` with two states:
-/// 1. Start state (id: 0) with a transition to the accept state on byte 255.
-/// 2. Accept state (id: 1) with no outgoing transitions.
-fn create_simple_caret_graph() -> DFAGraph {
-    DFAGraph {
-        states: vec![
-            DFAStateNode {
-                state_type: String::new(),
-                state_id: 0,
-                transitions: BTreeMap::from([(1, BTreeSet::from([255u8]))]),
-            },
-            DFAStateNode {
-                state_type: "accept".to_string(),
-                state_id: 1,
-                transitions: BTreeMap::new(),
-            },
-        ],
-    }
-}
-
-/// Handles the caret (^) anchor in a regex by modifying the DFA graph accordingly.
-///
-/// # Arguments
-///
-/// * `idx` - The index of the current regex part.
-/// * `caret_position` - The position of the caret in the regex, if present.
-/// * `regex` - The current regex part configuration.
-/// * `graph` - The DFA graph to be modified.
-///
-/// # Returns
-///
-/// A `Result` containing `()` if successful, or a `CompilerError` if modification fails.
-///
-/// # Function Behavior
-///
-/// - If it's the first regex part and a caret is present:
-///   - Creates a simple caret graph if the regex is just "^".
-///   - Otherwise, modifies the existing graph to handle the caret.
-fn handle_caret_regex(
-    idx: usize,
-    caret_position: Option<usize>,
-    regex: &RegexPartConfig,
-    graph: &mut DFAGraph,
-) -> Result<(), CompilerError> {
-    if idx == 0 && caret_position.is_some() {
-        if regex.regex_def == "^" {
-            *graph = create_simple_caret_graph();
-        } else {
-            modify_graph_for_caret(graph)?;
-        }
-    }
-    Ok(())
-}
-
 /// Renames the states in a DFA graph, offsetting their IDs by a given base value.
 ///
 /// # Arguments
@@ -919,46 +1324,194 @@ fn add_dfa(net_dfa: &DFAGraph, graph: &DFAGraph) -> DFAGraph {
     new_dfa
 }
 
-/// Constructs a RegexAndDFA structure from a decomposed regex configuration.
-///
-/// This function processes each part of the decomposed regex, builds individual DFAs,
-/// and combines them into a single DFA graph. It also handles special cases like
-/// caret (^) and end anchor ($) in the regex.
+/// Returns the regex matched by a part, repeated one or more times if `max_repetitions` is set.
 ///
 /// # Arguments
 ///
-/// * `decomposed_regex` - A mutable reference to a DecomposedRegexConfig.
+/// * `regex` - A reference to the RegexPartConfig.
 ///
 /// # Returns
 ///
-/// A Result containing a RegexAndDFA structure if successful, or a CompilerError if an error occurs.
-pub(crate) fn get_regex_and_dfa(
-    decomposed_regex: &mut DecomposedRegexConfig,
-) -> Result<RegexAndDFA, CompilerError> {
-    let mut net_dfa_graph = DFAGraph { states: Vec::new() };
-    let mut substring_ranges_array = Vec::new();
-    let mut substring_boundaries_array = Vec::new();
+/// A String containing the regex of the part.
+fn repeated_regex_def(regex: &RegexPartConfig) -> String {
+    match regex.max_repetitions {
+        Some(_) => format!("({})+", regex.regex_def),
+        None => regex.regex_def.clone(),
+    }
+}
 
-    let config = create_dfa_config();
+/// Validates the `max_repetitions` of a regex part.
+///
+/// # Arguments
+///
+/// * `regex` - A reference to the RegexPartConfig.
+///
+/// # Returns
+///
+/// A `Result` indicating whether the part is valid, or a `CompilerError`.
+fn validate_max_repetitions(regex: &RegexPartConfig) -> Result<(), CompilerError> {
+    match regex.max_repetitions {
+        Some(_) if !regex.is_public => Err(CompilerError::GenericError(format!(
+            "max_repetitions is only supported on public parts, found on \"{}\"",
+            regex.regex_def
+        ))),
+        Some(0) => Err(CompilerError::GenericError(format!(
+            "max_repetitions of \"{}\" must be at least 1",
+            regex.regex_def
+        ))),
+        _ => Ok(()),
+    }
+}
 
-    let caret_position = process_caret_in_regex(decomposed_regex)?;
+/// Builds the anchored DFA graph of a single regex part.
+///
+/// # Arguments
+///
+/// * `regex_def` - The regex of the part.
+/// * `config` - The DFA configuration.
+///
+/// # Returns
+///
+/// A `Result` containing the `DFAGraph` of the part, or a `CompilerError`.
+fn build_part_graph(regex_def: &str, config: &Config) -> Result<DFAGraph, CompilerError> {
+    let dfa = build_dfa(regex_def, &format!(r"^({})$", regex_def), config)?;
+    timed("DFA construction", || convert_dfa_to_graph(dfa))
+}
 
-    let mut end_anchor = false;
+/// Builds the DFA of a pattern, recording the time spent in each phase when profiling.
+///
+/// The builder parses, determinizes and minimizes the pattern in one go, so when profiling,
+/// the pattern is also compiled to an NFA and to an unminimized DFA to tell the phases apart.
+///
+/// # Arguments
+///
+/// * `regex` - The regex reported in errors.
+/// * `pattern` - The pattern to build, i.e. the regex with its anchors.
+/// * `config` - The configuration of the DFA.
+///
+/// # Returns
+///
+/// A `Result` containing the DFA, or a `CompilerError`.
+fn build_dfa(regex: &str, pattern: &str, config: &Config) -> Result<DFA<Vec<u32>>, CompilerError> {
+    check_syntax(regex)?;
+    // The priming byte standing for ^ is not valid UTF-8 on its own.
+    let syntax = syntax::Config::new().utf8(false);
+    let build = |config: Config| {
+        heartbeat("building the DFA", || {
+            DFA::builder()
+                .configure(config)
+                .syntax(syntax)
+                .build(pattern)
+        })
+        .map_err(|err| CompilerError::BuildError {
+            regex: regex.to_string(),
+            source: err,
+        })
+    };
+    if !is_profiling() {
+        return build(config.clone());
+    }
+    let (_, parsing) = measure(|| thompson::Compiler::new().syntax(syntax).build(pattern));
+    let (_, unminimized) = measure(|| build(config.clone().minimize(false)));
+    let (dfa, total) = measure(|| build(config.clone()));
+    record("parsing", parsing);
+    record("DFA construction", unminimized.saturating_sub(parsing));
+    record("minimization", total.saturating_sub(unminimized));
+    dfa
+}
+
+/// Constructs a RegexAndDFA structure from a decomposed regex configuration.
+///
+/// This function processes each part of the decomposed regex, builds individual DFAs,
+/// and combines them into a single DFA graph. It also handles special cases like
+/// caret (^) and end anchor ($) in the regex.
+///
+/// # Arguments
+///
+/// * `decomposed_regex` - A mutable reference to a DecomposedRegexConfig.
+///
+/// # Returns
+///
+/// A Result containing a RegexAndDFA structure if successful, or a CompilerError if an error occurs.
+pub(crate) fn get_regex_and_dfa(
+    decomposed_regex: &mut DecomposedRegexConfig,
+) -> Result<RegexAndDFA, CompilerError> {
+    let mut net_dfa_graph = DFAGraph { states: Vec::new() };
+    let mut substring_ranges_array = Vec::new();
+    let mut substring_boundaries_array = Vec::new();
+    let mut parts = Vec::new();
+
+    let config = create_dfa_config();
+
+    // Every syntax error of every part is reported at once.
+    let errors = decomposed_regex
+        .parts
+        .iter()
+        .enumerate()
+        .flat_map(|(i, part)| {
+            syntax_errors(&part.regex_def)
+                .into_iter()
+                .map(move |error| format!("part {}: {}", i, error))
+        })
+        .collect_vec();
+    if !errors.is_empty() {
+        return Err(CompilerError::GenericError(errors.join("\n")));
+    }
+    for part in decomposed_regex.parts.iter_mut() {
+        part.regex_def = absolute_anchors(&part.regex_def);
+    }
+    validate_start_anchors(
+        &decomposed_regex
+            .parts
+            .iter()
+            .map(|part| format!("(?:{})", part.regex_def))
+            .join(""),
+    )?;
+    process_caret_in_regex(decomposed_regex)?;
+
+    let mut end_anchor = false;
 
     for (i, regex) in decomposed_regex.parts.iter().enumerate() {
         end_anchor = validate_end_anchor(decomposed_regex, i, regex)?;
+        validate_max_repetitions(regex)?;
+        if (regex.min.is_some() || regex.max.is_some()) && !regex.numeric {
+            return Err(CompilerError::GenericError(format!(
+                "min and max bound the values of numeric parts, found them on \"{}\", which is not numeric",
+                regex.regex_def
+            )));
+        }
+        if let (Some(min), Some(max)) = (regex.min, regex.max) {
+            if min > max {
+                return Err(CompilerError::GenericError(format!(
+                    "the min {} of \"{}\" is greater than its max {}",
+                    min, regex.regex_def, max
+                )));
+            }
+        }
+        if regex.hole && !regex.is_public {
+            return Err(CompilerError::GenericError(format!(
+                "holes are captured and must be public, found a private one on \"{}\"",
+                regex.regex_def
+            )));
+        }
 
-        let dfa = DFA::builder()
-            .configure(config.clone())
-            .build(&format!(r"^({})$", regex.regex_def.as_str()))
-            .map_err(|err| CompilerError::BuildError {
-                regex: regex.regex_def.clone(),
-                source: err,
-            })?;
-
-        let mut dfa_graph = convert_dfa_to_graph(dfa)?;
+        let mut dfa_graph = build_part_graph(&prime_carets(&repeated_regex_def(regex)), &config)?;
 
-        handle_caret_regex(i, caret_position, regex, &mut dfa_graph)?;
+        // A repeated part is kept as a single repetition, so that each one can be captured.
+        let part_graph = match regex.max_repetitions {
+            Some(_) => build_part_graph(&prime_carets(&regex.regex_def), &config)?,
+            None => dfa_graph.clone(),
+        };
+        parts.push(RegexPartDFA {
+            dfa: part_graph,
+            substr_idx: regex.is_public.then_some(substring_ranges_array.len()),
+            max_repetitions: regex.max_repetitions,
+            max_length: regex.max_length,
+            numeric: regex.numeric,
+            hole: regex.hole,
+            min: regex.min,
+            max: regex.max,
+        });
 
         let max_state_index = net_dfa_graph
             .states
@@ -976,28 +1529,52 @@ pub(crate) fn get_regex_and_dfa(
             substring_boundaries_array.push((substr_starts, substr_ends));
         }
 
-        net_dfa_graph = add_dfa(&net_dfa_graph, &dfa_graph);
+        net_dfa_graph = timed("DFA construction", || add_dfa(&net_dfa_graph, &dfa_graph));
+        debug!(
+            part = i,
+            public = regex.is_public,
+            states = dfa_graph.states.len(),
+            "built the DFA of a regex part"
+        );
     }
 
     let regex_str = decomposed_regex
         .parts
         .iter()
-        .map(|regex| regex.regex_def.as_str())
+        .map(|part| scope_part_flags(repeated_regex_def(part)))
         .collect::<String>();
+    info!(
+        parts = parts.len(),
+        states = net_dfa_graph.states.len(),
+        substrings = substring_ranges_array.len(),
+        end_anchor,
+        "built the DFA of a decomposed regex"
+    );
 
-    Ok(RegexAndDFA {
+    let mut regex_and_dfa = RegexAndDFA {
         regex_pattern: regex_str,
         dfa: net_dfa_graph,
         has_end_anchor: end_anchor,
         substrings: SubstringDefinitions {
             substring_ranges: substring_ranges_array,
             substring_boundaries: Some(substring_boundaries_array),
+            substring_groups: None,
+            substring_names: None,
         },
-    })
+        parts,
+        counters: vec![],
+        whole_input: false,
+    };
+    prune_dead_states(&mut regex_and_dfa);
+    Ok(regex_and_dfa)
 }
 
 /// Creates a DFA graph from a regex string.
 ///
+/// Its start anchors, `^` and `\A`, are primed as in the parts of a decomposed regex, see
+/// `prime_carets`, so that an anchored branch cannot match once the matcher restarts past
+/// the start of the input.
+///
 /// # Arguments
 ///
 /// * `regex` - A string slice containing the regex pattern.
@@ -1005,22 +1582,137 @@ pub(crate) fn get_regex_and_dfa(
 /// # Returns
 ///
 /// A `Result` containing a `DFAGraph` or a `CompilerError`.
-fn create_dfa_graph_from_regex(regex: &str) -> Result<DFAGraph, CompilerError> {
+pub(crate) fn create_dfa_graph_from_regex(regex: &str) -> Result<DFAGraph, CompilerError> {
     let config = DFA::config()
         .minimize(true)
         .start_kind(StartKind::Anchored)
         .byte_classes(false)
         .accelerate(true);
 
-    let dfa = DFA::builder()
-        .configure(config)
-        .build(&format!(r"^{}$", regex))
-        .map_err(|e| CompilerError::BuildError {
-            regex: regex.to_string(),
-            source: e,
-        })?;
+    let primed = prime_carets(&absolute_anchors(regex));
+    let dfa = build_dfa(regex, &format!(r"^(?:{})$", primed), &config)?;
 
-    convert_dfa_to_graph(dfa)
+    timed("DFA construction", || convert_dfa_to_graph(dfa))
+}
+
+/// Keeps only the selected substrings of a `RegexAndDFA`, renumbering them from 0 in order.
+///
+/// The parts capturing a dropped substring are treated as private from then on, so no
+/// extraction constraints are generated for them.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - The RegexAndDFA struct whose substrings are filtered in place.
+/// * `indices` - The indices of the substrings to keep.
+///
+/// # Returns
+///
+/// A Result indicating success, or a CompilerError if an index does not name a substring.
+pub(crate) fn select_substrs(
+    regex_and_dfa: &mut RegexAndDFA,
+    indices: &BTreeSet<usize>,
+) -> Result<(), CompilerError> {
+    let substrings = &mut regex_and_dfa.substrings;
+    let num_substrs = substrings.substring_ranges.len();
+    if let Some(idx) = indices.iter().find(|&&idx| idx >= num_substrs) {
+        return Err(CompilerError::GenericError(format!(
+            "substring {} is selected but the regex only has {} substrings",
+            idx, num_substrs
+        )));
+    }
+    let keep = |idx: &usize| indices.contains(idx);
+    substrings.substring_ranges = std::mem::take(&mut substrings.substring_ranges)
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, _)| keep(idx))
+        .map(|(_, ranges)| ranges)
+        .collect();
+    if let Some(boundaries) = substrings.substring_boundaries.take() {
+        substrings.substring_boundaries = Some(
+            boundaries
+                .into_iter()
+                .enumerate()
+                .filter(|(idx, _)| keep(idx))
+                .map(|(_, boundaries)| boundaries)
+                .collect(),
+        );
+    }
+    if let Some(groups) = substrings.substring_groups.take() {
+        substrings.substring_groups = Some(
+            groups
+                .into_iter()
+                .enumerate()
+                .filter(|(idx, _)| keep(idx))
+                .map(|(_, group)| group)
+                .collect(),
+        );
+    }
+    if let Some(names) = substrings.substring_names.take() {
+        substrings.substring_names = Some(
+            names
+                .into_iter()
+                .enumerate()
+                .filter(|(idx, _)| keep(idx))
+                .map(|(_, name)| name)
+                .collect(),
+        );
+    }
+    for part in regex_and_dfa.parts.iter_mut() {
+        part.substr_idx = part
+            .substr_idx
+            .filter(keep)
+            .map(|idx| indices.range(..idx).count());
+    }
+    Ok(())
+}
+
+/// Makes exactly the given states of the DFA accepting, overriding those derived from the
+/// regex, so that a circuit can claim that the input reached a state rather than matched the
+/// whole pattern.
+///
+/// The matchers stop at the first accepting state they reach, as they do at the end of a
+/// match, unless the regex ends with `$`.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - The RegexAndDFA struct of a raw regex, whose DFA is changed in place.
+/// * `accept_states` - The IDs of the states to accept.
+///
+/// # Returns
+///
+/// A Result indicating success, or a CompilerError if a state does not exist or the automaton
+/// is not a single DFA.
+#[cfg(feature = "fs")]
+pub(crate) fn set_accept_states(
+    regex_and_dfa: &mut RegexAndDFA,
+    accept_states: &BTreeSet<usize>,
+) -> Result<(), CompilerError> {
+    if !regex_and_dfa.parts.is_empty() || !regex_and_dfa.counters.is_empty() {
+        return Err(CompilerError::GenericError(
+            "accept states can only be set on the DFA of a raw regex without repetition \
+             counters"
+                .to_string(),
+        ));
+    }
+    let states: BTreeSet<usize> = regex_and_dfa
+        .dfa
+        .states
+        .iter()
+        .map(|state| state.state_id)
+        .collect();
+    if let Some(state) = accept_states.difference(&states).next() {
+        return Err(CompilerError::GenericError(format!(
+            "state {} is set as accepting but the DFA has no such state",
+            state
+        )));
+    }
+    for state in regex_and_dfa.dfa.states.iter_mut() {
+        state.state_type = match accept_states.contains(&state.state_id) {
+            true => "accept".to_string(),
+            false => String::new(),
+        };
+    }
+    Ok(())
 }
 
 /// Checks if a given string matches the regex pattern represented by the DFAGraph.
@@ -1033,7 +1725,8 @@ fn create_dfa_graph_from_regex(regex: &str) -> Result<DFAGraph, CompilerError> {
 /// # Returns
 ///
 /// A boolean indicating whether the input string matches the regex pattern.
-fn match_string_with_dfa_graph(graph: &DFAGraph, input: &str) -> bool {
+#[cfg(test)]
+pub(crate) fn match_string_with_dfa_graph(graph: &DFAGraph, input: &str) -> bool {
     let mut current_state = 0;
 
     for &byte in input.as_bytes() {
@@ -1063,6 +1756,9 @@ fn match_string_with_dfa_graph(graph: &DFAGraph, input: &str) -> bool {
 
 /// Creates a `RegexAndDFA` from a regex string and substring definitions.
 ///
+/// The names given to the sets of transitions by the definitions, if any, name the
+/// substrings in the generated code and the report.
+///
 /// # Arguments
 ///
 /// * `regex_str` - A string slice containing the regex pattern.
@@ -1076,7 +1772,28 @@ pub(crate) fn create_regex_and_dfa_from_str_and_defs(
     substrs_defs_json: SubstringDefinitionsJson,
 ) -> Result<RegexAndDFA, CompilerError> {
     let dfa = create_dfa_graph_from_regex(regex_str)?;
-
+    validate_start_anchors(regex_str)?;
+
+    let names = &substrs_defs_json.names;
+    if !names.is_empty() && names.len() != substrs_defs_json.transitions.len() {
+        return Err(CompilerError::GenericError(format!(
+            "the substrings JSON names {} sets of transitions but defines {}",
+            names.len(),
+            substrs_defs_json.transitions.len()
+        )));
+    }
+    if let Some(name) = names.iter().flatten().duplicates().next() {
+        return Err(CompilerError::GenericError(format!(
+            "the substrings JSON gives the name {} to several sets of transitions",
+            name
+        )));
+    }
+    if names.iter().flatten().any(|name| name.trim().is_empty()) {
+        return Err(CompilerError::GenericError(
+            "the substrings JSON gives a set of transitions an empty name".to_string(),
+        ));
+    }
+    let substring_names = Some(substrs_defs_json.names).filter(|names| !names.is_empty());
     let substring_ranges = substrs_defs_json
         .transitions
         .into_iter()
@@ -1085,19 +1802,459 @@ pub(crate) fn create_regex_and_dfa_from_str_and_defs(
                 .into_iter()
                 .collect::<BTreeSet<(usize, usize)>>()
         })
-        .collect();
+        .collect::<Vec<_>>();
+    info!(
+        states = dfa.states.len(),
+        substrings = substring_ranges.len(),
+        "built the DFA of a raw regex"
+    );
 
     let substrings = SubstringDefinitions {
         substring_ranges,
         substring_boundaries: None,
+        substring_groups: None,
+        substring_names,
     };
 
-    Ok(RegexAndDFA {
+    let mut regex_and_dfa = RegexAndDFA {
         regex_pattern: regex_str.to_string(),
         dfa,
-        has_end_anchor: regex_str.ends_with('$'),
+        has_end_anchor: find_end_anchor(regex_str)?,
         substrings,
-    })
+        parts: vec![],
+        counters: vec![],
+        whole_input: false,
+    };
+    prune_dead_states(&mut regex_and_dfa);
+    Ok(regex_and_dfa)
+}
+
+/// A thread of the NFA of a regex: a state and the capture groups open on the way to it.
+type GroupThread = (usize, BTreeSet<usize>);
+
+/// Follows the epsilon transitions of NFA threads up to the states consuming a byte.
+///
+/// # Arguments
+///
+/// * `nfa` - The NFA of the regex.
+/// * `threads` - The threads to follow.
+/// * `at_start` - Whether no byte has been consumed yet, so that a start anchor holds.
+///
+/// # Returns
+///
+/// A `Result` containing the threads in states consuming a byte, or a `CompilerError` if the
+/// regex uses an assertion other than `^` and `$`.
+fn group_closure(
+    nfa: &thompson::NFA,
+    threads: Vec<GroupThread>,
+    at_start: bool,
+) -> Result<BTreeSet<GroupThread>, CompilerError> {
+    let mut seen = BTreeSet::new();
+    let mut consuming = BTreeSet::new();
+    let mut stack = threads;
+    while let Some((id, open)) = stack.pop() {
+        if !seen.insert((id, open.clone())) {
+            continue;
+        }
+        match nfa.state(StateID::new_unchecked(id)) {
+            State::ByteRange { .. } | State::Sparse(_) | State::Dense(_) => {
+                consuming.insert((id, open));
+            }
+            State::Look { look, next } => match look {
+                look::Look::Start | look::Look::StartLF | look::Look::StartCRLF => {
+                    if at_start {
+                        stack.push((next.as_usize(), open));
+                    }
+                }
+                // `$` only holds once the input is over, where no byte follows.
+                look::Look::End | look::Look::EndLF | look::Look::EndCRLF => {}
+                _ => {
+                    return Err(CompilerError::GenericError(format!(
+                        "capture groups cannot be derived from a regex using {:?}",
+                        look
+                    )))
+                }
+            },
+            State::Union { alternates } => {
+                stack.extend(
+                    alternates
+                        .iter()
+                        .map(|next| (next.as_usize(), open.clone())),
+                );
+            }
+            State::BinaryUnion { alt1, alt2 } => {
+                stack.push((alt1.as_usize(), open.clone()));
+                stack.push((alt2.as_usize(), open));
+            }
+            State::Capture {
+                next,
+                group_index,
+                slot,
+                ..
+            } => {
+                let mut open = open;
+                let group = group_index.as_usize();
+                // Every group has a start slot followed by an end slot, and group 0 is the
+                // whole match.
+                if group > 0 && slot.as_usize() % 2 == 0 {
+                    open.insert(group);
+                } else {
+                    open.remove(&group);
+                }
+                stack.push((next.as_usize(), open));
+            }
+            State::Fail | State::Match { .. } => {}
+        }
+    }
+    Ok(consuming)
+}
+
+/// The transitions of the substring of every capture group, and the groups themselves.
+type GroupSubstrings = (Vec<BTreeSet<(usize, usize)>>, Vec<CaptureGroup>);
+
+/// For every capture group, the transitions consuming a byte inside it and those consuming a
+/// byte outside it, and the groups themselves.
+type GroupTransitions = (
+    Vec<BTreeSet<(usize, usize)>>,
+    Vec<BTreeSet<(usize, usize)>>,
+    Vec<CaptureGroup>,
+);
+
+/// Derives the substrings of a raw regex from its capture groups, numbered from 1 in the
+/// order of their opening parentheses as in standard regex engines, nested groups included.
+///
+/// # Arguments
+///
+/// * `regex` - The raw regex.
+/// * `dfa` - The DFA of the regex.
+///
+/// # Returns
+///
+/// A `Result` containing, for every capture group, its transitions and its description, or
+/// a `CompilerError`.
+fn capture_group_substrings(regex: &str, dfa: &DFAGraph) -> Result<GroupSubstrings, CompilerError> {
+    let (inside, _, groups) = group_transitions(regex, dfa)?;
+    Ok((inside, groups))
+}
+
+/// Finds the transitions of a DFA consuming a byte inside and outside every capture group of
+/// its regex.
+///
+/// The DFA is run alongside the NFA of the regex, whose threads record the groups open on
+/// their path. A transition of the DFA is inside a group when some thread that can still
+/// complete a match consumes its byte inside the group, and outside it when some such thread
+/// consumes its byte outside the group. A transition can be both.
+///
+/// # Arguments
+///
+/// * `regex` - The raw regex.
+/// * `dfa` - The DFA of the regex.
+///
+/// # Returns
+///
+/// A `Result` containing, for every capture group, the transitions inside it, those outside
+/// it and its description, or a `CompilerError`.
+fn group_transitions(regex: &str, dfa: &DFAGraph) -> Result<GroupTransitions, CompilerError> {
+    // The DFA is built from the primed regex, see `create_dfa_graph_from_regex`.
+    let nfa = thompson::Compiler::new()
+        .syntax(syntax::Config::new().utf8(false))
+        .configure(thompson::Config::new().which_captures(WhichCaptures::All))
+        .build(&prime_carets(&absolute_anchors(regex)))
+        .map_err(|err| CompilerError::ParseError(format!("Invalid regex {}: {}", regex, err)))?;
+    let num_groups = nfa.group_info().group_len(PatternID::ZERO);
+    let groups = (1..num_groups)
+        .map(|index| CaptureGroup {
+            index,
+            name: nfa
+                .group_info()
+                .to_name(PatternID::ZERO, index)
+                .map(str::to_string),
+        })
+        .collect_vec();
+
+    // The NFA states from which a match can still be reached.
+    let mut predecessors: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    let mut live = BTreeSet::new();
+    for id in 0..nfa.states().len() {
+        let nexts = match nfa.state(StateID::new_unchecked(id)) {
+            State::ByteRange { trans } => vec![trans.next],
+            State::Sparse(sparse) => sparse.transitions.iter().map(|t| t.next).collect(),
+            State::Dense(dense) => dense.transitions.to_vec(),
+            State::Look { next, .. } | State::Capture { next, .. } => vec![*next],
+            State::Union { alternates } => alternates.to_vec(),
+            State::BinaryUnion { alt1, alt2 } => vec![*alt1, *alt2],
+            State::Fail => vec![],
+            State::Match { .. } => {
+                live.insert(id);
+                vec![]
+            }
+        };
+        for next in nexts {
+            predecessors.entry(next.as_usize()).or_default().push(id);
+        }
+    }
+    let mut stack = live.iter().copied().collect_vec();
+    while let Some(id) = stack.pop() {
+        for &prev in predecessors.get(&id).into_iter().flatten() {
+            if live.insert(prev) {
+                stack.push(prev);
+            }
+        }
+    }
+
+    let index_of = dfa
+        .states
+        .iter()
+        .enumerate()
+        .map(|(idx, state)| (state.state_id, idx))
+        .collect::<BTreeMap<_, _>>();
+    let mut ranges = vec![BTreeSet::new(); groups.len()];
+    let mut outside = vec![BTreeSet::new(); groups.len()];
+    let start = (
+        dfa.states.first().map_or(0, |state| state.state_id),
+        group_closure(
+            &nfa,
+            vec![(nfa.start_anchored().as_usize(), BTreeSet::new())],
+            true,
+        )?,
+    );
+    let mut seen = BTreeSet::from([start.clone()]);
+    let mut queue = VecDeque::from([start]);
+    while let Some((state, threads)) = queue.pop_front() {
+        for (&next, bytes) in &dfa.states[index_of[&state]].transitions {
+            for &byte in bytes {
+                let mut stepped = vec![];
+                for (id, open) in &threads {
+                    let target = match nfa.state(StateID::new_unchecked(*id)) {
+                        State::ByteRange { trans } => {
+                            trans.matches_byte(byte).then_some(trans.next)
+                        }
+                        State::Sparse(sparse) => sparse.matches_byte(byte),
+                        State::Dense(dense) => dense.matches_byte(byte),
+                        _ => None,
+                    };
+                    if let Some(target) = target.filter(|target| live.contains(&target.as_usize()))
+                    {
+                        for group in 1..num_groups {
+                            match open.contains(&group) {
+                                true => ranges[group - 1].insert((state, next)),
+                                false => outside[group - 1].insert((state, next)),
+                            };
+                        }
+                        stepped.push((target.as_usize(), open.clone()));
+                    }
+                }
+                let node = (next, group_closure(&nfa, stepped, false)?);
+                if seen.insert(node.clone()) {
+                    queue.push_back(node);
+                }
+            }
+        }
+    }
+    Ok((ranges, outside, groups))
+}
+
+/// Creates a RegexAndDFA from a raw regex whose substrings are its capture groups, see
+/// `capture_group_substrings`.
+///
+/// # Arguments
+///
+/// * `regex_str` - The raw regex.
+///
+/// # Returns
+///
+/// A `Result` containing the RegexAndDFA with one substring per capture group, or a
+/// `CompilerError` if the regex has no capture group.
+pub(crate) fn create_regex_and_dfa_from_groups(
+    regex_str: &str,
+) -> Result<RegexAndDFA, CompilerError> {
+    let mut regex_and_dfa = create_regex_and_dfa_from_str_and_defs(
+        regex_str,
+        SubstringDefinitionsJson {
+            transitions: vec![],
+            names: vec![],
+        },
+    )?;
+    let (ranges, groups) = capture_group_substrings(regex_str, &regex_and_dfa.dfa)?;
+    if groups.is_empty() {
+        return Err(CompilerError::GenericError(format!(
+            "the regex {} has no capture group",
+            regex_str
+        )));
+    }
+    debug!(
+        groups = groups.len(),
+        "derived the substrings of the capture groups"
+    );
+    regex_and_dfa.substrings.substring_ranges = ranges;
+    regex_and_dfa.substrings.substring_groups = Some(groups);
+    Ok(regex_and_dfa)
+}
+
+/// The prefix of the names of the groups marking the counted repetitions of a regex, see
+/// `count_repetitions`.
+const COUNTER_GROUP: &str = "zkregex_counter";
+
+/// Replaces the large bounded repetitions of a regex by unbounded ones, to be matched with a
+/// counter rather than unrolled into a state per repetition.
+///
+/// A repetition is counted when it repeats an expression matching exactly one byte more than
+/// `threshold` times, e.g. `[a-z]{1,500}`, and is not itself repeated. It then repeats its
+/// byte at least once, or any number of times if its minimum is 0.
+///
+/// # Arguments
+///
+/// * `hir` - The parsed regex.
+/// * `threshold` - The number of repetitions above which a repetition is counted.
+/// * `nested` - Whether the regex is repeated as a whole.
+/// * `counters` - The counted repetitions found so far, extended in order.
+///
+/// # Returns
+///
+/// The rewritten regex, and the same with every counted repetition in a group named after
+/// its counter.
+fn count_repetitions(
+    hir: &Hir,
+    threshold: usize,
+    nested: bool,
+    counters: &mut Vec<RepetitionCounter>,
+) -> (Hir, Hir) {
+    match hir.kind() {
+        HirKind::Repetition(repetition) => {
+            let properties = repetition.sub.properties();
+            let single_byte =
+                properties.minimum_len() == Some(1) && properties.maximum_len() == Some(1);
+            let bound = repetition.max.unwrap_or(repetition.min) as usize;
+            if !nested && single_byte && bound > threshold {
+                let unbounded = Hir::repetition(Repetition {
+                    min: repetition.min.min(1),
+                    max: None,
+                    greedy: repetition.greedy,
+                    sub: repetition.sub.clone(),
+                });
+                let marked = Hir::capture(Capture {
+                    index: 0,
+                    name: Some(format!("{}{}", COUNTER_GROUP, counters.len()).into()),
+                    sub: Box::new(unbounded.clone()),
+                });
+                counters.push(RepetitionCounter {
+                    repetition: hir.to_string(),
+                    min: repetition.min as usize,
+                    max: repetition.max.map(|max| max as usize),
+                    transitions: BTreeSet::new(),
+                });
+                return (unbounded, marked);
+            }
+            let (sub, marked) = count_repetitions(&repetition.sub, threshold, true, counters);
+            let rebuild = |sub: Hir| {
+                Hir::repetition(Repetition {
+                    sub: Box::new(sub),
+                    ..repetition.clone()
+                })
+            };
+            (rebuild(sub), rebuild(marked))
+        }
+        HirKind::Capture(capture) => {
+            let (sub, marked) = count_repetitions(&capture.sub, threshold, nested, counters);
+            let rebuild = |sub: Hir| {
+                Hir::capture(Capture {
+                    sub: Box::new(sub),
+                    ..capture.clone()
+                })
+            };
+            (rebuild(sub), rebuild(marked))
+        }
+        HirKind::Concat(subs) | HirKind::Alternation(subs) => {
+            let (subs, marked): (Vec<_>, Vec<_>) = subs
+                .iter()
+                .map(|sub| count_repetitions(sub, threshold, nested, counters))
+                .unzip();
+            match hir.kind() {
+                HirKind::Concat(_) => (Hir::concat(subs), Hir::concat(marked)),
+                _ => (Hir::alternation(subs), Hir::alternation(marked)),
+            }
+        }
+        _ => (hir.clone(), hir.clone()),
+    }
+}
+
+/// Creates a RegexAndDFA from a raw regex, matching its bounded repetitions of a single byte
+/// repeating more than `threshold` times with counters, see `count_repetitions`.
+///
+/// A counter counts the consecutive transitions of its repetition, so every transition of
+/// the DFA must consume a byte either inside the repetition or outside it, for all the ways
+/// the regex can match. Otherwise, as in `[a-z]{1,500}z`, the repetition cannot be counted.
+///
+/// # Arguments
+///
+/// * `regex_str` - The raw regex.
+/// * `threshold` - The number of repetitions above which a repetition is counted.
+/// * `capture_groups` - Whether the substrings are the capture groups of the regex.
+/// * `substrs_defs_json` - Otherwise, the substrings, which must not list any transition as
+///   the DFA differs from the one of the regex as written.
+///
+/// # Returns
+///
+/// A `Result` containing the RegexAndDFA with its counters, or a `CompilerError` if a
+/// repetition cannot be counted.
+pub(crate) fn create_counted_regex_and_dfa(
+    regex_str: &str,
+    threshold: usize,
+    capture_groups: bool,
+    substrs_defs_json: SubstringDefinitionsJson,
+) -> Result<RegexAndDFA, CompilerError> {
+    if !capture_groups && substrs_defs_json.transitions.iter().any(|t| !t.is_empty()) {
+        return Err(CompilerError::GenericError(
+            "the substrings of a regex whose repetitions are counted must be its capture groups"
+                .to_string(),
+        ));
+    }
+    check_syntax(regex_str)?;
+    let hir = regex_syntax::ParserBuilder::new()
+        .utf8(false)
+        .build()
+        .parse(regex_str)
+        .map_err(|err| {
+            CompilerError::ParseError(format!("Invalid regex {}: {}", regex_str, err))
+        })?;
+    let mut counters = vec![];
+    let (counted, marked) = count_repetitions(&hir, threshold, false, &mut counters);
+    let counted = match counters.is_empty() {
+        true => regex_str.to_string(),
+        false => counted.to_string(),
+    };
+    let mut regex_and_dfa = match capture_groups {
+        true => create_regex_and_dfa_from_groups(&counted)?,
+        false => create_regex_and_dfa_from_str_and_defs(&counted, substrs_defs_json)?,
+    };
+    regex_and_dfa.regex_pattern = regex_str.to_string();
+    if counters.is_empty() {
+        return Ok(regex_and_dfa);
+    }
+
+    let (inside, outside, groups) = group_transitions(&marked.to_string(), &regex_and_dfa.dfa)?;
+    for (idx, counter) in counters.iter_mut().enumerate() {
+        let name = format!("{}{}", COUNTER_GROUP, idx);
+        let group = groups
+            .iter()
+            .position(|group| group.name.as_deref() == Some(name.as_str()))
+            .ok_or_else(|| CompilerError::GenericError(format!("the group {} is missing", name)))?;
+        if !inside[group].is_disjoint(&outside[group]) {
+            return Err(CompilerError::GenericError(format!(
+                "the repetition {} of {} cannot be matched with a counter, as the bytes it \
+                 repeats can also be matched around it",
+                counter.repetition, regex_str
+            )));
+        }
+        counter.transitions = inside[group].clone();
+    }
+    info!(
+        counters = counters.len(),
+        states = regex_and_dfa.dfa.states.len(),
+        "matching the large repetitions of a raw regex with counters"
+    );
+    regex_and_dfa.counters = counters;
+    Ok(regex_and_dfa)
 }
 
 /// Gets the index of the accepted state in a DFA graph.
@@ -1132,8 +2289,229 @@ pub(crate) fn get_max_state(dfa: &DFAGraph) -> usize {
         .unwrap_or_default()
 }
 
+/// Finds the states of a DFA that lie on a path from the initial state to an accept state.
+///
+/// # Arguments
+///
+/// * `dfa` - A reference to a `DFAGraph`.
+///
+/// # Returns
+///
+/// The IDs of the states reachable from the initial state from which an accept state can be
+/// reached.
+fn live_states(dfa: &DFAGraph) -> BTreeSet<usize> {
+    let edges = dfa
+        .states
+        .iter()
+        .flat_map(|state| {
+            state
+                .transitions
+                .keys()
+                .map(move |&next| (state.state_id, next))
+        })
+        .collect_vec();
+    let closure = |start: Vec<usize>, forward: bool| {
+        let adjacent = edges
+            .iter()
+            .map(|&(from, to)| if forward { (from, to) } else { (to, from) })
+            .into_group_map();
+        let mut seen = start.iter().copied().collect::<BTreeSet<_>>();
+        let mut stack = start;
+        while let Some(state) = stack.pop() {
+            for &next in adjacent.get(&state).into_iter().flatten() {
+                if seen.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        seen
+    };
+    let accept_states = dfa
+        .states
+        .iter()
+        .filter(|state| state.state_type == "accept")
+        .map(|state| state.state_id)
+        .collect_vec();
+    closure(vec![0], true)
+        .intersection(&closure(accept_states, false))
+        .copied()
+        .collect()
+}
+
+/// Removes the states of the DFA that are unreachable from the initial state, and those from
+/// which no accept state can be reached, so that no code is generated for them.
+///
+/// The remaining states keep their order and are numbered from 0, and the transitions and
+/// boundaries of the substrings are renumbered with them. The initial state is always kept.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A mutable reference to the RegexAndDFA to prune.
+pub(crate) fn prune_dead_states(regex_and_dfa: &mut RegexAndDFA) {
+    let mut live = live_states(&regex_and_dfa.dfa);
+    live.insert(0);
+    let dfa = &mut regex_and_dfa.dfa;
+    if live.len() == dfa.states.len() {
+        return;
+    }
+    let ids = live
+        .iter()
+        .enumerate()
+        .map(|(id, &state)| (state, id))
+        .collect::<BTreeMap<_, _>>();
+    debug!(
+        pruned = dfa.states.len() - live.len(),
+        "pruned the dead states of the DFA"
+    );
+    dfa.states.retain(|state| live.contains(&state.state_id));
+    for state in &mut dfa.states {
+        state.state_id = ids[&state.state_id];
+        state.transitions = std::mem::take(&mut state.transitions)
+            .into_iter()
+            .filter_map(|(next, bytes)| Some((*ids.get(&next)?, bytes)))
+            .collect();
+    }
+    let substrings = &mut regex_and_dfa.substrings;
+    for ranges in &mut substrings.substring_ranges {
+        *ranges = ranges
+            .iter()
+            .filter_map(|(from, to)| Some((*ids.get(from)?, *ids.get(to)?)))
+            .collect();
+    }
+    let renumber = |states: &BTreeSet<usize>| {
+        states
+            .iter()
+            .filter_map(|state| ids.get(state).copied())
+            .collect::<BTreeSet<_>>()
+    };
+    for (starts, ends) in substrings.substring_boundaries.iter_mut().flatten() {
+        *starts = renumber(starts);
+        *ends = renumber(ends);
+    }
+}
+
+/// Finds the largest total weight of the transitions on a path from the initial state to an
+/// accept state.
+///
+/// # Arguments
+///
+/// * `dfa` - A reference to a `DFAGraph`.
+/// * `weight` - The weight of the transition between two states on the given bytes.
+///
+/// # Returns
+///
+/// The largest weight, or `None` if a cycle of positive weight lies on such a path, so that
+/// the weight is unbounded.
+fn longest_accepting_path(
+    dfa: &DFAGraph,
+    weight: impl Fn(usize, usize, &BTreeSet<u8>) -> usize,
+) -> Option<usize> {
+    let edges = dfa
+        .states
+        .iter()
+        .flat_map(|state| {
+            state
+                .transitions
+                .iter()
+                .map(move |(&to, bytes)| (state.state_id, to, bytes))
+        })
+        .collect_vec();
+    let accept_states = dfa
+        .states
+        .iter()
+        .filter(|state| state.state_type == "accept")
+        .map(|state| state.state_id)
+        .collect_vec();
+    let live = live_states(dfa);
+
+    let mut graph = DiGraphMap::<usize, usize>::new();
+    for &state in &live {
+        graph.add_node(state);
+    }
+    for &(from, to, bytes) in &edges {
+        if live.contains(&from) && live.contains(&to) {
+            let weight = weight(from, to, bytes);
+            let existing = graph.edge_weight(from, to).copied().unwrap_or(0);
+            graph.add_edge(from, to, weight.max(existing));
+        }
+    }
+    // Tarjan's algorithm lists the components in reverse topological order.
+    let components = tarjan_scc(&graph);
+    let component_of = components
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, component)| component.iter().map(move |&state| (state, idx)))
+        .collect::<BTreeMap<_, _>>();
+    let mut longest = vec![None; components.len()];
+    if let Some(&start) = component_of.get(&0) {
+        longest[start] = Some(0);
+    }
+    for idx in (0..components.len()).rev() {
+        for &from in &components[idx] {
+            for (_, to, &weight) in graph.edges(from) {
+                if component_of[&to] == idx {
+                    if weight > 0 {
+                        return None;
+                    }
+                    continue;
+                }
+                if let Some(length) = longest[idx] {
+                    let next = &mut longest[component_of[&to]];
+                    *next = Some(next.unwrap_or(0).max(length + weight));
+                }
+            }
+        }
+    }
+    Some(
+        accept_states
+            .iter()
+            .filter_map(|state| component_of.get(state).and_then(|&idx| longest[idx]))
+            .max()
+            .unwrap_or(0),
+    )
+}
+
+/// Computes the length in bytes of the longest match of a DFA, without the byte 255 priming `^`.
+///
+/// # Arguments
+///
+/// * `dfa` - A reference to a `DFAGraph`.
+///
+/// # Returns
+///
+/// The length of the longest match, or `None` if the matches are unbounded.
+#[cfg(feature = "fs")]
+pub(crate) fn max_match_len(dfa: &DFAGraph) -> Option<usize> {
+    longest_accepting_path(dfa, |_, _, bytes| {
+        bytes.iter().any(|&byte| byte != u8::MAX) as usize
+    })
+}
+
+/// Computes the length in bytes of the longest capture of every substring of a match.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the DFA and substrings.
+///
+/// # Returns
+///
+/// The length of the longest capture of every substring, or `None` for an unbounded one.
+pub(crate) fn max_capture_lens(regex_and_dfa: &RegexAndDFA) -> Vec<Option<usize>> {
+    regex_and_dfa
+        .substrings
+        .substring_ranges
+        .iter()
+        .map(|ranges| {
+            longest_accepting_path(&regex_and_dfa.dfa, |from, to, bytes| {
+                (ranges.contains(&(from, to)) && bytes.iter().any(|&byte| byte != u8::MAX)) as usize
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
 mod dfa_test {
-    use crate::regex::{create_dfa_graph_from_regex, match_string_with_dfa_graph};
+    use crate::regex::{create_dfa_graph_from_regex, match_string_with_dfa_graph, DFAGraph};
     use serde::{Deserialize, Serialize};
     use std::{env, fs::File, io::BufReader, path::PathBuf};
 
@@ -1144,6 +2522,22 @@ mod dfa_test {
         pub fail: Vec<String>,
     }
 
+    /// Matches an input preceded by the byte 255 priming `^`, as the generated matchers do.
+    fn matches_primed(graph: &DFAGraph, input: &str) -> bool {
+        let step = |state: usize, byte: u8| {
+            graph.states[state]
+                .transitions
+                .iter()
+                .find(|(_, bytes)| bytes.contains(&byte))
+                .map(|(&next, _)| next)
+        };
+        let start = step(0, u8::MAX).unwrap_or(0);
+        input
+            .bytes()
+            .try_fold(start, step)
+            .is_some_and(|state| graph.states[state].state_type == "accept")
+    }
+
     #[test]
     fn test_dfa_graph() {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -1166,7 +2560,7 @@ mod dfa_test {
 
             for pass_case in case.pass {
                 assert!(
-                    match_string_with_dfa_graph(&dfa_graph, &pass_case),
+                    matches_primed(&dfa_graph, &pass_case),
                     "Positive case failed for regex '{}': '{}'",
                     case.regex,
                     pass_case
@@ -1175,7 +2569,7 @@ mod dfa_test {
 
             for fail_case in case.fail {
                 assert!(
-                    !match_string_with_dfa_graph(&dfa_graph, &fail_case),
+                    !matches_primed(&dfa_graph, &fail_case),
                     "Negative case failed for regex '{}': '{}'",
                     case.regex,
                     fail_case
@@ -1183,4 +2577,580 @@ mod dfa_test {
             }
         }
     }
+
+    #[test]
+    fn test_max_match_len() {
+        use crate::regex::{get_regex_and_dfa, max_capture_lens, max_match_len};
+        use crate::structs::{DecomposedRegexConfig, RegexPartConfig};
+
+        let dfa = create_dfa_graph_from_regex("a[0-9]{2,4}(bc|d)").unwrap();
+        assert_eq!(max_match_len(&dfa), Some(7));
+        let dfa = create_dfa_graph_from_regex("a[0-9]+b").unwrap();
+        assert_eq!(max_match_len(&dfa), None);
+
+        let part = |regex_def: &str, is_public: bool| RegexPartConfig {
+            is_public,
+            regex_def: regex_def.to_string(),
+            max_repetitions: None,
+            max_length: None,
+            numeric: false,
+            hole: false,
+            min: None,
+            max: None,
+        };
+        let mut config = DecomposedRegexConfig {
+            parts: [
+                part("(\r\n|^)id:", false),
+                part("[a-z]{1,8}", true),
+                part("=", false),
+                part("[0-9]{1,3}", true),
+                part(";", false),
+            ]
+            .into(),
+        };
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        // The priming byte is not counted, but the line break is.
+        assert_eq!(max_match_len(&regex_and_dfa.dfa), Some(18));
+        assert_eq!(max_capture_lens(&regex_and_dfa), vec![Some(8), Some(3)]);
+    }
+
+    #[test]
+    fn test_scoped_flags() {
+        use crate::regex::{
+            create_regex_and_dfa_from_str_and_defs, find_end_anchor, get_regex_and_dfa,
+            top_level_flags,
+        };
+        use crate::structs::{DecomposedRegexConfig, RegexPartConfig, SubstringDefinitionsJson};
+
+        let regex_and_dfa = create_regex_and_dfa_from_str_and_defs(
+            "(?i:subject):[a-z]+",
+            SubstringDefinitionsJson {
+                transitions: vec![vec![]],
+                names: vec![],
+            },
+        )
+        .unwrap();
+        assert!(match_string_with_dfa_graph(
+            &regex_and_dfa.dfa,
+            "SubJect:abc"
+        ));
+        assert!(!match_string_with_dfa_graph(
+            &regex_and_dfa.dfa,
+            "subject:ABC"
+        ));
+
+        // The flags of a part stay with it when the caret splits it, and do not leak into the
+        // next part.
+        let part = |regex_def: &str, is_public: bool| RegexPartConfig {
+            is_public,
+            regex_def: regex_def.to_string(),
+            max_repetitions: None,
+            max_length: None,
+            numeric: false,
+            hole: false,
+            min: None,
+            max: None,
+        };
+        let mut config = DecomposedRegexConfig {
+            parts: [part("(?i)(^|\r\n)subject:", false), part("[a-z]+", true)].into(),
+        };
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        let accepts = |input: &[u8]| {
+            let mut state = 0;
+            for byte in input {
+                let next = regex_and_dfa.dfa.states[state]
+                    .transitions
+                    .iter()
+                    .find(|(_, bytes)| bytes.contains(byte));
+                match next {
+                    Some((&next, _)) => state = next,
+                    None => return false,
+                }
+            }
+            regex_and_dfa.dfa.states[state].state_type == "accept"
+        };
+        assert!(accepts(b"\xffSUBJECT:abc"));
+        assert!(accepts(b"\r\nSubject:abc"));
+        assert!(!accepts(b"\xffsubject:ABC"));
+        assert_eq!(
+            regex_and_dfa.regex_pattern,
+            "(?:(?i)(^|\r\n))(?:(?i)subject:)[a-z]+"
+        );
+
+        assert_eq!(top_level_flags("(?i)a(?s:.)(b(?x))(?-s)c"), "(?i)(?-s)");
+        assert!(find_end_anchor("(?m:a$)").is_err());
+    }
+
+    #[test]
+    fn test_literal_anchors() {
+        use crate::regex::{
+            create_regex_and_dfa_from_str_and_defs, find_end_anchor, split_anchors,
+        };
+        use crate::structs::SubstringDefinitionsJson;
+
+        let raw = |regex: &str| {
+            create_regex_and_dfa_from_str_and_defs(
+                regex,
+                SubstringDefinitionsJson {
+                    transitions: vec![],
+                    names: vec![],
+                },
+            )
+            .unwrap()
+        };
+        // Escaped dollars and carets are literals wherever they appear.
+        let regex_and_dfa = raw(r"price: \$[0-9]+\.[0-9]{2}");
+        assert!(!regex_and_dfa.has_end_anchor);
+        assert!(match_string_with_dfa_graph(
+            &regex_and_dfa.dfa,
+            "price: $12.50"
+        ));
+        let regex_and_dfa = raw(r"a\^b[$^]c");
+        assert!(match_string_with_dfa_graph(&regex_and_dfa.dfa, "a^b$c"));
+        assert!(match_string_with_dfa_graph(&regex_and_dfa.dfa, "a^b^c"));
+        // A dollar after an escaped backslash is an anchor.
+        let regex_and_dfa = raw(r"a\\$");
+        assert!(regex_and_dfa.has_end_anchor);
+        assert!(match_string_with_dfa_graph(&regex_and_dfa.dfa, "a\\"));
+
+        assert!(!find_end_anchor(r"[0-9]+\$").unwrap());
+        assert!(find_end_anchor(r"[0-9]+\\$").unwrap());
+        assert!(find_end_anchor(r"a$b").is_err());
+        // A caret following input can never match.
+        for regex in [r"a^b", r"x(^a)", r"(a|b)+^c"] {
+            let err = create_regex_and_dfa_from_str_and_defs(
+                regex,
+                SubstringDefinitionsJson {
+                    transitions: vec![],
+                    names: vec![],
+                },
+            )
+            .unwrap_err();
+            assert!(
+                err.to_string().contains("^ can only match at the start"),
+                "{regex}"
+            );
+        }
+        for regex in [r"(\r\n|^)from:", r"(^Subject:|^From:)", r"a?^b", r"^a|^b"] {
+            raw(regex);
+        }
+
+        assert_eq!(split_anchors(r"^\$[0-9]+$"), (true, r"\$[0-9]+", true));
+        assert_eq!(split_anchors(r"\^a\$"), (false, r"\^a\$", false));
+        assert_eq!(split_anchors(r"a\\$"), (false, r"a\\", true));
+        assert_eq!(split_anchors(r"[$]"), (false, r"[$]", false));
+        assert_eq!(split_anchors("^"), (true, "", false));
+    }
+
+    #[test]
+    fn test_absolute_anchors() {
+        use crate::regex::{absolute_anchors, get_regex_and_dfa};
+        use crate::structs::{DecomposedRegexConfig, RegexPartConfig};
+
+        assert_eq!(absolute_anchors(r"\Afrom:[a-z]+\z"), "^from:[a-z]+$");
+        assert_eq!(absolute_anchors(r"(\r\n|\A)to:"), r"(\r\n|^)to:");
+        assert_eq!(absolute_anchors(r"a\\A[\A]\x{41}\z"), r"a\\A[\A]\x{41}$");
+
+        // A decomposed regex anchored with \A and \z compiles as one anchored with ^ and $.
+        let part = |regex_def: &str, is_public: bool| RegexPartConfig {
+            is_public,
+            regex_def: regex_def.to_string(),
+            max_repetitions: None,
+            max_length: None,
+            numeric: false,
+            hole: false,
+            min: None,
+            max: None,
+        };
+        let graph = |first: &str, last: &str| {
+            let mut config = DecomposedRegexConfig {
+                parts: vec![part(first, false), part("[a-z]+", true), part(last, false)].into(),
+            };
+            let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+            (
+                regex_and_dfa.regex_pattern,
+                serde_json::to_string(&regex_and_dfa.dfa).unwrap(),
+                regex_and_dfa.has_end_anchor,
+            )
+        };
+        assert_eq!(graph(r"\Afrom:", r";\z"), graph("^from:", ";$"));
+        assert_eq!(graph(r"(\r\n|\A)from:", ";"), graph(r"(\r\n|^)from:", ";"));
+        // A caret in a later part follows the input matched by the earlier ones.
+        let mut config = DecomposedRegexConfig {
+            parts: vec![part("x", false), part("(^a)", true)].into(),
+        };
+        let err = get_regex_and_dfa(&mut config).unwrap_err();
+        assert!(err.to_string().contains("^ can only match at the start"));
+    }
+
+    #[test]
+    fn test_escaped_metacharacters() {
+        use crate::regex::{
+            create_regex_and_dfa_from_groups, create_regex_and_dfa_from_str_and_defs,
+            get_regex_and_dfa,
+        };
+        use crate::structs::{DecomposedRegexConfig, RegexPartConfig, SubstringDefinitionsJson};
+
+        for meta in ".[](){}\\|+*?^$-/".chars() {
+            let regex = format!(r"a\{}b", meta);
+            let literal = format!("a{}b", meta);
+            let regex_and_dfa = create_regex_and_dfa_from_str_and_defs(
+                &regex,
+                SubstringDefinitionsJson {
+                    transitions: vec![],
+                    names: vec![],
+                },
+            )
+            .unwrap_or_else(|err| panic!("{}: {}", regex, err));
+            assert!(
+                match_string_with_dfa_graph(&regex_and_dfa.dfa, &literal),
+                "{}",
+                regex
+            );
+            assert!(
+                !match_string_with_dfa_graph(&regex_and_dfa.dfa, "axb"),
+                "{}",
+                regex
+            );
+            assert!(
+                !match_string_with_dfa_graph(&regex_and_dfa.dfa, "ab"),
+                "{}",
+                regex
+            );
+
+            // The same escapes inside a character class and within decomposed parts.
+            let mut config = DecomposedRegexConfig {
+                parts: [
+                    RegexPartConfig {
+                        is_public: false,
+                        regex_def: format!(r"a\{}", meta),
+                        max_repetitions: None,
+                        max_length: None,
+                        numeric: false,
+                        hole: false,
+                        min: None,
+                        max: None,
+                    },
+                    RegexPartConfig {
+                        is_public: true,
+                        regex_def: format!(r"[\{}]+", meta),
+                        max_repetitions: None,
+                        max_length: None,
+                        numeric: false,
+                        hole: false,
+                        min: None,
+                        max: None,
+                    },
+                ]
+                .into(),
+            };
+            let regex_and_dfa =
+                get_regex_and_dfa(&mut config).unwrap_or_else(|err| panic!("{}: {}", meta, err));
+            let doubled = format!("a{}{}", meta, meta);
+            assert!(
+                match_string_with_dfa_graph(&regex_and_dfa.dfa, &doubled),
+                "{}",
+                meta
+            );
+            assert!(
+                !match_string_with_dfa_graph(&regex_and_dfa.dfa, "a"),
+                "{}",
+                meta
+            );
+        }
+
+        // Ranges between metacharacters, including the dash itself.
+        let regex_and_dfa = create_regex_and_dfa_from_str_and_defs(
+            r"[\(-\+][+-\-][\\-\^]",
+            SubstringDefinitionsJson {
+                transitions: vec![],
+                names: vec![],
+            },
+        )
+        .unwrap();
+        assert!(match_string_with_dfa_graph(&regex_and_dfa.dfa, "(+\\"));
+        assert!(match_string_with_dfa_graph(&regex_and_dfa.dfa, "+-^"));
+        assert!(match_string_with_dfa_graph(&regex_and_dfa.dfa, "*,]"));
+        assert!(!match_string_with_dfa_graph(&regex_and_dfa.dfa, ",+\\"));
+        assert!(!match_string_with_dfa_graph(&regex_and_dfa.dfa, "+.\\"));
+
+        // Escaped parentheses and pipes neither open groups nor split alternatives.
+        let regex_and_dfa = create_regex_and_dfa_from_groups(r"\(([0-9]+)\|\)").unwrap();
+        assert_eq!(regex_and_dfa.substrings.substring_groups.unwrap().len(), 1);
+        assert!(match_string_with_dfa_graph(&regex_and_dfa.dfa, "(42|)"));
+        for &(from, to) in &regex_and_dfa.substrings.substring_ranges[0] {
+            let bytes = &regex_and_dfa.dfa.states[from].transitions[&to];
+            assert!(bytes.iter().all(u8::is_ascii_digit));
+        }
+    }
+
+    #[test]
+    fn test_class_set_operations() {
+        use crate::regex::get_regex_and_dfa;
+        use crate::structs::{DecomposedRegexConfig, RegexPartConfig};
+
+        let consonants = create_dfa_graph_from_regex(r"[a-z&&[^aeiou]]+").unwrap();
+        assert!(match_string_with_dfa_graph(&consonants, "rhythm"));
+        assert!(!match_string_with_dfa_graph(&consonants, "rhyme"));
+        // Difference, symmetric difference and classes nested in a negated class.
+        for (regex, accepted, rejected) in [
+            (r"[a-z--aeiou]+", "xyz", "xyza"),
+            (r"[0-9a-f~~a-z]+", "0g9z", "0a"),
+            (r"[^[0-9][\s]]+", "a,b", "a b"),
+            (r"[\p{Greek}&&\p{Ll}]+", "αβγ", "αΒγ"),
+            (r"[[:alnum:]--[:digit:]]+", "Ab", "A1"),
+        ] {
+            let graph = create_dfa_graph_from_regex(regex).unwrap();
+            assert!(match_string_with_dfa_graph(&graph, accepted), "{}", regex);
+            assert!(!match_string_with_dfa_graph(&graph, rejected), "{}", regex);
+        }
+
+        // A public part captures the bytes of its class, with the operations applied.
+        let mut config = DecomposedRegexConfig {
+            parts: [("id:", false), ("[a-z0-9&&[^0o]]+", true), (";", false)]
+                .map(|(regex_def, is_public)| RegexPartConfig {
+                    is_public,
+                    regex_def: regex_def.to_string(),
+                    max_repetitions: None,
+                    max_length: None,
+                    numeric: false,
+                    hole: false,
+                    min: None,
+                    max: None,
+                })
+                .into(),
+        };
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        assert!(match_string_with_dfa_graph(&regex_and_dfa.dfa, "id:k1;"));
+        assert!(!match_string_with_dfa_graph(&regex_and_dfa.dfa, "id:k0;"));
+        for &(from, to) in &regex_and_dfa.substrings.substring_ranges[0] {
+            let bytes = &regex_and_dfa.dfa.states[from].transitions[&to];
+            assert!(bytes.iter().all(|&byte| byte != b'0' && byte != b'o'));
+        }
+    }
+
+    #[test]
+    fn test_syntax_error_recovery() {
+        use crate::regex::{get_regex_and_dfa, syntax_errors, MAX_SYNTAX_ERRORS};
+        use crate::structs::{DecomposedRegexConfig, RegexPartConfig};
+
+        for regex in [
+            r"[a-z&&[^aeiou]]+",
+            r"(?i)x{2,3}",
+            r"\p{Greek}|\$[0-9]+",
+            "(foo|)",
+        ] {
+            assert!(syntax_errors(regex).is_empty(), "{}", regex);
+        }
+        let errors = syntax_errors(r"*a\qb[z-a]");
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors[0].starts_with("repetition operator missing expression at byte 0 (\"*\")"));
+        assert!(errors[1].starts_with(r#"unrecognized escape sequence at byte 2 ("\q")"#));
+        assert!(errors[2].contains("at byte 6 (\"z-a\")"));
+        // A masked repetition count does not hide what follows it.
+        let errors = syntax_errors(r"a{,3}(b\p{Gerek}");
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors[0].starts_with(r"unknown Unicode property class \p{Gerek}"));
+        assert!(errors[1].contains("at byte 2"));
+        assert!(errors[2].starts_with("unclosed group at byte 5"));
+        assert_eq!(syntax_errors(&"(".repeat(40)).len(), MAX_SYNTAX_ERRORS);
+
+        // Every part of a decomposed regex is checked before failing.
+        let mut config = DecomposedRegexConfig {
+            parts: [("(from", false), ("[a-z]+", true), ("[x;", false)]
+                .map(|(regex_def, is_public)| RegexPartConfig {
+                    is_public,
+                    regex_def: regex_def.to_string(),
+                    max_repetitions: None,
+                    max_length: None,
+                    numeric: false,
+                    hole: false,
+                    min: None,
+                    max: None,
+                })
+                .into(),
+        };
+        let err = get_regex_and_dfa(&mut config).unwrap_err().to_string();
+        let lines = err.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2, "{}", err);
+        assert!(lines[0].starts_with("part 0: unclosed group at byte 0"));
+        assert!(lines[1].starts_with("part 2: unclosed character class at byte 0"));
+    }
+
+    #[test]
+    fn test_empty_alternation_branches() {
+        use crate::regex::get_regex_and_dfa;
+        use crate::structs::{DecomposedRegexConfig, RegexPartConfig};
+
+        // An empty branch makes its group optional, wherever it appears in the group.
+        for (regex, optional) in [
+            ("x(|foo)y", "x(foo)?y"),
+            ("x(foo||bar)y", "x(foo|bar)?y"),
+            ("x(foo|)y", "x(foo)?y"),
+            ("|foo", "(foo)?"),
+            ("foo|", "(foo)?"),
+            ("foo|bar", "(foo|bar)"),
+        ] {
+            let graph = create_dfa_graph_from_regex(regex).unwrap();
+            let expected = create_dfa_graph_from_regex(optional).unwrap();
+            assert_eq!(graph.states.len(), expected.states.len(), "{}", regex);
+            for state in &graph.states {
+                assert!(
+                    state.transitions.keys().all(|&to| to < graph.states.len()),
+                    "{}",
+                    regex
+                );
+            }
+            for input in ["", "xy", "xfooy", "xbary", "foo", "bar", "foobar", "x"] {
+                assert_eq!(
+                    match_string_with_dfa_graph(&graph, input),
+                    match_string_with_dfa_graph(&expected, input),
+                    "{} on {:?}",
+                    regex,
+                    input
+                );
+            }
+        }
+
+        // A public part with an empty branch captures like its optional form.
+        let substrings = |public: &str| {
+            let mut config = DecomposedRegexConfig {
+                parts: [("id:", false), (public, true), (";", false)]
+                    .map(|(regex_def, is_public)| RegexPartConfig {
+                        is_public,
+                        regex_def: regex_def.to_string(),
+                        max_repetitions: None,
+                        max_length: None,
+                        numeric: false,
+                        hole: false,
+                        min: None,
+                        max: None,
+                    })
+                    .into(),
+            };
+            let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+            assert!(match_string_with_dfa_graph(&regex_and_dfa.dfa, "id:;"));
+            assert!(match_string_with_dfa_graph(&regex_and_dfa.dfa, "id:ab;"));
+            regex_and_dfa.substrings.substring_ranges
+        };
+        assert_eq!(substrings("(ab|)"), substrings("(ab)?"));
+        assert_eq!(substrings("(|ab|cd)"), substrings("(ab|cd)?"));
+    }
+
+    #[test]
+    fn test_unicode_property_classes() {
+        use crate::regex::{create_regex_and_dfa_from_str_and_defs, get_regex_and_dfa};
+        use crate::structs::{DecomposedRegexConfig, RegexPartConfig, SubstringDefinitionsJson};
+
+        let graph = create_dfa_graph_from_regex(r"\p{Lu}\p{Ll}*").unwrap();
+        for input in ["Zoë", "Ωμέγα", "Łódź", "Ж"] {
+            assert!(match_string_with_dfa_graph(&graph, input), "{:?}", input);
+        }
+        for input in ["zoë", "Z0", "日本"] {
+            assert!(!match_string_with_dfa_graph(&graph, input), "{:?}", input);
+        }
+        let graph = create_dfa_graph_from_regex(r"[\p{Greek}\pN]+").unwrap();
+        assert!(match_string_with_dfa_graph(&graph, "αβγ٣7"));
+        assert!(!match_string_with_dfa_graph(&graph, "αbγ"));
+        let graph = create_dfa_graph_from_regex(r"\P{L}").unwrap();
+        assert!(match_string_with_dfa_graph(&graph, "€"));
+        assert!(!match_string_with_dfa_graph(&graph, "é"));
+
+        // A public part capturing a property class captures every byte of its characters.
+        let mut config = DecomposedRegexConfig {
+            parts: [("name:", false), (r"\p{Greek}+", true), (";", false)]
+                .map(|(regex_def, is_public)| RegexPartConfig {
+                    is_public,
+                    regex_def: regex_def.to_string(),
+                    max_repetitions: None,
+                    max_length: None,
+                    numeric: false,
+                    hole: false,
+                    min: None,
+                    max: None,
+                })
+                .into(),
+        };
+        let regex_and_dfa = get_regex_and_dfa(&mut config).unwrap();
+        assert!(match_string_with_dfa_graph(
+            &regex_and_dfa.dfa,
+            "name:Σοφία;"
+        ));
+        let captured: usize = regex_and_dfa.substrings.substring_ranges[0]
+            .iter()
+            .map(|&(from, to)| regex_and_dfa.dfa.states[from].transitions[&to].len())
+            .sum();
+        assert!(captured > 128, "{}", captured);
+
+        let err = create_regex_and_dfa_from_str_and_defs(
+            r"a\\p{Foo}\p{Greek}\p{Gerek}",
+            SubstringDefinitionsJson {
+                transitions: vec![vec![]],
+                names: vec![],
+            },
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with(r"unknown Unicode property class \p{Gerek} in"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_prune_dead_states() {
+        use crate::regex::{create_regex_and_dfa_from_str_and_defs, prune_dead_states};
+        use crate::structs::{DFAStateNode, SubstringDefinitionsJson};
+        use std::collections::{BTreeMap, BTreeSet};
+
+        let mut regex_and_dfa = create_regex_and_dfa_from_str_and_defs(
+            "ab",
+            SubstringDefinitionsJson {
+                transitions: vec![vec![(1, 2)]],
+                names: vec![],
+            },
+        )
+        .unwrap();
+        let pruned = regex_and_dfa.clone();
+        // A state reached from the initial state that cannot reach an accept state, inserted
+        // before the others, and a state that cannot be reached at all.
+        let num_states = regex_and_dfa.dfa.states.len();
+        for state in &mut regex_and_dfa.dfa.states {
+            state.state_id += 1;
+            state.transitions = std::mem::take(&mut state.transitions)
+                .into_iter()
+                .map(|(next, bytes)| (next + 1, bytes))
+                .collect();
+        }
+        regex_and_dfa.dfa.states[0].state_id = 0;
+        regex_and_dfa.dfa.states[0]
+            .transitions
+            .insert(1, BTreeSet::from([b'x']));
+        regex_and_dfa.dfa.states.insert(
+            1,
+            DFAStateNode {
+                state_type: String::new(),
+                state_id: 1,
+                transitions: BTreeMap::from([(1, BTreeSet::from([b'y']))]),
+            },
+        );
+        regex_and_dfa.dfa.states.push(DFAStateNode {
+            state_type: String::new(),
+            state_id: num_states + 1,
+            transitions: BTreeMap::from([(0, BTreeSet::from([b'z']))]),
+        });
+        regex_and_dfa.substrings.substring_ranges = vec![BTreeSet::from([(2, 3), (0, 1)])];
+
+        prune_dead_states(&mut regex_and_dfa);
+        assert_eq!(regex_and_dfa.dfa.states.len(), pruned.dfa.states.len());
+        assert_eq!(
+            serde_json::to_string(&regex_and_dfa.dfa).unwrap(),
+            serde_json::to_string(&pruned.dfa).unwrap()
+        );
+        assert_eq!(
+            regex_and_dfa.substrings.substring_ranges,
+            vec![BTreeSet::from([(1, 2)])]
+        );
+    }
 }
@@ -0,0 +1,248 @@
+use crate::{
+    errors::CompilerError,
+    noir::{
+        capture_ambiguity_warning, capture_bound_warnings, estimate_constraints_per_unit,
+        profile_corpus, report_proving_costs, trivial_match_warning,
+    },
+    regex::{max_capture_lens, max_match_len},
+    structs::{CompileReport, DFAStats, NoirOptions, NoirStrategy, OutputFile, PatternReport},
+    RegexAndDFA,
+};
+use itertools::Itertools;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{read, read_dir, File},
+    path::{Path, PathBuf},
+};
+
+/// The number of entries of the `next_state` table above which the table strategy is flagged.
+const LARGE_TABLE_ENTRIES: usize = 1 << 16;
+
+/// Summarizes a compiled pattern for the compile report.
+///
+/// # Arguments
+///
+/// * `source` - The decomposed regex file or the raw regex the pattern was compiled from.
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether the substrings are extracted.
+/// * `noir_options` - The options of the Noir matcher, if one is generated.
+/// * `outputs` - The files and directories written for the pattern.
+///
+/// # Returns
+///
+/// A `Result` containing the `PatternReport`, or a `CompilerError` if an output cannot be read.
+pub(crate) fn pattern_report(
+    source: &str,
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    noir_options: Option<&NoirOptions>,
+    outputs: &[PathBuf],
+) -> Result<PatternReport, CompilerError> {
+    Ok(summarize_pattern(
+        source,
+        regex_and_dfa,
+        gen_substrs,
+        noir_options,
+        outputs,
+        hash_outputs(outputs)?,
+    ))
+}
+
+/// Summarizes a compiled pattern for the compile report, from the contents of its outputs
+/// before they are written.
+///
+/// # Arguments
+///
+/// * `source` - The decomposed regex file or the raw regex the pattern was compiled from.
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether the substrings are extracted.
+/// * `noir_options` - The options of the Noir matcher, if one is generated.
+/// * `outputs` - The paths and contents of the files to write for the pattern.
+///
+/// # Returns
+///
+/// The `PatternReport`.
+pub(crate) fn pattern_report_from_contents(
+    source: &str,
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    noir_options: Option<&NoirOptions>,
+    outputs: &[(PathBuf, Vec<u8>)],
+) -> PatternReport {
+    let paths = outputs.iter().map(|(path, _)| path.clone()).collect_vec();
+    let mut files = outputs
+        .iter()
+        .map(|(path, contents)| hash_file(path, contents))
+        .collect_vec();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    summarize_pattern(
+        source,
+        regex_and_dfa,
+        gen_substrs,
+        noir_options,
+        &paths,
+        files,
+    )
+}
+
+/// Builds the `PatternReport` of a compiled pattern whose outputs are hashed.
+fn summarize_pattern(
+    source: &str,
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    noir_options: Option<&NoirOptions>,
+    outputs: &[PathBuf],
+    files: Vec<OutputFile>,
+) -> PatternReport {
+    let dfa = &regex_and_dfa.dfa;
+    PatternReport {
+        source: source.to_string(),
+        regex_pattern: regex_and_dfa.regex_pattern.clone(),
+        gen_substrs,
+        noir_options: noir_options.cloned().unwrap_or_default(),
+        dfa: DFAStats {
+            states: dfa.states.len(),
+            transitions: dfa.states.iter().map(|state| state.transitions.len()).sum(),
+            accept_states: dfa
+                .states
+                .iter()
+                .filter(|state| state.state_type == "accept")
+                .count(),
+            substrings: regex_and_dfa.substrings.substring_ranges.len(),
+            end_anchored: regex_and_dfa.has_end_anchor,
+            max_match_len: max_match_len(dfa),
+            max_capture_lens: max_capture_lens(regex_and_dfa),
+        },
+        estimated_constraints_per_unit: noir_options
+            .map(|options| estimate_constraints_per_unit(regex_and_dfa, gen_substrs, options)),
+        proving_estimates: noir_options
+            .map(|options| report_proving_costs(regex_and_dfa, gen_substrs, options))
+            .unwrap_or_default(),
+        capture_groups: regex_and_dfa
+            .substrings
+            .substring_groups
+            .clone()
+            .filter(|_| gen_substrs),
+        substring_names: regex_and_dfa
+            .substrings
+            .substring_names
+            .clone()
+            .filter(|_| gen_substrs),
+        warnings: pattern_warnings(regex_and_dfa, gen_substrs, noir_options, outputs),
+        corpus: noir_options
+            .filter(|options| !options.corpus.is_empty())
+            .map(|options| profile_corpus(regex_and_dfa, gen_substrs, options)),
+        outputs: files,
+    }
+}
+
+/// Lists the potential problems with a compiled pattern.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA struct containing the regex and DFA information.
+/// * `gen_substrs` - A boolean indicating whether the substrings are extracted.
+/// * `noir_options` - The options of the Noir matcher, if one is generated.
+/// * `outputs` - The files and directories written for the pattern.
+///
+/// # Returns
+///
+/// A Vec of human-readable warnings.
+fn pattern_warnings(
+    regex_and_dfa: &RegexAndDFA,
+    gen_substrs: bool,
+    noir_options: Option<&NoirOptions>,
+    outputs: &[PathBuf],
+) -> Vec<String> {
+    let mut warnings = vec![];
+    if outputs.is_empty() {
+        warnings.push("no output was requested".to_string());
+    }
+    if gen_substrs && regex_and_dfa.substrings.substring_ranges.is_empty() {
+        warnings.push("substrings are extracted but the regex defines none".to_string());
+    }
+    let Some(options) = noir_options else {
+        return warnings;
+    };
+    if gen_substrs {
+        for part in &regex_and_dfa.parts {
+            if let (Some(idx), None) = (part.substr_idx, part.max_length) {
+                warnings.push(format!(
+                    "substring {idx} has no max_length, so its capture is as long as the input"
+                ));
+            }
+        }
+        warnings.extend(capture_bound_warnings(regex_and_dfa));
+        warnings.extend(capture_ambiguity_warning(regex_and_dfa, options));
+    }
+    warnings.extend(trivial_match_warning(regex_and_dfa, options));
+    let table_entries = regex_and_dfa.dfa.states.len() * 256;
+    if options.strategy == NoirStrategy::Table && table_entries > LARGE_TABLE_ENTRIES {
+        warnings.push(format!(
+            "the next_state table holds about {table_entries} entries, consider another strategy"
+        ));
+    }
+    warnings
+}
+
+/// Hashes the written files, walking the written directories.
+///
+/// # Arguments
+///
+/// * `outputs` - The files and directories written for the pattern.
+///
+/// # Returns
+///
+/// A `Result` containing the hashed files sorted by path, or a `CompilerError`.
+fn hash_outputs(outputs: &[PathBuf]) -> Result<Vec<OutputFile>, CompilerError> {
+    let mut files = vec![];
+    for output in outputs {
+        collect_files(output, &mut files)?;
+    }
+    files.sort();
+    files.dedup();
+    files
+        .into_iter()
+        .map(|path| Ok(hash_file(&path, &read(&path)?)))
+        .collect()
+}
+
+/// Hashes the contents of a file.
+fn hash_file(path: &Path, contents: &[u8]) -> OutputFile {
+    OutputFile {
+        path: path.to_string_lossy().into_owned(),
+        bytes: contents.len() as u64,
+        sha256: Sha256::digest(contents)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect(),
+    }
+}
+
+/// Appends `path` to `files` if it is a file, and the files below it if it is a directory.
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), CompilerError> {
+    if path.is_dir() {
+        for entry in read_dir(path)? {
+            collect_files(&entry?.path(), files)?;
+        }
+    } else if path.exists() {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+/// Writes the compile report as pretty-printed JSON.
+///
+/// # Arguments
+///
+/// * `report_path` - The path of the JSON file.
+/// * `report` - The report to write.
+///
+/// # Returns
+///
+/// A `Result` indicating success or a `CompilerError`.
+pub(crate) fn write_report(report_path: &str, report: &CompileReport) -> Result<(), CompilerError> {
+    let file = File::create(report_path)?;
+    serde_json::to_writer_pretty(file, report)?;
+    Ok(())
+}
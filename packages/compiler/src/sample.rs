@@ -0,0 +1,315 @@
+use crate::{
+    errors::CompilerError,
+    structs::{RegexAndDFA, SampleOptions},
+};
+use std::collections::BTreeMap;
+
+/// A small deterministic random generator (SplitMix64), so that a seed always gives the same
+/// samples without depending on a random number crate.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Picks an index below `len`, which must not be 0.
+    fn below(&mut self, len: usize) -> usize {
+        (self.next() % len as u64) as usize
+    }
+}
+
+/// Maps every state of the DFA of a pattern to the state each byte other than the priming one
+/// leads to.
+fn byte_targets(regex_and_dfa: &RegexAndDFA) -> Vec<BTreeMap<u8, usize>> {
+    regex_and_dfa
+        .dfa
+        .states
+        .iter()
+        .map(|state| {
+            state
+                .transitions
+                .iter()
+                .flat_map(|(&next, bytes)| bytes.iter().map(move |&byte| (byte, next)))
+                .filter(|&(byte, _)| byte != u8::MAX)
+                .collect()
+        })
+        .collect()
+}
+
+/// The state the byte 255 priming `^` leads to from the initial state, if the pattern is
+/// anchored at the start.
+fn primed_state(regex_and_dfa: &RegexAndDFA) -> Option<usize> {
+    regex_and_dfa.dfa.states.first().and_then(|state| {
+        state
+            .transitions
+            .iter()
+            .find(|(_, bytes)| bytes.contains(&u8::MAX))
+            .map(|(&next, _)| next)
+    })
+}
+
+/// Whether the matchers accept an input, running the DFA as they do: primed by the byte 255,
+/// restarting from the initial state when no transition applies and, without an end anchor,
+/// staying accepted once a match has completed.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA of the pattern.
+/// * `targets` - The transitions of the DFA, see `byte_targets`.
+/// * `input` - The input to match.
+///
+/// # Returns
+///
+/// `true` if the input is accepted.
+fn matcher_accepts(
+    regex_and_dfa: &RegexAndDFA,
+    targets: &[BTreeMap<u8, usize>],
+    input: &[u8],
+) -> bool {
+    let accepting = |state: usize| regex_and_dfa.dfa.states[state].state_type == "accept";
+    // `None` stands for the state entered once a match has completed without an end anchor.
+    let mut state = Some(primed_state(regex_and_dfa).unwrap_or(0));
+    for byte in input {
+        state = match state {
+            Some(s) if accepting(s) && !regex_and_dfa.has_end_anchor => None,
+            Some(s) => Some(
+                targets[s]
+                    .get(byte)
+                    .or_else(|| targets[0].get(byte))
+                    .copied()
+                    .unwrap_or(0),
+            ),
+            None => None,
+        };
+    }
+    state.is_none_or(accepting)
+}
+
+/// Samples random inputs accepted by the automaton of a pattern.
+///
+/// The length of each sample is drawn uniformly among the lengths in the range of the options
+/// that the pattern accepts, then every byte is drawn uniformly among the bytes that keep an
+/// accepted input of that length within reach. The byte 255 priming `^` is not part of the
+/// samples, as the matchers add it themselves.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA of the pattern.
+/// * `options` - The number, lengths and seed of the samples.
+///
+/// # Returns
+///
+/// A `Result` containing the samples, or a `CompilerError` if the pattern accepts no input of
+/// the requested lengths.
+pub(crate) fn sample_inputs(
+    regex_and_dfa: &RegexAndDFA,
+    options: &SampleOptions,
+) -> Result<Vec<Vec<u8>>, CompilerError> {
+    if options.min_len > options.max_len {
+        return Err(CompilerError::GenericError(format!(
+            "the minimal sample length {} is above the maximal one {}",
+            options.min_len, options.max_len
+        )));
+    }
+    let states = &regex_and_dfa.dfa.states;
+    let targets = byte_targets(regex_and_dfa);
+    // reachable[k][s]: some input of k more bytes leads from s to an accept state.
+    let mut reachable = vec![states
+        .iter()
+        .map(|state| state.state_type == "accept")
+        .collect::<Vec<_>>()];
+    for k in 1..=options.max_len {
+        let row = targets
+            .iter()
+            .map(|next| next.values().any(|&next| reachable[k - 1][next]))
+            .collect();
+        reachable.push(row);
+    }
+    // The input starts in state 0, or in the state primed by the byte 255.
+    let primed = primed_state(regex_and_dfa);
+    let starts = std::iter::once(0).chain(primed).collect::<Vec<_>>();
+    let lengths = (options.min_len..=options.max_len)
+        .filter(|&len| starts.iter().any(|&start| reachable[len][start]))
+        .collect::<Vec<_>>();
+    if lengths.is_empty() {
+        return Err(CompilerError::GenericError(format!(
+            "the pattern accepts no input of {} to {} bytes",
+            options.min_len, options.max_len
+        )));
+    }
+
+    let mut rng = SplitMix64(options.seed);
+    let samples = (0..options.count)
+        .map(|_| {
+            let len = lengths[rng.below(lengths.len())];
+            let starts = starts
+                .iter()
+                .filter(|&&start| reachable[len][start])
+                .collect::<Vec<_>>();
+            let mut state = *starts[rng.below(starts.len())];
+            let mut sample = Vec::with_capacity(len);
+            for k in (1..=len).rev() {
+                let choices = targets[state]
+                    .iter()
+                    .filter(|(_, &next)| reachable[k - 1][next])
+                    .collect::<Vec<_>>();
+                let (&byte, &next) = choices[rng.below(choices.len())];
+                sample.push(byte);
+                state = next;
+            }
+            sample
+        })
+        .collect();
+    Ok(samples)
+}
+
+/// Samples random inputs that almost match a pattern but are rejected by the matchers, as
+/// negative tests for the cases where code generation bugs tend to hide.
+///
+/// Each near miss is derived from an accepted sample by an edit taken in turn among replacing
+/// its last byte, so that the last transition fails, dropping one of its bytes and repeating
+/// one of its bytes, so that a repetition runs one time short or one time over. Edits whose
+/// result is still accepted are skipped, and the lengths of the near misses may fall one byte
+/// outside the range of the options.
+///
+/// # Arguments
+///
+/// * `regex_and_dfa` - A reference to the RegexAndDFA of the pattern.
+/// * `options` - The number, lengths and seed of the samples.
+///
+/// # Returns
+///
+/// A `Result` containing the near misses, or a `CompilerError` if the pattern accepts no input
+/// of the requested lengths. Fewer near misses than requested are returned if every edit of a
+/// sample is accepted, as for patterns accepting any input.
+pub(crate) fn sample_near_misses(
+    regex_and_dfa: &RegexAndDFA,
+    options: &SampleOptions,
+) -> Result<Vec<Vec<u8>>, CompilerError> {
+    let targets = byte_targets(regex_and_dfa);
+    let mut rng = SplitMix64(options.seed ^ 0x6e65_6172_6d69_7373);
+    let mut near_misses = vec![];
+    let rejected = |candidates: Vec<Vec<u8>>| {
+        candidates
+            .into_iter()
+            .filter(|candidate| !matcher_accepts(regex_and_dfa, &targets, candidate))
+            .collect::<Vec<_>>()
+    };
+    for (k, sample) in sample_inputs(regex_and_dfa, options)?
+        .into_iter()
+        .enumerate()
+    {
+        let len = sample.len();
+        let replace_last = |bytes: &mut dyn Iterator<Item = u8>| match len {
+            0 => vec![],
+            _ => bytes
+                .map(|byte| [&sample[..len - 1], &[byte]].concat())
+                .collect(),
+        };
+        // Printable replacements first, so that the near misses stay readable.
+        let mut replaced = rejected(replace_last(&mut (0x20..=0x7e)));
+        if replaced.is_empty() {
+            replaced = rejected(replace_last(&mut (0..u8::MAX)));
+        }
+        let dropped = rejected(
+            (0..len)
+                .map(|i| [&sample[..i], &sample[i + 1..]].concat())
+                .collect(),
+        );
+        let repeated = rejected(
+            (0..len)
+                .map(|i| [&sample[..=i], &sample[i..]].concat())
+                .collect(),
+        );
+        let edits = [replaced, dropped, repeated];
+        let candidates = (0..edits.len())
+            .map(|offset| &edits[(k + offset) % edits.len()])
+            .find(|candidates| !candidates.is_empty());
+        if let Some(candidates) = candidates {
+            near_misses.push(candidates[rng.below(candidates.len())].clone());
+        }
+    }
+    Ok(near_misses)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        regex::{create_regex_and_dfa_from_str_and_defs, match_string_with_dfa_graph},
+        structs::SubstringDefinitionsJson,
+    };
+
+    fn raw(regex: &str) -> RegexAndDFA {
+        create_regex_and_dfa_from_str_and_defs(
+            regex,
+            SubstringDefinitionsJson {
+                transitions: vec![vec![]],
+                names: vec![],
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sample_inputs() {
+        let regex_and_dfa = raw("[a-z]+@(gmail|outlook)\\.com");
+        let options = SampleOptions {
+            count: 20,
+            min_len: 12,
+            max_len: 20,
+            seed: 7,
+            ..Default::default()
+        };
+        let samples = sample_inputs(&regex_and_dfa, &options).unwrap();
+        assert_eq!(samples.len(), 20);
+        for sample in &samples {
+            assert!((12..=20).contains(&sample.len()));
+            let sample = std::str::from_utf8(sample).unwrap();
+            assert!(match_string_with_dfa_graph(&regex_and_dfa.dfa, sample));
+        }
+        // The same seed gives the same samples, another one different ones.
+        assert_eq!(sample_inputs(&regex_and_dfa, &options).unwrap(), samples);
+        let reseeded = SampleOptions { seed: 8, ..options };
+        assert_ne!(sample_inputs(&regex_and_dfa, &reseeded).unwrap(), samples);
+
+        // Anchored patterns are sampled without the priming byte.
+        let anchored = raw("^(ab|cd)$");
+        let samples = sample_inputs(&anchored, &SampleOptions::default()).unwrap();
+        assert!(samples
+            .iter()
+            .all(|sample| sample == b"ab" || sample == b"cd"));
+
+        let short = SampleOptions {
+            max_len: 10,
+            ..options
+        };
+        assert!(sample_inputs(&regex_and_dfa, &short).is_err());
+    }
+
+    #[test]
+    fn test_sample_near_misses() {
+        let regex_and_dfa = raw("id=[0-9]{3};");
+        let targets = byte_targets(&regex_and_dfa);
+        assert!(matcher_accepts(&regex_and_dfa, &targets, b"xid=123;"));
+        let options = SampleOptions {
+            count: 12,
+            near_misses: true,
+            ..Default::default()
+        };
+        let near_misses = sample_near_misses(&regex_and_dfa, &options).unwrap();
+        assert_eq!(near_misses.len(), 12);
+        for near_miss in &near_misses {
+            assert!(!matcher_accepts(&regex_and_dfa, &targets, near_miss));
+            // One edit away from the only accepted length.
+            assert!((6..=8).contains(&near_miss.len()), "{:?}", near_miss);
+        }
+        assert!(near_misses.iter().any(|near_miss| near_miss.len() == 6));
+        assert!(near_misses.iter().any(|near_miss| near_miss.len() == 8));
+    }
+}
@@ -0,0 +1,72 @@
+/// The manifest of the project created by `init_project`, listing the sample pattern.
+pub(crate) const MANIFEST: &str = r#"{
+  "patterns": [
+    {
+      "name": "greeting",
+      "decomposed_regex_path": "greeting.json"
+    }
+  ]
+}
+"#;
+
+/// The decomposed regex of the sample pattern, revealing the name of the greeted person.
+pub(crate) const GREETING: &str = r#"{
+  "parts": [
+    {
+      "regex_def": "^Hello, ",
+      "is_public": false
+    },
+    {
+      "regex_def": "[A-Za-z]+",
+      "is_public": true,
+      "max_length": 16
+    },
+    {
+      "regex_def": "!$",
+      "is_public": false
+    }
+  ]
+}
+"#;
+
+/// The main circuit of the project, calling the generated matcher of the sample pattern.
+pub(crate) const MAIN: &str = r#"// `regex.nr` is generated from `patterns.json`. After editing the patterns, regenerate it by
+// running in the project directory:
+//
+//     zk-regex manifest -m patterns.json -n src/regex.nr -g true
+mod regex;
+
+// Proves that the input greets someone, revealing who.
+fn main(input: [u8; 13]) -> pub BoundedVec<u8, 16> {
+    let captures = regex::regex_match_greeting(input);
+    regex::capture_to_bytes(captures[0])
+}
+
+#[test]
+fn test_main() {
+    let name = main("Hello, world!".as_bytes());
+    assert_eq(name, BoundedVec::from_array("world".as_bytes()));
+}
+
+#[test(should_fail)]
+fn test_main_rejects_other_inputs() {
+    let _ = main("Goodbye, all!".as_bytes());
+}
+"#;
+
+/// The input of `nargo execute`, the bytes of `Hello, world!`.
+pub(crate) const PROVER: &str =
+    "input = [72, 101, 108, 108, 111, 44, 32, 119, 111, 114, 108, 100, 33]\n";
+
+/// Returns the `Nargo.toml` of the project.
+///
+/// # Arguments
+///
+/// * `name` - The name of the Nargo package.
+///
+/// # Returns
+///
+/// The contents of `Nargo.toml`.
+pub(crate) fn nargo_toml(name: &str) -> String {
+    format!("[package]\nname = \"{name}\"\ntype = \"bin\"\nauthors = [\"\"]\n\n[dependencies]\n")
+}
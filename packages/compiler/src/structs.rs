@@ -1,10 +1,34 @@
+use crate::automaton::CompiledAutomaton;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt,
+    path::PathBuf,
+    str::FromStr,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegexPartConfig {
     pub is_public: bool,
     pub regex_def: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_repetitions: Option<usize>,
+    /// The maximal number of bytes captured by the part, bounding its capture vector.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    /// Whether the part captures ASCII digits, to be converted to an integer in the circuit.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub numeric: bool,
+    /// Whether the part is a hole of a template: a public part whose capture is meant to stay
+    /// private, only a commitment to it being revealed.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hole: bool,
+    /// The smallest value of a numeric part, asserted by `numeric_captures`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<u64>,
+    /// The largest value of a numeric part, asserted by `numeric_captures`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,6 +64,47 @@ pub struct DFAGraph {
 pub struct SubstringDefinitions {
     pub substring_ranges: Vec<BTreeSet<(usize, usize)>>,
     pub substring_boundaries: Option<Vec<(BTreeSet<usize>, BTreeSet<usize>)>>,
+    /// For substrings derived from the capture groups of a raw regex, the group of each one.
+    #[serde(default)]
+    pub substring_groups: Option<Vec<CaptureGroup>>,
+    /// For the substrings of a raw regex named in its substrings JSON, the name of each one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub substring_names: Option<Vec<Option<String>>>,
+}
+
+/// A capture group of a raw regex, captured as a substring.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureGroup {
+    /// The number of the group, counting opening parentheses from 1 as regex engines do.
+    pub index: usize,
+    /// The name of the group, for `(?P<name>...)`.
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegexPartDFA {
+    pub dfa: DFAGraph,
+    pub substr_idx: Option<usize>,
+    pub max_repetitions: Option<usize>,
+    pub max_length: Option<usize>,
+    pub numeric: bool,
+    pub hole: bool,
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+/// A bounded repetition of a single byte matched with a counter rather than a state per
+/// repetition, its DFA repeating the byte without bound.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepetitionCounter {
+    /// The repetition in the regex, e.g. `[a-z]{1,500}`.
+    pub repetition: String,
+    /// The minimal number of repetitions.
+    pub min: usize,
+    /// The maximal number of repetitions, if bounded.
+    pub max: Option<usize>,
+    /// The `(from, to)` transitions of the DFA consuming a byte of the repetition.
+    pub transitions: BTreeSet<(usize, usize)>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,9 +113,1074 @@ pub struct RegexAndDFA {
     pub dfa: DFAGraph,
     pub has_end_anchor: bool,
     pub substrings: SubstringDefinitions,
+    #[serde(default, skip_serializing)]
+    pub parts: Vec<RegexPartDFA>,
+    /// The repetitions of the regex matched with counters.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub counters: Vec<RepetitionCounter>,
+    /// Whether the matcher accepts an input only if the DFA accepts it whole, as for the
+    /// automata built by the `algebra` module, instead of restarting after a failed match.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub whole_input: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubstringDefinitionsJson {
     pub transitions: Vec<Vec<(usize, usize)>>,
+    /// The name of every set of transitions, `null` leaving it unnamed, or none at all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub names: Vec<Option<String>>,
+}
+
+/// The element type of the input array taken by the generated Noir matcher.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoirInputType {
+    /// `[u8; N]`, matched byte by byte against the UTF-8 automaton.
+    #[default]
+    U8,
+    /// `[u16; N]`, matched code unit by code unit against an automaton over UTF-16.
+    U16,
+    /// `[Field; M]` holding the bytes packed 31 per Field in little-endian order.
+    PackedField,
+}
+
+impl FromStr for NoirInputType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "u8" => Ok(NoirInputType::U8),
+            "u16" => Ok(NoirInputType::U16),
+            "packed" => Ok(NoirInputType::PackedField),
+            _ => Err(format!(
+                "unknown input type \"{}\", expected u8, u16 or packed",
+                s
+            )),
+        }
+    }
+}
+
+/// The hash function used to bind the input of the generated Noir matcher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoirInputHash {
+    /// SHA-256 over the input bytes, returned as `[u8; 32]`.
+    Sha256,
+    /// Poseidon2 over the input elements as Fields, returned as `Field`.
+    Poseidon,
+}
+
+impl FromStr for NoirInputHash {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha256" => Ok(NoirInputHash::Sha256),
+            "poseidon" => Ok(NoirInputHash::Poseidon),
+            _ => Err(format!(
+                "unknown input hash \"{}\", expected sha256 or poseidon",
+                s
+            )),
+        }
+    }
+}
+
+/// The layout of the `next_state` function of the generated Noir matcher.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoirStrategy {
+    /// The layout estimated to take the fewest gates for the automaton and the input length
+    /// of the options, see `NoirOptions::input_len`.
+    #[default]
+    Auto,
+    /// An if/else-if chain with one branch per target state and code unit range, shared by all
+    /// the states taking that transition.
+    IfChain,
+    /// A `global` array holding the next state of every (state, byte) pair.
+    Table,
+    /// A binary tree of selections over the current state, with one leaf per state.
+    MuxTree,
+    /// An if/else-if chain where runs of literal code units are looked up in `global` arrays.
+    Keyword,
+}
+
+impl FromStr for NoirStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(NoirStrategy::Auto),
+            "if-chain" => Ok(NoirStrategy::IfChain),
+            "table" => Ok(NoirStrategy::Table),
+            "mux-tree" => Ok(NoirStrategy::MuxTree),
+            "keyword" => Ok(NoirStrategy::Keyword),
+            _ => Err(format!(
+                "unknown strategy \"{}\", expected auto, if-chain, table, mux-tree or keyword",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for NoirStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            NoirStrategy::Auto => "auto",
+            NoirStrategy::IfChain => "if-chain",
+            NoirStrategy::Table => "table",
+            NoirStrategy::MuxTree => "mux-tree",
+            NoirStrategy::Keyword => "keyword",
+        })
+    }
+}
+
+/// The Noir type of the automaton state in the generated matcher.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoirStateType {
+    /// A `Field`.
+    #[default]
+    Field,
+    /// A `u8` if every state fits in a byte, a `u32` otherwise.
+    Integer,
+}
+
+impl FromStr for NoirStateType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "field" => Ok(NoirStateType::Field),
+            "integer" => Ok(NoirStateType::Integer),
+            _ => Err(format!(
+                "unknown state type \"{}\", expected field or integer",
+                s
+            )),
+        }
+    }
+}
+
+/// How the generated Noir matcher treats patterns that match the empty string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoirEmptyMatch {
+    /// The empty match at the start of the input is accepted, so a pattern matching the
+    /// empty string accepts every input.
+    #[default]
+    Allow,
+    /// A match must consume at least one code unit.
+    Reject,
+}
+
+impl FromStr for NoirEmptyMatch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(NoirEmptyMatch::Allow),
+            "reject" => Ok(NoirEmptyMatch::Reject),
+            _ => Err(format!(
+                "unknown empty match policy \"{}\", expected allow or reject",
+                s
+            )),
+        }
+    }
+}
+
+/// Where the generated Noir matcher ends a match once it has reached an accepting state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoirMatchSemantics {
+    /// The match is extended greedily from every accepting state. Unlike a leftmost-longest
+    /// regex engine, the matcher does not go back to the last accepting state: if an
+    /// extension fails before reaching another one, it restarts and the shorter match is lost.
+    #[default]
+    Greedy,
+    /// The match ends at the first accepting state, so every capture is as short as possible.
+    /// Patterns ending with `$`, reversed matchers and counted repetitions are still matched
+    /// greedily, as their matches end at the end of the input or once their counter is in range.
+    FirstAccept,
+    /// The match is extended greedily, and ends at the last accepting state it went through
+    /// when an extension fails, like a leftmost-longest regex engine from where the match
+    /// started. The captures must not depend on an extension that may fail, and later
+    /// occurrences are not supported. Patterns ending with `$`, reversed matchers and counted
+    /// repetitions are matched greedily, as for `FirstAccept`.
+    Longest,
+}
+
+impl FromStr for NoirMatchSemantics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "greedy" => Ok(NoirMatchSemantics::Greedy),
+            "first-accept" => Ok(NoirMatchSemantics::FirstAccept),
+            "longest" => Ok(NoirMatchSemantics::Longest),
+            _ => Err(format!(
+                "unknown match semantics \"{}\", expected greedy, first-accept or longest",
+                s
+            )),
+        }
+    }
+}
+
+/// The code units the generated Noir matcher accepts in its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoirInputAlphabet {
+    /// Printable ASCII, tab, line feed and carriage return.
+    Printable,
+    /// The code units appearing in a transition of the automaton.
+    Declared,
+}
+
+impl FromStr for NoirInputAlphabet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "printable" => Ok(NoirInputAlphabet::Printable),
+            "declared" => Ok(NoirInputAlphabet::Declared),
+            _ => Err(format!(
+                "unknown input alphabet \"{}\", expected printable or declared",
+                s
+            )),
+        }
+    }
+}
+
+/// How the patterns of a composition are combined into a single automaton.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PatternComposition {
+    /// The input matches every pattern.
+    #[default]
+    All,
+    /// The input matches at least one pattern, and the index of the first one is returned.
+    Any,
+    /// The input matches the first of two patterns and contains no match of the second.
+    Difference,
+    /// The input matches every pattern in order, each match starting after the previous one
+    /// ends.
+    Sequence,
+}
+
+impl FromStr for PatternComposition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(PatternComposition::All),
+            "any" => Ok(PatternComposition::Any),
+            "difference" => Ok(PatternComposition::Difference),
+            "sequence" => Ok(PatternComposition::Sequence),
+            _ => Err(format!(
+                "unknown composition \"{}\", expected all, any, difference or sequence",
+                s
+            )),
+        }
+    }
+}
+
+/// The regex syntax a pattern is written in, which decides the escapes and constructs it may
+/// use before it is translated to the syntax of the compiler.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Dialect {
+    /// The syntax of the Rust `regex` crate, used by the compiler itself.
+    #[default]
+    Rust,
+    /// The syntax of RE2, whose `\d`, `\s` and `\w` are ASCII only and whose classes do not
+    /// nest.
+    Re2,
+    /// The syntax of PCRE, which also rejects the lookarounds, backreferences, atomic groups and
+    /// possessive quantifiers no automaton can match.
+    Pcre,
+    /// The POSIX extended syntax, whose backslash is literal in bracket expressions and whose `.`
+    /// also matches a line feed.
+    Ere,
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rust" => Ok(Dialect::Rust),
+            "re2" => Ok(Dialect::Re2),
+            "pcre" => Ok(Dialect::Pcre),
+            "ere" => Ok(Dialect::Ere),
+            _ => Err(format!(
+                "unknown dialect \"{}\", expected rust, re2, pcre or ere",
+                s
+            )),
+        }
+    }
+}
+
+/// Which substrings are extracted by the generated code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubstrSelection {
+    /// Every substring is extracted.
+    All,
+    /// No substring is extracted, only the match is checked.
+    Off,
+    /// Only the substrings with these indices are extracted, renumbered from 0 in order.
+    Only(BTreeSet<usize>),
+}
+
+impl FromStr for SubstrSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "true" => Ok(SubstrSelection::All),
+            "false" => Ok(SubstrSelection::Off),
+            _ => s
+                .split(',')
+                .map(|idx| idx.trim().parse::<usize>())
+                .collect::<Result<BTreeSet<_>, _>>()
+                .map(SubstrSelection::Only)
+                .map_err(|_| {
+                    format!(
+                        "invalid substring selection \"{}\", expected true, false or a comma-separated list of substring indices",
+                        s
+                    )
+                }),
+        }
+    }
+}
+
+/// What the generated Noir matcher does when a repeated part matches more times than it has
+/// captures for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoirCaptureOverflow {
+    /// The proof fails.
+    #[default]
+    Fail,
+    /// The first repetitions are captured and the others are dropped.
+    KeepFirst,
+    /// The last repetitions are captured and the others are dropped.
+    KeepLast,
+}
+
+impl FromStr for NoirCaptureOverflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(NoirCaptureOverflow::Fail),
+            "keep-first" => Ok(NoirCaptureOverflow::KeepFirst),
+            "keep-last" => Ok(NoirCaptureOverflow::KeepLast),
+            _ => Err(format!(
+                "unknown capture overflow policy \"{}\", expected fail, keep-first or keep-last",
+                s
+            )),
+        }
+    }
+}
+
+/// Options controlling the generated Noir matcher.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NoirOptions {
+    /// The element type of the input array.
+    pub input_type: NoirInputType,
+    /// If set, `regex_match` also hashes the input and returns the digest.
+    pub input_hash: Option<NoirInputHash>,
+    /// If set along with `input_hash`, a `regex_match_verified` entry point takes the expected
+    /// digest as an input and asserts that the input hashes to it instead of returning it.
+    #[serde(default)]
+    pub verify_input_hash: bool,
+    /// The layout of the `next_state` function.
+    #[serde(default)]
+    pub strategy: NoirStrategy,
+    /// The number of input code units the matcher is meant to be instantiated with, which the
+    /// `auto` strategy weighs against the size of the `global` arrays. 1024 if not set.
+    #[serde(default)]
+    pub input_len: Option<usize>,
+    /// The Noir type of the automaton state.
+    #[serde(default)]
+    pub state_type: NoirStateType,
+    /// If set, an if-chain or keyword `next_state` with more branches is split by state ranges
+    /// into helper functions of at most this many branches, which the Noir compiler handles
+    /// much faster than a single huge function.
+    #[serde(default)]
+    pub max_branches: Option<usize>,
+    /// If set, `next_state` and the capture helpers are emitted into separate modules.
+    #[serde(default)]
+    pub split_modules: bool,
+    /// How patterns matching the empty string are treated.
+    #[serde(default)]
+    pub empty_match: NoirEmptyMatch,
+    /// Whether a match is extended greedily, ends at the first accepting state or ends at the
+    /// last one.
+    #[serde(default)]
+    pub match_semantics: NoirMatchSemantics,
+    /// What happens when a repeated part matches more times than it has captures for.
+    #[serde(default)]
+    pub capture_overflow: NoirCaptureOverflow,
+    /// If set, a `regex_match_packed_captures` entry point returns the captures packed 31
+    /// bytes per Field.
+    #[serde(default)]
+    pub packed_captures: bool,
+    /// If set, the comments and indentation are stripped from the generated code, which
+    /// keeps the files of large patterns small and quick to parse.
+    #[serde(default)]
+    pub compact: bool,
+    /// If set, the code unit ranges tested by several branches of an if-chain or keyword
+    /// `next_state`, such as digits, are tested by shared predicates like `is_digit`.
+    #[serde(default)]
+    pub class_predicates: bool,
+    /// If set, `next_state` is public and a `StateInfo` struct describes its initial and
+    /// accepting states, a stable interface for matching loops written by hand.
+    #[serde(default)]
+    pub public_next_state: bool,
+    /// If set, a `ByteSource` trait is emitted along with a `regex_match_source` entry point
+    /// generic over it, accepting byte arrays, `BoundedVec`s and packed Fields.
+    #[serde(default)]
+    pub byte_source: bool,
+    /// If set, a `regex_match_fields` entry point takes the input as `[Field; N]`, asserting
+    /// that every Field fits in an input code unit.
+    #[serde(default)]
+    pub field_input: bool,
+    /// If set, a `regex_match_capture_digests` entry point returns the SHA-256 digest of
+    /// every capture.
+    #[serde(default)]
+    pub capture_digests: bool,
+    /// If set, a `regex_match_capture_lens` entry point returns the captures along with their
+    /// lengths in code units.
+    #[serde(default)]
+    pub capture_lens: bool,
+    /// If set, a `regex_match_prefix_len` function returns the length of the longest prefix
+    /// of a match found in the input, without asserting that the pattern matches.
+    #[serde(default)]
+    pub prefix_len: bool,
+    /// If set, a `regex_match_segments` function matches this many input arrays, at least 2,
+    /// as one input, carrying the state of the automaton from one array to the next.
+    #[serde(default)]
+    pub input_segments: Option<usize>,
+    /// If set, a `regex_match_mask` function asserts the match and returns a `[bool; N]`
+    /// marking the input positions it covers.
+    #[serde(default)]
+    pub match_mask: bool,
+    /// If set, a `regex_match_len` function asserts the match and returns the number of code
+    /// units it covers.
+    #[serde(default)]
+    pub match_len: bool,
+    /// If set, a `regex_match_end` function asserts the match and returns the index just past
+    /// the code unit at which it first reaches an accepting state.
+    #[serde(default)]
+    pub match_end: bool,
+    /// If set, a `regex_match_redacted` function proves that a redacted copy of the input
+    /// equals it except that the code units of the match are replaced with this character.
+    #[serde(default)]
+    pub redaction: Option<char>,
+    /// If set, `regex_match` asserts that the match is completed within this many code units
+    /// at the start of the input, which may be padded past them.
+    #[serde(default)]
+    pub match_bound: Option<usize>,
+    /// If set, a `regex_match_window` function takes `start` and `end` indices besides the
+    /// input and only matches the code units between them, asserting that they lie within it.
+    #[serde(default)]
+    pub match_window: bool,
+    /// If set, a `regex_match_at` function takes the index at which the match starts as a
+    /// witness and only runs the automaton over the window of at most `L` code units there.
+    #[serde(default)]
+    pub match_at: bool,
+    /// If set, `regex_match` asserts that the match produces exactly this many non-empty
+    /// captures.
+    #[serde(default)]
+    pub capture_count: Option<usize>,
+    /// If set, a non-generic `regex_match_fixed` function takes an input of exactly this many
+    /// code units, for ABIs and tooling without numeric generics.
+    #[serde(default)]
+    pub fixed_len: Option<usize>,
+    /// For several patterns, if set, a `regex_match_all` function checks all of them in a
+    /// single pass over the input, advancing one state per pattern.
+    #[serde(default)]
+    pub fused: bool,
+    /// If set, the captures are folded to ASCII lowercase in the circuit.
+    #[serde(default)]
+    pub lowercase_captures: bool,
+    /// If set, `regex_match` also returns the index in the input at which every capture
+    /// begins, 0 for an empty capture.
+    #[serde(default)]
+    pub capture_starts: bool,
+    /// If set, only this occurrence of the pattern, counted from 1, is matched and captured,
+    /// the earlier ones being required but skipped.
+    #[serde(default)]
+    pub occurrence: Option<usize>,
+    /// If set, the reversed pattern is matched by scanning the input from its end.
+    #[serde(default)]
+    pub reverse: bool,
+    /// With `reverse`, only this many code units at the end of the input are scanned.
+    #[serde(default)]
+    pub reverse_window: Option<usize>,
+    /// If set, every input code unit is asserted to belong to this alphabet.
+    #[serde(default)]
+    pub input_alphabet: Option<NoirInputAlphabet>,
+    /// If set, the bounded repetitions of a raw regex repeating a single byte more than this
+    /// many times are matched with a counter instead of a state per repetition.
+    #[serde(default)]
+    pub repetition_counter: Option<usize>,
+    /// Paths imported with `use` at the top of the generated code. An imported `sha256`,
+    /// `sha256_var` or `Poseidon2` is called instead of the one from `std`.
+    #[serde(default)]
+    pub imports: Vec<String>,
+    /// If set, the helpers that do not depend on the pattern, such as `capture_to_bytes`, are
+    /// imported from the Nargo library of this name, generated by `gen_noir_runtime`, instead
+    /// of being emitted with every matcher.
+    #[serde(default)]
+    pub runtime: Option<String>,
+    /// The dependencies of the generated Nargo projects, as name -> TOML value, e.g.
+    /// `sha256 -> { tag = "v0.1.0", git = "https://github.com/noir-lang/sha256" }`.
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+    /// Representative inputs run through the automaton before the code is generated, so that
+    /// the if-chain and keyword layouts of `next_state` check the branches they take most
+    /// first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub corpus: Vec<Vec<u8>>,
+}
+
+/// Options of `compile_raw` and `compile_decomposed`, choosing the artifacts to generate.
+#[derive(Debug, Clone, Default)]
+pub struct CompileOptions {
+    /// For a raw regex, the DFA transitions revealed by each substring.
+    pub substr_transitions: Vec<Vec<(usize, usize)>>,
+    /// For a raw regex, the name of every substring of `substr_transitions`, `None` leaving it
+    /// unnamed, or none at all.
+    pub substr_names: Vec<Option<String>>,
+    /// For a raw regex, whether the substrings are its capture groups instead.
+    pub capture_groups: bool,
+    /// If set, the Circom circuit is generated with this template name.
+    pub circom_template_name: Option<String>,
+    /// If set, the Halo2 lookup tables are generated.
+    pub halo2: bool,
+    /// If set, the Noir matcher is generated with these options.
+    pub noir: Option<NoirOptions>,
+    /// Which substrings to extract, by default all of them for a raw regex and none for a
+    /// decomposed one as in the CLI.
+    pub gen_substrs: Option<SubstrSelection>,
+    /// The values of the `{{name}}` variables used in the regex.
+    pub template_vars: BTreeMap<String, String>,
+    /// Whether every `\r\n` of the regex also matches a bare `\n`.
+    pub lenient_newlines: bool,
+    /// The regex syntax the pattern is written in.
+    pub dialect: Dialect,
+    /// Whether unknown escapes such as `\q` match the escaped character instead of failing.
+    pub permissive_escapes: bool,
+    /// If set, the compilation fails when the Noir matcher is estimated to exceed the budget.
+    pub gate_budget: Option<GateBudget>,
+    /// Whether to return the automaton run by the Noir matcher, shaped by the Noir options.
+    pub automaton: bool,
+}
+
+/// A pattern compiled by `compile_batch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternSource {
+    /// A raw regex, as given to `compile_raw`.
+    Raw(String),
+    /// The JSON of a decomposed regex, as given to `compile_decomposed`.
+    Decomposed(String),
+}
+
+/// A bound on the estimated size of the Noir matcher circuit for inputs of a given length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GateBudget {
+    /// The largest estimated number of gates allowed.
+    pub max_gates: usize,
+    /// The number of input code units the circuit is instantiated with.
+    pub input_len: usize,
+}
+
+/// The estimated size of the Noir matcher for an input length and a layout of `next_state`, as
+/// listed by `bench_pattern`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeEstimate {
+    /// The number of input code units the circuit is instantiated with.
+    pub input_len: usize,
+    /// The layout of `next_state`.
+    pub strategy: NoirStrategy,
+    /// The estimated number of gates.
+    pub gates: usize,
+    /// The estimated number of gates each additional input code unit costs.
+    pub per_unit: usize,
+    /// Whether `NoirStrategy::Auto` chooses this layout for the input length.
+    pub chosen: bool,
+}
+
+/// A proving system of `bb`, the Barretenberg backend of Noir, whose cost `bench_proving`
+/// estimates or measures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ProvingBackend {
+    /// UltraHonk, the default proving system of recent `bb` versions.
+    #[default]
+    UltraHonk,
+    /// UltraPlonk, the proving system of earlier `bb` versions.
+    UltraPlonk,
+}
+
+impl FromStr for ProvingBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ultra-honk" => Ok(ProvingBackend::UltraHonk),
+            "ultra-plonk" => Ok(ProvingBackend::UltraPlonk),
+            _ => Err(format!(
+                "unknown proving backend \"{}\", expected ultra-honk or ultra-plonk",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ProvingBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            ProvingBackend::UltraHonk => "ultra-honk",
+            ProvingBackend::UltraPlonk => "ultra-plonk",
+        })
+    }
+}
+
+/// The cost of proving the Noir matcher of a pattern with a backend for an input length, as
+/// listed by `bench_proving` and in a `PatternReport`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvingCost {
+    /// The number of input code units the circuit is instantiated with.
+    pub input_len: usize,
+    /// The proving system.
+    pub backend: ProvingBackend,
+    /// The number of gates of the circuit, as estimated for a `SizeEstimate` or as counted by
+    /// `bb gates`.
+    pub gates: usize,
+    /// The number of rows the backend pads the gates to, a power of two.
+    pub circuit_size: usize,
+    /// The time taken by `bb` to generate the proof, in milliseconds.
+    pub prove_ms: u64,
+    /// The peak resident memory of `bb` while proving in kilobytes, if known.
+    pub peak_memory_kb: Option<u64>,
+    /// Whether the cost was measured by running `bb` rather than estimated.
+    pub measured: bool,
+}
+
+/// An artifact of a pattern written by `--emit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum EmitKind {
+    /// The Noir matcher, `<stem>.nr`.
+    Noir,
+    /// The regex and its DFA as JSON, `<stem>.json`.
+    Json,
+    /// The automaton as a Graphviz DOT graph, `<stem>.dot`.
+    Dot,
+    /// A self-contained HTML page exploring the automaton, `<stem>.html`.
+    Html,
+    /// The compile report of the other artifacts, `<stem>.report.json`.
+    Report,
+    /// The signatures of the public functions of the Noir matcher and the captures they
+    /// return, as JSON, `<stem>.abi.json`.
+    Abi,
+}
+
+impl FromStr for EmitKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nr" => Ok(EmitKind::Noir),
+            "json" => Ok(EmitKind::Json),
+            "dot" => Ok(EmitKind::Dot),
+            "html" => Ok(EmitKind::Html),
+            "report" => Ok(EmitKind::Report),
+            "abi" => Ok(EmitKind::Abi),
+            _ => Err(format!(
+                "unknown artifact \"{}\", expected nr, json, dot, html, report or abi",
+                s
+            )),
+        }
+    }
+}
+
+/// A public output of the `main` function generated by `--noir-main`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum MainOutput {
+    /// The captures, as returned by the matcher.
+    Captures,
+    /// A Poseidon2 commitment to every capture, hashing its first `len()` elements.
+    Commitments,
+    /// The index in the input at which every capture begins.
+    Starts,
+    /// The hash of the input.
+    InputHash,
+    /// A flag that is always `true`, for ABIs expecting one: `main` asserts the match, so only
+    /// matching inputs can be proven.
+    Match,
+    /// A Poseidon2 commitment to the capture of every hole, see `RegexPartConfig::hole`.
+    Holes,
+}
+
+impl MainOutput {
+    /// Returns the name of the output, as parsed by `from_str`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MainOutput::Captures => "captures",
+            MainOutput::Commitments => "commitments",
+            MainOutput::Starts => "starts",
+            MainOutput::InputHash => "input-hash",
+            MainOutput::Match => "match",
+            MainOutput::Holes => "holes",
+        }
+    }
+}
+
+impl FromStr for MainOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "captures" => Ok(MainOutput::Captures),
+            "commitments" => Ok(MainOutput::Commitments),
+            "starts" => Ok(MainOutput::Starts),
+            "input-hash" => Ok(MainOutput::InputHash),
+            "match" => Ok(MainOutput::Match),
+            "holes" => Ok(MainOutput::Holes),
+            _ => Err(format!(
+                "unknown output \"{}\", expected captures, commitments, starts, input-hash, match or holes",
+                s
+            )),
+        }
+    }
+}
+
+/// The `main` function of a Noir binary wrapping the matcher, with a private input and public
+/// outputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoirMainOptions {
+    /// The path of the file holding `main`, in the directory of the Noir matcher.
+    pub path: String,
+    /// The number of code units of the input.
+    pub input_len: usize,
+    /// The public outputs of `main` in order, or every value returned by the matcher if empty.
+    pub outputs: Vec<MainOutput>,
+}
+
+/// The artifacts of a pattern to write together, sharing a path stem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmitOptions {
+    /// The path every artifact is named after, e.g. `out/email` for `out/email.nr`.
+    pub stem: String,
+    /// The artifacts to write.
+    pub kinds: Vec<EmitKind>,
+}
+
+/// The files and directories `gen_from_raw` and `gen_from_decomposed` write, each only if its
+/// path is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputPaths {
+    /// The directory of the Halo2 lookup tables.
+    pub halo2_dir: Option<String>,
+    /// The Circom template, named after the `circom_template_name` of the `CompileOptions`.
+    pub circom_file: Option<String>,
+    /// The Noir matcher.
+    pub noir_file: Option<String>,
+    /// A Nargo project running the Noir matcher.
+    pub noir_example_dir: Option<String>,
+    /// An Aztec contract wrapping the Noir matcher.
+    pub aztec_contract_dir: Option<String>,
+    /// The input length of the Aztec contract function.
+    pub aztec_input_len: usize,
+    /// A `main` function calling the Noir matcher, which requires `noir_file`.
+    pub noir_main: Option<NoirMainOptions>,
+    /// A JSON report summarizing the compilation.
+    pub report: Option<String>,
+    /// Artifacts to write together, see `emit_artifacts`.
+    pub emit: Option<EmitOptions>,
+}
+
+/// How many inputs accepted by a pattern to sample, and of which lengths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SampleOptions {
+    /// The number of inputs to sample.
+    pub count: usize,
+    /// The smallest length of a sample in bytes.
+    pub min_len: usize,
+    /// The largest length of a sample in bytes.
+    pub max_len: usize,
+    /// The seed of the random generator, the same seed giving the same samples.
+    pub seed: u64,
+    /// Whether to sample inputs that almost match and are rejected, rather than accepted ones.
+    pub near_misses: bool,
+}
+
+impl Default for SampleOptions {
+    fn default() -> Self {
+        SampleOptions {
+            count: 10,
+            min_len: 1,
+            max_len: 32,
+            seed: 0,
+            near_misses: false,
+        }
+    }
+}
+
+/// The contents of the Halo2 lookup tables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Halo2Tables {
+    /// The transitions of the whole DFA, written to `allstr.txt` by the CLI.
+    pub allstr: String,
+    /// The definition of each substring, written to `substr_{idx}.txt` by the CLI.
+    pub substrs: Vec<String>,
+}
+
+/// The artifacts generated by `compile_raw` and `compile_decomposed`.
+#[derive(Debug, Clone)]
+pub struct CompiledRegex {
+    /// The full regex pattern.
+    pub regex_pattern: String,
+    /// The Circom circuit, if requested.
+    pub circom: Option<String>,
+    /// The Halo2 lookup tables, if requested.
+    pub halo2: Option<Halo2Tables>,
+    /// The main Noir file, if requested.
+    pub noir: Option<String>,
+    /// The Noir modules next to the main file, as (module name, code) tuples, when
+    /// `split_modules` is set.
+    pub noir_modules: Vec<(String, String)>,
+    /// The automaton run by the Noir matcher, if requested.
+    pub automaton: Option<CompiledAutomaton>,
+}
+
+/// The outcome of `nargo test` on the Nargo project generated for a pattern by
+/// `test_from_manifest`.
+#[derive(Debug, Clone)]
+pub struct PatternTestResult {
+    /// The name of the pattern.
+    pub name: String,
+    /// Whether the tests passed.
+    pub passed: bool,
+    /// The directory of the Nargo project, only kept when the tests fail.
+    pub project_dir: PathBuf,
+    /// The output of `nargo test`.
+    pub output: String,
+}
+
+/// An input on which the Noir matcher run by `nargo execute` disagrees with its simulation in
+/// Rust, found by `validate_pattern`.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// The input.
+    pub input: Vec<u8>,
+    /// The substrings extracted by the simulation, or `None` if it rejects the input.
+    pub simulated: Option<Vec<Vec<u8>>>,
+    /// Whether the Noir matcher accepts the input. When both accept it, the substrings they
+    /// extract differ.
+    pub noir_accepts: bool,
+    /// The directory of the Nargo project checking the input, kept for inspection.
+    pub project_dir: PathBuf,
+    /// The output of `nargo execute`.
+    pub output: String,
+}
+
+/// A machine-readable summary of a compilation, written by `--report`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompileReport {
+    /// One entry per compiled pattern.
+    pub patterns: Vec<PatternReport>,
+}
+
+/// The summary of one compiled pattern in a `CompileReport`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatternReport {
+    /// The decomposed regex file or the raw regex the pattern was compiled from.
+    pub source: String,
+    /// The full regex pattern.
+    pub regex_pattern: String,
+    /// Whether the substrings are extracted.
+    pub gen_substrs: bool,
+    /// The options of the Noir matcher.
+    pub noir_options: NoirOptions,
+    /// The size of the DFA.
+    pub dfa: DFAStats,
+    /// The estimated number of comparisons the Noir matcher makes per input code unit, if a
+    /// Noir matcher is generated.
+    pub estimated_constraints_per_unit: Option<usize>,
+    /// The estimated cost of proving the Noir matcher with every backend, for the input length
+    /// of the Noir options, if a Noir matcher is generated.
+    #[serde(default)]
+    pub proving_estimates: Vec<ProvingCost>,
+    /// The capture group of every extracted substring, in the order of the output slots, if
+    /// the substrings are the capture groups of the regex.
+    #[serde(default)]
+    pub capture_groups: Option<Vec<CaptureGroup>>,
+    /// The name of every extracted substring, in the order of the output slots, if the
+    /// substrings JSON of a raw regex names them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub substring_names: Option<Vec<Option<String>>>,
+    /// Potential problems with the pattern or the generated code.
+    pub warnings: Vec<String>,
+    /// The states and transitions the corpus of the Noir options spends its code units in, if
+    /// it has one.
+    #[serde(default)]
+    pub corpus: Option<CorpusProfile>,
+    /// The files written for the pattern.
+    pub outputs: Vec<OutputFile>,
+}
+
+/// How the automaton of the Noir matcher runs over a corpus of representative inputs, as
+/// reported in a `PatternReport`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorpusProfile {
+    /// The number of inputs.
+    pub inputs: usize,
+    /// The number of inputs the automaton accepts.
+    pub matched: usize,
+    /// The number of bytes of the inputs.
+    pub units: usize,
+    /// The states the code units are consumed from, the busiest first.
+    pub states: Vec<StateVisits>,
+    /// The transitions taken, the busiest first.
+    pub transitions: Vec<TransitionVisits>,
+}
+
+/// The number of code units a state of the automaton consumed over a corpus.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateVisits {
+    /// The ID of the state.
+    pub state: usize,
+    /// The number of code units consumed from it.
+    pub units: usize,
+}
+
+/// The number of code units a transition of the automaton consumed over a corpus.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransitionVisits {
+    /// The source state.
+    pub from: usize,
+    /// The target state.
+    pub to: usize,
+    /// The number of code units it consumed.
+    pub units: usize,
+}
+
+/// The size of a DFA, as reported in a `PatternReport`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DFAStats {
+    /// The number of states.
+    pub states: usize,
+    /// The number of (from, to) transitions, whatever the number of bytes they consume.
+    pub transitions: usize,
+    /// The number of accepting states.
+    pub accept_states: usize,
+    /// The number of substrings.
+    pub substrings: usize,
+    /// Whether the regex ends with `$`.
+    pub end_anchored: bool,
+    /// The length in bytes of the longest match, or `None` if the matches are unbounded.
+    #[serde(default)]
+    pub max_match_len: Option<usize>,
+    /// The length in bytes of the longest capture of every substring, or `None` for an
+    /// unbounded one.
+    #[serde(default)]
+    pub max_capture_lens: Vec<Option<usize>>,
+}
+
+/// A file written by the compiler, identified by its hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputFile {
+    /// The path of the file.
+    pub path: String,
+    /// The size of the file in bytes.
+    pub bytes: u64,
+    /// The hex-encoded SHA-256 hash of the file.
+    pub sha256: String,
+}
+
+/// A set of patterns compiled into a single Noir library sharing one automaton.
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatternManifest {
+    pub patterns: Vec<ManifestPattern>,
+    /// The values of the `{{name}}` variables shared by all the patterns.
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+    /// The regexes of the `{{name}}` fragments the patterns may refer to.
+    #[serde(default)]
+    pub fragments: BTreeMap<String, String>,
+    /// The paths of JSON files mapping more fragment names to their regexes.
+    #[serde(default)]
+    pub includes: Vec<String>,
+}
+
+/// A pattern of a `PatternManifest`, given either as a decomposed config or as a raw regex.
+///
+/// Relative paths are resolved against the directory of the manifest.
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestPattern {
+    /// The name of the pattern, used as the suffix of its `regex_match_<name>` function.
+    pub name: String,
+    #[serde(default)]
+    pub decomposed_regex_path: Option<String>,
+    #[serde(default)]
+    pub raw_regex: Option<String>,
+    #[serde(default)]
+    pub substrs_json_path: Option<String>,
+    /// The values of the `{{name}}` variables of this pattern, overriding the shared ones.
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+}
+
+/// A set of fields extracted from a structured body, such as `to=alice;amount=10`, into a
+/// named Noir struct.
+///
+/// The fields must appear in the body in the order they are listed, one after the other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldsConfig {
+    /// The name of the generated Noir struct.
+    #[serde(default = "default_fields_struct_name")]
+    pub struct_name: String,
+    #[serde(default)]
+    pub format: FieldsFormat,
+    /// The separator between two `key=value` pairs, `;` by default.
+    #[serde(default = "default_fields_separator")]
+    pub separator: String,
+    pub fields: Vec<FieldConfig>,
+}
+
+fn default_fields_struct_name() -> String {
+    "Fields".to_string()
+}
+
+fn default_fields_separator() -> String {
+    ";".to_string()
+}
+
+/// The layout of the body of a `FieldsConfig`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldsFormat {
+    /// `key=value` pairs joined by the separator, the last one followed by the separator or a
+    /// line break.
+    #[default]
+    KeyValue,
+    /// The members of a JSON object, e.g. `"to": "alice", "amount": 10`, with quoted values
+    /// except for the numeric fields.
+    Json,
+}
+
+/// A field of a `FieldsConfig`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldConfig {
+    /// The name of the member of the generated struct.
+    pub name: String,
+    /// The key of the field in the body, the name by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// The regex matching the value of the field.
+    pub pattern: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    /// Whether the value is made of ASCII digits, converted to a `u64` member.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub numeric: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<u64>,
 }
@@ -0,0 +1,212 @@
+use crate::{errors::CompilerError, structs::DecomposedRegexConfig};
+use std::collections::BTreeMap;
+
+/// Replaces the `{{name}}` variables of a pattern template with their values.
+///
+/// Values are escaped, so they always match literally: `{{domain}}` bound to `example.com`
+/// matches `example.com` but not `exampleXcom`. Text between `{{` and `}}` that is not a
+/// variable name is left untouched.
+///
+/// # Arguments
+///
+/// * `pattern` - The pattern template.
+/// * `vars` - The values of the variables, keyed by name.
+///
+/// # Returns
+///
+/// A `Result` containing the resolved pattern, or a `CompilerError` naming the first variable
+/// without a value.
+pub(crate) fn substitute_vars(
+    pattern: &str,
+    vars: &BTreeMap<String, String>,
+) -> Result<String, CompilerError> {
+    replace_names(pattern, |name| {
+        let value = vars.get(name).ok_or_else(|| {
+            CompilerError::GenericError(format!("pattern variable \"{}\" has no value", name))
+        })?;
+        Ok(Some(regex::escape(value)))
+    })
+}
+
+/// Expands the `{{name}}` references to named fragments of a pattern.
+///
+/// Unlike variables, fragments are regexes: `{{date}}` bound to `[0-9]{4}-[0-9]{2}-[0-9]{2}`
+/// matches any date. Each fragment is wrapped in a non-capturing group, so that `{{date}}+`
+/// repeats the whole fragment, and may itself refer to other fragments. References to names
+/// that are not fragments are left for `substitute_vars`.
+///
+/// # Arguments
+///
+/// * `pattern` - The pattern referring to the fragments.
+/// * `fragments` - The regexes of the fragments, keyed by name.
+///
+/// # Returns
+///
+/// A `Result` containing the expanded pattern, or a `CompilerError` if the fragments refer to
+/// each other in a cycle.
+#[cfg(feature = "fs")]
+pub(crate) fn expand_fragments(
+    pattern: &str,
+    fragments: &BTreeMap<String, String>,
+) -> Result<String, CompilerError> {
+    expand_fragments_within(pattern, fragments, &mut vec![])
+}
+
+#[cfg(feature = "fs")]
+fn expand_fragments_within<'a>(
+    pattern: &str,
+    fragments: &'a BTreeMap<String, String>,
+    expanding: &mut Vec<&'a str>,
+) -> Result<String, CompilerError> {
+    replace_names(pattern, |name| {
+        let Some((name, fragment)) = fragments.get_key_value(name) else {
+            return Ok(None);
+        };
+        if expanding.contains(&name.as_str()) {
+            return Err(CompilerError::GenericError(format!(
+                "pattern fragments refer to each other in a cycle: {} -> {}",
+                expanding.join(" -> "),
+                name
+            )));
+        }
+        expanding.push(name);
+        let expanded = expand_fragments_within(fragment, fragments, expanding)?;
+        expanding.pop();
+        Ok(Some(format!("(?:{})", expanded)))
+    })
+}
+
+/// Replaces the `{{name}}` references of a pattern with the text `replacement` returns for
+/// them, keeping those it returns `None` for. Text between `{{` and `}}` that is not a valid
+/// name is left untouched.
+fn replace_names(
+    pattern: &str,
+    mut replacement: impl FnMut(&str) -> Result<Option<String>, CompilerError>,
+) -> Result<String, CompilerError> {
+    let mut resolved = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let name = after.find("}}").map(|end| &after[..end]);
+        let Some(name) = name.filter(|name| is_var_name(name)) else {
+            resolved.push_str(&rest[..start + 2]);
+            rest = after;
+            continue;
+        };
+        resolved.push_str(&rest[..start]);
+        match replacement(name)? {
+            Some(value) => resolved.push_str(&value),
+            None => resolved.push_str(&rest[start..start + name.len() + 4]),
+        }
+        rest = &after[name.len() + 2..];
+    }
+    resolved.push_str(rest);
+    Ok(resolved)
+}
+
+/// Replaces the variables in every part of a decomposed regex.
+///
+/// # Arguments
+///
+/// * `config` - The decomposed regex whose parts are resolved in place.
+/// * `vars` - The values of the variables, keyed by name.
+///
+/// # Returns
+///
+/// A `Result` containing nothing, or a `CompilerError` naming the first variable without a value.
+pub(crate) fn substitute_config_vars(
+    config: &mut DecomposedRegexConfig,
+    vars: &BTreeMap<String, String>,
+) -> Result<(), CompilerError> {
+    for part in config.parts.iter_mut() {
+        part.regex_def = substitute_vars(&part.regex_def, vars)?;
+    }
+    Ok(())
+}
+
+/// Expands the fragments referred to in every part of a decomposed regex.
+///
+/// # Arguments
+///
+/// * `config` - The decomposed regex whose parts are expanded in place.
+/// * `fragments` - The regexes of the fragments, keyed by name.
+///
+/// # Returns
+///
+/// A `Result` containing nothing, or a `CompilerError` if the fragments refer to each other in
+/// a cycle.
+#[cfg(feature = "fs")]
+pub(crate) fn expand_config_fragments(
+    config: &mut DecomposedRegexConfig,
+    fragments: &BTreeMap<String, String>,
+) -> Result<(), CompilerError> {
+    for part in config.parts.iter_mut() {
+        part.regex_def = expand_fragments(&part.regex_def, fragments)?;
+    }
+    Ok(())
+}
+
+/// Returns whether `name` is a valid variable name: an ASCII letter or underscore followed by
+/// ASCII letters, digits and underscores.
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_substitute_vars() {
+        let vars = vec![
+            ("domain".to_string(), "example.com".to_string()),
+            ("user".to_string(), "alice".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            substitute_vars("from:{{user}}@{{domain}}", &vars).unwrap(),
+            "from:alice@example\\.com"
+        );
+        assert_eq!(
+            substitute_vars("a{2}{{1}} {{ c }}", &vars).unwrap(),
+            "a{2}{{1}} {{ c }}"
+        );
+        assert!(substitute_vars("to:{{tenant}}", &vars)
+            .unwrap_err()
+            .to_string()
+            .contains("\"tenant\""));
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_expand_fragments() {
+        let fragments = vec![
+            ("digit".to_string(), "[0-9]".to_string()),
+            (
+                "date".to_string(),
+                "{{digit}}{4}-{{digit}}{2}-{{digit}}{2}".to_string(),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            expand_fragments("on {{date}} at {{host}}", &fragments).unwrap(),
+            "on (?:(?:[0-9]){4}-(?:[0-9]){2}-(?:[0-9]){2}) at {{host}}"
+        );
+        let fragments = vec![
+            ("a".to_string(), "x{{b}}".to_string()),
+            ("b".to_string(), "y|{{a}}".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert!(expand_fragments("{{a}}", &fragments)
+            .unwrap_err()
+            .to_string()
+            .contains("a -> b -> a"));
+    }
+}
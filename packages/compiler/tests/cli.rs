@@ -0,0 +1,90 @@
+//! Runs the `zk-regex` binary.
+
+use std::{
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+/// Creates a directory for the outputs of a test, unique to the test and the process running
+/// it.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("zk_regex_cli_{name}_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Compiles the decomposed regex of `id:[0-9]+` to Circom with the given global flags.
+fn compile_decomposed(name: &str, flags: &[&str]) -> Output {
+    let dir = scratch_dir(name);
+    let config = dir.join("id.json");
+    std::fs::write(
+        &config,
+        r#"{"parts": [{"is_public": false, "regex_def": "id:"}, {"is_public": true, "regex_def": "[0-9]+"}]}"#,
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_zk-regex"))
+        .args(flags)
+        .arg("decomposed")
+        .arg("-d")
+        .arg(&config)
+        .arg("-c")
+        .arg(dir.join("id_regex.circom"))
+        .args(["-t", "IdRegex"])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    output
+}
+
+#[test]
+fn test_verbose_logging() {
+    let quiet = compile_decomposed("quiet", &[]);
+    assert!(quiet.stderr.is_empty());
+
+    let verbose = compile_decomposed("verbose", &["-v"]);
+    let log = String::from_utf8(verbose.stderr).unwrap();
+    assert!(log.contains("compiling a decomposed regex"));
+    assert!(log.contains("built the DFA of a decomposed regex parts=2 states=5"));
+    assert!(log.contains("writing the Circom template"));
+    assert!(!log.contains("phase finished"));
+
+    let trace = compile_decomposed("trace", &["-vvv"]);
+    let log = String::from_utf8(trace.stderr).unwrap();
+    assert!(log.contains("phase finished"));
+    assert!(log.contains("phase=\"Circom codegen\""));
+}
+
+#[test]
+fn test_short_h_is_the_halo2_dir() {
+    let output = Command::new(env!("CARGO_BIN_EXE_zk-regex"))
+        .args(["raw", "--help"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let help = String::from_utf8(output.stdout).unwrap();
+    assert!(help.contains("-h, --halo2-dir-path"));
+}
+
+#[test]
+fn test_raw_reverse_without_substrings() {
+    let dir = scratch_dir("raw_reverse");
+    let noir = dir.join("rev.nr");
+    let output = Command::new(env!("CARGO_BIN_EXE_zk-regex"))
+        .args([
+            "raw",
+            "-r",
+            "a(b|c)+d",
+            "-g",
+            "false",
+            "--noir-reverse",
+            "-n",
+        ])
+        .arg(&noir)
+        .output()
+        .unwrap();
+    let code = std::fs::read_to_string(&noir);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(code.unwrap().contains("pub fn regex_match"));
+}